@@ -0,0 +1,188 @@
+//! Generator of the orders and lineitem tables.
+//!
+//! The tables follow the shape of TPC-H's: scale factor 1 has 1.5 million orders, each
+//! with 1 to 7 line items, and dates between 1992-01-01 and 1998-08-02. Prices are
+//! decimals with two digits, so sums of them do not overflow, and discounts are percents.
+//! Every order is generated from a random number generator seeded with the seed and its
+//! key, so the same scale factor and seed always give the same tables, in order of key.
+
+use common::{Attribute, DataType, Date, Decimal, Field, TableSchema, Tuple};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Number of orders at scale factor 1.
+pub const ORDERS_PER_SCALE: f64 = 1_500_000.0;
+
+/// Number of days orders are placed over, from `first_order_date`.
+const ORDER_DAYS: i32 = 2405;
+
+/// Line items shipped up to this date are returned or accepted, later ones are not.
+const RETURN_DATE: (i32, u32, u32) = (1995, 6, 17);
+
+const PRIORITIES: [&str; 5] = ["1-URGENT", "2-HIGH", "3-MEDIUM", "4-NOT SPECIFIED", "5-LOW"];
+
+/// Schema of the orders table.
+pub fn orders_schema() -> TableSchema {
+    TableSchema::new(vec![
+        Attribute::new_pk(String::from("o_orderkey"), DataType::Int),
+        Attribute::new(String::from("o_custkey"), DataType::Int),
+        Attribute::new(String::from("o_totalprice"), DataType::Decimal(12, 2)),
+        Attribute::new(String::from("o_orderdate"), DataType::Date),
+        Attribute::new(String::from("o_orderpriority"), DataType::String),
+    ])
+}
+
+/// Schema of the lineitem table.
+pub fn lineitem_schema() -> TableSchema {
+    TableSchema::from_vecs(
+        vec![
+            "l_orderkey",
+            "l_linenumber",
+            "l_partkey",
+            "l_quantity",
+            "l_extendedprice",
+            "l_discount",
+            "l_returnflag",
+            "l_shipdate",
+        ],
+        vec![
+            DataType::Int,
+            DataType::Int,
+            DataType::Int,
+            DataType::Int,
+            DataType::Decimal(12, 2),
+            DataType::Int,
+            DataType::String,
+            DataType::Date,
+        ],
+    )
+}
+
+/// Date of the first order.
+pub fn first_order_date() -> Date {
+    Date::from_ymd(1992, 1, 1).unwrap()
+}
+
+/// Generates the tables at a scale factor.
+#[derive(Clone, Copy, Debug)]
+pub struct Generator {
+    orders: usize,
+    seed: u64,
+}
+
+impl Generator {
+    /// Creates a generator.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - Scale factor of the tables; 1 is 1.5 million orders.
+    /// * `seed` - Seed of the random values.
+    pub fn new(scale: f64, seed: u64) -> Self {
+        Generator {
+            orders: ((scale * ORDERS_PER_SCALE).round() as usize).max(1),
+            seed,
+        }
+    }
+
+    /// Returns the number of orders.
+    pub fn num_orders(&self) -> usize {
+        self.orders
+    }
+
+    /// Returns the records of the orders table.
+    pub fn orders(&self) -> impl Iterator<Item = Tuple> + '_ {
+        (1..=self.orders as i32).map(move |key| self.order(key).0)
+    }
+
+    /// Returns the records of the lineitem table.
+    pub fn lineitems(&self) -> impl Iterator<Item = Tuple> + '_ {
+        (1..=self.orders as i32).flat_map(move |key| self.order(key).1)
+    }
+
+    /// Returns an order and its line items.
+    fn order(&self, key: i32) -> (Tuple, Vec<Tuple>) {
+        let mut rng = StdRng::seed_from_u64(self.seed ^ (key as u64).wrapping_mul(0x9e37_79b9));
+        let order_date = Date(first_order_date().0 + rng.gen_range(0..ORDER_DAYS));
+        let return_date = Date::from_ymd(RETURN_DATE.0, RETURN_DATE.1, RETURN_DATE.2).unwrap();
+        let mut total_price = 0;
+        let lines = (1..=rng.gen_range(1..=7))
+            .map(|line| {
+                let part_key = rng.gen_range(1..=200_000);
+                let quantity = rng.gen_range(1..=50);
+                // in cents
+                let extended_price = quantity as i64 * (90_000 + part_key as i64 % 20_000);
+                let discount = rng.gen_range(0..=10);
+                let ship_date = Date(order_date.0 + rng.gen_range(1..=121));
+                let return_flag = match ship_date <= return_date {
+                    true if rng.gen_bool(0.5) => "R",
+                    true => "A",
+                    false => "N",
+                };
+                total_price += extended_price * (100 - discount as i64) / 100;
+                Tuple::new(vec![
+                    Field::IntField(key),
+                    Field::IntField(line),
+                    Field::IntField(part_key),
+                    Field::IntField(quantity),
+                    Field::DecimalField(Decimal::new(extended_price, 2)),
+                    Field::IntField(discount),
+                    Field::StringField(return_flag.to_string()),
+                    Field::DateField(ship_date),
+                ])
+            })
+            .collect();
+        let order = Tuple::new(vec![
+            Field::IntField(key),
+            Field::IntField(rng.gen_range(1..=(self.orders as i32 / 10).max(1))),
+            Field::DecimalField(Decimal::new(total_price, 2)),
+            Field::DateField(order_date),
+            Field::StringField(PRIORITIES[rng.gen_range(0..PRIORITIES.len())].to_string()),
+        ]);
+        (order, lines)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generator() {
+        let gen = Generator::new(0.001, 7);
+        assert_eq!(1500, gen.num_orders());
+        let orders: Vec<Tuple> = gen.orders().collect();
+        let lineitems: Vec<Tuple> = gen.lineitems().collect();
+        assert_eq!(orders.len(), gen.num_orders());
+        assert!(lineitems.len() >= orders.len() && lineitems.len() <= 7 * orders.len());
+        assert_eq!(
+            orders,
+            Generator::new(0.001, 7).orders().collect::<Vec<_>>()
+        );
+        assert_ne!(
+            orders,
+            Generator::new(0.001, 8).orders().collect::<Vec<_>>()
+        );
+
+        // the line items of an order add up to its price and ship after it is placed
+        let order = &orders[0];
+        let lines: Vec<&Tuple> = lineitems
+            .iter()
+            .filter(|l| l.get_field(0) == order.get_field(0))
+            .collect();
+        let total: i64 = lines
+            .iter()
+            .map(|l| match (l.get_field(4), l.get_field(5)) {
+                (Some(Field::DecimalField(p)), Some(Field::IntField(d))) => {
+                    p.value * (100 - *d as i64) / 100
+                }
+                _ => panic!("Expected a price and a discount"),
+            })
+            .sum();
+        let total = Field::DecimalField(Decimal::new(total, 2));
+        assert_eq!(Some(&total), order.get_field(2));
+        assert!(lines.iter().all(|l| l.get_field(7) > order.get_field(3)));
+        for tuple in orders.iter().take(10) {
+            assert_eq!(orders_schema().size(), tuple.size());
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! TPC-H style benchmark of the storage and executor layers.
+//!
+//! `generator` produces orders and lineitem tables scaled like TPC-H's. A run bulk loads
+//! them into a heapstore storage manager and scans them back, then loads them into the
+//! storage manager queryexe is built with and runs the plans of `plans` through queryexe
+//! operators. The `Report` gives the rows per second of every load, scan and query, and
+//! the rows and time of each operator of the plans, so that runs before and after a change
+//! can be compared. Run it with `cargo run --release -p benchmark -- --scale 0.01`.
+
+pub mod generator;
+pub mod plans;
+mod report;
+
+pub use report::{QueryTiming, Report, Timing};
+
+use common::ids::{ContainerId, Permissions, TransactionId};
+use common::storage_trait::StorageTrait;
+use common::CrustyError;
+use generator::Generator;
+use plans::{Tables, LINEITEM, ORDERS};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Settings of a benchmark run.
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    /// Scale factor of the tables; 1 is 1.5 million orders.
+    pub scale: f64,
+    /// Seed of the generated values.
+    pub seed: u64,
+    /// Number of times each query is run. The fastest run is reported.
+    pub runs: usize,
+    /// Directory of the heapstore storage manager, removed after the run.
+    pub storage_path: PathBuf,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            scale: 0.001,
+            seed: 0,
+            runs: 3,
+            storage_path: std::env::temp_dir().join(format!("crusty_bench_{}", std::process::id())),
+        }
+    }
+}
+
+impl BenchConfig {
+    /// Generates tables at another scale factor.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Generates the values from another seed.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Runs each query a number of times. At least one run is made.
+    pub fn with_runs(mut self, runs: usize) -> Self {
+        self.runs = runs;
+        self
+    }
+}
+
+/// Times a bulk load of serialized records into a container.
+fn time_load<T: StorageTrait>(
+    name: String,
+    sm: &T,
+    container_id: ContainerId,
+    values: &[Vec<u8>],
+) -> Result<Timing, CrustyError> {
+    let start = Instant::now();
+    let ids = sm.bulk_load(container_id, values.iter().cloned(), TransactionId::new())?;
+    Ok(Timing {
+        name,
+        rows: ids.len() as u64,
+        elapsed: start.elapsed(),
+    })
+}
+
+/// Times a scan of all the records of a container.
+fn time_scan<T: StorageTrait>(name: String, sm: &T, container_id: ContainerId) -> Timing {
+    let start = Instant::now();
+    let rows = sm
+        .get_iterator(container_id, TransactionId::new(), Permissions::ReadOnly)
+        .count();
+    Timing {
+        name,
+        rows: rows as u64,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Loads the tables into heapstore and scans them.
+fn bench_heapstore(
+    config: &BenchConfig,
+    tables: &[(&str, ContainerId, &[Vec<u8>])],
+) -> Result<Vec<Timing>, CrustyError> {
+    let sm = heapstore::storage_manager::StorageManager::new(config.storage_path.clone());
+    let mut timings = Vec::new();
+    for (name, container_id, values) in tables {
+        sm.create_table(*container_id)?;
+        let load = format!("heapstore bulk load {}", name);
+        timings.push(time_load(load, &sm, *container_id, values)?);
+    }
+    for (name, container_id, _) in tables {
+        let scan = format!("heapstore scan {}", name);
+        timings.push(time_scan(scan, &sm, *container_id));
+    }
+    sm.reset()?;
+    drop(sm);
+    let _ = fs::remove_dir_all(&config.storage_path);
+    Ok(timings)
+}
+
+/// Runs a query the configured number of times and returns its fastest run.
+fn bench_query(
+    query: &plans::Query,
+    tables: &Tables,
+    runs: usize,
+) -> Result<QueryTiming, CrustyError> {
+    let mut fastest: Option<QueryTiming> = None;
+    for _ in 0..runs.max(1) {
+        let start = Instant::now();
+        let mut plan = query.build(tables, TransactionId::new());
+        plan.open()?;
+        let mut rows = 0;
+        while plan.next()?.is_some() {
+            rows += 1;
+        }
+        plan.close()?;
+        let elapsed = start.elapsed();
+        if fastest.as_ref().is_none_or(|f| elapsed < f.elapsed) {
+            fastest = Some(QueryTiming {
+                name: query.name.to_string(),
+                rows,
+                elapsed,
+                plan: plan.describe(),
+            });
+        }
+    }
+    Ok(fastest.unwrap())
+}
+
+/// Generates the tables, loads them, and runs the queries.
+///
+/// # Arguments
+///
+/// * `config` - Settings of the run.
+pub fn run(config: &BenchConfig) -> Result<Report, CrustyError> {
+    let gen = Generator::new(config.scale, config.seed);
+    let orders: Vec<Vec<u8>> = gen.orders().map(|t| t.to_bytes()).collect();
+    let lineitems: Vec<Vec<u8>> = gen.lineitems().map(|t| t.to_bytes()).collect();
+    let tables = [
+        ("orders", ORDERS, &orders[..]),
+        ("lineitem", LINEITEM, &lineitems[..]),
+    ];
+    let mut storage = bench_heapstore(config, &tables)?;
+
+    let sm = Arc::new(queryexe::StorageManager::new_test_sm());
+    let query_tables = Tables::create(sm.clone())?;
+    for (name, container_id, values) in tables {
+        let load = format!("queryexe bulk load {}", name);
+        storage.push(time_load(load, sm.as_ref(), container_id, values)?);
+    }
+    let queries = plans::queries()
+        .iter()
+        .map(|query| bench_query(query, &query_tables, config.runs))
+        .collect::<Result<_, _>>()?;
+    sm.reset()?;
+    Ok(Report {
+        scale: config.scale,
+        orders: orders.len() as u64,
+        lineitems: lineitems.len() as u64,
+        storage,
+        queries,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use queryexe::opiterator::PlanNode;
+
+    /// Returns the number of rows the inputs of the first join of a plan returned.
+    fn join_input_rows(node: &PlanNode) -> u64 {
+        if node.name.contains("Join") {
+            return node
+                .children
+                .iter()
+                .map(|c| c.counters.map_or(0, |c| c.rows))
+                .sum();
+        }
+        node.children.iter().map(join_input_rows).sum()
+    }
+
+    #[test]
+    fn test_run() {
+        let config = BenchConfig::default().with_scale(0.0005).with_runs(2);
+        let report = run(&config).unwrap();
+        assert_eq!(750, report.orders);
+        assert_eq!(6, report.storage.len());
+        assert!(report
+            .storage
+            .iter()
+            .all(|t| t.rows == report.orders || t.rows == report.lineitems));
+        assert!(!config.storage_path.exists());
+
+        let names: Vec<&str> = report.queries.iter().map(|q| q.name.as_str()).collect();
+        assert_eq!(
+            vec![
+                "scan",
+                "pricing summary",
+                "shipping priority",
+                "order priority",
+                "small quantities planned",
+                "small quantities rewritten"
+            ],
+            names
+        );
+        let scan = &report.queries[0];
+        assert_eq!(0, scan.rows);
+        assert_eq!(report.lineitems, scan.scanned_rows());
+        // the pricing summary has one row per return flag
+        let summary = &report.queries[1];
+        assert_eq!(3, summary.rows);
+        assert_eq!(report.lineitems, summary.scanned_rows());
+        assert!(summary.plan.counters.is_some());
+        // the rewrite filters the line items before the join reads them
+        let (planned, rewritten) = (&report.queries[4], &report.queries[5]);
+        assert_eq!(planned.rows, rewritten.rows);
+        assert_eq!(planned.scanned_rows(), rewritten.scanned_rows());
+        assert!(join_input_rows(&rewritten.plan) < join_input_rows(&planned.plan));
+        assert!(report.to_string().contains("heapstore bulk load lineitem"));
+    }
+}
@@ -0,0 +1,57 @@
+use benchmark::BenchConfig;
+use clap::{App, Arg};
+use std::process;
+
+fn main() {
+    env_logger::init();
+
+    let matches = App::new(env!("CARGO_PKG_NAME"))
+        .version(env!("CARGO_PKG_VERSION"))
+        .about(env!("CARGO_PKG_DESCRIPTION"))
+        .arg(
+            Arg::with_name("scale")
+                .short("s")
+                .long("scale")
+                .value_name("FACTOR")
+                .default_value("0.001")
+                .help("Scale factor of the tables; 1 is 1.5 million orders")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .default_value("0")
+                .help("Seed of the generated values")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("runs")
+                .short("r")
+                .long("runs")
+                .value_name("RUNS")
+                .default_value("3")
+                .help("Number of runs of each query; the fastest is reported")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let parse = |name: &str| {
+        let value = matches.value_of(name).unwrap();
+        value.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid {}: {}", name, value);
+            process::exit(2);
+        })
+    };
+    let config = BenchConfig::default()
+        .with_scale(parse("scale"))
+        .with_seed(parse("seed") as u64)
+        .with_runs(parse("runs") as usize);
+    match benchmark::run(&config) {
+        Ok(report) => print!("{}", report),
+        Err(e) => {
+            eprintln!("Benchmark failed: {:?}", e);
+            process::exit(1);
+        }
+    }
+}
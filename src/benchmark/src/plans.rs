@@ -0,0 +1,303 @@
+//! Query plans run by the benchmark.
+//!
+//! The plans are built directly from queryexe operators, so they stay the same when the
+//! planner changes. Every operator is wrapped in a `Profile`, so the description of an
+//! executed plan has the rows and time of each operator. The planned queries are the
+//! exception: they are planned from SQL like the server plans them, once as translated
+//! and once rewritten, so that the rows of their operators show what the rewrites save.
+
+use crate::generator::{lineitem_schema, orders_schema};
+use common::database::Database;
+use common::ids::{ContainerId, StateType, TransactionId};
+use common::storage_trait::StorageTrait;
+use common::table::Table;
+use common::traits::transaction_manager_trait::TransactionManagerTrait;
+use common::{AggOp, CrustyError, Date, Field, SimplePredicateOp};
+use optimizer::optimizer::Optimizer;
+use queryexe::opiterator::{
+    Aggregate, Filter, HashEqJoin, MemoryBudget, OpIterator, Profile, Profiling, SeqScan,
+    DEFAULT_MEMORY_LIMIT,
+};
+use queryexe::query::{rewrite_plan, Attachments, Executor, TranslateAndValidate};
+use queryexe::{StorageManager, TransactionManager};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// Container of the orders table.
+pub const ORDERS: ContainerId = 1;
+/// Container of the lineitem table.
+pub const LINEITEM: ContainerId = 2;
+
+/// The tables the plans read.
+pub struct Tables {
+    sm: Arc<StorageManager>,
+    tm: &'static TransactionManager,
+    orders: Arc<RwLock<Table>>,
+    lineitem: Arc<RwLock<Table>>,
+    /// Catalog of the tables, which the planned queries are planned with.
+    catalog: Database,
+}
+
+impl Tables {
+    /// Creates the empty orders and lineitem tables.
+    ///
+    /// # Arguments
+    ///
+    /// * `sm` - Storage manager to create the tables in.
+    pub fn create(sm: Arc<StorageManager>) -> Result<Self, CrustyError> {
+        sm.create_table(ORDERS)?;
+        sm.create_table(LINEITEM)?;
+        let orders = Arc::new(RwLock::new(Table::new(
+            String::from("orders"),
+            orders_schema(),
+        )));
+        let lineitem = Arc::new(RwLock::new(Table::new(
+            String::from("lineitem"),
+            lineitem_schema(),
+        )));
+        let catalog = Database::new(String::from("benchmark"));
+        for (container_id, table) in [(ORDERS, &orders), (LINEITEM, &lineitem)] {
+            let name = table.read().unwrap().name.clone();
+            catalog
+                .named_containers
+                .write()
+                .unwrap()
+                .insert(container_id, (name, StateType::BaseTable));
+            catalog
+                .tables
+                .write()
+                .unwrap()
+                .insert(container_id, table.clone());
+        }
+        Ok(Tables {
+            sm,
+            // held by the operators for the life of the program
+            tm: Box::leak(Box::new(TransactionManager::new(Path::new("")))),
+            orders,
+            lineitem,
+            catalog,
+        })
+    }
+
+    /// Returns the storage manager holding the tables.
+    pub fn storage_manager(&self) -> &StorageManager {
+        &self.sm
+    }
+
+    fn scan_orders(&self, tid: TransactionId) -> Box<dyn OpIterator> {
+        profile(SeqScan::new(
+            self.sm.clone(),
+            self.orders.clone(),
+            "o",
+            &ORDERS,
+            tid,
+        ))
+    }
+
+    fn scan_lineitem(&self, tid: TransactionId) -> Box<dyn OpIterator> {
+        profile(SeqScan::new(
+            self.sm.clone(),
+            self.lineitem.clone(),
+            "l",
+            &LINEITEM,
+            tid,
+        ))
+    }
+
+    /// Plans a query over the tables like the server plans it, and builds its operators.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - Query to plan.
+    /// * `rewrite` - Whether to rewrite the translated logical plan.
+    /// * `tid` - Transaction running the plan.
+    fn plan(&self, sql: &str, rewrite: bool, tid: TransactionId) -> Box<dyn OpIterator> {
+        let mut logical_plan =
+            TranslateAndValidate::parse(sql, &self.catalog).expect("benchmark query plans");
+        if rewrite {
+            rewrite_plan(&mut logical_plan);
+        }
+        let physical_plan = Optimizer::new()
+            .logical_plan_to_physical_plan(logical_plan, &self.catalog, false)
+            .unwrap();
+        Executor::physical_plan_to_op_iterator(
+            &self.sm,
+            self.tm,
+            &self.catalog,
+            &Attachments::new(),
+            &physical_plan,
+            &MemoryBudget::new(DEFAULT_MEMORY_LIMIT),
+            Profiling::Analyze,
+            tid,
+            0,
+        )
+        .unwrap()
+    }
+}
+
+/// A plan run by the benchmark.
+pub struct Query {
+    /// Name of the query in reports.
+    pub name: &'static str,
+    build: fn(&Tables, TransactionId) -> Box<dyn OpIterator>,
+}
+
+impl Query {
+    /// Builds the plan of the query. Joins and aggregates read their input when they are
+    /// built, so building the plan runs part of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tables` - Tables the plan reads.
+    /// * `tid` - Transaction running the plan.
+    pub fn build(&self, tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+        (self.build)(tables, tid)
+    }
+}
+
+/// Returns the queries of the benchmark.
+pub fn queries() -> Vec<Query> {
+    vec![
+        Query {
+            name: "scan",
+            build: scan,
+        },
+        Query {
+            name: "pricing summary",
+            build: pricing_summary,
+        },
+        Query {
+            name: "shipping priority",
+            build: shipping_priority,
+        },
+        Query {
+            name: "order priority",
+            build: order_priority,
+        },
+        Query {
+            name: "small quantities planned",
+            build: small_quantities,
+        },
+        Query {
+            name: "small quantities rewritten",
+            build: small_quantities_rewritten,
+        },
+    ]
+}
+
+fn profile(op: impl OpIterator + 'static) -> Box<dyn OpIterator> {
+    Box::new(Profile::new(Box::new(op), None).with_timing())
+}
+
+fn date(year: i32, month: u32, day: u32) -> Field {
+    Field::DateField(Date::from_ymd(year, month, day).unwrap())
+}
+
+/// Scans lineitem through a filter no line item passes.
+fn scan(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    let lineitem = tables.scan_lineitem(tid);
+    profile(Filter::new(
+        SimplePredicateOp::GreaterThan,
+        5,
+        Field::IntField(100),
+        lineitem,
+    ))
+}
+
+/// Quantities, prices and discounts of the line items shipped by 1998-09-02, by return
+/// flag, like TPC-H Q1.
+fn pricing_summary(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    let lineitem = tables.scan_lineitem(tid);
+    let shipped = profile(Filter::new(
+        SimplePredicateOp::LessThanOrEq,
+        7,
+        date(1998, 9, 2),
+        lineitem,
+    ));
+    profile(Aggregate::new(
+        vec![6],
+        vec!["l_returnflag"],
+        vec![3, 4, 5, 0],
+        vec!["sum_qty", "sum_base_price", "avg_disc", "count_order"],
+        vec![AggOp::Sum, AggOp::Sum, AggOp::Avg, AggOp::Count],
+        shipped,
+    ))
+}
+
+/// Prices of the line items shipped after 1995-03-15 of the orders placed before it, by
+/// order, like TPC-H Q3.
+fn shipping_priority(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    let orders = profile(Filter::new(
+        SimplePredicateOp::LessThan,
+        3,
+        date(1995, 3, 15),
+        tables.scan_orders(tid),
+    ));
+    let lineitem = profile(Filter::new(
+        SimplePredicateOp::GreaterThan,
+        7,
+        date(1995, 3, 15),
+        tables.scan_lineitem(tid),
+    ));
+    // the hash table is built over the smaller orders table
+    let join = profile(HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, lineitem, orders).unwrap());
+    profile(Aggregate::new(
+        vec![0],
+        vec!["l_orderkey"],
+        vec![4],
+        vec!["revenue"],
+        vec![AggOp::Sum],
+        join,
+    ))
+}
+
+/// Number of line items of the orders placed in the third quarter of 1993, by order
+/// priority, like TPC-H Q4.
+fn order_priority(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    let placed_after = profile(Filter::new(
+        SimplePredicateOp::GreaterThanOrEq,
+        3,
+        date(1993, 7, 1),
+        tables.scan_orders(tid),
+    ));
+    let orders = profile(Filter::new(
+        SimplePredicateOp::LessThan,
+        3,
+        date(1993, 10, 1),
+        placed_after,
+    ));
+    let join = profile(
+        HashEqJoin::new(
+            SimplePredicateOp::Equals,
+            0,
+            0,
+            tables.scan_lineitem(tid),
+            orders,
+        )
+        .unwrap(),
+    );
+    // the priority is the fifth column of orders, after the eight of lineitem
+    profile(Aggregate::new(
+        vec![12],
+        vec!["o_orderpriority"],
+        vec![0],
+        vec!["line_count"],
+        vec![AggOp::Count],
+        join,
+    ))
+}
+
+/// Number of line items with fewer than 10 units, by order priority.
+const SMALL_QUANTITIES: &str = "SELECT orders.o_orderpriority, COUNT(lineitem.l_orderkey) \
+     FROM lineitem JOIN orders ON lineitem.l_orderkey = orders.o_orderkey \
+     WHERE lineitem.l_quantity < 10 GROUP BY orders.o_orderpriority";
+
+/// `SMALL_QUANTITIES` as translated, which filters the line items after joining them.
+fn small_quantities(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    tables.plan(SMALL_QUANTITIES, false, tid)
+}
+
+/// `SMALL_QUANTITIES` rewritten, which filters the line items before joining them.
+fn small_quantities_rewritten(tables: &Tables, tid: TransactionId) -> Box<dyn OpIterator> {
+    tables.plan(SMALL_QUANTITIES, true, tid)
+}
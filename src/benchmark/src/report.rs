@@ -0,0 +1,111 @@
+use queryexe::opiterator::PlanNode;
+use std::fmt;
+use std::time::Duration;
+
+/// Time taken to load or scan the rows of a table.
+#[derive(Clone, Debug)]
+pub struct Timing {
+    /// What was timed, such as `heapstore bulk load lineitem`.
+    pub name: String,
+    /// Number of rows loaded or scanned.
+    pub rows: u64,
+    pub elapsed: Duration,
+}
+
+impl Timing {
+    /// Returns the number of rows per second.
+    pub fn rows_per_sec(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Time taken by the fastest run of a query.
+#[derive(Clone, Debug)]
+pub struct QueryTiming {
+    /// Name of the query.
+    pub name: String,
+    /// Number of rows of the result.
+    pub rows: u64,
+    /// Time to build, open, read and close the plan.
+    pub elapsed: Duration,
+    /// The executed plan, with the rows and time of each operator.
+    pub plan: PlanNode,
+}
+
+impl QueryTiming {
+    /// Returns the number of rows the scans of the plan read.
+    pub fn scanned_rows(&self) -> u64 {
+        fn scanned(node: &PlanNode) -> u64 {
+            if node.children.is_empty() {
+                node.counters.map_or(0, |c| c.rows)
+            } else {
+                node.children.iter().map(scanned).sum()
+            }
+        }
+        scanned(&self.plan)
+    }
+
+    /// Returns the number of rows the scans of the plan read per second.
+    pub fn rows_per_sec(&self) -> f64 {
+        self.scanned_rows() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Results of a run of the benchmark.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// Scale factor of the tables.
+    pub scale: f64,
+    /// Number of rows of the orders table.
+    pub orders: u64,
+    /// Number of rows of the lineitem table.
+    pub lineitems: u64,
+    /// Loads and scans of the tables.
+    pub storage: Vec<Timing>,
+    /// Runs of the queries.
+    pub queries: Vec<QueryTiming>,
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+impl fmt::Display for Report {
+    /// Prints a table of the loads and scans, then the queries, each with its plan.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Scale factor {}: {} orders, {} line items",
+            self.scale, self.orders, self.lineitems
+        )?;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "{:<36} {:>10} {:>12} {:>14}",
+            "storage", "rows", "ms", "rows/s"
+        )?;
+        for timing in &self.storage {
+            writeln!(
+                f,
+                "{:<36} {:>10} {:>12.3} {:>14.0}",
+                timing.name,
+                timing.rows,
+                millis(timing.elapsed),
+                timing.rows_per_sec()
+            )?;
+        }
+        for query in &self.queries {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "{}: {} rows in {:.3} ms, {:.0} scanned rows/s",
+                query.name,
+                query.rows,
+                millis(query.elapsed),
+                query.rows_per_sec()
+            )?;
+            writeln!(f, "{}", query.plan)?;
+        }
+        Ok(())
+    }
+}
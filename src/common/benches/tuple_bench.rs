@@ -0,0 +1,52 @@
+use common::testutil::gen_test_tuples;
+use common::Tuple;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Compares the compact tuple encoding to CBOR, which tuples were serialized with before.
+pub fn tuple_benchmark(c: &mut Criterion) {
+    let tuples = gen_test_tuples(1000);
+
+    let cbor: Vec<Vec<u8>> = tuples
+        .iter()
+        .map(|t| serde_cbor::to_vec(t).unwrap())
+        .collect();
+    let compact: Vec<Vec<u8>> = tuples.iter().map(Tuple::to_bytes).collect();
+    let total = |encoded: &[Vec<u8>]| encoded.iter().map(Vec::len).sum::<usize>();
+    println!(
+        "bytes per tuple: cbor {}, compact {}",
+        total(&cbor) / tuples.len(),
+        total(&compact) / tuples.len()
+    );
+
+    c.bench_function("encode 1k cbor", |b| {
+        b.iter(|| {
+            for t in black_box(&tuples) {
+                black_box(serde_cbor::to_vec(t).unwrap());
+            }
+        })
+    });
+    c.bench_function("encode 1k compact", |b| {
+        b.iter(|| {
+            for t in black_box(&tuples) {
+                black_box(t.to_bytes());
+            }
+        })
+    });
+    c.bench_function("decode 1k cbor", |b| {
+        b.iter(|| {
+            for bytes in black_box(&cbor) {
+                black_box(serde_cbor::from_slice::<Tuple>(bytes).unwrap());
+            }
+        })
+    });
+    c.bench_function("decode 1k compact", |b| {
+        b.iter(|| {
+            for bytes in black_box(&compact) {
+                black_box(Tuple::from_bytes(bytes));
+            }
+        })
+    });
+}
+
+criterion_group!(benches, tuple_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+//! Decodes arbitrary bytes as tuples with `common::encoding`. Run with
+//! `cargo fuzz run tuple_decode` from `src/common`.
+//!
+//! Bytes that are not a tuple must be rejected with an error, and a decoded tuple must
+//! encode to bytes that decode to the same tuple.
+#![no_main]
+
+use common::encoding::{decode, encode};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(tuple) = decode(data) {
+        let bytes = encode(&tuple);
+        assert_eq!(
+            tuple,
+            decode(&bytes).expect("encoded tuple does not decode")
+        );
+    }
+});
@@ -0,0 +1,331 @@
+//! Records as Apache Arrow record batches, with the `arrow` feature.
+//!
+//! `TupleBatches` groups the tuples of a scan or a query into record batches and implements
+//! `RecordBatchReader`, so the records can be handed to Arrow consumers such as DataFusion, or
+//! to pyarrow through the Arrow C stream interface. The columns of a table map to Arrow types
+//! as follows, with nullable columns marked as such:
+//!
+//! | DataType         | Arrow type                   |
+//! |------------------|------------------------------|
+//! | Int              | Int32                        |
+//! | String           | Utf8                         |
+//! | Float            | Float64                      |
+//! | Decimal(p, s)    | Decimal128(p, s)             |
+//! | Date             | Date32                       |
+//! | Timestamp        | Timestamp(Microsecond, None) |
+//! | Boolean          | Boolean                      |
+
+use crate::{CrustyError, DataType, Field, Float, TableSchema, Tuple};
+use arrow_array::builder::{
+    BooleanBuilder, Date32Builder, Decimal128Builder, Float64Builder, Int32Builder, StringBuilder,
+    TimestampMicrosecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatchOptions};
+pub use arrow_array::{RecordBatch, RecordBatchReader};
+use arrow_schema::{ArrowError, DataType as ArrowType, Field as ArrowField, Schema, SchemaRef};
+use std::sync::Arc;
+
+/// Rows per batch when the caller has no preference.
+pub const DEFAULT_BATCH_ROWS: usize = 8192;
+
+/// Returns the Arrow type of a column type.
+pub fn arrow_type(dtype: &DataType) -> Result<ArrowType, CrustyError> {
+    let arrow_type = match dtype {
+        DataType::Int => ArrowType::Int32,
+        DataType::String => ArrowType::Utf8,
+        DataType::Float => ArrowType::Float64,
+        DataType::Decimal(precision, scale) => {
+            let precision = u8::try_from(*precision);
+            let scale = i8::try_from(*scale);
+            match (precision, scale) {
+                (Ok(precision), Ok(scale)) => ArrowType::Decimal128(precision, scale),
+                _ => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "{:?} does not fit an Arrow decimal",
+                        dtype
+                    )))
+                }
+            }
+        }
+        DataType::Date => ArrowType::Date32,
+        DataType::Timestamp => ArrowType::Timestamp(arrow_schema::TimeUnit::Microsecond, None),
+        DataType::Boolean => ArrowType::Boolean,
+    };
+    Ok(arrow_type)
+}
+
+/// Returns the Arrow schema of a table schema, with the same column names.
+pub fn arrow_schema(schema: &TableSchema) -> Result<Schema, CrustyError> {
+    let fields = schema
+        .attributes()
+        .map(|attr| {
+            Ok(ArrowField::new(
+                attr.name(),
+                arrow_type(attr.dtype())?,
+                attr.is_nullable(),
+            ))
+        })
+        .collect::<Result<Vec<_>, CrustyError>>()?;
+    Ok(Schema::new(fields))
+}
+
+/// Builder of the array of a column.
+enum ColumnBuilder {
+    Int(Int32Builder),
+    String(StringBuilder),
+    Float(Float64Builder),
+    Decimal(Decimal128Builder, u32, u32),
+    Date(Date32Builder),
+    Timestamp(TimestampMicrosecondBuilder),
+    Boolean(BooleanBuilder),
+}
+
+impl ColumnBuilder {
+    /// Creates the builder of a column whose type `arrow_schema` accepted.
+    fn new(dtype: &DataType) -> Result<Self, ArrowError> {
+        let builder = match dtype {
+            DataType::Int => ColumnBuilder::Int(Int32Builder::new()),
+            DataType::String => ColumnBuilder::String(StringBuilder::new()),
+            DataType::Float => ColumnBuilder::Float(Float64Builder::new()),
+            DataType::Decimal(p, s) => ColumnBuilder::Decimal(
+                Decimal128Builder::new().with_precision_and_scale(*p as u8, *s as i8)?,
+                *p,
+                *s,
+            ),
+            DataType::Date => ColumnBuilder::Date(Date32Builder::new()),
+            DataType::Timestamp => ColumnBuilder::Timestamp(TimestampMicrosecondBuilder::new()),
+            DataType::Boolean => ColumnBuilder::Boolean(BooleanBuilder::new()),
+        };
+        Ok(builder)
+    }
+
+    /// Appends a field. Returns false if the field is not of the type of the column.
+    fn append(&mut self, field: &Field) -> bool {
+        match (self, field) {
+            (ColumnBuilder::Int(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::String(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::Float(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::Decimal(b, ..), Field::Null) => b.append_null(),
+            (ColumnBuilder::Date(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::Timestamp(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::Boolean(b), Field::Null) => b.append_null(),
+            (ColumnBuilder::Int(b), Field::IntField(i)) => b.append_value(*i),
+            (ColumnBuilder::String(b), Field::StringField(s)) => b.append_value(s),
+            (ColumnBuilder::Float(b), Field::FloatField(Float(f))) => b.append_value(*f),
+            (ColumnBuilder::Decimal(b, precision, scale), Field::DecimalField(d)) => {
+                match d.rescale(*precision, *scale) {
+                    Some(d) => b.append_value(d.value.into()),
+                    None => return false,
+                }
+            }
+            (ColumnBuilder::Date(b), Field::DateField(d)) => b.append_value(d.0),
+            (ColumnBuilder::Timestamp(b), Field::TimestampField(t)) => b.append_value(t.0),
+            (ColumnBuilder::Boolean(b), Field::BoolField(v)) => b.append_value(*v),
+            _ => return false,
+        }
+        true
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Int(b) => Arc::new(b.finish()),
+            ColumnBuilder::String(b) => Arc::new(b.finish()),
+            ColumnBuilder::Float(b) => Arc::new(b.finish()),
+            ColumnBuilder::Decimal(b, ..) => Arc::new(b.finish()),
+            ColumnBuilder::Date(b) => Arc::new(b.finish()),
+            ColumnBuilder::Timestamp(b) => Arc::new(b.finish()),
+            ColumnBuilder::Boolean(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Returns a record batch of tuples of a table.
+///
+/// # Arguments
+///
+/// * `schema` - Schema of the tuples.
+/// * `tuples` - Tuples of the batch.
+pub fn record_batch<'a>(
+    schema: &TableSchema,
+    tuples: impl IntoIterator<Item = &'a Tuple>,
+) -> Result<RecordBatch, CrustyError> {
+    let arrow_schema = Arc::new(arrow_schema(schema)?);
+    build_batch(schema, &arrow_schema, tuples).map_err(arrow_error_to_crusty)
+}
+
+/// Returns a record batch of tuples, with the errors of the records wrapped as external
+/// errors so that `TupleBatches` can return them.
+fn build_batch<'a>(
+    schema: &TableSchema,
+    arrow_schema: &SchemaRef,
+    tuples: impl IntoIterator<Item = &'a Tuple>,
+) -> Result<RecordBatch, ArrowError> {
+    let mut builders = schema
+        .attributes()
+        .map(|attr| ColumnBuilder::new(attr.dtype()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rows = 0;
+    for tuple in tuples {
+        for ((attr, field), builder) in schema
+            .attributes()
+            .zip(tuple.field_vals())
+            .zip(&mut builders)
+        {
+            if (*field == Field::Null && !attr.is_nullable()) || !builder.append(field) {
+                return Err(ArrowError::ExternalError(Box::new(
+                    CrustyError::ValidationError(format!(
+                        "Value {} of {} is not a {:?}",
+                        field,
+                        attr.name(),
+                        attr.dtype()
+                    )),
+                )));
+            }
+        }
+        rows += 1;
+    }
+    let columns = builders.iter_mut().map(ColumnBuilder::finish).collect();
+    RecordBatch::try_new_with_options(
+        arrow_schema.clone(),
+        columns,
+        &RecordBatchOptions::new().with_row_count(Some(rows)),
+    )
+}
+
+/// Returns the error of a failed conversion, unwrapping the errors of the records.
+fn arrow_error_to_crusty(error: ArrowError) -> CrustyError {
+    match error {
+        ArrowError::ExternalError(e) => match e.downcast::<CrustyError>() {
+            Ok(e) => *e,
+            Err(e) => CrustyError::ExecutionError(e.to_string()),
+        },
+        e => CrustyError::ExecutionError(e.to_string()),
+    }
+}
+
+/// The tuples of a scan or a query, grouped into record batches. A tuple that could not be
+/// read is returned as the error of its batch.
+pub struct TupleBatches<I: Iterator<Item = Result<Tuple, CrustyError>>> {
+    schema: TableSchema,
+    arrow_schema: SchemaRef,
+    tuples: I,
+    batch_rows: usize,
+}
+
+impl<I: Iterator<Item = Result<Tuple, CrustyError>>> TupleBatches<I> {
+    /// Creates batches of tuples. Error if a column type has no Arrow type.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the tuples.
+    /// * `tuples` - Tuples to group into batches.
+    /// * `batch_rows` - Tuples per batch; the last batch may have fewer.
+    pub fn new(schema: TableSchema, tuples: I, batch_rows: usize) -> Result<Self, CrustyError> {
+        let arrow_schema = Arc::new(arrow_schema(&schema)?);
+        Ok(TupleBatches {
+            schema,
+            arrow_schema,
+            tuples,
+            batch_rows: batch_rows.max(1),
+        })
+    }
+}
+
+impl<I: Iterator<Item = Result<Tuple, CrustyError>>> Iterator for TupleBatches<I> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tuples = match self
+            .tuples
+            .by_ref()
+            .take(self.batch_rows)
+            .collect::<Result<Vec<Tuple>, _>>()
+        {
+            Ok(tuples) => tuples,
+            Err(e) => return Some(Err(ArrowError::ExternalError(Box::new(e)))),
+        };
+        if tuples.is_empty() {
+            return None;
+        }
+        Some(build_batch(&self.schema, &self.arrow_schema, &tuples))
+    }
+}
+
+impl<I: Iterator<Item = Result<Tuple, CrustyError>>> RecordBatchReader for TupleBatches<I> {
+    fn schema(&self) -> SchemaRef {
+        self.arrow_schema.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Attribute, Date, Decimal, Timestamp};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{Decimal128Type, Int32Type};
+    use arrow_array::Array;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![
+            Attribute::new_pk(String::from("id"), DataType::Int),
+            Attribute::new(String::from("name"), DataType::String),
+            Attribute::new(String::from("price"), DataType::Decimal(6, 2)),
+            Attribute::new(String::from("day"), DataType::Date),
+            Attribute::new(String::from("at"), DataType::Timestamp),
+        ])
+    }
+
+    fn tuples(n: i32) -> Vec<Tuple> {
+        (0..n)
+            .map(|i| {
+                Tuple::new(vec![
+                    Field::IntField(i),
+                    if i % 3 == 0 {
+                        Field::Null
+                    } else {
+                        Field::StringField(i.to_string())
+                    },
+                    Field::DecimalField(Decimal::new(i as i64, 1)),
+                    Field::DateField(Date(i)),
+                    Field::TimestampField(Timestamp(i as i64)),
+                ])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_schema() {
+        let arrow = arrow_schema(&schema()).unwrap();
+        assert_eq!(5, arrow.fields().len());
+        assert!(!arrow.field(0).is_nullable());
+        assert!(arrow.field(1).is_nullable());
+        assert_eq!(&ArrowType::Decimal128(6, 2), arrow.field(2).data_type());
+        assert_eq!("day", arrow.field(3).name());
+    }
+
+    #[test]
+    fn test_batches() {
+        let batches: Vec<RecordBatch> =
+            TupleBatches::new(schema(), tuples(10).into_iter().map(Ok), 4)
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+        assert_eq!(
+            vec![4, 4, 2],
+            batches.iter().map(|b| b.num_rows()).collect::<Vec<_>>()
+        );
+        let last = &batches[2];
+        assert_eq!(9, last.column(0).as_primitive::<Int32Type>().value(1));
+        assert!(last.column(1).is_null(1));
+        assert_eq!("8", last.column(1).as_string::<i32>().value(0));
+        // 0.8 rescaled to the scale of the column.
+        assert_eq!(80, last.column(2).as_primitive::<Decimal128Type>().value(0));
+
+        let mut bad = tuples(1);
+        bad[0].field_vals[0] = Field::Null;
+        assert!(matches!(
+            record_batch(&schema(), &bad),
+            Err(CrustyError::ValidationError(_))
+        ));
+    }
+}
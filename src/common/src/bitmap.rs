@@ -0,0 +1,463 @@
+//! Compressed bitmaps of 32-bit positions.
+//!
+//! Bitmaps follow the roaring layout: positions are split into chunks of 2^16 by their high
+//! 16 bits, and each chunk stores its low 16 bits in whichever of three forms is smallest:
+//!
+//! * a sorted array of positions, for sparse chunks,
+//! * a bitset of 2^16 bits, for dense chunks,
+//! * sorted runs of consecutive positions, for chunks of long runs.
+//!
+//! Intersections and unions work chunk by chunk, and only visit the chunks of both bitmaps
+//! that share their high bits.
+
+use std::cmp::Ordering;
+
+/// Most positions of an array chunk: a larger array takes more bytes than a bitset.
+const ARRAY_MAX: usize = 4096;
+/// Words of a bitset chunk.
+const WORDS: usize = 1024;
+
+/// Positions of one chunk: their low 16 bits.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Chunk {
+    /// Sorted positions.
+    Array(Vec<u16>),
+    /// Bit i of word i / 64 is set for position i.
+    Bits(Vec<u64>),
+    /// Sorted, disjoint and non-adjacent runs, as their first and last positions.
+    Runs(Vec<(u16, u16)>),
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::Array(positions) => positions.len(),
+            Chunk::Bits(words) => words.iter().map(|w| w.count_ones() as usize).sum(),
+            Chunk::Runs(runs) => runs.iter().map(|(s, e)| (e - s) as usize + 1).sum(),
+        }
+    }
+
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Chunk::Array(positions) => positions.binary_search(&low).is_ok(),
+            Chunk::Bits(words) => words[low as usize / 64] & (1 << (low % 64)) != 0,
+            Chunk::Runs(runs) => runs
+                .binary_search_by(|(start, end)| {
+                    if *end < low {
+                        Ordering::Less
+                    } else if *start > low {
+                        Ordering::Greater
+                    } else {
+                        Ordering::Equal
+                    }
+                })
+                .is_ok(),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Chunk::Array(positions) => Box::new(positions.iter().copied()),
+            Chunk::Bits(words) => Box::new(words.iter().enumerate().flat_map(|(i, word)| {
+                let mut word = *word;
+                std::iter::from_fn(move || {
+                    if word == 0 {
+                        return None;
+                    }
+                    let bit = word.trailing_zeros();
+                    word &= word - 1;
+                    Some((i * 64) as u16 + bit as u16)
+                })
+            })),
+            Chunk::Runs(runs) => Box::new(runs.iter().flat_map(|(start, end)| *start..=*end)),
+        }
+    }
+
+    /// The positions as a bitset.
+    fn words(&self) -> Vec<u64> {
+        match self {
+            Chunk::Bits(words) => words.clone(),
+            _ => {
+                let mut words = vec![0; WORDS];
+                for low in self.iter() {
+                    words[low as usize / 64] |= 1 << (low % 64);
+                }
+                words
+            }
+        }
+    }
+
+    /// The positions as runs.
+    fn runs(&self) -> Vec<(u16, u16)> {
+        if let Chunk::Runs(runs) = self {
+            return runs.clone();
+        }
+        let mut runs: Vec<(u16, u16)> = Vec::new();
+        for low in self.iter() {
+            match runs.last_mut() {
+                Some((_, end)) if *end as u32 + 1 == low as u32 => *end = low,
+                _ => runs.push((low, low)),
+            }
+        }
+        runs
+    }
+
+    /// Converts the chunk to its smallest form. Returns None if it is empty.
+    fn optimize(self) -> Option<Chunk> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        let runs = self.runs();
+        // bytes taken by the positions in each form
+        let array_size = 2 * len;
+        let bits_size = 8 * WORDS;
+        let runs_size = 4 * runs.len();
+        if runs_size < array_size.min(bits_size) {
+            Some(Chunk::Runs(runs))
+        } else if len <= ARRAY_MAX {
+            Some(Chunk::Array(self.iter().collect()))
+        } else {
+            Some(Chunk::Bits(self.words()))
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        if self.contains(low) {
+            return false;
+        }
+        match self {
+            Chunk::Array(positions) if positions.len() < ARRAY_MAX => {
+                let i = positions.binary_search(&low).unwrap_err();
+                positions.insert(i, low);
+            }
+            Chunk::Bits(words) => words[low as usize / 64] |= 1 << (low % 64),
+            _ => {
+                let mut words = self.words();
+                words[low as usize / 64] |= 1 << (low % 64);
+                *self = Chunk::Bits(words);
+            }
+        }
+        true
+    }
+
+    fn remove(&mut self, low: u16) -> bool {
+        if !self.contains(low) {
+            return false;
+        }
+        match self {
+            Chunk::Array(positions) => {
+                let i = positions.binary_search(&low).unwrap();
+                positions.remove(i);
+            }
+            Chunk::Bits(words) => words[low as usize / 64] &= !(1 << (low % 64)),
+            Chunk::Runs(runs) => {
+                let i = runs
+                    .iter()
+                    .position(|(start, end)| *start <= low && low <= *end)
+                    .unwrap();
+                let (start, end) = runs[i];
+                runs.remove(i);
+                if low < end {
+                    runs.insert(i, (low + 1, end));
+                }
+                if start < low {
+                    runs.insert(i, (start, low - 1));
+                }
+            }
+        }
+        true
+    }
+
+    fn and(&self, other: &Chunk) -> Option<Chunk> {
+        let chunk = match (self, other) {
+            (Chunk::Array(positions), other) | (other, Chunk::Array(positions)) => Chunk::Array(
+                positions
+                    .iter()
+                    .copied()
+                    .filter(|low| other.contains(*low))
+                    .collect(),
+            ),
+            (Chunk::Runs(a), Chunk::Runs(b)) => {
+                let mut runs = Vec::new();
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    let start = a[i].0.max(b[j].0);
+                    let end = a[i].1.min(b[j].1);
+                    if start <= end {
+                        runs.push((start, end));
+                    }
+                    if a[i].1 < b[j].1 {
+                        i += 1;
+                    } else {
+                        j += 1;
+                    }
+                }
+                Chunk::Runs(runs)
+            }
+            _ => Chunk::Bits(
+                self.words()
+                    .iter()
+                    .zip(other.words())
+                    .map(|(a, b)| a & b)
+                    .collect(),
+            ),
+        };
+        chunk.optimize()
+    }
+
+    fn or(&self, other: &Chunk) -> Chunk {
+        let chunk = match (self, other) {
+            (Chunk::Array(a), Chunk::Array(b)) => {
+                let mut positions = Vec::with_capacity(a.len() + b.len());
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() || j < b.len() {
+                    let next = match (a.get(i), b.get(j)) {
+                        (Some(x), Some(y)) if x == y => {
+                            i += 1;
+                            j += 1;
+                            *x
+                        }
+                        (Some(x), Some(y)) if x < y => {
+                            i += 1;
+                            *x
+                        }
+                        (Some(x), None) => {
+                            i += 1;
+                            *x
+                        }
+                        (_, Some(y)) => {
+                            j += 1;
+                            *y
+                        }
+                        (None, None) => unreachable!(),
+                    };
+                    positions.push(next);
+                }
+                Chunk::Array(positions)
+            }
+            (Chunk::Runs(a), Chunk::Runs(b)) => {
+                let mut all: Vec<(u16, u16)> = a.iter().chain(b).copied().collect();
+                all.sort_unstable();
+                let mut runs: Vec<(u16, u16)> = Vec::with_capacity(all.len());
+                for (start, end) in all {
+                    match runs.last_mut() {
+                        Some((_, last)) if start as u32 <= *last as u32 + 1 => {
+                            *last = (*last).max(end)
+                        }
+                        _ => runs.push((start, end)),
+                    }
+                }
+                Chunk::Runs(runs)
+            }
+            _ => Chunk::Bits(
+                self.words()
+                    .iter()
+                    .zip(other.words())
+                    .map(|(a, b)| a | b)
+                    .collect(),
+            ),
+        };
+        chunk.optimize().unwrap()
+    }
+}
+
+/// A compressed set of 32-bit positions.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bitmap {
+    /// Non-empty chunks, sorted by their high 16 bits.
+    chunks: Vec<(u16, Chunk)>,
+}
+
+impl Bitmap {
+    /// Creates an empty bitmap.
+    pub fn new() -> Self {
+        Bitmap::default()
+    }
+
+    /// Index of the chunk of a position in `chunks`, or where to insert it.
+    fn chunk(&self, high: u16) -> Result<usize, usize> {
+        self.chunks.binary_search_by_key(&high, |(h, _)| *h)
+    }
+
+    /// Adds a position. Returns whether it was absent.
+    pub fn insert(&mut self, position: u32) -> bool {
+        let (high, low) = ((position >> 16) as u16, position as u16);
+        match self.chunk(high) {
+            Ok(i) => self.chunks[i].1.insert(low),
+            Err(i) => {
+                self.chunks.insert(i, (high, Chunk::Array(vec![low])));
+                true
+            }
+        }
+    }
+
+    /// Removes a position. Returns whether it was present.
+    pub fn remove(&mut self, position: u32) -> bool {
+        let (high, low) = ((position >> 16) as u16, position as u16);
+        match self.chunk(high) {
+            Ok(i) => {
+                let removed = self.chunks[i].1.remove(low);
+                if self.chunks[i].1.len() == 0 {
+                    self.chunks.remove(i);
+                }
+                removed
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn contains(&self, position: u32) -> bool {
+        match self.chunk((position >> 16) as u16) {
+            Ok(i) => self.chunks[i].1.contains(position as u16),
+            Err(_) => false,
+        }
+    }
+
+    /// Number of positions in the bitmap.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|(_, chunk)| chunk.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns the positions in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.chunks.iter().flat_map(|(high, chunk)| {
+            chunk
+                .iter()
+                .map(move |low| ((*high as u32) << 16) | low as u32)
+        })
+    }
+
+    /// Converts every chunk to its smallest form, such as before the bitmap is stored.
+    pub fn optimize(&mut self) {
+        self.chunks = std::mem::take(&mut self.chunks)
+            .into_iter()
+            .filter_map(|(high, chunk)| Some((high, chunk.optimize()?)))
+            .collect();
+    }
+
+    /// Returns the positions in both bitmaps.
+    pub fn and(&self, other: &Bitmap) -> Bitmap {
+        let mut chunks = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.chunks.len() && j < other.chunks.len() {
+            let (a, b) = (&self.chunks[i], &other.chunks[j]);
+            match a.0.cmp(&b.0) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    if let Some(chunk) = a.1.and(&b.1) {
+                        chunks.push((a.0, chunk));
+                    }
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        Bitmap { chunks }
+    }
+
+    /// Returns the positions in either bitmap.
+    pub fn or(&self, other: &Bitmap) -> Bitmap {
+        let mut chunks = Vec::with_capacity(self.chunks.len() + other.chunks.len());
+        let (mut i, mut j) = (0, 0);
+        while i < self.chunks.len() || j < other.chunks.len() {
+            match (self.chunks.get(i), other.chunks.get(j)) {
+                (Some(a), Some(b)) if a.0 == b.0 => {
+                    chunks.push((a.0, a.1.or(&b.1)));
+                    i += 1;
+                    j += 1;
+                }
+                (Some(a), Some(b)) if a.0 < b.0 => {
+                    chunks.push(a.clone());
+                    i += 1;
+                }
+                (Some(a), None) => {
+                    chunks.push(a.clone());
+                    i += 1;
+                }
+                (_, Some(b)) => {
+                    chunks.push(b.clone());
+                    j += 1;
+                }
+                (None, None) => unreachable!(),
+            }
+        }
+        Bitmap { chunks }
+    }
+}
+
+impl FromIterator<u32> for Bitmap {
+    fn from_iter<I: IntoIterator<Item = u32>>(positions: I) -> Self {
+        let mut bitmap = Bitmap::new();
+        for position in positions {
+            bitmap.insert(position);
+        }
+        bitmap.optimize();
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Sparse, dense and run chunks.
+    fn positions(seed: u32) -> BTreeSet<u32> {
+        let sparse = (0..300).map(|i| (i * 7919 + seed) % 65536);
+        let dense = (0..20000).map(|i| 65536 + (i * 3 + seed) % 65536);
+        let runs = (0..10).flat_map(|i| {
+            let start = 3 * 65536 + i * 1000 + seed;
+            start..start + 500
+        });
+        sparse.chain(dense).chain(runs).collect()
+    }
+
+    #[test]
+    fn test_bitmap_forms() {
+        let expected = positions(0);
+        let bitmap: Bitmap = expected.iter().copied().collect();
+        assert!(matches!(bitmap.chunks[0].1, Chunk::Array(_)));
+        assert!(matches!(bitmap.chunks[1].1, Chunk::Bits(_)));
+        assert!(matches!(bitmap.chunks[2].1, Chunk::Runs(_)));
+        assert_eq!(expected.len(), bitmap.len());
+        assert!(bitmap.iter().eq(expected.iter().copied()));
+        assert!(!bitmap.contains(2 * 65536));
+
+        let bytes = serde_cbor::to_vec(&bitmap).unwrap();
+        assert!(bytes.len() < 4 * expected.len() / 2);
+        assert_eq!(bitmap, serde_cbor::from_slice(&bytes).unwrap());
+    }
+
+    #[test]
+    fn test_bitmap_insert_remove() {
+        let mut expected = positions(5);
+        let mut bitmap: Bitmap = expected.iter().copied().collect();
+        for position in (0..4 * 65536).step_by(37) {
+            assert_eq!(expected.insert(position), bitmap.insert(position));
+        }
+        for position in (0..4 * 65536).step_by(11) {
+            assert_eq!(expected.remove(&position), bitmap.remove(position));
+        }
+        assert!(bitmap.iter().eq(expected.iter().copied()));
+        for position in expected.clone() {
+            bitmap.remove(position);
+        }
+        assert!(bitmap.is_empty());
+    }
+
+    #[test]
+    fn test_bitmap_and_or() {
+        let (a, b) = (positions(0), positions(1));
+        let (x, y): (Bitmap, Bitmap) = (a.iter().copied().collect(), b.iter().copied().collect());
+        assert!(x.and(&y).iter().eq(a.intersection(&b).copied()));
+        assert!(x.or(&y).iter().eq(a.union(&b).copied()));
+        assert!(x.and(&Bitmap::new()).is_empty());
+        assert_eq!(x, x.or(&Bitmap::new()));
+    }
+}
@@ -0,0 +1,326 @@
+//! Bitmap indexes.
+//!
+//! A bitmap index suits columns with few distinct values. It keeps, for each key, a
+//! compressed bitmap (see `bitmap`) of the records that have the key, so the records that
+//! satisfy a combination of comparisons on indexed columns are found by intersecting and
+//! uniting bitmaps before any record is read.
+//!
+//! A record is the position `page_id << 16 | slot_id` in the bitmaps of its table. Each
+//! value of the index container holds the part of the bitmap of one key for the records of
+//! one page, the chunk of the bitmap that shares the page id as its high 16 bits.
+
+use crate::bitmap::Bitmap;
+use crate::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use crate::index::{IndexInfo, KeyRange};
+use crate::storage_trait::StorageTrait;
+use crate::{CrustyError, Field};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::RwLock;
+
+/// Held for reading by lookups and for writing by changes, so concurrent changes of the
+/// same chunk do not lose records.
+static BITMAP_INDEX_LOCK: RwLock<()> = RwLock::new(());
+
+/// Chunk of the bitmap of a key, stored as a value of the index container.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BitmapEntry {
+    key: Vec<Field>,
+    /// High 16 bits of the positions of the chunk.
+    high: u16,
+    /// Whether the value ids of the records have page ids.
+    page_ids: bool,
+    records: Bitmap,
+}
+
+impl BitmapEntry {
+    fn to_bytes(&self) -> Vec<u8> {
+        serde_cbor::to_vec(&self).unwrap()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CrustyError> {
+        serde_cbor::from_slice(bytes)
+            .map_err(|_| CrustyError::CrustyError(String::from("Corrupt bitmap index entry")))
+    }
+}
+
+/// Returns the position of a record in the bitmaps.
+fn position(value_id: &ValueId) -> Result<u32, CrustyError> {
+    match (value_id.segment_id, value_id.slot_id) {
+        (None, Some(slot_id)) => {
+            Ok(((value_id.page_id.unwrap_or(0) as u32) << 16) | slot_id as u32)
+        }
+        _ => Err(CrustyError::CrustyError(format!(
+            "Record {:?} cannot be stored in a bitmap index",
+            value_id
+        ))),
+    }
+}
+
+/// Records of a table, as a bitmap of their positions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordSet {
+    table_id: ContainerId,
+    /// Whether the value ids of the records have page ids, or None if no record was seen.
+    page_ids: Option<bool>,
+    records: Bitmap,
+}
+
+impl RecordSet {
+    /// Creates an empty set of records of a table.
+    pub fn new(table_id: ContainerId) -> Self {
+        RecordSet {
+            table_id,
+            page_ids: None,
+            records: Bitmap::new(),
+        }
+    }
+
+    /// Number of records in the set.
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Returns the records in both sets.
+    pub fn and(&self, other: &RecordSet) -> RecordSet {
+        RecordSet {
+            table_id: self.table_id,
+            page_ids: self.page_ids.or(other.page_ids),
+            records: self.records.and(&other.records),
+        }
+    }
+
+    /// Returns the records in either set.
+    pub fn or(&self, other: &RecordSet) -> RecordSet {
+        RecordSet {
+            table_id: self.table_id,
+            page_ids: self.page_ids.or(other.page_ids),
+            records: self.records.or(&other.records),
+        }
+    }
+
+    /// Returns the value ids of the records, in the order of their pages and slots.
+    pub fn value_ids(&self) -> Vec<ValueId> {
+        let page_ids = self.page_ids.unwrap_or(false);
+        self.records
+            .iter()
+            .map(|position| ValueId {
+                container_id: self.table_id,
+                segment_id: None,
+                page_id: page_ids.then_some((position >> 16) as u16),
+                slot_id: Some(position as u16),
+            })
+            .collect()
+    }
+}
+
+/// Combination of key ranges of bitmap indexes over one table, evaluated by combining their
+/// bitmaps.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BitmapQuery {
+    /// The records whose keys in an index are in a range.
+    Keys(IndexInfo, KeyRange),
+    /// The records that satisfy all the queries.
+    And(Vec<BitmapQuery>),
+    /// The records that satisfy any of the queries.
+    Or(Vec<BitmapQuery>),
+}
+
+impl BitmapQuery {
+    /// Returns the records of a table that satisfy the query.
+    ///
+    /// # Arguments
+    ///
+    /// * `sm` - Storage manager holding the indexes.
+    /// * `table_id` - Table the indexes are built over.
+    /// * `tid` - Transaction reading the indexes.
+    pub fn evaluate<T: StorageTrait>(
+        &self,
+        sm: &T,
+        table_id: ContainerId,
+        tid: TransactionId,
+    ) -> Result<RecordSet, CrustyError> {
+        match self {
+            BitmapQuery::Keys(index, range) => scan(sm, index, range, tid),
+            BitmapQuery::And(queries) => {
+                let mut records: Option<RecordSet> = None;
+                for query in queries {
+                    let next = query.evaluate(sm, table_id, tid)?;
+                    let result = match records {
+                        Some(records) => records.and(&next),
+                        None => next,
+                    };
+                    if result.is_empty() {
+                        return Ok(result);
+                    }
+                    records = Some(result);
+                }
+                Ok(records.unwrap_or_else(|| RecordSet::new(table_id)))
+            }
+            BitmapQuery::Or(queries) => {
+                let mut records = RecordSet::new(table_id);
+                for query in queries {
+                    records = records.or(&query.evaluate(sm, table_id, tid)?);
+                }
+                Ok(records)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BitmapQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (queries, op) = match self {
+            BitmapQuery::Keys(index, range) => return write!(f, "{} {}", index.name, range),
+            BitmapQuery::And(queries) => (queries, " and "),
+            BitmapQuery::Or(queries) => (queries, " or "),
+        };
+        let queries: Vec<String> = queries.iter().map(|q| q.to_string()).collect();
+        write!(f, "({})", queries.join(op))
+    }
+}
+
+/// Adds entries to a bitmap index.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Bitmap index to update.
+/// * `entries` - Keys and records of the entries.
+/// * `tid` - Transaction adding the entries.
+pub fn insert<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    entries: Vec<(Vec<Field>, ValueId)>,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    let mut chunks: HashMap<(Vec<Field>, u16), BitmapEntry> = HashMap::new();
+    for (key, value_id) in entries {
+        let position = position(&value_id)?;
+        let high = (position >> 16) as u16;
+        chunks
+            .entry((key.clone(), high))
+            .or_insert_with(|| BitmapEntry {
+                key,
+                high,
+                page_ids: value_id.page_id.is_some(),
+                records: Bitmap::new(),
+            })
+            .records
+            .insert(position);
+    }
+    let _lock = BITMAP_INDEX_LOCK.write().unwrap();
+    let stored: Vec<(Vec<u8>, ValueId)> = sm
+        .get_iterator(index.container_id, tid, Permissions::ReadOnly)
+        .collect();
+    for (bytes, id) in stored {
+        let mut entry = BitmapEntry::from_bytes(&bytes)?;
+        if let Some(new) = chunks.remove(&(entry.key.clone(), entry.high)) {
+            entry.records = entry.records.or(&new.records);
+            entry.records.optimize();
+            sm.update_value(entry.to_bytes(), id, tid)?;
+        }
+    }
+    let values = chunks
+        .into_values()
+        .map(|mut entry| {
+            entry.records.optimize();
+            entry.to_bytes()
+        })
+        .collect();
+    sm.insert_values(index.container_id, values, tid)?;
+    Ok(())
+}
+
+/// Removes the entries of records from a bitmap index and returns the number of removed
+/// entries.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Bitmap index to update.
+/// * `value_ids` - Records whose entries are removed.
+/// * `tid` - Transaction removing the entries.
+pub fn remove<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    value_ids: &HashSet<ValueId>,
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    let removed: Bitmap = value_ids
+        .iter()
+        .filter(|id| id.container_id == index.table_id)
+        .filter_map(|id| position(id).ok())
+        .collect();
+    let _lock = BITMAP_INDEX_LOCK.write().unwrap();
+    let stored: Vec<(Vec<u8>, ValueId)> = sm
+        .get_iterator(index.container_id, tid, Permissions::ReadOnly)
+        .collect();
+    let mut count = 0;
+    for (bytes, id) in stored {
+        let mut entry = BitmapEntry::from_bytes(&bytes)?;
+        let stale = entry.records.and(&removed);
+        if stale.is_empty() {
+            continue;
+        }
+        count += stale.len();
+        for position in stale.iter() {
+            entry.records.remove(position);
+        }
+        if entry.records.is_empty() {
+            sm.delete_value(id, tid)?;
+        } else {
+            entry.records.optimize();
+            sm.update_value(entry.to_bytes(), id, tid)?;
+        }
+    }
+    Ok(count)
+}
+
+/// Returns the records of the table whose keys are in a range.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Bitmap index to search.
+/// * `range` - Keys to return the records of.
+/// * `tid` - Transaction reading the index.
+pub fn scan<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    range: &KeyRange,
+    tid: TransactionId,
+) -> Result<RecordSet, CrustyError> {
+    let _lock = BITMAP_INDEX_LOCK.read().unwrap();
+    let mut records = RecordSet::new(index.table_id);
+    for (bytes, _) in sm.get_iterator(index.container_id, tid, Permissions::ReadOnly) {
+        let entry = BitmapEntry::from_bytes(&bytes)?;
+        if range.contains(&entry.key) {
+            records.page_ids = Some(entry.page_ids);
+            records.records = records.records.or(&entry.records);
+        }
+    }
+    Ok(records)
+}
+
+/// Returns the keys in a bitmap index.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Bitmap index to read.
+/// * `tid` - Transaction reading the index.
+pub fn keys<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    tid: TransactionId,
+) -> Result<HashSet<Vec<Field>>, CrustyError> {
+    let _lock = BITMAP_INDEX_LOCK.read().unwrap();
+    sm.get_iterator(index.container_id, tid, Permissions::ReadOnly)
+        .map(|(bytes, _)| Ok(BitmapEntry::from_bytes(&bytes)?.key))
+        .collect()
+}
@@ -0,0 +1,317 @@
+//! Bloom filters over a key column of a container.
+//!
+//! A storage manager keeps at most one filter per container, over a column designated when
+//! the filter is created. The filter is maintained as values are inserted and deleted, so
+//! `may_contain` answers false only if no value of the container has the key. Operators use
+//! it to skip probes for keys that cannot be found, such as the probes of a hash join or
+//! the scan of a unique index for a new key.
+//!
+//! Filters count the keys hashed to each position rather than setting bits, so deleting a
+//! value can remove its key. A counter that saturates is never decremented again.
+
+use crate::encoding;
+use crate::ids::ContainerId;
+use crate::{CrustyError, Field};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+
+/// Counters allocated per expected key, for a false positive rate of about 1%.
+const COUNTERS_PER_KEY: usize = 10;
+/// Positions each key is hashed to.
+const HASHES: u64 = 7;
+/// Smallest number of counters of a filter.
+const MIN_COUNTERS: usize = 64;
+
+/// A counting bloom filter over one column of the tuples stored in a container.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    /// Index of the key column in the stored tuples.
+    key_column: usize,
+    counters: Vec<u8>,
+    /// Whether a value whose key could not be read was inserted. The filter then cannot
+    /// rule out any key.
+    incomplete: bool,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `key_column` - Index of the key column in the stored tuples.
+    /// * `expected_keys` - Number of keys the filter is sized for. More keys raise the
+    ///   false positive rate.
+    pub fn new(key_column: usize, expected_keys: usize) -> Self {
+        BloomFilter {
+            key_column,
+            counters: vec![0; (expected_keys * COUNTERS_PER_KEY).max(MIN_COUNTERS)],
+            incomplete: false,
+        }
+    }
+
+    /// Index of the key column in the stored tuples.
+    pub fn key_column(&self) -> usize {
+        self.key_column
+    }
+
+    /// Removes all the keys, keeping the size and key column of the filter.
+    pub fn clear(&mut self) {
+        self.counters.fill(0);
+        self.incomplete = false;
+    }
+
+    /// Counters the key is hashed to.
+    fn positions(&self, key: &Field) -> impl Iterator<Item = usize> {
+        let bytes = key.to_bytes();
+        let h1 = fnv1a(&bytes, 0xcbf2_9ce4_8422_2325);
+        // odd, so the positions of a key differ while HASHES < counters
+        let h2 = fnv1a(&bytes, 0x8422_2325_cbf2_9ce4) | 1;
+        let len = self.counters.len() as u64;
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    /// Returns the key of a stored value, None if it is null, or an error if the value is
+    /// not a tuple with the key column.
+    fn key(&self, value: &[u8]) -> Result<Option<Field>, CrustyError> {
+        let tuple = encoding::decode(value)?;
+        match tuple.get_field(self.key_column) {
+            Some(Field::Null) => Ok(None),
+            Some(field) => Ok(Some(field.clone())),
+            None => Err(CrustyError::CrustyError(format!(
+                "Value has no column {}",
+                self.key_column
+            ))),
+        }
+    }
+
+    /// Adds the key of a stored value.
+    pub fn insert(&mut self, value: &[u8]) {
+        match self.key(value) {
+            Ok(Some(key)) => {
+                for i in self.positions(&key) {
+                    self.counters[i] = self.counters[i].saturating_add(1);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => {
+                warn!("Bloom filter cannot read the key of a value: {}", e);
+                self.incomplete = true;
+            }
+        }
+    }
+
+    /// Removes the key of a deleted value. The value must have been inserted.
+    pub fn remove(&mut self, value: &[u8]) {
+        if let Ok(Some(key)) = self.key(value) {
+            for i in self.positions(&key) {
+                if self.counters[i] != u8::MAX {
+                    self.counters[i] = self.counters[i].saturating_sub(1);
+                }
+            }
+        }
+    }
+
+    /// Returns false if no value in the filter has the key. Null keys are never contained,
+    /// as null is not equal to any key.
+    pub fn may_contain(&self, key: &Field) -> bool {
+        if self.incomplete {
+            return true;
+        }
+        if *key == Field::Null {
+            return false;
+        }
+        self.positions(key).all(|i| self.counters[i] > 0)
+    }
+}
+
+/// 64-bit FNV-1a hash of bytes, starting from `seed`. Filters and hash indexes are
+/// persisted, so the hash must not change between runs.
+pub(crate) fn fnv1a(bytes: &[u8], seed: u64) -> u64 {
+    bytes.iter().fold(seed, |hash, b| {
+        (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The bloom filters of the containers of a storage manager.
+#[derive(Default)]
+pub struct BloomFilters {
+    filters: RwLock<HashMap<ContainerId, BloomFilter>>,
+}
+
+impl BloomFilters {
+    /// Replaces the filter of a container by an empty one. Existing values of the
+    /// container must be added with `insert`.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to filter.
+    /// * `key_column` - Index of the key column in the stored tuples.
+    /// * `expected_keys` - Number of keys the filter is sized for.
+    pub fn create(&self, container_id: ContainerId, key_column: usize, expected_keys: usize) {
+        self.filters
+            .write()
+            .unwrap()
+            .insert(container_id, BloomFilter::new(key_column, expected_keys));
+    }
+
+    /// Drops the filter of a container, if it has one.
+    pub fn remove_container(&self, container_id: ContainerId) {
+        self.filters.write().unwrap().remove(&container_id);
+    }
+
+    /// Moves the filter of a container, if it has one, to a new container id.
+    pub fn rename_container(&self, container_id: ContainerId, new_container_id: ContainerId) {
+        let mut filters = self.filters.write().unwrap();
+        if let Some(filter) = filters.remove(&container_id) {
+            filters.insert(new_container_id, filter);
+        }
+    }
+
+    /// Removes all the keys of the filter of a container, e.g. when it is truncated.
+    pub fn clear_container(&self, container_id: ContainerId) {
+        if let Some(filter) = self.filters.write().unwrap().get_mut(&container_id) {
+            filter.clear();
+        }
+    }
+
+    /// Drops all the filters.
+    pub fn clear(&self) {
+        self.filters.write().unwrap().clear();
+    }
+
+    /// Returns the key column of the filter of a container, if it has one.
+    pub fn key_column(&self, container_id: ContainerId) -> Option<usize> {
+        self.filters
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .map(BloomFilter::key_column)
+    }
+
+    /// Adds the key of a value inserted into a container. Storage managers call it before
+    /// the value becomes readable, so `may_contain` never misses a stored key.
+    pub fn insert(&self, container_id: ContainerId, value: &[u8]) {
+        if let Some(filter) = self.filters.write().unwrap().get_mut(&container_id) {
+            filter.insert(value);
+        }
+    }
+
+    /// Removes the key of a value deleted from a container.
+    pub fn remove(&self, container_id: ContainerId, value: &[u8]) {
+        if let Some(filter) = self.filters.write().unwrap().get_mut(&container_id) {
+            filter.remove(value);
+        }
+    }
+
+    /// Returns false if no value of the container has the key in the key column of its
+    /// filter. Always true for a container without a filter.
+    pub fn may_contain(&self, container_id: ContainerId, key: &Field) -> bool {
+        match self.filters.read().unwrap().get(&container_id) {
+            Some(filter) => filter.may_contain(key),
+            None => true,
+        }
+    }
+
+    /// Writes the filters to a file.
+    pub fn save(&self, path: &Path) -> Result<(), CrustyError> {
+        let filters = self.filters.read().unwrap();
+        let bytes = serde_cbor::to_vec(&*filters)
+            .map_err(|e| CrustyError::CrustyError(format!("Cannot serialize filters: {}", e)))?;
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    /// Reads filters written by `save`. There are no filters if the file does not exist.
+    pub fn load(path: &Path) -> Result<Self, CrustyError> {
+        let filters = match fs::read(path) {
+            Ok(bytes) => serde_cbor::from_slice(&bytes).map_err(|e| {
+                CrustyError::CrustyError(format!("Cannot read bloom filters: {}", e))
+            })?,
+            Err(_) => HashMap::new(),
+        };
+        Ok(BloomFilters {
+            filters: RwLock::new(filters),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Tuple;
+
+    fn value(key: Field) -> Vec<u8> {
+        Tuple::new(vec![Field::IntField(0), key]).to_bytes()
+    }
+
+    #[test]
+    fn test_bloom_insert_remove() {
+        let mut filter = BloomFilter::new(1, 100);
+        for i in 0..100 {
+            filter.insert(&value(Field::IntField(i)));
+        }
+        assert!((0..100).all(|i| filter.may_contain(&Field::IntField(i))));
+        let false_positives = (100..1100)
+            .filter(|i| filter.may_contain(&Field::IntField(*i)))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+
+        for i in 0..50 {
+            filter.remove(&value(Field::IntField(i)));
+        }
+        assert!((50..100).all(|i| filter.may_contain(&Field::IntField(i))));
+        assert!(
+            (0..50)
+                .filter(|i| filter.may_contain(&Field::IntField(*i)))
+                .count()
+                < 5
+        );
+    }
+
+    #[test]
+    fn test_bloom_null_and_unreadable_keys() {
+        let mut filter = BloomFilter::new(1, 10);
+        filter.insert(&value(Field::Null));
+        assert!(!filter.may_contain(&Field::Null));
+        assert!(!filter.may_contain(&Field::StringField(String::from("a"))));
+        // a value without the key column could have any key
+        filter.insert(&Tuple::new(vec![Field::IntField(1)]).to_bytes());
+        assert!(filter.may_contain(&Field::StringField(String::from("a"))));
+    }
+
+    #[test]
+    fn test_bloom_filters_save_load() {
+        let filters = BloomFilters::default();
+        filters.create(1, 1, 10);
+        filters.insert(1, &value(Field::IntField(7)));
+        filters.insert(2, &value(Field::IntField(7)));
+        assert_eq!(Some(1), filters.key_column(1));
+        assert_eq!(None, filters.key_column(2));
+        assert!(filters.may_contain(2, &Field::IntField(8)));
+
+        let path = crate::testutil::gen_random_test_sm_dir();
+        fs::create_dir_all(&path).unwrap();
+        filters.save(&path.join("bloom")).unwrap();
+        let loaded = BloomFilters::load(&path.join("bloom")).unwrap();
+        assert!(loaded.may_contain(1, &Field::IntField(7)));
+        assert!(!loaded.may_contain(1, &Field::IntField(8)));
+        assert!(BloomFilters::load(&path.join("missing")).is_ok());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_bloom_filters_rename_clear() {
+        let filters = BloomFilters::default();
+        filters.create(1, 1, 10);
+        filters.insert(1, &value(Field::IntField(7)));
+        filters.rename_container(1, 3);
+        assert_eq!(None, filters.key_column(1));
+        assert!(filters.may_contain(3, &Field::IntField(7)));
+        assert!(!filters.may_contain(3, &Field::IntField(8)));
+        filters.clear_container(3);
+        assert_eq!(Some(1), filters.key_column(3));
+        assert!(!filters.may_contain(3, &Field::IntField(7)));
+    }
+}
@@ -1,5 +1,7 @@
 use crate::ids::StateType;
+use crate::index::IndexInfo;
 use crate::prelude::*;
+use crate::statistics::TableStats;
 use crate::table::*;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
@@ -79,6 +81,24 @@ pub trait Catalog {
         }
     }
 
+    /// Gets the statistics of a table, if it has been analyzed.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Id of table to get the statistics for.
+    fn get_table_stats(&self, _table_id: ContainerId) -> Option<TableStats> {
+        None
+    }
+
+    /// Gets the indexes built over a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Id of table to get the indexes for.
+    fn get_indexes(&self, _table_id: ContainerId) -> Vec<IndexInfo> {
+        Vec::new()
+    }
+
     /// Gets the table name from the catalog.
     ///
     /// # Arguments
@@ -0,0 +1,138 @@
+//! Column-oriented storage for analytical tables.
+//!
+//! A table created `WITH (storage = 'column')` stores each column in its own container, as
+//! chunks holding the values of the column for a group of up to `CHUNK_ROWS` rows. Inserts
+//! append whole row groups: one chunk per column, and a `RowGroup` record in the table's own
+//! container pointing to the chunks. A scan walks the row groups and reads only the chunks of
+//! the columns it needs, so aggregates over a few columns of a wide table skip most of it.
+//!
+//! Columnar tables are append only. The value id of a row is the id of its row group record.
+
+use crate::encoding;
+use crate::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use crate::storage_trait::StorageTrait;
+use crate::{CrustyError, Field, Tuple};
+
+/// Maximum number of rows of a row group.
+pub const CHUNK_ROWS: usize = 1024;
+
+/// A group of rows of a columnar table, stored as a value of the table's container.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RowGroup {
+    /// Number of rows in the group.
+    pub rows: usize,
+    /// Chunks of the group, one per column in schema order.
+    pub chunks: Vec<ValueId>,
+}
+
+impl RowGroup {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_cbor::to_vec(&self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        serde_cbor::from_slice(bytes).unwrap()
+    }
+}
+
+/// Appends records to a columnar table, in row groups of at most `CHUNK_ROWS` records.
+/// Returns the value id of every record, the id of its row group.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `table_id` - Container of the table, holding its row groups.
+/// * `columns` - Containers of the columns of the table, in schema order.
+/// * `tuples` - Records to append.
+/// * `tid` - Transaction appending the records.
+pub fn insert<T: StorageTrait>(
+    sm: &T,
+    table_id: ContainerId,
+    columns: &[ContainerId],
+    tuples: &[Tuple],
+    tid: TransactionId,
+) -> Result<Vec<ValueId>, CrustyError> {
+    let mut value_ids = Vec::with_capacity(tuples.len());
+    for rows in tuples.chunks(CHUNK_ROWS) {
+        let chunks = columns
+            .iter()
+            .enumerate()
+            .map(|(i, container_id)| {
+                let values = rows
+                    .iter()
+                    .map(|tuple| tuple.get_field(i).cloned().unwrap_or(Field::Null))
+                    .collect();
+                sm.insert_value(*container_id, encoding::encode(&Tuple::new(values)), tid)
+            })
+            .collect::<Result<_, _>>()?;
+        let group = RowGroup {
+            rows: rows.len(),
+            chunks,
+        };
+        let group_id = sm.insert_value(table_id, group.to_bytes(), tid)?;
+        value_ids.extend(std::iter::repeat_n(group_id, rows.len()));
+    }
+    Ok(value_ids)
+}
+
+/// Reads the records of a row group. Only the chunks of `columns` are read, the other
+/// columns of the records are null.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `group` - Row group to read.
+/// * `columns` - Indices of the columns to read.
+/// * `tid` - Transaction reading the records.
+pub fn read_group<T: StorageTrait>(
+    sm: &T,
+    group: &RowGroup,
+    columns: &[usize],
+    tid: TransactionId,
+) -> Result<Vec<Tuple>, CrustyError> {
+    let mut tuples = vec![Tuple::new(vec![Field::Null; group.chunks.len()]); group.rows];
+    for i in columns {
+        let chunk_id = group.chunks.get(*i).ok_or_else(|| {
+            CrustyError::CrustyError(format!("Row group has no chunk for column {}", i))
+        })?;
+        let values = encoding::decode(&sm.get_value(*chunk_id, tid, Permissions::ReadOnly)?)?;
+        if values.size() != group.rows {
+            return Err(CrustyError::CrustyError(format!(
+                "Chunk of column {} has {} values for {} rows",
+                i,
+                values.size(),
+                group.rows
+            )));
+        }
+        for (tuple, value) in tuples.iter_mut().zip(values.field_vals) {
+            tuple.field_vals[*i] = value;
+        }
+    }
+    Ok(tuples)
+}
+
+/// Reads all the columns of the records of the row groups yielded by `groups`, an iterator
+/// over values of a columnar table's container. Records get the value id of their group.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `groups` - Row group records and their value ids.
+/// * `width` - Number of columns of the table.
+/// * `tid` - Transaction reading the records.
+pub fn read_records<T: StorageTrait>(
+    sm: &T,
+    groups: impl Iterator<Item = (Vec<u8>, ValueId)>,
+    width: usize,
+    tid: TransactionId,
+) -> Result<Vec<Tuple>, CrustyError> {
+    let columns: Vec<usize> = (0..width).collect();
+    let mut records = Vec::new();
+    for (bytes, value_id) in groups {
+        for mut tuple in read_group(sm, &RowGroup::from_bytes(&bytes), &columns, tid)? {
+            tuple.value_id = Some(value_id);
+            records.push(tuple);
+        }
+    }
+    Ok(records)
+}
@@ -5,6 +5,8 @@ pub enum Commands {
     Create(String),
     /// Connect to a database.
     Connect(String),
+    /// Drop a database.
+    Drop(String),
     /// Import a database.
     Import(String),
     /// Execute SQL statement
@@ -33,6 +35,12 @@ pub enum Commands {
     QuietMode,
     /// Generates CSV table
     Generate(String),
+    /// Attach the storage directory at a path under an alias.
+    Attach(String, String),
+    /// Detach the storage directory attached under an alias.
+    Detach(String),
+    /// List the attached storage directories.
+    ShowAttached,
     /// Test
     Test,
 }
@@ -66,6 +74,10 @@ pub fn parse_command(mut cmd: String) -> Option<Commands> {
 
     //FIXME:ae maps commands to help/enum
 
+    if let Some(attach_cmd) = parse_attach(&cmd) {
+        return attach_cmd;
+    }
+
     if !cmd.starts_with('\\') {
         return Some(Commands::ExecuteSQL(cmd));
     }
@@ -76,6 +88,9 @@ pub fn parse_command(mut cmd: String) -> Option<Commands> {
     } else if let Some(clean_cmd) = cmd.strip_prefix("\\c ") {
         // usage: \c <name>
         return Some(Commands::Connect(clean_cmd.to_string()));
+    } else if let Some(clean_cmd) = cmd.strip_prefix("\\drop ") {
+        // usage: \drop <name>
+        return Some(Commands::Drop(clean_cmd.to_string()));
     } else if let Some(clean_cmd) = cmd.strip_prefix("\\i ") {
         // usage: \i <path> <table_name>
         return Some(Commands::Import(clean_cmd.to_string()));
@@ -100,6 +115,9 @@ pub fn parse_command(mut cmd: String) -> Option<Commands> {
     } else if cmd == "\\l" {
         // usage: \l
         return Some(Commands::ShowDatabases);
+    } else if cmd == "\\da" {
+        // usage: \da
+        return Some(Commands::ShowAttached);
     } else if cmd == "\\reset" {
         // usage: \reset
         return Some(Commands::Reset);
@@ -119,6 +137,51 @@ pub fn parse_command(mut cmd: String) -> Option<Commands> {
     None
 }
 
+/// Parses `ATTACH [DATABASE] 'path' AS alias` and `DETACH [DATABASE] alias`.
+///
+/// Returns None if `cmd` is neither statement, and Some(None) if it is one but malformed.
+fn parse_attach(cmd: &str) -> Option<Option<Commands>> {
+    let cmd = cmd.trim().trim_end_matches(';');
+    let mut tokens = cmd.split_whitespace();
+    let keyword = tokens.next()?.to_uppercase();
+    if keyword != "ATTACH" && keyword != "DETACH" {
+        return None;
+    }
+    let mut rest = cmd[keyword.len()..].trim_start();
+    if rest.len() >= 9 && rest[..9].eq_ignore_ascii_case("DATABASE ") {
+        rest = rest[9..].trim_start();
+    }
+
+    if keyword == "DETACH" {
+        if rest.is_empty() || rest.contains(char::is_whitespace) {
+            info!("Invalid DETACH received {}", cmd);
+            return Some(None);
+        }
+        return Some(Some(Commands::Detach(rest.to_string())));
+    }
+
+    // The path may be quoted so that it can contain spaces.
+    let (path, rest) = match rest.chars().next() {
+        Some(quote @ ('\'' | '"')) => match rest[1..].find(quote) {
+            Some(end) => (&rest[1..end + 1], &rest[end + 2..]),
+            None => return Some(None),
+        },
+        _ => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+    };
+    let mut tokens = rest.split_whitespace();
+    match (tokens.next(), tokens.next(), tokens.next()) {
+        (Some(as_kw), Some(alias), None)
+            if as_kw.eq_ignore_ascii_case("AS") && !path.is_empty() =>
+        {
+            Some(Some(Commands::Attach(path.to_string(), alias.to_string())))
+        }
+        _ => {
+            info!("Invalid ATTACH received {}", cmd);
+            Some(None)
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -141,6 +204,15 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_drop() {
+        let drop: String = String::from("\\drop name");
+        assert_eq!(
+            Commands::Drop("name".to_string()),
+            parse_command(drop).unwrap()
+        );
+    }
+
     #[test]
     fn test_import() {
         let import: String = String::from("\\i path name");
@@ -161,4 +233,26 @@ mod test {
         let show_tables: String = String::from("\\dt\n");
         assert_eq!(Commands::ShowTables, parse_command(show_tables).unwrap());
     }
+
+    #[test]
+    fn test_attach() {
+        assert_eq!(
+            Commands::Attach("/tmp/other db".to_string(), "ext".to_string()),
+            parse_command(String::from("ATTACH '/tmp/other db' AS ext;\n")).unwrap()
+        );
+        assert_eq!(
+            Commands::Attach("/tmp/other".to_string(), "ext".to_string()),
+            parse_command(String::from("attach database /tmp/other as ext")).unwrap()
+        );
+        assert_eq!(
+            Commands::Detach("ext".to_string()),
+            parse_command(String::from("DETACH ext")).unwrap()
+        );
+        assert!(parse_command(String::from("ATTACH '/tmp/other' ext")).is_none());
+        assert!(parse_command(String::from("ATTACH '/tmp/other AS ext")).is_none());
+        assert_eq!(
+            Commands::ExecuteSQL("attachments".to_string()),
+            parse_command(String::from("attachments")).unwrap()
+        );
+    }
 }
@@ -153,6 +153,40 @@ impl<T> CrustyGraph<T> {
         node.outgoing_edge = Some(index);
     }
 
+    /// Points the edge from source to target at new_target instead, keeping its place among
+    /// the edges of source. Returns false if there is no such edge.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source node of the edge.
+    /// * `target` - Current target node of the edge.
+    /// * `new_target` - Target node to point the edge at.
+    ///
+    /// # Panics
+    ///
+    /// if new_target is not a valid NodeIndex
+    pub fn retarget_edge(
+        &mut self,
+        source: NodeIndex,
+        target: NodeIndex,
+        new_target: NodeIndex,
+    ) -> bool {
+        if !self.node_index_exists(new_target) {
+            panic!("Target node does not exist");
+        }
+        match self
+            .edges
+            .iter_mut()
+            .find(|e| e.source == source && e.target == target)
+        {
+            Some(edge) => {
+                edge.target = new_target;
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Returns an iterator over all NodeIndex's that have an edge from source.
     ///
     /// Edges iterated in the reverse order of how they were added.
@@ -171,6 +205,11 @@ impl<T> CrustyGraph<T> {
         self.nodes.get(node).map(|n| &n.data)
     }
 
+    /// Mutable access to the data for a node
+    pub fn node_data_mut(&mut self, node: NodeIndex) -> Option<&mut T> {
+        self.nodes.get_mut(node).map(|n| &mut n.data)
+    }
+
     /// Iterator over all nodes in the graph.
     ///
     /// Iterates over NodeIndex's and their corresponding Node structs. Returned iterator shares lifetime of self.
@@ -383,6 +422,21 @@ mod test {
         }
     }
 
+    #[test]
+    fn retarget_edge() {
+        let mut graph = CrustyGraph::<i32>::new();
+        let parent = graph.add_node(0);
+        let a = graph.add_node(1);
+        let b = graph.add_node(2);
+        let c = graph.add_node(3);
+        graph.add_edge(parent, a);
+        graph.add_edge(parent, b);
+
+        assert!(graph.retarget_edge(parent, a, c));
+        assert_eq!(vec![b, c], graph.edges(parent).collect::<Vec<_>>());
+        assert!(!graph.retarget_edge(parent, a, c));
+    }
+
     #[test]
     fn node() {
         let mut graph = CrustyGraph::<i32>::new();
@@ -0,0 +1,156 @@
+//! Writing the records of a container to a csv file.
+//!
+//! Records are written one at a time as they are read, so a container is never held in
+//! memory. With the default options the file can be loaded back with
+//! `StorageTrait::import_csv`: no header row, commas, and nulls written as `null`.
+
+use crate::{CrustyError, Field, TableSchema, Tuple};
+use std::path::Path;
+
+/// Which fields are quoted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvQuoting {
+    /// Only fields holding the delimiter, a quote, or a line break.
+    Necessary,
+    /// Every field.
+    Always,
+    /// Every field that is not a number.
+    NonNumeric,
+    /// No field, even if that makes the file ambiguous.
+    Never,
+}
+
+/// Format of an exported csv file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvExportOptions {
+    /// Byte separating the fields of a record.
+    pub delimiter: u8,
+    /// Whether the first row holds the names of the columns.
+    pub header: bool,
+    /// Which fields are quoted.
+    pub quoting: CsvQuoting,
+}
+
+impl Default for CsvExportOptions {
+    fn default() -> Self {
+        CsvExportOptions {
+            delimiter: b',',
+            header: false,
+            quoting: CsvQuoting::Necessary,
+        }
+    }
+}
+
+impl CsvExportOptions {
+    /// Separates the fields with a delimiter other than a comma.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Writes the names of the columns as the first row.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// Quotes the fields as given instead of only when necessary.
+    pub fn with_quoting(mut self, quoting: CsvQuoting) -> Self {
+        self.quoting = quoting;
+        self
+    }
+}
+
+/// Writes records to a csv file, replacing the file if it exists. Returns the number of
+/// records written.
+///
+/// # Arguments
+///
+/// * `values` - Serialized tuples of the records.
+/// * `schema` - Schema of the records, naming the columns of the header.
+/// * `path` - Path of the file.
+/// * `options` - Format of the file.
+pub fn export_csv(
+    values: impl Iterator<Item = Vec<u8>>,
+    schema: &TableSchema,
+    path: &Path,
+    options: &CsvExportOptions,
+) -> Result<usize, CrustyError> {
+    let csv_error = |e: csv::Error| CrustyError::IoWithPath {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let quote_style = match options.quoting {
+        CsvQuoting::Necessary => csv::QuoteStyle::Necessary,
+        CsvQuoting::Always => csv::QuoteStyle::Always,
+        CsvQuoting::NonNumeric => csv::QuoteStyle::NonNumeric,
+        CsvQuoting::Never => csv::QuoteStyle::Never,
+    };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(options.delimiter)
+        .quote_style(quote_style)
+        .from_path(path)
+        .map_err(csv_error)?;
+    if options.header {
+        writer
+            .write_record(schema.attributes().map(|attr| attr.name()))
+            .map_err(csv_error)?;
+    }
+    let mut written = 0;
+    for bytes in values {
+        let tuple = Tuple::from_bytes(&bytes);
+        let record = tuple.field_vals().map(|field| match field {
+            // import_csv reads nulls back from this literal
+            Field::Null => String::from("null"),
+            field => field.to_string(),
+        });
+        writer.write_record(record).map_err(csv_error)?;
+        written += 1;
+    }
+    writer
+        .flush()
+        .map_err(|e| CrustyError::io_with_path(path, e))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::gen_random_test_sm_dir;
+    use crate::DataType;
+    use std::fs;
+
+    #[test]
+    fn test_export_csv() {
+        let schema = TableSchema::from_vecs(vec!["a", "b"], vec![DataType::Int, DataType::String]);
+        let tuples = [
+            Tuple::new(vec![
+                Field::IntField(1),
+                Field::StringField("x;y".to_string()),
+            ]),
+            Tuple::new(vec![Field::IntField(2), Field::Null]),
+        ];
+        let dir = gen_random_test_sm_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.csv");
+
+        let values = tuples.iter().map(|t| t.to_bytes());
+        assert_eq!(
+            2,
+            export_csv(values, &schema, &path, &CsvExportOptions::default()).unwrap()
+        );
+        assert_eq!("1,x;y\n2,null\n", fs::read_to_string(&path).unwrap());
+
+        let options = CsvExportOptions::default()
+            .with_delimiter(b';')
+            .with_header()
+            .with_quoting(CsvQuoting::NonNumeric);
+        let values = tuples.iter().map(|t| t.to_bytes());
+        export_csv(values, &schema, &path, &options).unwrap();
+        assert_eq!(
+            "\"a\";\"b\"\n1;\"x;y\"\n2;\"null\"\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
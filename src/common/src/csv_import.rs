@@ -0,0 +1,395 @@
+//! Reading the records of a csv file into a container.
+//!
+//! The cells of a record are parsed as the types of the columns of the table, in order, and
+//! the cell `null` is a null field. A record that cannot be parsed, or that has a null in a
+//! NOT NULL column, is handled by the `CsvErrorPolicy` of the import: the import stops with
+//! the line of the record, or the record is skipped and counted, optionally copied to a
+//! dead-letter file to be fixed and imported again. Storage errors always stop the import.
+//!
+//! `infer_schema` samples the first records of a file to pick the types of the columns of a
+//! table to import it into.
+
+use crate::{
+    Attribute, CrustyError, DataType, Date, Decimal, Field, Float, TableSchema, Timestamp, Tuple,
+};
+use csv::{ByteRecord, Reader, ReaderBuilder, Writer, WriterBuilder};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// What an import does with a record that cannot be read as a record of the table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CsvErrorPolicy {
+    /// Stop the import with the line of the record, keeping the records imported before it.
+    Abort,
+    /// Leave the record out and count it.
+    Skip,
+    /// Leave the record out, count it, and append it to a csv file with the same delimiter.
+    /// The file is created, or replaced, when the first record is rejected.
+    DeadLetter(PathBuf),
+}
+
+/// Format of an imported csv file and handling of its bad records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvImportOptions {
+    /// Byte separating the fields of a record.
+    pub delimiter: u8,
+    /// Whether the first row holds the names of the columns rather than a record.
+    pub header: bool,
+    /// What to do with records that cannot be read.
+    pub on_error: CsvErrorPolicy,
+}
+
+impl Default for CsvImportOptions {
+    fn default() -> Self {
+        CsvImportOptions {
+            delimiter: b',',
+            header: false,
+            on_error: CsvErrorPolicy::Abort,
+        }
+    }
+}
+
+impl CsvImportOptions {
+    /// Reads fields separated by a delimiter other than a comma.
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Skips the first row, which holds the names of the columns.
+    pub fn with_header(mut self) -> Self {
+        self.header = true;
+        self
+    }
+
+    /// Handles bad records as given instead of stopping the import.
+    pub fn with_error_policy(mut self, on_error: CsvErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    fn reader(&self, path: &Path) -> Result<Reader<File>, CrustyError> {
+        ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.header)
+            // Records of the wrong width are handled by the error policy.
+            .flexible(true)
+            .from_path(path)
+            .map_err(|e| csv_error(path, e))
+    }
+}
+
+/// Outcome of an import.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CsvImportReport {
+    /// Records inserted.
+    pub imported: usize,
+    /// Records left out by the error policy.
+    pub skipped: usize,
+}
+
+fn csv_error(path: &Path, e: csv::Error) -> CrustyError {
+    CrustyError::IoWithPath {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    }
+}
+
+/// Reads the records of a csv file and hands them to `insert`.
+///
+/// # Arguments
+///
+/// * `schema` - Schema of the table the records are imported into.
+/// * `path` - Path of the file.
+/// * `options` - Format of the file and handling of its bad records.
+/// * `insert` - Stores a record.
+pub fn import_csv(
+    schema: &TableSchema,
+    path: &Path,
+    options: &CsvImportOptions,
+    mut insert: impl FnMut(Tuple) -> Result<(), CrustyError>,
+) -> Result<CsvImportReport, CrustyError> {
+    let mut reader = options.reader(path)?;
+    let mut dead_letters: Option<(&Path, Writer<File>)> = None;
+    let mut report = CsvImportReport::default();
+    let mut record = ByteRecord::new();
+    while reader
+        .read_byte_record(&mut record)
+        .map_err(|e| csv_error(path, e))?
+    {
+        let line = record.position().map_or(0, |p| p.line());
+        match parse_record(&record, schema) {
+            Ok(tuple) => {
+                insert(tuple)?;
+                report.imported += 1;
+            }
+            Err(e) => match &options.on_error {
+                CsvErrorPolicy::Abort => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Line {}: {}",
+                        line, e
+                    )))
+                }
+                CsvErrorPolicy::Skip => {
+                    debug!("Skipping line {} of {:?}: {}", line, path, e);
+                    report.skipped += 1;
+                }
+                CsvErrorPolicy::DeadLetter(dead_path) => {
+                    if dead_letters.is_none() {
+                        let writer = WriterBuilder::new()
+                            .delimiter(options.delimiter)
+                            .flexible(true)
+                            .from_path(dead_path)
+                            .map_err(|e| csv_error(dead_path, e))?;
+                        dead_letters = Some((dead_path, writer));
+                    }
+                    let (dead_path, writer) = dead_letters.as_mut().unwrap();
+                    writer
+                        .write_byte_record(&record)
+                        .map_err(|e| csv_error(dead_path, e))?;
+                    report.skipped += 1;
+                }
+            },
+        }
+    }
+    if let Some((dead_path, mut writer)) = dead_letters {
+        writer
+            .flush()
+            .map_err(|e| CrustyError::io_with_path(dead_path, e))?;
+    }
+    Ok(report)
+}
+
+/// Reads a record as a record of the table.
+fn parse_record(record: &ByteRecord, schema: &TableSchema) -> Result<Tuple, String> {
+    if record.len() != schema.size() {
+        return Err(format!(
+            "{} fields for {} columns",
+            record.len(),
+            schema.size()
+        ));
+    }
+    let mut fields = Vec::with_capacity(record.len());
+    for (cell, attr) in record.iter().zip(schema.attributes()) {
+        let cell = std::str::from_utf8(cell)
+            .map_err(|_| format!("value of {} is not valid UTF-8", attr.name()))?;
+        fields.push(parse_field(cell, attr)?);
+    }
+    Ok(Tuple::new(fields))
+}
+
+/// Returns the field of a column for a cell, or an error describing why the cell does not
+/// fit the column.
+///
+/// # Arguments
+///
+/// * `cell` - Text of the cell.
+/// * `attr` - Column the cell is stored in.
+pub fn parse_field(cell: &str, attr: &Attribute) -> Result<Field, String> {
+    if cell == "null" {
+        return if attr.is_nullable() {
+            Ok(Field::Null)
+        } else {
+            Err(format!("null value in NOT NULL column {}", attr.name()))
+        };
+    }
+    parse_typed(cell, attr.dtype()).ok_or_else(|| {
+        format!(
+            "{:?} is not a {:?} for column {}",
+            cell,
+            attr.dtype(),
+            attr.name()
+        )
+    })
+}
+
+fn parse_typed(cell: &str, dtype: &DataType) -> Option<Field> {
+    let field = match dtype {
+        DataType::Int => Field::IntField(cell.trim().parse().ok()?),
+        DataType::String => Field::StringField(cell.to_string()),
+        DataType::Float => Field::FloatField(Float(cell.trim().parse().ok()?)),
+        DataType::Decimal(precision, scale) => {
+            Field::DecimalField(Decimal::parse(cell.trim())?.rescale(*precision, *scale)?)
+        }
+        DataType::Date => Field::DateField(Date::parse(cell.trim())?),
+        DataType::Timestamp => Field::TimestampField(Timestamp::parse(cell.trim())?),
+        DataType::Boolean => Field::from_bool(cell)?,
+    };
+    Some(field)
+}
+
+/// Types tried for the columns of a file, in order of preference.
+const INFERRED_TYPES: [DataType; 5] = [
+    DataType::Int,
+    DataType::Float,
+    DataType::Date,
+    DataType::Timestamp,
+    DataType::Boolean,
+];
+
+/// Returns a schema for the records of a csv file, from the first records of the file. Each
+/// column has the first of Int, Float, Date, Timestamp and Boolean that all its sampled cells
+/// parse as, and is a String otherwise. Cells that are empty or `null` fit any type. The
+/// columns are nullable and named after the header row if the file has one, or `column1`,
+/// `column2` and so on otherwise.
+///
+/// # Arguments
+///
+/// * `path` - Path of the file.
+/// * `options` - Format of the file.
+/// * `sample_rows` - Number of records sampled.
+pub fn infer_schema(
+    path: &Path,
+    options: &CsvImportOptions,
+    sample_rows: usize,
+) -> Result<TableSchema, CrustyError> {
+    let mut reader = options.reader(path)?;
+    let mut names: Vec<String> = if options.header {
+        reader
+            .headers()
+            .map_err(|e| csv_error(path, e))?
+            .iter()
+            .map(String::from)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    // Types each column can still have, or None while only nulls were sampled.
+    let mut candidates: Vec<Option<Vec<&DataType>>> = Vec::new();
+    for record in reader.records().take(sample_rows) {
+        let record = record.map_err(|e| csv_error(path, e))?;
+        if candidates.len() < record.len() {
+            candidates.resize(record.len(), None);
+        }
+        for (cell, types) in record.iter().zip(&mut candidates) {
+            if !cell.is_empty() && cell != "null" {
+                types
+                    .get_or_insert_with(|| INFERRED_TYPES.iter().collect())
+                    .retain(|dtype| parse_typed(cell, dtype).is_some());
+            }
+        }
+    }
+    candidates.resize(candidates.len().max(names.len()), None);
+    for i in names.len()..candidates.len() {
+        names.push(format!("column{}", i + 1));
+    }
+    let attributes = names
+        .into_iter()
+        .zip(candidates)
+        .map(|(name, types)| {
+            let dtype = types
+                .and_then(|types| types.first().map(|t| (*t).clone()))
+                .unwrap_or(DataType::String);
+            Attribute::new(name, dtype)
+        })
+        .collect();
+    Ok(TableSchema::new(attributes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::gen_random_test_sm_dir;
+    use std::fs;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![
+            Attribute::new_pk(String::from("id"), DataType::Int),
+            Attribute::new(String::from("name"), DataType::String),
+            Attribute::new(String::from("price"), DataType::Decimal(6, 2)),
+        ])
+    }
+
+    fn import(
+        path: &Path,
+        options: &CsvImportOptions,
+    ) -> Result<(CsvImportReport, Vec<Tuple>), CrustyError> {
+        let mut tuples = Vec::new();
+        let report = import_csv(&schema(), path, options, |t| {
+            tuples.push(t);
+            Ok(())
+        })?;
+        Ok((report, tuples))
+    }
+
+    #[test]
+    fn test_error_policies() {
+        let dir = gen_random_test_sm_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.csv");
+        fs::write(
+            &path,
+            "id;name;price\n1;a;1.5\nx;b;2\n3;c\nnull;d;4\n5;null;6.25\n",
+        )
+        .unwrap();
+        let options = CsvImportOptions::default()
+            .with_delimiter(b';')
+            .with_header();
+
+        assert_eq!(
+            Err(CrustyError::ValidationError(String::from(
+                "Line 3: \"x\" is not a Int for column id"
+            ))),
+            import(&path, &options)
+        );
+        let (report, tuples) = import(
+            &path,
+            &options.clone().with_error_policy(CsvErrorPolicy::Skip),
+        )
+        .unwrap();
+        assert_eq!(
+            CsvImportReport {
+                imported: 2,
+                skipped: 3
+            },
+            report
+        );
+        assert_eq!(
+            vec![
+                Field::IntField(5),
+                Field::Null,
+                Field::DecimalField(Decimal::new(625, 2))
+            ],
+            tuples[1].field_vals
+        );
+
+        let dead = dir.join("dead.csv");
+        let options = options.with_error_policy(CsvErrorPolicy::DeadLetter(dead.clone()));
+        assert_eq!(2, import(&path, &options).unwrap().0.imported);
+        assert_eq!("x;b;2\n3;c\nnull;d;4\n", fs::read_to_string(&dead).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_infer_schema() {
+        let dir = gen_random_test_sm_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.csv");
+        fs::write(
+            &path,
+            "1,2.5,2024-01-31,t,x,\n2,3,2024-02-01 10:00:00,f,7,\nnull,,null,null,y,\n4,1e3,bad,t,z,\n",
+        )
+        .unwrap();
+        let sampled = |rows| {
+            let schema = infer_schema(&path, &CsvImportOptions::default(), rows).unwrap();
+            schema
+                .attributes()
+                .map(|a| (a.name().to_string(), a.dtype().clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                (String::from("column1"), DataType::Int),
+                (String::from("column2"), DataType::Float),
+                (String::from("column3"), DataType::Timestamp),
+                (String::from("column4"), DataType::Boolean),
+                (String::from("column5"), DataType::String),
+                (String::from("column6"), DataType::String),
+            ],
+            sampled(3)
+        );
+        // The fourth record is not sampled with fewer rows.
+        assert_eq!(DataType::String, sampled(4)[2].1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -2,8 +2,11 @@ extern crate log;
 
 use crate::catalog;
 use crate::ids::{ContainerId, StateType, CONTAINER_COUNTER};
+use crate::index::IndexInfo;
 use crate::prelude::*;
+use crate::statistics::TableStats;
 use crate::table::*;
+use crate::temp_container::is_temp_container;
 use catalog::Catalog;
 use std::collections::HashMap;
 use std::fs::File;
@@ -23,6 +26,12 @@ pub struct Database {
     pub tables: Arc<RwLock<HashMap<ContainerId, Arc<RwLock<Table>>>>>,
     // #[serde(skip)]
     pub named_containers: Arc<RwLock<HashMap<ContainerId, (String, StateType)>>>,
+    /// Statistics of the tables that have been analyzed.
+    #[serde(default)]
+    pub table_stats: Arc<RwLock<HashMap<ContainerId, TableStats>>>,
+    /// Indexes of the tables, by the id of their container.
+    #[serde(default)]
+    pub indexes: Arc<RwLock<HashMap<ContainerId, IndexInfo>>>,
 }
 
 impl Database {
@@ -36,9 +45,69 @@ impl Database {
             name,
             tables: Arc::new(RwLock::new(HashMap::new())),
             named_containers: Arc::new(RwLock::new(HashMap::new())),
+            table_stats: Arc::new(RwLock::new(HashMap::new())),
+            indexes: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Store the statistics of a table, replacing any earlier statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Id of the analyzed table.
+    /// * `stats` - Statistics of the table.
+    pub fn set_table_stats(&self, table_id: ContainerId, stats: TableStats) {
+        self.table_stats.write().unwrap().insert(table_id, stats);
+    }
+
+    /// Get the id of an index container by the name of the index.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the index.
+    pub fn get_index_id(&self, name: &str) -> Option<ContainerId> {
+        let containers = self.named_containers.read().unwrap();
+        containers
+            .iter()
+            .find_map(|(id, (c_name, state_type))| match state_type {
+                StateType::Index if c_name == name => Some(*id),
+                _ => None,
+            })
+    }
+
+    /// Get the catalog entry of an index by its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the index.
+    pub fn get_index(&self, name: &str) -> Option<IndexInfo> {
+        let container_id = self.get_index_id(name)?;
+        self.indexes.read().unwrap().get(&container_id).cloned()
+    }
+
+    /// Register an index whose container was allocated with `get_new_container_id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Catalog entry of the index.
+    pub fn add_index(&self, index: IndexInfo) {
+        self.indexes
+            .write()
+            .unwrap()
+            .insert(index.container_id, index);
+    }
+
+    /// Unregister an index and release its name. Returns its catalog entry, if it existed.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the index.
+    pub fn remove_index(&self, name: &str) -> Option<IndexInfo> {
+        let container_id = self.get_index_id(name)?;
+        self.named_containers.write().unwrap().remove(&container_id);
+        self.indexes.write().unwrap().remove(&container_id)
+    }
+
     pub fn load(filename: PathBuf) -> Self {
         debug!("Loading database from file {}", filename.display());
         let reader = File::open(&filename).expect("error opening file");
@@ -52,6 +121,23 @@ impl Catalog for Database {
         self.tables.clone()
     }
 
+    fn get_table_stats(&self, table_id: ContainerId) -> Option<TableStats> {
+        self.table_stats.read().unwrap().get(&table_id).cloned()
+    }
+
+    fn get_indexes(&self, table_id: ContainerId) -> Vec<IndexInfo> {
+        let mut indexes: Vec<IndexInfo> = self
+            .indexes
+            .read()
+            .unwrap()
+            .values()
+            .filter(|index| index.table_id == table_id)
+            .cloned()
+            .collect();
+        indexes.sort_by_key(|index| index.container_id);
+        indexes
+    }
+
     fn get_table_id(&self, name: &str) -> Option<ContainerId> {
         //TODO mixed usage of &str and &String. for code that had &str it was coded as &x.to_string()
         let containers = self.named_containers.read().unwrap();
@@ -76,6 +162,22 @@ impl Catalog for Database {
         state_type: StateType,
         name: Option<String>,
     ) -> Result<ContainerId, CrustyError> {
+        if let StateType::Index = state_type {
+            match &name {
+                Some(name) => {
+                    if self.get_index_id(name).is_some() {
+                        return Err(CrustyError::CrustyError(String::from(
+                            "database already has an index with this name",
+                        )));
+                    }
+                }
+                None => {
+                    return Err(CrustyError::CrustyError(String::from(
+                        "indexes must have name",
+                    )))
+                }
+            }
+        }
         if let StateType::BaseTable = state_type {
             match name.clone() {
                 Some(name) => {
@@ -93,6 +195,11 @@ impl Catalog for Database {
             }
         }
         let new_cid = CONTAINER_COUNTER.fetch_add(1, Ordering::SeqCst);
+        if is_temp_container(new_cid) {
+            return Err(CrustyError::CrustyError(String::from(
+                "database has no container ids left",
+            )));
+        }
         if let Some(n) = name {
             //Save the cid if this has a name
             let mut containers = self.named_containers.write().unwrap();
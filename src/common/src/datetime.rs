@@ -0,0 +1,327 @@
+//! Date and timestamp values.
+//!
+//! Dates are stored as days since 1970-01-01 and timestamps as microseconds since
+//! 1970-01-01 00:00:00, both in the proleptic Gregorian calendar and without a time zone, so
+//! they compare, sort, and hash as integers. Values are read and printed in ISO 8601 format.
+
+use crate::{CrustyError, Field};
+use sqlparser::ast;
+use std::fmt;
+
+const MICROS_PER_SECOND: i64 = 1_000_000;
+const SECONDS_PER_DAY: i64 = 86_400;
+const MICROS_PER_DAY: i64 = SECONDS_PER_DAY * MICROS_PER_SECOND;
+
+/// Calendar date, stored as the number of days since 1970-01-01.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date(pub i32);
+
+/// Date and time of day with microsecond precision, stored as the number of microseconds
+/// since 1970-01-01 00:00:00.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub i64);
+
+/// Part of a date or timestamp returned by `extract`.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DatePart {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl Date {
+    /// Creates a date from a year, month, and day. Returns None if the date does not exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `year` - Year, between 1 and 9999.
+    /// * `month` - Month, between 1 and 12.
+    /// * `day` - Day of the month, starting at 1.
+    pub fn from_ymd(year: i32, month: u32, day: u32) -> Option<Self> {
+        if !(1..=9999).contains(&year)
+            || !(1..=12).contains(&month)
+            || day == 0
+            || day > days_in_month(year, month)
+        {
+            return None;
+        }
+        Some(Date(days_from_civil(year, month, day)))
+    }
+
+    /// Parses a date in `YYYY-MM-DD` format.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - Date to parse.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, '-');
+        let year = parse_digits(parts.next()?, 4, 4)?;
+        let month = parse_digits(parts.next()?, 2, 2)?;
+        let day = parse_digits(parts.next()?, 2, 2)?;
+        Date::from_ymd(year as i32, month, day)
+    }
+
+    /// Returns the year, month, and day of the date.
+    pub fn ymd(&self) -> (i32, u32, u32) {
+        civil_from_days(self.0)
+    }
+}
+
+impl Timestamp {
+    /// Parses a timestamp in `YYYY-MM-DD[( |T)HH:MM:SS[.ffffff]][Z]` format. A timestamp
+    /// without a time is at midnight.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - Timestamp to parse.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.strip_suffix('Z').unwrap_or(s);
+        if s.len() <= 10 {
+            return Date::parse(s).map(Timestamp::from);
+        }
+        if !s.is_char_boundary(10) {
+            return None;
+        }
+        let (date, time) = s.split_at(10);
+        let date = Date::parse(date)?;
+        let time = time.strip_prefix(|c| c == ' ' || c == 'T')?;
+        let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+        let mut parts = time.splitn(3, ':');
+        let hour = parse_digits(parts.next()?, 2, 2)?;
+        let minute = parse_digits(parts.next()?, 2, 2)?;
+        let second = parse_digits(parts.next()?, 2, 2)?;
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        let micros = if fraction.is_empty() {
+            0
+        } else {
+            // Pad the fraction to microseconds, so .5 is 500000 microseconds
+            let digits = parse_digits(fraction, 1, 6)?;
+            digits * 10u32.pow(6 - fraction.len() as u32)
+        };
+        let seconds = (hour * 3600 + minute * 60 + second) as i64;
+        Some(Timestamp(
+            Timestamp::from(date).0 + seconds * MICROS_PER_SECOND + micros as i64,
+        ))
+    }
+
+    /// Returns the date of the timestamp.
+    pub fn date(&self) -> Date {
+        Date(self.0.div_euclid(MICROS_PER_DAY) as i32)
+    }
+
+    /// Returns the number of microseconds since the start of the timestamp's day.
+    fn time_of_day(&self) -> i64 {
+        self.0.rem_euclid(MICROS_PER_DAY)
+    }
+}
+
+impl From<Date> for Timestamp {
+    /// Returns midnight of the date.
+    fn from(date: Date) -> Self {
+        Timestamp(date.0 as i64 * MICROS_PER_DAY)
+    }
+}
+
+impl From<&ast::DateTimeField> for DatePart {
+    fn from(field: &ast::DateTimeField) -> Self {
+        match field {
+            ast::DateTimeField::Year => DatePart::Year,
+            ast::DateTimeField::Month => DatePart::Month,
+            ast::DateTimeField::Day => DatePart::Day,
+            ast::DateTimeField::Hour => DatePart::Hour,
+            ast::DateTimeField::Minute => DatePart::Minute,
+            ast::DateTimeField::Second => DatePart::Second,
+        }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = self.ymd();
+        write!(f, "{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time_of_day();
+        let seconds = time / MICROS_PER_SECOND;
+        write!(
+            f,
+            "{} {:02}:{:02}:{:02}",
+            self.date(),
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )?;
+        let micros = time % MICROS_PER_SECOND;
+        if micros != 0 {
+            write!(f, ".{:06}", micros)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DatePart {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DatePart::Year => "YEAR",
+            DatePart::Month => "MONTH",
+            DatePart::Day => "DAY",
+            DatePart::Hour => "HOUR",
+            DatePart::Minute => "MINUTE",
+            DatePart::Second => "SECOND",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns a part of a date or timestamp field as an integer field, like SQL's
+/// `EXTRACT(part FROM field)`. The time parts of a date are 0, and the parts of null are null.
+/// Grouping on the year or month of a column groups its values by calendar year or month.
+///
+/// # Arguments
+///
+/// * `field` - Date or timestamp field.
+/// * `part` - Part to return.
+pub fn extract(field: &Field, part: DatePart) -> Result<Field, CrustyError> {
+    let timestamp = match field {
+        Field::DateField(date) => Timestamp::from(*date),
+        Field::TimestampField(timestamp) => *timestamp,
+        Field::Null => return Ok(Field::Null),
+        _ => {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot extract {} from {}",
+                part, field
+            )))
+        }
+    };
+    let (year, month, day) = timestamp.date().ymd();
+    let seconds = timestamp.time_of_day() / MICROS_PER_SECOND;
+    let value = match part {
+        DatePart::Year => year,
+        DatePart::Month => month as i32,
+        DatePart::Day => day as i32,
+        DatePart::Hour => (seconds / 3600) as i32,
+        DatePart::Minute => (seconds / 60 % 60) as i32,
+        DatePart::Second => (seconds % 60) as i32,
+    };
+    Ok(Field::IntField(value))
+}
+
+/// Parses a number of `min` to `max` ASCII digits.
+fn parse_digits(s: &str, min: usize, max: usize) -> Option<u32> {
+    if s.len() < min || s.len() > max || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days between 1970-01-01 and a date, following
+/// http://howardhinnant.github.io/date_algorithms.html#days_from_civil.
+fn days_from_civil(year: i32, month: u32, day: u32) -> i32 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year as i32;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date that is a number of days after 1970-01-01, following
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i32) -> (i32, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_date_round_trip() {
+        assert_eq!(Some(Date(0)), Date::parse("1970-01-01"));
+        assert_eq!(Some(Date(-1)), Date::parse("1969-12-31"));
+        for s in [
+            "2000-02-29",
+            "1999-12-31",
+            "0001-01-01",
+            "9999-12-31",
+            "2024-03-01",
+        ] {
+            assert_eq!(s, Date::parse(s).unwrap().to_string());
+        }
+        for s in [
+            "2023-02-29",
+            "2024-13-01",
+            "2024-1-01",
+            "24-01-01",
+            "2024-01-01x",
+        ] {
+            assert_eq!(None, Date::parse(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_timestamp_parse() {
+        let t = Timestamp::parse("2024-02-29T13:05:09.25Z").unwrap();
+        assert_eq!("2024-02-29 13:05:09.250000", t.to_string());
+        assert_eq!(t, Timestamp::parse("2024-02-29 13:05:09.250000").unwrap());
+        assert_eq!(
+            Timestamp::from(Date::parse("2024-02-29").unwrap()),
+            Timestamp::parse("2024-02-29").unwrap()
+        );
+        assert_eq!(
+            "1969-12-31 23:59:59",
+            Timestamp::parse("1969-12-31 23:59:59").unwrap().to_string()
+        );
+        assert_eq!(None, Timestamp::parse("2024-02-29 24:00:00"));
+        assert_eq!(None, Timestamp::parse("2024-02-29 10:00"));
+        assert!(Timestamp::parse("2024-01-01 00:00:01") > Timestamp::parse("2023-12-31"));
+    }
+
+    #[test]
+    fn test_extract() {
+        let t = Field::TimestampField(Timestamp::parse("2021-07-04 18:30:15").unwrap());
+        let d = Field::DateField(Date::parse("1960-11-08").unwrap());
+        assert_eq!(Field::IntField(2021), extract(&t, DatePart::Year).unwrap());
+        assert_eq!(Field::IntField(7), extract(&t, DatePart::Month).unwrap());
+        assert_eq!(Field::IntField(30), extract(&t, DatePart::Minute).unwrap());
+        assert_eq!(Field::IntField(11), extract(&d, DatePart::Month).unwrap());
+        assert_eq!(Field::IntField(0), extract(&d, DatePart::Hour).unwrap());
+        assert_eq!(Field::Null, extract(&Field::Null, DatePart::Day).unwrap());
+        assert!(extract(&Field::IntField(1), DatePart::Day).is_err());
+    }
+}
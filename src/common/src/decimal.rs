@@ -0,0 +1,253 @@
+//! Fixed-point decimal values.
+//!
+//! A `Decimal` is an integer number of units of `10^-scale`, so sums and comparisons of
+//! decimals are exact. A DECIMAL(p, s) column stores values with exactly `s` digits after the
+//! point and at most `p` digits in total.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Add;
+
+/// Largest precision of a decimal column. Values of that precision fit in an i64.
+pub const MAX_PRECISION: u32 = 18;
+
+/// Precision of DECIMAL columns declared without one.
+pub const DEFAULT_PRECISION: u32 = MAX_PRECISION;
+
+/// Fixed-point decimal value of a field, equal to `value * 10^-scale`.
+///
+/// Decimals compare by their numeric value, so 1.5 and 1.50 are equal and hash the same.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Decimal {
+    /// Unscaled value.
+    pub value: i64,
+    /// Number of digits after the decimal point.
+    pub scale: u32,
+}
+
+impl Decimal {
+    /// Creates a decimal from its unscaled value and scale.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Unscaled value.
+    /// * `scale` - Number of digits after the decimal point.
+    pub fn new(value: i64, scale: u32) -> Self {
+        Decimal { value, scale }
+    }
+
+    /// Parses a decimal literal such as `-12.50`, keeping the digits after the point as its
+    /// scale. Returns None if the literal is not a plain decimal number or does not fit.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - Literal to parse.
+    pub fn parse(s: &str) -> Option<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        if int_part.is_empty() && frac_part.is_empty() {
+            return None;
+        }
+        if !int_part
+            .bytes()
+            .chain(frac_part.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return None;
+        }
+        let scale = frac_part.len() as u32;
+        if scale > MAX_PRECISION {
+            return None;
+        }
+        let mut value: i64 = 0;
+        for b in int_part.bytes().chain(frac_part.bytes()) {
+            value = value.checked_mul(10)?.checked_add((b - b'0') as i64)?;
+        }
+        Some(Decimal::new(if negative { -value } else { value }, scale))
+    }
+
+    /// Returns the decimal with another scale, rounding half away from zero if digits are
+    /// dropped. Returns None if the result does not fit in `precision` digits.
+    ///
+    /// # Arguments
+    ///
+    /// * `precision` - Maximum number of digits of the result.
+    /// * `scale` - Number of digits after the point of the result.
+    pub fn rescale(&self, precision: u32, scale: u32) -> Option<Self> {
+        let value = rescale_value(self.value as i128, self.scale, scale)?;
+        if value.unsigned_abs() >= 10u128.checked_pow(precision)? {
+            return None;
+        }
+        Some(Decimal::new(i64::try_from(value).ok()?, scale))
+    }
+
+    /// Divides the decimal by a count, keeping its scale and rounding half away from zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Divisor, which must not be zero.
+    pub fn div_count(&self, count: i64) -> Self {
+        Decimal::new(
+            div_round(self.value as i128, count as i128) as i64,
+            self.scale,
+        )
+    }
+
+    /// Converts the decimal to the closest float.
+    pub fn to_f64(&self) -> f64 {
+        self.value as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    /// Returns the unscaled value at a larger scale.
+    fn scaled(&self, scale: u32) -> i128 {
+        self.value as i128 * 10i128.pow(scale - self.scale)
+    }
+}
+
+/// Changes the scale of an unscaled value, rounding half away from zero.
+fn rescale_value(value: i128, from: u32, to: u32) -> Option<i128> {
+    if to >= from {
+        value.checked_mul(10i128.checked_pow(to - from)?)
+    } else {
+        Some(div_round(value, 10i128.checked_pow(from - to)?))
+    }
+}
+
+/// Divides two integers, rounding half away from zero.
+fn div_round(value: i128, divisor: i128) -> i128 {
+    let quotient = value / divisor;
+    let remainder = value % divisor;
+    if 2 * remainder.abs() >= divisor.abs() {
+        quotient + value.signum() * divisor.signum()
+    } else {
+        quotient
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    /// Adds two decimals exactly. The result has the larger of the two scales.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sum does not fit in a decimal.
+    fn add(self, other: Self) -> Self {
+        let scale = self.scale.max(other.scale);
+        let sum = self.scaled(scale) + other.scaled(scale);
+        Decimal::new(
+            i64::try_from(sum).expect("Decimal overflow in addition"),
+            scale,
+        )
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let scale = self.scale.max(other.scale);
+        self.scaled(scale).cmp(&other.scaled(scale))
+    }
+}
+
+impl Hash for Decimal {
+    /// Hashes the decimal without its trailing zeros, so that equal decimals of different
+    /// scales hash the same.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let (mut value, mut scale) = (self.value, self.scale);
+        while scale > 0 && value % 10 == 0 {
+            value /= 10;
+            scale -= 1;
+        }
+        value.hash(state);
+        scale.hash(state);
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.value);
+        }
+        let digits = format!(
+            "{:0width$}",
+            self.value.unsigned_abs(),
+            width = self.scale as usize + 1
+        );
+        let (int_part, frac_part) = digits.split_at(digits.len() - self.scale as usize);
+        let sign = if self.value < 0 { "-" } else { "" };
+        write!(f, "{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::hash_map::DefaultHasher;
+
+    fn hash(d: &Decimal) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        d.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for s in ["12.50", "-0.05", "7", "0.000", "-3.1"] {
+            assert_eq!(s, Decimal::parse(s).unwrap().to_string());
+        }
+        assert_eq!(Decimal::new(5, 1), Decimal::parse(".5").unwrap());
+        assert_eq!(None, Decimal::parse("1e5"));
+        assert_eq!(None, Decimal::parse("."));
+        assert_eq!(None, Decimal::parse("99999999999999999999"));
+    }
+
+    #[test]
+    fn test_rescale() {
+        let d = Decimal::parse("2.345").unwrap();
+        assert_eq!("2.35", d.rescale(10, 2).unwrap().to_string());
+        assert_eq!(
+            "-2.35",
+            Decimal::new(-2345, 3).rescale(10, 2).unwrap().to_string()
+        );
+        assert_eq!("2.34500", d.rescale(10, 5).unwrap().to_string());
+        assert_eq!(None, d.rescale(2, 2));
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let tenth = Decimal::parse("0.1").unwrap();
+        let sum = tenth + Decimal::parse("0.2").unwrap();
+        assert_eq!(Decimal::parse("0.3").unwrap(), sum);
+        assert_eq!("0.3", sum.to_string());
+        assert_eq!("0.67", Decimal::new(200, 2).div_count(3).to_string());
+        assert_eq!("-0.67", Decimal::new(-200, 2).div_count(3).to_string());
+    }
+
+    #[test]
+    fn test_compare_across_scales() {
+        let a = Decimal::parse("1.5").unwrap();
+        let b = Decimal::parse("1.50").unwrap();
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+        assert!(Decimal::parse("1.49").unwrap() < a);
+        assert!(Decimal::parse("-2").unwrap() < Decimal::parse("-1.99").unwrap());
+    }
+}
@@ -0,0 +1,292 @@
+//! Compact binary encoding of tuples.
+//!
+//! Fields are written in little-endian order with a fixed width for their type: 4 bytes for
+//! ints and dates, 8 bytes for floats, timestamps and the unscaled value of decimals, and 1
+//! byte for booleans. Strings are written as a varint length followed by their UTF-8 bytes.
+//!
+//! A tuple, as written by `Tuple::to_bytes`, is a format byte, the varint transaction id and
+//! field count of the tuple, and each field preceded by a one byte tag for its type. Null
+//! fields are only a tag. The layout is self-describing: it can be read without the schema,
+//! which keeps stored records readable by tools and by code that has no catalog at hand.
+//!
+//! Records written by older versions as CBOR are still read by `decode`.
+
+use crate::decimal::MAX_PRECISION;
+use crate::{CrustyError, Date, Decimal, Field, Float, Timestamp, Tuple};
+
+/// First byte of an encoded tuple. CBOR records start with a map header instead.
+const FORMAT: u8 = 0x01;
+
+// Tags of the field types.
+const NULL: u8 = 0;
+const INT: u8 = 1;
+const STRING: u8 = 2;
+const FLOAT: u8 = 3;
+const DECIMAL: u8 = 4;
+const DATE: u8 = 5;
+const TIMESTAMP: u8 = 6;
+const BOOLEAN: u8 = 7;
+
+/// Appends a LEB128 varint.
+pub fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Reads encoded values from a byte slice.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], CrustyError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(CrustyError::CrustyError(String::from(
+                "Encoded tuple is truncated",
+            )));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], CrustyError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn byte(&mut self) -> Result<u8, CrustyError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, CrustyError> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(CrustyError::CrustyError(String::from(
+            "Encoded tuple has an invalid varint",
+        )))
+    }
+
+    fn finish(&self) -> Result<(), CrustyError> {
+        if self.pos == self.bytes.len() {
+            Ok(())
+        } else {
+            Err(CrustyError::CrustyError(String::from(
+                "Encoded tuple has trailing bytes",
+            )))
+        }
+    }
+}
+
+/// Appends the value of a non-null field, without its type.
+fn write_field(out: &mut Vec<u8>, field: &Field) {
+    match field {
+        Field::IntField(i) => out.extend_from_slice(&i.to_le_bytes()),
+        Field::StringField(s) => {
+            write_varint(out, s.len() as u64);
+            out.extend_from_slice(s.as_bytes());
+        }
+        Field::FloatField(x) => out.extend_from_slice(&x.0.to_le_bytes()),
+        Field::DecimalField(d) => out.extend_from_slice(&d.value.to_le_bytes()),
+        Field::DateField(d) => out.extend_from_slice(&d.0.to_le_bytes()),
+        Field::TimestampField(t) => out.extend_from_slice(&t.0.to_le_bytes()),
+        Field::BoolField(b) => out.push(*b as u8),
+        Field::Null => {}
+    }
+}
+
+/// Reads the value of a non-null field of a type. The scale of decimals is passed separately.
+fn read_field(reader: &mut Reader, tag: u8, scale: u32) -> Result<Field, CrustyError> {
+    Ok(match tag {
+        INT => Field::IntField(i32::from_le_bytes(reader.array()?)),
+        STRING => {
+            let len = reader.varint()? as usize;
+            let bytes = reader.take(len)?.to_vec();
+            Field::StringField(String::from_utf8(bytes).map_err(|_| {
+                CrustyError::CrustyError(String::from("Encoded string is not UTF-8"))
+            })?)
+        }
+        FLOAT => Field::FloatField(Float(f64::from_le_bytes(reader.array()?))),
+        DECIMAL => Field::DecimalField(Decimal::new(i64::from_le_bytes(reader.array()?), scale)),
+        DATE => Field::DateField(Date(i32::from_le_bytes(reader.array()?))),
+        TIMESTAMP => Field::TimestampField(Timestamp(i64::from_le_bytes(reader.array()?))),
+        BOOLEAN => Field::BoolField(reader.byte()? != 0),
+        NULL => Field::Null,
+        _ => {
+            return Err(CrustyError::CrustyError(format!(
+                "Encoded field has unknown type {}",
+                tag
+            )))
+        }
+    })
+}
+
+/// Returns an encoded decimal scale, or an error if no decimal column has it. Larger scales
+/// would overflow when decimals are compared.
+fn check_scale(scale: u64) -> Result<u32, CrustyError> {
+    if scale > u64::from(MAX_PRECISION) {
+        return Err(CrustyError::CrustyError(format!(
+            "Encoded decimal has a scale of {}, more than {}",
+            scale, MAX_PRECISION
+        )));
+    }
+    Ok(scale as u32)
+}
+
+/// Returns the tag of the type of a field.
+fn field_tag(field: &Field) -> u8 {
+    match field {
+        Field::IntField(_) => INT,
+        Field::StringField(_) => STRING,
+        Field::FloatField(_) => FLOAT,
+        Field::DecimalField(_) => DECIMAL,
+        Field::DateField(_) => DATE,
+        Field::TimestampField(_) => TIMESTAMP,
+        Field::BoolField(_) => BOOLEAN,
+        Field::Null => NULL,
+    }
+}
+
+/// Encodes a tuple.
+///
+/// # Arguments
+///
+/// * `tuple` - Tuple to encode.
+pub fn encode(tuple: &Tuple) -> Vec<u8> {
+    let mut out = Vec::with_capacity(3 + tuple.size() * 5);
+    out.push(FORMAT);
+    write_varint(&mut out, tuple.tid);
+    write_varint(&mut out, tuple.size() as u64);
+    for field in tuple.field_vals() {
+        out.push(field_tag(field));
+        if let Field::DecimalField(d) = field {
+            write_varint(&mut out, u64::from(d.scale));
+        }
+        write_field(&mut out, field);
+    }
+    out
+}
+
+/// Decodes a tuple encoded with `encode`, or a CBOR record written by older versions.
+///
+/// # Arguments
+///
+/// * `bytes` - Encoded tuple.
+pub fn decode(bytes: &[u8]) -> Result<Tuple, CrustyError> {
+    match bytes.first() {
+        Some(&FORMAT) => {}
+        // CBOR map header
+        Some(0xa0..=0xbf) => {
+            let tuple: Tuple = serde_cbor::from_slice(bytes)
+                .map_err(|e| CrustyError::CrustyError(format!("Invalid CBOR tuple: {}", e)))?;
+            for field in tuple.field_vals() {
+                if let Field::DecimalField(d) = field {
+                    check_scale(u64::from(d.scale))?;
+                }
+            }
+            return Ok(tuple);
+        }
+        _ => {
+            return Err(CrustyError::CrustyError(String::from(
+                "Unknown tuple encoding",
+            )))
+        }
+    }
+    let mut reader = Reader::new(&bytes[1..]);
+    let tid = reader.varint()?;
+    let size = reader.varint()? as usize;
+    let mut field_vals = Vec::with_capacity(size.min(bytes.len()));
+    for _ in 0..size {
+        let tag = reader.byte()?;
+        let scale = if tag == DECIMAL {
+            check_scale(reader.varint()?)?
+        } else {
+            0
+        };
+        field_vals.push(read_field(&mut reader, tag, scale)?);
+    }
+    reader.finish()?;
+    let mut tuple = Tuple::new(field_vals);
+    tuple.tid = tid;
+    Ok(tuple)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+
+    fn mixed_tuple() -> Tuple {
+        Tuple::new(vec![
+            Field::IntField(-7),
+            Field::StringField(String::from("crusty ünïcode")),
+            Field::FloatField(Float(2.5)),
+            Field::DecimalField(Decimal::new(-1999, 2)),
+            Field::DateField(Date::parse("2024-02-29").unwrap()),
+            Field::TimestampField(Timestamp::parse("2024-02-29 10:00:00.25").unwrap()),
+            Field::BoolField(true),
+            Field::Null,
+            Field::StringField(String::new()),
+        ])
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut tuple = mixed_tuple();
+        tuple.tid = 300;
+        assert_eq!(tuple, decode(&encode(&tuple)).unwrap());
+        let empty = Tuple::new(Vec::new());
+        assert_eq!(empty, decode(&encode(&empty)).unwrap());
+        let long = Tuple::new(vec![Field::StringField(gen_rand_string(1000))]);
+        assert_eq!(long, decode(&encode(&long)).unwrap());
+    }
+
+    #[test]
+    fn test_smaller_than_cbor() {
+        let tuple = int_vec_to_tuple(vec![1, 2, 3, 4, 5]);
+        let cbor = serde_cbor::to_vec(&tuple).unwrap();
+        let bytes = encode(&tuple);
+        assert_eq!(3 + 5 * 5, bytes.len());
+        assert!(bytes.len() * 2 < cbor.len());
+        assert_eq!(tuple, decode(&cbor).unwrap());
+
+        let tuple = mixed_tuple();
+        assert!(encode(&tuple).len() * 2 < serde_cbor::to_vec(&tuple).unwrap().len());
+    }
+
+    #[test]
+    fn test_invalid_bytes() {
+        let bytes = encode(&mixed_tuple());
+        for len in 0..bytes.len() {
+            assert!(decode(&bytes[..len]).is_err());
+        }
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_invalid_decimals() {
+        // a decimal scale larger than any column's
+        let mut bytes = vec![FORMAT, 0, 1, DECIMAL, MAX_PRECISION as u8 + 1];
+        bytes.extend_from_slice(&1i64.to_le_bytes());
+        assert!(decode(&bytes).is_err());
+        bytes[4] = MAX_PRECISION as u8;
+        assert!(decode(&bytes).is_ok());
+    }
+}
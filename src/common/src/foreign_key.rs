@@ -0,0 +1,361 @@
+//! Foreign keys between base tables.
+//!
+//! A foreign key makes some columns of a child table refer to the primary key or a unique
+//! constraint of a parent table, whose unique index is used to look the referred keys up.
+//! Foreign keys are stored in the catalog entry of the child table, and the container of the
+//! child is created with its parents as dependencies, so a parent cannot be removed while a
+//! child refers to it.
+//!
+//! A record whose key has a null value does not refer to any parent record. New or updated
+//! child records must refer to an existing parent record. Deleting a referenced parent record
+//! fails if the foreign key restricts deletes and also deletes the referencing records if it
+//! cascades. Changing a referenced parent key always fails.
+
+use crate::catalog::Catalog;
+use crate::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use crate::index::{self, IndexInfo};
+use crate::storage_trait::StorageTrait;
+use crate::table::Table;
+use crate::{CrustyError, Field, Tuple};
+use std::collections::{HashMap, HashSet};
+
+/// What deleting a referenced parent record does to the child records referring to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OnDelete {
+    /// The delete fails.
+    Restrict,
+    /// The child records are deleted as well.
+    Cascade,
+}
+
+/// Catalog entry of a foreign key, stored with the child table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ForeignKey {
+    /// Name of the foreign key.
+    pub name: String,
+    /// Indices of the referring columns in the child schema, in key order.
+    pub columns: Vec<usize>,
+    /// Table the key refers to.
+    pub parent_id: ContainerId,
+    /// Indices of the referred columns in the parent schema, in key order. They are the
+    /// columns of a unique index of the parent.
+    pub parent_columns: Vec<usize>,
+    /// Action on the child records when a referenced parent record is deleted.
+    pub on_delete: OnDelete,
+}
+
+impl ForeignKey {
+    /// Returns the key a child record refers to, or `None` if the key has a null value.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Record of the child table.
+    pub fn key(&self, tuple: &Tuple) -> Option<Vec<Field>> {
+        key_of(&self.columns, tuple)
+    }
+
+    /// Returns the key child records use to refer to a parent record, or `None` if the key
+    /// has a null value.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Record of the parent table.
+    pub fn parent_key(&self, tuple: &Tuple) -> Option<Vec<Field>> {
+        key_of(&self.parent_columns, tuple)
+    }
+}
+
+/// Returns the values of some columns of a record, or `None` if one of them is null.
+fn key_of(columns: &[usize], tuple: &Tuple) -> Option<Vec<Field>> {
+    let key: Vec<Field> = columns
+        .iter()
+        .map(|i| tuple.get_field(*i).cloned().unwrap_or(Field::Null))
+        .collect();
+    if key.contains(&Field::Null) {
+        None
+    } else {
+        Some(key)
+    }
+}
+
+/// A foreign key of a changed table and the unique index of the parent key it refers to.
+#[derive(Clone, Debug)]
+pub struct ParentKey {
+    pub foreign_key: ForeignKey,
+    pub index: IndexInfo,
+}
+
+/// A foreign key referring to a table records are deleted from, with what is needed to
+/// delete the child records it cascades to.
+#[derive(Clone)]
+pub struct ChildKey {
+    pub foreign_key: ForeignKey,
+    /// The child table.
+    pub table_id: ContainerId,
+    pub table: Table,
+    /// Indexes of the child table, which lose the entries of cascaded deletes.
+    pub indexes: Vec<IndexInfo>,
+}
+
+/// The foreign keys a statement changing a table has to enforce.
+#[derive(Clone, Default)]
+pub struct ForeignKeys {
+    /// The changed table.
+    pub table_id: ContainerId,
+    /// Foreign keys of the table, checked for new and updated records.
+    pub parents: Vec<ParentKey>,
+    /// Foreign keys referring to the table, and the foreign keys referring to the tables
+    /// deletes of the table cascade to.
+    pub children: Vec<ChildKey>,
+}
+
+impl ForeignKeys {
+    /// Looks up the foreign keys to enforce for a table in the catalog.
+    ///
+    /// # Arguments
+    ///
+    /// * `catalog` - Catalog holding the table.
+    /// * `table_id` - The changed table.
+    pub fn new<C: Catalog>(catalog: &C, table_id: ContainerId) -> Result<Self, CrustyError> {
+        let tables: Vec<(ContainerId, Table)> = catalog
+            .get_tables()
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(id, table)| (*id, table.read().unwrap().clone()))
+            .collect();
+        let mut parents = Vec::new();
+        if let Some((_, table)) = tables.iter().find(|(id, _)| *id == table_id) {
+            for foreign_key in &table.foreign_keys {
+                let index = catalog
+                    .get_indexes(foreign_key.parent_id)
+                    .into_iter()
+                    .find(|index| index.unique && index.columns == foreign_key.parent_columns)
+                    .ok_or_else(|| {
+                        CrustyError::CrustyError(format!(
+                            "No unique index for the parent key of foreign key {}",
+                            foreign_key.name
+                        ))
+                    })?;
+                parents.push(ParentKey {
+                    foreign_key: foreign_key.clone(),
+                    index,
+                });
+            }
+        }
+
+        // Follow the cascading foreign keys from the table, visiting each table once.
+        let mut children = Vec::new();
+        let mut visited = HashSet::from([table_id]);
+        let mut pending = vec![table_id];
+        while let Some(parent_id) = pending.pop() {
+            for (child_id, child) in &tables {
+                for foreign_key in &child.foreign_keys {
+                    if foreign_key.parent_id != parent_id {
+                        continue;
+                    }
+                    if foreign_key.on_delete == OnDelete::Cascade && visited.insert(*child_id) {
+                        pending.push(*child_id);
+                    }
+                    children.push(ChildKey {
+                        foreign_key: foreign_key.clone(),
+                        table_id: *child_id,
+                        table: child.clone(),
+                        indexes: catalog.get_indexes(*child_id),
+                    });
+                }
+            }
+        }
+        Ok(ForeignKeys {
+            table_id,
+            parents,
+            children,
+        })
+    }
+}
+
+/// Checks that the records of a table refer to existing parent records.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the parent indexes.
+/// * `foreign_keys` - Foreign keys of the table.
+/// * `tuples` - New records of the table.
+/// * `tid` - Transaction reading the parent indexes.
+pub fn check_parents<T: StorageTrait>(
+    sm: &T,
+    foreign_keys: &ForeignKeys,
+    tuples: &[Tuple],
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    for parent in &foreign_keys.parents {
+        let foreign_key = &parent.foreign_key;
+        let mut keys: HashSet<Vec<Field>> =
+            tuples.iter().filter_map(|t| foreign_key.key(t)).collect();
+        // Records of a table referring to itself may refer to records of the same batch.
+        if foreign_key.parent_id == foreign_keys.table_id {
+            for tuple in tuples {
+                if let Some(key) = foreign_key.parent_key(tuple) {
+                    keys.remove(&key);
+                }
+            }
+        }
+        if keys.is_empty() {
+            continue;
+        }
+        index::retain_absent(sm, &parent.index, &mut keys, tid)?;
+        if let Some(key) = keys.iter().next() {
+            return Err(CrustyError::ConstraintViolation(format!(
+                "Key ({}) of foreign key {} is not present in the parent table",
+                display_key(key),
+                foreign_key.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that records of a table can be deleted and deletes the child records the deletes
+/// cascade to. Returns the number of deleted child records. Nothing is deleted if a foreign
+/// key restricts the deletes.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the child tables.
+/// * `foreign_keys` - Foreign keys of the table.
+/// * `tuples` - Records to delete from the table, with their value ids set.
+/// * `tid` - Transaction deleting the records.
+pub fn delete_children<T: StorageTrait>(
+    sm: &T,
+    foreign_keys: &ForeignKeys,
+    tuples: &[Tuple],
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    if foreign_keys.children.is_empty() {
+        return Ok(0);
+    }
+    // Records deleted from each table, starting with the records of the statement.
+    let mut deleted: HashMap<ContainerId, HashSet<ValueId>> = HashMap::new();
+    deleted.insert(
+        foreign_keys.table_id,
+        tuples.iter().filter_map(|t| t.value_id).collect(),
+    );
+    let mut pending = vec![(foreign_keys.table_id, tuples.to_vec())];
+    while let Some((parent_id, parent_tuples)) = pending.pop() {
+        for child in &foreign_keys.children {
+            let foreign_key = &child.foreign_key;
+            if foreign_key.parent_id != parent_id {
+                continue;
+            }
+            let keys: HashSet<Vec<Field>> = parent_tuples
+                .iter()
+                .filter_map(|t| foreign_key.parent_key(t))
+                .collect();
+            if keys.is_empty() {
+                continue;
+            }
+            let child_deleted = deleted.entry(child.table_id).or_default();
+            let mut cascaded = Vec::new();
+            for (bytes, value_id) in sm.get_iterator(child.table_id, tid, Permissions::ReadOnly) {
+                if child_deleted.contains(&value_id) {
+                    continue;
+                }
+                let mut tuple = child.table.read_tuple(&bytes);
+                let key = match foreign_key.key(&tuple) {
+                    Some(key) if keys.contains(&key) => key,
+                    _ => continue,
+                };
+                if foreign_key.on_delete == OnDelete::Restrict {
+                    return Err(still_referenced(child, &key));
+                }
+                child_deleted.insert(value_id);
+                tuple.value_id = Some(value_id);
+                cascaded.push(tuple);
+            }
+            if !cascaded.is_empty() {
+                pending.push((child.table_id, cascaded));
+            }
+        }
+    }
+
+    let mut count = 0;
+    for (table_id, value_ids) in deleted {
+        if table_id == foreign_keys.table_id || value_ids.is_empty() {
+            continue;
+        }
+        for value_id in &value_ids {
+            sm.delete_value(*value_id, tid)?;
+        }
+        if let Some(child) = foreign_keys
+            .children
+            .iter()
+            .find(|c| c.table_id == table_id)
+        {
+            for index in &child.indexes {
+                index::remove_entries(sm, index, &value_ids, tid)?;
+            }
+        }
+        count += value_ids.len();
+    }
+    Ok(count)
+}
+
+/// Checks that updating records of a table does not change parent keys that child records
+/// still refer to.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the child tables.
+/// * `foreign_keys` - Foreign keys of the table.
+/// * `old_tuples` - Records to update, before the update.
+/// * `new_tuples` - The same records after the update.
+/// * `tid` - Transaction reading the child tables.
+pub fn check_unreferenced<T: StorageTrait>(
+    sm: &T,
+    foreign_keys: &ForeignKeys,
+    old_tuples: &[Tuple],
+    new_tuples: &[Tuple],
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    for child in &foreign_keys.children {
+        let foreign_key = &child.foreign_key;
+        if foreign_key.parent_id != foreign_keys.table_id {
+            continue;
+        }
+        let kept: HashSet<Vec<Field>> = new_tuples
+            .iter()
+            .filter_map(|t| foreign_key.parent_key(t))
+            .collect();
+        let removed: HashSet<Vec<Field>> = old_tuples
+            .iter()
+            .filter_map(|t| foreign_key.parent_key(t))
+            .filter(|key| !kept.contains(key))
+            .collect();
+        if removed.is_empty() {
+            continue;
+        }
+        for (bytes, _) in sm.get_iterator(child.table_id, tid, Permissions::ReadOnly) {
+            if let Some(key) = foreign_key.key(&child.table.read_tuple(&bytes)) {
+                if removed.contains(&key) {
+                    return Err(still_referenced(child, &key));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the error for a parent key that child records still refer to.
+fn still_referenced(child: &ChildKey, key: &[Field]) -> CrustyError {
+    CrustyError::ConstraintViolation(format!(
+        "Key ({}) is still referenced from table {} by foreign key {}",
+        display_key(key),
+        child.table.name,
+        child.foreign_key.name
+    ))
+}
+
+fn display_key(key: &[Field]) -> String {
+    let key: Vec<String> = key.iter().map(|f| f.to_string()).collect();
+    key.join(", ")
+}
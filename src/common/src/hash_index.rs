@@ -0,0 +1,493 @@
+//! Extendible hash indexes.
+//!
+//! A hash index answers equality lookups by reading a bounded number of pages instead of
+//! scanning all its entries, so it suits point-lookup-heavy workloads. Its container holds
+//! fixed-size pages that are changed in place with `StorageTrait::overwrite_value`:
+//!
+//! * The meta page, the first value of the container, holds the global depth and the
+//!   locations of the directory pages.
+//! * Directory pages map the low `global_depth` bits of the hash of a key to a bucket. The
+//!   directory doubles when a bucket whose local depth equals the global depth splits.
+//! * Bucket pages hold entries whose hashes share their low `local_depth` bits. A full
+//!   bucket splits in two on the next bit. Entries that cannot be split apart, such as
+//!   duplicate keys, go to a chain of overflow pages instead.
+//!
+//! A lookup reads the meta page, one directory page and the chain of one bucket.
+
+use crate::bloom::fnv1a;
+use crate::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use crate::index::IndexInfo;
+use crate::storage_trait::StorageTrait;
+use crate::{CrustyError, Field};
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+/// Size in bytes of every page of a hash index.
+pub const HASH_PAGE_SIZE: usize = 2048;
+/// Largest global depth of the directory, which then has 2^14 buckets.
+pub const MAX_GLOBAL_DEPTH: u8 = 14;
+/// Buckets per directory page. The meta page holds the locations of at most
+/// 2^MAX_GLOBAL_DEPTH / DIRECTORY_PAGE_ENTRIES directory pages.
+const DIRECTORY_PAGE_ENTRIES: usize = 128;
+/// Bytes of a bucket page taken by everything but its entries, with room to spare.
+const BUCKET_OVERHEAD: usize = 96;
+/// Bytes of a page taken by the length of its encoding.
+const LENGTH_PREFIX: usize = 4;
+
+/// Held for reading by lookups and for writing by changes, so a lookup never sees a split
+/// half done.
+static HASH_INDEX_LOCK: RwLock<()> = RwLock::new(());
+
+/// Location of a value within a container: a value id without the container id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+struct Location(Option<u8>, Option<u16>, Option<u16>);
+
+impl Location {
+    fn new(id: ValueId) -> Self {
+        Location(id.segment_id, id.page_id, id.slot_id)
+    }
+
+    fn value_id(self, container_id: ContainerId) -> ValueId {
+        ValueId {
+            container_id,
+            segment_id: self.0,
+            page_id: self.1,
+            slot_id: self.2,
+        }
+    }
+}
+
+/// Key of an entry and the location of its record in the indexed table.
+type Entry = (Vec<Field>, Location);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum HashPage {
+    Meta {
+        global_depth: u8,
+        directory: Vec<Location>,
+    },
+    Directory(Vec<Location>),
+    Bucket {
+        local_depth: u8,
+        entries: Vec<Entry>,
+        overflow: Option<Location>,
+    },
+}
+
+impl HashPage {
+    fn to_bytes(&self) -> Result<Vec<u8>, CrustyError> {
+        let encoded = serde_cbor::to_vec(self)
+            .map_err(|e| CrustyError::CrustyError(format!("Cannot encode hash page: {}", e)))?;
+        if LENGTH_PREFIX + encoded.len() > HASH_PAGE_SIZE {
+            return Err(CrustyError::CrustyError(format!(
+                "Hash index page of {} bytes does not fit in {} bytes",
+                encoded.len(),
+                HASH_PAGE_SIZE - LENGTH_PREFIX
+            )));
+        }
+        let mut bytes = Vec::with_capacity(HASH_PAGE_SIZE);
+        bytes.extend_from_slice(&(encoded.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&encoded);
+        bytes.resize(HASH_PAGE_SIZE, 0);
+        Ok(bytes)
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CrustyError> {
+        let corrupt = || CrustyError::CrustyError(String::from("Corrupt hash index page"));
+        let len = u32::from_le_bytes(
+            bytes
+                .get(..LENGTH_PREFIX)
+                .ok_or_else(corrupt)?
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let encoded = bytes
+            .get(LENGTH_PREFIX..LENGTH_PREFIX + len)
+            .ok_or_else(corrupt)?;
+        serde_cbor::from_slice(encoded).map_err(|_| corrupt())
+    }
+}
+
+/// Hash of a key. Indexes are persisted, so the hash must not change between runs.
+fn key_hash(key: &[Field]) -> u64 {
+    fnv1a(&serde_cbor::to_vec(&key).unwrap(), 0xcbf2_9ce4_8422_2325)
+}
+
+/// Mask of the low `depth` bits of a hash.
+fn mask(depth: u8) -> u64 {
+    (1 << depth) - 1
+}
+
+/// Encoded size of the entries of a bucket, without the bucket overhead.
+fn entries_size(entries: &[Entry]) -> usize {
+    entries
+        .iter()
+        .map(|entry| serde_cbor::to_vec(entry).unwrap().len())
+        .sum()
+}
+
+/// Whether splitting a bucket can separate some of its entries.
+fn splittable(local_depth: u8, entries: &[Entry]) -> bool {
+    if local_depth >= MAX_GLOBAL_DEPTH {
+        return false;
+    }
+    let bits = |entry: &Entry| (key_hash(&entry.0) & mask(MAX_GLOBAL_DEPTH)) >> local_depth;
+    let first = bits(&entries[0]);
+    entries.iter().any(|entry| bits(entry) != first)
+}
+
+/// Pages of one hash index, read and written by a transaction.
+struct HashIndex<'a, T: StorageTrait> {
+    sm: &'a T,
+    index: &'a IndexInfo,
+    tid: TransactionId,
+}
+
+impl<'a, T: StorageTrait> HashIndex<'a, T> {
+    fn read(&self, location: Location) -> Result<HashPage, CrustyError> {
+        let id = location.value_id(self.index.container_id);
+        HashPage::from_bytes(&self.sm.get_value(id, self.tid, Permissions::ReadOnly)?)
+    }
+
+    fn write(&self, location: Location, page: &HashPage) -> Result<(), CrustyError> {
+        let id = location.value_id(self.index.container_id);
+        self.sm.overwrite_value(page.to_bytes()?, id, self.tid)
+    }
+
+    fn allocate(&self, page: &HashPage) -> Result<Location, CrustyError> {
+        let id = self
+            .sm
+            .insert_value(self.index.container_id, page.to_bytes()?, self.tid)?;
+        Ok(Location::new(id))
+    }
+
+    fn free(&self, location: Location) -> Result<(), CrustyError> {
+        self.sm
+            .delete_value(location.value_id(self.index.container_id), self.tid)
+    }
+
+    /// Returns the location, global depth and directory page locations of the meta page.
+    fn meta(&self) -> Result<(Location, u8, Vec<Location>), CrustyError> {
+        let (bytes, id) = self
+            .sm
+            .get_iterator(self.index.container_id, self.tid, Permissions::ReadOnly)
+            .next()
+            .ok_or_else(|| {
+                CrustyError::CrustyError(format!("Hash index {} has no pages", self.index.name))
+            })?;
+        match HashPage::from_bytes(&bytes)? {
+            HashPage::Meta {
+                global_depth,
+                directory,
+            } => Ok((Location::new(id), global_depth, directory)),
+            _ => Err(CrustyError::CrustyError(format!(
+                "Hash index {} has no meta page",
+                self.index.name
+            ))),
+        }
+    }
+
+    /// Returns the location of the bucket of a hash.
+    fn bucket(
+        &self,
+        global_depth: u8,
+        directory: &[Location],
+        hash: u64,
+    ) -> Result<Location, CrustyError> {
+        let slot = (hash & mask(global_depth)) as usize;
+        match self.read(directory[slot / DIRECTORY_PAGE_ENTRIES])? {
+            HashPage::Directory(buckets) => Ok(buckets[slot % DIRECTORY_PAGE_ENTRIES]),
+            page => Err(CrustyError::CrustyError(format!(
+                "Expected a directory page, found {:?}",
+                page
+            ))),
+        }
+    }
+
+    /// Returns all the buckets of the directory.
+    fn buckets(&self, directory: &[Location]) -> Result<Vec<Location>, CrustyError> {
+        let mut buckets = Vec::new();
+        for location in directory {
+            match self.read(*location)? {
+                HashPage::Directory(page) => buckets.extend(page),
+                page => {
+                    return Err(CrustyError::CrustyError(format!(
+                        "Expected a directory page, found {:?}",
+                        page
+                    )))
+                }
+            }
+        }
+        Ok(buckets)
+    }
+
+    /// Returns the local depth of a bucket, the locations of its pages starting with the
+    /// bucket itself, and its entries.
+    fn chain(&self, bucket: Location) -> Result<(u8, Vec<Location>, Vec<Entry>), CrustyError> {
+        let mut depth = 0;
+        let mut pages = Vec::new();
+        let mut all_entries = Vec::new();
+        let mut next = Some(bucket);
+        while let Some(location) = next {
+            match self.read(location)? {
+                HashPage::Bucket {
+                    local_depth,
+                    entries,
+                    overflow,
+                } => {
+                    depth = local_depth;
+                    pages.push(location);
+                    all_entries.extend(entries);
+                    next = overflow;
+                }
+                page => {
+                    return Err(CrustyError::CrustyError(format!(
+                        "Expected a bucket page, found {:?}",
+                        page
+                    )))
+                }
+            }
+        }
+        Ok((depth, pages, all_entries))
+    }
+
+    /// Writes the entries of a bucket to the pages of its chain, allocating overflow pages
+    /// as needed and freeing the pages left over.
+    fn write_chain(
+        &self,
+        pages: &[Location],
+        local_depth: u8,
+        entries: Vec<Entry>,
+    ) -> Result<(), CrustyError> {
+        let capacity = HASH_PAGE_SIZE - LENGTH_PREFIX - BUCKET_OVERHEAD;
+        let mut groups = vec![Vec::new()];
+        let mut size = 0;
+        for entry in entries {
+            let entry_size = entries_size(std::slice::from_ref(&entry));
+            if size + entry_size > capacity && !groups.last().unwrap().is_empty() {
+                groups.push(Vec::new());
+                size = 0;
+            }
+            size += entry_size;
+            groups.last_mut().unwrap().push(entry);
+        }
+        let mut locations = pages[..pages.len().min(groups.len())].to_vec();
+        while locations.len() < groups.len() {
+            locations.push(self.allocate(&HashPage::Bucket {
+                local_depth,
+                entries: Vec::new(),
+                overflow: None,
+            })?);
+        }
+        for (i, entries) in groups.into_iter().enumerate() {
+            let page = HashPage::Bucket {
+                local_depth,
+                entries,
+                overflow: locations.get(i + 1).copied(),
+            };
+            self.write(locations[i], &page)?;
+        }
+        for spare in &pages[locations.len().min(pages.len())..] {
+            self.free(*spare)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the directory pages that changed and the meta page.
+    fn write_directory(
+        &self,
+        meta: Location,
+        global_depth: u8,
+        mut directory: Vec<Location>,
+        old_buckets: &[Location],
+        buckets: &[Location],
+    ) -> Result<(), CrustyError> {
+        for (i, page) in buckets.chunks(DIRECTORY_PAGE_ENTRIES).enumerate() {
+            let old = old_buckets.chunks(DIRECTORY_PAGE_ENTRIES).nth(i);
+            if old == Some(page) {
+                continue;
+            }
+            let page = HashPage::Directory(page.to_vec());
+            match directory.get(i) {
+                Some(location) => self.write(*location, &page)?,
+                None => directory.push(self.allocate(&page)?),
+            }
+        }
+        self.write(
+            meta,
+            &HashPage::Meta {
+                global_depth,
+                directory,
+            },
+        )
+    }
+
+    /// Adds an entry, splitting its bucket if it is full.
+    fn insert(&self, entry: Entry) -> Result<(), CrustyError> {
+        let hash = key_hash(&entry.0);
+        let (meta, global_depth, directory) = self.meta()?;
+        let bucket = self.bucket(global_depth, &directory, hash)?;
+        let (local_depth, pages, mut entries) = self.chain(bucket)?;
+        entries.push(entry);
+        if entries_size(&entries) <= HASH_PAGE_SIZE - LENGTH_PREFIX - BUCKET_OVERHEAD
+            || !splittable(local_depth, &entries)
+        {
+            return self.write_chain(&pages, local_depth, entries);
+        }
+
+        // split the bucket on bit local_depth, doubling the directory if no other bit is left
+        let old_buckets = self.buckets(&directory)?;
+        let mut buckets = old_buckets.clone();
+        let mut global_depth = global_depth;
+        if local_depth == global_depth {
+            buckets.extend_from_within(..);
+            global_depth += 1;
+        }
+        let bit = 1 << local_depth;
+        let (high, low): (Vec<Entry>, Vec<Entry>) = entries
+            .into_iter()
+            .partition(|(key, _)| key_hash(key) & bit != 0);
+        let split = self.allocate(&HashPage::Bucket {
+            local_depth: local_depth + 1,
+            entries: Vec::new(),
+            overflow: None,
+        })?;
+        for (slot, location) in buckets.iter_mut().enumerate() {
+            if *location == bucket && slot as u64 & bit != 0 {
+                *location = split;
+            }
+        }
+        self.write_chain(&pages, local_depth + 1, low)?;
+        self.write_chain(&[split], local_depth + 1, high)?;
+        self.write_directory(meta, global_depth, directory, &old_buckets, &buckets)
+    }
+}
+
+/// Initializes the pages of an empty hash index container.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Hash index to initialize.
+/// * `tid` - Transaction creating the index.
+pub fn create<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    let _lock = HASH_INDEX_LOCK.write().unwrap();
+    let pages = HashIndex { sm, index, tid };
+    // the meta page must be the first value of the container
+    let meta = pages.allocate(&HashPage::Meta {
+        global_depth: 0,
+        directory: Vec::new(),
+    })?;
+    let bucket = pages.allocate(&HashPage::Bucket {
+        local_depth: 0,
+        entries: Vec::new(),
+        overflow: None,
+    })?;
+    pages.write_directory(meta, 0, Vec::new(), &[], &[bucket])
+}
+
+/// Adds entries to a hash index.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Hash index to update.
+/// * `entries` - Keys and the records of the table they belong to.
+/// * `tid` - Transaction inserting the entries.
+pub fn insert<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    entries: Vec<(Vec<Field>, ValueId)>,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    let _lock = HASH_INDEX_LOCK.write().unwrap();
+    let pages = HashIndex { sm, index, tid };
+    for (key, value_id) in entries {
+        pages.insert((key, Location::new(value_id)))?;
+    }
+    Ok(())
+}
+
+/// Removes the entries of records from a hash index and returns the number of removed
+/// entries. Entries are found by record rather than by key, so all the bucket pages are
+/// read.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Hash index to update.
+/// * `value_ids` - Records whose entries are removed.
+/// * `tid` - Transaction removing the entries.
+pub fn remove<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    value_ids: &HashSet<ValueId>,
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    let _lock = HASH_INDEX_LOCK.write().unwrap();
+    let pages = HashIndex { sm, index, tid };
+    let records: HashSet<Location> = value_ids
+        .iter()
+        .filter(|id| id.container_id == index.table_id)
+        .map(|id| Location::new(*id))
+        .collect();
+    let mut changed = Vec::new();
+    let mut removed = 0;
+    for (bytes, id) in sm.get_iterator(index.container_id, tid, Permissions::ReadOnly) {
+        if let HashPage::Bucket {
+            local_depth,
+            entries,
+            overflow,
+        } = HashPage::from_bytes(&bytes)?
+        {
+            let count = entries.len();
+            let entries: Vec<Entry> = entries
+                .into_iter()
+                .filter(|(_, record)| !records.contains(record))
+                .collect();
+            if entries.len() < count {
+                removed += count - entries.len();
+                let page = HashPage::Bucket {
+                    local_depth,
+                    entries,
+                    overflow,
+                };
+                changed.push((Location::new(id), page));
+            }
+        }
+    }
+    for (location, page) in changed {
+        pages.write(location, &page)?;
+    }
+    Ok(removed)
+}
+
+/// Returns the records of the table whose key equals `key`.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Hash index to search.
+/// * `key` - Values of the indexed columns.
+/// * `tid` - Transaction reading the index.
+pub fn lookup<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    key: &[Field],
+    tid: TransactionId,
+) -> Result<Vec<ValueId>, CrustyError> {
+    let _lock = HASH_INDEX_LOCK.read().unwrap();
+    let pages = HashIndex { sm, index, tid };
+    let (_, global_depth, directory) = pages.meta()?;
+    let bucket = pages.bucket(global_depth, &directory, key_hash(key))?;
+    let (_, _, entries) = pages.chain(bucket)?;
+    Ok(entries
+        .into_iter()
+        .filter(|(entry_key, _)| entry_key == key)
+        .map(|(_, record)| record.value_id(index.table_id))
+        .collect())
+}
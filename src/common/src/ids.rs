@@ -57,6 +57,15 @@ pub enum StateType {
     HashTable,
     BaseTable,
     MatView,
+    Index,
+    /// Chunks of one column of a columnar table.
+    Column,
+    /// Records of one partition of a partitioned table.
+    Partition,
+    /// Tuples spilled by an operator, removed when the operator is done.
+    Spill,
+    /// Result of a query kept by a result cache, removed when it is invalidated or evicted.
+    CachedResult,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -0,0 +1,673 @@
+//! Secondary indexes over base tables.
+//!
+//! An index is a container holding one entry per record of its table: the values of the
+//! indexed columns and the value id of the record. Indexes are registered in the database
+//! catalog together with the table they depend on. `build` bulk loads a new index from its
+//! table, and the DML operators keep it up to date with `insert_entries` and `remove_entries`.
+//!
+//! Unique indexes reject records whose key is already in the index. Tables get one for their
+//! primary key and each of their unique constraints, and the DML operators call
+//! `check_unique` before they change the table.
+//!
+//! Keys are ordered tuples of the values of the indexed columns, compared column by column,
+//! so an index on (a, b) serves both lookups of whole keys and scans of a `KeyRange`: the
+//! keys with given values of a and a range of values of b, or a range of values of a.
+//!
+//! The entries of an index are stored according to its `IndexKind`: unordered in a heap,
+//! searched by scanning, in an extendible hash table (see `hash_index`) for equality
+//! lookups that read a few pages, or as a bitmap of records per key (see `bitmap_index`)
+//! for columns with few distinct values.
+
+use crate::bitmap_index;
+use crate::columnar;
+use crate::hash_index;
+use crate::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use crate::like;
+use crate::logical_plan::SimplePredicateOp;
+use crate::storage_trait::StorageTrait;
+use crate::table::{Table, TableLayout};
+use crate::{CrustyError, Field, Tuple};
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::{Bound, RangeBounds};
+
+/// How the entries of an index are stored.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IndexKind {
+    /// Unordered entries, one value of the index container each. Lookups scan them.
+    #[default]
+    Heap,
+    /// Entries in the buckets of an extendible hash table, for point lookups.
+    Hash,
+    /// A compressed bitmap of records per key, for low-cardinality columns.
+    Bitmap,
+}
+
+/// Catalog entry of an index.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexInfo {
+    /// Name of the index.
+    pub name: String,
+    /// Container holding the entries of the index.
+    pub container_id: ContainerId,
+    /// Table the index is built over.
+    pub table_id: ContainerId,
+    /// Indices of the indexed columns in the table schema, in key order.
+    pub columns: Vec<usize>,
+    /// Whether no two records may have the same key. Keys with a null value are never equal
+    /// to another key.
+    #[serde(default)]
+    pub unique: bool,
+    /// Whether the index enforces a primary key or unique constraint of the table, and so
+    /// cannot be dropped on its own.
+    #[serde(default)]
+    pub constraint: bool,
+    /// How the entries are stored.
+    #[serde(default)]
+    pub kind: IndexKind,
+}
+
+impl IndexInfo {
+    /// Returns the key of a record of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Record of the indexed table.
+    pub fn key(&self, tuple: &Tuple) -> Vec<Field> {
+        self.columns
+            .iter()
+            .map(|i| tuple.get_field(*i).cloned().unwrap_or(Field::Null))
+            .collect()
+    }
+
+    /// Returns the range of keys of the records that satisfy comparisons of columns of the
+    /// table to values, or None if the comparisons do not restrict the first column. The
+    /// range fixes the leading columns compared for equality and bounds the next column
+    /// with its other comparisons, a `LIKE` bounding it to the strings that start with the
+    /// characters before the pattern's first wildcard. A hash index only serves ranges that
+    /// fix every column.
+    ///
+    /// # Arguments
+    ///
+    /// * `comparisons` - Indices of columns of the table, comparison operators and the
+    ///   values the columns are compared to, all of which the records satisfy.
+    pub fn key_range(&self, comparisons: &[(usize, SimplePredicateOp, Field)]) -> Option<KeyRange> {
+        let mut range = KeyRange::exact(Vec::new());
+        for column in &self.columns {
+            let on_column = comparisons.iter().filter(|(c, _, _)| c == column);
+            let equal = on_column
+                .clone()
+                .find(|(_, op, _)| matches!(op, SimplePredicateOp::Equals));
+            if let Some((_, _, value)) = equal {
+                range.prefix.push(value.clone());
+                continue;
+            }
+            for (_, op, value) in on_column {
+                let value = value.clone();
+                match op {
+                    SimplePredicateOp::GreaterThan => {
+                        range.lower = tighter(range.lower, Bound::Excluded(value), true)
+                    }
+                    SimplePredicateOp::GreaterThanOrEq => {
+                        range.lower = tighter(range.lower, Bound::Included(value), true)
+                    }
+                    SimplePredicateOp::LessThan => {
+                        range.upper = tighter(range.upper, Bound::Excluded(value), false)
+                    }
+                    SimplePredicateOp::LessThanOrEq => {
+                        range.upper = tighter(range.upper, Bound::Included(value), false)
+                    }
+                    SimplePredicateOp::Like => {
+                        // Matches start with the characters before the first wildcard.
+                        if let Field::StringField(pattern) = value {
+                            let prefix = like::prefix(&pattern);
+                            if !prefix.is_empty() {
+                                let lower = Bound::Included(Field::StringField(prefix.into()));
+                                range.lower = tighter(range.lower, lower, true);
+                            }
+                            if let Some(next) = like::prefix_successor(prefix) {
+                                let upper = Bound::Excluded(Field::StringField(next));
+                                range.upper = tighter(range.upper, upper, false);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            break;
+        }
+        let bounded = range.lower != Bound::Unbounded || range.upper != Bound::Unbounded;
+        match self.kind {
+            _ if range.prefix.is_empty() && !bounded => None,
+            IndexKind::Hash if range.prefix.len() < self.columns.len() => None,
+            _ => Some(range),
+        }
+    }
+}
+
+/// Returns the tighter of two lower bounds, or of two upper bounds if `lower` is false.
+fn tighter(a: Bound<Field>, b: Bound<Field>, lower: bool) -> Bound<Field> {
+    let value = |bound: &Bound<Field>| match bound {
+        Bound::Included(v) | Bound::Excluded(v) => Some(v.clone()),
+        Bound::Unbounded => None,
+    };
+    match (value(&a), value(&b)) {
+        (None, _) => b,
+        (_, None) => a,
+        (Some(x), Some(y)) if x == y => match a {
+            Bound::Excluded(_) => a,
+            _ => b,
+        },
+        (Some(x), Some(y)) => {
+            if (x > y) == lower {
+                a
+            } else {
+                b
+            }
+        }
+    }
+}
+
+/// Range of keys of an index: the keys that start with the values of `prefix` and whose
+/// next column lies between `lower` and `upper`. Keys compare column by column, so the keys
+/// of a range are contiguous in key order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyRange {
+    /// Values of the leading columns of the keys.
+    pub prefix: Vec<Field>,
+    /// Lower bound of the column after the prefix.
+    pub lower: Bound<Field>,
+    /// Upper bound of the column after the prefix.
+    pub upper: Bound<Field>,
+}
+
+impl KeyRange {
+    /// Returns the range of the keys that start with `prefix`, or of a single key.
+    pub fn exact(prefix: Vec<Field>) -> Self {
+        KeyRange {
+            prefix,
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+
+    /// Returns whether a key is in the range. Fields compare as in filters.
+    pub fn contains(&self, key: &[Field]) -> bool {
+        if !key.starts_with(&self.prefix) {
+            return false;
+        }
+        match key.get(self.prefix.len()) {
+            Some(next) => (self.lower.as_ref(), self.upper.as_ref()).contains(next),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for KeyRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut columns: Vec<String> = self.prefix.iter().map(|v| v.to_string()).collect();
+        let mut bounds = Vec::new();
+        match &self.lower {
+            Bound::Included(v) => bounds.push(format!(">= {}", v)),
+            Bound::Excluded(v) => bounds.push(format!("> {}", v)),
+            Bound::Unbounded => {}
+        }
+        match &self.upper {
+            Bound::Included(v) => bounds.push(format!("<= {}", v)),
+            Bound::Excluded(v) => bounds.push(format!("< {}", v)),
+            Bound::Unbounded => {}
+        }
+        if !bounds.is_empty() {
+            columns.push(bounds.join(" and "));
+        }
+        write!(f, "({})", columns.join(", "))
+    }
+}
+
+/// Entry of an index, stored as a value of the index container.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Values of the indexed columns, in key order.
+    pub key: Vec<Field>,
+    /// Record the key belongs to.
+    pub value_id: ValueId,
+}
+
+impl IndexEntry {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serde_cbor::to_vec(&self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        serde_cbor::from_slice(bytes).unwrap()
+    }
+}
+
+/// Fills an empty index container with the entries of all the records of its table and
+/// returns the number of entries. Fails without adding entries if the index is unique and
+/// two records have the same key.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table and the index.
+/// * `table` - The indexed table.
+/// * `index` - Index to build.
+/// * `tid` - Transaction reading the table.
+pub fn build<T: StorageTrait>(
+    sm: &T,
+    table: &Table,
+    index: &IndexInfo,
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    debug!(
+        "Building index {} over container {}",
+        index.name, index.table_id
+    );
+    let records = table
+        .layout
+        .record_containers(index.table_id)
+        .into_iter()
+        .flat_map(|container_id| sm.get_iterator(container_id, tid, Permissions::ReadOnly));
+    let entries = record_entries(sm, table, index, records, tid)?;
+    if index.unique {
+        let mut keys = HashSet::new();
+        for entry in &entries {
+            if !entry.key.contains(&Field::Null) && !keys.insert(&entry.key) {
+                return Err(duplicate_key(index, &entry.key));
+            }
+        }
+    }
+    if index.kind == IndexKind::Hash {
+        hash_index::create(sm, index, tid)?;
+    }
+    let count = entries.len();
+    add_entries(sm, index, entries, tid)?;
+    Ok(count)
+}
+
+/// Returns the entries of the records of a table yielded by `records`, values of the
+/// containers holding the table's records and their value ids.
+fn record_entries<T: StorageTrait>(
+    sm: &T,
+    table: &Table,
+    index: &IndexInfo,
+    records: impl Iterator<Item = (Vec<u8>, ValueId)>,
+    tid: TransactionId,
+) -> Result<Vec<IndexEntry>, CrustyError> {
+    Ok(match table.layout {
+        TableLayout::Row | TableLayout::Partitioned(_) => records
+            .map(|(bytes, value_id)| IndexEntry {
+                key: index.key(&table.read_tuple(&bytes)),
+                value_id,
+            })
+            .collect(),
+        TableLayout::Column(_) => columnar::read_records(sm, records, table.schema.size(), tid)?
+            .into_iter()
+            .map(|tuple| IndexEntry {
+                key: index.key(&tuple),
+                value_id: tuple.value_id.unwrap(),
+            })
+            .collect(),
+    })
+}
+
+/// Adds entries to an index, as its kind stores them.
+fn add_entries<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    entries: Vec<IndexEntry>,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    match index.kind {
+        IndexKind::Heap => {
+            let entries = entries.iter().map(IndexEntry::to_bytes).collect();
+            sm.insert_values(index.container_id, entries, tid)?;
+        }
+        IndexKind::Hash => {
+            let entries = entries.into_iter().map(|e| (e.key, e.value_id)).collect();
+            hash_index::insert(sm, index, entries, tid)?;
+        }
+        IndexKind::Bitmap => {
+            let entries = entries.into_iter().map(|e| (e.key, e.value_id)).collect();
+            bitmap_index::insert(sm, index, entries, tid)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks that adding records to a unique index keeps its keys unique. Does nothing if the
+/// index is not unique.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index the records will be added to.
+/// * `tuples` - New records of the table.
+/// * `replaced` - Records whose entries are removed when the new records are added, such as
+///   the old versions of updated records.
+/// * `tid` - Transaction reading the index.
+pub fn check_unique<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    tuples: &[Tuple],
+    replaced: &HashSet<ValueId>,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    if !index.unique {
+        return Ok(());
+    }
+    let mut keys = HashSet::new();
+    for tuple in tuples {
+        let key = index.key(tuple);
+        if key.contains(&Field::Null) {
+            continue;
+        }
+        if keys.contains(&key) {
+            return Err(duplicate_key(index, &key));
+        }
+        keys.insert(key);
+    }
+    if keys.is_empty() {
+        return Ok(());
+    }
+    // A bloom filter over the key column of the table can rule out every new key without
+    // scanning the index.
+    if let [column] = index.columns.as_slice() {
+        if sm.bloom_filter_column(index.table_id) == Some(*column)
+            && !keys
+                .iter()
+                .any(|key| sm.may_contain(index.table_id, &key[0]))
+        {
+            return Ok(());
+        }
+    }
+    if index.kind != IndexKind::Heap {
+        for key in &keys {
+            let records = lookup(sm, index, key, tid)?;
+            if records.iter().any(|value_id| !replaced.contains(value_id)) {
+                return Err(duplicate_key(index, key));
+            }
+        }
+        return Ok(());
+    }
+    for (bytes, _) in sm.get_iterator(index.container_id, tid, Permissions::ReadOnly) {
+        let entry = IndexEntry::from_bytes(&bytes);
+        if keys.contains(&entry.key) && !replaced.contains(&entry.value_id) {
+            return Err(duplicate_key(index, &entry.key));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the error for a key that is already in a unique index.
+fn duplicate_key(index: &IndexInfo, key: &[Field]) -> CrustyError {
+    let key: Vec<String> = key.iter().map(|f| f.to_string()).collect();
+    CrustyError::DuplicateKey(format!(
+        "Key ({}) already exists in unique index {}",
+        key.join(", "),
+        index.name
+    ))
+}
+
+/// Adds the entries of new records to an index.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index to update.
+/// * `records` - New records of the table and their value ids.
+/// * `tid` - Transaction inserting the records.
+pub fn insert_entries<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    records: &[(Tuple, ValueId)],
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    let entries = records
+        .iter()
+        .map(|(tuple, value_id)| IndexEntry {
+            key: index.key(tuple),
+            value_id: *value_id,
+        })
+        .collect();
+    add_entries(sm, index, entries, tid)
+}
+
+/// Removes the entries of records from an index and returns the number of removed entries.
+///
+/// The index is scanned once, so callers should remove the entries of all the records a
+/// statement changes together.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index to update.
+/// * `value_ids` - Records whose entries are removed.
+/// * `tid` - Transaction removing the records.
+pub fn remove_entries<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    value_ids: &HashSet<ValueId>,
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    if value_ids.is_empty() {
+        return Ok(0);
+    }
+    match index.kind {
+        IndexKind::Hash => return hash_index::remove(sm, index, value_ids, tid),
+        IndexKind::Bitmap => return bitmap_index::remove(sm, index, value_ids, tid),
+        IndexKind::Heap => {}
+    }
+    let stale: Vec<ValueId> = sm
+        .get_iterator(index.container_id, tid, Permissions::ReadOnly)
+        .filter(|(bytes, _)| value_ids.contains(&IndexEntry::from_bytes(bytes).value_id))
+        .map(|(_, entry_id)| entry_id)
+        .collect();
+    for entry_id in &stale {
+        sm.delete_value(*entry_id, tid)?;
+    }
+    Ok(stale.len())
+}
+
+/// Points the entries of records the storage manager moved at their new value ids, e.g.
+/// after a compaction that left forwarding stubs at the old ones, and returns the number of
+/// entries rewritten. The records are read at their new ids for their keys; the entries of
+/// the ones that cannot be read anymore are only removed.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table and the index.
+/// * `table` - The indexed table.
+/// * `index` - Index to update.
+/// * `moved` - Old and new value ids of the moved records.
+/// * `tid` - Transaction updating the index.
+pub fn remap_entries<T: StorageTrait>(
+    sm: &T,
+    table: &Table,
+    index: &IndexInfo,
+    moved: &[(ValueId, ValueId)],
+    tid: TransactionId,
+) -> Result<usize, CrustyError> {
+    let old_ids: HashSet<ValueId> = moved.iter().map(|(old, _)| *old).collect();
+    let records: Vec<(Vec<u8>, ValueId)> = moved
+        .iter()
+        .filter_map(|(_, new)| {
+            let bytes = sm.get_value(*new, tid, Permissions::ReadOnly).ok()?;
+            Some((bytes, *new))
+        })
+        .collect();
+    let entries = record_entries(sm, table, index, records.into_iter(), tid)?;
+    remove_entries(sm, index, &old_ids, tid)?;
+    let count = entries.len();
+    add_entries(sm, index, entries, tid)?;
+    Ok(count)
+}
+
+/// Returns the records of the table whose indexed columns equal a key.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index to search.
+/// * `key` - Values of the indexed columns.
+/// * `tid` - Transaction reading the index.
+pub fn lookup<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    key: &[Field],
+    tid: TransactionId,
+) -> Result<Vec<ValueId>, CrustyError> {
+    scan(sm, index, &KeyRange::exact(key.to_vec()), tid)
+}
+
+/// Returns the records of the table whose keys are in a range, in key order, or in the
+/// order of the records for a bitmap index.
+///
+/// A heap index is scanned; a hash index reads the bucket of the key, so it only serves
+/// ranges that fix every column; a bitmap index unites the bitmaps of the keys in range.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index to search.
+/// * `range` - Keys to return the records of.
+/// * `tid` - Transaction reading the index.
+pub fn scan<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    range: &KeyRange,
+    tid: TransactionId,
+) -> Result<Vec<ValueId>, CrustyError> {
+    if index.kind == IndexKind::Hash {
+        if range.prefix.len() != index.columns.len() {
+            return Err(CrustyError::ExecutionError(format!(
+                "Hash index {} cannot scan the range {}",
+                index.name, range
+            )));
+        }
+        return hash_index::lookup(sm, index, &range.prefix, tid);
+    }
+    if index.kind == IndexKind::Bitmap {
+        return Ok(bitmap_index::scan(sm, index, range, tid)?.value_ids());
+    }
+    let mut entries: Vec<IndexEntry> = sm
+        .get_iterator(index.container_id, tid, Permissions::ReadOnly)
+        .map(|(bytes, _)| IndexEntry::from_bytes(&bytes))
+        .filter(|entry| range.contains(&entry.key))
+        .collect();
+    entries.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(entries.into_iter().map(|entry| entry.value_id).collect())
+}
+
+/// Removes from `keys` the keys that are in an index.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the index.
+/// * `index` - Index to search.
+/// * `keys` - Keys to search for. Only the keys absent from the index are left.
+/// * `tid` - Transaction reading the index.
+pub fn retain_absent<T: StorageTrait>(
+    sm: &T,
+    index: &IndexInfo,
+    keys: &mut HashSet<Vec<Field>>,
+    tid: TransactionId,
+) -> Result<(), CrustyError> {
+    if index.kind == IndexKind::Hash {
+        for key in keys.clone() {
+            if !hash_index::lookup(sm, index, &key, tid)?.is_empty() {
+                keys.remove(&key);
+            }
+        }
+        return Ok(());
+    }
+    if index.kind == IndexKind::Bitmap {
+        for key in bitmap_index::keys(sm, index, tid)? {
+            keys.remove(&key);
+        }
+        return Ok(());
+    }
+    for (bytes, _) in sm.get_iterator(index.container_id, tid, Permissions::ReadOnly) {
+        keys.remove(&IndexEntry::from_bytes(&bytes).key);
+        if keys.is_empty() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn index(columns: Vec<usize>, kind: IndexKind) -> IndexInfo {
+        IndexInfo {
+            name: String::from("i"),
+            container_id: 2,
+            table_id: 1,
+            columns,
+            unique: false,
+            constraint: false,
+            kind,
+        }
+    }
+
+    fn int(i: i32) -> Field {
+        Field::IntField(i)
+    }
+
+    #[test]
+    fn test_key_range() {
+        use SimplePredicateOp::*;
+        let ab = index(vec![0, 1], IndexKind::Heap);
+        let range = ab
+            .key_range(&[
+                (1, GreaterThan, int(3)),
+                (0, Equals, int(7)),
+                (1, GreaterThanOrEq, int(3)),
+                (1, LessThan, int(9)),
+                (1, LessThanOrEq, int(8)),
+                (2, Equals, int(0)),
+            ])
+            .unwrap();
+        assert_eq!(vec![int(7)], range.prefix);
+        assert_eq!(Bound::Excluded(int(3)), range.lower);
+        assert_eq!(Bound::Included(int(8)), range.upper);
+        assert_eq!("(7, > 3 and <= 8)", range.to_string());
+        assert!(range.contains(&[int(7), int(8)]));
+        assert!(!range.contains(&[int(7), int(3)]));
+        assert!(!range.contains(&[int(6), int(5)]));
+
+        // the leading column must be compared
+        assert_eq!(None, ab.key_range(&[(1, Equals, int(3))]));
+        assert_eq!(None, ab.key_range(&[(0, NotEq, int(3))]));
+        let range = ab.key_range(&[(0, LessThan, int(3)), (1, Equals, int(3))]);
+        assert_eq!(Some(Bound::Excluded(int(3))), range.map(|r| r.upper));
+
+        // a hash index needs every column
+        let hash = index(vec![0, 1], IndexKind::Hash);
+        assert_eq!(None, hash.key_range(&[(0, Equals, int(3))]));
+        let range = hash
+            .key_range(&[(0, Equals, int(3)), (1, Equals, int(4))])
+            .unwrap();
+        assert_eq!(KeyRange::exact(vec![int(3), int(4)]), range);
+    }
+
+    #[test]
+    fn test_like_key_range() {
+        use SimplePredicateOp::*;
+        let string = |s: &str| Field::StringField(s.to_string());
+        let a = index(vec![0], IndexKind::Heap);
+        let range = a.key_range(&[(0, Like, string("ap%le"))]).unwrap();
+        assert_eq!(Bound::Included(string("ap")), range.lower);
+        assert_eq!(Bound::Excluded(string("aq")), range.upper);
+        assert!(range.contains(&[string("apple")]));
+        assert!(range.contains(&[string("ap")]));
+        assert!(!range.contains(&[string("aq")]));
+        assert!(!range.contains(&[string("a")]));
+
+        // patterns starting with a wildcard and NOT LIKE do not restrict the column
+        assert_eq!(None, a.key_range(&[(0, Like, string("%le"))]));
+        assert_eq!(None, a.key_range(&[(0, NotLike, string("ap%"))]));
+    }
+}
@@ -4,26 +4,55 @@ extern crate serde;
 #[macro_use]
 extern crate log;
 
-use prelude::{TidType, TransactionId, ValueId};
+use prelude::{ContainerId, PageId, SlotId, TidType, TransactionId, ValueId};
 use serde::de::{Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
 use sqlparser::ast;
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::path::{Path, PathBuf};
 // use proc_macro::bridge::client::ProcMacro::Attr;
 
+#[cfg(feature = "arrow")]
+pub mod arrow_batch;
+pub mod bitmap;
+pub mod bitmap_index;
+pub mod bloom;
 pub mod catalog;
+pub mod columnar;
 pub mod commands;
 pub mod crusty_graph;
+pub mod csv_export;
+pub mod csv_import;
 pub mod database;
+pub mod datetime;
+pub use datetime::{Date, Timestamp};
+pub mod decimal;
+pub use decimal::Decimal;
+pub mod encoding;
+pub mod foreign_key;
+pub mod hash_index;
 pub mod ids;
+pub mod index;
+pub mod like;
 pub mod logical_plan;
+pub mod metrics;
+pub mod ndjson;
+#[cfg(feature = "parquet")]
+pub mod parquet_io;
+pub mod partition;
 pub use logical_plan::{AggOp, SimplePredicateOp};
 pub mod physical_plan;
+pub mod statistics;
+pub mod storage_conformance;
 pub mod storage_trait;
+pub use storage_trait::ContainerConfig;
 pub mod table;
+pub mod temp_container;
 pub mod testutil;
 pub mod traits;
 
@@ -41,7 +70,8 @@ pub mod prelude {
     };
     pub use crate::table::Table;
     pub use crate::CrustyError;
-    pub use crate::{DataType, Field, TableSchema, Tuple};
+    pub use crate::{DataType, Decimal, Field, Float, TableSchema, Tuple};
+    pub use crate::{Date, Timestamp};
 }
 
 /// Custom error type.
@@ -61,6 +91,54 @@ pub enum CrustyError {
     InvalidMutationError(String),
     /// Transaction Rollback
     TransactionRollback(TransactionId),
+    /// Records that break a constraint of their table.
+    ConstraintViolation(String),
+    /// Records whose key is already in a unique index of their table.
+    DuplicateKey(String),
+    /// A value that does not fit in a page of a container.
+    PageFull {
+        container_id: ContainerId,
+        page_id: PageId,
+    },
+    /// A value id whose slot does not hold a value.
+    InvalidSlot {
+        container_id: ContainerId,
+        page_id: Option<PageId>,
+        slot_id: Option<SlotId>,
+    },
+    /// A container the storage manager does not have.
+    ContainerMissing(ContainerId),
+    /// A container that would grow past the page limit of its `ContainerConfig`.
+    QuotaExceeded {
+        container_id: ContainerId,
+        max_pages: PageId,
+    },
+    /// IO errors on a file.
+    IoWithPath { path: PathBuf, message: String },
+}
+
+impl CrustyError {
+    /// Returns an IO error on a file with the path of the file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file.
+    /// * `error` - Error of the IO operation.
+    pub fn io_with_path(path: &Path, error: io::Error) -> Self {
+        CrustyError::IoWithPath {
+            path: path.to_path_buf(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Returns the error for a value id whose slot does not hold a value.
+    pub fn invalid_slot(id: &ValueId) -> Self {
+        CrustyError::InvalidSlot {
+            container_id: id.container_id,
+            page_id: id.page_id,
+            slot_id: id.slot_id,
+        }
+    }
 }
 
 impl fmt::Display for CrustyError {
@@ -77,6 +155,36 @@ impl fmt::Display for CrustyError {
                 CrustyError::InvalidMutationError(s) => format!("InvalidMutationError {}", s),
                 CrustyError::TransactionRollback(tid) =>
                     format!("Transaction Rolledback {:?}", tid),
+                CrustyError::ConstraintViolation(s) => format!("Constraint Violation: {}", s),
+                CrustyError::DuplicateKey(s) => format!("Duplicate Key: {}", s),
+                CrustyError::PageFull {
+                    container_id,
+                    page_id,
+                } => format!(
+                    "Page Full: value does not fit in page {} of container {}",
+                    page_id, container_id
+                ),
+                CrustyError::InvalidSlot {
+                    container_id,
+                    page_id,
+                    slot_id,
+                } => format!(
+                    "Invalid Slot: no value in slot {:?} of page {:?} of container {}",
+                    slot_id, page_id, container_id
+                ),
+                CrustyError::ContainerMissing(container_id) => format!(
+                    "Container Missing: container {} does not exist",
+                    container_id
+                ),
+                CrustyError::QuotaExceeded {
+                    container_id,
+                    max_pages,
+                } => format!(
+                    "Quota Exceeded: container {} cannot grow past {} pages",
+                    container_id, max_pages
+                ),
+                CrustyError::IoWithPath { path, message } =>
+                    format!("{}: {}", path.to_string_lossy(), message),
             }
         )
     }
@@ -318,6 +426,18 @@ impl Attribute {
         }
     }
 
+    /// Returns whether the attribute can hold null values. Primary keys and columns declared
+    /// NOT NULL cannot.
+    pub fn is_nullable(&self) -> bool {
+        !matches!(
+            self.constraint,
+            Constraint::PrimaryKey
+                | Constraint::NotNull
+                | Constraint::UniqueNotNull
+                | Constraint::NotNullFKey(_)
+        )
+    }
+
     /// Returns the name of the attribute.
     pub fn name(&self) -> &str {
         &self.name
@@ -334,6 +454,11 @@ impl Attribute {
         match self.dtype {
             DataType::Int => 4,
             DataType::String => 132,
+            DataType::Float => 8,
+            DataType::Decimal(_, _) => 8,
+            DataType::Date => 4,
+            DataType::Timestamp => 8,
+            DataType::Boolean => 1,
         }
     }
 }
@@ -343,6 +468,93 @@ impl Attribute {
 pub enum DataType {
     Int,
     String,
+    Float,
+    /// Fixed-point decimal with a precision (total number of digits) and a scale (number of
+    /// digits after the point).
+    Decimal(u32, u32),
+    Date,
+    Timestamp,
+    Boolean,
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataType::Int => write!(f, "INT"),
+            DataType::String => write!(f, "VARCHAR"),
+            DataType::Float => write!(f, "FLOAT"),
+            DataType::Decimal(precision, scale) => write!(f, "DECIMAL({}, {})", precision, scale),
+            DataType::Date => write!(f, "DATE"),
+            DataType::Timestamp => write!(f, "TIMESTAMP"),
+            DataType::Boolean => write!(f, "BOOLEAN"),
+        }
+    }
+}
+
+impl DataType {
+    /// Returns whether `Field::coerce` converts some values of this type to another type, so
+    /// that columns of this type may be stored in columns of the other.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtype` - Type of the column.
+    pub fn coerces_to(&self, dtype: &DataType) -> bool {
+        matches!(
+            (self, dtype),
+            (DataType::Int, _)
+                | (
+                    DataType::Float | DataType::Decimal(_, _),
+                    DataType::Int | DataType::Float | DataType::Decimal(_, _) | DataType::String
+                )
+                | (
+                    DataType::String,
+                    DataType::String | DataType::Date | DataType::Timestamp | DataType::Boolean
+                )
+                | (DataType::Date, DataType::Date | DataType::Timestamp)
+                | (DataType::Timestamp, DataType::Timestamp)
+                | (DataType::Boolean, DataType::Boolean)
+        )
+    }
+}
+
+/// Double precision float value of a field.
+///
+/// Floats are compared with the IEEE 754 total order (`f64::total_cmp`), so that fields can
+/// be sorted, hashed, and joined on like the other field types. In this order -0.0 is less
+/// than 0.0 and NaNs are equal only to NaNs with the same bits.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_bits().hash(state);
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
 
 /// For each of the dtypes, make sure that there is a corresponding field type.
@@ -350,6 +562,11 @@ pub enum DataType {
 pub enum Field {
     IntField(i32),
     StringField(String),
+    FloatField(Float),
+    DecimalField(Decimal),
+    DateField(Date),
+    TimestampField(Timestamp),
+    BoolField(bool),
     Null,
 }
 
@@ -360,6 +577,11 @@ impl Field {
     pub fn to_bytes(&self) -> Vec<u8> {
         match self {
             Field::IntField(x) => x.to_le_bytes().to_vec(),
+            Field::FloatField(x) => x.0.to_le_bytes().to_vec(),
+            Field::DecimalField(x) => x.value.to_le_bytes().to_vec(),
+            Field::DateField(x) => x.0.to_le_bytes().to_vec(),
+            Field::TimestampField(x) => x.0.to_le_bytes().to_vec(),
+            Field::BoolField(x) => vec![*x as u8],
             Field::StringField(s) => {
                 let s_len: usize = s.len();
                 let mut result = s_len.to_le_bytes().to_vec();
@@ -389,6 +611,249 @@ impl Field {
             _ => panic!("Expected String"),
         }
     }
+
+    /// Unwraps float fields.
+    pub fn unwrap_float_field(&self) -> f64 {
+        match self {
+            Field::FloatField(x) => x.0,
+            _ => panic!("Expected f64"),
+        }
+    }
+
+    /// Unwraps decimal fields.
+    pub fn unwrap_decimal_field(&self) -> Decimal {
+        match self {
+            Field::DecimalField(x) => *x,
+            _ => panic!("Expected Decimal"),
+        }
+    }
+
+    /// Unwraps boolean fields.
+    pub fn unwrap_bool_field(&self) -> bool {
+        match self {
+            Field::BoolField(x) => *x,
+            _ => panic!("Expected bool"),
+        }
+    }
+
+    /// Parses a boolean literal, ignoring case. Accepts true/false, t/f, and 1/0. Returns None
+    /// if the literal is not a boolean.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - Literal to parse.
+    pub fn from_bool(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Some(Field::BoolField(true)),
+            "false" | "f" | "0" => Some(Field::BoolField(false)),
+            _ => None,
+        }
+    }
+
+    /// Parses a numeric literal into an integer field, a decimal field if it has a decimal
+    /// point, or a float field if it has an exponent. Returns None if the literal is not a
+    /// number.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - Literal to parse.
+    pub fn from_number(s: &str) -> Option<Self> {
+        if let Ok(i) = s.parse::<i32>() {
+            return Some(Field::IntField(i));
+        }
+        if let Some(d) = Decimal::parse(s) {
+            return Some(Field::DecimalField(d));
+        }
+        // Only accept literals with digits, so that words like inf or nan stay strings.
+        if s.bytes().any(|b| b.is_ascii_digit()) {
+            if let Ok(x) = s.parse::<f64>() {
+                return Some(Field::FloatField(Float(x)));
+            }
+        }
+        None
+    }
+
+    /// Returns whether the field can be stored in a column of a type as it is. Null can be
+    /// stored in columns of any type.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtype` - Type of the column.
+    pub fn has_type(&self, dtype: &DataType) -> bool {
+        match (self, dtype) {
+            (Field::Null, _)
+            | (Field::IntField(_), DataType::Int)
+            | (Field::StringField(_), DataType::String)
+            | (Field::FloatField(_), DataType::Float)
+            | (Field::DateField(_), DataType::Date)
+            | (Field::TimestampField(_), DataType::Timestamp)
+            | (Field::BoolField(_), DataType::Boolean) => true,
+            (Field::DecimalField(d), DataType::Decimal(precision, scale)) => {
+                d.scale == *scale && d.rescale(*precision, *scale).is_some()
+            }
+            _ => false,
+        }
+    }
+
+    /// Converts a field to the type of the column it is stored in or compared to, where the
+    /// conversion loses nothing. Fields that cannot be converted exactly, and values that do
+    /// not fit the column, are returned unchanged, so `has_type` rejects them.
+    ///
+    /// | From \ To  | Int         | Float | Decimal     | String | Date/Timestamp | Boolean |
+    /// |------------|-------------|-------|-------------|--------|----------------|---------|
+    /// | Int        | yes         | yes   | yes         | yes    |                | 0 and 1 |
+    /// | Float      | if integral | yes   | if exact    | yes    |                |         |
+    /// | Decimal    | if integral | yes   | rounded     | yes    |                |         |
+    /// | String     |             |       |             | yes    | if ISO 8601    | `t`/`f` |
+    /// | Date       |             |       |             |        | yes            |         |
+    ///
+    /// Decimals are rounded half away from zero to the scale of a decimal column. Strings
+    /// are never read as numbers implicitly, only with `cast`.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtype` - Type of the column.
+    pub fn coerce(self, dtype: &DataType) -> Self {
+        let converted = match (&self, dtype) {
+            (Field::IntField(i), DataType::Boolean) if *i != 0 && *i != 1 => return self,
+            (Field::FloatField(_) | Field::DecimalField(_), DataType::Int)
+            | (Field::FloatField(_), DataType::Decimal(_, _)) => {
+                return match self.clone().cast(dtype) {
+                    Ok(value) if value.numeric_eq(&self) => value,
+                    _ => self,
+                };
+            }
+            (
+                Field::IntField(_) | Field::DecimalField(_),
+                DataType::Float | DataType::Decimal(_, _),
+            )
+            | (
+                Field::IntField(_) | Field::FloatField(_) | Field::DecimalField(_),
+                DataType::String,
+            )
+            | (Field::StringField(_), DataType::Date | DataType::Timestamp | DataType::Boolean)
+            | (Field::DateField(_), DataType::Timestamp)
+            | (Field::IntField(_), DataType::Boolean) => self.clone().cast(dtype),
+            _ => return self,
+        };
+        converted.unwrap_or(self)
+    }
+
+    /// Returns whether two numeric fields have the same value.
+    fn numeric_eq(&self, other: &Field) -> bool {
+        let decimal = |field: &Field| match field {
+            Field::IntField(i) => Some(Decimal::new(*i as i64, 0)),
+            Field::DecimalField(d) => Some(*d),
+            _ => None,
+        };
+        match (self, other) {
+            (Field::FloatField(f), other) | (other, Field::FloatField(f)) => {
+                match (decimal(other), other) {
+                    (Some(d), _) => d.to_f64() == f.0,
+                    (None, Field::FloatField(g)) => f == g,
+                    _ => false,
+                }
+            }
+            _ => decimal(self).is_some_and(|d| decimal(other) == Some(d)),
+        }
+    }
+
+    /// Converts a field to a type, as `CAST(value AS type)` does. NULL converts to every
+    /// type. Numbers convert to each other, rounding half away from zero when digits are
+    /// dropped; strings parse as numbers, dates, timestamps and booleans; integers convert
+    /// to booleans, nonzero being true; and every value converts to its text. Returns an
+    /// error if the type cannot hold the value or the conversion is not supported.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtype` - Type to convert the field to.
+    pub fn cast(self, dtype: &DataType) -> Result<Self, CrustyError> {
+        let invalid = |field: &Field| {
+            CrustyError::ExecutionError(format!("Cannot cast {} to {}", field, dtype))
+        };
+        let out_of_range = |field: &Field| {
+            CrustyError::ExecutionError(format!("Value {} is out of range for {}", field, dtype))
+        };
+        let value = match (&self, dtype) {
+            (Field::Null, _) => Field::Null,
+            (Field::StringField(s), DataType::String) => Field::StringField(s.clone()),
+            (field, DataType::String) => Field::StringField(field.to_string()),
+            (Field::IntField(i), DataType::Int) => Field::IntField(*i),
+            (Field::FloatField(f), DataType::Int) => {
+                let rounded = f.0.round();
+                if !(i32::MIN as f64..=i32::MAX as f64).contains(&rounded) {
+                    return Err(out_of_range(&self));
+                }
+                Field::IntField(rounded as i32)
+            }
+            (Field::DecimalField(d), DataType::Int) => {
+                let rounded = d.rescale(decimal::MAX_PRECISION, 0);
+                match rounded.and_then(|r| i32::try_from(r.value).ok()) {
+                    Some(i) => Field::IntField(i),
+                    None => return Err(out_of_range(&self)),
+                }
+            }
+            (Field::BoolField(b), DataType::Int) => Field::IntField(*b as i32),
+            (Field::IntField(i), DataType::Float) => Field::FloatField(Float(*i as f64)),
+            (Field::FloatField(f), DataType::Float) => Field::FloatField(*f),
+            (Field::DecimalField(d), DataType::Float) => Field::FloatField(Float(d.to_f64())),
+            (Field::IntField(i), DataType::Decimal(p, s)) => {
+                match Decimal::new(*i as i64, 0).rescale(*p, *s) {
+                    Some(d) => Field::DecimalField(d),
+                    None => return Err(out_of_range(&self)),
+                }
+            }
+            (Field::DecimalField(d), DataType::Decimal(p, s)) => match d.rescale(*p, *s) {
+                Some(d) => Field::DecimalField(d),
+                None => return Err(out_of_range(&self)),
+            },
+            (Field::FloatField(f), DataType::Decimal(p, s)) => {
+                let scaled = (f.0 * 10f64.powi(*s as i32)).round();
+                if scaled.is_nan() || scaled.abs() >= 10f64.powi(*p as i32) {
+                    return Err(out_of_range(&self));
+                }
+                Field::DecimalField(Decimal::new(scaled as i64, *s))
+            }
+            (Field::StringField(s), DataType::Int) => match s.trim().parse::<i32>() {
+                Ok(i) => Field::IntField(i),
+                Err(_) => return Err(invalid(&self)),
+            },
+            (Field::StringField(s), DataType::Float) => {
+                // Only accept text with digits, so that words like inf or nan are invalid.
+                let s = s.trim();
+                match s.parse::<f64>() {
+                    Ok(f) if s.bytes().any(|b| b.is_ascii_digit()) => Field::FloatField(Float(f)),
+                    _ => return Err(invalid(&self)),
+                }
+            }
+            (Field::StringField(s), DataType::Decimal(_, _)) => match Decimal::parse(s.trim()) {
+                Some(d) => return Field::DecimalField(d).cast(dtype),
+                None => return Err(invalid(&self)),
+            },
+            (Field::StringField(s), DataType::Date) => match Date::parse(s.trim()) {
+                Some(date) => Field::DateField(date),
+                None => return Err(invalid(&self)),
+            },
+            (Field::StringField(s), DataType::Timestamp) => match Timestamp::parse(s.trim()) {
+                Some(timestamp) => Field::TimestampField(timestamp),
+                None => return Err(invalid(&self)),
+            },
+            (Field::StringField(s), DataType::Boolean) => match Field::from_bool(s) {
+                Some(b) => b,
+                None => return Err(invalid(&self)),
+            },
+            (Field::DateField(date), DataType::Date) => Field::DateField(*date),
+            (Field::DateField(date), DataType::Timestamp) => {
+                Field::TimestampField(Timestamp::from(*date))
+            }
+            (Field::TimestampField(t), DataType::Timestamp) => Field::TimestampField(*t),
+            (Field::TimestampField(t), DataType::Date) => Field::DateField(t.date()),
+            (Field::IntField(i), DataType::Boolean) => Field::BoolField(*i != 0),
+            (Field::BoolField(b), DataType::Boolean) => Field::BoolField(*b),
+            _ => return Err(invalid(&self)),
+        };
+        Ok(value)
+    }
 }
 
 impl fmt::Display for Field {
@@ -396,6 +861,11 @@ impl fmt::Display for Field {
         match self {
             Field::IntField(x) => write!(f, "{}", x),
             Field::StringField(x) => write!(f, "{}", x),
+            Field::FloatField(x) => write!(f, "{}", x),
+            Field::DecimalField(x) => write!(f, "{}", x),
+            Field::DateField(x) => write!(f, "{}", x),
+            Field::TimestampField(x) => write!(f, "{}", x),
+            Field::BoolField(x) => write!(f, "{}", x),
             Field::Null => write!(f, "[null]"),
         }
     }
@@ -492,12 +962,18 @@ impl Tuple {
         Self::new(fields)
     }
 
+    /// Serializes the tuple in the compact self-describing encoding of `encoding::encode`.
     pub fn to_bytes(&self) -> Vec<u8> {
-        serde_cbor::to_vec(&self).unwrap()
+        encoding::encode(self)
     }
 
+    /// Deserializes a tuple written by `to_bytes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bytes are not an encoded tuple.
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        serde_cbor::from_slice(bytes).unwrap()
+        encoding::decode(bytes).unwrap()
     }
 
     pub fn to_csv(&self) -> String {
@@ -506,6 +982,11 @@ impl Tuple {
             let val = match field {
                 Field::IntField(i) => i.to_string(),
                 Field::StringField(s) => s.to_string(),
+                Field::FloatField(x) => x.to_string(),
+                Field::DecimalField(x) => x.to_string(),
+                Field::DateField(x) => x.to_string(),
+                Field::TimestampField(x) => x.to_string(),
+                Field::BoolField(x) => x.to_string(),
                 Field::Null => String::from("null"),
             };
             res.push(val);
@@ -521,6 +1002,11 @@ impl fmt::Display for Tuple {
             let val = match field {
                 Field::IntField(i) => i.to_string(),
                 Field::StringField(s) => s.to_string(),
+                Field::FloatField(x) => x.to_string(),
+                Field::DecimalField(x) => x.to_string(),
+                Field::DateField(x) => x.to_string(),
+                Field::TimestampField(x) => x.to_string(),
+                Field::BoolField(x) => x.to_string(),
                 Field::Null => String::from("[null]"),
             };
             res.push_str(&val);
@@ -575,6 +1061,25 @@ pub fn get_attr(dtype: &ast::DataType) -> Result<DataType, CrustyError> {
     match dtype {
         ast::DataType::Int => Ok(DataType::Int),
         ast::DataType::Varchar(_) => Ok(DataType::String),
+        ast::DataType::Float(_) | ast::DataType::Real | ast::DataType::Double => {
+            Ok(DataType::Float)
+        }
+        ast::DataType::Decimal(precision, scale) => {
+            let precision = precision.unwrap_or(decimal::DEFAULT_PRECISION as u64);
+            let scale = scale.unwrap_or(0);
+            if precision == 0 || precision > decimal::MAX_PRECISION as u64 || scale > precision {
+                return Err(CrustyError::CrustyError(format!(
+                    "Invalid decimal precision {} and scale {}, precision must be between 1 and {} and scale at most the precision",
+                    precision,
+                    scale,
+                    decimal::MAX_PRECISION
+                )));
+            }
+            Ok(DataType::Decimal(precision as u32, scale as u32))
+        }
+        ast::DataType::Date => Ok(DataType::Date),
+        ast::DataType::Timestamp => Ok(DataType::Timestamp),
+        ast::DataType::Boolean => Ok(DataType::Boolean),
         //TODO append type
         _ => Err(CrustyError::CrustyError(String::from(
             "Unsupported data type ",
@@ -582,6 +1087,46 @@ pub fn get_attr(dtype: &ast::DataType) -> Result<DataType, CrustyError> {
     }
 }
 
+/// Converts a typed string literal such as `DATE '2024-01-31'` to a field of its type.
+///
+/// # Arguments
+///
+/// * `data_type` - Type of the literal.
+/// * `value` - Text of the literal.
+pub fn parse_typed_string(data_type: &ast::DataType, value: &str) -> Result<Field, CrustyError> {
+    let dtype = get_attr(data_type)?;
+    let field = Field::StringField(value.to_string()).coerce(&dtype);
+    if field.has_type(&dtype) {
+        Ok(field)
+    } else {
+        Err(CrustyError::ValidationError(format!(
+            "Invalid {} literal '{}'",
+            data_type, value
+        )))
+    }
+}
+
+/// Converts a negative number literal, which the command parser reads as a minus applied to
+/// a number, to a field. None if the expression is not one or the number is invalid.
+///
+/// # Arguments
+///
+/// * `expr` - Expression from the command parser.
+pub fn negative_number(expr: &ast::Expr) -> Option<Field> {
+    match expr {
+        ast::Expr::UnaryOp {
+            op: ast::UnaryOperator::Minus,
+            expr,
+        } => match expr.as_ref() {
+            ast::Expr::Value(ast::Value::Number(val, false)) => {
+                Field::from_number(&format!("-{}", val))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub enum QueryResultType {
     CSV(bool), // header
     WIDTH(bool, usize), // header, default width
@@ -600,4 +1145,203 @@ mod libtests {
         let check_tuple: Tuple = Tuple::from_bytes(&tuple_bytes);
         assert_eq!(tuple, check_tuple);
     }
+
+    #[test]
+    fn test_float_field() {
+        let tuple = Tuple::new(vec![
+            Field::FloatField(Float(1.5)),
+            Field::FloatField(Float(f64::NAN)),
+            Field::IntField(2),
+        ]);
+        let check_tuple = Tuple::from_bytes(&tuple.to_bytes());
+        assert_eq!(tuple, check_tuple);
+
+        let mut fields: Vec<Field> = [f64::NAN, 2.0, -1.0, f64::NEG_INFINITY]
+            .iter()
+            .map(|x| Field::FloatField(Float(*x)))
+            .collect();
+        fields.sort();
+        let sorted: Vec<f64> = fields.iter().map(|f| f.unwrap_float_field()).collect();
+        assert_eq!(vec![f64::NEG_INFINITY, -1.0, 2.0], sorted[..3].to_vec());
+        assert!(sorted[3].is_nan());
+
+        assert_eq!(Some(Field::IntField(3)), Field::from_number("3"));
+        assert_eq!(
+            Some(Field::FloatField(Float(0.25))),
+            Field::from_number("2.5e-1")
+        );
+        assert_eq!(None, Field::from_number("inf"));
+        assert_eq!(
+            Field::FloatField(Float(3.0)),
+            Field::IntField(3).coerce(&DataType::Float)
+        );
+    }
+
+    #[test]
+    fn test_date_fields() {
+        let day = Field::StringField("2024-02-29".to_string()).coerce(&DataType::Date);
+        assert_eq!(Field::DateField(Date::parse("2024-02-29").unwrap()), day);
+        assert!(day.has_type(&DataType::Date));
+        let midnight = day.clone().coerce(&DataType::Timestamp);
+        assert_eq!("2024-02-29 00:00:00", midnight.to_string());
+
+        let bad = Field::StringField("2023-02-29".to_string()).coerce(&DataType::Date);
+        assert!(!bad.has_type(&DataType::Date));
+
+        let t = parse_typed_string(&ast::DataType::Timestamp, "2024-02-29T10:00:00").unwrap();
+        assert!(t > midnight);
+        assert!(parse_typed_string(&ast::DataType::Date, "yesterday").is_err());
+        let tuple = Tuple::new(vec![day, t]);
+        assert_eq!(tuple, Tuple::from_bytes(&tuple.to_bytes()));
+    }
+
+    #[test]
+    fn test_bool_field() {
+        assert_eq!(Some(Field::BoolField(true)), Field::from_bool("TRUE"));
+        assert_eq!(Some(Field::BoolField(false)), Field::from_bool("f"));
+        assert_eq!(None, Field::from_bool("yes"));
+        let flag = Field::StringField("false".to_string()).coerce(&DataType::Boolean);
+        assert!(flag.has_type(&DataType::Boolean));
+        assert!(flag < Field::BoolField(true));
+        let tuple = Tuple::new(vec![flag, Field::IntField(1)]);
+        assert_eq!("false,1", tuple.to_csv());
+        assert_eq!(tuple, Tuple::from_bytes(&tuple.to_bytes()));
+    }
+
+    #[test]
+    fn test_decimal_field() {
+        let price = Field::from_number("19.99").unwrap();
+        assert_eq!(Field::DecimalField(Decimal::new(1999, 2)), price);
+        let tuple = Tuple::new(vec![price.clone(), Field::IntField(1)]);
+        assert_eq!(tuple, Tuple::from_bytes(&tuple.to_bytes()));
+
+        let money = DataType::Decimal(10, 2);
+        assert_eq!(
+            Field::DecimalField(Decimal::new(500, 2)),
+            Field::IntField(5).coerce(&money)
+        );
+        assert_eq!(
+            "20.00",
+            Field::from_number("19.995")
+                .unwrap()
+                .coerce(&money)
+                .to_string()
+        );
+        assert!(Field::from_number("20").unwrap().coerce(&money) > price);
+        assert_eq!(
+            Field::FloatField(Float(19.99)),
+            price.coerce(&DataType::Float)
+        );
+    }
+
+    #[test]
+    fn test_cast() {
+        let string = |s: &str| Field::StringField(s.to_string());
+        let money = DataType::Decimal(10, 2);
+        let cases = [
+            (
+                Field::FloatField(Float(2.5)),
+                DataType::Int,
+                Field::IntField(3),
+            ),
+            (
+                Field::FloatField(Float(-2.5)),
+                DataType::Int,
+                Field::IntField(-3),
+            ),
+            (
+                Field::DecimalField(Decimal::new(1999, 2)),
+                DataType::Int,
+                Field::IntField(20),
+            ),
+            (
+                Field::FloatField(Float(0.125)),
+                money.clone(),
+                Field::DecimalField(Decimal::new(13, 2)),
+            ),
+            (Field::IntField(7), DataType::String, string("7")),
+            (
+                Field::DecimalField(Decimal::new(150, 2)),
+                DataType::String,
+                string("1.50"),
+            ),
+            (string(" 42 "), DataType::Int, Field::IntField(42)),
+            (
+                string("2.5e1"),
+                DataType::Float,
+                Field::FloatField(Float(25.0)),
+            ),
+            (
+                string("3.456"),
+                money.clone(),
+                Field::DecimalField(Decimal::new(346, 2)),
+            ),
+            (
+                string("2024-01-31"),
+                DataType::Date,
+                Field::DateField(Date::from_ymd(2024, 1, 31).unwrap()),
+            ),
+            (
+                Field::IntField(2),
+                DataType::Boolean,
+                Field::BoolField(true),
+            ),
+            (Field::Null, DataType::Int, Field::Null),
+        ];
+        for (field, dtype, expected) in cases {
+            assert_eq!(expected, field.clone().cast(&dtype).unwrap(), "{}", field);
+        }
+        for (field, dtype) in [
+            (string("abc"), DataType::Int),
+            (string("inf"), DataType::Float),
+            (string("2023-02-29"), DataType::Date),
+            (Field::FloatField(Float(1e10)), DataType::Int),
+            (Field::FloatField(Float(f64::NAN)), DataType::Int),
+            (Field::IntField(1_000_000_000), money.clone()),
+            (
+                Field::DateField(Date::from_ymd(2024, 1, 31).unwrap()),
+                DataType::Int,
+            ),
+        ] {
+            assert!(
+                field.clone().cast(&dtype).is_err(),
+                "{} to {}",
+                field,
+                dtype
+            );
+        }
+    }
+
+    #[test]
+    fn test_coerce_lossless() {
+        let string = |s: &str| Field::StringField(s.to_string());
+        let money = DataType::Decimal(10, 2);
+        assert_eq!(
+            Field::IntField(3),
+            Field::FloatField(Float(3.0)).coerce(&DataType::Int)
+        );
+        assert_eq!(
+            Field::FloatField(Float(3.5)),
+            Field::FloatField(Float(3.5)).coerce(&DataType::Int)
+        );
+        assert_eq!(
+            Field::IntField(4),
+            Field::DecimalField(Decimal::new(400, 2)).coerce(&DataType::Int)
+        );
+        assert_eq!(
+            Field::DecimalField(Decimal::new(25, 2)),
+            Field::FloatField(Float(0.25)).coerce(&money)
+        );
+        assert_eq!(
+            Field::FloatField(Float(0.125)),
+            Field::FloatField(Float(0.125)).coerce(&money)
+        );
+        assert_eq!(string("12"), Field::IntField(12).coerce(&DataType::String));
+        assert_eq!(string("12"), string("12").coerce(&DataType::Int));
+        assert_eq!(string("1.5"), string("1.5").coerce(&money));
+        assert_eq!(
+            Field::IntField(2),
+            Field::IntField(2).coerce(&DataType::Boolean)
+        );
+    }
 }
@@ -0,0 +1,122 @@
+//! SQL `LIKE` pattern matching.
+//!
+//! In a pattern `%` matches any sequence of characters, including none, and `_` matches
+//! exactly one character. Every other character matches itself, case sensitively. There is
+//! no escape character.
+
+/// Returns true if a string matches a `LIKE` pattern.
+///
+/// # Arguments
+///
+/// * `value` - String to match.
+/// * `pattern` - Pattern to match the string against.
+pub fn matches(value: &str, pattern: &str) -> bool {
+    let value: Vec<char> = value.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    let (mut v, mut p) = (0, 0);
+    // Position of the last `%` seen and of the value character it was last resumed at.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('%') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some(c) if *c == '_' || *c == value[v] => {
+                v += 1;
+                p += 1;
+            }
+            _ => match backtrack {
+                // Let the last `%` absorb one more character and retry.
+                Some((star, resume)) => {
+                    backtrack = Some((star, resume + 1));
+                    p = star + 1;
+                    v = resume + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
+}
+
+/// Returns the characters every match of a pattern starts with, which are those before
+/// its first wildcard.
+///
+/// # Arguments
+///
+/// * `pattern` - `LIKE` pattern.
+pub fn prefix(pattern: &str) -> &str {
+    let end = pattern.find(['%', '_']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+/// Returns the smallest string greater than every string starting with a prefix, or None
+/// if there is no such string or the prefix is empty. Strings starting with the prefix are
+/// exactly those in `[prefix, successor)`.
+///
+/// # Arguments
+///
+/// * `prefix` - Prefix of the strings.
+pub fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut chars: Vec<char> = prefix.chars().collect();
+    while let Some(last) = chars.pop() {
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            chars.push(next);
+            return Some(chars.into_iter().collect());
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        for (value, pattern, expected) in [
+            ("apple", "apple", true),
+            ("apple", "app", false),
+            ("apple", "app%", true),
+            ("apple", "%ple", true),
+            ("apple", "%pp%", true),
+            ("apple", "a_ple", true),
+            ("apple", "a_le", false),
+            ("apple", "%", true),
+            ("", "%", true),
+            ("", "_", false),
+            ("apple", "A%", false),
+            ("banana", "%an%na", true),
+            ("banana", "b%n_", true),
+            ("banana", "%a%a%a%a", false),
+            ("a%b", "a%b", true),
+        ] {
+            assert_eq!(
+                expected,
+                matches(value, pattern),
+                "{} LIKE {}",
+                value,
+                pattern
+            );
+        }
+    }
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!("app", prefix("app%"));
+        assert_eq!("a", prefix("a_p%"));
+        assert_eq!("", prefix("%le"));
+        assert_eq!("apple", prefix("apple"));
+    }
+
+    #[test]
+    fn test_prefix_successor() {
+        assert_eq!(Some("apq".to_string()), prefix_successor("app"));
+        assert_eq!(Some("b".to_string()), prefix_successor("a"));
+        assert_eq!(Some("b".to_string()), prefix_successor("a\u{10FFFF}"));
+        assert_eq!(None, prefix_successor(""));
+        assert_eq!(None, prefix_successor("\u{10FFFF}"));
+    }
+}
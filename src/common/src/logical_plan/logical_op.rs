@@ -3,7 +3,7 @@ use std::fmt;
 use std::fmt::Debug;
 
 use crate::ids::ContainerId;
-use crate::Field;
+use crate::{DataType, Field};
 
 /// Scan node.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -38,6 +38,22 @@ pub struct AggregateNode {
     pub group_by: Vec<FieldIdentifier>,
 }
 
+/// Sort node.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SortNode {
+    /// Columns to sort by, in order, each with true if it is sorted in ascending order.
+    pub fields: Vec<(FieldIdentifier, bool)>,
+}
+
+/// Limit node.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LimitNode {
+    /// Maximum number of rows to return, or None to return all of them.
+    pub limit: Option<usize>,
+    /// Number of rows to skip before returning rows.
+    pub offset: usize,
+}
+
 /// JoinNode
 /// * left - field on left side of op
 /// * op - comparison operator
@@ -77,11 +93,109 @@ pub struct UpdateNode {
     pub assignments: Vec<(FieldIdentifier, Field)>,
 }
 
+/// Insert node. Inserts the records of its child.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InsertNode {
+    /// Table to insert into.
+    pub alias: String,
+    /// Container of the table.
+    pub container_id: ContainerId,
+}
+
+/// Delete node. Deletes the records of its child.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeleteNode {
+    /// Table to delete from.
+    pub alias: String,
+    /// Container of the table.
+    pub container_id: ContainerId,
+}
+
 /// Predicate to be used in filter
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Predicate {
     SimplePredicate(SimplePredicate),
     CompoundPredicate(CompoundPredicate),
+    /// Conjunction or disjunction of predicates that are not all simple.
+    NestedPredicate(NestedPredicate),
+    /// Negation of a predicate.
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Returns the simple predicates the predicate is composed of.
+    pub fn simple_predicates(&self) -> Vec<&SimplePredicate> {
+        match self {
+            Predicate::SimplePredicate(simple_predicate) => vec![simple_predicate],
+            Predicate::CompoundPredicate(compound_predicate) => {
+                compound_predicate.simple_predicates.iter().collect()
+            }
+            Predicate::NestedPredicate(nested_predicate) => nested_predicate
+                .predicates
+                .iter()
+                .flat_map(|p| p.simple_predicates())
+                .collect(),
+            Predicate::Not(predicate) => predicate.simple_predicates(),
+        }
+    }
+
+    /// Returns the subqueries the predicate compares columns to.
+    pub fn subqueries(&self) -> Vec<&SubqueryExpr> {
+        self.simple_predicates()
+            .into_iter()
+            .flat_map(|p| [&p.left, &p.right])
+            .filter_map(|expr| expr.subquery())
+            .collect()
+    }
+
+    /// Returns the subqueries the predicate compares columns to for modification.
+    pub fn subqueries_mut(&mut self) -> Vec<&mut SubqueryExpr> {
+        let simple_predicates: Vec<&mut SimplePredicate> = match self {
+            Predicate::SimplePredicate(simple_predicate) => vec![simple_predicate],
+            Predicate::CompoundPredicate(compound_predicate) => {
+                compound_predicate.simple_predicates.iter_mut().collect()
+            }
+            Predicate::NestedPredicate(nested_predicate) => {
+                return nested_predicate
+                    .predicates
+                    .iter_mut()
+                    .flat_map(|p| p.subqueries_mut())
+                    .collect()
+            }
+            Predicate::Not(predicate) => return predicate.subqueries_mut(),
+        };
+        simple_predicates
+            .into_iter()
+            .flat_map(|p| [&mut p.left, &mut p.right])
+            .filter_map(|expr| match expr {
+                PredExpr::Subquery(s) => Some(s),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Writes the predicate as SQL, with parentheses around nested conjunctions and disjunctions.
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nested = |p: &Predicate| match p {
+            Predicate::CompoundPredicate(_) | Predicate::NestedPredicate(_) => format!("({})", p),
+            _ => p.to_string(),
+        };
+        match self {
+            Predicate::SimplePredicate(p) => write!(f, "{}", p),
+            Predicate::CompoundPredicate(p) => {
+                let operands: Vec<String> =
+                    p.simple_predicates.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}", operands.join(&format!(" {} ", p.op)))
+            }
+            Predicate::NestedPredicate(p) => {
+                let operands: Vec<String> = p.predicates.iter().map(nested).collect();
+                write!(f, "{}", operands.join(&format!(" {} ", p.op)))
+            }
+            Predicate::Not(p) => write!(f, "NOT {}", nested(p)),
+        }
+    }
 }
 
 /// All the operations that can be in a predicate
@@ -99,15 +213,22 @@ pub struct SimplePredicate {
     pub right: PredExpr,
 }
 
+impl fmt::Display for SimplePredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.left, self.op, self.right)
+    }
+}
+
 /// The operations which can be used in a simple predicate
 impl SimplePredicateOp {
-    /// Do predicate comparison.
+    /// Do predicate comparison. `LIKE` and `NOT LIKE` match a string on the left against a
+    /// pattern on the right, and are false for any other fields.
     ///
     /// # Arguments
     ///
     /// * `left_field` - Left field of the predicate.
     /// * `right_field` - Right field of the predicate.
-    pub fn compare<T: Ord>(&self, left_field: &T, right_field: &T) -> bool {
+    pub fn compare(&self, left_field: &Field, right_field: &Field) -> bool {
         match self {
             SimplePredicateOp::Equals => left_field == right_field,
             SimplePredicateOp::GreaterThan => left_field > right_field,
@@ -115,6 +236,8 @@ impl SimplePredicateOp {
             SimplePredicateOp::LessThanOrEq => left_field <= right_field,
             SimplePredicateOp::GreaterThanOrEq => left_field >= right_field,
             SimplePredicateOp::NotEq => left_field != right_field,
+            SimplePredicateOp::Like => like(left_field, right_field) == Some(true),
+            SimplePredicateOp::NotLike => like(left_field, right_field) == Some(false),
             SimplePredicateOp::All => true,
         }
     }
@@ -131,6 +254,14 @@ impl SimplePredicateOp {
     }
 }
 
+/// Returns whether a string matches a pattern, or None if either field is not a string.
+fn like(value: &Field, pattern: &Field) -> Option<bool> {
+    match (value, pattern) {
+        (Field::StringField(v), Field::StringField(p)) => Some(crate::like::matches(v, p)),
+        _ => None,
+    }
+}
+
 /// Operators for simple predicates
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum SimplePredicateOp {
@@ -140,6 +271,9 @@ pub enum SimplePredicateOp {
     LessThanOrEq,
     GreaterThanOrEq,
     NotEq,
+    /// String matches a pattern, such as `name LIKE 'a%'`. See `crate::like`.
+    Like,
+    NotLike,
     All,
 }
 
@@ -150,6 +284,13 @@ pub struct CompoundPredicate {
     pub simple_predicates: Vec<SimplePredicate>,
 }
 
+/// Compound predicate over arbitrary predicates, such as `a = 1 OR (b = 2 AND c = 3)`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NestedPredicate {
+    pub op: CompoundPredicateOp,
+    pub predicates: Vec<Predicate>,
+}
+
 /// Operations for compound predicates
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum CompoundPredicateOp {
@@ -157,6 +298,15 @@ pub enum CompoundPredicateOp {
     Or,
 }
 
+impl fmt::Display for CompoundPredicateOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundPredicateOp::And => write!(f, "AND"),
+            CompoundPredicateOp::Or => write!(f, "OR"),
+        }
+    }
+}
+
 impl CompoundPredicateOp {
     /// Gets the identity element of this compound predicate operator
     pub fn identity(&self) -> bool {
@@ -179,6 +329,36 @@ impl CompoundPredicateOp {
 pub enum PredExpr {
     Literal(Field),
     Ident(FieldIdentifier),
+    /// Constant bound each time the plan runs, by position: `$1` is parameter 0.
+    Parameter(usize),
+    /// Value of a subquery returning at most one row of one column.
+    Subquery(SubqueryExpr),
+}
+
+impl fmt::Display for PredExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PredExpr::Literal(Field::StringField(s)) => write!(f, "'{}'", s),
+            PredExpr::Literal(field) => write!(f, "{}", field),
+            PredExpr::Ident(ident) => write!(f, "{}", ident),
+            PredExpr::Parameter(i) => write!(f, "${}", i + 1),
+            PredExpr::Subquery(_) => write!(f, "(subquery)"),
+        }
+    }
+}
+
+/// Scalar subquery of a predicate, such as `b = (SELECT MAX(u.v) FROM u WHERE u.k = t.a)`.
+///
+/// The plan of the subquery is part of the plan holding the predicate: its root is a child
+/// of the filter, added before the filter's input. Columns of the enclosing query that the
+/// subquery refers to are parameters of the subquery's plan, bound from each filtered tuple.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SubqueryExpr {
+    /// Root of the plan of the subquery.
+    pub root: usize,
+    /// Columns of the enclosing query the subquery refers to. Column `i` is parameter `i`
+    /// of the subquery's plan.
+    pub parameters: Vec<FieldIdentifier>,
 }
 
 impl PredExpr {
@@ -189,6 +369,39 @@ impl PredExpr {
             _ => None,
         }
     }
+
+    /// Get the position of the parameter from the predicate expression.
+    pub fn parameter(&self) -> Option<usize> {
+        match self {
+            PredExpr::Parameter(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Get the subquery from the predicate expression.
+    pub fn subquery(&self) -> Option<&SubqueryExpr> {
+        match self {
+            PredExpr::Subquery(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SimplePredicateOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op_str = match self {
+            SimplePredicateOp::Equals => "=",
+            SimplePredicateOp::GreaterThan => ">",
+            SimplePredicateOp::LessThan => "<",
+            SimplePredicateOp::LessThanOrEq => "<=",
+            SimplePredicateOp::GreaterThanOrEq => ">=",
+            SimplePredicateOp::NotEq => "!=",
+            SimplePredicateOp::Like => "LIKE",
+            SimplePredicateOp::NotLike => "NOT LIKE",
+            SimplePredicateOp::All => "all",
+        };
+        write!(f, "{}", op_str)
+    }
 }
 
 /// Aggregation operations.
@@ -214,6 +427,146 @@ impl fmt::Display for AggOp {
     }
 }
 
+/// Arithmetic operators of scalar expressions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+}
+
+impl fmt::Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op_str = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+            ArithmeticOp::Modulo => "%",
+        };
+        write!(f, "{}", op_str)
+    }
+}
+
+/// String functions of scalar expressions.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarFunction {
+    /// `UPPER(s)`.
+    Upper,
+    /// `LOWER(s)`.
+    Lower,
+    /// `LENGTH(s)`, the number of characters of a string.
+    Length,
+    /// `CONCAT(s, ...)` or `s || t`.
+    Concat,
+    /// `SUBSTRING(s FROM start [FOR length])`, with the first character at position 1.
+    Substring,
+    /// `TRIM(s)`, a string without its leading and trailing whitespace.
+    Trim,
+}
+
+impl fmt::Display for ScalarFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScalarFunction::Upper => "upper",
+            ScalarFunction::Lower => "lower",
+            ScalarFunction::Length => "length",
+            ScalarFunction::Concat => "concat",
+            ScalarFunction::Substring => "substring",
+            ScalarFunction::Trim => "trim",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Scalar expression computed from the columns of a tuple, such as `a % 2`, `UPPER(name)` or
+/// `CASE WHEN a > 3 THEN 'high' ELSE 'low' END`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ScalarExpr {
+    /// Value of a column.
+    Column(FieldIdentifier),
+    Literal(Field),
+    /// Arithmetic on two numeric expressions.
+    Arithmetic(Box<ScalarExpr>, ArithmeticOp, Box<ScalarExpr>),
+    /// String function applied to its arguments.
+    Function(ScalarFunction, Vec<ScalarExpr>),
+    /// Comparison of two expressions, such as `a > b`.
+    Compare(Box<ScalarExpr>, SimplePredicateOp, Box<ScalarExpr>),
+    /// Result of the first branch whose condition holds, else the else expression, else NULL.
+    Case(Vec<CaseBranch>, Option<Box<ScalarExpr>>),
+    /// Value of an expression converted to a type, `CAST(expr AS type)`.
+    Cast(Box<ScalarExpr>, DataType),
+}
+
+/// Branch of a CASE expression: `WHEN left op right THEN result`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CaseBranch {
+    pub left: ScalarExpr,
+    pub op: SimplePredicateOp,
+    pub right: ScalarExpr,
+    pub result: ScalarExpr,
+}
+
+impl ScalarExpr {
+    /// Returns the columns the expression reads.
+    pub fn columns(&self) -> Vec<&FieldIdentifier> {
+        match self {
+            ScalarExpr::Column(ident) => vec![ident],
+            ScalarExpr::Literal(_) => Vec::new(),
+            ScalarExpr::Arithmetic(left, _, right) | ScalarExpr::Compare(left, _, right) => {
+                let mut columns = left.columns();
+                columns.extend(right.columns());
+                columns
+            }
+            ScalarExpr::Function(_, args) => args.iter().flat_map(|a| a.columns()).collect(),
+            ScalarExpr::Case(branches, otherwise) => branches
+                .iter()
+                .flat_map(|b| [&b.left, &b.right, &b.result])
+                .chain(otherwise.as_deref())
+                .flat_map(|e| e.columns())
+                .collect(),
+            ScalarExpr::Cast(expr, _) => expr.columns(),
+        }
+    }
+}
+
+/// Writes the expression with the qualified names of its columns, so that the same
+/// expression written twice in a query, such as in SELECT and GROUP BY, has the same text.
+impl fmt::Display for ScalarExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScalarExpr::Column(ident) => write!(f, "{}", ident.column()),
+            ScalarExpr::Literal(Field::StringField(s)) => write!(f, "'{}'", s),
+            ScalarExpr::Literal(field) => write!(f, "{}", field),
+            ScalarExpr::Arithmetic(left, op, right) => {
+                let operand = |e: &ScalarExpr| match e {
+                    ScalarExpr::Arithmetic(..) => format!("({})", e),
+                    _ => e.to_string(),
+                };
+                write!(f, "{} {} {}", operand(left), op, operand(right))
+            }
+            ScalarExpr::Function(function, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", function, args.join(", "))
+            }
+            ScalarExpr::Compare(left, op, right) => write!(f, "{} {} {}", left, op, right),
+            ScalarExpr::Case(branches, otherwise) => {
+                write!(f, "CASE")?;
+                for b in branches {
+                    write!(f, " WHEN {} {} {} THEN {}", b.left, b.op, b.right, b.result)?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, " ELSE {}", otherwise)?;
+                }
+                write!(f, " END")
+            }
+            ScalarExpr::Cast(expr, dtype) => write!(f, "CAST({} AS {})", expr, dtype),
+        }
+    }
+}
+
 /// Represents a field identifier.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FieldIdentifier {
@@ -225,6 +578,8 @@ pub struct FieldIdentifier {
     alias: Option<String>,
     /// An aggregate operation performed on column.
     op: Option<AggOp>,
+    /// Expression computed in place of the column, whose text is the column name.
+    expr: Option<Box<ScalarExpr>>,
 }
 
 impl FieldIdentifier {
@@ -240,9 +595,26 @@ impl FieldIdentifier {
             column: column.to_string(),
             alias: None,
             op: None,
+            expr: None,
         }
     }
 
+    /// Creates a field identifier for a computed expression. Its column is the text of the
+    /// expression and its table is the table of the first column the expression reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Expression computed for the field.
+    pub fn new_expr(expr: ScalarExpr) -> Self {
+        let table = expr
+            .columns()
+            .first()
+            .map_or_else(String::new, |c| c.table().to_string());
+        let mut id = Self::new(&table, &expr.to_string());
+        id.expr = Some(Box::new(expr));
+        id
+    }
+
     /// Creates a new field identifier with alias.
     ///
     /// # Arguments
@@ -276,6 +648,11 @@ impl FieldIdentifier {
         self.op
     }
 
+    /// Returns the expression computed for the field, if it is not a column.
+    pub fn expr(&self) -> Option<&ScalarExpr> {
+        self.expr.as_deref()
+    }
+
     /// Set an alias for the field identifier.
     ///
     /// # Argument
@@ -301,3 +678,13 @@ impl FieldIdentifier {
         self.op = Some(op);
     }
 }
+
+/// Writes the column, or the aggregate of the column, without the alias.
+impl fmt::Display for FieldIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.op {
+            Some(op) => write!(f, "{}({})", op, self.column),
+            None => write!(f, "{}", self.column),
+        }
+    }
+}
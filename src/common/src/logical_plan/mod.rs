@@ -24,9 +24,111 @@ pub enum LogicalOp {
     Aggregate(AggregateNode),
     Join(JoinNode),
     Filter(FilterNode),
+    Sort(SortNode),
+    Limit(LimitNode),
     ReadDeltas(ReadDeltasNode),
     WriteDeltas(WriteDeltasNode),
     Update(UpdateNode),
+    Insert(InsertNode),
+    Delete(DeleteNode),
+}
+
+/// Writes the operator as a line of EXPLAIN: its name and its parameters.
+impl fmt::Display for LogicalOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |fields: &[FieldIdentifier]| {
+            let names: Vec<String> = fields
+                .iter()
+                .map(|field| match field.alias() {
+                    Some(alias) if alias != field.column() => format!("{} AS {}", field, alias),
+                    _ => field.to_string(),
+                })
+                .collect();
+            names.join(", ")
+        };
+        let (name, params) = match self {
+            LogicalOp::Scan(ScanNode { alias, .. }) => ("Scan", vec![("table", alias.clone())]),
+            LogicalOp::Project(ProjectNode { identifiers }) => {
+                let columns = match identifiers {
+                    ProjectIdentifiers::Wildcard => String::from("*"),
+                    ProjectIdentifiers::List(fields) => list(fields),
+                };
+                ("Project", vec![("columns", columns)])
+            }
+            LogicalOp::Aggregate(AggregateNode { fields, group_by }) => {
+                let mut params = Vec::new();
+                if !group_by.is_empty() {
+                    params.push(("group by", list(group_by)));
+                }
+                let aggregates: Vec<FieldIdentifier> = fields
+                    .iter()
+                    .filter(|f| f.agg_op().is_some())
+                    .cloned()
+                    .collect();
+                params.push(("aggregates", list(&aggregates)));
+                ("Aggregate", params)
+            }
+            LogicalOp::Join(JoinNode {
+                left, op, right, ..
+            }) => ("Join", vec![("on", format!("{} {} {}", left, op, right))]),
+            LogicalOp::Filter(FilterNode { predicate, .. }) => {
+                ("Filter", vec![("predicate", predicate.to_string())])
+            }
+            LogicalOp::Sort(SortNode { fields }) => {
+                let keys: Vec<String> = fields
+                    .iter()
+                    .map(|(field, ascending)| match ascending {
+                        true => field.to_string(),
+                        false => format!("{} DESC", field),
+                    })
+                    .collect();
+                ("Sort", vec![("keys", keys.join(", "))])
+            }
+            LogicalOp::Limit(LimitNode { limit, offset }) => {
+                let mut params = Vec::new();
+                if let Some(limit) = limit {
+                    params.push(("limit", limit.to_string()));
+                }
+                if *offset > 0 {
+                    params.push(("offset", offset.to_string()));
+                }
+                ("Limit", params)
+            }
+            LogicalOp::ReadDeltas(ReadDeltasNode { object_id }) => {
+                ("ReadDeltas", vec![("container", object_id.to_string())])
+            }
+            LogicalOp::WriteDeltas(WriteDeltasNode { object_id }) => {
+                ("WriteDeltas", vec![("container", object_id.to_string())])
+            }
+            LogicalOp::Update(UpdateNode {
+                alias, assignments, ..
+            }) => {
+                let set: Vec<String> = assignments
+                    .iter()
+                    .map(|(field, value)| format!("{} = {}", field, value))
+                    .collect();
+                (
+                    "Update",
+                    vec![("table", alias.clone()), ("set", set.join(", "))],
+                )
+            }
+            LogicalOp::Insert(InsertNode { alias, .. }) => {
+                ("Insert", vec![("table", alias.clone())])
+            }
+            LogicalOp::Delete(DeleteNode { alias, .. }) => {
+                ("Delete", vec![("table", alias.clone())])
+            }
+        };
+        write!(f, "{}", name)?;
+        if !params.is_empty() {
+            let params: Vec<String> = params
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            write!(f, " ({})", params.join(", "))?;
+        }
+        Ok(())
+    }
 }
 
 /// Graph where nodes represent logical operations and edges represent the flow of data.
@@ -82,6 +184,18 @@ impl LogicalPlan {
         self.dataflow.add_edge(source, target);
     }
 
+    /// Makes the node consuming the data of target consume the data of new_target instead.
+    /// Returns false if source has no edge to target.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Data consumer.
+    /// * `target` - Data producer source currently consumes.
+    /// * `new_target` - Data producer to consume instead.
+    pub fn retarget_edge(&mut self, source: OpIndex, target: OpIndex, new_target: OpIndex) -> bool {
+        self.dataflow.retarget_edge(source, target, new_target)
+    }
+
     /// Returns an iterator over all nodes that 'from' has an edge to.
     ///
     /// # Arguments
@@ -109,6 +223,64 @@ impl LogicalPlan {
         self.dataflow.node_data(index)
     }
 
+    /// Returns the LogicalOperation associated with a node for modification.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the node to get the logical operation of.
+    pub fn get_operator_mut(&mut self, index: OpIndex) -> Option<&mut LogicalOp> {
+        self.dataflow.node_data_mut(index)
+    }
+
+    /// Removes the nodes that are not reachable from the root, such as those a rewrite of
+    /// the plan bypassed, and returns the number of nodes removed.
+    ///
+    /// The remaining nodes keep their order and the order of their edges, so their indices
+    /// change. The subqueries of filters are updated to the new indices of their roots.
+    pub fn remove_unreachable(&mut self) -> usize {
+        let root = match self.root {
+            Some(root) => root,
+            None => return 0,
+        };
+        let mut reachable = vec![false; self.node_count()];
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            if !reachable[node] {
+                reachable[node] = true;
+                stack.extend(self.edges(node));
+            }
+        }
+        let removed = reachable.iter().filter(|r| !**r).count();
+        if removed == 0 {
+            return 0;
+        }
+
+        let mut indices = HashMap::new();
+        let mut dataflow = CrustyGraph::new();
+        for (i, node) in self.dataflow.node_references() {
+            if reachable[i] {
+                indices.insert(i, dataflow.add_node(node.data().clone()));
+            }
+        }
+        for edge in self.dataflow.edge_references() {
+            if let (Some(source), Some(target)) =
+                (indices.get(&edge.source()), indices.get(&edge.target()))
+            {
+                dataflow.add_edge(*source, *target);
+            }
+        }
+        for index in indices.values() {
+            if let Some(LogicalOp::Filter(filter)) = dataflow.node_data_mut(*index) {
+                for subquery in filter.predicate.subqueries_mut() {
+                    subquery.root = indices[&subquery.root];
+                }
+            }
+        }
+        self.dataflow = dataflow;
+        self.root = Some(indices[&root]);
+        removed
+    }
+
     /// Iterator over all nodes in the graph.
     ///
     /// Iterates over NodeIndex's and their corresponding Node structs. Returned iterator shares lifetime of self.
@@ -136,6 +308,10 @@ impl LogicalPlan {
     }
 
     /// Serializes the Logical Plan as json.
+    ///
+    /// Nodes are keyed by their indices, and the targets of each source are listed in the
+    /// order their edges were added. Keys are sorted, so the same plan always has the same
+    /// json.
     pub fn to_json(&self) -> serde_json::Value {
         let mut node_map = HashMap::new();
         let mut edge_map = HashMap::new();
@@ -163,6 +339,9 @@ impl LogicalPlan {
     }
 
     /// De-Serializes a json representation of the Logical Plan created in to_json
+    ///
+    /// The plan has the indices and the order of children of the serialized plan. Subqueries
+    /// of filters refer to their roots by their indices in the json.
     pub fn from_json(json: &str) -> Result<Self, CrustyError> {
         let malformed_err =
             CrustyError::CrustyError(String::from("Malformatted logical plan json"));
@@ -180,23 +359,49 @@ impl LogicalPlan {
             serde_json::from_value(v["root"].clone()),
             malformed_err.clone(),
         )?;
+        // Nodes are added in the order of their indices and edges in the order of their
+        // sources, so that a plan read from its own json has the same indices and children.
+        let index = |i: &str| i.parse::<OpIndex>().map_err(|_| malformed_err.clone());
+        let mut nodes = nodes
+            .into_iter()
+            .map(|(i, op)| Ok((index(&i)?, op)))
+            .collect::<Result<Vec<_>, CrustyError>>()?;
+        nodes.sort_by_key(|(i, _)| *i);
         let mut graph_map = HashMap::new();
         let mut plan = LogicalPlan::new();
-        for (i, val) in nodes.iter() {
-            let node = plan.add_node(val.clone());
+        for (i, val) in nodes {
+            let node = plan.add_node(val);
             graph_map.insert(i, node);
         }
+        for node in graph_map.values() {
+            if let Some(LogicalOp::Filter(filter)) = plan.dataflow.node_data_mut(*node) {
+                for subquery in filter.predicate.subqueries_mut() {
+                    subquery.root = *graph_map
+                        .get(&subquery.root)
+                        .ok_or_else(|| malformed_err.clone())?;
+                }
+            }
+        }
 
         if let Some(i) = root {
-            let root_node = graph_map.get(&i).ok_or_else(|| malformed_err.clone())?;
+            let root_node = graph_map
+                .get(&index(&i)?)
+                .ok_or_else(|| malformed_err.clone())?;
             plan.root = Some(*root_node);
         }
 
-        for (source, targets) in edges.iter() {
-            let source_node = graph_map.get(source).ok_or_else(|| malformed_err.clone())?;
+        let mut edges = edges
+            .into_iter()
+            .map(|(source, targets)| Ok((index(&source)?, targets)))
+            .collect::<Result<Vec<_>, CrustyError>>()?;
+        edges.sort_by_key(|(source, _)| *source);
+        for (source, targets) in edges {
+            let source_node = graph_map
+                .get(&source)
+                .ok_or_else(|| malformed_err.clone())?;
             for target in targets {
                 let target_node = graph_map
-                    .get(&target.to_string())
+                    .get(&index(&target)?)
                     .ok_or_else(|| malformed_err.clone())?;
                 plan.add_edge(*source_node, *target_node);
             }
@@ -217,6 +422,31 @@ impl LogicalPlan {
         Ok(plan)
     }
 
+    /// Returns the plan as an indented tree, one operator per line, starting at the root.
+    /// The inputs of an operator follow it, one level deeper, and the plans of a filter's
+    /// subqueries follow its input.
+    pub fn explain(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(root) = self.root {
+            self.explain_node(root, 0, &mut lines);
+        }
+        lines.join("\n")
+    }
+
+    fn explain_node(&self, node: OpIndex, depth: usize, lines: &mut Vec<String>) {
+        let op = match self.get_operator(node) {
+            Some(op) => op,
+            None => return,
+        };
+        match depth {
+            0 => lines.push(op.to_string()),
+            _ => lines.push(format!("{:width$}-> {}", "", op, width = 2 * (depth - 1))),
+        }
+        for child in self.edges(node) {
+            self.explain_node(child, depth + 1, lines);
+        }
+    }
+
     /// Checks if the logical plan has a cycle
     /// if this has a cycle, the query could run forever
     pub fn cycle_free(&self) -> bool {
@@ -246,6 +476,7 @@ mod test {
     use super::*;
 
     use crate::ids::ContainerId;
+    use crate::Field;
 
     #[test]
     fn test_new() {
@@ -378,6 +609,77 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_json_keeps_indices() {
+        // More than ten nodes, so that their indices do not sort as strings, and a filter
+        // whose subquery is its second child.
+        let mut lp = LogicalPlan::new();
+        let project = |lp: &mut LogicalPlan| {
+            lp.add_node(LogicalOp::Project(ProjectNode {
+                identifiers: ProjectIdentifiers::Wildcard,
+            }))
+        };
+        let root = project(&mut lp);
+        let mut node = root;
+        for _ in 0..9 {
+            let child = project(&mut lp);
+            lp.add_edge(node, child);
+            node = child;
+        }
+        let u = lp.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("u"),
+            container_id: 1,
+        }));
+        let t = lp.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("t"),
+            container_id: 0,
+        }));
+        let filter = lp.add_node(LogicalOp::Filter(FilterNode {
+            table: String::from("t"),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new("t", "t.a")),
+                op: SimplePredicateOp::Equals,
+                right: PredExpr::Subquery(SubqueryExpr {
+                    root: u,
+                    parameters: Vec::new(),
+                }),
+            }),
+        }));
+        lp.add_edge(node, filter);
+        lp.add_edge(filter, u);
+        lp.add_edge(filter, t);
+
+        let json = lp.to_json();
+        let new_lp = LogicalPlan::from_json(&json.to_string()).unwrap();
+        assert_eq!(json, new_lp.to_json());
+        assert_eq!(lp.explain(), new_lp.explain());
+        assert_eq!(Some(root), new_lp.root());
+        assert_eq!(vec![t, u], new_lp.edges(filter).collect::<Vec<_>>());
+        match new_lp.get_operator(filter) {
+            Some(LogicalOp::Filter(f)) => assert_eq!(u, f.predicate.subqueries()[0].root),
+            op => panic!("Expected a filter, got {:?}", op),
+        }
+
+        // Subqueries of plans written by hand refer to nodes by the indices of the json.
+        let json = json
+            .to_string()
+            .replace("\"10\"", "\"20\"")
+            .replace("\"root\":10", "\"root\":20");
+        let new_lp = LogicalPlan::from_json(&json).unwrap();
+        assert_eq!(lp.explain(), new_lp.explain());
+        let filter = new_lp.edges(9).next().unwrap();
+        match new_lp.get_operator(filter) {
+            Some(LogicalOp::Filter(f)) => {
+                let root = f.predicate.subqueries()[0].root;
+                assert!(
+                    matches!(new_lp.get_operator(root), Some(LogicalOp::Scan(s)) if s.alias == "u")
+                );
+            }
+            op => panic!("Expected a filter, got {:?}", op),
+        }
+        assert!(LogicalPlan::from_json(&json.replace("\"root\":20", "\"root\":10")).is_err());
+    }
+
     #[test]
     fn test_cycle_free() {
         let mut lp = LogicalPlan::new();
@@ -417,4 +719,114 @@ mod test {
         lp.add_edge(scan, project);
         assert!(lp.all_reachable_from_root().unwrap());
     }
+
+    #[test]
+    fn test_remove_unreachable() {
+        let mut lp = LogicalPlan::new();
+        let scan = |alias: &str| {
+            LogicalOp::Scan(ScanNode {
+                alias: String::from(alias),
+                container_id: 0,
+            })
+        };
+        let bypassed = lp.add_node(LogicalOp::Project(ProjectNode {
+            identifiers: ProjectIdentifiers::Wildcard,
+        }));
+        let t = lp.add_node(scan("t"));
+        let u = lp.add_node(scan("u"));
+        let filter = lp.add_node(LogicalOp::Filter(FilterNode {
+            table: String::from("t"),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new("t", "t.a")),
+                op: SimplePredicateOp::Equals,
+                right: PredExpr::Subquery(SubqueryExpr {
+                    root: u,
+                    parameters: Vec::new(),
+                }),
+            }),
+        }));
+        lp.add_edge(filter, u);
+        lp.add_edge(filter, bypassed);
+        lp.add_edge(bypassed, t);
+        assert!(lp.retarget_edge(filter, bypassed, t));
+        assert_eq!(lp.root(), Some(filter));
+
+        assert_eq!(1, lp.remove_unreachable());
+        assert_eq!(3, lp.node_count());
+        assert_eq!(2, lp.edge_count());
+        assert!(lp.all_reachable_from_root().unwrap());
+        let root = lp.root().unwrap();
+        let children: Vec<OpIndex> = lp.edges(root).collect();
+        let aliases: Vec<&str> = children
+            .iter()
+            .map(|c| match lp.get_operator(*c) {
+                Some(LogicalOp::Scan(s)) => s.alias.as_str(),
+                op => panic!("Expected a scan, got {:?}", op),
+            })
+            .collect();
+        assert_eq!(vec!["t", "u"], aliases);
+        match lp.get_operator(root) {
+            Some(LogicalOp::Filter(filter)) => {
+                assert_eq!(children[1], filter.predicate.subqueries()[0].root)
+            }
+            op => panic!("Expected a filter, got {:?}", op),
+        }
+        assert_eq!(0, lp.remove_unreachable());
+    }
+
+    #[test]
+    fn test_explain() {
+        let mut lp = LogicalPlan::new();
+        let limit = lp.add_node(LogicalOp::Limit(LimitNode {
+            limit: Some(3),
+            offset: 1,
+        }));
+        let mut x = FieldIdentifier::new("t", "t.a");
+        x.set_alias(String::from("x"));
+        let project = lp.add_node(LogicalOp::Project(ProjectNode {
+            identifiers: ProjectIdentifiers::List(vec![x, FieldIdentifier::new("t", "t.b")]),
+        }));
+        let sort = lp.add_node(LogicalOp::Sort(SortNode {
+            fields: vec![(FieldIdentifier::new("t", "t.b"), false)],
+        }));
+        let simple = |column: &str, op, right| SimplePredicate {
+            left: PredExpr::Ident(FieldIdentifier::new("t", column)),
+            op,
+            right: PredExpr::Literal(right),
+        };
+        let filter = lp.add_node(LogicalOp::Filter(FilterNode {
+            table: String::from("t"),
+            predicate: Predicate::NestedPredicate(NestedPredicate {
+                op: CompoundPredicateOp::Or,
+                predicates: vec![
+                    Predicate::SimplePredicate(simple(
+                        "t.c",
+                        SimplePredicateOp::Like,
+                        Field::StringField(String::from("a%")),
+                    )),
+                    Predicate::CompoundPredicate(CompoundPredicate {
+                        op: CompoundPredicateOp::And,
+                        simple_predicates: vec![
+                            simple("t.a", SimplePredicateOp::GreaterThan, Field::IntField(1)),
+                            simple("t.b", SimplePredicateOp::NotEq, Field::IntField(2)),
+                        ],
+                    }),
+                ],
+            }),
+        }));
+        let scan = lp.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("t"),
+            container_id: 0,
+        }));
+        lp.add_edge(limit, project);
+        lp.add_edge(project, sort);
+        lp.add_edge(sort, filter);
+        lp.add_edge(filter, scan);
+        let expected = "Limit (limit: 3, offset: 1)\n\
+                        -> Project (columns: t.a AS x, t.b)\n  \
+                        -> Sort (keys: t.b DESC)\n    \
+                        -> Filter (predicate: t.c LIKE 'a%' OR (t.a > 1 AND t.b != 2))\n      \
+                        -> Scan (table: t)";
+        assert_eq!(expected, lp.explain());
+    }
 }
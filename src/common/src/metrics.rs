@@ -0,0 +1,157 @@
+//! Runtime metrics of a storage manager and of the operators run against it.
+//!
+//! Heap files count their page reads, writes and page faults in `FileMetrics`, and the
+//! operators of executed plans record the rows they returned and the time spent producing
+//! them in the `MetricsRegistry` of the storage manager. `StorageTrait::get_metrics` returns
+//! both. The counting is compiled in with the `profile` feature of the crates that record;
+//! without it the metrics stay empty.
+
+use crate::ids::ContainerId;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Page counters of a file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileCounters {
+    /// Pages read, from the file or from memory.
+    pub reads: u64,
+    /// Pages written to the file.
+    pub writes: u64,
+    /// Reads that had to go to the file.
+    pub page_faults: u64,
+}
+
+/// Counters of a file, updated by the file as it is read and written.
+#[derive(Debug, Default)]
+pub struct FileMetrics {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    page_faults: AtomicU64,
+}
+
+impl FileMetrics {
+    /// Counts a page read.
+    ///
+    /// # Arguments
+    ///
+    /// * `fault` - Whether the page was read from the file rather than from memory.
+    pub fn record_read(&self, fault: bool) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        if fault {
+            self.page_faults.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a page write.
+    pub fn record_write(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the counters so far.
+    pub fn counters(&self) -> FileCounters {
+        FileCounters {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            page_faults: self.page_faults.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Totals of the runs of an operator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OperatorMetrics {
+    /// Number of times the operator was closed after running.
+    pub runs: u64,
+    /// Rows the operator returned.
+    pub rows: u64,
+    /// Time spent producing the rows.
+    pub time: Duration,
+}
+
+/// Metrics of the operators run against a storage manager, by operator name.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    operators: Mutex<HashMap<String, OperatorMetrics>>,
+}
+
+impl MetricsRegistry {
+    /// Adds a run of an operator to its totals.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the operator, as in its plan description.
+    /// * `rows` - Rows returned during the run.
+    /// * `time` - Time spent producing the rows.
+    pub fn record_operator(&self, name: &str, rows: u64, time: Duration) {
+        let mut operators = self.operators.lock().unwrap();
+        let metrics = operators.entry(name.to_string()).or_default();
+        metrics.runs += 1;
+        metrics.rows += rows;
+        metrics.time += time;
+    }
+
+    /// Returns the metrics of the operators with the counters of the files of a storage
+    /// manager.
+    ///
+    /// # Arguments
+    ///
+    /// * `files` - Counters of the files, by container.
+    pub fn snapshot(&self, files: HashMap<ContainerId, FileCounters>) -> Metrics {
+        Metrics {
+            files,
+            operators: self.operators.lock().unwrap().clone(),
+        }
+    }
+
+    /// Forgets the metrics of the operators.
+    pub fn reset(&self) {
+        self.operators.lock().unwrap().clear();
+    }
+}
+
+/// Metrics of a storage manager, returned by `StorageTrait::get_metrics`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Counters of the files of the containers.
+    pub files: HashMap<ContainerId, FileCounters>,
+    /// Totals of the operators, by operator name.
+    pub operators: HashMap<String, OperatorMetrics>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let file = FileMetrics::default();
+        file.record_read(true);
+        file.record_read(false);
+        file.record_write();
+        let registry = MetricsRegistry::default();
+        registry.record_operator("Filter", 3, Duration::from_millis(2));
+        registry.record_operator("Filter", 1, Duration::from_millis(1));
+
+        let metrics = registry.snapshot(HashMap::from([(7, file.counters())]));
+        assert_eq!(
+            FileCounters {
+                reads: 2,
+                writes: 1,
+                page_faults: 1
+            },
+            metrics.files[&7]
+        );
+        assert_eq!(
+            OperatorMetrics {
+                runs: 2,
+                rows: 4,
+                time: Duration::from_millis(3)
+            },
+            metrics.operators["Filter"]
+        );
+        registry.reset();
+        assert!(registry.snapshot(HashMap::new()).operators.is_empty());
+    }
+}
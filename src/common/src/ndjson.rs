@@ -0,0 +1,247 @@
+//! Reading and writing records as newline-delimited JSON.
+//!
+//! Each line of a file holds one record as a JSON object keyed by column name. Records are
+//! read and written one line at a time, so a file or container is never held in memory.
+//! When importing, the values are coerced to the types of the columns: numbers and strings
+//! such as `"12"` or `"2024-01-31"` become the fields of numeric and date columns, and a
+//! missing column or a JSON `null` is a null field. Dates, timestamps and decimals are
+//! exported as strings so that they are imported back without losing precision.
+
+use crate::{CrustyError, DataType, Decimal, Field, Float, TableSchema, Tuple};
+use serde_json::{Map, Number, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// What an import does with a line that cannot be read as a record of the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowErrorPolicy {
+    /// Leave the line out.
+    Skip,
+    /// Stop the import with the error, keeping the records imported before it.
+    Abort,
+    /// Leave the line out and log a warning with the error.
+    Log,
+}
+
+/// Reads the records of a newline-delimited JSON file and hands them to `insert`. Returns
+/// the number of records inserted. Blank lines are ignored. Errors of `insert` always stop
+/// the import.
+///
+/// # Arguments
+///
+/// * `schema` - Schema of the table the records are imported into.
+/// * `path` - Path of the file.
+/// * `on_error` - What to do with lines that are not records of the table.
+/// * `insert` - Stores a record.
+pub fn import_json(
+    schema: &TableSchema,
+    path: &Path,
+    on_error: RowErrorPolicy,
+    mut insert: impl FnMut(Tuple) -> Result<(), CrustyError>,
+) -> Result<usize, CrustyError> {
+    let file = File::open(path).map_err(|e| CrustyError::io_with_path(path, e))?;
+    let mut imported = 0;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| CrustyError::io_with_path(path, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_record(&line, schema) {
+            Ok(tuple) => {
+                insert(tuple)?;
+                imported += 1;
+            }
+            Err(e) => {
+                let e = CrustyError::ValidationError(format!("Line {}: {}", i + 1, e));
+                match on_error {
+                    RowErrorPolicy::Skip => {}
+                    RowErrorPolicy::Abort => return Err(e),
+                    RowErrorPolicy::Log => warn!("Skipping record of {:?}: {}", path, e),
+                }
+            }
+        }
+    }
+    Ok(imported)
+}
+
+/// Writes records to a newline-delimited JSON file, replacing the file if it exists.
+/// Returns the number of records written.
+///
+/// # Arguments
+///
+/// * `values` - Serialized tuples of the records.
+/// * `schema` - Schema of the records, naming the keys of the objects.
+/// * `path` - Path of the file.
+pub fn export_json(
+    values: impl Iterator<Item = Vec<u8>>,
+    schema: &TableSchema,
+    path: &Path,
+) -> Result<usize, CrustyError> {
+    let io_error = |e| CrustyError::io_with_path(path, e);
+    let mut writer = BufWriter::new(File::create(path).map_err(io_error)?);
+    let mut written = 0;
+    for bytes in values {
+        let tuple = Tuple::from_bytes(&bytes);
+        let record: Map<String, Value> = schema
+            .attributes()
+            .zip(tuple.field_vals())
+            .map(|(attr, field)| (attr.name().to_string(), to_json(field)))
+            .collect();
+        serde_json::to_writer(&mut writer, &record).map_err(|e| CrustyError::IoWithPath {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        writer.write_all(b"\n").map_err(io_error)?;
+        written += 1;
+    }
+    writer.flush().map_err(io_error)?;
+    Ok(written)
+}
+
+/// Returns the JSON value of a field.
+fn to_json(field: &Field) -> Value {
+    match field {
+        Field::IntField(i) => Value::from(*i),
+        Field::FloatField(Float(f)) => Number::from_f64(*f).map_or(Value::Null, Value::Number),
+        Field::StringField(s) => Value::from(s.as_str()),
+        Field::BoolField(b) => Value::from(*b),
+        Field::DecimalField(_) | Field::DateField(_) | Field::TimestampField(_) => {
+            Value::from(field.to_string())
+        }
+        Field::Null => Value::Null,
+    }
+}
+
+/// Reads a line as a record of the table, coercing its values to the types of the columns.
+fn parse_record(line: &str, schema: &TableSchema) -> Result<Tuple, String> {
+    let mut record = match serde_json::from_str(line) {
+        Ok(Value::Object(record)) => record,
+        Ok(_) => return Err(String::from("not a JSON object")),
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut fields = Vec::with_capacity(schema.size());
+    for attr in schema.attributes() {
+        let value = record.remove(attr.name()).unwrap_or(Value::Null);
+        let field = from_json(value, attr.dtype())
+            .ok_or_else(|| format!("value of {} is not a {:?}", attr.name(), attr.dtype()))?;
+        if field == Field::Null && !attr.is_nullable() {
+            return Err(format!("null value in NOT NULL column {}", attr.name()));
+        }
+        fields.push(field);
+    }
+    if let Some(key) = record.keys().next() {
+        return Err(format!("unknown column {}", key));
+    }
+    Ok(Tuple::new(fields))
+}
+
+/// Returns the field of a column of the type for a JSON value, or None if the value cannot
+/// be coerced to the type.
+fn from_json(value: Value, dtype: &DataType) -> Option<Field> {
+    let field = match (value, dtype) {
+        (Value::Null, _) => Field::Null,
+        (Value::Bool(b), DataType::String) => Field::StringField(b.to_string()),
+        (Value::Bool(b), _) => Field::BoolField(b),
+        (Value::Number(n), DataType::String) => Field::StringField(n.to_string()),
+        (Value::Number(n), DataType::Float) => Field::FloatField(Float(n.as_f64()?)),
+        (Value::Number(n), DataType::Decimal(..)) => {
+            Field::DecimalField(Decimal::parse(&n.to_string())?)
+        }
+        (Value::Number(n), _) => Field::IntField(i32::try_from(n.as_i64()?).ok()?),
+        (Value::String(s), DataType::Int) => Field::IntField(s.trim().parse().ok()?),
+        (Value::String(s), DataType::Float) => Field::FloatField(Float(s.trim().parse().ok()?)),
+        (Value::String(s), DataType::Decimal(..)) => Field::DecimalField(Decimal::parse(s.trim())?),
+        (Value::String(s), _) => Field::StringField(s),
+        (Value::Array(_) | Value::Object(_), _) => return None,
+    };
+    let field = field.coerce(dtype);
+    field.has_type(dtype).then_some(field)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::gen_random_test_sm_dir;
+    use crate::{Attribute, Constraint};
+    use std::fs;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![
+            Attribute::new_pk(String::from("id"), DataType::Int),
+            Attribute::new(String::from("price"), DataType::Decimal(6, 2)),
+            Attribute::new(String::from("day"), DataType::Date),
+            Attribute::new_with_constraint(
+                String::from("ok"),
+                DataType::Boolean,
+                Constraint::NotNull,
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_coercion() {
+        let schema = schema();
+        let tuple = parse_record(
+            r#"{"id": "7", "price": 1.5, "day": "2024-01-31", "ok": "t"}"#,
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Field::IntField(7),
+                Field::DecimalField(Decimal::parse("1.50").unwrap()),
+                Field::DateField(crate::Date::parse("2024-01-31").unwrap()),
+                Field::BoolField(true),
+            ],
+            tuple.field_vals
+        );
+        let tuple = parse_record(r#"{"id": 8, "ok": false}"#, &schema).unwrap();
+        assert_eq!(Field::Null, tuple.field_vals[1]);
+
+        assert!(parse_record(r#"{"id": 9}"#, &schema).is_err());
+        assert!(parse_record(r#"{"id": 9.5, "ok": true}"#, &schema).is_err());
+        assert!(parse_record(r#"{"id": 9, "ok": true, "other": 1}"#, &schema).is_err());
+        assert!(parse_record("[9, true]", &schema).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_and_policies() {
+        let schema = schema();
+        let dir = gen_random_test_sm_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.json");
+        fs::write(
+            &path,
+            concat!(
+                "{\"id\": 1, \"price\": \"3.25\", \"day\": \"2024-02-01\", \"ok\": true}\n",
+                "not json\n",
+                "\n",
+                "{\"id\": 2, \"ok\": 0}\n",
+            ),
+        )
+        .unwrap();
+
+        let import = |policy| {
+            let mut tuples = Vec::new();
+            import_json(&schema, &path, policy, |t| {
+                tuples.push(t);
+                Ok(())
+            })
+            .map(|n| (n, tuples))
+        };
+        let (imported, tuples) = import(RowErrorPolicy::Skip).unwrap();
+        assert_eq!(2, imported);
+        assert_eq!(import(RowErrorPolicy::Log), Ok((2, tuples.clone())));
+        assert!(matches!(
+            import(RowErrorPolicy::Abort),
+            Err(CrustyError::ValidationError(e)) if e.starts_with("Line 2")
+        ));
+
+        let values = tuples.iter().map(|t| t.to_bytes());
+        assert_eq!(2, export_json(values, &schema, &path).unwrap());
+        let (_, exported) = import(RowErrorPolicy::Abort).unwrap();
+        assert_eq!(tuples, exported);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,589 @@
+//! Reading and writing records as Apache Parquet files, with the `parquet` feature.
+//!
+//! The columns of a table map to Parquet columns as follows, with nullable columns written
+//! as optional and the others as required:
+//!
+//! | DataType         | Parquet physical type | Parquet logical type |
+//! |------------------|-----------------------|----------------------|
+//! | Int              | INT32                 |                      |
+//! | String           | BYTE_ARRAY            | STRING               |
+//! | Float            | DOUBLE                |                      |
+//! | Decimal(p, s)    | INT64                 | DECIMAL(p, s)        |
+//! | Date             | INT32                 | DATE                 |
+//! | Timestamp        | INT64                 | TIMESTAMP(MICROS)    |
+//! | Boolean          | BOOLEAN               |                      |
+//!
+//! Imports also accept the other integer and floating point columns, decimals of any
+//! physical type, enums and JSON as strings, and millisecond timestamps. Integers that do not
+//! fit an Int are errors when they are read. Nested and repeated columns, binary columns,
+//! INT96 timestamps, times and intervals are not supported.
+
+use crate::{CrustyError, DataType, Date, Decimal, Field, Float, TableSchema, Timestamp, Tuple};
+use parquet::basic::{ConvertedType, LogicalType, Repetition, TimeUnit, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int32Type, Int64Type};
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::writer::SerializedFileWriter;
+use parquet::format::{MicroSeconds, MilliSeconds};
+use parquet::record::Field as ParquetField;
+use parquet::schema::types::Type;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Format of an exported Parquet file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParquetExportOptions {
+    /// Records per row group. A row group is buffered in memory before it is written.
+    pub row_group_size: usize,
+}
+
+impl Default for ParquetExportOptions {
+    fn default() -> Self {
+        ParquetExportOptions {
+            row_group_size: 64 * 1024,
+        }
+    }
+}
+
+impl ParquetExportOptions {
+    /// Writes row groups of a number of records other than the default.
+    pub fn with_row_group_size(mut self, row_group_size: usize) -> Self {
+        self.row_group_size = row_group_size.max(1);
+        self
+    }
+}
+
+/// Returns the type of the column of a table that holds the values of a Parquet column.
+/// Error naming the column and its Parquet type if the type is not supported.
+///
+/// # Arguments
+///
+/// * `column` - Type of a top-level column of a Parquet schema.
+pub fn data_type_of(column: &Type) -> Result<DataType, CrustyError> {
+    let unsupported = |what: String| {
+        CrustyError::ValidationError(format!(
+            "Column {} has unsupported Parquet type {}",
+            column.name(),
+            what
+        ))
+    };
+    if !column.is_primitive() {
+        return Err(unsupported(String::from("group")));
+    }
+    let info = column.get_basic_info();
+    if info.has_repetition() && info.repetition() == Repetition::REPEATED {
+        return Err(unsupported(String::from("repeated")));
+    }
+    let physical = column.get_physical_type();
+    let logical = info
+        .logical_type()
+        .or_else(|| legacy_logical_type(info.converted_type(), column));
+    let dtype = match (physical, &logical) {
+        (PhysicalType::BOOLEAN, None) => DataType::Boolean,
+        (PhysicalType::INT32 | PhysicalType::INT64, None) => DataType::Int,
+        (PhysicalType::INT32 | PhysicalType::INT64, Some(LogicalType::Integer { .. })) => {
+            DataType::Int
+        }
+        (PhysicalType::FLOAT | PhysicalType::DOUBLE, None) => DataType::Float,
+        (
+            PhysicalType::INT32
+            | PhysicalType::INT64
+            | PhysicalType::BYTE_ARRAY
+            | PhysicalType::FIXED_LEN_BYTE_ARRAY,
+            Some(LogicalType::Decimal { scale, precision }),
+        ) if (1..=18).contains(precision) && (0..=*precision).contains(scale) => {
+            DataType::Decimal(*precision as u32, *scale as u32)
+        }
+        (PhysicalType::INT32, Some(LogicalType::Date)) => DataType::Date,
+        (
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp {
+                unit: TimeUnit::MILLIS(_) | TimeUnit::MICROS(_),
+                ..
+            }),
+        ) => DataType::Timestamp,
+        (
+            PhysicalType::BYTE_ARRAY,
+            Some(LogicalType::String | LogicalType::Enum | LogicalType::Json),
+        ) => DataType::String,
+        (physical, Some(logical)) => {
+            return Err(unsupported(format!("{} ({:?})", physical, logical)))
+        }
+        (physical, None) => return Err(unsupported(physical.to_string())),
+    };
+    Ok(dtype)
+}
+
+/// Returns the logical type of a column written with a converted type only, as older
+/// writers do.
+fn legacy_logical_type(converted: ConvertedType, column: &Type) -> Option<LogicalType> {
+    let integer = |bit_width, is_signed| LogicalType::Integer {
+        bit_width,
+        is_signed,
+    };
+    let logical = match converted {
+        ConvertedType::UTF8 => LogicalType::String,
+        ConvertedType::ENUM => LogicalType::Enum,
+        ConvertedType::JSON => LogicalType::Json,
+        ConvertedType::DATE => LogicalType::Date,
+        ConvertedType::DECIMAL => LogicalType::Decimal {
+            scale: column.get_scale(),
+            precision: column.get_precision(),
+        },
+        ConvertedType::TIMESTAMP_MILLIS => LogicalType::Timestamp {
+            is_adjusted_to_u_t_c: true,
+            unit: TimeUnit::MILLIS(MilliSeconds {}),
+        },
+        ConvertedType::TIMESTAMP_MICROS => LogicalType::Timestamp {
+            is_adjusted_to_u_t_c: true,
+            unit: TimeUnit::MICROS(MicroSeconds {}),
+        },
+        ConvertedType::INT_8 => integer(8, true),
+        ConvertedType::INT_16 => integer(16, true),
+        ConvertedType::INT_32 => integer(32, true),
+        ConvertedType::INT_64 => integer(64, true),
+        ConvertedType::UINT_8 => integer(8, false),
+        ConvertedType::UINT_16 => integer(16, false),
+        ConvertedType::UINT_32 => integer(32, false),
+        ConvertedType::UINT_64 => integer(64, false),
+        ConvertedType::NONE => return None,
+        // Times, intervals and the nested types, which are not supported.
+        _ => LogicalType::Unknown,
+    };
+    Some(logical)
+}
+
+/// Returns the Parquet column of a column of a table.
+fn parquet_column(name: &str, dtype: &DataType, nullable: bool) -> Result<Type, ParquetError> {
+    let (physical, logical) = match dtype {
+        DataType::Int => (PhysicalType::INT32, None),
+        DataType::String => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        DataType::Float => (PhysicalType::DOUBLE, None),
+        DataType::Decimal(precision, scale) => (
+            PhysicalType::INT64,
+            Some(LogicalType::Decimal {
+                scale: *scale as i32,
+                precision: *precision as i32,
+            }),
+        ),
+        DataType::Date => (PhysicalType::INT32, Some(LogicalType::Date)),
+        DataType::Timestamp => (
+            PhysicalType::INT64,
+            Some(LogicalType::Timestamp {
+                is_adjusted_to_u_t_c: false,
+                unit: TimeUnit::MICROS(MicroSeconds {}),
+            }),
+        ),
+        DataType::Boolean => (PhysicalType::BOOLEAN, None),
+    };
+    let repetition = if nullable {
+        Repetition::OPTIONAL
+    } else {
+        Repetition::REQUIRED
+    };
+    let mut builder = Type::primitive_type_builder(name, physical)
+        .with_repetition(repetition)
+        .with_logical_type(logical);
+    if let DataType::Decimal(precision, scale) = dtype {
+        builder = builder
+            .with_precision(*precision as i32)
+            .with_scale(*scale as i32);
+    }
+    builder.build()
+}
+
+/// Reads the records of a Parquet file and hands them to `insert`. Returns the number of
+/// records inserted. The columns of the table are read from the columns of the file with the
+/// same names; a nullable column missing from the file is null. Stops at the first record
+/// that cannot be read.
+///
+/// # Arguments
+///
+/// * `schema` - Schema of the table the records are imported into.
+/// * `path` - Path of the file.
+/// * `insert` - Stores a record.
+pub fn import_parquet(
+    schema: &TableSchema,
+    path: &Path,
+    mut insert: impl FnMut(Tuple) -> Result<(), CrustyError>,
+) -> Result<usize, CrustyError> {
+    let parquet_error = |e: ParquetError| CrustyError::IoWithPath {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let file = File::open(path).map_err(|e| CrustyError::io_with_path(path, e))?;
+    let reader = SerializedFileReader::new(file).map_err(parquet_error)?;
+    let columns = reader.metadata().file_metadata().schema().get_fields();
+
+    // Index in the file of each column of the table, checked before reading any record.
+    let mut indexes = Vec::with_capacity(schema.size());
+    for attr in schema.attributes() {
+        match columns.iter().position(|c| c.name() == attr.name()) {
+            Some(i) => {
+                data_type_of(&columns[i])?;
+                indexes.push(Some(i));
+            }
+            None if attr.is_nullable() => indexes.push(None),
+            None => {
+                return Err(CrustyError::ValidationError(format!(
+                    "Column {} is not in {:?}",
+                    attr.name(),
+                    path
+                )))
+            }
+        }
+    }
+
+    let mut imported = 0;
+    for (row_number, row) in reader
+        .get_row_iter(None)
+        .map_err(parquet_error)?
+        .enumerate()
+    {
+        let mut values: Vec<ParquetField> = row
+            .map_err(parquet_error)?
+            .into_columns()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect();
+        let mut fields = Vec::with_capacity(schema.size());
+        for (attr, index) in schema.attributes().zip(&indexes) {
+            let value = match index {
+                Some(i) => std::mem::replace(&mut values[*i], ParquetField::Null),
+                None => ParquetField::Null,
+            };
+            let field = from_parquet(value, attr.dtype())
+                .filter(|f| *f != Field::Null || attr.is_nullable())
+                .ok_or_else(|| {
+                    CrustyError::ValidationError(format!(
+                        "Row {}: value of {} is not a {:?}",
+                        row_number + 1,
+                        attr.name(),
+                        attr.dtype()
+                    ))
+                })?;
+            fields.push(field);
+        }
+        insert(Tuple::new(fields))?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Returns the field of a column of the type for a Parquet value, or None if the value does
+/// not fit the type.
+fn from_parquet(value: ParquetField, dtype: &DataType) -> Option<Field> {
+    let field = match value {
+        ParquetField::Null => Field::Null,
+        ParquetField::Bool(b) => Field::BoolField(b),
+        ParquetField::Byte(i) => Field::IntField(i.into()),
+        ParquetField::Short(i) => Field::IntField(i.into()),
+        ParquetField::Int(i) => Field::IntField(i),
+        ParquetField::Long(i) => Field::IntField(i32::try_from(i).ok()?),
+        ParquetField::UByte(i) => Field::IntField(i.into()),
+        ParquetField::UShort(i) => Field::IntField(i.into()),
+        ParquetField::UInt(i) => Field::IntField(i32::try_from(i).ok()?),
+        ParquetField::ULong(i) => Field::IntField(i32::try_from(i).ok()?),
+        ParquetField::Float(f) => Field::FloatField(Float(f.into())),
+        ParquetField::Double(f) => Field::FloatField(Float(f)),
+        ParquetField::Decimal(d) => {
+            // Big-endian two's complement of the unscaled value.
+            let bytes = d.data();
+            if bytes.len() > 8 {
+                return None;
+            }
+            let fill = if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+                0xFF
+            } else {
+                0
+            };
+            let mut be = [fill; 8];
+            be[8 - bytes.len()..].copy_from_slice(bytes);
+            Field::DecimalField(Decimal::new(i64::from_be_bytes(be), d.scale() as u32))
+        }
+        ParquetField::Str(s) => Field::StringField(s),
+        ParquetField::Date(days) => Field::DateField(Date(days)),
+        ParquetField::TimestampMillis(ms) => {
+            Field::TimestampField(Timestamp(ms.checked_mul(1000)?))
+        }
+        ParquetField::TimestampMicros(us) => Field::TimestampField(Timestamp(us)),
+        _ => return None,
+    };
+    let field = field.coerce(dtype);
+    field.has_type(dtype).then_some(field)
+}
+
+/// Values and definition levels of a column of a row group.
+enum ColumnBuffer {
+    Bool(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+    Bytes(Vec<ByteArray>),
+}
+
+impl ColumnBuffer {
+    fn new(dtype: &DataType) -> Self {
+        match dtype {
+            DataType::Int | DataType::Date => ColumnBuffer::Int32(Vec::new()),
+            DataType::Decimal(..) | DataType::Timestamp => ColumnBuffer::Int64(Vec::new()),
+            DataType::Float => ColumnBuffer::Double(Vec::new()),
+            DataType::String => ColumnBuffer::Bytes(Vec::new()),
+            DataType::Boolean => ColumnBuffer::Bool(Vec::new()),
+        }
+    }
+
+    /// Adds a value that is not null. Returns false if the field is not of the type of the
+    /// column.
+    fn push(&mut self, field: &Field, dtype: &DataType) -> bool {
+        match (self, field) {
+            (ColumnBuffer::Int32(v), Field::IntField(i)) => v.push(*i),
+            (ColumnBuffer::Int32(v), Field::DateField(d)) => v.push(d.0),
+            (ColumnBuffer::Int64(v), Field::TimestampField(t)) => v.push(t.0),
+            (ColumnBuffer::Int64(v), Field::DecimalField(d)) => match dtype {
+                DataType::Decimal(precision, scale) => match d.rescale(*precision, *scale) {
+                    Some(d) => v.push(d.value),
+                    None => return false,
+                },
+                _ => return false,
+            },
+            (ColumnBuffer::Double(v), Field::FloatField(Float(f))) => v.push(*f),
+            (ColumnBuffer::Bytes(v), Field::StringField(s)) => v.push(ByteArray::from(s.as_str())),
+            (ColumnBuffer::Bool(v), Field::BoolField(b)) => v.push(*b),
+            _ => return false,
+        }
+        true
+    }
+
+    fn clear(&mut self) {
+        match self {
+            ColumnBuffer::Bool(v) => v.clear(),
+            ColumnBuffer::Int32(v) => v.clear(),
+            ColumnBuffer::Int64(v) => v.clear(),
+            ColumnBuffer::Double(v) => v.clear(),
+            ColumnBuffer::Bytes(v) => v.clear(),
+        }
+    }
+}
+
+/// Writes records to a Parquet file, replacing the file if it exists. Returns the number of
+/// records written.
+///
+/// # Arguments
+///
+/// * `values` - Serialized tuples of the records.
+/// * `schema` - Schema of the records.
+/// * `path` - Path of the file.
+/// * `options` - Format of the file.
+pub fn export_parquet(
+    values: impl Iterator<Item = Vec<u8>>,
+    schema: &TableSchema,
+    path: &Path,
+    options: &ParquetExportOptions,
+) -> Result<usize, CrustyError> {
+    let parquet_error = |e: ParquetError| CrustyError::IoWithPath {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    };
+    let columns = schema
+        .attributes()
+        .map(|attr| parquet_column(attr.name(), attr.dtype(), attr.is_nullable()).map(Arc::new))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| CrustyError::ValidationError(e.to_string()))?;
+    let parquet_schema = Type::group_type_builder("schema")
+        .with_fields(columns)
+        .build()
+        .map_err(|e| CrustyError::ValidationError(e.to_string()))?;
+    let file = File::create(path).map_err(|e| CrustyError::io_with_path(path, e))?;
+    let mut writer = SerializedFileWriter::new(
+        file,
+        Arc::new(parquet_schema),
+        Arc::new(WriterProperties::builder().build()),
+    )
+    .map_err(parquet_error)?;
+
+    let mut buffers: Vec<(ColumnBuffer, Vec<i16>)> = schema
+        .attributes()
+        .map(|attr| (ColumnBuffer::new(attr.dtype()), Vec::new()))
+        .collect();
+    let mut buffered = 0;
+    let mut written = 0;
+    for bytes in values {
+        let tuple = Tuple::from_bytes(&bytes);
+        for ((attr, field), (buffer, levels)) in schema
+            .attributes()
+            .zip(tuple.field_vals())
+            .zip(&mut buffers)
+        {
+            if *field == Field::Null && attr.is_nullable() {
+                levels.push(0);
+            } else if buffer.push(field, attr.dtype()) {
+                levels.push(1);
+            } else {
+                return Err(CrustyError::ValidationError(format!(
+                    "Value {} of {} is not a {:?}",
+                    field,
+                    attr.name(),
+                    attr.dtype()
+                )));
+            }
+        }
+        buffered += 1;
+        if buffered == options.row_group_size {
+            write_row_group(&mut writer, schema, &mut buffers).map_err(parquet_error)?;
+            written += buffered;
+            buffered = 0;
+        }
+    }
+    if buffered > 0 {
+        write_row_group(&mut writer, schema, &mut buffers).map_err(parquet_error)?;
+        written += buffered;
+    }
+    writer.close().map_err(parquet_error)?;
+    Ok(written)
+}
+
+/// Writes the buffered columns as a row group and clears them.
+fn write_row_group(
+    writer: &mut SerializedFileWriter<File>,
+    schema: &TableSchema,
+    buffers: &mut [(ColumnBuffer, Vec<i16>)],
+) -> Result<(), ParquetError> {
+    let mut row_group = writer.next_row_group()?;
+    for (attr, (buffer, levels)) in schema.attributes().zip(buffers.iter_mut()) {
+        let mut column = row_group
+            .next_column()?
+            .expect("a column writer for each column of the schema");
+        // Required columns have no definition levels.
+        let levels_arg = attr.is_nullable().then_some(levels.as_slice());
+        match buffer {
+            ColumnBuffer::Bool(v) => column.typed::<BoolType>().write_batch(v, levels_arg, None),
+            ColumnBuffer::Int32(v) => column.typed::<Int32Type>().write_batch(v, levels_arg, None),
+            ColumnBuffer::Int64(v) => column.typed::<Int64Type>().write_batch(v, levels_arg, None),
+            ColumnBuffer::Double(v) => column
+                .typed::<DoubleType>()
+                .write_batch(v, levels_arg, None),
+            ColumnBuffer::Bytes(v) => column
+                .typed::<ByteArrayType>()
+                .write_batch(v, levels_arg, None),
+        }?;
+        column.close()?;
+        buffer.clear();
+        levels.clear();
+    }
+    row_group.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::gen_random_test_sm_dir;
+    use crate::{Attribute, Constraint};
+    use std::fs;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![
+            Attribute::new_pk(String::from("id"), DataType::Int),
+            Attribute::new(String::from("name"), DataType::String),
+            Attribute::new(String::from("score"), DataType::Float),
+            Attribute::new(String::from("price"), DataType::Decimal(8, 2)),
+            Attribute::new(String::from("day"), DataType::Date),
+            Attribute::new(String::from("at"), DataType::Timestamp),
+            Attribute::new_with_constraint(
+                String::from("ok"),
+                DataType::Boolean,
+                Constraint::NotNull,
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let schema = schema();
+        let tuples: Vec<Tuple> = (0..25)
+            .map(|i| {
+                let name = if i % 5 == 0 {
+                    Field::Null
+                } else {
+                    Field::StringField(format!("n{}", i))
+                };
+                Tuple::new(vec![
+                    Field::IntField(i),
+                    name,
+                    Field::FloatField(Float(i as f64 / 4.0)),
+                    Field::DecimalField(Decimal::new(-(i as i64) * 37, 2)),
+                    Field::DateField(Date(19000 + i)),
+                    Field::TimestampField(Timestamp(1_700_000_000_000_000 + i as i64)),
+                    Field::BoolField(i % 2 == 0),
+                ])
+            })
+            .collect();
+        let dir = gen_random_test_sm_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("t.parquet");
+
+        let options = ParquetExportOptions::default().with_row_group_size(10);
+        let values = tuples.iter().map(|t| t.to_bytes());
+        assert_eq!(
+            25,
+            export_parquet(values, &schema, &path, &options).unwrap()
+        );
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(3, reader.metadata().num_row_groups());
+
+        let mut imported = Vec::new();
+        let count = import_parquet(&schema, &path, |t| {
+            imported.push(t);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(25, count);
+        assert_eq!(tuples, imported);
+
+        // Nullable columns missing from the file are null, required ones are errors.
+        let narrow = TableSchema::new(vec![
+            Attribute::new(String::from("id"), DataType::Int),
+            Attribute::new(String::from("missing"), DataType::String),
+        ]);
+        import_parquet(&narrow, &path, |t| {
+            assert_eq!(Field::Null, t.field_vals[1]);
+            Ok(())
+        })
+        .unwrap();
+        let required = TableSchema::new(vec![Attribute::new_pk(
+            String::from("missing"),
+            DataType::Int,
+        )]);
+        assert!(import_parquet(&required, &path, |_| Ok(())).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_data_type_of() {
+        let column = |physical, logical| {
+            Type::primitive_type_builder("c", physical)
+                .with_logical_type(logical)
+                .build()
+                .unwrap()
+        };
+        assert_eq!(
+            DataType::Int,
+            data_type_of(&column(PhysicalType::INT64, None)).unwrap()
+        );
+        assert_eq!(
+            DataType::String,
+            data_type_of(&column(PhysicalType::BYTE_ARRAY, Some(LogicalType::Json))).unwrap()
+        );
+        let err = data_type_of(&column(PhysicalType::INT96, None)).unwrap_err();
+        assert_eq!(
+            CrustyError::ValidationError(String::from(
+                "Column c has unsupported Parquet type INT96"
+            )),
+            err
+        );
+        assert!(data_type_of(&column(PhysicalType::BYTE_ARRAY, None)).is_err());
+    }
+}
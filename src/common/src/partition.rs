@@ -0,0 +1,348 @@
+//! Partitioned tables.
+//!
+//! A table created `WITH (partition_by = 'hash(c)', partitions = n)` or
+//! `WITH (partition_by = 'range(c)', partition_bounds = 'b1, b2, ...')` stores its records in
+//! one container per partition instead of its own container, which stays empty. The value of
+//! the partition column of a record picks its partition: its hash, or the range of the bounds
+//! it falls in. Value ids of records refer to the partition containers, so records are read,
+//! deleted and updated in place like those of a row table.
+//!
+//! A scan under a filter comparing the partition column to literals only reads the
+//! partitions that may hold matching records (see `Partitioning::prune`): equality picks one
+//! hash partition, and comparisons skip the range partitions outside their bounds.
+
+use crate::bloom::fnv1a;
+use crate::ids::{ContainerId, TransactionId, ValueId};
+use crate::like;
+use crate::logical_plan::SimplePredicateOp;
+use crate::storage_trait::StorageTrait;
+use crate::{CrustyError, DataType, Field, Tuple};
+
+/// Seed of the hash of the values of hash partitioned columns.
+const PARTITION_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// How the records of a partitioned table are spread over its partitions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Partitioning {
+    /// Index of the partition column in the schema of the table.
+    pub column: usize,
+    /// How the values of the partition column map to partitions.
+    pub scheme: PartitionScheme,
+    /// Containers of the partitions, in partition order.
+    pub partitions: Vec<ContainerId>,
+}
+
+/// How the values of a partition column map to partitions.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PartitionScheme {
+    /// A value goes to the partition of its hash, modulo the number of partitions.
+    Hash,
+    /// Increasing bounds, one fewer than the partitions. Partition i holds the values from
+    /// bound i - 1, included, to bound i, excluded. The first partition holds the values
+    /// below the first bound and nulls, and the last the values from the last bound.
+    Range(Vec<Field>),
+}
+
+impl PartitionScheme {
+    /// Parses the bounds of range partitions, a comma separated list of increasing values
+    /// of the partition column. Strings may be quoted.
+    ///
+    /// # Arguments
+    ///
+    /// * `bounds` - The list of bounds.
+    /// * `dtype` - Type of the partition column.
+    pub fn parse_bounds(bounds: &str, dtype: &DataType) -> Result<Vec<Field>, CrustyError> {
+        let mut fields: Vec<Field> = Vec::new();
+        for bound in bounds.split(',') {
+            let bound = bound.trim();
+            let unquoted = bound
+                .strip_prefix('\'')
+                .and_then(|b| b.strip_suffix('\''))
+                .unwrap_or(bound);
+            let field = match Field::from_number(unquoted) {
+                Some(number) if unquoted == bound && *dtype != DataType::String => number,
+                _ => Field::StringField(unquoted.to_string()),
+            }
+            .coerce(dtype);
+            if field == Field::Null || !field.has_type(dtype) {
+                return Err(CrustyError::ValidationError(format!(
+                    "Partition bound {} is not a value of type {:?}",
+                    bound, dtype
+                )));
+            }
+            if fields.last().is_some_and(|last| *last >= field) {
+                return Err(CrustyError::ValidationError(String::from(
+                    "Partition bounds must be increasing",
+                )));
+            }
+            fields.push(field);
+        }
+        Ok(fields)
+    }
+}
+
+impl Partitioning {
+    /// Returns the index of the partition holding records with a value of the partition
+    /// column. Nulls go to the first partition.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value of the partition column.
+    pub fn partition_index(&self, value: &Field) -> usize {
+        if *value == Field::Null {
+            return 0;
+        }
+        match &self.scheme {
+            PartitionScheme::Hash => {
+                let bytes = serde_cbor::to_vec(value).unwrap();
+                (fnv1a(&bytes, PARTITION_SEED) % self.partitions.len() as u64) as usize
+            }
+            PartitionScheme::Range(bounds) => bounds.partition_point(|bound| bound <= value),
+        }
+    }
+
+    /// Returns the container of the partition of a record.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Record of the table.
+    pub fn partition_of(&self, tuple: &Tuple) -> ContainerId {
+        let value = tuple.get_field(self.column).unwrap_or(&Field::Null);
+        self.partitions[self.partition_index(value)]
+    }
+
+    /// Returns the containers of the partitions that may hold records satisfying
+    /// comparisons of columns to values, in partition order. Comparisons of other columns,
+    /// and to values that are not of the type of the partition column, prune nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `dtype` - Type of the partition column.
+    /// * `comparisons` - Indices of columns of the table, comparison operators and the
+    ///   values the columns are compared to, all of which the records satisfy.
+    pub fn prune(
+        &self,
+        dtype: &DataType,
+        comparisons: &[(usize, SimplePredicateOp, Field)],
+    ) -> Vec<ContainerId> {
+        let mut keep = vec![true; self.partitions.len()];
+        for (column, op, value) in comparisons {
+            let value = value.clone().coerce(dtype);
+            if *column != self.column || value == Field::Null || !value.has_type(dtype) {
+                continue;
+            }
+            match (&self.scheme, op) {
+                (PartitionScheme::Hash, SimplePredicateOp::Equals) => {
+                    let partition = self.partition_index(&value);
+                    for (i, keep) in keep.iter_mut().enumerate() {
+                        *keep &= i == partition;
+                    }
+                }
+                (PartitionScheme::Hash, _) => {}
+                (PartitionScheme::Range(bounds), op) => {
+                    for (i, keep) in keep.iter_mut().enumerate() {
+                        // Partition i holds the values in [lower, upper).
+                        let lower = i.checked_sub(1).map(|j| &bounds[j]);
+                        let upper = bounds.get(i);
+                        let above = |v: &Field| upper.is_some_and(|u| v >= u);
+                        let below = |v: &Field| lower.is_some_and(|l| v < l);
+                        let outside = match op {
+                            SimplePredicateOp::Equals => above(&value) || below(&value),
+                            SimplePredicateOp::GreaterThan | SimplePredicateOp::GreaterThanOrEq => {
+                                above(&value)
+                            }
+                            SimplePredicateOp::LessThan => lower.is_some_and(|l| value <= *l),
+                            SimplePredicateOp::LessThanOrEq => below(&value),
+                            // Matches lie in [prefix, successor of prefix).
+                            SimplePredicateOp::Like => match &value {
+                                Field::StringField(pattern) => {
+                                    let prefix = like::prefix(pattern);
+                                    above(&Field::StringField(prefix.to_string()))
+                                        || like::prefix_successor(prefix).is_some_and(|next| {
+                                            lower.is_some_and(|l| Field::StringField(next) <= *l)
+                                        })
+                                }
+                                _ => false,
+                            },
+                            _ => false,
+                        };
+                        *keep &= !outside;
+                    }
+                }
+            }
+        }
+        self.partitions
+            .iter()
+            .zip(keep)
+            .filter(|(_, keep)| *keep)
+            .map(|(partition, _)| *partition)
+            .collect()
+    }
+}
+
+/// Inserts records into the partitions of a partitioned table. Returns the value id of
+/// every record, in the order of the records.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `partitioning` - Partitions of the table.
+/// * `tuples` - Records to insert.
+/// * `tid` - Transaction inserting the records.
+pub fn insert<T: StorageTrait>(
+    sm: &T,
+    partitioning: &Partitioning,
+    tuples: &[Tuple],
+    tid: TransactionId,
+) -> Result<Vec<ValueId>, CrustyError> {
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new(); partitioning.partitions.len()];
+    for (row, tuple) in tuples.iter().enumerate() {
+        let value = tuple.get_field(partitioning.column).unwrap_or(&Field::Null);
+        rows[partitioning.partition_index(value)].push(row);
+    }
+    let mut value_ids = vec![ValueId::new(0); tuples.len()];
+    for (rows, container_id) in rows.into_iter().zip(&partitioning.partitions) {
+        if rows.is_empty() {
+            continue;
+        }
+        let values = rows.iter().map(|row| tuples[*row].to_bytes()).collect();
+        let ids = sm.insert_values(*container_id, values, tid)?;
+        for (row, id) in rows.into_iter().zip(ids) {
+            value_ids[row] = id;
+        }
+    }
+    Ok(value_ids)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(bounds: Vec<i32>) -> Partitioning {
+        Partitioning {
+            column: 0,
+            scheme: PartitionScheme::Range(bounds.into_iter().map(Field::IntField).collect()),
+            partitions: (0..4).map(|i| 10 + i).collect(),
+        }
+    }
+
+    #[test]
+    fn test_range_partitions() {
+        let partitioning = range(vec![0, 10, 20]);
+        let partition = |v| partitioning.partition_index(&Field::IntField(v));
+        assert_eq!(0, partition(-5));
+        assert_eq!(1, partition(0));
+        assert_eq!(1, partition(9));
+        assert_eq!(2, partition(10));
+        assert_eq!(3, partition(20));
+        assert_eq!(3, partition(1000));
+        assert_eq!(0, partitioning.partition_index(&Field::Null));
+    }
+
+    #[test]
+    fn test_range_pruning() {
+        let partitioning = range(vec![0, 10, 20]);
+        let prune = |comparisons: Vec<(SimplePredicateOp, i32)>| {
+            let comparisons: Vec<_> = comparisons
+                .into_iter()
+                .map(|(op, v)| (0, op, Field::IntField(v)))
+                .collect();
+            partitioning.prune(&DataType::Int, &comparisons)
+        };
+        assert_eq!(vec![12], prune(vec![(SimplePredicateOp::Equals, 15)]));
+        assert_eq!(vec![11], prune(vec![(SimplePredicateOp::Equals, 0)]));
+        assert_eq!(vec![10, 11], prune(vec![(SimplePredicateOp::LessThan, 10)]));
+        assert_eq!(
+            vec![10, 11, 12],
+            prune(vec![(SimplePredicateOp::LessThanOrEq, 10)])
+        );
+        assert_eq!(
+            vec![12, 13],
+            prune(vec![(SimplePredicateOp::GreaterThan, 10)])
+        );
+        assert_eq!(
+            vec![11, 12],
+            prune(vec![
+                (SimplePredicateOp::GreaterThanOrEq, 5),
+                (SimplePredicateOp::LessThan, 20)
+            ])
+        );
+        assert_eq!(
+            vec![10, 11, 12, 13],
+            prune(vec![(SimplePredicateOp::NotEq, 5)])
+        );
+        // Comparisons of other columns prune nothing.
+        assert_eq!(
+            4,
+            partitioning
+                .prune(
+                    &DataType::Int,
+                    &[(1, SimplePredicateOp::Equals, Field::IntField(5))]
+                )
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_like_pruning() {
+        let bounds = ["b", "d", "f"].map(|b| Field::StringField(b.to_string()));
+        let partitioning = Partitioning {
+            column: 0,
+            scheme: PartitionScheme::Range(bounds.to_vec()),
+            partitions: vec![10, 11, 12, 13],
+        };
+        let prune = |pattern: &str| {
+            let pattern = Field::StringField(pattern.to_string());
+            partitioning.prune(&DataType::String, &[(0, SimplePredicateOp::Like, pattern)])
+        };
+        assert_eq!(vec![11], prune("b%"));
+        assert_eq!(vec![12], prune("dog_"));
+        assert_eq!(vec![13], prune("z%"));
+        assert_eq!(vec![10, 11, 12, 13], prune("%b"));
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        assert_eq!(
+            vec![Field::IntField(-5), Field::IntField(10)],
+            PartitionScheme::parse_bounds("-5, 10", &DataType::Int).unwrap()
+        );
+        assert_eq!(
+            vec![
+                Field::StringField("g".to_string()),
+                Field::StringField("p".to_string())
+            ],
+            PartitionScheme::parse_bounds("'g', p", &DataType::String).unwrap()
+        );
+        assert_eq!(
+            vec![Field::StringField("10".to_string())],
+            PartitionScheme::parse_bounds("10", &DataType::String).unwrap()
+        );
+        assert!(PartitionScheme::parse_bounds("10, 5", &DataType::Int).is_err());
+        assert!(PartitionScheme::parse_bounds("1, x", &DataType::Int).is_err());
+        assert!(PartitionScheme::parse_bounds("'1'", &DataType::Int).is_err());
+    }
+
+    #[test]
+    fn test_hash_pruning() {
+        let partitioning = Partitioning {
+            column: 1,
+            scheme: PartitionScheme::Hash,
+            partitions: vec![5, 6, 7],
+        };
+        let mut seen = [false; 3];
+        for v in 0..30 {
+            let tuple = Tuple::new(vec![Field::Null, Field::IntField(v)]);
+            let partition = partitioning.partition_of(&tuple);
+            seen[partition as usize - 5] = true;
+            let comparison = (1, SimplePredicateOp::Equals, Field::IntField(v));
+            assert_eq!(
+                vec![partition],
+                partitioning.prune(&DataType::Int, &[comparison])
+            );
+        }
+        assert!(seen.iter().all(|s| *s));
+        let comparison = (1, SimplePredicateOp::LessThan, Field::IntField(3));
+        assert_eq!(3, partitioning.prune(&DataType::Int, &[comparison]).len());
+    }
+}
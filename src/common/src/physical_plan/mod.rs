@@ -21,8 +21,12 @@ pub enum PhysicalOp {
     NestedLoopJoin(PhysicalNestedLoopJoinNode),
     HashJoin(PhysicalHashJoinNode),
     Filter(PhysicalFilterNode),
+    Sort(PhysicalSortNode),
+    Limit(PhysicalLimitNode),
     MaterializedView(MaterializedViewNode),
     Update(PhysicalUpdateNode),
+    Insert(PhysicalInsertNode),
+    Delete(PhysicalDeleteNode),
 }
 
 /// Graph where nodes represent physical operations and edges represent the flow of data.
@@ -129,6 +133,16 @@ impl PhysicalPlan {
         self.dataflow.node_data(index)
     }
 
+    /// Makes every hash aggregate of the plan return its groups sorted by their group by
+    /// keys, so the plan returns the same rows in the same order on every run.
+    pub fn sort_aggregate_groups(&mut self) {
+        for index in 0..self.node_count() {
+            if let Some(PhysicalOp::HashAggregate(node)) = self.dataflow.node_data_mut(index) {
+                node.sorted_groups = true;
+            }
+        }
+    }
+
     /// Returns the total number of nodes present in the graph.
     pub fn node_count(&self) -> usize {
         self.dataflow.node_count()
@@ -156,6 +170,10 @@ impl PhysicalPlan {
     }
 
     /// Serializes the Physical Plan as json.
+    ///
+    /// Nodes are keyed by their indices, and the targets of each source are listed in the
+    /// order their edges were added. Keys are sorted, so the same plan always has the same
+    /// json.
     pub fn to_json(&self) -> serde_json::Value {
         let mut node_map = HashMap::new();
         let mut edge_map = HashMap::new();
@@ -185,6 +203,9 @@ impl PhysicalPlan {
     }
 
     /// De-Serializes a json representation of the Physical Plan created in to_json
+    ///
+    /// The plan has the indices and the order of children of the serialized plan. Subqueries
+    /// of filters refer to their roots by their indices in the json.
     pub fn from_json(json: &str) -> Result<Self, CrustyError> {
         let malformed_err =
             CrustyError::CrustyError(String::from("Malformatted Physical plan json"));
@@ -211,23 +232,49 @@ impl PhysicalPlan {
             malformed_err.clone(),
         )?;
 
+        // Nodes are added in the order of their indices and edges in the order of their
+        // sources, so that a plan read from its own json has the same indices and children.
+        let index = |i: &str| i.parse::<OpIndex>().map_err(|_| malformed_err.clone());
+        let mut nodes = nodes
+            .into_iter()
+            .map(|(i, op)| Ok((index(&i)?, op)))
+            .collect::<Result<Vec<_>, CrustyError>>()?;
+        nodes.sort_by_key(|(i, _)| *i);
         let mut graph_map = HashMap::new();
         let mut plan = PhysicalPlan::new();
-        for (i, val) in nodes.iter() {
-            let node = plan.add_node(val.clone());
+        for (i, val) in nodes {
+            let node = plan.add_node(val);
             graph_map.insert(i, node);
         }
+        for node in graph_map.values() {
+            if let Some(PhysicalOp::Filter(filter)) = plan.dataflow.node_data_mut(*node) {
+                for subquery in filter.predicate.subqueries_mut() {
+                    subquery.root = *graph_map
+                        .get(&subquery.root)
+                        .ok_or_else(|| malformed_err.clone())?;
+                }
+            }
+        }
 
         if let Some(i) = root {
-            let root_node = graph_map.get(&i).ok_or_else(|| malformed_err.clone())?;
+            let root_node = graph_map
+                .get(&index(&i)?)
+                .ok_or_else(|| malformed_err.clone())?;
             plan.root = Some(*root_node);
         }
 
-        for (source, targets) in edges.iter() {
-            let source_node = graph_map.get(source).ok_or_else(|| malformed_err.clone())?;
+        let mut edges = edges
+            .into_iter()
+            .map(|(source, targets)| Ok((index(&source)?, targets)))
+            .collect::<Result<Vec<_>, CrustyError>>()?;
+        edges.sort_by_key(|(source, _)| *source);
+        for (source, targets) in edges {
+            let source_node = graph_map
+                .get(&source)
+                .ok_or_else(|| malformed_err.clone())?;
             for target in targets {
                 let target_node = graph_map
-                    .get(&target.to_string())
+                    .get(&index(&target)?)
                     .ok_or_else(|| malformed_err.clone())?;
                 plan.add_edge(*source_node, *target_node);
             }
@@ -401,7 +448,7 @@ mod tests {
     use super::*;
     use crate::logical_plan::{
         FieldIdentifier, PredExpr, Predicate, ProjectIdentifiers, SimplePredicate,
-        SimplePredicateOp,
+        SimplePredicateOp, SubqueryExpr,
     };
     use crate::Field;
 
@@ -515,6 +562,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_sort_aggregate_groups() {
+        let mut physical_plan = PhysicalPlan::new();
+        let scan = physical_plan.add_node(PhysicalOp::Scan(PhysicalScanNode {
+            alias: String::from("Table"),
+            container_id: 0,
+        }));
+        let aggregate =
+            physical_plan.add_node(PhysicalOp::HashAggregate(PhysicalHashAggregateNode {
+                fields: Vec::new(),
+                group_by: Vec::new(),
+                hash_table_state_id: 1,
+                hash_table_key: Vec::new(),
+                sorted_groups: false,
+            }));
+        physical_plan.add_edge(aggregate, scan);
+        physical_plan.sort_aggregate_groups();
+        match physical_plan.get_operator(aggregate) {
+            Some(PhysicalOp::HashAggregate(node)) => assert!(node.sorted_groups),
+            _ => panic!("Incorrect operator"),
+        }
+    }
+
     #[test]
     fn test_json() {
         let mut physical_plan = PhysicalPlan::new();
@@ -563,6 +633,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_json_keeps_indices() {
+        // More than ten nodes, so that their indices do not sort as strings, and a filter
+        // whose subquery is its second child.
+        let mut physical_plan = PhysicalPlan::new();
+        let mut node = physical_plan.add_node(PhysicalOp::Project(PhysicalProjectNode {
+            identifiers: ProjectIdentifiers::Wildcard,
+        }));
+        physical_plan.set_root(node).unwrap();
+        for _ in 0..9 {
+            let child = physical_plan.add_node(PhysicalOp::Project(PhysicalProjectNode {
+                identifiers: ProjectIdentifiers::Wildcard,
+            }));
+            physical_plan.add_edge(node, child);
+            node = child;
+        }
+        let scan = |alias: &str| {
+            PhysicalOp::Scan(PhysicalScanNode {
+                alias: String::from(alias),
+                container_id: 0,
+            })
+        };
+        let u = physical_plan.add_node(scan("u"));
+        let t = physical_plan.add_node(scan("t"));
+        let filter = physical_plan.add_node(PhysicalOp::Filter(PhysicalFilterNode {
+            table: String::from("t"),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new("t", "t.a")),
+                op: SimplePredicateOp::Equals,
+                right: PredExpr::Subquery(SubqueryExpr {
+                    root: u,
+                    parameters: Vec::new(),
+                }),
+            }),
+        }));
+        physical_plan.add_edge(node, filter);
+        physical_plan.add_edge(filter, u);
+        physical_plan.add_edge(filter, t);
+
+        let json = physical_plan.to_json();
+        let new_physical_plan = PhysicalPlan::from_json(&json.to_string()).unwrap();
+        assert_eq!(json, new_physical_plan.to_json());
+        assert_eq!(
+            vec![t, u],
+            new_physical_plan.edges(filter).collect::<Vec<_>>()
+        );
+    }
+
     #[test]
     fn test_cycle_free() {
         let mut physical_plan = PhysicalPlan::new();
@@ -748,6 +866,7 @@ mod tests {
                 group_by: Vec::new(),
                 hash_table_state_id: 5,
                 hash_table_key: Vec::new(),
+                sorted_groups: false,
             }));
 
         let filter = physical_plan2.add_node(PhysicalOp::Filter(PhysicalFilterNode {
@@ -28,6 +28,26 @@ pub struct PhysicalUpdateNode {
     pub assignments: Vec<(FieldIdentifier, Field)>,
 }
 
+/// Physical Insert Operator
+/// Same as Logical
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhysicalInsertNode {
+    /// Table to insert into.
+    pub alias: String,
+    /// Container of the table.
+    pub container_id: ContainerId,
+}
+
+/// Physical Delete Operator
+/// Same as Logical
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhysicalDeleteNode {
+    /// Table to delete from.
+    pub alias: String,
+    /// Container of the table.
+    pub container_id: ContainerId,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PhysicalHashAggregateNode {
     /// Fields to aggregate.
@@ -38,6 +58,27 @@ pub struct PhysicalHashAggregateNode {
     pub hash_table_state_id: ContainerId,
     /// Vector of the keys to hash by
     pub hash_table_key: Vec<FieldIdentifier>,
+    /// Whether groups are returned sorted by their group by keys instead of in hash order.
+    #[serde(default)]
+    pub sorted_groups: bool,
+}
+
+/// Physical Sort Operator
+/// Same as Logical
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhysicalSortNode {
+    /// Columns to sort by, in order, each with true if it is sorted in ascending order.
+    pub fields: Vec<(FieldIdentifier, bool)>,
+}
+
+/// Physical Limit Operator
+/// Same as Logical
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PhysicalLimitNode {
+    /// Maximum number of rows to return, or None to return all of them.
+    pub limit: Option<usize>,
+    /// Number of rows to skip before returning rows.
+    pub offset: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -0,0 +1,405 @@
+//! Table and column statistics for cost-based planning.
+//!
+//! Statistics are built by `analyze`, which scans a table (or a sample of its pages) and
+//! summarizes every column. They are stored in the database catalog, so they are persisted
+//! with it and are available to the planner after a restart.
+//!
+//! Storage managers count the values inserted, deleted and updated in each container in
+//! `ModificationCounters`. Once the changes to a table since it was last analyzed exceed
+//! `AUTO_ANALYZE_BASE` plus `AUTO_ANALYZE_FRACTION` of its rows, `refresh` analyzes it again,
+//! on a sample of its pages, so that its statistics do not go stale as it changes.
+
+use crate::columnar;
+use crate::ids::{ContainerId, Permissions, TransactionId};
+use crate::storage_trait::StorageTrait;
+use crate::table::{Table, TableLayout};
+use crate::{CrustyError, Field, TableSchema, Tuple};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Number of buckets in an equi-depth histogram.
+pub const HISTOGRAM_BUCKETS: usize = 10;
+
+/// Seed used to pick the pages analyzed when sampling.
+const ANALYZE_SEED: u64 = 0;
+
+/// Number of changes to a table that make its statistics stale, in addition to
+/// `AUTO_ANALYZE_FRACTION` of its rows.
+pub const AUTO_ANALYZE_BASE: u64 = 50;
+
+/// Fraction of the rows of a table that must change for its statistics to be stale.
+pub const AUTO_ANALYZE_FRACTION: f64 = 0.1;
+
+/// Number of rows `refresh` samples; smaller tables are read whole.
+pub const AUTO_ANALYZE_SAMPLE_ROWS: u64 = 10_000;
+
+/// Counts of the values inserted, deleted or updated in the containers of a storage
+/// manager since their table was last analyzed. The counts are kept in memory, so they
+/// start again from 0 when the storage manager is created.
+#[derive(Default)]
+pub struct ModificationCounters {
+    counts: RwLock<HashMap<ContainerId, u64>>,
+}
+
+impl ModificationCounters {
+    /// Counts changed values of a container.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the values.
+    /// * `count` - Number of values inserted, deleted or updated.
+    pub fn add(&self, container_id: ContainerId, count: usize) {
+        if count > 0 {
+            *self
+                .counts
+                .write()
+                .unwrap()
+                .entry(container_id)
+                .or_default() += count as u64;
+        }
+    }
+
+    /// Returns the number of values changed in a container since its count was reset.
+    pub fn get(&self, container_id: ContainerId) -> u64 {
+        self.counts
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Resets the count of a container, e.g. once its table is analyzed or it is removed.
+    pub fn reset(&self, container_id: ContainerId) {
+        self.counts.write().unwrap().remove(&container_id);
+    }
+
+    /// Resets the counts of all the containers.
+    pub fn clear(&self) {
+        self.counts.write().unwrap().clear();
+    }
+}
+
+/// Statistics about a table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    /// Estimated number of rows.
+    pub row_count: u64,
+    /// Fraction of the table's pages that were read to build the statistics.
+    pub sample_fraction: f64,
+    /// Statistics for each column, in schema order.
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Statistics about a column. Counts are estimates for the whole table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    /// Smallest non-null value, None if there are no non-null values.
+    pub min: Option<Field>,
+    /// Largest non-null value, None if there are no non-null values.
+    pub max: Option<Field>,
+    /// Number of null values.
+    pub null_count: u64,
+    /// Number of distinct non-null values.
+    pub distinct_count: u64,
+    /// Equi-depth histogram of the non-null values.
+    pub histogram: Histogram,
+}
+
+/// An equi-depth histogram. Bucket `i` holds the values greater than `bounds[i - 1]` and
+/// at most `bounds[i]`; every bucket holds about the same number of values, except where
+/// one value is repeated more often than a bucket holds.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    /// Inclusive upper bound of each bucket, ascending.
+    pub bounds: Vec<Field>,
+    /// Estimated number of values in each bucket.
+    pub counts: Vec<u64>,
+}
+
+impl Histogram {
+    /// Builds a histogram with up to `buckets` buckets from sorted values, scaling the
+    /// counts by `scale`.
+    fn from_sorted(values: &[&Field], buckets: usize, scale: f64) -> Self {
+        let mut hist = Histogram::default();
+        let mut start = 0;
+        for i in 0..buckets {
+            let end = (values.len() * (i + 1)) / buckets;
+            if end <= start {
+                continue;
+            }
+            // Keep all copies of the bound in this bucket so bounds are strictly ascending.
+            let bound = values[end - 1];
+            let end = start + values[start..].partition_point(|v| *v <= bound);
+            hist.bounds.push(bound.clone());
+            hist.counts
+                .push(((end - start) as f64 * scale).round() as u64);
+            start = end;
+        }
+        hist
+    }
+
+    /// Total number of values in the histogram.
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl TableStats {
+    /// Builds statistics from the tuples read from a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the tuples.
+    /// * `tuples` - Tuples read from the table.
+    /// * `sample_fraction` - Fraction of the table the tuples were read from.
+    pub fn from_tuples<I: IntoIterator<Item = Tuple>>(
+        schema: &TableSchema,
+        tuples: I,
+        sample_fraction: f64,
+    ) -> Self {
+        let mut columns: Vec<Vec<Field>> = vec![Vec::new(); schema.size()];
+        let mut rows = 0;
+        for tuple in tuples {
+            rows += 1;
+            for (i, column) in columns.iter_mut().enumerate() {
+                column.push(tuple.get_field(i).cloned().unwrap_or(Field::Null));
+            }
+        }
+        let scale = if sample_fraction > 0.0 {
+            1.0 / sample_fraction
+        } else {
+            0.0
+        };
+        TableStats {
+            row_count: (rows as f64 * scale).round() as u64,
+            sample_fraction,
+            columns: columns
+                .iter()
+                .map(|values| ColumnStats::from_values(values, scale))
+                .collect(),
+        }
+    }
+}
+
+impl ColumnStats {
+    /// Builds the statistics of a column that holds the same value in all `rows` rows, such
+    /// as a column just added to a table.
+    pub fn constant(value: &Field, rows: u64) -> Self {
+        if *value == Field::Null || rows == 0 {
+            return ColumnStats {
+                min: None,
+                max: None,
+                null_count: rows,
+                distinct_count: 0,
+                histogram: Histogram::default(),
+            };
+        }
+        ColumnStats {
+            min: Some(value.clone()),
+            max: Some(value.clone()),
+            null_count: 0,
+            distinct_count: 1,
+            histogram: Histogram {
+                bounds: vec![value.clone()],
+                counts: vec![rows],
+            },
+        }
+    }
+
+    /// Builds the statistics of a column from its values, scaling counts by `scale`.
+    fn from_values(values: &[Field], scale: f64) -> Self {
+        let mut non_null: Vec<&Field> = values.iter().filter(|f| **f != Field::Null).collect();
+        non_null.sort();
+        let null_count = ((values.len() - non_null.len()) as f64 * scale).round() as u64;
+
+        // Frequency of each value in the sample, to estimate the distinct count.
+        let mut frequencies: HashMap<&Field, u64> = HashMap::new();
+        for value in &non_null {
+            *frequencies.entry(value).or_insert(0) += 1;
+        }
+        ColumnStats {
+            min: non_null.first().map(|f| (*f).clone()),
+            max: non_null.last().map(|f| (*f).clone()),
+            null_count,
+            distinct_count: Self::estimate_distinct(&frequencies, scale),
+            histogram: Histogram::from_sorted(&non_null, HISTOGRAM_BUCKETS, scale),
+        }
+    }
+
+    /// Estimates the number of distinct values in the table from the frequencies of the
+    /// values in a sample, using the GEE estimator: values seen once in the sample stand
+    /// for sqrt(scale) distinct values each, values seen more than once only for themselves.
+    /// Without sampling (scale 1) this is the exact count.
+    fn estimate_distinct(frequencies: &HashMap<&Field, u64>, scale: f64) -> u64 {
+        let seen = frequencies.len() as f64;
+        let singletons = frequencies.values().filter(|c| **c == 1).count() as f64;
+        (scale.sqrt() * singletons + (seen - singletons)).round() as u64
+    }
+}
+
+/// Scans a table (or a sample of its pages) and builds its statistics, and resets the
+/// modification counts of the containers of its records.
+/// Errors if a columnar table references a missing or corrupt column chunk.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `container_id` - Container of the table.
+/// * `table` - The table, whose records are padded to its current schema.
+/// * `sample_fraction` - Fraction of pages to read; 1.0 or more scans the whole table.
+/// * `tid` - Transaction used to read the table.
+pub fn analyze<T: StorageTrait>(
+    sm: &T,
+    container_id: ContainerId,
+    table: &Table,
+    sample_fraction: f64,
+    tid: TransactionId,
+) -> Result<TableStats, CrustyError> {
+    debug!(
+        "Analyzing container {} with sample fraction {}",
+        container_id, sample_fraction
+    );
+    let sample_fraction = sample_fraction.min(1.0);
+    let containers = table.layout.record_containers(container_id);
+    for container_id in &containers {
+        sm.modifications().reset(*container_id);
+    }
+    // Partitioned tables are sampled partition by partition.
+    let records = containers.into_iter().flat_map(|container_id| {
+        if sample_fraction >= 1.0 {
+            sm.get_iterator(container_id, tid, Permissions::ReadOnly)
+        } else {
+            sm.sample_iterator(container_id, sample_fraction, ANALYZE_SEED, tid)
+        }
+    });
+    match table.layout {
+        TableLayout::Row | TableLayout::Partitioned(_) => {
+            let tuples = records.map(|(bytes, _)| table.read_tuple(&bytes));
+            Ok(TableStats::from_tuples(
+                &table.schema,
+                tuples,
+                sample_fraction,
+            ))
+        }
+        // The pages sampled from a columnar table hold row groups.
+        TableLayout::Column(_) => {
+            let tuples = columnar::read_records(sm, records, table.schema.size(), tid)?;
+            Ok(TableStats::from_tuples(
+                &table.schema,
+                tuples,
+                sample_fraction,
+            ))
+        }
+    }
+}
+
+/// Analyzes a table again if it changed enough since its statistics were computed, and
+/// returns its new statistics, or None if they are still fresh. Tables of more than
+/// `AUTO_ANALYZE_SAMPLE_ROWS` rows are sampled. Tables never analyzed have no rows for this.
+/// The changes to a columnar table are counted in row groups.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `container_id` - Container of the table.
+/// * `table` - The table.
+/// * `stats` - Current statistics of the table, if it has any.
+/// * `tid` - Transaction used to read the table.
+pub fn refresh<T: StorageTrait>(
+    sm: &T,
+    container_id: ContainerId,
+    table: &Table,
+    stats: Option<&TableStats>,
+    tid: TransactionId,
+) -> Result<Option<TableStats>, CrustyError> {
+    let rows = stats.map_or(0, |stats| stats.row_count);
+    let changes: u64 = table
+        .layout
+        .record_containers(container_id)
+        .into_iter()
+        .map(|container_id| sm.modifications().get(container_id))
+        .sum();
+    if changes as f64 <= AUTO_ANALYZE_BASE as f64 + AUTO_ANALYZE_FRACTION * rows as f64 {
+        return Ok(None);
+    }
+    // Inserts may have added as many rows as there were changes.
+    let sample_fraction = AUTO_ANALYZE_SAMPLE_ROWS as f64 / (rows + changes) as f64;
+    info!(
+        "Refreshing the statistics of container {} after {} changes",
+        container_id, changes
+    );
+    analyze(sm, container_id, table, sample_fraction, tid).map(Some)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+    use crate::DataType;
+
+    fn int_column(values: Vec<Option<i32>>) -> Vec<Tuple> {
+        values
+            .into_iter()
+            .map(|v| Tuple::new(vec![v.map_or(Field::Null, Field::IntField)]))
+            .collect()
+    }
+
+    #[test]
+    fn test_column_stats() {
+        let schema = TableSchema::from_vecs(vec!["a"], vec![DataType::Int]);
+        let mut values: Vec<Option<i32>> = (0..100).map(|i| Some(i % 40)).collect();
+        values.extend([None, None, None]);
+        let stats = TableStats::from_tuples(&schema, int_column(values), 1.0);
+
+        assert_eq!(103, stats.row_count);
+        let col = &stats.columns[0];
+        assert_eq!(Some(Field::IntField(0)), col.min);
+        assert_eq!(Some(Field::IntField(39)), col.max);
+        assert_eq!(3, col.null_count);
+        assert_eq!(40, col.distinct_count);
+        assert_eq!(HISTOGRAM_BUCKETS, col.histogram.bounds.len());
+        assert_eq!(100, col.histogram.total());
+        assert_eq!(Some(&Field::IntField(39)), col.histogram.bounds.last());
+        assert!(col.histogram.bounds.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_histogram_skew() {
+        // One value fills more than a bucket, so the buckets it spans are merged.
+        let schema = TableSchema::from_vecs(vec!["a"], vec![DataType::Int]);
+        let mut values: Vec<Option<i32>> = vec![Some(5); 50];
+        values.extend((0..50).map(Some));
+        let stats = TableStats::from_tuples(&schema, int_column(values), 1.0);
+        let hist = &stats.columns[0].histogram;
+        assert_eq!(100, hist.total());
+        assert!(hist.bounds.windows(2).all(|w| w[0] < w[1]));
+        let five = hist
+            .bounds
+            .iter()
+            .position(|b| *b == Field::IntField(5))
+            .unwrap();
+        assert!(hist.counts[five] >= 50);
+    }
+
+    #[test]
+    fn test_sampled_stats_scale() {
+        let schema = get_int_table_schema(2);
+        let tuples: Vec<Tuple> = (0..50).map(|i| int_vec_to_tuple(vec![i, 1])).collect();
+        let stats = TableStats::from_tuples(&schema, tuples, 0.25);
+        assert_eq!(200, stats.row_count);
+        assert_eq!(200, stats.columns[0].histogram.total());
+        // Every value seen once: each stands for sqrt(4) = 2 distinct values.
+        assert_eq!(100, stats.columns[0].distinct_count);
+        // A value seen many times is counted once.
+        assert_eq!(1, stats.columns[1].distinct_count);
+    }
+
+    #[test]
+    fn test_empty_table() {
+        let stats = TableStats::from_tuples(&get_int_table_schema(1), Vec::new(), 1.0);
+        assert_eq!(0, stats.row_count);
+        assert_eq!(None, stats.columns[0].min);
+        assert!(stats.columns[0].histogram.bounds.is_empty());
+    }
+}
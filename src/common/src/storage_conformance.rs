@@ -0,0 +1,715 @@
+//! A conformance suite for `StorageTrait` implementations.
+//!
+//! Each check is a generic function that builds its own storage manager, so a backend
+//! can be validated by invoking `storage_conformance_tests!` with its storage manager
+//! type from an integration test. All backends should pass the same checks.
+
+use crate::csv_export::CsvExportOptions;
+use crate::csv_import::CsvImportOptions;
+use crate::ndjson::RowErrorPolicy;
+use crate::prelude::*;
+use crate::storage_trait::StorageTrait;
+use crate::temp_container::{is_temp_container, TEMP_CONTAINER_START};
+use crate::testutil::*;
+use std::collections::HashSet;
+use std::fs;
+
+const RO: Permissions = Permissions::ReadOnly;
+
+/// Collect the bytes returned by a container's iterator.
+fn scan<T: StorageTrait>(sm: &T, container_id: ContainerId, tid: TransactionId) -> Vec<Vec<u8>> {
+    sm.get_iterator(container_id, tid, RO)
+        .map(|(bytes, _)| bytes)
+        .collect()
+}
+
+/// Values that are inserted can be read back by their value id.
+pub fn insert_get<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let vals = get_random_vec_of_byte_vec(200, 20, 300);
+    let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+    assert_eq!(vals.len(), ids.len());
+    for (val, id) in vals.iter().zip(ids.iter()) {
+        assert_eq!(id.container_id, cid);
+        assert_eq!(val[..], sm.get_value(*id, tid, RO).unwrap()[..]);
+    }
+    let single = get_random_byte_vec(64);
+    let id = sm.insert_value(cid, single.clone(), tid).unwrap();
+    assert!(
+        !ids.contains(&id),
+        "value ids must be unique within a container"
+    );
+    assert_eq!(single, sm.get_value(id, tid, RO).unwrap());
+}
+
+/// Bulk loaded values can be read back by their value id and are returned by the iterator
+/// along with the values inserted before them.
+pub fn bulk_load<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, missing) = (1, 2);
+    sm.create_table(cid).unwrap();
+    let inserted = get_random_vec_of_byte_vec(20, 20, 100);
+    let mut ids = sm.insert_values(cid, inserted.clone(), tid).unwrap();
+    let loaded = get_random_vec_of_byte_vec(500, 20, 300);
+    let loaded_ids = sm.bulk_load(cid, loaded.clone().into_iter(), tid).unwrap();
+    assert_eq!(loaded.len(), loaded_ids.len());
+    for (val, id) in loaded.iter().zip(loaded_ids.iter()) {
+        assert_eq!(val[..], sm.get_value(*id, tid, RO).unwrap()[..]);
+    }
+    ids.extend(loaded_ids);
+    assert_eq!(
+        ids.len(),
+        ids.iter().collect::<HashSet<_>>().len(),
+        "value ids must be unique within a container"
+    );
+    let mut expected: Vec<Vec<u8>> = inserted.into_iter().chain(loaded).collect();
+    let mut found = scan(&sm, cid, tid);
+    expected.sort();
+    found.sort();
+    assert_eq!(expected, found);
+
+    assert_eq!(
+        Err(CrustyError::ContainerMissing(missing)),
+        sm.bulk_load(missing, vec![vec![1]].into_iter(), tid)
+    );
+    sm.reset().unwrap();
+}
+
+/// A deleted value is no longer readable or returned by the iterator, and deleting it
+/// again is not an error.
+pub fn delete<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let mut vals = get_random_vec_of_byte_vec(100, 20, 200);
+    let mut ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+    for idx in [0, 50, 97, 10] {
+        let id = ids.remove(idx);
+        vals.remove(idx);
+        sm.delete_value(id, tid).unwrap();
+        assert!(sm.get_value(id, tid, RO).is_err());
+        sm.delete_value(id, tid).unwrap();
+        assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+    }
+    for (val, id) in vals.iter().zip(ids.iter()) {
+        assert_eq!(val[..], sm.get_value(*id, tid, RO).unwrap()[..]);
+    }
+}
+
+/// Values deleted together, across containers, are no longer readable or returned by the
+/// iterator, and deleting them again is not an error.
+pub fn delete_many<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.create_table(2).unwrap();
+    let vals = get_random_vec_of_byte_vec(100, 20, 200);
+    let ids1 = sm.insert_values(1, vals.clone(), tid).unwrap();
+    let ids2 = sm.insert_values(2, vals.clone(), tid).unwrap();
+    let deleted: Vec<ValueId> = ids1[..40].iter().chain(&ids2[90..]).copied().collect();
+    sm.delete_values(deleted.clone(), tid).unwrap();
+    sm.delete_values(deleted.clone(), tid).unwrap();
+    sm.delete_values(Vec::new(), tid).unwrap();
+    for id in &deleted {
+        assert!(sm.get_value(*id, tid, RO).is_err());
+    }
+    assert!(compare_unordered_byte_vecs(&vals[40..], scan(&sm, 1, tid)));
+    assert!(compare_unordered_byte_vecs(&vals[..90], scan(&sm, 2, tid)));
+}
+
+/// An overwrite replaces the bytes of a value and keeps its value id, and fails if the
+/// length changes or the value does not exist.
+pub fn overwrite<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let mut vals = get_random_vec_of_byte_vec(100, 50, 50);
+    let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+    for idx in [0, 42, 99] {
+        let new_bytes = get_random_byte_vec(50);
+        sm.overwrite_value(new_bytes.clone(), ids[idx], tid)
+            .unwrap();
+        assert_eq!(new_bytes, sm.get_value(ids[idx], tid, RO).unwrap());
+        vals[idx] = new_bytes;
+    }
+    assert!(sm
+        .overwrite_value(get_random_byte_vec(51), ids[1], tid)
+        .is_err());
+    sm.delete_value(ids[2], tid).unwrap();
+    assert!(sm
+        .overwrite_value(get_random_byte_vec(50), ids[2], tid)
+        .is_err());
+    vals.remove(2);
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+}
+
+/// An update replaces the value. The returned value id reads the new bytes, and if the id
+/// changed the old one no longer resolves.
+pub fn update<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let mut vals = get_random_vec_of_byte_vec(100, 20, 200);
+    let mut ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+    for (idx, size) in [(3, 10), (40, 150), (99, 400), (3, 20)] {
+        let new_bytes = get_random_byte_vec(size);
+        let old_id = ids[idx];
+        let new_id = sm.update_value(new_bytes.clone(), old_id, tid).unwrap();
+        assert_eq!(new_bytes, sm.get_value(new_id, tid, RO).unwrap());
+        if new_id != old_id {
+            assert!(sm.get_value(old_id, tid, RO).is_err());
+        }
+        vals[idx] = new_bytes;
+        ids[idx] = new_id;
+        assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+    }
+}
+
+/// The iterator returns every live value exactly once, with value ids that resolve to
+/// the same bytes, and never returns values from another container.
+pub fn iterator_complete<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, other) = (1, 2);
+    sm.create_table(cid).unwrap();
+    sm.create_table(other).unwrap();
+    assert_eq!(0, sm.get_iterator(cid, tid, RO).count());
+
+    let vals = get_random_vec_of_byte_vec(1000, 50, 100);
+    let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+    sm.insert_values(other, get_random_vec_of_byte_vec(50, 10, 20), tid)
+        .unwrap();
+
+    let mut seen = HashSet::new();
+    let mut check_vals = Vec::new();
+    for (bytes, id) in sm.get_iterator(cid, tid, RO) {
+        assert_eq!(cid, id.container_id);
+        assert!(seen.insert(id), "iterator returned {:?} twice", id);
+        assert_eq!(bytes, sm.get_value(id, tid, RO).unwrap());
+        check_vals.push(bytes);
+    }
+    assert_eq!(ids.into_iter().collect::<HashSet<_>>(), seen);
+    assert!(compare_unordered_byte_vecs(&vals, check_vals));
+}
+
+/// A sample is a repeatable subset of the container's values, and the fractions 0 and 1
+/// sample nothing and everything.
+pub fn sample<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let vals = get_random_vec_of_byte_vec(600, 50, 100);
+    sm.insert_values(cid, vals.clone(), tid).unwrap();
+    let all: HashSet<(Vec<u8>, ValueId)> = sm.get_iterator(cid, tid, RO).collect();
+
+    assert_eq!(0, sm.sample_iterator(cid, 0.0, 7, tid).count());
+    let everything: HashSet<_> = sm.sample_iterator(cid, 1.0, 7, tid).collect();
+    assert_eq!(all, everything);
+
+    let half: Vec<_> = sm.sample_iterator(cid, 0.5, 7, tid).collect();
+    assert!(!half.is_empty() && half.len() < vals.len());
+    assert!(half.iter().all(|val| all.contains(val)));
+    assert_eq!(
+        half,
+        sm.sample_iterator(cid, 0.5, 7, tid).collect::<Vec<_>>()
+    );
+}
+
+/// Containers can be created, removed and created again empty.
+pub fn container_lifecycle<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, other) = (1, 2);
+    sm.create_table(cid).unwrap();
+    sm.create_container(other, None, StateType::HashTable, None)
+        .unwrap();
+    let vals = get_random_vec_of_byte_vec(20, 10, 50);
+    sm.insert_values(cid, vals.clone(), tid).unwrap();
+    let other_vals = get_random_vec_of_byte_vec(20, 10, 50);
+    sm.insert_values(other, other_vals.clone(), tid).unwrap();
+
+    sm.remove_container(cid).unwrap();
+    assert!(compare_unordered_byte_vecs(
+        &other_vals,
+        scan(&sm, other, tid)
+    ));
+
+    sm.create_table(cid).unwrap();
+    assert!(scan(&sm, cid, tid).is_empty());
+    sm.insert_values(cid, vals.clone(), tid).unwrap();
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+}
+
+/// A container cannot be created with missing dependencies, and a container cannot be
+/// removed while another container depends on it.
+pub fn dependencies<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (parent, child, missing) = (1, 2, 3);
+    sm.create_table(parent).unwrap();
+    assert!(sm
+        .create_container(child, None, StateType::BaseTable, Some(vec![missing]))
+        .is_err());
+    sm.create_container(child, None, StateType::BaseTable, Some(vec![parent]))
+        .unwrap();
+    let vals = get_random_vec_of_byte_vec(20, 10, 50);
+    sm.insert_values(parent, vals.clone(), tid).unwrap();
+
+    assert!(sm.remove_container(parent).is_err());
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, parent, tid)));
+    sm.remove_container(child).unwrap();
+    sm.remove_container(parent).unwrap();
+}
+
+/// Reset removes all containers and values, and the storage manager is usable afterwards.
+pub fn reset<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    sm.insert_values(cid, get_random_vec_of_byte_vec(100, 10, 100), tid)
+        .unwrap();
+    sm.reset().unwrap();
+
+    sm.create_table(cid).unwrap();
+    assert!(scan(&sm, cid, tid).is_empty());
+    let vals = get_random_vec_of_byte_vec(10, 10, 100);
+    sm.insert_values(cid, vals.clone(), tid).unwrap();
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+}
+
+/// State written before shutdown is visible to a storage manager created on the same path.
+pub fn shutdown_startup<T: StorageTrait>() {
+    let path = gen_random_test_sm_dir();
+    let tid = TransactionId::new();
+    let (cid, other) = (1, 2);
+    let vals = get_random_vec_of_byte_vec(300, 50, 100);
+    let other_vals = get_random_vec_of_byte_vec(10, 50, 100);
+    let ids = {
+        let sm = T::new(path.clone());
+        sm.create_table(cid).unwrap();
+        sm.create_container(other, None, StateType::BaseTable, Some(vec![cid]))
+            .unwrap();
+        let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+        sm.insert_values(other, other_vals.clone(), tid).unwrap();
+        sm.shutdown();
+        ids
+    };
+
+    let sm = T::new(path.clone());
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+    assert!(compare_unordered_byte_vecs(
+        &other_vals,
+        scan(&sm, other, tid)
+    ));
+    for (val, id) in vals.iter().zip(ids.iter()) {
+        assert_eq!(val[..], sm.get_value(*id, tid, RO).unwrap()[..]);
+    }
+    assert!(sm.remove_container(cid).is_err());
+    sm.reset().unwrap();
+    drop(sm);
+    let _ = fs::remove_dir_all(path);
+}
+
+/// Databases have their own container ids, are persisted across shutdown, and are dropped
+/// with their containers.
+pub fn databases<T: StorageTrait>() {
+    let path = gen_random_test_sm_dir();
+    let tid = TransactionId::new();
+    let cid = 1;
+    let vals = get_random_vec_of_byte_vec(20, 10, 50);
+    let db_vals = get_random_vec_of_byte_vec(30, 10, 50);
+    {
+        let sm = T::new(path.clone());
+        assert!(sm.create_database("../other").is_err());
+        assert!(sm.use_database("sales").is_err());
+        sm.create_table(cid).unwrap();
+        sm.insert_values(cid, vals.clone(), tid).unwrap();
+        sm.create_database("sales").unwrap();
+        sm.create_database("hr").unwrap();
+        assert!(sm.create_database("sales").is_err());
+
+        // the same container id is another container in each database
+        let sales = sm.use_database("sales").unwrap();
+        sales.create_table(cid).unwrap();
+        sales.insert_values(cid, db_vals.clone(), tid).unwrap();
+        let hr = sm.use_database("hr").unwrap();
+        assert!(hr.insert_value(cid, vals[0].clone(), tid).is_err());
+        assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+        assert_eq!(vec!["hr", "sales"], sm.list_databases());
+        sm.shutdown();
+    }
+
+    let sm = T::new(path.clone());
+    assert_eq!(vec!["hr", "sales"], sm.list_databases());
+    let sales = sm.use_database("sales").unwrap();
+    assert!(compare_unordered_byte_vecs(
+        &db_vals,
+        scan(&*sales, cid, tid)
+    ));
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+    sm.drop_database("sales").unwrap();
+    assert!(sales.insert_value(cid, vals[0].clone(), tid).is_err());
+    assert!(sm.use_database("sales").is_err());
+    assert!(sm.drop_database("sales").is_err());
+    assert_eq!(vec!["hr"], sm.list_databases());
+    assert!(compare_unordered_byte_vecs(&vals, scan(&sm, cid, tid)));
+    sm.reset().unwrap();
+    assert!(sm.list_databases().is_empty());
+    drop(sm);
+    let _ = fs::remove_dir_all(path);
+}
+
+/// A bloom filter never misses a key of a stored tuple, through inserts, deletes, updates,
+/// creation over existing tuples, and shutdown. It is dropped with its container.
+pub fn bloom_filter<T: StorageTrait>() {
+    let path = gen_random_test_sm_dir();
+    let tid = TransactionId::new();
+    let cid = 1;
+    let tuple = |key: i32| Tuple::new(vec![Field::IntField(key), Field::IntField(key * 2)]);
+    {
+        let sm = T::new(path.clone());
+        sm.create_table(cid).unwrap();
+        assert!(sm.may_contain(cid, &Field::IntField(1)));
+        let existing: Vec<Vec<u8>> = (0..50).map(|k| tuple(k).to_bytes()).collect();
+        sm.insert_values(cid, existing, tid).unwrap();
+        sm.create_bloom_filter(cid, 0, 200).unwrap();
+        assert_eq!(Some(0), sm.bloom_filter_column(cid));
+
+        let ids = sm
+            .insert_values(cid, (50..100).map(|k| tuple(k).to_bytes()).collect(), tid)
+            .unwrap();
+        assert!((0..100).all(|k| sm.may_contain(cid, &Field::IntField(k))));
+        let absent = (100..1100)
+            .filter(|k| sm.may_contain(cid, &Field::IntField(*k)))
+            .count();
+        assert!(absent < 100, "{} false positives", absent);
+
+        sm.delete_value(ids[0], tid).unwrap();
+        let id = sm.update_value(tuple(500).to_bytes(), ids[1], tid).unwrap();
+        assert!(sm.may_contain(cid, &Field::IntField(500)));
+        assert_eq!(tuple(500).to_bytes(), sm.get_value(id, tid, RO).unwrap());
+        sm.shutdown();
+    }
+
+    let sm = T::new(path.clone());
+    assert_eq!(Some(0), sm.bloom_filter_column(cid));
+    assert!((0..50)
+        .chain(52..100)
+        .all(|k| sm.may_contain(cid, &Field::IntField(k))));
+    assert!(sm.may_contain(cid, &Field::IntField(500)));
+    sm.remove_container(cid).unwrap();
+    assert_eq!(None, sm.bloom_filter_column(cid));
+    sm.reset().unwrap();
+    drop(sm);
+    let _ = fs::remove_dir_all(path);
+}
+
+/// Temporary containers get ids in the reserved range, and are removed when their
+/// transaction finishes and at shutdown.
+pub fn temp_containers<T: StorageTrait>() {
+    let path = gen_random_test_sm_dir();
+    let (tid, other_tid) = (TransactionId::new(), TransactionId::new());
+    let cid = 1;
+    {
+        let sm = T::new(path.clone());
+        sm.create_table(cid).unwrap();
+        let temp = sm.create_temp_container(tid).unwrap();
+        let other = sm.create_temp_container(other_tid).unwrap();
+        assert!(is_temp_container(temp) && is_temp_container(other));
+        assert_ne!(temp, other);
+        let vals = get_random_vec_of_byte_vec(20, 10, 50);
+        sm.insert_values(temp, vals.clone(), tid).unwrap();
+        assert!(compare_unordered_byte_vecs(&vals, scan(&sm, temp, tid)));
+
+        // A container can only depend on containers that exist.
+        sm.transaction_finished(tid);
+        assert!(sm
+            .create_container(2, None, StateType::BaseTable, Some(vec![temp]))
+            .is_err());
+        sm.create_container(2, None, StateType::BaseTable, Some(vec![other]))
+            .unwrap();
+        sm.remove_container(2).unwrap();
+        // A removed temporary container frees its id.
+        assert_eq!(temp, sm.create_temp_container(tid).unwrap());
+        sm.shutdown();
+    }
+
+    let sm = T::new(path.clone());
+    assert!(sm
+        .create_container(2, None, StateType::BaseTable, Some(vec![cid]))
+        .is_ok());
+    assert!(sm
+        .create_container(
+            3,
+            None,
+            StateType::BaseTable,
+            Some(vec![TEMP_CONTAINER_START])
+        )
+        .is_err());
+    sm.reset().unwrap();
+    drop(sm);
+    let _ = fs::remove_dir_all(path);
+}
+
+/// Operations on containers that do not exist and on empty slots return errors with the
+/// container and slot, rather than panicking.
+pub fn errors<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, missing) = (1, 2);
+    sm.create_table(cid).unwrap();
+    let id = sm.insert_value(cid, vec![1, 2, 3], tid).unwrap();
+
+    assert_eq!(
+        Err(CrustyError::ContainerMissing(missing)),
+        sm.insert_value(missing, vec![1], tid)
+    );
+    let elsewhere = ValueId {
+        container_id: missing,
+        ..id
+    };
+    assert_eq!(
+        Err(CrustyError::ContainerMissing(missing)),
+        sm.get_value(elsewhere, tid, RO)
+    );
+    let empty = ValueId {
+        slot_id: id.slot_id.map(|slot_id| slot_id + 1),
+        ..id
+    };
+    assert_eq!(
+        Err(CrustyError::invalid_slot(&empty)),
+        sm.get_value(empty, tid, RO)
+    );
+    assert_eq!(
+        Err(CrustyError::invalid_slot(&empty)),
+        sm.overwrite_value(vec![4, 5, 6], empty, tid)
+    );
+    sm.reset().unwrap();
+}
+
+/// Records exported to csv with the default options are imported back unchanged, and
+/// exporting a container that does not exist is an error.
+pub fn export_csv<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, imported, missing) = (1, 2, 3);
+    sm.create_table(cid).unwrap();
+    sm.create_table(imported).unwrap();
+    let schema = TableSchema::from_vecs(
+        vec!["id", "name", "flag"],
+        vec![DataType::Int, DataType::String, DataType::Boolean],
+    );
+    let tuples: Vec<Tuple> = (0..50)
+        .map(|i| {
+            let name = match i % 3 {
+                0 => Field::Null,
+                1 => Field::StringField(format!("a,\"{}\"", i)),
+                _ => Field::StringField(format!("line\n{}", i)),
+            };
+            Tuple::new(vec![Field::IntField(i), name, Field::BoolField(i % 2 == 0)])
+        })
+        .collect();
+    let vals = tuples.iter().map(|t| t.to_bytes()).collect();
+    sm.insert_values(cid, vals, tid).unwrap();
+
+    let dir = gen_random_test_sm_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("export.csv").to_string_lossy().to_string();
+    let options = CsvExportOptions::default();
+    assert_eq!(
+        Ok(tuples.len()),
+        sm.export_csv(cid, &schema, path.clone(), &options, tid)
+    );
+    let table = Table::new(String::from("t"), schema.clone());
+    let report = sm
+        .import_csv(
+            &table,
+            path.clone(),
+            tid,
+            imported,
+            &CsvImportOptions::default(),
+        )
+        .unwrap();
+    assert_eq!(tuples.len(), report.imported);
+    let fields = |container_id| {
+        let mut rows: Vec<Vec<Field>> = scan(&sm, container_id, tid)
+            .iter()
+            .map(|bytes| Tuple::from_bytes(bytes).field_vals)
+            .collect();
+        rows.sort_by_key(|row| row[0].clone());
+        rows
+    };
+    assert_eq!(fields(cid), fields(imported));
+
+    assert_eq!(
+        Err(CrustyError::ContainerMissing(missing)),
+        sm.export_csv(missing, &schema, path, &options, tid)
+    );
+    sm.reset().unwrap();
+    let _ = fs::remove_dir_all(dir);
+}
+
+/// Records exported to newline-delimited JSON are imported back unchanged.
+pub fn export_json<T: StorageTrait>() {
+    let sm = T::new_test_sm();
+    let tid = TransactionId::new();
+    let (cid, imported, missing) = (1, 2, 3);
+    sm.create_table(cid).unwrap();
+    sm.create_table(imported).unwrap();
+    let schema = TableSchema::from_vecs(
+        vec!["id", "price", "day"],
+        vec![DataType::Int, DataType::Decimal(8, 2), DataType::Date],
+    );
+    let day = Date::parse("2024-01-31").unwrap();
+    let tuples: Vec<Tuple> = (0..50)
+        .map(|i| {
+            let price = match i % 4 {
+                0 => Field::Null,
+                _ => Field::DecimalField(Decimal::new(i as i64 * 101, 2)),
+            };
+            Tuple::new(vec![Field::IntField(i), price, Field::DateField(day)])
+        })
+        .collect();
+    let vals = tuples.iter().map(|t| t.to_bytes()).collect();
+    sm.insert_values(cid, vals, tid).unwrap();
+
+    let dir = gen_random_test_sm_dir();
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("export.json").to_string_lossy().to_string();
+    assert_eq!(
+        Ok(tuples.len()),
+        sm.export_json(cid, &schema, path.clone(), tid)
+    );
+    let table = Table::new(String::from("t"), schema.clone());
+    assert_eq!(
+        Ok(tuples.len()),
+        sm.import_json(&table, path.clone(), tid, imported, RowErrorPolicy::Abort)
+    );
+    let fields = |container_id| {
+        let mut rows: Vec<Vec<Field>> = scan(&sm, container_id, tid)
+            .iter()
+            .map(|bytes| Tuple::from_bytes(bytes).field_vals)
+            .collect();
+        rows.sort_by_key(|row| row[0].clone());
+        rows
+    };
+    assert_eq!(fields(cid), fields(imported));
+
+    assert_eq!(
+        Err(CrustyError::ContainerMissing(missing)),
+        sm.export_json(missing, &schema, path, tid)
+    );
+    sm.reset().unwrap();
+    let _ = fs::remove_dir_all(dir);
+}
+
+/// Generate a `#[test]` for each conformance check against the given storage manager type.
+///
+/// ```ignore
+/// mod conformance {
+///     common::storage_conformance_tests!(heapstore::storage_manager::StorageManager);
+/// }
+/// ```
+#[macro_export]
+macro_rules! storage_conformance_tests {
+    ($sm:ty) => {
+        #[test]
+        fn conformance_insert_get() {
+            $crate::storage_conformance::insert_get::<$sm>();
+        }
+
+        #[test]
+        fn conformance_bulk_load() {
+            $crate::storage_conformance::bulk_load::<$sm>();
+        }
+
+        #[test]
+        fn conformance_delete() {
+            $crate::storage_conformance::delete::<$sm>();
+        }
+
+        #[test]
+        fn conformance_delete_many() {
+            $crate::storage_conformance::delete_many::<$sm>();
+        }
+
+        #[test]
+        fn conformance_update() {
+            $crate::storage_conformance::update::<$sm>();
+        }
+
+        #[test]
+        fn conformance_overwrite() {
+            $crate::storage_conformance::overwrite::<$sm>();
+        }
+
+        #[test]
+        fn conformance_iterator_complete() {
+            $crate::storage_conformance::iterator_complete::<$sm>();
+        }
+
+        #[test]
+        fn conformance_sample() {
+            $crate::storage_conformance::sample::<$sm>();
+        }
+
+        #[test]
+        fn conformance_container_lifecycle() {
+            $crate::storage_conformance::container_lifecycle::<$sm>();
+        }
+
+        #[test]
+        fn conformance_dependencies() {
+            $crate::storage_conformance::dependencies::<$sm>();
+        }
+
+        #[test]
+        fn conformance_reset() {
+            $crate::storage_conformance::reset::<$sm>();
+        }
+
+        #[test]
+        fn conformance_shutdown_startup() {
+            $crate::storage_conformance::shutdown_startup::<$sm>();
+        }
+
+        #[test]
+        fn conformance_databases() {
+            $crate::storage_conformance::databases::<$sm>();
+        }
+
+        #[test]
+        fn conformance_bloom_filter() {
+            $crate::storage_conformance::bloom_filter::<$sm>();
+        }
+
+        #[test]
+        fn conformance_temp_containers() {
+            $crate::storage_conformance::temp_containers::<$sm>();
+        }
+
+        #[test]
+        fn conformance_errors() {
+            $crate::storage_conformance::errors::<$sm>();
+        }
+
+        #[test]
+        fn conformance_export_csv() {
+            $crate::storage_conformance::export_csv::<$sm>();
+        }
+
+        #[test]
+        fn conformance_export_json() {
+            $crate::storage_conformance::export_json::<$sm>();
+        }
+    };
+}
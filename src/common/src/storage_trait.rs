@@ -1,6 +1,17 @@
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::csv_export::CsvExportOptions;
+use crate::csv_import::{CsvImportOptions, CsvImportReport};
+use crate::ids::SegmentId;
+use crate::metrics::{Metrics, MetricsRegistry};
+use crate::ndjson::RowErrorPolicy;
 use crate::prelude::*;
+use crate::statistics::ModificationCounters;
+use crate::PAGE_SIZE;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 // TODO: What does ContainerId add as a type? If nothing, then make it u16 and make it easier for clients of
 // TODO: storage managers to use them
@@ -24,26 +35,46 @@ pub trait StorageTrait {
     /// Insert some bytes into a container for a particular value (e.g. record).
     /// Any validation will be assumed to happen before.
     /// Returns the value id associated with the stored value.
+    /// Error if the container does not exist or the value does not fit in a page.
     fn insert_value(
         &self,
         container_id: ContainerId,
         value: Vec<u8>,
         tid: TransactionId,
-    ) -> ValueId;
+    ) -> Result<ValueId, CrustyError>;
 
     /// Insert some bytes into a container for vector of values (e.g. record).
     /// Any validation will be assumed to happen before.
     /// Returns a vector of value ids associated with the stored values.
+    /// Error on the first value that cannot be inserted; the values before it stay inserted.
     fn insert_values(
         &self,
         container_id: ContainerId,
         values: Vec<Vec<u8>>,
         tid: TransactionId,
-    ) -> Vec<ValueId>;
+    ) -> Result<Vec<ValueId>, CrustyError>;
+
+    /// Loads many values into a container at once, for initial loads. Unlike
+    /// `insert_values`, the values are not placed in the free space of existing pages but
+    /// packed into new pages after them, and the load is logged as one change instead of
+    /// one per value. Returns the value ids of the loaded values, in order.
+    /// Error if the container does not exist or a value does not fit in a page; nothing is
+    /// loaded then.
+    fn bulk_load(
+        &self,
+        container_id: ContainerId,
+        values: impl Iterator<Item = Vec<u8>>,
+        tid: TransactionId,
+    ) -> Result<Vec<ValueId>, CrustyError>;
 
     /// Delete the data for a value. If the valueID is not found it returns Ok() still.
     fn delete_value(&self, id: ValueId, tid: TransactionId) -> Result<(), CrustyError>;
 
+    /// Delete the data for many values, e.g. the records matching the predicate of a
+    /// DELETE. Values that are not found are ignored, as with `delete_value`.
+    /// Error on the first value that cannot be deleted; values deleted before it stay deleted.
+    fn delete_values(&self, ids: Vec<ValueId>, tid: TransactionId) -> Result<(), CrustyError>;
+
     /// Updates a value. Returns record ID on update (which may have changed). Error on failure
     /// Any process that needs to determine if a value changed will need to compare the return valueId against
     /// the sent value.
@@ -54,16 +85,29 @@ pub trait StorageTrait {
         tid: TransactionId,
     ) -> Result<ValueId, CrustyError>;
 
+    /// Replaces the bytes of a value with as many new bytes, keeping its value id, so
+    /// structures that refer to values by id can change them in place. The value is not
+    /// versioned: every reader sees the new bytes. Error if the value does not exist or the
+    /// lengths differ.
+    fn overwrite_value(
+        &self,
+        value: Vec<u8>,
+        id: ValueId,
+        tid: TransactionId,
+    ) -> Result<(), CrustyError>;
+
     /// Create a new container to be stored.
     /// fn create_container(&self, name: String) -> ContainerId;
     /// Creates a new container object.
     /// For this milestone you will not need to utilize
-    /// the container_config, name, container_type, or dependencies
+    /// the container_config, name, or container_type
     ///
     ///
     /// # Arguments
     ///
     /// * `container_id` - Id of container to add delta to.
+    /// * `dependencies` - Containers the new container depends on, such as the parent tables
+    ///   of its foreign keys. They must exist, and cannot be removed while it exists.
     fn create_container(
         &self,
         container_id: ContainerId,
@@ -74,10 +118,38 @@ pub trait StorageTrait {
 
     fn create_table(&self, container_id: ContainerId) -> Result<(), CrustyError>;
 
+    /// Creates an empty container for scratch space of a transaction, such as the tuples an
+    /// operator spills, and returns its id. The id is in the range reserved for temporary
+    /// containers. The container is removed by `remove_container`, or when the transaction
+    /// finishes, and is never persisted.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - Transaction the container belongs to.
+    fn create_temp_container(&self, tid: TransactionId) -> Result<ContainerId, CrustyError>;
+
     /// Remove the container and all stored values in the container.
     /// If the container is persisted remove the underlying files
+    /// Error if another container depends on it.
     fn remove_container(&self, container_id: ContainerId) -> Result<(), CrustyError>;
 
+    /// Creates a database: a namespace with its own container ids, persisted in the
+    /// directory of that name in the `DATABASES_DIR` directory of the storage path.
+    /// Error if the name is not valid (see `validate_database_name`) or the database exists.
+    fn create_database(&self, name: &str) -> Result<(), CrustyError>;
+
+    /// Removes a database and all its containers. Storage managers returned for it by
+    /// `use_database` are left without containers. Error if the database does not exist.
+    fn drop_database(&self, name: &str) -> Result<(), CrustyError>;
+
+    /// Returns the storage manager of a database, which stores the containers of the
+    /// database apart from the ones of this storage manager and of other databases. It is
+    /// shut down and reset with this storage manager. Error if the database does not exist.
+    fn use_database(&self, name: &str) -> Result<Arc<Self>, CrustyError>;
+
+    /// Returns the names of the databases, in order.
+    fn list_databases(&self) -> Vec<String>;
+
     /// Get an iterator that returns all valid records
     fn get_iterator(
         &self,
@@ -86,6 +158,18 @@ pub trait StorageTrait {
         perm: Permissions,
     ) -> Self::ValIterator;
 
+    /// Get an iterator over a random sample of the records of a container.
+    /// Each page is included with probability `fraction`, independently of the other pages,
+    /// and only included pages are read. A storage manager without pages samples fixed-size
+    /// runs of values instead. The same seed samples the same pages of an unchanged container.
+    fn sample_iterator(
+        &self,
+        container_id: ContainerId,
+        fraction: f64,
+        seed: u64,
+        tid: TransactionId,
+    ) -> Self::ValIterator;
+
     /// Get the data for a particular ValueId. Error if does not exists
     fn get_value(
         &self,
@@ -94,9 +178,50 @@ pub trait StorageTrait {
         perm: Permissions,
     ) -> Result<Vec<u8>, CrustyError>;
 
+    /// Create a bloom filter over a column of the tuples of a container, replacing its
+    /// filter if it has one, and add the keys of the values already in it. The filter is
+    /// kept up to date on inserts, deletes and updates, dropped with the container, and
+    /// persisted by shutdown.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of tuples to filter.
+    /// * `key_column` - Index of the key column in the tuples.
+    /// * `expected_keys` - Number of keys the filter is sized for.
+    fn create_bloom_filter(
+        &self,
+        container_id: ContainerId,
+        key_column: usize,
+        expected_keys: usize,
+    ) -> Result<(), CrustyError>;
+
+    /// Returns the key column of the bloom filter of a container, if it has one.
+    fn bloom_filter_column(&self, container_id: ContainerId) -> Option<usize>;
+
+    /// Returns false if no value of the container has the key in the key column of its
+    /// bloom filter. False positives are possible, so true only means the key may be there.
+    /// Always true for a container without a bloom filter.
+    fn may_contain(&self, container_id: ContainerId, key: &Field) -> bool;
+
     /// Notify the storage manager that the transaction is finished so that any held resources can be released.
+    /// The temporary containers of the transaction are removed.
     fn transaction_finished(&self, tid: TransactionId);
 
+    /// Commit the transaction, making its changes durable and visible to other transactions,
+    /// and release its resources as `transaction_finished` does. Storage managers that can
+    /// refuse a commit, e.g. on a serialization failure, return the error and abort it.
+    fn transaction_committed(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        self.transaction_finished(tid);
+        Ok(())
+    }
+
+    /// Abort the transaction, discarding its changes, and release its resources as
+    /// `transaction_finished` does. Storage managers that do not keep versions of values
+    /// cannot discard the changes and only release the resources.
+    fn transaction_aborted(&self, tid: TransactionId) {
+        self.transaction_finished(tid);
+    }
+
     /// Reset all state associated the storage manager.
     /// Deletes all tables and stored items
     fn reset(&self) -> Result<(), CrustyError>;
@@ -104,6 +229,17 @@ pub trait StorageTrait {
     /// for testing. clear anything cache in memory for performance
     fn clear_cache(&self);
 
+    /// Returns the registry the operators of executed plans record their metrics in.
+    fn metrics(&self) -> &Arc<MetricsRegistry>;
+
+    /// Returns the counts of the values inserted, deleted and updated in each container,
+    /// which tell when the statistics of a table are stale. See `statistics::refresh`.
+    fn modifications(&self) -> &ModificationCounters;
+
+    /// Returns the page counters of the files of the containers and the metrics of the
+    /// operators recorded since the storage manager was created or reset.
+    fn get_metrics(&self) -> Metrics;
+
     /// Call shutdown to persist state or clean up. Will be called by drop in addition to explicitly.
     /// Shutdown also needs to persist the state of the storage trait to disk, allowing the storage
     /// to retain state after the db is rerun.
@@ -117,11 +253,203 @@ pub trait StorageTrait {
     /// storage path passed in during instantiation.
     fn shutdown(&self);
 
+    /// Imports the records of a csv file into a container, parsing the fields as the types of
+    /// the columns of the table. Records that cannot be parsed are handled by the error policy
+    /// of the options. Returns the numbers of records imported and skipped.
+    /// Error if the file cannot be read or a record cannot be inserted.
     fn import_csv(
         &self,
         table: &Table,
         path: String,
         tid: TransactionId,
         container_id: ContainerId,
-    ) -> Result<(), CrustyError>;
+        options: &CsvImportOptions,
+    ) -> Result<CsvImportReport, CrustyError>;
+
+    /// Writes the records of a container to a csv file, one record at a time as they are
+    /// scanned, and returns the number of records written.
+    /// Error if the container does not exist or the file cannot be written.
+    fn export_csv(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        options: &CsvExportOptions,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError>;
+
+    /// Imports the records of a newline-delimited JSON file into a container, coercing the
+    /// values to the types of the columns of the table, and returns the number of records
+    /// imported. Lines that are not records of the table are handled by `on_error`.
+    /// Error if the file cannot be read or a record cannot be inserted.
+    fn import_json(
+        &self,
+        table: &Table,
+        path: String,
+        tid: TransactionId,
+        container_id: ContainerId,
+        on_error: RowErrorPolicy,
+    ) -> Result<usize, CrustyError>;
+
+    /// Writes the records of a container to a newline-delimited JSON file, one record at a
+    /// time as they are scanned, and returns the number of records written.
+    /// Error if the container does not exist or the file cannot be written.
+    fn export_json(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError>;
+}
+
+/// Directory of the storage path of a storage manager that holds the storage paths of its
+/// databases, see `StorageTrait::create_database`.
+pub const DATABASES_DIR: &str = "databases";
+
+/// Errors if a database cannot have name: it names the directory of the database, so it
+/// must be non-empty and hold only ASCII letters, digits, `_` and `-`.
+pub fn validate_database_name(name: &str) -> Result<(), CrustyError> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(CrustyError::ValidationError(format!(
+            "Invalid database name \"{}\"",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Options of a container, given when it is created. Storage managers that do not support an
+/// option reject a config that sets it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerConfig {
+    /// Percentage of a page inserts fill before moving on to the next page, leaving the rest
+    /// for values to grow into. 100 fills pages completely.
+    pub fill_factor: u8,
+    /// Whether pages are compressed on disk, if the storage manager was built with a
+    /// compression codec.
+    pub compression: bool,
+    /// Most pages the container may have. Changes that need a page past it fail with
+    /// `CrustyError::QuotaExceeded`.
+    pub max_pages: Option<PageId>,
+    /// Most bytes the pages of the container may take, counting `PAGE_SIZE` bytes per page
+    /// whether or not pages are compressed on disk. Enforced like `max_pages`.
+    pub max_bytes: Option<u64>,
+    /// Whether the pages of the container are kept in memory instead of in a file, e.g. for
+    /// temporary tables that fit in memory. The container is not persisted by shutdown.
+    pub in_memory: bool,
+    /// How long the values of the container live once inserted or updated, e.g. for
+    /// session or cache tables. Expired values are no longer read, and their space is
+    /// reclaimed by vacuum. Values are stored with their insertion time, so a container
+    /// cannot gain or lose a time-to-live once created, only change it.
+    pub ttl: Option<Duration>,
+    /// How long deleted values are retained, None if they are removed when deleted. Deleted
+    /// values are then kept as tombstones that readers skip, can be restored with the
+    /// storage manager's undelete until the window passes, and are reclaimed by vacuum
+    /// after it. Values are stored with room for their deletion time, so a container cannot
+    /// gain or lose a retention window once created, only change it.
+    pub retention: Option<Duration>,
+    /// Most pages of each file of the container, None to keep it in a single file. Pages
+    /// past it go to further segment files, at most `SegmentId::MAX` of them after the
+    /// first, so the file size stays under a filesystem's limit, segments are read and
+    /// written independently, and old segments can be truncated on their own. The ids of
+    /// the values of the container carry the segment of their page. A container cannot be
+    /// split into segments or change their size once created.
+    pub segment_pages: Option<PageId>,
+}
+
+impl Default for ContainerConfig {
+    fn default() -> Self {
+        ContainerConfig {
+            fill_factor: 100,
+            compression: true,
+            max_pages: None,
+            max_bytes: None,
+            in_memory: false,
+            ttl: None,
+            retention: None,
+            segment_pages: None,
+        }
+    }
+}
+
+impl ContainerConfig {
+    /// Returns the most pages the container may have under `max_pages`, `max_bytes` and the
+    /// number of segments `segment_pages` allows, None if none of them is set.
+    pub fn page_limit(&self) -> Option<PageId> {
+        let from_bytes = self
+            .max_bytes
+            .map(|max_bytes| (max_bytes / PAGE_SIZE as u64).min(PageId::MAX as u64) as PageId);
+        let from_segments = self.segment_pages.map(|pages| {
+            (pages as u64 * (SegmentId::MAX as u64 + 1)).min(PageId::MAX as u64) as PageId
+        });
+        [self.max_pages, from_bytes, from_segments]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
+    /// Returns the segment of a page of the container, None if it is kept in a single file.
+    pub fn segment_of(&self, page_id: PageId) -> Option<SegmentId> {
+        self.segment_pages
+            .map(|pages| (page_id / pages).min(SegmentId::MAX as PageId) as SegmentId)
+    }
+
+    /// Errors if an option is out of range.
+    pub fn validate(&self) -> Result<(), CrustyError> {
+        if !(1..=100).contains(&self.fill_factor) {
+            return Err(CrustyError::ValidationError(format!(
+                "Fill factor must be between 1 and 100, not {}",
+                self.fill_factor
+            )));
+        }
+        if self.ttl == Some(Duration::ZERO) {
+            return Err(CrustyError::ValidationError(String::from(
+                "Time-to-live cannot be 0",
+            )));
+        }
+        if self.retention == Some(Duration::ZERO) {
+            return Err(CrustyError::ValidationError(String::from(
+                "Retention window cannot be 0",
+            )));
+        }
+        if self.segment_pages == Some(0) {
+            return Err(CrustyError::ValidationError(String::from(
+                "Segment size cannot be 0",
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Bernoulli page selection for `StorageTrait::sample_iterator`.
+/// Pages must be offered to `select` in page order for a seed to give a repeatable sample.
+pub struct PageSampler {
+    rng: StdRng,
+    fraction: f64,
+}
+
+impl PageSampler {
+    /// Create a sampler that selects pages with probability `fraction`, clamped to [0, 1].
+    pub fn new(fraction: f64, seed: u64) -> Self {
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        PageSampler {
+            rng: StdRng::seed_from_u64(seed),
+            fraction,
+        }
+    }
+
+    /// Decide whether the next page is part of the sample.
+    pub fn select(&mut self) -> bool {
+        self.rng.gen_bool(self.fraction)
+    }
 }
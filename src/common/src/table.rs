@@ -1,12 +1,69 @@
-use crate::TableSchema;
+use crate::foreign_key::ForeignKey;
+use crate::ids::ContainerId;
+use crate::partition::Partitioning;
+use crate::{Attribute, Field, TableSchema, Tuple};
 
 /// Table implementation.
+///
+/// Columns added with ALTER TABLE are appended to the schema without rewriting the records
+/// stored before. A record therefore holds the columns of the schema version it was written
+/// under, and the records of older versions are padded with the defaults of the columns
+/// added since when they are read.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Table {
     /// Table name.
     pub name: String,
     /// Table schema.
     pub schema: TableSchema,
+    /// Version of the schema, incremented by every change to it.
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Columns added after the table was created, in schema order.
+    #[serde(default)]
+    pub added_columns: Vec<AddedColumn>,
+    /// Foreign keys of the table, referring to other tables.
+    #[serde(default)]
+    pub foreign_keys: Vec<ForeignKey>,
+    /// How the records of the table are stored.
+    #[serde(default)]
+    pub layout: TableLayout,
+}
+
+/// How the records of a table are stored.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum TableLayout {
+    /// Each record is a value of the table's container.
+    #[default]
+    Row,
+    /// Row groups in the table's container and column chunks in one container per
+    /// column, in schema order. See `columnar`.
+    Column(Vec<ContainerId>),
+    /// Each record is a value of the container of its partition. See `partition`.
+    Partitioned(Partitioning),
+}
+
+impl TableLayout {
+    /// Returns the containers holding the records of a table: its partitions, or its own
+    /// container.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the table.
+    pub fn record_containers(&self, container_id: ContainerId) -> Vec<ContainerId> {
+        match self {
+            TableLayout::Partitioned(partitioning) => partitioning.partitions.clone(),
+            _ => vec![container_id],
+        }
+    }
+}
+
+/// A column added to a table after it was created.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AddedColumn {
+    /// Schema version that added the column.
+    pub version: u32,
+    /// Value of the column in the records written before it was added.
+    pub default: Field,
 }
 
 impl Table {
@@ -17,6 +74,101 @@ impl Table {
     /// * `name` - Name of table.
     /// * `file` - HeapFile of the table.
     pub fn new(name: String, schema: TableSchema) -> Self {
-        Table { name, schema }
+        Table {
+            name,
+            schema,
+            schema_version: 0,
+            added_columns: Vec::new(),
+            foreign_keys: Vec::new(),
+            layout: TableLayout::Row,
+        }
+    }
+
+    /// Appends a column to the schema and starts a new schema version.
+    ///
+    /// # Arguments
+    ///
+    /// * `attribute` - The new column.
+    /// * `default` - Value of the column in the records already stored.
+    pub fn add_column(&mut self, attribute: Attribute, default: Field) {
+        let mut attributes: Vec<Attribute> = self.schema.attributes().cloned().collect();
+        attributes.push(attribute);
+        self.schema = TableSchema::new(attributes);
+        self.schema_version += 1;
+        self.added_columns.push(AddedColumn {
+            version: self.schema_version,
+            default,
+        });
+    }
+
+    /// Pads a record written under an older schema version with the defaults of the
+    /// columns added since.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Record of the table.
+    pub fn pad(&self, tuple: &mut Tuple) {
+        let first_added = self.schema.size() - self.added_columns.len();
+        for i in tuple.size()..self.schema.size() {
+            let default = match i.checked_sub(first_added) {
+                Some(j) => self.added_columns[j].default.clone(),
+                None => Field::Null,
+            };
+            tuple.field_vals.push(default);
+        }
+    }
+
+    /// Deserializes a record of the table, padded to the current schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Stored bytes of the record.
+    pub fn read_tuple(&self, bytes: &[u8]) -> Tuple {
+        let mut tuple = Tuple::from_bytes(bytes);
+        self.pad(&mut tuple);
+        tuple
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::*;
+    use crate::DataType;
+
+    #[test]
+    fn test_add_column_pads_old_records() {
+        let mut table = Table::new("t".to_string(), get_int_table_schema(2));
+        let old = int_vec_to_tuple(vec![1, 2]).to_bytes();
+        table.add_column(
+            Attribute::new("c".to_string(), DataType::Int),
+            Field::IntField(7),
+        );
+        table.add_column(
+            Attribute::new("d".to_string(), DataType::String),
+            Field::Null,
+        );
+        let newer = int_vec_to_tuple(vec![3, 4, 5]).to_bytes();
+
+        assert_eq!(2, table.schema_version);
+        assert_eq!(4, table.schema.size());
+        assert_eq!(
+            vec![
+                Field::IntField(1),
+                Field::IntField(2),
+                Field::IntField(7),
+                Field::Null
+            ],
+            table.read_tuple(&old).field_vals
+        );
+        assert_eq!(
+            vec![
+                Field::IntField(3),
+                Field::IntField(4),
+                Field::IntField(5),
+                Field::Null
+            ],
+            table.read_tuple(&newer).field_vals
+        );
     }
 }
@@ -0,0 +1,73 @@
+//! Temporary containers that operators spill to.
+//!
+//! Temporary containers take their ids from a range reserved above the ids the catalog hands
+//! out, and belong to the transaction that created them. An operator removes its containers
+//! when it is done with them, and a storage manager removes the ones left of a transaction
+//! when it is notified that the transaction finished. They are never persisted.
+
+use crate::ids::{ContainerId, TransactionId};
+use crate::CrustyError;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// First id of the range reserved for temporary containers.
+pub const TEMP_CONTAINER_START: ContainerId = 0xF000;
+
+/// Returns true if the id is in the range reserved for temporary containers.
+pub fn is_temp_container(container_id: ContainerId) -> bool {
+    container_id >= TEMP_CONTAINER_START
+}
+
+/// The temporary containers of a storage manager and the transactions they belong to.
+#[derive(Debug, Default)]
+pub struct TempContainers {
+    owners: RwLock<HashMap<ContainerId, TransactionId>>,
+}
+
+impl TempContainers {
+    /// Reserves an unused temporary id for a transaction. The storage manager creates the
+    /// container. Error if all the temporary ids are in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `tid` - Transaction the container belongs to.
+    pub fn allocate(&self, tid: TransactionId) -> Result<ContainerId, CrustyError> {
+        let mut owners = self.owners.write().unwrap();
+        let container_id = (TEMP_CONTAINER_START..=ContainerId::MAX)
+            .find(|c| !owners.contains_key(c))
+            .ok_or_else(|| {
+                CrustyError::ExecutionError(String::from("No temporary container ids left"))
+            })?;
+        owners.insert(container_id, tid);
+        Ok(container_id)
+    }
+
+    /// Forgets a container once it is removed, so its id can be reused.
+    pub fn release(&self, container_id: ContainerId) {
+        self.owners.write().unwrap().remove(&container_id);
+    }
+
+    /// Returns the containers of a transaction, which must be removed, and forgets them.
+    pub fn take_transaction(&self, tid: TransactionId) -> Vec<ContainerId> {
+        let mut owners = self.owners.write().unwrap();
+        let taken: Vec<ContainerId> = owners
+            .iter()
+            .filter(|(_, owner)| **owner == tid)
+            .map(|(c, _)| *c)
+            .collect();
+        for container_id in &taken {
+            owners.remove(container_id);
+        }
+        taken
+    }
+
+    /// Returns all the containers, which must be removed, and forgets them.
+    pub fn take_all(&self) -> Vec<ContainerId> {
+        self.owners
+            .write()
+            .unwrap()
+            .drain()
+            .map(|(c, _)| c)
+            .collect()
+    }
+}
@@ -1,8 +1,18 @@
 use crate::{ids::TupleAssignments, logical_plan::Predicate, prelude::*};
 use std::path::Path;
 
+/// How much of the changes of concurrent transactions a transaction can see.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IsolationLevel {
+    /// Each read sees the changes committed before it, so reading twice can return
+    /// different results.
     ReadCommitted,
+    /// Reads see the changes committed before the transaction started.
+    RepeatableRead,
+    /// Like RepeatableRead, and the transaction fails to commit if its reads and writes and
+    /// the ones of concurrent transactions could not have happened one transaction at a
+    /// time, so transactions act as if run one at a time.
+    Serializable,
 }
 
 pub trait TransactionManagerTrait {
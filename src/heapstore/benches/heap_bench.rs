@@ -2,6 +2,13 @@ use criterion::{criterion_group, criterion_main};
 
 mod page_bench;
 mod sm_bench;
+mod wal_bench;
 
-criterion_group!(benches, page_bench::page_benchmark, sm_bench::sm_ins_bench);
+criterion_group!(
+    benches,
+    page_bench::page_benchmark,
+    sm_bench::sm_ins_bench,
+    sm_bench::sm_text_bench,
+    wal_bench::group_commit_bench
+);
 criterion_main!(benches);
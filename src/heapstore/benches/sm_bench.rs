@@ -1,6 +1,7 @@
-use criterion::{black_box, Criterion};
+use common::ids::{Permissions, TransactionId};
 use common::storage_trait::StorageTrait;
 use common::testutil::get_random_vec_of_byte_vec;
+use criterion::{black_box, BatchSize, Criterion};
 use heapstore::storage_manager::StorageManager;
 use heapstore::testutil::bench_sm_insert;
 
@@ -13,3 +14,34 @@ pub fn sm_ins_bench(c: &mut Criterion) {
         b.iter(|| bench_sm_insert(&sm, black_box(&to_insert)))
     });
 }
+
+/// Inserts and scans text values, which unlike random bytes compress well.
+/// Compare runs with and without the `lz4` or `snappy` feature.
+pub fn sm_text_bench(c: &mut Criterion) {
+    let to_insert: Vec<Vec<u8>> = (0..1000)
+        .map(|i| format!("{:08},customer#{:08},BUILDING,regular deposits", i, i % 7).into_bytes())
+        .collect();
+    let sm = StorageManager::new_test_sm();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    c.bench_function("sm insert 1k text", |b| {
+        b.iter_batched(
+            || {
+                sm.reset().unwrap();
+                sm.create_table(cid).unwrap();
+            },
+            |_| bench_sm_insert(&sm, black_box(&to_insert)),
+            BatchSize::PerIteration,
+        )
+    });
+
+    let sm = StorageManager::new_test_sm();
+    sm.create_table(cid).unwrap();
+    bench_sm_insert(&sm, &to_insert);
+    c.bench_function("sm scan 1k text", |b| {
+        b.iter(|| {
+            sm.get_iterator(cid, TransactionId::new(), Permissions::ReadOnly)
+                .count()
+        })
+    });
+}
@@ -0,0 +1,58 @@
+use common::ids::TransactionId;
+use common::storage_trait::StorageTrait;
+use criterion::{BatchSize, BenchmarkId, Criterion};
+use heapstore::storage_manager::StorageManager;
+use heapstore::wal::WalConfig;
+use std::thread;
+use std::time::Duration;
+
+const THREADS: usize = 8;
+const COMMITS_PER_THREAD: usize = 8;
+
+/// Commits small transactions from several threads at once, with and without a group commit
+/// window. Each commit syncs the WAL, so without batching the syncs dominate.
+pub fn group_commit_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("commit 64 txns on 8 threads");
+    for window in [
+        Duration::ZERO,
+        Duration::from_micros(200),
+        Duration::from_millis(1),
+    ] {
+        let config = WalConfig {
+            group_commit_window: window,
+            ..Default::default()
+        };
+        let sm = StorageManager::new_test_sm()
+            .with_wal(config)
+            .unwrap()
+            .with_mvcc();
+        let cid = 1;
+        group.bench_with_input(
+            BenchmarkId::new("window", format!("{:?}", window)),
+            &sm,
+            |b, sm| {
+                b.iter_batched(
+                    || {
+                        sm.reset().unwrap();
+                        sm.create_table(cid).unwrap();
+                    },
+                    |_| {
+                        thread::scope(|s| {
+                            for _ in 0..THREADS {
+                                s.spawn(|| {
+                                    for _ in 0..COMMITS_PER_THREAD {
+                                        let tid = TransactionId::new();
+                                        sm.insert_value(cid, vec![7; 40], tid).unwrap();
+                                        sm.commit_transaction(tid).unwrap();
+                                    }
+                                });
+                            }
+                        })
+                    },
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+    group.finish();
+}
@@ -0,0 +1,10 @@
+//! Parses arbitrary bytes as a heap file page, see `heapstore::fuzz::page_from_bytes`.
+//! Run with `cargo fuzz run page_from_bytes` from `src/heapstore`. Seeding the corpus with
+//! pages of a heap file gets past the length check sooner.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    heapstore::fuzz::page_from_bytes(data);
+});
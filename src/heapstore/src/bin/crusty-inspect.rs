@@ -0,0 +1,247 @@
+//! Inspects the storage directory of a heapstore storage manager without a server, e.g. to
+//! debug a corrupted database. See `heapstore::inspect`.
+//!
+//! ```text
+//! crusty-inspect <DIR> containers
+//! crusty-inspect <DIR> pages <CONTAINER>
+//! crusty-inspect <DIR> page <CONTAINER> <PAGE> [--json] [--hex]
+//! crusty-inspect <DIR> verify [CONTAINER]
+//! crusty-inspect <DIR> slot <CONTAINER> <PAGE> <SLOT> [--schema FILE] [--decode] [--mvcc]
+//! ```
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use common::{CrustyError, TableSchema};
+use heapstore::inspect::{self, Inspector};
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+fn main() {
+    env_logger::init();
+
+    let container_arg = || {
+        Arg::with_name("container")
+            .value_name("CONTAINER")
+            .help("Container id")
+            .required(true)
+    };
+    let page_arg = || {
+        Arg::with_name("page")
+            .value_name("PAGE")
+            .help("Position of the page in the heap file")
+            .required(true)
+    };
+    let matches = App::new("crusty-inspect")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Inspects the heap files of a storage directory without a server")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("dir")
+                .value_name("DIR")
+                .help("Storage directory")
+                .required(true),
+        )
+        .subcommand(SubCommand::with_name("containers").about("Lists the containers"))
+        .subcommand(
+            SubCommand::with_name("pages")
+                .about("Prints the header of every page of a container")
+                .arg(container_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("page")
+                .about("Prints the header and slot table of a page")
+                .arg(container_arg())
+                .arg(page_arg())
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the header and slot table as json"),
+                )
+                .arg(
+                    Arg::with_name("hex")
+                        .long("hex")
+                        .help("Print the bytes of the page, even if it is corrupted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("Checks the pages of a container, or of every container")
+                .arg(container_arg().required(false)),
+        )
+        .subcommand(
+            SubCommand::with_name("slot")
+                .about("Prints the value in a slot of a page as hex, or decoded as a tuple")
+                .arg(container_arg())
+                .arg(page_arg())
+                .arg(
+                    Arg::with_name("slot")
+                        .value_name("SLOT")
+                        .help("Slot id")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("schema")
+                        .long("schema")
+                        .value_name("FILE")
+                        .help("Decode the value with the schema in FILE, a json list of attributes")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("decode")
+                        .long("decode")
+                        .help("Decode the value as a tuple without a schema"),
+                )
+                .arg(Arg::with_name("mvcc").long("mvcc").help(
+                    "The value has a record header: stored with MVCC or in a container \
+                             with a time-to-live or a retention window",
+                )),
+        )
+        .get_matches();
+
+    let dir = Path::new(matches.value_of("dir").unwrap());
+    let result = Inspector::open(dir).and_then(|inspector| match matches.subcommand() {
+        ("containers", _) => containers(&inspector),
+        ("pages", Some(args)) => pages(&inspector, args),
+        ("page", Some(args)) => page(&inspector, args),
+        ("verify", Some(args)) => verify(&inspector, args),
+        ("slot", Some(args)) => slot(&inspector, args),
+        _ => unreachable!(),
+    });
+    match result {
+        Ok(true) => {}
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Parses the value of an argument, exiting if it is invalid.
+fn parse<T: FromStr>(args: &ArgMatches, name: &str) -> T {
+    let value = args.value_of(name).unwrap();
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Invalid {}: {}", name, value);
+        process::exit(2);
+    })
+}
+
+fn containers(inspector: &Inspector) -> Result<bool, CrustyError> {
+    println!(
+        "{:>9} {:>6} {:>12}  depends on",
+        "container", "pages", "bytes"
+    );
+    for c in inspector.containers()? {
+        println!(
+            "{:>9} {:>6} {:>12}  {:?}",
+            c.container_id, c.num_pages, c.file_size, c.dependencies
+        );
+    }
+    Ok(true)
+}
+
+fn pages(inspector: &Inspector, args: &ArgMatches) -> Result<bool, CrustyError> {
+    let container_id = parse(args, "container");
+    let num_pages = inspector
+        .containers()?
+        .into_iter()
+        .find(|c| c.container_id == container_id)
+        .ok_or(CrustyError::ContainerMissing(container_id))?
+        .num_pages;
+    println!(
+        "{:>6} {:>7} {:>6} {:>6} {:>5} {:>7} {:>6} {:>6} {:>6}",
+        "page", "page id", "slots", "live", "open", "values", "free", "wasted", "frag"
+    );
+    for page in 0..num_pages {
+        match inspector.describe_page(container_id, page) {
+            Ok(d) => println!(
+                "{:>6} {:>7} {:>6} {:>6} {:>5} {:>7} {:>6} {:>6} {:>5.1}%",
+                page,
+                d.page_id,
+                d.slots.len(),
+                d.live_count(),
+                d.open_slot
+                    .map_or_else(|| String::from("-"), |slot| slot.to_string()),
+                d.value_size,
+                d.free_space,
+                d.wasted_space,
+                d.fragmentation * 100.0
+            ),
+            Err(e) => println!("{:>6} corrupted: {}", page, e),
+        }
+    }
+    Ok(true)
+}
+
+fn page(inspector: &Inspector, args: &ArgMatches) -> Result<bool, CrustyError> {
+    let container_id = parse(args, "container");
+    let page = parse(args, "page");
+    if args.is_present("hex") {
+        print!(
+            "{}",
+            inspect::hex_dump(&inspector.page_bytes(container_id, page)?)
+        );
+        return Ok(true);
+    }
+    let d = inspector.describe_page(container_id, page)?;
+    if args.is_present("json") {
+        println!("{}", serde_json::to_string_pretty(&d.to_json()).unwrap());
+    } else {
+        print!("{}", d);
+    }
+    Ok(true)
+}
+
+/// Prints the corrupted pages. Returns false if there are any.
+fn verify(inspector: &Inspector, args: &ArgMatches) -> Result<bool, CrustyError> {
+    let container_ids = match args.value_of("container") {
+        Some(_) => vec![parse(args, "container")],
+        None => inspector
+            .containers()?
+            .iter()
+            .map(|c| c.container_id)
+            .collect(),
+    };
+    let mut ok = true;
+    for container_id in container_ids {
+        let problems = inspector.verify(container_id)?;
+        for p in &problems {
+            println!(
+                "container {} page {}: {}",
+                p.container_id, p.page, p.message
+            );
+        }
+        ok &= problems.is_empty();
+    }
+    if ok {
+        println!("No corrupted pages");
+    }
+    Ok(ok)
+}
+
+fn slot(inspector: &Inspector, args: &ArgMatches) -> Result<bool, CrustyError> {
+    let value = inspector.slot(
+        parse(args, "container"),
+        parse(args, "page"),
+        parse(args, "slot"),
+    )?;
+    let schema: Option<TableSchema> = match args.value_of("schema") {
+        Some(path) => {
+            let json = fs::read(path).map_err(|e| CrustyError::io_with_path(Path::new(path), e))?;
+            Some(serde_json::from_slice(&json).map_err(|e| {
+                CrustyError::CrustyError(format!("Cannot read schema {}: {}", path, e))
+            })?)
+        }
+        None => None,
+    };
+    if schema.is_some() || args.is_present("decode") {
+        print!(
+            "{}",
+            inspect::decode_value(&value, schema.as_ref(), args.is_present("mvcc"))?
+        );
+    } else {
+        print!("{}", inspect::hex_dump(&value));
+    }
+    Ok(true)
+}
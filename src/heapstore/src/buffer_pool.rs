@@ -0,0 +1,714 @@
+//! Buffer pool of a storage manager: the pages of its heap files most recently read with
+//! `StorageManager::get_page` or by the iterators of `get_iterator`, kept in memory so
+//! reading them again does not go to the file.
+//!
+//! A page is returned in a `PageGuard`. A guard taken with `pin` keeps its page pinned in
+//! the pool until it is dropped, so a caller fetching several values from the page, e.g.
+//! an index scan, reads it from the file once. Unpinned pages are evicted once the pool
+//! holds more than its capacity: first the pages only scans read, see `RequestClass`, then
+//! the others in the order of its `EvictionPolicy`. Pinned pages are never evicted: if
+//! every page is pinned, the pool holds more than its capacity until some are unpinned.
+//!
+//! With a cold tier, evicted pages are compressed and kept in memory instead of dropped,
+//! up to a number of pages, and reading one again decompresses it rather than reading the
+//! file. Pages are compressed with the codec of the compression feature, and kept as is
+//! without one. The pool counts its hits, misses and evictions by container, see
+//! `BufferPoolStats`.
+//!
+//! Each frame holds the write sequence number of its page when it was read, and is only
+//! used while the page has not been written since; see `HeapFile::fetch_page`. A guard
+//! keeps the page it was given even if the page is written or evicted meanwhile.
+
+use crate::compression;
+use crate::page::{Page, PageDescription};
+use common::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Number of pages a buffer pool holds by default.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Share of the capacity of a pool, in percent, the protected queue of `TwoQueue` holds.
+const PROTECTED_PERCENT: usize = 75;
+
+/// How a buffer pool picks the unpinned pages to evict.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least recently used first.
+    #[default]
+    Lru,
+    /// 2Q: a page enters a probation queue when it is read, and moves to a protected queue
+    /// when it is used again. Probation pages are evicted first, least recently used first,
+    /// so a scan reading many pages once does not evict the pages used repeatedly. The
+    /// protected queue holds at most three quarters of the capacity; past that, its least
+    /// recently used pages go back to probation.
+    TwoQueue,
+}
+
+/// Counters of the pages of a container in a buffer pool, returned by
+/// `StorageManager::buffer_pool_stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferPoolStats {
+    /// Reads of pages the pool held.
+    pub hits: u64,
+    /// Hits on compressed pages of the cold tier.
+    pub cold_hits: u64,
+    /// Reads of pages the pool did not hold, or held as of an older write.
+    pub misses: u64,
+    /// Pages evicted from the pool, whether or not they were kept in the cold tier.
+    pub evictions: u64,
+    /// Pages the pool holds now.
+    pub resident: usize,
+    /// Pages the cold tier holds now.
+    pub cold: usize,
+}
+
+impl BufferPoolStats {
+    /// Returns the share of reads that were hits, 0 if there were none.
+    pub fn hit_ratio(&self) -> f64 {
+        let reads = self.hits + self.misses;
+        if reads == 0 {
+            0.0
+        } else {
+            self.hits as f64 / reads as f64
+        }
+    }
+}
+
+/// Whether a page is requested by a scan, which reads it once, or by a point read, which
+/// may read it again. Pages read by scans are kept apart and evicted first, so a large scan
+/// does not evict the pages point reads use.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum RequestClass {
+    Point,
+    Scan,
+}
+
+/// A page of a container.
+type FrameKey = (ContainerId, PageId);
+
+/// The queues frames are evicted from, in order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Queue {
+    /// Frames only read by scans, oldest first.
+    Scan,
+    /// Unprotected frames, least recently used first. With `Lru` every frame read by a point
+    /// read is here.
+    Probation,
+    /// Protected frames of `TwoQueue`, least recently used first.
+    Protected,
+}
+
+/// A page held by the pool.
+struct Frame {
+    page: Arc<Page>,
+    /// Write sequence number of the page in its heap file when it was read.
+    lsn: u64,
+    /// Number of guards pinning the page.
+    pins: usize,
+    /// Tick of the last time a point read returned the page, or a scan added it; its key
+    /// in its queue. 0 if it was never returned.
+    last_used: u64,
+    queue: Queue,
+}
+
+/// An evicted page kept compressed in the cold tier.
+struct ColdPage {
+    lsn: u64,
+    codec: u8,
+    payload: Vec<u8>,
+    /// Tick of the last use of the page before it was evicted.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Frames {
+    policy: EvictionPolicy,
+    frames: HashMap<FrameKey, Frame>,
+    /// The frames of each `Queue`, by the tick of their last use.
+    scan: BTreeMap<u64, FrameKey>,
+    lru: BTreeMap<u64, FrameKey>,
+    protected: BTreeMap<u64, FrameKey>,
+    /// Number of compressed pages the cold tier holds, 0 without a cold tier.
+    cold_capacity: usize,
+    cold: HashMap<FrameKey, ColdPage>,
+    /// The cold pages by the tick of their last use, least recently used first.
+    cold_lru: BTreeMap<u64, FrameKey>,
+    stats: HashMap<ContainerId, BufferPoolStats>,
+    /// Incremented every time a page is returned.
+    tick: u64,
+}
+
+impl Frames {
+    fn queue(&mut self, queue: Queue) -> &mut BTreeMap<u64, FrameKey> {
+        match queue {
+            Queue::Scan => &mut self.scan,
+            Queue::Probation => &mut self.lru,
+            Queue::Protected => &mut self.protected,
+        }
+    }
+
+    /// Returns the page of a frame, pinning it if pin. A point read marks the frame used
+    /// now; with `TwoQueue`, a frame it used before is protected. A scan leaves the frame
+    /// where it is.
+    fn touch(
+        &mut self,
+        key: FrameKey,
+        pin: bool,
+        class: RequestClass,
+        capacity: usize,
+    ) -> Arc<Page> {
+        let frame = self.frames.get_mut(&key).unwrap();
+        if pin {
+            frame.pins += 1;
+        }
+        let page = frame.page.clone();
+        if class == RequestClass::Scan {
+            return page;
+        }
+        let (last_used, old_queue) = (frame.last_used, frame.queue);
+        let reused = last_used != 0 && old_queue != Queue::Scan;
+        let queue = match old_queue {
+            Queue::Protected => Queue::Protected,
+            _ if reused && self.policy == EvictionPolicy::TwoQueue => Queue::Protected,
+            _ => Queue::Probation,
+        };
+        self.tick += 1;
+        let tick = self.tick;
+        self.queue(old_queue).remove(&last_used);
+        let frame = self.frames.get_mut(&key).unwrap();
+        frame.last_used = tick;
+        frame.queue = queue;
+        self.queue(queue).insert(tick, key);
+        if queue == Queue::Protected {
+            self.demote(capacity);
+        }
+        page
+    }
+
+    /// Moves the least recently used protected frames back to probation until the
+    /// protected queue holds at most its share of capacity.
+    fn demote(&mut self, capacity: usize) {
+        let limit = (capacity * PROTECTED_PERCENT / 100).max(1);
+        while self.protected.len() > limit {
+            let (tick, key) = self.protected.pop_first().unwrap();
+            self.frames.get_mut(&key).unwrap().queue = Queue::Probation;
+            self.lru.insert(tick, key);
+        }
+    }
+
+    fn remove(&mut self, key: &FrameKey) -> Option<Frame> {
+        let frame = self.frames.remove(key)?;
+        self.queue(frame.queue).remove(&frame.last_used);
+        Some(frame)
+    }
+
+    fn remove_cold(&mut self, key: &FrameKey) -> Option<ColdPage> {
+        let cold = self.cold.remove(key)?;
+        self.cold_lru.remove(&cold.last_used);
+        Some(cold)
+    }
+
+    fn stats(&mut self, container_id: ContainerId) -> &mut BufferPoolStats {
+        self.stats.entry(container_id).or_default()
+    }
+
+    /// Evicts unpinned frames, scanned ones first, then unprotected ones, least recently
+    /// used first, until at most capacity are left. Evicted frames go to the cold tier if
+    /// there is one.
+    fn evict(&mut self, capacity: usize) {
+        let mut excess = self.frames.len().saturating_sub(capacity);
+        if excess == 0 {
+            return;
+        }
+        let evicted: Vec<FrameKey> = self
+            .scan
+            .values()
+            .chain(self.lru.values())
+            .chain(self.protected.values())
+            .filter(|key| self.frames[key].pins == 0)
+            .take(excess)
+            .copied()
+            .collect();
+        for key in evicted {
+            let frame = self.remove(&key).unwrap();
+            self.stats(key.0).evictions += 1;
+            self.make_cold(key, frame);
+            excess -= 1;
+        }
+        if excess > 0 {
+            debug!("Buffer pool over capacity by {} pinned pages", excess);
+        }
+    }
+
+    /// Compresses an evicted frame into the cold tier, dropping the least recently used
+    /// cold pages past its capacity.
+    fn make_cold(&mut self, key: FrameKey, frame: Frame) {
+        if self.cold_capacity == 0 {
+            return;
+        }
+        let (codec, payload) = compression::compress(&frame.page.to_bytes());
+        self.remove_cold(&key);
+        self.cold.insert(
+            key,
+            ColdPage {
+                lsn: frame.lsn,
+                codec,
+                payload,
+                last_used: frame.last_used,
+            },
+        );
+        self.cold_lru.insert(frame.last_used, key);
+        while self.cold.len() > self.cold_capacity {
+            let (_, key) = self.cold_lru.pop_first().unwrap();
+            self.cold.remove(&key);
+        }
+    }
+
+    /// Returns a page of the cold tier as of write sequence number lsn, removing it from
+    /// the tier.
+    fn take_cold(&mut self, key: &FrameKey, lsn: u64) -> Option<Page> {
+        let cold = self.remove_cold(key)?;
+        if cold.lsn != lsn {
+            return None;
+        }
+        let page = compression::decompress(cold.codec, &cold.payload)
+            .and_then(|bytes| Page::from_bytes(&bytes));
+        match page {
+            Ok(page) => Some(page),
+            Err(e) => {
+                debug!("Dropping unreadable cold page {:?}: {:?}", key, e);
+                None
+            }
+        }
+    }
+
+    /// Adds a frame, replacing the one for the same page, and returns its page. A frame
+    /// replacing another keeps its place in the queues.
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &mut self,
+        key: FrameKey,
+        lsn: u64,
+        page: Page,
+        protected: bool,
+        pin: bool,
+        class: RequestClass,
+        capacity: usize,
+    ) -> Arc<Page> {
+        self.remove_cold(&key);
+        let (last_used, queue) = match self.remove(&key) {
+            Some(old) if protected => (old.last_used, Queue::Protected),
+            Some(old) => (old.last_used, old.queue),
+            None if protected => (0, Queue::Protected),
+            None if class == RequestClass::Scan => {
+                self.tick += 1;
+                (self.tick, Queue::Scan)
+            }
+            None => (0, Queue::Probation),
+        };
+        self.frames.insert(
+            key,
+            Frame {
+                page: Arc::new(page),
+                lsn,
+                pins: 0,
+                last_used,
+                queue,
+            },
+        );
+        self.queue(queue).insert(last_used, key);
+        let page = self.touch(key, pin, class, capacity);
+        self.evict(capacity);
+        page
+    }
+}
+
+/// Pages of the heap files of a storage manager kept in memory. See the module
+/// documentation.
+pub(crate) struct BufferPool {
+    capacity: AtomicUsize,
+    frames: Mutex<Frames>,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BufferPool {
+    /// Creates an empty pool holding up to capacity unpinned pages.
+    pub(crate) fn new(capacity: usize) -> Self {
+        BufferPool {
+            capacity: AtomicUsize::new(capacity),
+            frames: Mutex::new(Frames::default()),
+        }
+    }
+
+    /// Changes the number of pages the pool holds, evicting pages if it holds more.
+    pub(crate) fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::Relaxed);
+        self.frames.lock().unwrap().evict(capacity);
+    }
+
+    /// Changes the order pages are evicted in. Pages the pool holds stay unprotected.
+    pub(crate) fn set_policy(&self, policy: EvictionPolicy) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.policy = policy;
+        let protected = std::mem::take(&mut frames.protected);
+        for (tick, key) in protected {
+            frames.frames.get_mut(&key).unwrap().queue = Queue::Probation;
+            frames.lru.insert(tick, key);
+        }
+    }
+
+    /// Keeps up to pages evicted pages compressed in memory, none to drop them.
+    pub(crate) fn set_cold_capacity(&self, pages: usize) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.cold_capacity = pages;
+        while frames.cold.len() > pages {
+            let (_, key) = frames.cold_lru.pop_first().unwrap();
+            frames.cold.remove(&key);
+        }
+    }
+
+    /// Returns the counters of the containers whose pages were read through the pool.
+    pub(crate) fn stats(&self) -> HashMap<ContainerId, BufferPoolStats> {
+        let frames = self.frames.lock().unwrap();
+        let mut stats = frames.stats.clone();
+        for (container_id, _) in frames.frames.keys() {
+            stats.entry(*container_id).or_default().resident += 1;
+        }
+        for (container_id, _) in frames.cold.keys() {
+            stats.entry(*container_id).or_default().cold += 1;
+        }
+        stats
+    }
+
+    /// Returns the number of pages the pool holds, pinned or not.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.frames.lock().unwrap().frames.len()
+    }
+
+    /// Returns a page of a container if the pool holds it as of write sequence number lsn,
+    /// pinning it if pin. A page of the cold tier is decompressed back into the pool.
+    pub(crate) fn get(
+        self: &Arc<Self>,
+        container_id: ContainerId,
+        page_id: PageId,
+        lsn: u64,
+        pin: bool,
+        class: RequestClass,
+    ) -> Option<PageGuard> {
+        let key = (container_id, page_id);
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let mut frames = self.frames.lock().unwrap();
+        let page = match frames.frames.get(&key).map(|frame| frame.lsn) {
+            Some(frame_lsn) if frame_lsn == lsn => Some(frames.touch(key, pin, class, capacity)),
+            Some(_) => None,
+            None => frames.take_cold(&key, lsn).map(|page| {
+                frames.stats(container_id).cold_hits += 1;
+                // a page read again after its eviction is used repeatedly
+                let protected =
+                    frames.policy == EvictionPolicy::TwoQueue && class == RequestClass::Point;
+                frames.add(key, lsn, page, protected, pin, class, capacity)
+            }),
+        };
+        let stats = frames.stats(container_id);
+        match page {
+            Some(page) => {
+                stats.hits += 1;
+                Some(self.guard(key, page, pin))
+            }
+            None => {
+                stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Adds a page of a container read at write sequence number lsn, replacing the one the
+    /// pool holds, and returns it, pinned if pin. Guards on the replaced page keep it.
+    pub(crate) fn insert(
+        self: &Arc<Self>,
+        container_id: ContainerId,
+        lsn: u64,
+        page: Page,
+        pin: bool,
+        class: RequestClass,
+    ) -> PageGuard {
+        let key = (container_id, page.get_page_id());
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let page = self
+            .frames
+            .lock()
+            .unwrap()
+            .add(key, lsn, page, false, pin, class, capacity);
+        self.guard(key, page, pin)
+    }
+
+    /// Drops the pages of a container, e.g. because its file was truncated or replaced.
+    pub(crate) fn discard_container(&self, container_id: ContainerId) {
+        let mut frames = self.frames.lock().unwrap();
+        let keys: Vec<FrameKey> = frames
+            .frames
+            .keys()
+            .chain(frames.cold.keys())
+            .filter(|(c_id, _)| *c_id == container_id)
+            .copied()
+            .collect();
+        for key in keys {
+            frames.remove(&key);
+            frames.remove_cold(&key);
+        }
+    }
+
+    /// Drops every page, including the cold tier, and the counters. Pinned pages stay with
+    /// their guards.
+    pub(crate) fn clear(&self) {
+        let mut frames = self.frames.lock().unwrap();
+        frames.frames.clear();
+        frames.scan.clear();
+        frames.lru.clear();
+        frames.protected.clear();
+        frames.cold.clear();
+        frames.cold_lru.clear();
+        frames.stats.clear();
+    }
+
+    /// Returns the number of guards pinning a page.
+    #[cfg(test)]
+    pub(crate) fn pins(&self, container_id: ContainerId, page_id: PageId) -> usize {
+        self.frames
+            .lock()
+            .unwrap()
+            .frames
+            .get(&(container_id, page_id))
+            .map_or(0, |frame| frame.pins)
+    }
+
+    fn guard(self: &Arc<Self>, key: FrameKey, page: Arc<Page>, pin: bool) -> PageGuard {
+        PageGuard {
+            page,
+            pin: if pin { Some((self.clone(), key)) } else { None },
+        }
+    }
+
+    /// Unpins a page, unless it was replaced or dropped since it was pinned.
+    fn unpin(&self, key: FrameKey, page: &Arc<Page>) {
+        let mut frames = self.frames.lock().unwrap();
+        match frames.frames.get_mut(&key) {
+            Some(frame) if Arc::ptr_eq(&frame.page, page) => frame.pins -= 1,
+            _ => return,
+        }
+        frames.evict(self.capacity.load(Ordering::Relaxed));
+    }
+}
+
+/// A page returned by `StorageManager::get_page`. If it was pinned, it stays in the buffer
+/// pool until the guard is dropped.
+pub struct PageGuard {
+    page: Arc<Page>,
+    /// The pool and the frame the page is pinned in.
+    pin: Option<(Arc<BufferPool>, FrameKey)>,
+}
+
+impl PageGuard {
+    /// Returns a guard on a page that is not in a buffer pool.
+    pub(crate) fn unbuffered(page: Page) -> Self {
+        PageGuard {
+            page: Arc::new(page),
+            pin: None,
+        }
+    }
+
+    /// Returns whether the guard pins its page in the buffer pool.
+    pub fn is_pinned(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    pub(crate) fn page(&self) -> &Page {
+        &self.page
+    }
+
+    /// Returns the id of the page.
+    pub fn page_id(&self) -> PageId {
+        self.page.get_page_id()
+    }
+
+    /// Returns the value in a slot of the page, None if the slot holds no value.
+    pub fn get_value(&self, slot_id: SlotId) -> Option<Vec<u8>> {
+        self.page.get_value(slot_id)
+    }
+
+    /// Like `get_value`, but borrows the value from the page instead of copying it.
+    pub fn get_value_ref(&self, slot_id: SlotId) -> Option<&[u8]> {
+        self.page.get_value_ref(slot_id)
+    }
+
+    /// Returns a summary of the page, with its slot table and free space.
+    pub fn describe(&self) -> PageDescription {
+        self.page.describe()
+    }
+
+    /// Returns the bytes of the page as it is stored.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.page.to_bytes()
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        if let Some((pool, key)) = self.pin.take() {
+            pool.unpin(key, &self.page);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn page(page_id: PageId, value: u8) -> Page {
+        let mut page = Page::new(page_id);
+        page.add_value(&[value; 10]);
+        page
+    }
+
+    #[test]
+    fn hs_bp_evict_least_recently_used() {
+        let pool = Arc::new(BufferPool::new(2));
+        pool.insert(1, 0, page(0, 0), false, RequestClass::Point);
+        pool.insert(1, 0, page(1, 1), false, RequestClass::Point);
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_some());
+        pool.insert(1, 0, page(2, 2), false, RequestClass::Point);
+        assert_eq!(2, pool.len());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_none());
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_some());
+        // a page written since it was read is not returned
+        assert!(pool.get(1, 2, 1, false, RequestClass::Point).is_none());
+    }
+
+    #[test]
+    fn hs_bp_pinned_pages_stay() {
+        let pool = Arc::new(BufferPool::new(1));
+        let pinned = pool.insert(1, 0, page(0, 7), true, RequestClass::Point);
+        assert!(pinned.is_pinned());
+        let again = pool.get(1, 0, 0, true, RequestClass::Point).unwrap();
+        assert_eq!(2, pool.pins(1, 0));
+        let other = pool.insert(1, 0, page(1, 1), true, RequestClass::Point);
+        pool.insert(2, 0, page(0, 2), false, RequestClass::Point);
+        assert_eq!(2, pool.len());
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_some());
+        drop(again);
+        assert_eq!(1, pool.pins(1, 0));
+        assert_eq!(Some(vec![7; 10]), pinned.get_value(0));
+
+        // once unpinned, the pool shrinks back to its capacity
+        drop(other);
+        assert_eq!(1, pool.len());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_none());
+        drop(pinned);
+        assert_eq!(0, pool.pins(1, 0));
+
+        // a page replaced while pinned is not unpinned by the old guard
+        let old = pool.insert(2, 0, page(0, 3), true, RequestClass::Point);
+        let new = pool.insert(2, 1, page(0, 4), true, RequestClass::Point);
+        drop(old);
+        assert_eq!(1, pool.pins(2, 0));
+        assert_eq!(Some(vec![4; 10]), new.get_value(0));
+        pool.discard_container(2);
+        assert!(pool.get(2, 0, 1, false, RequestClass::Point).is_none());
+    }
+
+    #[test]
+    fn hs_bp_two_queue_resists_scans() {
+        let pool = Arc::new(BufferPool::new(4));
+        pool.set_policy(EvictionPolicy::TwoQueue);
+        pool.insert(1, 0, page(0, 0), false, RequestClass::Point);
+        pool.insert(1, 0, page(1, 1), false, RequestClass::Point);
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_some());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_some());
+        // a scan reading many pages once evicts the other scanned pages, not the hot ones
+        for page_id in 0..10 {
+            pool.insert(2, 0, page(page_id, 2), false, RequestClass::Point);
+        }
+        assert_eq!(4, pool.len());
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_some());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_some());
+        assert!(pool.get(2, 0, 0, false, RequestClass::Point).is_none());
+        assert!(pool.get(2, 9, 0, false, RequestClass::Point).is_some());
+
+        // with LRU the scan evicts them
+        pool.set_policy(EvictionPolicy::Lru);
+        for page_id in 10..20 {
+            pool.insert(2, 0, page(page_id, 2), false, RequestClass::Point);
+        }
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_none());
+    }
+
+    #[test]
+    fn hs_bp_cold_pages() {
+        let pool = Arc::new(BufferPool::new(1));
+        pool.set_cold_capacity(2);
+        pool.insert(1, 0, page(0, 0), false, RequestClass::Point);
+        pool.insert(1, 0, page(1, 1), false, RequestClass::Point);
+        pool.insert(1, 0, page(2, 2), false, RequestClass::Point);
+        assert_eq!(1, pool.len());
+        // evicted pages are decompressed back into the pool
+        let guard = pool.get(1, 0, 0, false, RequestClass::Point).unwrap();
+        assert_eq!(Some(vec![0; 10]), guard.get_value(0));
+        // but not once written since
+        assert!(pool.get(1, 1, 1, false, RequestClass::Point).is_none());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_none());
+
+        let stats = pool.stats()[&1];
+        assert_eq!(1, stats.hits);
+        assert_eq!(1, stats.cold_hits);
+        assert_eq!(2, stats.misses);
+        assert_eq!(3, stats.evictions);
+        assert_eq!(1, stats.resident);
+        assert_eq!(1, stats.cold);
+        assert_eq!(1.0 / 3.0, stats.hit_ratio());
+
+        // the cold tier drops its least recently used pages past its capacity
+        pool.set_cold_capacity(0);
+        assert_eq!(0, pool.stats()[&1].cold);
+        pool.clear();
+        assert!(pool.stats().is_empty());
+    }
+
+    #[test]
+    fn hs_bp_scans_evicted_first() {
+        let pool = Arc::new(BufferPool::new(3));
+        pool.insert(1, 0, page(0, 0), false, RequestClass::Point);
+        pool.insert(1, 0, page(1, 1), false, RequestClass::Point);
+        // scanned pages only replace each other
+        for page_id in 0..10 {
+            pool.insert(2, 0, page(page_id, 2), false, RequestClass::Scan);
+        }
+        assert_eq!(3, pool.len());
+        assert!(pool.get(2, 8, 0, false, RequestClass::Scan).is_none());
+        assert!(pool.get(2, 9, 0, false, RequestClass::Scan).is_some());
+        // a scan reading a page does not make it recently used
+        assert!(pool.get(1, 0, 0, false, RequestClass::Scan).is_some());
+        pool.insert(1, 0, page(2, 2), false, RequestClass::Point);
+        pool.insert(1, 0, page(3, 3), false, RequestClass::Point);
+        assert!(pool.get(1, 0, 0, false, RequestClass::Point).is_none());
+        assert!(pool.get(1, 1, 0, false, RequestClass::Point).is_some());
+        // a scanned page does not stay in a full pool
+        pool.insert(2, 0, page(0, 2), false, RequestClass::Scan);
+        assert!(pool.get(2, 0, 0, false, RequestClass::Point).is_none());
+
+        // but once read by a point read, it is kept like the others
+        pool.set_capacity(4);
+        pool.insert(2, 0, page(0, 2), false, RequestClass::Scan);
+        assert!(pool.get(2, 0, 0, false, RequestClass::Point).is_some());
+        pool.insert(1, 0, page(4, 4), false, RequestClass::Point);
+        assert!(pool.get(2, 0, 0, false, RequestClass::Point).is_some());
+        assert!(pool.get(1, 2, 0, false, RequestClass::Point).is_none());
+    }
+}
@@ -0,0 +1,416 @@
+//! Change data capture: streams of the inserts, updates and deletes made to containers, for
+//! external systems to consume without parsing the physical WAL.
+//!
+//! Capturing the changes of a container is started with `StorageManager::subscribe_changes`.
+//! Each change is recorded as a `ChangeEvent` holding the values before and after it and the
+//! transaction that made it, numbered by a sequence number of the container, and appended to
+//! the container's change log, a file in the `cdc` directory of the storage path. With MVCC
+//! the changes of a transaction are recorded when it commits and dropped if it aborts;
+//! otherwise they are recorded as they are made. The logs are synced when a transaction
+//! commits or finishes and by shutdown, so the events survive a crash from then on.
+//!
+//! Consumers read the events from the sequence number after the last one they processed
+//! with `StorageManager::read_changes`, and discard the ones processed with
+//! `StorageManager::ack_changes`. Bulk loads are recorded as inserts; truncating a container
+//! is not recorded.
+//!
+//! A change log starts with the sequence number of its first event (8), followed by the
+//! events, each written as
+//!
+//! | length (4) | seq (8) | tid (8) | kind (1) | ids | before length (4) | before | after |
+//!
+//! where length counts the bytes after it, and ids are the page (2) and slot (2) of the
+//! value before the change, if any, followed by the ones of the value after it, if any.
+
+use common::ids::{ContainerId, PageId, SlotId, TidType, ValueId};
+use common::CrustyError;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Extension of change log files.
+const LOG_EXTENSION: &str = "cdc";
+
+const KIND_INSERT: u8 = 0;
+const KIND_UPDATE: u8 = 1;
+const KIND_DELETE: u8 = 2;
+
+/// The kind of a `ChangeEvent`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A change made to a value of a container, see the `cdc` module.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChangeEvent {
+    /// Position of the event in the stream of its container, starting at 1.
+    pub seq: u64,
+    /// Transaction that made the change.
+    pub tid: TidType,
+    pub kind: ChangeKind,
+    /// Id and bytes of the value before the change, None for an insert.
+    pub before: Option<(ValueId, Vec<u8>)>,
+    /// Id and bytes of the value after the change, None for a delete.
+    pub after: Option<(ValueId, Vec<u8>)>,
+}
+
+/// A change not yet given a sequence number, e.g. one made by a transaction that has not
+/// committed.
+#[derive(Clone, Debug)]
+pub(crate) struct Change {
+    pub before: Option<(ValueId, Vec<u8>)>,
+    pub after: Option<(ValueId, Vec<u8>)>,
+}
+
+impl Change {
+    fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Insert,
+            (Some(_), Some(_)) => ChangeKind::Update,
+            (Some(_), None) => ChangeKind::Delete,
+        }
+    }
+}
+
+/// Returns the path of the change log of a container in the storage path dir.
+pub(crate) fn log_path(dir: &Path, container_id: ContainerId) -> PathBuf {
+    dir.join("cdc")
+        .join(format!("{}.{}", container_id, LOG_EXTENSION))
+}
+
+/// Returns the containers whose changes are captured in the storage path dir.
+pub(crate) fn find_logs(dir: &Path) -> Result<Vec<ContainerId>, CrustyError> {
+    let entries = match fs::read_dir(dir.join("cdc")) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(Vec::new()),
+    };
+    Ok(entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?
+                .strip_suffix(&format!(".{}", LOG_EXTENSION))?
+                .parse()
+                .ok()
+        })
+        .collect())
+}
+
+fn corrupt(reason: &str) -> CrustyError {
+    CrustyError::CrustyError(format!("Corrupt change log: {}", reason))
+}
+
+fn write_id(bytes: &mut Vec<u8>, id: &ValueId) {
+    bytes.extend_from_slice(&id.page_id.unwrap_or(0).to_le_bytes());
+    bytes.extend_from_slice(&id.slot_id.unwrap_or(0).to_le_bytes());
+}
+
+impl ChangeEvent {
+    /// Serializes the event, with its length.
+    fn to_bytes(&self) -> Vec<u8> {
+        let kind = match self.kind {
+            ChangeKind::Insert => KIND_INSERT,
+            ChangeKind::Update => KIND_UPDATE,
+            ChangeKind::Delete => KIND_DELETE,
+        };
+        let mut bytes = vec![0; 4];
+        bytes.extend_from_slice(&self.seq.to_le_bytes());
+        bytes.extend_from_slice(&self.tid.to_le_bytes());
+        bytes.push(kind);
+        for (id, _) in self.before.iter().chain(self.after.iter()) {
+            write_id(&mut bytes, id);
+        }
+        let before: &[u8] = self.before.as_ref().map_or(&[], |(_, value)| value);
+        bytes.extend_from_slice(&(before.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(before);
+        if let Some((_, value)) = &self.after {
+            bytes.extend_from_slice(value);
+        }
+        let len = (bytes.len() - 4) as u32;
+        bytes[0..4].copy_from_slice(&len.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes an event written by `to_bytes`, without its length.
+    fn from_bytes(container_id: ContainerId, bytes: &[u8]) -> Result<Self, CrustyError> {
+        if bytes.len() < 17 {
+            return Err(corrupt("event is shorter than its header"));
+        }
+        let seq = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let tid = TidType::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let (kind, ids) = match bytes[16] {
+            KIND_INSERT => (ChangeKind::Insert, 1),
+            KIND_UPDATE => (ChangeKind::Update, 2),
+            KIND_DELETE => (ChangeKind::Delete, 1),
+            _ => return Err(corrupt("unknown event kind")),
+        };
+        let values_offset = 17 + 4 * ids + 4;
+        if bytes.len() < values_offset {
+            return Err(corrupt("event is shorter than its ids"));
+        }
+        let ids: Vec<ValueId> = bytes[17..17 + 4 * ids]
+            .chunks(4)
+            .map(|id| ValueId {
+                container_id,
+                segment_id: None,
+                page_id: Some(PageId::from_le_bytes([id[0], id[1]])),
+                slot_id: Some(SlotId::from_le_bytes([id[2], id[3]])),
+            })
+            .collect();
+        let before_len =
+            u32::from_le_bytes(bytes[values_offset - 4..values_offset].try_into().unwrap())
+                as usize;
+        if bytes.len() < values_offset + before_len {
+            return Err(corrupt("event is shorter than its value"));
+        }
+        let before = bytes[values_offset..values_offset + before_len].to_vec();
+        let after = bytes[values_offset + before_len..].to_vec();
+        let (before, after) = match kind {
+            ChangeKind::Insert => (None, Some((ids[0], after))),
+            ChangeKind::Update => (Some((ids[0], before)), Some((ids[1], after))),
+            ChangeKind::Delete => (Some((ids[0], before)), None),
+        };
+        Ok(ChangeEvent {
+            seq,
+            tid,
+            kind,
+            before,
+            after,
+        })
+    }
+}
+
+/// Reads the events of the bytes of a change log, after its first sequence number,
+/// returning them and the number of bytes of the whole events.
+fn read_events(
+    container_id: ContainerId,
+    bytes: &[u8],
+) -> Result<(Vec<ChangeEvent>, usize), CrustyError> {
+    let mut events = Vec::new();
+    let mut offset = 8;
+    while offset + 4 <= bytes.len() {
+        let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let end = offset + 4 + len;
+        if end > bytes.len() {
+            break;
+        }
+        events.push(ChangeEvent::from_bytes(
+            container_id,
+            &bytes[offset + 4..end],
+        )?);
+        offset = end;
+    }
+    Ok((events, offset))
+}
+
+/// The file of a change log and the sequence number of its next event.
+struct LogState {
+    file: File,
+    next_seq: u64,
+    /// Whether events were written since the file was last synced.
+    unsynced: bool,
+}
+
+/// The change log of a container, see the `cdc` module.
+pub(crate) struct ChangeLog {
+    container_id: ContainerId,
+    path: PathBuf,
+    state: Mutex<LogState>,
+}
+
+impl ChangeLog {
+    /// Create an empty change log for a container in the storage path dir.
+    pub fn create(dir: &Path, container_id: ContainerId) -> Result<Self, CrustyError> {
+        let path = log_path(dir, container_id);
+        fs::create_dir_all(path.parent().unwrap())?;
+        let mut file = File::create(&path)?;
+        file.write_all(&1u64.to_le_bytes())?;
+        file.sync_all()?;
+        Ok(ChangeLog {
+            container_id,
+            path,
+            state: Mutex::new(LogState {
+                file,
+                next_seq: 1,
+                unsynced: false,
+            }),
+        })
+    }
+
+    /// Open the change log of a container in the storage path dir. An event cut short by a
+    /// crash is cut off the file.
+    pub fn open(dir: &Path, container_id: ContainerId) -> Result<Self, CrustyError> {
+        let path = log_path(dir, container_id);
+        let bytes = fs::read(&path).map_err(|e| CrustyError::io_with_path(&path, e))?;
+        if bytes.len() < 8 {
+            return Err(corrupt("log has no first sequence number"));
+        }
+        let first_seq = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let (events, len) = read_events(container_id, &bytes)?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+        if len < bytes.len() {
+            file.set_len(len as u64)?;
+            file.sync_all()?;
+        }
+        Ok(ChangeLog {
+            container_id,
+            path,
+            state: Mutex::new(LogState {
+                file,
+                next_seq: events.last().map_or(first_seq, |e| e.seq + 1),
+                unsynced: false,
+            }),
+        })
+    }
+
+    /// Append changes to the log, numbering them in order. They are durable once the log is
+    /// synced.
+    pub fn append(
+        &self,
+        tid: TidType,
+        changes: impl IntoIterator<Item = Change>,
+    ) -> Result<(), CrustyError> {
+        let mut state = self.state.lock().unwrap();
+        let mut bytes = Vec::new();
+        let mut seq = state.next_seq;
+        for change in changes {
+            let event = ChangeEvent {
+                seq,
+                tid,
+                kind: change.kind(),
+                before: change.before,
+                after: change.after,
+            };
+            bytes.extend_from_slice(&event.to_bytes());
+            seq += 1;
+        }
+        if bytes.is_empty() {
+            return Ok(());
+        }
+        state.file.write_all(&bytes)?;
+        state.next_seq = seq;
+        state.unsynced = true;
+        Ok(())
+    }
+
+    /// Sync the events appended to the log to disk.
+    pub fn sync(&self) -> Result<(), CrustyError> {
+        let mut state = self.state.lock().unwrap();
+        if state.unsynced {
+            state.file.sync_data()?;
+            state.unsynced = false;
+        }
+        Ok(())
+    }
+
+    /// Returns up to max events of the log, from the one numbered from on.
+    pub fn read(&self, from: u64, max: usize) -> Result<Vec<ChangeEvent>, CrustyError> {
+        // hold off appends, so no event is read cut short
+        let _state = self.state.lock().unwrap();
+        let bytes = fs::read(&self.path)?;
+        let (events, _) = read_events(self.container_id, &bytes)?;
+        Ok(events
+            .into_iter()
+            .skip_while(|e| e.seq < from)
+            .take(max)
+            .collect())
+    }
+
+    /// Discard the events up to the one numbered through, inclusive. The log is rewritten
+    /// to a temporary file renamed over it, so a crash leaves the old or the new one.
+    pub fn discard(&self, through: u64) -> Result<(), CrustyError> {
+        let mut state = self.state.lock().unwrap();
+        let bytes = fs::read(&self.path)?;
+        let (events, _) = read_events(self.container_id, &bytes)?;
+        let first_seq = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let mut kept = (through + 1)
+            .clamp(first_seq, state.next_seq)
+            .to_le_bytes()
+            .to_vec();
+        for event in events.iter().filter(|e| e.seq > through) {
+            kept.extend_from_slice(&event.to_bytes());
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(&kept)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        state.file = OpenOptions::new().append(true).open(&self.path)?;
+        state.unsynced = false;
+        Ok(())
+    }
+
+    /// Delete the log file.
+    pub fn remove(&self) -> Result<(), CrustyError> {
+        fs::remove_file(&self.path).map_err(|e| CrustyError::io_with_path(&self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::testutil::*;
+
+    fn id(page_id: PageId, slot_id: SlotId) -> ValueId {
+        ValueId {
+            container_id: 3,
+            segment_id: None,
+            page_id: Some(page_id),
+            slot_id: Some(slot_id),
+        }
+    }
+
+    #[test]
+    fn hs_cdc_log() {
+        init();
+        let dir = gen_random_test_sm_dir();
+        let log = ChangeLog::create(&dir, 3).unwrap();
+        let changes = vec![
+            Change {
+                before: None,
+                after: Some((id(0, 0), vec![1, 2, 3])),
+            },
+            Change {
+                before: Some((id(0, 0), vec![1, 2, 3])),
+                after: Some((id(1, 4), vec![])),
+            },
+            Change {
+                before: Some((id(1, 4), vec![])),
+                after: None,
+            },
+        ];
+        log.append(7, changes.clone()).unwrap();
+        log.sync().unwrap();
+        let events = log.read(1, 10).unwrap();
+        let kinds: Vec<ChangeKind> = events.iter().map(|e| e.kind).collect();
+        assert_eq!(
+            vec![ChangeKind::Insert, ChangeKind::Update, ChangeKind::Delete],
+            kinds
+        );
+        for (seq, (event, change)) in (1..).zip(events.iter().zip(&changes)) {
+            assert_eq!(seq, event.seq);
+            assert_eq!(7, event.tid);
+            assert_eq!(change.before, event.before);
+            assert_eq!(change.after, event.after);
+        }
+        assert_eq!(events[1..], log.read(2, 10).unwrap());
+        assert_eq!(events[..1], log.read(0, 1).unwrap());
+
+        // An event cut short is dropped on open, and sequence numbers go on after a discard
+        let mut bytes = fs::read(log_path(&dir, 3)).unwrap();
+        bytes.truncate(bytes.len() - 1);
+        fs::write(log_path(&dir, 3), bytes).unwrap();
+        let log = ChangeLog::open(&dir, 3).unwrap();
+        assert_eq!(events[..2], log.read(0, 10).unwrap());
+        log.discard(2).unwrap();
+        assert!(log.read(0, 10).unwrap().is_empty());
+        let log = ChangeLog::open(&dir, 3).unwrap();
+        log.append(8, changes[..1].to_vec()).unwrap();
+        assert_eq!(3, log.read(0, 10).unwrap()[0].seq);
+        assert_eq!(vec![3], find_logs(&dir).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,318 @@
+//! Transparent page compression for heap files.
+//!
+//! Without a compression feature a heap file is a sequence of fixed size pages. With the
+//! `lz4` or `snappy` feature it is a sequence of frames instead, each a header followed by
+//! the (compressed) page bytes:
+//!
+//! | page id (2) | codec (1) | length (2) | capacity (2) | payload (capacity bytes) |
+//!
+//! `length` is the number of payload bytes in use and `capacity` the number reserved for the
+//! frame, so a page can be rewritten in place as long as it still fits. A page that no longer
+//! fits moves to a free frame or the end of the file and its old frame is marked free.
+//! Pages that do not compress below `PAGE_SIZE` are stored as is.
+//!
+//! The feature selects the file format: a file written with compression enabled can only be
+//! read by a build with compression enabled.
+
+use crate::page::Page;
+use common::prelude::*;
+use common::PAGE_SIZE;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::SeekFrom;
+
+/// Whether heap files are written as compressed frames.
+pub(crate) const ENABLED: bool = cfg!(any(feature = "lz4", feature = "snappy"));
+
+/// Serialized size of a frame header: page id (2), codec (1), length (2), capacity (2).
+const FRAME_HEADER_SIZE: usize = 7;
+/// Frame capacities are rounded up to a multiple of this to leave room for the page to grow.
+const FRAME_ALIGN: usize = 64;
+/// Page id of a frame whose page moved elsewhere in the file.
+const FREE_FRAME: PageId = PageId::MAX;
+
+/// Codec ids stored in the frame header.
+const CODEC_NONE: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+const CODEC_SNAPPY: u8 = 2;
+
+/// The header at the start of every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct FrameHeader {
+    page_id: PageId,
+    codec: u8,
+    length: u16,
+    capacity: u16,
+}
+
+impl FrameHeader {
+    fn to_bytes(self) -> [u8; FRAME_HEADER_SIZE] {
+        let mut bytes = [0; FRAME_HEADER_SIZE];
+        bytes[0..2].copy_from_slice(&self.page_id.to_le_bytes());
+        bytes[2] = self.codec;
+        bytes[3..5].copy_from_slice(&self.length.to_le_bytes());
+        bytes[5..7].copy_from_slice(&self.capacity.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; FRAME_HEADER_SIZE]) -> Self {
+        FrameHeader {
+            page_id: PageId::from_le_bytes([bytes[0], bytes[1]]),
+            codec: bytes[2],
+            length: u16::from_le_bytes([bytes[3], bytes[4]]),
+            capacity: u16::from_le_bytes([bytes[5], bytes[6]]),
+        }
+    }
+}
+
+/// Compress a serialized page, returning the codec used and the payload.
+/// The page is stored uncompressed if compressing does not make it smaller.
+pub(crate) fn compress(bytes: &[u8]) -> (u8, Vec<u8>) {
+    #[cfg(feature = "lz4")]
+    let (codec, compressed) = (CODEC_LZ4, lz4_flex::compress(bytes));
+    #[cfg(all(feature = "snappy", not(feature = "lz4")))]
+    let (codec, compressed) = match snap::raw::Encoder::new().compress_vec(bytes) {
+        Ok(compressed) => (CODEC_SNAPPY, compressed),
+        Err(_) => return (CODEC_NONE, bytes.to_vec()),
+    };
+    #[cfg(not(any(feature = "lz4", feature = "snappy")))]
+    let (codec, compressed) = (CODEC_NONE, bytes.to_vec());
+    if compressed.len() < bytes.len() {
+        (codec, compressed)
+    } else {
+        (CODEC_NONE, bytes.to_vec())
+    }
+}
+
+/// Decompress a frame payload back into the serialized page.
+pub(crate) fn decompress(codec: u8, payload: &[u8]) -> Result<Vec<u8>, CrustyError> {
+    let bytes = match codec {
+        CODEC_NONE => Ok(payload.to_vec()),
+        #[cfg(feature = "lz4")]
+        CODEC_LZ4 => lz4_flex::decompress(payload, PAGE_SIZE).map_err(|e| e.to_string()),
+        #[cfg(feature = "snappy")]
+        CODEC_SNAPPY => snap::raw::Decoder::new()
+            .decompress_vec(payload)
+            .map_err(|e| e.to_string()),
+        #[cfg(not(feature = "lz4"))]
+        CODEC_LZ4 => Err("lz4 is not enabled in this build".to_string()),
+        #[cfg(not(feature = "snappy"))]
+        CODEC_SNAPPY => Err("snappy is not enabled in this build".to_string()),
+        _ => Err(format!("unknown codec {}", codec)),
+    };
+    match bytes {
+        Ok(bytes) if bytes.len() == PAGE_SIZE => Ok(bytes),
+        Ok(bytes) => Err(CrustyError::CrustyError(format!(
+            "Decompressed page has {} bytes instead of {}",
+            bytes.len(),
+            PAGE_SIZE
+        ))),
+        Err(e) => Err(CrustyError::CrustyError(format!(
+            "Cannot decompress page: {}",
+            e
+        ))),
+    }
+}
+
+/// The capacity reserved for a new frame holding a payload of `length` bytes.
+fn frame_capacity(length: usize) -> usize {
+    (length.div_ceil(FRAME_ALIGN) * FRAME_ALIGN).min(PAGE_SIZE)
+}
+
+/// Read the offset and header of every frame in the file, in file order.
+/// A partially written frame at the end of the file is ignored.
+fn read_frames(f: &mut File) -> Result<Vec<(u64, FrameHeader)>, CrustyError> {
+    let len = f.metadata()?.len();
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset + FRAME_HEADER_SIZE as u64 <= len {
+        f.seek(SeekFrom::Start(offset))?;
+        let mut buf = [0; FRAME_HEADER_SIZE];
+        f.read_exact(&mut buf)?;
+        let header = FrameHeader::from_bytes(&buf);
+        let next = offset + (FRAME_HEADER_SIZE + header.capacity as usize) as u64;
+        if next > len {
+            break;
+        }
+        frames.push((offset, header));
+        offset = next;
+    }
+    Ok(frames)
+}
+
+/// Count the pages stored in a compressed heap file.
+pub(crate) fn count_pages(f: &mut File) -> Result<PageId, CrustyError> {
+    let frames = read_frames(f)?;
+    Ok(frames
+        .iter()
+        .filter(|(_, header)| header.page_id != FREE_FRAME)
+        .count() as PageId)
+}
+
+/// Read a page from a compressed heap file. Returns None if the file does not hold the page.
+pub(crate) fn read_page(f: &mut File, pid: PageId) -> Result<Option<Page>, CrustyError> {
+    let frame = read_frames(f)?
+        .into_iter()
+        .find(|(_, header)| header.page_id == pid);
+    let (offset, header) = match frame {
+        Some(frame) => frame,
+        None => return Ok(None),
+    };
+    f.seek(SeekFrom::Start(offset + FRAME_HEADER_SIZE as u64))?;
+    let mut payload = vec![0; header.length as usize];
+    f.read_exact(&mut payload)?;
+    let bytes = decompress(header.codec, &payload)?;
+    Page::from_bytes(&bytes).map(Some)
+}
+
+/// Write a page to a compressed heap file, in place if it still fits in its frame.
+/// Returns true if the page was not in the file before.
+///
+/// # Arguments
+///
+/// * `f` - The heap file.
+/// * `page` - Page to write.
+/// * `compress_page` - Whether to compress the page. If not, it is stored as is in a frame,
+///   see `ContainerConfig::compression`.
+pub(crate) fn write_page(
+    f: &mut File,
+    page: &Page,
+    compress_page: bool,
+) -> Result<bool, CrustyError> {
+    let pid = page.get_page_id();
+    let (codec, payload) = if compress_page {
+        compress(&page.to_bytes())
+    } else {
+        (CODEC_NONE, page.to_bytes())
+    };
+    let frames = read_frames(f)?;
+    let existing = frames.iter().find(|(_, header)| header.page_id == pid);
+    let free = frames.iter().find(|(_, header)| {
+        header.page_id == FREE_FRAME && header.capacity as usize >= payload.len()
+    });
+
+    let (offset, capacity) = match (existing, free) {
+        (Some((offset, header)), _) if header.capacity as usize >= payload.len() => {
+            (*offset, header.capacity)
+        }
+        (_, Some((offset, header))) => (*offset, header.capacity),
+        _ => (
+            f.seek(SeekFrom::End(0))?,
+            frame_capacity(payload.len()) as u16,
+        ),
+    };
+    let header = FrameHeader {
+        page_id: pid,
+        codec,
+        length: payload.len() as u16,
+        capacity,
+    };
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + capacity as usize);
+    frame.extend_from_slice(&header.to_bytes());
+    frame.extend_from_slice(&payload);
+    frame.resize(FRAME_HEADER_SIZE + capacity as usize, 0);
+    f.seek(SeekFrom::Start(offset))?;
+    f.write_all(&frame)?;
+
+    // Free the old frame once the page is safely written elsewhere.
+    if let Some((old_offset, old_header)) = existing {
+        if *old_offset != offset {
+            let freed = FrameHeader {
+                page_id: FREE_FRAME,
+                ..*old_header
+            };
+            f.seek(SeekFrom::Start(*old_offset))?;
+            f.write_all(&freed.to_bytes())?;
+        }
+    }
+    Ok(existing.is_none())
+}
+
+#[cfg(all(test, any(feature = "lz4", feature = "snappy")))]
+mod test {
+    use super::*;
+    use common::testutil::*;
+    use temp_testdir::TempDir;
+
+    fn temp_file(tdir: &TempDir) -> File {
+        let mut path = tdir.to_path_buf();
+        path.push(gen_rand_string(4));
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap()
+    }
+
+    #[test]
+    fn hs_compression_frame_header() {
+        let header = FrameHeader {
+            page_id: 3,
+            codec: CODEC_LZ4,
+            length: 1234,
+            capacity: 1280,
+        };
+        assert_eq!(header, FrameHeader::from_bytes(&header.to_bytes()));
+        assert_eq!(1280, frame_capacity(1234));
+        assert_eq!(PAGE_SIZE, frame_capacity(PAGE_SIZE));
+    }
+
+    #[test]
+    fn hs_compression_rewrite_and_move() {
+        init();
+        let tdir = TempDir::new(gen_random_test_sm_dir(), true);
+        let mut f = temp_file(&tdir);
+
+        let mut p0 = Page::new(0);
+        p0.add_value(&[1; 100]);
+        let mut p1 = Page::new(1);
+        p1.add_value(&[2; 100]);
+        assert!(write_page(&mut f, &p0, true).unwrap());
+        assert!(write_page(&mut f, &p1, true).unwrap());
+        assert_eq!(2, count_pages(&mut f).unwrap());
+
+        // Random bytes do not compress, so page 0 outgrows its frame and moves.
+        p0.add_value(&get_random_byte_vec(1000));
+        assert!(!write_page(&mut f, &p0, true).unwrap());
+        assert_eq!(2, count_pages(&mut f).unwrap());
+        let frames = read_frames(&mut f).unwrap();
+        assert_eq!(FREE_FRAME, frames[0].1.page_id);
+        assert_eq!(0, frames[2].1.page_id);
+
+        assert_eq!(
+            p0.to_bytes(),
+            read_page(&mut f, 0).unwrap().unwrap().to_bytes()
+        );
+        assert_eq!(
+            p1.to_bytes(),
+            read_page(&mut f, 1).unwrap().unwrap().to_bytes()
+        );
+        assert!(read_page(&mut f, 2).unwrap().is_none());
+    }
+
+    #[test]
+    fn hs_compression_disabled_for_page() {
+        init();
+        let tdir = TempDir::new(gen_random_test_sm_dir(), true);
+        let mut f = temp_file(&tdir);
+
+        let mut p0 = Page::new(0);
+        p0.add_value(&[1; 100]);
+        assert!(write_page(&mut f, &p0, false).unwrap());
+        let frames = read_frames(&mut f).unwrap();
+        assert_eq!(CODEC_NONE, frames[0].1.codec);
+        assert_eq!(PAGE_SIZE, frames[0].1.length as usize);
+        assert_eq!(
+            p0.to_bytes(),
+            read_page(&mut f, 0).unwrap().unwrap().to_bytes()
+        );
+
+        // compressing it again shrinks it in place
+        assert!(!write_page(&mut f, &p0, true).unwrap());
+        let frames = read_frames(&mut f).unwrap();
+        assert_eq!(1, frames.len());
+        assert!((frames[0].1.length as usize) < PAGE_SIZE);
+    }
+}
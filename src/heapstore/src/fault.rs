@@ -0,0 +1,535 @@
+//! Fault injection for the crash consistency tests.
+//!
+//! Heap files write their pages, and the WAL writes and syncs its entries, through `write`
+//! and `sync`. Outside tests these only make the write. In tests a fault can be armed at one
+//! of these points with `FaultyStorageManager::inject`. When it fires, the write is dropped,
+//! cut short, duplicated or made in full. Except after a duplicate, the storage manager
+//! then crashes: every later write and sync fails, as if the process had died, until
+//! `FaultyStorageManager::crash_and_reopen` reopens it and it recovers from its WAL.
+//!
+//! The faults model a process dying, or a write torn by a power loss: the bytes written
+//! before the crash are in the files. Faults are injected in the writes made by the thread
+//! that armed them, and not in compressed heap files.
+
+#[cfg(not(test))]
+use std::io;
+
+/// A kind of write a fault can be injected in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FaultPoint {
+    /// A page written to a heap file.
+    PageWrite,
+    /// An entry appended to the WAL.
+    WalWrite,
+    /// A sync of the WAL, e.g. for a commit.
+    WalSync,
+}
+
+/// Make a write of bytes at a fault point.
+///
+/// # Arguments
+///
+/// * `point` - Kind of the write.
+/// * `bytes` - Bytes to write.
+/// * `write` - Writes bytes where they go. It may be called with part of the bytes, or twice.
+#[cfg(not(test))]
+pub(crate) fn write(
+    _point: FaultPoint,
+    bytes: &[u8],
+    mut write: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    write(bytes)
+}
+
+/// Make a sync at a fault point.
+#[cfg(not(test))]
+pub(crate) fn sync(_point: FaultPoint, sync: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    let mut sync = sync;
+    sync()
+}
+
+#[cfg(test)]
+pub(crate) use injector::*;
+
+#[cfg(test)]
+mod injector {
+    use super::FaultPoint;
+    use crate::storage_manager::StorageManager;
+    use crate::wal::WalConfig;
+    use common::prelude::*;
+    use common::storage_trait::StorageTrait;
+    use common::testutil::gen_random_test_sm_dir;
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io;
+    use std::ops::Deref;
+    use std::path::{Path, PathBuf};
+
+    /// What a fault does to the write it fires on.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub(crate) enum Fault {
+        /// The write is not made.
+        Drop,
+        /// Only this many bytes of the write are made, at most all but the last one.
+        Truncate(usize),
+        /// The write is made twice, as if it was retried. The storage manager does not crash.
+        Duplicate,
+        /// The write is made in full, then the storage manager crashes.
+        CrashAfter,
+    }
+
+    #[derive(Default)]
+    struct Injector {
+        /// The fault to inject, the point it is injected at, and the number of writes at the
+        /// point to make before it fires.
+        armed: Option<(FaultPoint, usize, Fault)>,
+        fired: bool,
+        crashed: bool,
+    }
+
+    thread_local! {
+        static INJECTOR: RefCell<Injector> = RefCell::new(Injector::default());
+    }
+
+    fn crash_error() -> io::Error {
+        io::Error::other("Crashed by an injected fault")
+    }
+
+    /// Returns the fault firing on a write at point, erroring if the storage manager crashed.
+    fn fire(point: FaultPoint) -> io::Result<Option<Fault>> {
+        INJECTOR.with(|injector| {
+            let mut injector = injector.borrow_mut();
+            if injector.crashed {
+                return Err(crash_error());
+            }
+            match &mut injector.armed {
+                Some((p, skip, _)) if *p == point && *skip > 0 => *skip -= 1,
+                Some((p, _, fault)) if *p == point => {
+                    let fault = *fault;
+                    injector.armed = None;
+                    injector.fired = true;
+                    injector.crashed = fault != Fault::Duplicate;
+                    return Ok(Some(fault));
+                }
+                _ => {}
+            }
+            Ok(None)
+        })
+    }
+
+    /// Make a write of bytes at a fault point, unless an injected fault changes it.
+    ///
+    /// # Arguments
+    ///
+    /// * `point` - Kind of the write.
+    /// * `bytes` - Bytes to write.
+    /// * `write` - Writes bytes where they go. It may be called with part of the bytes, or
+    ///   twice.
+    pub(crate) fn write(
+        point: FaultPoint,
+        bytes: &[u8],
+        mut write: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        match fire(point)? {
+            None => write(bytes),
+            Some(Fault::Drop) => Err(crash_error()),
+            Some(Fault::Truncate(len)) => {
+                write(&bytes[..len.min(bytes.len().saturating_sub(1))])?;
+                Err(crash_error())
+            }
+            Some(Fault::Duplicate) => {
+                write(bytes)?;
+                write(bytes)
+            }
+            Some(Fault::CrashAfter) => {
+                write(bytes)?;
+                Err(crash_error())
+            }
+        }
+    }
+
+    /// Make a sync at a fault point, unless an injected fault changes it. A dropped or
+    /// truncated sync is not made.
+    pub(crate) fn sync(
+        point: FaultPoint,
+        mut sync: impl FnMut() -> io::Result<()>,
+    ) -> io::Result<()> {
+        match fire(point)? {
+            None => sync(),
+            Some(Fault::Drop) | Some(Fault::Truncate(_)) => Err(crash_error()),
+            Some(Fault::Duplicate) => {
+                sync()?;
+                sync()
+            }
+            Some(Fault::CrashAfter) => {
+                sync()?;
+                Err(crash_error())
+            }
+        }
+    }
+
+    /// A storage manager with MVCC and a WAL in a test directory, which faults can be
+    /// injected in and which can be crashed and reopened. It derefs to the storage manager.
+    pub(crate) struct FaultyStorageManager {
+        sm: Option<StorageManager>,
+        path: PathBuf,
+    }
+
+    impl FaultyStorageManager {
+        /// Creates a storage manager in a new test directory.
+        pub fn new() -> Self {
+            let path = gen_random_test_sm_dir();
+            let sm = Self::open(&path).unwrap();
+            FaultyStorageManager { sm: Some(sm), path }
+        }
+
+        fn open(path: &Path) -> Result<StorageManager, CrustyError> {
+            StorageManager::new(path.to_path_buf())
+                .with_mvcc()
+                .with_wal(WalConfig::default())
+        }
+
+        /// Arms a fault, replacing the one armed before. The writes made while the
+        /// storage manager reopens count too.
+        ///
+        /// # Arguments
+        ///
+        /// * `point` - Kind of write to inject the fault in.
+        /// * `skip` - Number of writes at the point to make before the fault fires.
+        /// * `fault` - What the fault does.
+        pub fn inject(&self, point: FaultPoint, skip: usize, fault: Fault) {
+            INJECTOR.with(|injector| {
+                let mut injector = injector.borrow_mut();
+                injector.armed = Some((point, skip, fault));
+                injector.fired = false;
+            });
+        }
+
+        /// Returns true if the armed fault fired.
+        pub fn fired(&self) -> bool {
+            INJECTOR.with(|injector| injector.borrow().fired)
+        }
+
+        /// Drops the storage manager without shutting it down and reopens it, recovering
+        /// from the WAL. A fault that has not fired yet stays armed.
+        pub fn crash_and_reopen(&mut self) -> Result<(), CrustyError> {
+            drop(self.sm.take());
+            INJECTOR.with(|injector| injector.borrow_mut().crashed = false);
+            self.sm = Some(Self::open(&self.path)?);
+            Ok(())
+        }
+    }
+
+    impl Deref for FaultyStorageManager {
+        type Target = StorageManager;
+
+        fn deref(&self) -> &StorageManager {
+            self.sm.as_ref().expect("Storage manager did not reopen")
+        }
+    }
+
+    impl Drop for FaultyStorageManager {
+        fn drop(&mut self) {
+            INJECTOR.with(|injector| *injector.borrow_mut() = Injector::default());
+            if let Some(sm) = self.sm.take() {
+                let _ = sm.reset();
+            }
+            let _ = fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+#[cfg(all(test, not(any(feature = "lz4", feature = "snappy"))))]
+mod test {
+    use super::*;
+    use crate::storage_manager::container_path;
+    use common::prelude::*;
+    use common::storage_trait::StorageTrait;
+    use common::testutil::init;
+    use common::PAGE_SIZE;
+    use std::collections::BTreeSet;
+
+    const CONTAINER: ContainerId = 1;
+
+    /// A change made by a transaction of the workload.
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(Vec<u8>),
+        /// Deletes the value at this position among the committed ones.
+        Delete(usize),
+        Update(usize, Vec<u8>),
+        Load(Vec<Vec<u8>>),
+    }
+
+    /// Distinct values of 100 to 900 bytes, so pages fill up after a few.
+    fn value(i: usize) -> Vec<u8> {
+        let mut value = i.to_le_bytes().to_vec();
+        value.resize(100 + i * 97 % 800, i as u8);
+        value
+    }
+
+    fn workload() -> Vec<Op> {
+        let mut ops = Vec::new();
+        for i in 0..40 {
+            ops.push(match i % 8 {
+                3 => Op::Delete(i * 7),
+                5 => Op::Update(i * 3, value(1000 + i)),
+                7 => Op::Load((0..6).map(|j| value(2000 + i * 10 + j)).collect()),
+                _ => Op::Insert(value(i)),
+            });
+        }
+        ops
+    }
+
+    /// Runs an op in a transaction, updating the committed values if it commits. Returns
+    /// the values it changes, whether it commits or not.
+    fn run(
+        sm: &FaultyStorageManager,
+        op: &Op,
+        committed: &mut Vec<(ValueId, Vec<u8>)>,
+    ) -> (Vec<Vec<u8>>, Result<(), CrustyError>) {
+        let tid = TransactionId::new();
+        let n = committed.len().max(1);
+        let changed = match op {
+            Op::Insert(v) => vec![v.clone()],
+            Op::Delete(i) if !committed.is_empty() => vec![committed[i % n].1.clone()],
+            Op::Update(i, v) if !committed.is_empty() => {
+                vec![committed[i % n].1.clone(), v.clone()]
+            }
+            Op::Load(values) => values.clone(),
+            _ => return (Vec::new(), Ok(())),
+        };
+        let result = (|| {
+            match op {
+                Op::Insert(v) => {
+                    let id = sm.insert_value(CONTAINER, v.clone(), tid)?;
+                    sm.commit_transaction(tid)?;
+                    committed.push((id, v.clone()));
+                }
+                Op::Delete(i) => {
+                    sm.delete_value(committed[i % n].0, tid)?;
+                    sm.commit_transaction(tid)?;
+                    committed.remove(i % n);
+                }
+                Op::Update(i, v) => {
+                    let id = sm.update_value(v.clone(), committed[i % n].0, tid)?;
+                    sm.commit_transaction(tid)?;
+                    committed[i % n] = (id, v.clone());
+                }
+                Op::Load(values) => {
+                    let ids = sm.bulk_load(CONTAINER, values.iter().cloned(), tid)?;
+                    sm.commit_transaction(tid)?;
+                    committed.extend(ids.into_iter().zip(values.iter().cloned()));
+                }
+            }
+            Ok(())
+        })();
+        (changed, result)
+    }
+
+    /// Checks that every page of the container is whole, and that it holds the committed
+    /// values, leaving out the ones the transaction cut short by the crash changed, which
+    /// may or may not have been recovered.
+    fn check(sm: &FaultyStorageManager, committed: &[(ValueId, Vec<u8>)], in_flight: &[Vec<u8>]) {
+        let tid = TransactionId::new();
+        for page_id in 0..sm.get_num_pages(CONTAINER) {
+            sm.get_page(CONTAINER, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap_or_else(|e| panic!("Page {} is torn: {:?}", page_id, e));
+        }
+        let in_flight: BTreeSet<&Vec<u8>> = in_flight.iter().collect();
+        let stored: Vec<Vec<u8>> = sm
+            .get_iterator(CONTAINER, tid, Permissions::ReadOnly)
+            .map(|(value, _)| value)
+            .collect();
+        let stored_set: BTreeSet<&Vec<u8>> = stored.iter().collect();
+        assert_eq!(stored.len(), stored_set.len(), "A value is stored twice");
+        let expected: BTreeSet<&Vec<u8>> = committed.iter().map(|(_, v)| v).collect();
+        assert_eq!(
+            expected.difference(&in_flight).collect::<Vec<_>>(),
+            stored_set.difference(&in_flight).collect::<Vec<_>>()
+        );
+        for (id, value) in committed {
+            if !in_flight.contains(value) {
+                assert_eq!(
+                    *value,
+                    sm.get_value(*id, tid, Permissions::ReadOnly).unwrap()
+                );
+            }
+        }
+    }
+
+    /// Runs the workload with the fault injected in each write at the point in turn,
+    /// crashing and recovering each time. Returns the number of writes faulted.
+    fn crash_at_each_write(point: FaultPoint, fault: Fault) -> usize {
+        init();
+        for skip in 0.. {
+            let mut sm = FaultyStorageManager::new();
+            sm.create_table(CONTAINER).unwrap();
+            sm.inject(point, skip, fault);
+            let mut committed = Vec::new();
+            let mut in_flight = Vec::new();
+            for op in workload() {
+                let (changed, result) = run(&sm, &op, &mut committed);
+                if result.is_err() {
+                    in_flight = changed;
+                    break;
+                }
+            }
+            if !sm.fired() {
+                return skip;
+            }
+            sm.crash_and_reopen().unwrap();
+            check(&sm, &committed, &in_flight);
+
+            // the recovered storage manager takes new changes
+            let (_, result) = run(&sm, &Op::Insert(value(9999)), &mut committed);
+            result.unwrap();
+            sm.crash_and_reopen().unwrap();
+            check(&sm, &committed, &in_flight);
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn hs_fault_dropped_page_write() {
+        // the WAL entry is written but the page is not
+        assert!(crash_at_each_write(FaultPoint::PageWrite, Fault::Drop) > 20);
+    }
+
+    #[test]
+    fn hs_fault_torn_page_write() {
+        assert!(crash_at_each_write(FaultPoint::PageWrite, Fault::Truncate(PAGE_SIZE / 2)) > 20);
+        assert!(crash_at_each_write(FaultPoint::PageWrite, Fault::Truncate(1)) > 20);
+    }
+
+    #[test]
+    fn hs_fault_duplicated_page_write() {
+        assert!(crash_at_each_write(FaultPoint::PageWrite, Fault::Duplicate) > 20);
+    }
+
+    #[test]
+    fn hs_fault_crash_after_page_write() {
+        assert!(crash_at_each_write(FaultPoint::PageWrite, Fault::CrashAfter) > 20);
+    }
+
+    #[test]
+    fn hs_fault_wal_writes() {
+        for fault in [
+            Fault::Drop,
+            Fault::Truncate(10),
+            Fault::Truncate(PAGE_SIZE / 2),
+            Fault::Duplicate,
+            Fault::CrashAfter,
+        ] {
+            assert!(crash_at_each_write(FaultPoint::WalWrite, fault) > 40);
+        }
+    }
+
+    #[test]
+    fn hs_fault_wal_syncs() {
+        // between the sync of a commit and the page writes of the next transaction
+        assert!(crash_at_each_write(FaultPoint::WalSync, Fault::CrashAfter) > 20);
+        assert!(crash_at_each_write(FaultPoint::WalSync, Fault::Drop) > 20);
+    }
+
+    #[test]
+    fn hs_fault_crash_during_recovery() {
+        init();
+        let mut sm = FaultyStorageManager::new();
+        sm.create_table(CONTAINER).unwrap();
+        let mut committed = Vec::new();
+        for op in workload() {
+            run(&sm, &op, &mut committed).1.unwrap();
+        }
+        // the last page write is dropped, then a page write of the recovery is torn
+        sm.inject(FaultPoint::PageWrite, 0, Fault::Drop);
+        let (in_flight, result) = run(&sm, &Op::Insert(value(5000)), &mut committed);
+        assert!(result.is_err());
+        sm.inject(FaultPoint::PageWrite, 3, Fault::Truncate(100));
+        assert!(sm.crash_and_reopen().is_err());
+        assert!(sm.fired());
+        sm.crash_and_reopen().unwrap();
+        check(&sm, &committed, &in_flight);
+    }
+
+    #[test]
+    fn hs_fault_checkpoint() {
+        init();
+        let mut sm = FaultyStorageManager::new();
+        sm.create_table(CONTAINER).unwrap();
+        let mut committed = Vec::new();
+        for op in workload().iter().take(10) {
+            run(&sm, op, &mut committed).1.unwrap();
+        }
+        sm.shutdown();
+        // nothing is logged after the checkpoint, so reopening writes no page
+        sm.inject(FaultPoint::PageWrite, 0, Fault::Drop);
+        sm.crash_and_reopen().unwrap();
+        assert!(!sm.fired());
+        check(&sm, &committed, &[]);
+    }
+
+    fn stored_values(sm: &FaultyStorageManager, container_id: ContainerId) -> BTreeSet<Vec<u8>> {
+        sm.get_iterator(container_id, TransactionId::new(), Permissions::ReadOnly)
+            .map(|(value, _)| value)
+            .collect()
+    }
+
+    #[test]
+    fn hs_fault_rename_truncate() {
+        init();
+        let expected = |committed: &[(ValueId, Vec<u8>)]| -> BTreeSet<Vec<u8>> {
+            committed.iter().map(|(_, v)| v.clone()).collect()
+        };
+        // a crash after the rename, after the WAL sync but before the file is renamed, and
+        // before the rename is logged
+        for (fault, renamed) in [
+            (None, true),
+            (Some((FaultPoint::WalSync, Fault::CrashAfter)), true),
+            (Some((FaultPoint::WalWrite, Fault::Drop)), false),
+        ] {
+            let mut sm = FaultyStorageManager::new();
+            sm.create_table(CONTAINER).unwrap();
+            let mut committed = Vec::new();
+            for op in workload().iter().take(20) {
+                run(&sm, op, &mut committed).1.unwrap();
+            }
+            if let Some((point, fault)) = fault {
+                sm.inject(point, 0, fault);
+            }
+            assert_eq!(fault.is_none(), sm.rename_container(CONTAINER, 3).is_ok());
+            sm.crash_and_reopen().unwrap();
+            let (cid, other) = if renamed {
+                (3, CONTAINER)
+            } else {
+                (CONTAINER, 3)
+            };
+            let containers = sm.list_containers().unwrap();
+            assert_eq!(
+                vec![cid],
+                containers
+                    .iter()
+                    .map(|c| c.container_id)
+                    .collect::<Vec<_>>()
+            );
+            assert!(!container_path(&sm.storage_path, other).exists());
+            assert_eq!(expected(&committed), stored_values(&sm, cid));
+
+            // changes made after the rename are recovered too
+            let tid = TransactionId::new();
+            sm.insert_value(cid, value(7000), tid).unwrap();
+            sm.commit_transaction(tid).unwrap();
+            sm.crash_and_reopen().unwrap();
+            assert_eq!(
+                expected(&committed).len() + 1,
+                stored_values(&sm, cid).len()
+            );
+
+            sm.inject(FaultPoint::WalSync, 0, Fault::CrashAfter);
+            assert!(sm.truncate_container(cid).is_err());
+            sm.crash_and_reopen().unwrap();
+            assert!(stored_values(&sm, cid).is_empty());
+            assert_eq!(0, sm.get_num_pages(cid));
+        }
+    }
+}
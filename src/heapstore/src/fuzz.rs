@@ -0,0 +1,40 @@
+//! Entry points of the fuzz targets in `fuzz/`, which cannot reach the private page
+//! module otherwise. Built with the `fuzz` feature.
+
+use crate::page::Page;
+use common::ids::SlotId;
+
+/// Parses arbitrary bytes as a page. Bytes that are not a page must be rejected with an
+/// error; a page that parses must serialize to a page with the same values and take
+/// deletes and adds like any other page. Panics if any of this does not hold.
+///
+/// # Arguments
+///
+/// * `data` - Bytes to parse.
+pub fn page_from_bytes(data: &[u8]) {
+    let page = match Page::from_bytes(data) {
+        Ok(page) => page,
+        Err(_) => return,
+    };
+    let bytes = page.to_bytes();
+    let values: Vec<(Vec<u8>, SlotId)> = page.into_iter().collect();
+    let mut page = Page::from_bytes(&bytes).expect("serialized page does not parse");
+    assert_eq!(bytes, page.to_bytes());
+    for (value, slot_id) in &values {
+        assert_eq!(Some(value), page.get_value(*slot_id).as_ref());
+    }
+
+    // delete every other value, then fill the freed space
+    for (_, slot_id) in values.iter().step_by(2) {
+        assert_eq!(Some(()), page.delete_value(*slot_id));
+        assert_eq!(None, page.get_value(*slot_id));
+    }
+    for (value, slot_id) in values.iter().skip(1).step_by(2) {
+        assert_eq!(Some(value), page.get_value(*slot_id).as_ref());
+    }
+    let value = [0xab; 100];
+    while let Some(slot_id) = page.add_value(&value) {
+        assert_eq!(Some(&value[..]), page.get_value(slot_id).as_deref());
+    }
+    Page::from_bytes(&page.to_bytes()).expect("modified page does not parse");
+}
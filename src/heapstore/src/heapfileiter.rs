@@ -1,8 +1,11 @@
-use crate::heapfile::HeapFile;
-use crate::page::{PageIntoIter, self};
+use crate::heapfile::{HeapFile, Snapshot};
+use crate::page::Page;
+use crate::page::{self, PageIntoIter};
+use crate::record::Visibility;
 use common::prelude::*;
+use common::storage_trait::PageSampler;
+use std::collections::VecDeque;
 use std::sync::Arc;
-use crate::page::Page;
 
 #[allow(dead_code)]
 /// The struct for a HeapFileIterator.
@@ -13,9 +16,23 @@ use crate::page::Page;
 /// HINT: This will need an Arc<HeapFile>
 pub struct HeapFileIterator {
     tid: TransactionId,
-    hf: Arc<HeapFile>,     
+    hf: Arc<HeapFile>,
     curr_pid: u16,
-    curr_record_idx: u16,
+    /// The records of the current page not returned yet, None before the page is read
+    page_iter: Option<PageIntoIter>,
+    snapshot: Option<Snapshot>,
+    sampler: Option<PageSampler>,
+    /// Versions the iterator returns, if the heap file stores versions of values
+    visibility: Option<Visibility>,
+    /// Number of pages read past the current one once the scan is sequential
+    read_ahead: PageId,
+    /// Pages read ahead and not reached yet, with the write sequence numbers they were
+    /// read at
+    pages: VecDeque<(Page, u64)>,
+    /// The page the iterator read last, to detect a sequential scan
+    last_pid: Option<PageId>,
+    /// The error reading a page that stopped the iterator, if any
+    error: Option<CrustyError>,
 }
 
 /// Required HeapFileIterator functions
@@ -23,10 +40,134 @@ impl HeapFileIterator {
     /// Create a new HeapFileIterator that stores the tid, and heapFile pointer.
     /// This should initialize the state required to iterate through the heap file.
     pub(crate) fn new(tid: TransactionId, hf: Arc<HeapFile>) -> Self {
-        HeapFileIterator {tid,
-        hf,
-        curr_pid: 0,
-        curr_record_idx: 0,
+        HeapFileIterator {
+            tid,
+            hf,
+            curr_pid: 0,
+            page_iter: None,
+            snapshot: None,
+            sampler: None,
+            visibility: None,
+            read_ahead: 0,
+            pages: VecDeque::new(),
+            last_pid: None,
+            error: None,
+        }
+    }
+
+    /// Create a HeapFileIterator over a Bernoulli sample of the pages of the heap file.
+    /// Pages left out of the sample are skipped without being read.
+    pub(crate) fn new_sample(tid: TransactionId, hf: Arc<HeapFile>, sampler: PageSampler) -> Self {
+        HeapFileIterator {
+            tid,
+            hf,
+            curr_pid: 0,
+            page_iter: None,
+            snapshot: None,
+            sampler: Some(sampler),
+            visibility: None,
+            read_ahead: 0,
+            pages: VecDeque::new(),
+            last_pid: None,
+            error: None,
+        }
+    }
+
+    /// Create a HeapFileIterator over a snapshot of the heap file taken now.
+    ///
+    /// The iterator returns exactly the records that were live when it was created:
+    /// records inserted afterwards are skipped, records deleted afterwards are still
+    /// returned, and records updated afterwards are returned with their old bytes and
+    /// value id. Writes to the file keep earlier page images while the iterator is alive,
+    /// so it should be dropped once it is no longer needed.
+    pub(crate) fn new_snapshot(tid: TransactionId, hf: Arc<HeapFile>) -> Self {
+        let snapshot = Some(hf.snapshot());
+        HeapFileIterator {
+            tid,
+            hf,
+            curr_pid: 0,
+            page_iter: None,
+            snapshot,
+            sampler: None,
+            visibility: None,
+            read_ahead: 0,
+            pages: VecDeque::new(),
+            last_pid: None,
+            error: None,
+        }
+    }
+
+    /// Return only the versions of values visible to a transaction, without their
+    /// record headers.
+    pub(crate) fn with_visibility(mut self, visibility: Option<Visibility>) -> Self {
+        self.visibility = visibility;
+        self
+    }
+
+    /// Once the iterator moves on to the page after the one it read last, read this many
+    /// pages past it with the same read, 0 not to read ahead. Pages written after they were
+    /// read ahead are read again. Snapshot and sample iterators do not read ahead.
+    pub(crate) fn with_read_ahead(mut self, pages: PageId) -> Self {
+        self.read_ahead = pages;
+        self
+    }
+
+    /// The error reading a page that stopped the iterator, if any. The iterator returns
+    /// no more values after an error, so a scan that ends early can be told apart from
+    /// one that reached the last page.
+    pub fn error(&self) -> Option<&CrustyError> {
+        self.error.as_ref()
+    }
+
+    /// Read the page the iterator is on. Returns None past the last page, and if the page
+    /// cannot be read, keeping the error.
+    fn read_curr_page(&mut self) -> Option<Page> {
+        let page = match &self.snapshot {
+            Some(snapshot) => self.hf.read_page_at_snapshot(self.curr_pid, snapshot),
+            None if self.curr_pid < self.hf.num_pages() => self.read_ahead_page(),
+            None => Ok(None),
+        };
+        page.unwrap_or_else(|e| {
+            error!(
+                "{}, stopping the scan of container {} at page {}",
+                e, self.hf.container_id, self.curr_pid
+            );
+            self.error = Some(e);
+            None
+        })
+    }
+
+    /// Read the page the iterator is on from the pages read ahead, if it was not written
+    /// since, or from the file, with the pages after it if the scan is sequential.
+    fn read_ahead_page(&mut self) -> Result<Option<Page>, CrustyError> {
+        let pid = self.curr_pid;
+        let sequential = self.sampler.is_none()
+            && !self.hf.is_in_memory()
+            && pid > 0
+            && self.last_pid == Some(pid - 1);
+        self.last_pid = Some(pid);
+        while matches!(self.pages.front(), Some((page, _)) if page.get_page_id() != pid) {
+            self.pages.pop_front();
+        }
+        if let Some((page, lsn)) = self.pages.pop_front() {
+            if lsn == self.hf.page_lsn(pid) {
+                return Ok(Some(page));
+            }
+        }
+        let count = if sequential {
+            self.read_ahead.saturating_add(1)
+        } else {
+            1
+        };
+        self.pages = self.hf.read_pages(pid, count)?.into();
+        Ok(self.pages.pop_front().map(|(page, _)| page))
+    }
+}
+
+impl Drop for HeapFileIterator {
+    fn drop(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            self.hf.release_snapshot(snapshot);
         }
     }
 }
@@ -35,35 +176,37 @@ impl HeapFileIterator {
 /// Note this will need to iterate through the pages and their respective iterators.
 impl Iterator for HeapFileIterator {
     type Item = (Vec<u8>, ValueId);
+    /// Each page is read once, when the iterator gets to it, and only the values returned
+    /// are copied out of it.
     fn next(&mut self) -> Option<Self::Item> {
-        if self.curr_pid < self.hf.num_pages() {
-            // create page iterator local variable based on current page
-            // we will use this to iterate through all values in the page
-            let mut page_iterator = self.hf.read_page_from_file(self.curr_pid).unwrap().into_iter();
-
-            // move to current record index
-            for _ in 0..self.curr_record_idx {
-                page_iterator.next();
-            }
-            
-            if let Some((value, value_id)) = page_iterator.next() {
-                let id = ValueId {
-                    container_id: self.hf.container_id, 
-                    segment_id: None, 
-                    page_id: Some(self.curr_pid), 
-                    slot_id: value_id.into()
-                };
-                // increment record index
-                self.curr_record_idx += 1;
-                return Some((value, id));
-            } else {
-                // reset record index and increment page id
-                self.curr_record_idx = 0;
+        if self.error.is_some() {
+            return None;
+        }
+        loop {
+            if let Some(page_iterator) = &mut self.page_iter {
+                while let Some((bytes, slot_id)) = page_iterator.next_ref() {
+                    let value = match &self.visibility {
+                        None => bytes,
+                        Some(visibility) => match visibility.value(bytes) {
+                            Some(value) => value,
+                            None => continue,
+                        },
+                    };
+                    let id = self.hf.value_id(self.curr_pid, slot_id);
+                    return Some((value.to_vec(), id));
+                }
+                // move on to the next page
+                self.page_iter = None;
                 self.curr_pid += 1;
-                return self.next();
             }
+            // on entering a page, skip ahead to the next page in the sample
+            if let Some(sampler) = &mut self.sampler {
+                while self.curr_pid < self.hf.num_pages() && !sampler.select() {
+                    self.curr_pid += 1;
+                }
+            }
+            self.page_iter = Some(self.read_curr_page()?.into_iter());
         }
-        None
     }
 }
 
@@ -72,6 +215,7 @@ impl Iterator for HeapFileIterator {
 mod test {
     use super::*;
     use common::testutil::*;
+    use std::io::{Seek, SeekFrom, Write};
     use temp_testdir::TempDir;
 
     #[test]
@@ -124,6 +268,111 @@ mod test {
         assert_eq!(iter.next().unwrap().0, bytes3);
         assert_eq!(iter.next().unwrap().0, bytes11);
         assert_eq!(iter.next().unwrap().0, bytes12);
+    }
+
+    #[test]
+    fn hs_hf_iter_read_ahead() {
+        init();
+        let f = gen_random_test_sm_dir();
+        let tdir = TempDir::new(f, true);
+        let mut f = tdir.to_path_buf();
+        f.push(gen_rand_string(4));
+        f.set_extension("hf");
+        let hf = Arc::new(HeapFile::new(f.to_path_buf(), 0).unwrap());
+        let mut values = Vec::new();
+        for pid in 0..10 {
+            let mut page = Page::new(pid);
+            for _ in 0..3 {
+                let value = get_random_byte_vec(100);
+                page.add_value(&value);
+                values.push(value);
+            }
+            hf.write_page_to_file(page).unwrap();
+        }
+
+        // the scan is sequential from the second page, read with the 4 pages after it
+        let mut iter = HeapFileIterator::new(TransactionId::new(), hf.clone()).with_read_ahead(4);
+        let mut read = Vec::new();
+        for _ in 0..9 {
+            read.push(iter.next().unwrap().0);
+        }
+        #[cfg(feature = "profile")]
+        assert_eq!(6, hf.metrics.counters().reads);
+
+        // a page written after it was read ahead is read again
+        let mut page = Page::new(3);
+        let value = get_random_byte_vec(50);
+        page.add_value(&value);
+        hf.write_page_to_file(page).unwrap();
+        assert_eq!(value, iter.next().unwrap().0);
+        read.extend(iter.map(|(value, _)| value));
+        assert!(values[..9] == read[..9]);
+        assert!(values[12..] == read[9..]);
+        #[cfg(feature = "profile")]
+        assert_eq!(13, hf.metrics.counters().reads);
+    }
+
+    #[test]
+    fn hs_hf_iter_reads_page_once() {
+        init();
+        let f = gen_random_test_sm_dir();
+        let tdir = TempDir::new(f, true);
+        let mut f = tdir.to_path_buf();
+        f.push(gen_rand_string(4));
+        f.set_extension("hf");
+        let hf = Arc::new(HeapFile::new(f.to_path_buf(), 0).unwrap());
+        let mut values = Vec::new();
+        for pid in 0..5 {
+            let mut page = Page::new(pid);
+            for _ in 0..20 {
+                let value = get_random_byte_vec(100);
+                page.add_value(&value);
+                values.push(value);
+            }
+            hf.write_page_to_file(page).unwrap();
+        }
+
+        let iter = HeapFileIterator::new(TransactionId::new(), hf.clone());
+        let read: Vec<Vec<u8>> = iter.map(|(value, _)| value).collect();
+        assert!(values == read);
+        #[cfg(feature = "profile")]
+        assert_eq!(5, hf.metrics.counters().reads);
+    }
+
+    #[test]
+    fn hs_hf_iter_read_error() {
+        init();
+        let f = gen_random_test_sm_dir();
+        let tdir = TempDir::new(f, true);
+        let mut f = tdir.to_path_buf();
+        f.push(gen_rand_string(4));
+        f.set_extension("hf");
+        let hf = Arc::new(HeapFile::new(f.to_path_buf(), 0).unwrap());
+        let mut values = Vec::new();
+        let mut first_page_end = 0;
+        for pid in 0..3 {
+            let mut page = Page::new(pid);
+            let value = get_random_byte_vec(100);
+            page.add_value(&value);
+            values.push(value);
+            hf.write_page_to_file(page).unwrap();
+            if pid == 0 {
+                first_page_end = std::fs::metadata(&f).unwrap().len();
+            }
+        }
+        // overwrite what follows the first page, plain or compressed, with garbage
+        let len = std::fs::metadata(&f).unwrap().len();
+        let mut file = std::fs::OpenOptions::new().write(true).open(&f).unwrap();
+        file.seek(SeekFrom::Start(first_page_end)).unwrap();
+        file.write_all(&vec![0xab; (len - first_page_end) as usize])
+            .unwrap();
 
+        // the scan stops at the page it cannot read and keeps the error
+        let mut iter = HeapFileIterator::new(TransactionId::new(), hf.clone());
+        assert_eq!(values[0], iter.next().unwrap().0);
+        assert!(iter.error().is_none());
+        assert!(iter.next().is_none());
+        assert!(iter.error().is_some());
+        assert!(iter.next().is_none());
     }
 }
@@ -0,0 +1,428 @@
+//! Offline inspection of a storage directory, used by the `crusty-inspect` binary.
+//!
+//! An `Inspector` reads the heap files of a directory without opening a storage manager, so
+//! it works on a copy of a database, or on one that is corrupted or was not shut down. The
+//! containers are the ones persisted by `shutdown`, or, if none were, the heap files found
+//! in the directory. Pages are addressed by their position in the heap file, which is their
+//! page id unless the file is corrupted. The heap file of a container split into segments
+//! spans the files of its segments.
+//!
+//! Pages carry no checksums, so `verify` checks that each page parses as a valid page (see
+//! `Page::from_bytes`), that it holds the page id of its position, and that the file does
+//! not end in a partially written page.
+
+use crate::compression;
+use crate::heapfile::HeapFile;
+use crate::page::{Page, PageDescription};
+use crate::record::RecordHeader;
+use crate::storage_manager::{container_path, find_heap_files, read_configs, read_metadata};
+use common::encoding;
+use common::ids::{ContainerId, PageId, SlotId};
+use common::{ContainerConfig, CrustyError, TableSchema, Tuple, PAGE_SIZE};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// A container of an inspected directory, see `Inspector::containers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerSummary {
+    pub container_id: ContainerId,
+    /// Path of the heap file, or of its first segment.
+    pub path: PathBuf,
+    /// Size of the heap file in bytes, with all its segments.
+    pub file_size: u64,
+    /// Number of whole pages in the heap file.
+    pub num_pages: PageId,
+    /// Containers it depends on.
+    pub dependencies: Vec<ContainerId>,
+}
+
+/// A page `Inspector::verify` found corrupted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageProblem {
+    pub container_id: ContainerId,
+    /// Position of the page in the heap file.
+    pub page: PageId,
+    pub message: String,
+}
+
+/// Reads the containers of a storage directory, see the module documentation.
+pub struct Inspector {
+    dir: PathBuf,
+    containers: Vec<ContainerId>,
+    dependencies: HashMap<ContainerId, Vec<ContainerId>>,
+    configs: HashMap<ContainerId, ContainerConfig>,
+}
+
+impl Inspector {
+    /// Opens a storage directory for inspection. Nothing in it is written.
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - Storage path of the storage manager.
+    pub fn open(dir: &Path) -> Result<Self, CrustyError> {
+        if !dir.is_dir() {
+            return Err(CrustyError::IOError(format!(
+                "{} is not a directory",
+                dir.to_string_lossy()
+            )));
+        }
+        let (mut containers, dependencies) = match read_metadata(dir) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("{}, listing the heap files instead", e);
+                (find_heap_files(dir)?, HashMap::new())
+            }
+        };
+        containers.sort_unstable();
+        let configs = read_configs(dir).unwrap_or_else(|e| {
+            warn!("{}, reading the containers with the default options", e);
+            HashMap::new()
+        });
+        Ok(Inspector {
+            dir: dir.to_path_buf(),
+            containers,
+            dependencies,
+            configs,
+        })
+    }
+
+    /// Returns the containers, in order of id.
+    pub fn containers(&self) -> Result<Vec<ContainerSummary>, CrustyError> {
+        self.containers
+            .iter()
+            .map(|container_id| {
+                let hf = self.heap_file(*container_id)?;
+                Ok(ContainerSummary {
+                    container_id: *container_id,
+                    path: container_path(&self.dir, *container_id),
+                    file_size: hf.file_size()?,
+                    num_pages: hf.num_pages(),
+                    dependencies: self
+                        .dependencies
+                        .get(container_id)
+                        .cloned()
+                        .unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    /// Opens the heap file of a container, with the options it was created with.
+    fn heap_file(&self, container_id: ContainerId) -> Result<HeapFile, CrustyError> {
+        if !self.containers.contains(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
+        }
+        HeapFile::open_read_only(container_path(&self.dir, container_id), container_id)?
+            .with_config(self.configs.get(&container_id).copied().unwrap_or_default())
+    }
+
+    /// Returns the bytes of the page at a position of a container's heap file.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the page.
+    /// * `page` - Position of the page in the heap file.
+    pub fn page_bytes(
+        &self,
+        container_id: ContainerId,
+        page: PageId,
+    ) -> Result<Vec<u8>, CrustyError> {
+        let hf = self.heap_file(container_id)?;
+        if page >= hf.num_pages() {
+            return Err(CrustyError::CrustyError(format!(
+                "Container {} has {} pages, there is no page {}",
+                container_id,
+                hf.num_pages(),
+                page
+            )));
+        }
+        // compressed files are a sequence of frames, and the pages of a file split into
+        // segments are in the files of their segments, read through the heap file
+        if compression::ENABLED || hf.segment_pages().is_some() {
+            return Ok(hf.read_page_from_file(page)?.to_bytes());
+        }
+        let path = container_path(&self.dir, container_id);
+        let mut f = File::open(&path).map_err(|e| CrustyError::io_with_path(&path, e))?;
+        let mut bytes = vec![0; PAGE_SIZE];
+        f.seek(SeekFrom::Start(page as u64 * PAGE_SIZE as u64))
+            .and_then(|_| f.read_exact(&mut bytes))
+            .map_err(|e| CrustyError::io_with_path(&path, e))?;
+        Ok(bytes)
+    }
+
+    /// Returns the page at a position of a container's heap file, erroring if it is
+    /// corrupted.
+    fn page(&self, container_id: ContainerId, page: PageId) -> Result<Page, CrustyError> {
+        Page::from_bytes(&self.page_bytes(container_id, page)?)
+    }
+
+    /// Returns a summary of the page at a position of a container's heap file, with its
+    /// header and slot table. Errors if it is corrupted.
+    pub fn describe_page(
+        &self,
+        container_id: ContainerId,
+        page: PageId,
+    ) -> Result<PageDescription, CrustyError> {
+        Ok(self.page(container_id, page)?.describe())
+    }
+
+    /// Returns the stored value in a slot of a page.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the page.
+    /// * `page` - Position of the page in the heap file.
+    /// * `slot_id` - Slot of the value.
+    pub fn slot(
+        &self,
+        container_id: ContainerId,
+        page: PageId,
+        slot_id: SlotId,
+    ) -> Result<Vec<u8>, CrustyError> {
+        self.page(container_id, page)?
+            .get_value(slot_id)
+            .ok_or(CrustyError::InvalidSlot {
+                container_id,
+                page_id: Some(page),
+                slot_id: Some(slot_id),
+            })
+    }
+
+    /// Checks every page of a container and returns the corrupted ones.
+    pub fn verify(&self, container_id: ContainerId) -> Result<Vec<PageProblem>, CrustyError> {
+        let hf = self.heap_file(container_id)?;
+        let mut problems = Vec::new();
+        let mut problem = |page, message| {
+            problems.push(PageProblem {
+                container_id,
+                page,
+                message,
+            })
+        };
+        for page in 0..hf.num_pages() {
+            match self.page(container_id, page) {
+                Ok(p) if p.get_page_id() != page => problem(
+                    page,
+                    format!("Page {} is stored at position {}", p.get_page_id(), page),
+                ),
+                Ok(_) => {}
+                Err(e) => problem(page, e.to_string()),
+            }
+        }
+        if !compression::ENABLED {
+            let partial = hf.file_size()? % PAGE_SIZE as u64;
+            if partial != 0 {
+                problem(
+                    hf.num_pages(),
+                    format!("The file ends in a partial page of {} bytes", partial),
+                );
+            }
+        }
+        Ok(problems)
+    }
+}
+
+/// Formats bytes as lines of 16 hex bytes, each preceded by its offset and followed by the
+/// bytes that are printable ASCII.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        write!(out, "{:06x} ", i * 16).unwrap();
+        for byte in line {
+            write!(out, " {:02x}", byte).unwrap();
+        }
+        let text: String = line
+            .iter()
+            .map(|b| match b {
+                0x20..=0x7e => *b as char,
+                _ => '.',
+            })
+            .collect();
+        writeln!(out, "{:pad$}  |{}|", "", text, pad = 3 * (16 - line.len())).unwrap();
+    }
+    out
+}
+
+/// Decodes a stored value as a tuple and formats it one field per line.
+///
+/// # Arguments
+///
+/// * `bytes` - Stored value.
+/// * `schema` - Schema of the table. It names the fields, and fields that do not have the
+///   type of their column are marked. Values are read in the self-describing layout of
+///   `Tuple::to_bytes`, which stored records are written in.
+/// * `mvcc` - Whether the value was stored by a storage manager with MVCC, or in a container
+///   with a time-to-live or a retention window, which prefix it with a record header of the
+///   transactions that created and deleted it and of its insertion and deletion times.
+pub fn decode_value(
+    bytes: &[u8],
+    schema: Option<&TableSchema>,
+    mvcc: bool,
+) -> Result<String, CrustyError> {
+    let mut out = String::new();
+    let mut bytes = bytes;
+    if mvcc {
+        let (header, value) = RecordHeader::read(bytes)?;
+        writeln!(out, "created by transaction {}", header.creator).unwrap();
+        if let Some(deleter) = header.deleter() {
+            writeln!(out, "deleted by transaction {}", deleter).unwrap();
+        }
+        if let Some(inserted) = header.inserted() {
+            writeln!(out, "inserted at {} ms since the epoch", inserted).unwrap();
+        }
+        if let Some(deleted) = header.deletion_time() {
+            writeln!(out, "deleted at {} ms since the epoch", deleted).unwrap();
+        }
+        bytes = value;
+    }
+    let tuple: Tuple = encoding::decode(bytes)?;
+    for (i, field) in tuple.field_vals().enumerate() {
+        let attr = schema.and_then(|schema| schema.get_attribute(i));
+        match attr {
+            Some(attr) if !field.has_type(&attr.dtype) => {
+                writeln!(out, "{}: {} (expected {:?})", attr.name, field, attr.dtype)
+            }
+            Some(attr) => writeln!(out, "{}: {}", attr.name, field),
+            None => writeln!(out, "{}: {}", i, field),
+        }
+        .unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage_manager::StorageManager;
+    use common::ids::{TransactionId, ValueId};
+    use common::storage_trait::StorageTrait;
+    use common::testutil::{gen_random_test_sm_dir, init};
+    use common::{Attribute, DataType, Field};
+    use std::fs::{self, OpenOptions};
+    use std::os::unix::fs::FileExt;
+
+    fn schema() -> TableSchema {
+        TableSchema::new(vec![
+            Attribute::new(String::from("id"), DataType::Int),
+            Attribute::new(String::from("name"), DataType::String),
+        ])
+    }
+
+    /// Stores tuples in two containers and shuts the storage manager down.
+    fn store(path: &Path, mvcc: bool) -> Vec<ValueId> {
+        let sm = StorageManager::new(path.to_path_buf());
+        let sm = if mvcc { sm.with_mvcc() } else { sm };
+        sm.create_table(1).unwrap();
+        sm.create_table(2).unwrap();
+        let tid = TransactionId::new();
+        let ids = (0..1000)
+            .map(|i| {
+                let tuple = Tuple::new(vec![
+                    Field::IntField(i),
+                    Field::StringField(format!("name {}", i)),
+                ]);
+                sm.insert_value(1, tuple.to_bytes(), tid).unwrap()
+            })
+            .collect();
+        if mvcc {
+            sm.commit_transaction(tid).unwrap();
+        }
+        sm.shutdown();
+        ids
+    }
+
+    #[test]
+    fn hs_inspect_containers() {
+        init();
+        let path = gen_random_test_sm_dir();
+        let ids = store(&path, false);
+        let inspector = Inspector::open(&path).unwrap();
+        let containers = inspector.containers().unwrap();
+        assert_eq!(
+            vec![1, 2],
+            containers
+                .iter()
+                .map(|c| c.container_id)
+                .collect::<Vec<_>>()
+        );
+        let pages = containers[0].num_pages;
+        assert!(pages > 1);
+        if !compression::ENABLED {
+            assert_eq!(pages as u64 * PAGE_SIZE as u64, containers[0].file_size);
+        }
+        assert_eq!(0, containers[1].num_pages);
+
+        let d = inspector.describe_page(1, 0).unwrap();
+        assert_eq!(0, d.page_id);
+        let id = ids[0];
+        let value = inspector
+            .slot(1, id.page_id.unwrap(), id.slot_id.unwrap())
+            .unwrap();
+        assert!(decode_value(&value, None, false).unwrap().contains("0: 0"));
+        let decoded = decode_value(&value, Some(&schema()), false).unwrap();
+        assert_eq!("id: 0\nname: name 0\n", decoded);
+        assert!(hex_dump(&value).contains("name 0|"));
+        assert!(inspector.slot(1, 0, 1000).is_err());
+        assert!(inspector.describe_page(1, pages).is_err());
+        assert!(inspector.describe_page(3, 0).is_err());
+        assert!(inspector.verify(1).unwrap().is_empty());
+
+        // without the metadata of shutdown, the heap files are listed
+        fs::remove_file(path.join("c_map")).unwrap();
+        let inspector = Inspector::open(&path).unwrap();
+        assert_eq!(2, inspector.containers().unwrap().len());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn hs_inspect_mvcc_value() {
+        init();
+        let path = gen_random_test_sm_dir();
+        let ids = store(&path, true);
+        let inspector = Inspector::open(&path).unwrap();
+        let id = ids[5];
+        let value = inspector
+            .slot(1, id.page_id.unwrap(), id.slot_id.unwrap())
+            .unwrap();
+        let decoded = decode_value(&value, Some(&schema()), true).unwrap();
+        assert!(decoded.starts_with("created by transaction"));
+        assert!(decoded.ends_with("id: 5\nname: name 5\n"));
+        assert!(decode_value(&value, None, false).is_err());
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[cfg(not(any(feature = "lz4", feature = "snappy")))]
+    #[test]
+    fn hs_inspect_verify_corrupted() {
+        init();
+        let path = gen_random_test_sm_dir();
+        store(&path, false);
+        let file_path = container_path(&path, 1);
+        let f = OpenOptions::new().write(true).open(&file_path).unwrap();
+        // the number of slots of page 1 no longer fits in the page
+        f.write_all_at(&[0xff, 0xff], PAGE_SIZE as u64 + 5).unwrap();
+        // and page 0 claims to be page 7
+        f.write_all_at(&[7, 0], 0).unwrap();
+        // and the last page is torn
+        let len = f.metadata().unwrap().len();
+        f.set_len(len - 100).unwrap();
+        drop(f);
+
+        let inspector = Inspector::open(&path).unwrap();
+        let problems = inspector.verify(1).unwrap();
+        let pages: Vec<PageId> = problems.iter().map(|p| p.page).collect();
+        let last = (len / PAGE_SIZE as u64 - 1) as PageId;
+        assert_eq!(vec![0, 1, last], pages);
+        assert!(problems[0]
+            .message
+            .contains("Page 7 is stored at position 0"));
+        assert!(problems[2].message.contains("partial page"));
+        assert!(inspector.describe_page(1, 1).is_err());
+        // the bytes of a corrupted page can still be dumped
+        assert_eq!(PAGE_SIZE, inspector.page_bytes(1, 1).unwrap().len());
+        fs::remove_dir_all(path).unwrap();
+    }
+}
@@ -2,9 +2,21 @@
 extern crate log;
 #[macro_use]
 extern crate serde;
-mod page;
+pub mod buffer_pool;
+pub mod cdc;
+mod compression;
+mod fault;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 mod heapfile;
 mod heapfileiter;
+pub mod inspect;
+mod mvcc;
+mod page;
+mod record;
+pub mod replication;
 pub mod storage_manager;
 pub mod testutil;
+pub mod wal;
 
+pub use page::{PageDelta, PageDescription, SlotDescription};
@@ -0,0 +1,650 @@
+//! Multi-version concurrency control for a storage manager opened with
+//! `StorageManager::with_mvcc`.
+//!
+//! Every stored value is prefixed with a record header (see the `record` module) holding
+//! the id of the transaction that created it and of the one that deleted it, if any.
+//! Deleting a value only sets its deleter, and updating it deletes the old version and
+//! inserts a new one, so the old version stays readable by the transactions that can still
+//! see it.
+//!
+//! Each transaction reads from a snapshot taken the first time it uses the storage
+//! manager: it sees the versions created by transactions that committed before that, and
+//! its own changes. Reads never wait for writers. A transaction deleting or updating a
+//! version another transaction already deleted or updated, whether that one is still active
+//! or committed after the snapshot, fails with `CrustyError::TransactionRollback` (first
+//! updater wins).
+//!
+//! Transactions run at the `IsolationLevel` given to `begin`, RepeatableRead if they were
+//! not begun explicitly. RepeatableRead is the behaviour above (snapshot isolation).
+//! ReadCommitted takes a new snapshot for every read, so it sees the changes committed since
+//! its previous reads.
+//!
+//! Serializable runs serializable snapshot isolation (SSI). The containers a Serializable
+//! transaction reads are recorded, and so is a rw-antidependency T1 -> T2 when T1 reads a
+//! container a concurrent T2 writes, as T1 does not see the changes of T2 and so must come
+//! before it in any serial order. Such a dependency alone is harmless, but a non-serial
+//! execution, e.g. write skew, always has two consecutive ones T1 -> T2 -> T3 where T3
+//! commits first (T1 and T3 may be the same transaction). A Serializable transaction that
+//! would complete such a structure, as T2 once T3 committed, or as T1 once T2 and T3
+//! committed, fails its commit with `CrustyError::TransactionRollback`. Dependencies are
+//! tracked by container, so transactions touching the same containers may be aborted
+//! without an actual anomaly, but never wait for each other.
+//!
+//! A transaction can also be prepared, the first phase of a two-phase commit run by an
+//! external coordinator: it can make no more changes, a Serializable one is validated, and
+//! it is later committed or aborted as a whole. Until then its changes stay invisible to
+//! other transactions, and updating or deleting a value it updated or deleted fails.
+//!
+//! The table of transaction states is kept in memory only, except for the prepared
+//! transactions the storage manager persists and restores. Transactions it does not know,
+//! e.g. ones from before a restart, are treated as committed before every snapshot.
+
+use crate::record::RecordHeader;
+use common::ids::TidType;
+use common::prelude::*;
+use common::traits::transaction_manager_trait::IsolationLevel;
+use std::collections::{HashMap, HashSet};
+use std::sync::RwLock;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TxnStatus {
+    /// Reading the versions committed up to the snapshot timestamp, or with ReadCommitted
+    /// the ones committed when reading; the snapshot is then the oldest one it may use.
+    Active {
+        snapshot: u64,
+        level: IsolationLevel,
+    },
+    /// Waiting for the coordinator of a two-phase commit to commit or abort it.
+    Prepared,
+    Committed {
+        commit_ts: u64,
+    },
+    Aborted,
+}
+
+/// What a transaction makes of a version.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TxnView {
+    /// The transaction is the reader itself.
+    Own,
+    /// Committed changes the reader's snapshot includes.
+    Visible,
+    /// Changes of an active transaction, or committed after the reader's snapshot.
+    Pending,
+    Aborted,
+}
+
+#[derive(Default)]
+struct TxnTableState {
+    /// Timestamp of the last commit.
+    last_commit: u64,
+    txns: HashMap<TidType, TxnStatus>,
+    /// Containers read by the Serializable transactions, kept after they commit to find the
+    /// rw-antidependencies of the transactions that ran concurrently.
+    reads: HashMap<TidType, HashSet<ContainerId>>,
+    /// Containers written by the transactions, kept after they commit to find the
+    /// rw-antidependencies of the Serializable transactions that ran concurrently.
+    writes: HashMap<TidType, HashSet<ContainerId>>,
+    /// rw-antidependencies by reader: the concurrent transactions that wrote a container it
+    /// read.
+    conflicts_out: HashMap<TidType, HashSet<TidType>>,
+    /// rw-antidependencies by writer: the concurrent transactions that read a container it
+    /// wrote.
+    conflicts_in: HashMap<TidType, HashSet<TidType>>,
+}
+
+impl TxnTableState {
+    /// Returns true if tid ran concurrently with a transaction reading from snapshot: it is
+    /// active or prepared, or committed after the snapshot.
+    fn concurrent(&self, snapshot: u64, tid: TidType) -> bool {
+        match self.txns.get(&tid) {
+            Some(TxnStatus::Active { .. }) | Some(TxnStatus::Prepared) => true,
+            Some(TxnStatus::Committed { commit_ts }) => *commit_ts > snapshot,
+            _ => false,
+        }
+    }
+
+    /// Returns the commit timestamp of tid, `u64::MAX` if it is prepared, as it commits
+    /// after the others, and None if it did not commit.
+    fn commit_ts(&self, tid: TidType) -> Option<u64> {
+        match self.txns.get(&tid) {
+            Some(TxnStatus::Committed { commit_ts }) => Some(*commit_ts),
+            Some(TxnStatus::Prepared) => Some(u64::MAX),
+            _ => None,
+        }
+    }
+
+    /// Record the rw-antidependency reader -> writer.
+    fn add_conflict(&mut self, reader: TidType, writer: TidType) {
+        self.conflicts_out.entry(reader).or_default().insert(writer);
+        self.conflicts_in.entry(writer).or_default().insert(reader);
+    }
+}
+
+/// States of the transactions using a storage manager.
+#[derive(Default)]
+pub(crate) struct TxnTable {
+    state: RwLock<TxnTableState>,
+}
+
+impl TxnTable {
+    /// Start tid at an isolation level, taking its snapshot. Errors if tid already used the
+    /// storage manager.
+    pub fn begin(&self, tid: TransactionId, level: IsolationLevel) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        if state.txns.contains_key(&tid.id()) {
+            return Err(CrustyError::ExecutionError(format!(
+                "Transaction {:?} has already begun",
+                tid
+            )));
+        }
+        let snapshot = state.last_commit;
+        state
+            .txns
+            .insert(tid.id(), TxnStatus::Active { snapshot, level });
+        Ok(())
+    }
+
+    /// Returns the snapshot tid reads from now, beginning tid at RepeatableRead if it is new.
+    /// Errors if tid is finished.
+    pub fn snapshot(&self, tid: TransactionId) -> Result<u64, CrustyError> {
+        let state = self.state.read().unwrap();
+        match state.txns.get(&tid.id()) {
+            Some(TxnStatus::Active { level, .. }) if *level == IsolationLevel::ReadCommitted => {
+                Ok(state.last_commit)
+            }
+            Some(TxnStatus::Active { snapshot, .. }) => Ok(*snapshot),
+            Some(_) => Err(CrustyError::TransactionNotActive),
+            None => {
+                drop(state);
+                // another thread may begin tid in between, which is fine
+                let _ = self.begin(tid, IsolationLevel::RepeatableRead);
+                self.snapshot(tid)
+            }
+        }
+    }
+
+    /// Record that tid reads a container, with its rw-antidependencies on the concurrent
+    /// transactions that wrote it, if tid is Serializable.
+    pub fn record_read(&self, tid: TransactionId, container_id: ContainerId) {
+        let mut state = self.state.write().unwrap();
+        if let Some(TxnStatus::Active {
+            snapshot,
+            level: IsolationLevel::Serializable,
+        }) = state.txns.get(&tid.id()).copied()
+        {
+            state
+                .reads
+                .entry(tid.id())
+                .or_default()
+                .insert(container_id);
+            let writers: Vec<TidType> = state
+                .writes
+                .iter()
+                .filter(|(writer, writes)| {
+                    **writer != tid.id()
+                        && writes.contains(&container_id)
+                        && state.concurrent(snapshot, **writer)
+                })
+                .map(|(writer, _)| *writer)
+                .collect();
+            for writer in writers {
+                state.add_conflict(tid.id(), writer);
+            }
+        }
+    }
+
+    /// Record that tid writes a container, erroring if tid is finished.
+    pub fn record_write(
+        &self,
+        tid: TransactionId,
+        container_id: ContainerId,
+    ) -> Result<(), CrustyError> {
+        self.snapshot(tid)?;
+        let mut state = self.state.write().unwrap();
+        state
+            .writes
+            .entry(tid.id())
+            .or_default()
+            .insert(container_id);
+        // with ReadCommitted the oldest snapshot it used
+        let snapshot = match state.txns.get(&tid.id()) {
+            Some(TxnStatus::Active { snapshot, .. }) => *snapshot,
+            _ => return Ok(()),
+        };
+        let readers: Vec<TidType> = state
+            .reads
+            .iter()
+            .filter(|(reader, reads)| {
+                **reader != tid.id()
+                    && reads.contains(&container_id)
+                    && state.concurrent(snapshot, **reader)
+            })
+            .map(|(reader, _)| *reader)
+            .collect();
+        for reader in readers {
+            state.add_conflict(reader, tid.id());
+        }
+        Ok(())
+    }
+
+    /// Returns true if tid wrote to a container.
+    pub fn has_writes(&self, tid: TransactionId) -> bool {
+        self.state.read().unwrap().writes.contains_key(&tid.id())
+    }
+
+    /// Errors if committing tid would complete a dangerous structure of rw-antidependencies
+    /// T1 -> T2 -> T3 where T3 committed first: tid is T2, a transaction that did not abort
+    /// depends on it and it depends on a committed one, or tid is T1 and depends on a
+    /// committed T2 that depends on a T3 committed before it. Prepared transactions count as
+    /// committed.
+    fn validate(state: &TxnTableState, tid: TransactionId) -> Result<(), CrustyError> {
+        let no_conflicts = HashSet::new();
+        let conflicts_out = |tid| state.conflicts_out.get(&tid).unwrap_or(&no_conflicts);
+        let has_in = state.conflicts_in.get(&tid.id()).is_some_and(|readers| {
+            readers
+                .iter()
+                .any(|reader| state.txns.get(reader) != Some(&TxnStatus::Aborted))
+        });
+        let pivot = conflicts_out(tid.id()).iter().any(|writer| {
+            match state.commit_ts(*writer) {
+                // tid is T2
+                Some(_) if has_in => true,
+                // tid is T1
+                Some(commit_ts) => conflicts_out(*writer)
+                    .iter()
+                    .any(|t3| state.commit_ts(*t3).is_some_and(|ts| ts < commit_ts)),
+                None => false,
+            }
+        });
+        if pivot {
+            return Err(CrustyError::TransactionRollback(tid));
+        }
+        Ok(())
+    }
+
+    /// Commit tid, making its changes visible to the transactions starting after. A
+    /// Serializable transaction that fails validation is aborted instead.
+    pub fn commit(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        match state.txns.get(&tid.id()).copied() {
+            Some(TxnStatus::Active { level, .. }) => {
+                if level == IsolationLevel::Serializable {
+                    if let Err(e) = Self::validate(&state, tid) {
+                        state.txns.insert(tid.id(), TxnStatus::Aborted);
+                        state.reads.remove(&tid.id());
+                        state.writes.remove(&tid.id());
+                        return Err(e);
+                    }
+                }
+                state.last_commit += 1;
+                let commit_ts = state.last_commit;
+                state
+                    .txns
+                    .insert(tid.id(), TxnStatus::Committed { commit_ts });
+                Ok(())
+            }
+            // a transaction that never used the storage manager has nothing to commit
+            None => Ok(()),
+            Some(_) => Err(CrustyError::TransactionNotActive),
+        }
+    }
+
+    /// Prepare tid to commit: it can make no more changes and is committed or aborted with
+    /// `commit_prepared` or `abort_prepared`. A Serializable transaction that fails
+    /// validation is aborted instead.
+    pub fn prepare(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        match state.txns.get(&tid.id()).copied() {
+            Some(TxnStatus::Active { level, .. }) => {
+                if level == IsolationLevel::Serializable {
+                    if let Err(e) = Self::validate(&state, tid) {
+                        state.txns.insert(tid.id(), TxnStatus::Aborted);
+                        state.reads.remove(&tid.id());
+                        state.writes.remove(&tid.id());
+                        return Err(e);
+                    }
+                }
+                state.txns.insert(tid.id(), TxnStatus::Prepared);
+                Ok(())
+            }
+            // a transaction that never used the storage manager has nothing to prepare
+            None => {
+                state.txns.insert(tid.id(), TxnStatus::Prepared);
+                Ok(())
+            }
+            Some(_) => Err(CrustyError::TransactionNotActive),
+        }
+    }
+
+    /// Put back a transaction prepared before a restart, with the containers it wrote.
+    pub fn restore_prepared(&self, tid: TidType, containers: &[ContainerId]) {
+        let mut state = self.state.write().unwrap();
+        state.txns.insert(tid, TxnStatus::Prepared);
+        state
+            .writes
+            .insert(tid, containers.iter().copied().collect());
+    }
+
+    /// Commit a prepared transaction, making its changes visible to the transactions
+    /// starting after.
+    pub fn commit_prepared(&self, tid: TidType) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        if state.txns.get(&tid) != Some(&TxnStatus::Prepared) {
+            return Err(CrustyError::TransactionNotActive);
+        }
+        state.last_commit += 1;
+        let commit_ts = state.last_commit;
+        state.txns.insert(tid, TxnStatus::Committed { commit_ts });
+        Ok(())
+    }
+
+    /// Abort a prepared transaction, like `abort`.
+    pub fn abort_prepared(&self, tid: TidType) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        if state.txns.get(&tid) != Some(&TxnStatus::Prepared) {
+            return Err(CrustyError::TransactionNotActive);
+        }
+        state.txns.insert(tid, TxnStatus::Aborted);
+        state.writes.remove(&tid);
+        Ok(())
+    }
+
+    /// Returns the containers tid wrote to.
+    pub fn writes(&self, tid: TidType) -> Vec<ContainerId> {
+        let state = self.state.read().unwrap();
+        let mut writes: Vec<ContainerId> = state
+            .writes
+            .get(&tid)
+            .map(|writes| writes.iter().copied().collect())
+            .unwrap_or_default();
+        writes.sort_unstable();
+        writes
+    }
+
+    /// Abort tid. Its versions are never visible and its deletes are ignored.
+    pub fn abort(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        let mut state = self.state.write().unwrap();
+        match state.txns.get(&tid.id()) {
+            Some(TxnStatus::Active { .. }) | None => {
+                state.txns.insert(tid.id(), TxnStatus::Aborted);
+                state.reads.remove(&tid.id());
+                state.writes.remove(&tid.id());
+                Ok(())
+            }
+            Some(_) => Err(CrustyError::TransactionNotActive),
+        }
+    }
+
+    fn view(state: &TxnTableState, reader: TidType, snapshot: u64, tid: TidType) -> TxnView {
+        if tid == reader {
+            return TxnView::Own;
+        }
+        match state.txns.get(&tid) {
+            Some(TxnStatus::Committed { commit_ts }) if *commit_ts <= snapshot => TxnView::Visible,
+            Some(TxnStatus::Committed { .. })
+            | Some(TxnStatus::Active { .. })
+            | Some(TxnStatus::Prepared) => TxnView::Pending,
+            Some(TxnStatus::Aborted) => TxnView::Aborted,
+            None => TxnView::Visible,
+        }
+    }
+
+    /// Returns true if the version is in the snapshot of reader.
+    pub fn is_visible(&self, header: &RecordHeader, reader: TidType, snapshot: u64) -> bool {
+        let state = self.state.read().unwrap();
+        let created = matches!(
+            Self::view(&state, reader, snapshot, header.creator),
+            TxnView::Own | TxnView::Visible
+        );
+        let deleted = header.deleter().is_some_and(|deleter| {
+            matches!(
+                Self::view(&state, reader, snapshot, deleter),
+                TxnView::Own | TxnView::Visible
+            )
+        });
+        created && !deleted
+    }
+
+    /// Errors if tid cannot delete the version, because it is not in the snapshot of tid or
+    /// another transaction that did not abort deleted it.
+    pub fn check_delete(
+        &self,
+        header: &RecordHeader,
+        tid: TransactionId,
+    ) -> Result<(), CrustyError> {
+        let snapshot = self.snapshot(tid)?;
+        if !self.is_visible(header, tid.id(), snapshot) {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Value is not visible to the transaction",
+            )));
+        }
+        let state = self.state.read().unwrap();
+        match header.deleter() {
+            Some(deleter)
+                if Self::view(&state, tid.id(), snapshot, deleter) != TxnView::Aborted =>
+            {
+                Err(CrustyError::TransactionRollback(tid))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns what vacuum does with a version: remove it if no transaction can see it, or
+    /// clear its deleter if the deleter aborted.
+    pub fn vacuum_action(&self, header: &RecordHeader) -> VacuumAction {
+        let state = self.state.read().unwrap();
+        match state.txns.get(&header.creator) {
+            Some(TxnStatus::Aborted) => return VacuumAction::Remove,
+            Some(TxnStatus::Active { .. }) | Some(TxnStatus::Prepared) => {
+                return VacuumAction::Keep
+            }
+            _ => {}
+        }
+        match header.deleter().map(|deleter| state.txns.get(&deleter)) {
+            None => VacuumAction::Keep,
+            Some(Some(TxnStatus::Aborted)) => VacuumAction::Undelete,
+            Some(Some(TxnStatus::Committed { commit_ts }))
+                if *commit_ts <= Self::horizon(&state) =>
+            {
+                VacuumAction::Remove
+            }
+            Some(None) => VacuumAction::Remove,
+            Some(_) => VacuumAction::Keep,
+        }
+    }
+
+    /// Oldest snapshot of the active transactions, or the last commit if there are none.
+    fn horizon(state: &TxnTableState) -> u64 {
+        state
+            .txns
+            .values()
+            .filter_map(|status| match status {
+                TxnStatus::Active { snapshot, .. } => Some(*snapshot),
+                _ => None,
+            })
+            .min()
+            .unwrap_or(state.last_commit)
+    }
+
+    /// Forget the transactions that committed before every active snapshot. They are visible
+    /// to every transaction, like the ones the table does not know.
+    pub fn prune(&self) {
+        let mut state = self.state.write().unwrap();
+        let horizon = Self::horizon(&state);
+        state.txns.retain(|_, status| match status {
+            TxnStatus::Committed { commit_ts } => *commit_ts > horizon,
+            _ => true,
+        });
+        let TxnTableState {
+            txns,
+            reads,
+            writes,
+            conflicts_out,
+            conflicts_in,
+            ..
+        } = &mut *state;
+        reads.retain(|tid, _| txns.contains_key(tid));
+        writes.retain(|tid, _| txns.contains_key(tid));
+        for conflicts in [conflicts_out, conflicts_in] {
+            conflicts.retain(|tid, _| txns.contains_key(tid));
+            for others in conflicts.values_mut() {
+                others.retain(|tid| txns.contains_key(tid));
+            }
+        }
+    }
+}
+
+/// What vacuum does with a version.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum VacuumAction {
+    Keep,
+    Remove,
+    /// Clear the deleter of the version.
+    Undelete,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hs_mvcc_visibility() {
+        let txns = TxnTable::default();
+        let (writer, old_reader) = (TransactionId::new(), TransactionId::new());
+        let old_snapshot = txns.snapshot(old_reader).unwrap();
+        let writer_snapshot = txns.snapshot(writer).unwrap();
+        let version = RecordHeader::new(writer);
+        assert!(txns.is_visible(&version, writer.id(), writer_snapshot));
+        assert!(!txns.is_visible(&version, old_reader.id(), old_snapshot));
+
+        txns.commit(writer).unwrap();
+        let new_reader = TransactionId::new();
+        let new_snapshot = txns.snapshot(new_reader).unwrap();
+        assert!(!txns.is_visible(&version, old_reader.id(), old_snapshot));
+        assert!(txns.is_visible(&version, new_reader.id(), new_snapshot));
+        assert!(txns.snapshot(writer).is_err());
+
+        // an aborted delete leaves the version visible
+        let deleter = TransactionId::new();
+        let deleted = version.deleted_by(deleter);
+        txns.snapshot(deleter).unwrap();
+        assert!(matches!(
+            txns.check_delete(&deleted, new_reader),
+            Err(CrustyError::TransactionRollback(_))
+        ));
+        txns.abort(deleter).unwrap();
+        assert!(txns.is_visible(&deleted, new_reader.id(), new_snapshot));
+        assert!(txns.check_delete(&deleted, new_reader).is_ok());
+        assert_eq!(VacuumAction::Undelete, txns.vacuum_action(&deleted));
+    }
+
+    #[test]
+    fn hs_mvcc_vacuum_horizon() {
+        let txns = TxnTable::default();
+        let (writer, reader) = (TransactionId::new(), TransactionId::new());
+        txns.snapshot(reader).unwrap();
+        txns.snapshot(writer).unwrap();
+        let deleted = RecordHeader::new(TransactionId::new()).deleted_by(writer);
+        txns.commit(writer).unwrap();
+        // the reader's snapshot still includes the version
+        assert_eq!(VacuumAction::Keep, txns.vacuum_action(&deleted));
+        txns.commit(reader).unwrap();
+        assert_eq!(VacuumAction::Remove, txns.vacuum_action(&deleted));
+        txns.prune();
+        assert_eq!(VacuumAction::Remove, txns.vacuum_action(&deleted));
+        assert_eq!(
+            VacuumAction::Keep,
+            txns.vacuum_action(&RecordHeader::new(writer))
+        );
+    }
+    #[test]
+    fn hs_mvcc_prepare() {
+        let txns = TxnTable::default();
+        let (writer, reader) = (TransactionId::new(), TransactionId::new());
+        txns.record_write(writer, 1).unwrap();
+        txns.prepare(writer).unwrap();
+        assert!(txns.record_write(writer, 1).is_err());
+        assert!(txns.abort(writer).is_err());
+        assert_eq!(vec![1], txns.writes(writer.id()));
+
+        // a prepared transaction's changes are pending until it commits
+        let version = RecordHeader::new(writer);
+        let snapshot = txns.snapshot(reader).unwrap();
+        assert!(!txns.is_visible(&version, reader.id(), snapshot));
+        assert_eq!(VacuumAction::Keep, txns.vacuum_action(&version));
+        assert!(matches!(
+            txns.check_delete(&RecordHeader::new(reader).deleted_by(writer), reader),
+            Err(CrustyError::TransactionRollback(_))
+        ));
+        txns.commit_prepared(writer.id()).unwrap();
+        assert!(txns.commit_prepared(writer.id()).is_err());
+        let new_reader = TransactionId::new();
+        let snapshot = txns.snapshot(new_reader).unwrap();
+        assert!(txns.is_visible(&version, new_reader.id(), snapshot));
+
+        // a prepared transaction counts as committed for Serializable validation
+        let (prepared, t1, t2) = (
+            TransactionId::new(),
+            TransactionId::new(),
+            TransactionId::new(),
+        );
+        txns.restore_prepared(prepared.id(), &[2]);
+        txns.begin(t1, IsolationLevel::Serializable).unwrap();
+        txns.begin(t2, IsolationLevel::Serializable).unwrap();
+        txns.record_read(t1, 3);
+        txns.record_read(t2, 2);
+        txns.record_write(t2, 3).unwrap();
+        assert!(txns.commit(t2).is_err());
+        txns.abort_prepared(prepared.id()).unwrap();
+        assert_eq!(
+            VacuumAction::Remove,
+            txns.vacuum_action(&RecordHeader::new(prepared))
+        );
+    }
+    #[test]
+    fn hs_mvcc_ssi() {
+        let txns = TxnTable::default();
+        let [t1, t2, t3] = [(); 3].map(|_| TransactionId::new());
+        for tid in [t1, t2, t3] {
+            txns.begin(tid, IsolationLevel::Serializable).unwrap();
+        }
+        // a single rw-antidependency t1 -> t2 is serializable, with t1 first
+        txns.record_read(t1, 1);
+        txns.record_write(t2, 1).unwrap();
+        txns.commit(t2).unwrap();
+        txns.record_write(t1, 2).unwrap();
+        txns.commit(t1).unwrap();
+
+        // t1 -> t2 -> t3 with t3 committing first aborts the pivot t2
+        let [t1, t2, t3] = [(); 3].map(|_| TransactionId::new());
+        for tid in [t1, t2, t3] {
+            txns.begin(tid, IsolationLevel::Serializable).unwrap();
+        }
+        txns.record_read(t1, 1);
+        txns.record_read(t2, 2);
+        txns.record_write(t2, 1).unwrap();
+        txns.record_write(t3, 2).unwrap();
+        txns.commit(t3).unwrap();
+        assert!(matches!(
+            txns.commit(t2),
+            Err(CrustyError::TransactionRollback(_))
+        ));
+        // with t2 aborted, t1 has nothing to conflict with
+        txns.commit(t1).unwrap();
+
+        // once t2 and t3 committed in that order, the t1 closing the structure aborts
+        let [t1, t2, t3] = [(); 3].map(|_| TransactionId::new());
+        for tid in [t1, t2, t3] {
+            txns.begin(tid, IsolationLevel::Serializable).unwrap();
+        }
+        txns.record_read(t2, 2);
+        txns.record_write(t3, 2).unwrap();
+        txns.commit(t3).unwrap();
+        txns.record_write(t2, 1).unwrap();
+        txns.commit(t2).unwrap();
+        txns.record_read(t1, 1);
+        assert!(txns.commit(t1).is_err());
+        txns.prune();
+    }
+}
@@ -1,7 +1,7 @@
 use common::ids::{PageId, SlotId};
-use common::PAGE_SIZE;
+use common::{CrustyError, PAGE_SIZE};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::fs::File;
 use std::hash::Hash;
@@ -16,6 +16,65 @@ use std::{fmt, option};
 pub type Offset = u16;
 // For debug
 const BYTES_PER_LINE: usize = 40;
+// Serialized size of the fixed page metadata: p_id (2), open_slot flag (1) and id (2), num_slots (2)
+pub(crate) const PAGE_METADATA_SIZE: usize = 7;
+// Serialized size of a slot entry: slot id (2), end index (2), length (2)
+pub(crate) const SLOT_ENTRY_SIZE: usize = 6;
+// Flags in the high bits of the serialized length of a slot entry, free as values are
+// shorter than a page. A forwarded slot holds a stub with the page id and slot id its value
+// moved to; a moved-in slot received a moved value, so it is not reused while a stub may
+// forward to it. See `Page::forward`.
+const FORWARDED: Offset = 0x8000;
+const MOVED_IN: Offset = 0x4000;
+const LENGTH_MASK: Offset = 0x3fff;
+// Serialized size of a forwarding stub: page id (2), slot id (2)
+pub(crate) const FORWARD_STUB_SIZE: usize = 4;
+
+/// Returns the page id and the number of slots in the metadata at the start of a serialized
+/// page, so a value can be read without reading the whole page; see `slot_entry_offset`.
+pub(crate) fn read_page_metadata(metadata: &[u8; PAGE_METADATA_SIZE]) -> (PageId, u16) {
+    (
+        PageId::from_le_bytes([metadata[0], metadata[1]]),
+        u16::from_le_bytes([metadata[5], metadata[6]]),
+    )
+}
+
+/// Offset of the entry of a slot in a serialized page. Entries are serialized in slot id
+/// order and a page with n slots has ids 0..n, so the entry of a slot is at a fixed offset.
+pub(crate) fn slot_entry_offset(slot_id: SlotId) -> usize {
+    PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * slot_id as usize
+}
+
+/// Returns the byte range of the value of a slot in a serialized page with num_slots slots,
+/// read from the slot's entry, None if the slot holds no value or a forwarding stub. Errors
+/// if the entry is not the one of the slot or the range is outside the values of the page.
+pub(crate) fn value_range(
+    entry: &[u8; SLOT_ENTRY_SIZE],
+    slot_id: SlotId,
+    num_slots: u16,
+) -> Result<Option<std::ops::Range<usize>>, CrustyError> {
+    let key = SlotId::from_le_bytes([entry[0], entry[1]]);
+    let end = Offset::from_le_bytes([entry[2], entry[3]]) as usize;
+    let len = Offset::from_le_bytes([entry[4], entry[5]]);
+    let (forwarded, len) = (len & FORWARDED != 0, (len & LENGTH_MASK) as usize);
+    if key != slot_id {
+        return Err(CrustyError::CrustyError(format!(
+            "Corrupt page: entry of slot {} is for slot {}",
+            slot_id, key
+        )));
+    }
+    if len == 0 || forwarded {
+        return Ok(None);
+    }
+    let header_end = PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * num_slots as usize;
+    if end >= PAGE_SIZE || len > end + 1 || end + 1 - len < header_end {
+        return Err(CrustyError::CrustyError(format!(
+            "Corrupt page: value of slot {} ({} bytes ending at {}) is outside the page",
+            slot_id, len, end
+        )));
+    }
+    Ok(Some(end + 1 - len..end + 1))
+}
 
 /// Page struct. This must occupy not more than PAGE_SIZE when serialized.
 /// In the header, you are allowed to allocate 8 bytes for general page metadata and
@@ -28,13 +87,17 @@ const BYTES_PER_LINE: usize = 40;
 ///
 /// I built own struct, header, to hold information about the page
 ///
+#[derive(Clone)]
 pub struct Header {
     p_id: PageId,                                // 2 bytes
     open_slot: Option<SlotId>, // None if no open slots, if open_slot not in hash_map, its length and index is given by remaining space.
     slot_map: HashMap<SlotId, (Offset, Offset)>, // slot id maps to its index and its size (6 bytes per entry)
     s_space: Offset, // allocated space for slots ** May have to get rid of this since we need bitmap for deletes**
-                     // or just don't write this var when we serialize but derive it from the hashmap
+    // or just don't write this var when we serialize but derive it from the hashmap
+    forwarded: HashSet<SlotId>, // slots holding a forwarding stub instead of a value
+    moved_in: HashSet<SlotId>, // slots that received a moved value, kept until the forwards are dropped
 }
+#[derive(Clone)]
 pub(crate) struct Page {
     // the metadata for a given page
     header: Header,
@@ -42,6 +105,150 @@ pub(crate) struct Page {
     data: [u8; PAGE_SIZE],
 }
 
+/// Summary of a page for debugging and tooling, returned by `Page::describe`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PageDescription {
+    pub page_id: PageId,
+    /// Slot the next added value goes in, None if the page is full.
+    pub open_slot: Option<SlotId>,
+    /// Bytes of the page metadata and slot table.
+    pub header_size: usize,
+    /// Bytes of the values.
+    pub value_size: usize,
+    /// Bytes between the header and the values, where new values go.
+    pub free_space: usize,
+    /// Bytes that are neither used nor in the free space: the slot entries of deleted
+    /// values, kept to reuse their ids, and any gaps between values.
+    pub wasted_space: usize,
+    /// Share of the unused bytes that are wasted rather than free, from 0 to 1.
+    pub fragmentation: f64,
+    /// The slot table, in order of slot id.
+    pub slots: Vec<SlotDescription>,
+}
+
+/// A slot of a page, see `PageDescription`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SlotDescription {
+    pub slot_id: SlotId,
+    /// Offsets in the page of the first and last bytes of the value, None if it is deleted.
+    pub range: Option<(Offset, Offset)>,
+    /// Length of the value, 0 if it is deleted.
+    pub len: Offset,
+    /// Page id and slot id the value moved to, if the slot holds a forwarding stub.
+    #[serde(default)]
+    pub forward: Option<(PageId, SlotId)>,
+}
+
+impl SlotDescription {
+    /// Returns true if the slot holds a value, false if its value was deleted or moved.
+    pub fn is_live(&self) -> bool {
+        self.range.is_some() && self.forward.is_none()
+    }
+}
+
+impl PageDescription {
+    /// Returns the number of slots holding a value.
+    pub fn live_count(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.is_live()).count()
+    }
+
+    /// Serializes the description as json.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap()
+    }
+}
+
+/// Changed runs closer than this many bytes are merged into one, as a run costs this much
+/// to encode.
+const DELTA_RUN_HEADER_SIZE: usize = 4;
+
+/// The bytes of a page that changed in a write, as runs of (offset, new bytes), returned
+/// by `Page::delta`. Logged or shipped instead of the whole page when a write changes a few
+/// bytes. Applying a delta again is harmless, as it holds the new bytes.
+///
+/// Serialized as
+///
+/// | page id (2) | runs (2) | per run: offset (2) | length (2) | bytes |
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PageDelta {
+    pub page_id: PageId,
+    pub runs: Vec<(Offset, Vec<u8>)>,
+}
+
+impl PageDelta {
+    /// Returns the size of the serialized delta in bytes.
+    pub fn size(&self) -> usize {
+        4 + self
+            .runs
+            .iter()
+            .map(|(_, bytes)| DELTA_RUN_HEADER_SIZE + bytes.len())
+            .sum::<usize>()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.size());
+        bytes.extend_from_slice(&self.page_id.to_le_bytes());
+        bytes.extend_from_slice(&(self.runs.len() as u16).to_le_bytes());
+        for (offset, run) in &self.runs {
+            bytes.extend_from_slice(&offset.to_le_bytes());
+            bytes.extend_from_slice(&(run.len() as u16).to_le_bytes());
+            bytes.extend_from_slice(run);
+        }
+        bytes
+    }
+
+    /// Deserializes a delta written by `to_bytes`. Errors if it is cut short or a run does
+    /// not fit in a page.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CrustyError> {
+        let corrupt =
+            |reason: &str| CrustyError::CrustyError(format!("Corrupt page delta: {}", reason));
+        let read_u16 = |at: usize| -> Result<u16, CrustyError> {
+            bytes
+                .get(at..at + 2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .ok_or_else(|| corrupt("cut short"))
+        };
+        let page_id = read_u16(0)?;
+        let count = read_u16(2)?;
+        let mut at = 4;
+        let mut runs = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let offset = read_u16(at)?;
+            let len = read_u16(at + 2)? as usize;
+            at += DELTA_RUN_HEADER_SIZE;
+            if offset as usize + len > PAGE_SIZE {
+                return Err(corrupt("run past the end of the page"));
+            }
+            let run = bytes
+                .get(at..at + len)
+                .ok_or_else(|| corrupt("cut short"))?;
+            runs.push((offset, run.to_vec()));
+            at += len;
+        }
+        if at != bytes.len() {
+            return Err(corrupt("trailing bytes"));
+        }
+        Ok(PageDelta { page_id, runs })
+    }
+
+    /// Writes the new bytes of the runs into the bytes of the page.
+    pub fn apply(&self, page: &mut [u8]) -> Result<(), CrustyError> {
+        let len = page.len();
+        for (offset, run) in &self.runs {
+            let range = *offset as usize..*offset as usize + run.len();
+            page.get_mut(range)
+                .ok_or_else(|| {
+                    CrustyError::CrustyError(format!(
+                        "Page delta run at {} does not fit in {} bytes",
+                        offset, len
+                    ))
+                })?
+                .copy_from_slice(run);
+        }
+        Ok(())
+    }
+}
+
 /// The functions required for page
 impl Page {
     /*
@@ -67,7 +274,7 @@ impl Page {
         let mut deleted = false;
         // iterate through the hashmap and find the min deleted slot id and max slot id
         for (slot_id, (_idx, len)) in slot_map.iter() {
-            if *len == 0 {
+            if *len == 0 && !self.header.moved_in.contains(slot_id) {
                 if *slot_id < min {
                     min = *slot_id;
                 }
@@ -106,26 +313,18 @@ impl Page {
         // get the length of the value as offset for tuple
         let len = bytes.len() as Offset;
 
-        // get the start index of the value using j and len as usize
-        let i = j - len as usize;
-
-        // if the value doesn't fit, return None, as no insertion can occur
-        // no need to check upperbound since s_space is unsigned int
-
-        // also need to check if there is enough space to add a slot id
-        // if slot_id isn't in the hashmap already
-        // that is what the - 6 is for
-        if i - 6 < self.get_header_size() {
+        // if the value doesn't fit, return None, as no insertion can occur.
+        // a reused slot id already has its 6 header bytes, a new one needs them
+        if self.get_free_space() < self.required_space(slot_id, bytes.len()) {
             return None;
         }
 
+        // get the start index of the value using j and len as usize
+        let i = j - len as usize;
+
         // insert the value into the page
         self.data[i..j].clone_from_slice(bytes);
 
-        // make sure you reuse old slot id's by using a for loop to
-        // iterate through the hashmap and finding keys where the associated
-        // size is 0
-
         // insert the slot id with tuple into the hashmap
         self.header.slot_map.insert(slot_id, (e_idx, len));
 
@@ -135,13 +334,23 @@ impl Page {
         // update the s_space length to include the added slot length
         self.header.s_space += len;
 
-        // print the page
-        // println!("Page after append: {:?}", self);
-
         // return the slot id
         Some(slot_id)
     }
 
+    /*
+    HELPER: Required Space
+    DESCRIPTION: This function returns the number of free bytes needed to store a value
+                of len bytes in slot_id, including a new slot entry if one is needed.
+    */
+    fn required_space(&self, slot_id: SlotId, len: usize) -> usize {
+        if self.header.slot_map.contains_key(&slot_id) {
+            len
+        } else {
+            len + SLOT_ENTRY_SIZE
+        }
+    }
+
     /*
         HELPER: FIRST_SPACE
         DESCRIPTION: this function finds the first open space in that data byte array and
@@ -164,6 +373,8 @@ impl Page {
             open_slot: Some(0),       // since 0 is the first id the tests expect
             slot_map: HashMap::new(), // empty bitmap takes up no space
             s_space: 0,
+            forwarded: HashSet::new(),
+            moved_in: HashSet::new(),
         };
 
         Page {
@@ -195,44 +406,113 @@ impl Page {
 
     #[allow(dead_code)]
     pub fn add_value(&mut self, bytes: &[u8]) -> Option<SlotId> {
-        //header.slot_map.insert(0, (SIZE_OFFSET - 1, 0)); // can't do this
-        if bytes.is_empty() || self.get_free_space() < bytes.len() {
+        // if the open_slot is None, page is full
+        let open_slot = self.header.open_slot?;
+        if bytes.is_empty() || self.get_free_space() < self.required_space(open_slot, bytes.len()) {
             // works since we compact after each deletion
             return None;
         }
 
-        // if the open_slot is None, page is full
-        let open_slot = self.header.open_slot;
-        if open_slot.is_none() {
-            println!("Page Full!");
-            return None;
+        // if the open_slot is not in the hashmap, then it should be appended
+        self.append_slot(open_slot, bytes)
+    }
+
+    /// Adds several values as `add_value` would one after the other, each taking the lowest
+    /// available slot id, but finds the open slots and the free space once and places the
+    /// values next to each other. Returns the slot id of each value, None for the ones that
+    /// did not fit.
+    pub fn add_values(&mut self, values: &[&[u8]]) -> Vec<Option<SlotId>> {
+        if self.header.open_slot.is_none() {
+            return vec![None; values.len()];
+        }
+        // the deleted slots are reused first, lowest first, then new ones are appended
+        let mut reused: Vec<SlotId> = (self.header.slot_map.iter())
+            .filter(|(slot_id, (_, len))| *len == 0 && !self.header.moved_in.contains(slot_id))
+            .map(|(slot_id, _)| *slot_id)
+            .collect();
+        reused.sort_unstable_by(|a, b| b.cmp(a));
+        let mut next_new = match self.header.slot_map.keys().max() {
+            Some(max) => max + 1,
+            None => 0,
+        };
+        let mut free = self.get_free_space();
+        let mut end = PAGE_SIZE - self.header.s_space as usize;
+        let mut slot_ids = Vec::with_capacity(values.len());
+        for bytes in values {
+            let (slot_id, needed) = match reused.last() {
+                Some(slot_id) => (*slot_id, bytes.len()),
+                None if next_new < SlotId::MAX => (next_new, bytes.len() + SLOT_ENTRY_SIZE),
+                None => (SlotId::MAX, usize::MAX),
+            };
+            if bytes.is_empty() || needed > free {
+                slot_ids.push(None);
+                continue;
+            }
+            if reused.pop().is_none() {
+                next_new += 1;
+            }
+            let start = end - bytes.len();
+            self.data[start..end].clone_from_slice(bytes);
+            self.header
+                .slot_map
+                .insert(slot_id, ((end - 1) as Offset, bytes.len() as Offset));
+            end = start;
+            free -= needed;
+            slot_ids.push(Some(slot_id));
         }
+        self.header.s_space = (PAGE_SIZE - end) as Offset;
+        self.header.open_slot = self.find_next_slot();
+        slot_ids
+    }
 
-        // if the open_slot is not in the hashmap, then it should be appended
-        self.append_slot(open_slot.unwrap(), bytes)
+    /// Like `add_value`, but only adds the value if the page is then at most fill_factor
+    /// percent full, counting its header, so the rest is left for values to grow into. A
+    /// page without values takes any value that fits, so values larger than the target are
+    /// still stored. See `ContainerConfig::fill_factor`.
+    pub fn add_value_with_fill_factor(&mut self, bytes: &[u8], fill_factor: u8) -> Option<SlotId> {
+        let open_slot = self.header.open_slot?;
+        let used = PAGE_SIZE - self.get_free_space() + self.required_space(open_slot, bytes.len());
+        if self.header.s_space > 0 && used * 100 > PAGE_SIZE * fill_factor as usize {
+            return None;
+        }
+        self.add_value(bytes)
     }
 
     /// Return the bytes for the slotId. If the slotId is not valid then return None
     pub fn get_value(&self, slot_id: SlotId) -> Option<Vec<u8>> {
-        // if there are no insertions, return none
-        if self.header.slot_map.is_empty() {
+        self.get_value_ref(slot_id).map(<[u8]>::to_vec)
+    }
+
+    /// Like `get_value`, but borrows the bytes from the page instead of copying them.
+    pub fn get_value_ref(&self, slot_id: SlotId) -> Option<&[u8]> {
+        if self.header.forwarded.contains(&slot_id) {
             return None;
         }
-        // get the Optional tuple from the given slotid
-        let tuple = self.header.slot_map.get(&slot_id);
-        if tuple.is_some() {
-            // if there is some tuple, then spit out value
-            let (idx, len) = *self.header.slot_map.get(&slot_id).unwrap();
-            if len == 0 {
-                return None;
-            }
-            let j = idx as usize;
-            let i: usize = j - len as usize + 1;
-            //second index of slice is non-inclusive
-            Some(self.data[i..j + 1].to_vec())
-        } else {
-            None
+        self.slot_bytes(slot_id)
+    }
+
+    /// Returns the bytes stored in a slot, a value or a forwarding stub.
+    fn slot_bytes(&self, slot_id: SlotId) -> Option<&[u8]> {
+        let (idx, len) = *self.header.slot_map.get(&slot_id)?;
+        if len == 0 {
+            return None;
         }
+        let j = idx as usize;
+        let i: usize = j - len as usize + 1;
+        //second index of slice is non-inclusive
+        Some(&self.data[i..j + 1])
+    }
+
+    /// Overwrite the bytes for the slotId with bytes of the same length, keeping the slot.
+    /// Returns None if the slotId is not valid or the length differs.
+    pub fn overwrite_value(&mut self, slot_id: SlotId, bytes: &[u8]) -> Option<()> {
+        let (idx, len) = *self.header.slot_map.get(&slot_id)?;
+        if len == 0 || len as usize != bytes.len() || self.header.forwarded.contains(&slot_id) {
+            return None;
+        }
+        let j = idx as usize;
+        self.data[j + 1 - bytes.len()..j + 1].clone_from_slice(bytes);
+        Some(())
     }
 
     /// Delete the bytes/slot for the slotId. If the slotId is not valid then return None
@@ -241,35 +521,36 @@ impl Page {
     /// HINT: Return Some(()) for a valid delete
     #[allow(dead_code)]
     pub fn delete_value(&mut self, slot_id: SlotId) -> Option<()> {
-        // request the tuple from the slotmap
-        let tuple = self.header.slot_map.get(&slot_id);
-        // if its non-existent, then no delete can occur
-        tuple?;
-        // otherwise we can delete by moving the rest of the array down
-        // by length of the slot
-        let data_start = self.get_header_size();
-        let data_end = (tuple?.0 - tuple?.1) as usize + 1;
-
-        let len = tuple?.1 as usize;
-        // copy slice of data[start to end] to data[start + len to end + len]
-        let moved_data = &self.data[data_start..data_end];
-        let copy = moved_data.to_vec();
+        // request the tuple from the slotmap. if its non-existent or already
+        // deleted, then no delete can occur
+        let (e_idx, len) = *self.header.slot_map.get(&slot_id)?;
+        if len == 0 {
+            return None;
+        }
+        // otherwise we can delete by moving the values stored below the deleted
+        // one up by length of the slot
+        let data_start = PAGE_SIZE - self.header.s_space as usize;
+        let data_end = (e_idx - len) as usize + 1;
+        let len = len as usize;
 
-        self.data[(data_start + len)..(data_end + len)].clone_from_slice(&copy);
-        // iterate through length of usize setting data[start + i] = 0
+        // copy slice of data[start to end] to data[start + len to end + len]
+        self.data
+            .copy_within(data_start..data_end, data_start + len);
+        // zero out the bytes that were freed at the front of the value region
         for i in 0..len {
             self.data[data_start + i] = 0;
         }
 
         // update hashmap indices accordingly
         for tuple in self.header.slot_map.values_mut() {
-            if tuple.0 < data_end as Offset {
+            if tuple.1 != 0 && tuple.0 < data_end as Offset {
                 tuple.0 += len as Offset; // Update the value using a mutable reference
             }
         }
 
         // set the length of the deleted id to zero in the hm
         self.header.slot_map.insert(slot_id, (0, 0));
+        self.header.forwarded.remove(&slot_id);
 
         // check if theres enough space, if so, assign openslot to deleted slot
         // otherwise, set open_slot to none
@@ -283,13 +564,82 @@ impl Page {
         Some(())
     }
 
-    /// Deserialize bytes into Page
+    /// Replace the value in a slot with a forwarding stub to the page id and slot id it moved
+    /// to, or point the stub already in the slot there. Reads of the slot then find no value,
+    /// and the storage manager follows the stub, so the value keeps its id. Returns None,
+    /// leaving the page unchanged, if the slot holds nothing or the stub does not fit.
+    pub(crate) fn forward(&mut self, slot_id: SlotId, to: (PageId, SlotId)) -> Option<()> {
+        let len = self.slot_bytes(slot_id)?.len();
+        if len < FORWARD_STUB_SIZE && self.get_free_space() < FORWARD_STUB_SIZE - len {
+            return None;
+        }
+        let mut stub = [0; FORWARD_STUB_SIZE];
+        stub[..2].copy_from_slice(&to.0.to_le_bytes());
+        stub[2..].copy_from_slice(&to.1.to_le_bytes());
+        self.delete_value(slot_id);
+        self.append_slot(slot_id, &stub);
+        self.header.forwarded.insert(slot_id);
+        Some(())
+    }
+
+    /// Returns the page id and slot id the value of a slot moved to, if the slot holds a
+    /// forwarding stub.
+    pub(crate) fn forwarded_to(&self, slot_id: SlotId) -> Option<(PageId, SlotId)> {
+        if !self.header.forwarded.contains(&slot_id) {
+            return None;
+        }
+        let stub = self.slot_bytes(slot_id)?;
+        Some((
+            PageId::from_le_bytes([stub[0], stub[1]]),
+            SlotId::from_le_bytes([stub[2], stub[3]]),
+        ))
+    }
+
+    /// Returns the forwarding stubs of the page, in slot order: their slot ids, and the page
+    /// id and slot id they forward to.
+    pub(crate) fn forwards(&self) -> Vec<(SlotId, (PageId, SlotId))> {
+        let mut forwards: Vec<_> = (self.header.forwarded.iter())
+            .map(|slot_id| (*slot_id, self.forwarded_to(*slot_id).unwrap()))
+            .collect();
+        forwards.sort_unstable();
+        forwards
+    }
+
+    /// Like `add_value_with_fill_factor`, for a value moved from another page, whose slot is
+    /// not reused once the value is deleted until `clear_forwards`, as a stub may forward to
+    /// it.
+    pub(crate) fn add_moved_value(&mut self, bytes: &[u8], fill_factor: u8) -> Option<SlotId> {
+        let slot_id = self.add_value_with_fill_factor(bytes, fill_factor)?;
+        self.header.moved_in.insert(slot_id);
+        Some(slot_id)
+    }
+
+    /// Delete the forwarding stubs of the page and let the slots that received moved values
+    /// be reused, once nothing refers to the values by their old ids. Returns the number of
+    /// stubs deleted, None if the page has neither.
+    pub(crate) fn clear_forwards(&mut self) -> Option<usize> {
+        if self.header.forwarded.is_empty() && self.header.moved_in.is_empty() {
+            return None;
+        }
+        let stubs: Vec<SlotId> = self.header.forwarded.iter().copied().collect();
+        for slot_id in &stubs {
+            self.delete_value(*slot_id);
+        }
+        self.header.moved_in.clear();
+        self.header.open_slot = self.find_next_slot();
+        Some(stubs.len())
+    }
+
+    /// Deserialize bytes into Page, checking that they are a page written by `to_bytes`:
+    /// PAGE_SIZE bytes whose header fits in the page, whose slot ids are the numbers
+    /// below the number of slots, and whose values lie packed at the end of the page
+    /// without overlapping each other or the header. Errors describing the first
+    /// inconsistency otherwise, e.g. for a page corrupted on disk.
     ///
     /// HINT to create a primitive data type from a slice you can use the following
     /// (the example is for a u16 type and the data store in little endian)
     /// u16::from_le_bytes(data[X..Y].try_into().unwrap());
-    #[allow(dead_code)]
-    pub fn from_bytes(data: &[u8]) -> Self {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, CrustyError> {
         //first 8 bytes are fixed elements of the header
         // - data[0..2] = p_id
         // - data[2..5] = option open_slot
@@ -300,35 +650,116 @@ impl Page {
         // into the struct.data
         // - data[6 + num_slots .. PAGE_SIZE-1] = values
         //
+        if data.len() != PAGE_SIZE {
+            return Err(CrustyError::CrustyError(format!(
+                "Corrupt page: {} bytes instead of {}",
+                data.len(),
+                PAGE_SIZE
+            )));
+        }
 
         // pull in basic info from data to local variables following
         // schema
         let p_id = u16::from_le_bytes(data[0..2].try_into().unwrap());
+        let corrupt =
+            |reason: String| CrustyError::CrustyError(format!("Corrupt page {}: {}", p_id, reason));
         // option data
         let none = data[2];
         let open_slot = u16::from_le_bytes(data[3..5].try_into().unwrap());
         // this value is stored but not represented in our page struct
         let num_slots = u16::from_le_bytes(data[5..7].try_into().unwrap());
-        let mut s_space = 0;
-        let mut slot_map = HashMap::new();
         // set page's open slot
-        let mut option_open_slot = None;
-        if none == 1 {
+        let option_open_slot = match none {
+            0 => None,
             // 1 means something
-            option_open_slot = Some(open_slot);
+            1 => Some(open_slot),
+            flag => return Err(corrupt(format!("open slot flag is {}", flag))),
+        };
+        let header_end = PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * num_slots as usize;
+        if header_end > PAGE_SIZE {
+            return Err(corrupt(format!("{} slots do not fit in a page", num_slots)));
         }
 
         // iterate through bytes using num_slots inserting vals into slot_map
+        let mut slot_map = HashMap::new();
+        let mut forwarded = HashSet::new();
+        let mut moved_in = HashSet::new();
+        // (start, end, slot id) of the stored values
+        let mut values = Vec::new();
         for i in 0..num_slots {
-            let idx = 7 + 6 * i as usize;
+            let idx = PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * i as usize;
             let key = u16::from_le_bytes(data[idx..(idx + 2)].try_into().unwrap());
             let eidx = u16::from_le_bytes(data[(idx + 2)..(idx + 4)].try_into().unwrap());
-            let len = u16::from_le_bytes(data[(idx + 4)..(idx + 6)].try_into().unwrap());
-            slot_map.insert(key, (eidx, len));
+            let flagged_len = u16::from_le_bytes(data[(idx + 4)..(idx + 6)].try_into().unwrap());
+            let len = flagged_len & LENGTH_MASK;
+            // slot ids are handed out lowest first, so a page with n slots has ids 0..n
+            if key >= num_slots {
+                return Err(corrupt(format!(
+                    "slot id {} is not one of its {} slots",
+                    key, num_slots
+                )));
+            }
+            if slot_map.insert(key, (eidx, len)).is_some() {
+                return Err(corrupt(format!("slot {} is listed twice", key)));
+            }
+            if flagged_len & MOVED_IN != 0 {
+                moved_in.insert(key);
+            }
+            if flagged_len & FORWARDED != 0 {
+                if len as usize != FORWARD_STUB_SIZE {
+                    return Err(corrupt(format!(
+                        "forwarding stub of slot {} is {} bytes",
+                        key, len
+                    )));
+                }
+                forwarded.insert(key);
+            }
+            // deleted slots keep their entry with a length of 0
+            if len == 0 {
+                continue;
+            }
+            if eidx as usize >= PAGE_SIZE || len > eidx + 1 {
+                return Err(corrupt(format!(
+                    "value of slot {} ({} bytes ending at {}) is outside the page",
+                    key, len, eidx
+                )));
+            }
+            values.push((eidx + 1 - len, eidx, key));
         }
 
-        for (_key, tuple) in slot_map.clone() {
-            s_space += tuple.1;
+        // values are packed at the end of the page, so they must tile the bytes from
+        // PAGE_SIZE - s_space to the end
+        let s_space: usize = values
+            .iter()
+            .map(|(start, end, _)| (end - start) as usize + 1)
+            .sum();
+        if header_end + s_space > PAGE_SIZE {
+            return Err(corrupt(format!(
+                "{} bytes of values overlap the header of {} bytes",
+                s_space, header_end
+            )));
+        }
+        values.sort_unstable();
+        let mut next_start = PAGE_SIZE - s_space;
+        for (start, end, key) in values {
+            if start as usize != next_start {
+                return Err(corrupt(format!(
+                    "value of slot {} starts at {} instead of {}",
+                    key, start, next_start
+                )));
+            }
+            next_start = end as usize + 1;
+        }
+        if let Some(open_slot) = option_open_slot {
+            if open_slot > num_slots {
+                return Err(corrupt(format!(
+                    "open slot {} is past its {} slots",
+                    open_slot, num_slots
+                )));
+            }
+            if slot_map.get(&open_slot).is_some_and(|(_, len)| *len != 0) {
+                return Err(corrupt(format!("open slot {} holds a value", open_slot)));
+            }
         }
 
         // construct page
@@ -336,18 +767,19 @@ impl Page {
             p_id,
             open_slot: option_open_slot, // since 0 is the first id the tests expect
             slot_map,                    // empty bitmap takes up no space
-            s_space,
+            s_space: s_space as Offset,
+            forwarded,
+            moved_in,
         };
         let mut data_trait: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
-        let len = data.len();
-        data_trait[0..len].clone_from_slice(data);
+        data_trait.clone_from_slice(data);
 
-        Page {
+        Ok(Page {
             // header will be placed into data when serialized
             header,
             // initialize page to all zeros
             data: data_trait,
-        }
+        })
     }
 
     /// Serialize page into a byte array. This must be same size as PAGE_SIZE.
@@ -369,7 +801,7 @@ impl Page {
             res_arr[2] = 0; // 0 means None
         }
 
-        res_arr[3..5].clone_from_slice(&(self.header.open_slot.unwrap().to_le_bytes()));
+        res_arr[3..5].clone_from_slice(&(self.header.open_slot.unwrap_or(0).to_le_bytes()));
 
         res_arr[5..7].clone_from_slice(&((self.header.slot_map.len() as Offset).to_le_bytes()));
 
@@ -385,7 +817,14 @@ impl Page {
         for key in keys {
             res_arr[idx..(idx + 2)].clone_from_slice(&key.to_le_bytes());
             res_arr[(idx + 2)..(idx + 4)].clone_from_slice(&map[&key].0.to_le_bytes());
-            res_arr[(idx + 4)..(idx + 6)].clone_from_slice(&map[&key].1.to_le_bytes());
+            let mut len = map[&key].1;
+            if self.header.forwarded.contains(&key) {
+                len |= FORWARDED;
+            }
+            if self.header.moved_in.contains(&key) {
+                len |= MOVED_IN;
+            }
+            res_arr[(idx + 4)..(idx + 6)].clone_from_slice(&len.to_le_bytes());
 
             /*
 
@@ -407,13 +846,10 @@ impl Page {
     #[allow(dead_code)]
     pub(crate) fn get_header_size(&self) -> usize {
         /*
-        since each element in the vector is 2 bytes, the total space taken by the
-        header is 2 * size of vector.
+        the fixed part of the header is the page id, the open slot flag and id,
+        and the number of slots. each slot then takes SLOT_ENTRY_SIZE bytes.
          */
-        6 * self.header.slot_map.len()
-            + self.header.p_id.to_le_bytes().len()
-            + serde_cbor::to_vec(&self.header.open_slot).unwrap().len()
-            + self.header.s_space.to_le_bytes().len()
+        PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * self.header.slot_map.len()
     }
 
     /// A utility function to determine the total current free space in the page.
@@ -424,9 +860,68 @@ impl Page {
         PAGE_SIZE - self.get_header_size() - self.header.s_space as usize
     }
 
+    /// Returns the slot table, in order of slot id: where the value of each slot is and
+    /// its length, or that it was deleted. Deleted slots keep their entry until their id is
+    /// reused.
+    pub fn slots(&self) -> Vec<SlotDescription> {
+        let mut slots: Vec<SlotDescription> = self
+            .header
+            .slot_map
+            .iter()
+            .map(|(slot_id, (end, len))| SlotDescription {
+                slot_id: *slot_id,
+                range: (*len != 0).then(|| (end + 1 - len, *end)),
+                len: *len,
+                forward: self.forwarded_to(*slot_id),
+            })
+            .collect();
+        slots.sort_by_key(|slot| slot.slot_id);
+        slots
+    }
+
+    /// Returns the number of values stored in the page, leaving out the deleted and moved
+    /// ones.
+    pub fn live_count(&self) -> usize {
+        let stored = (self.header.slot_map.values())
+            .filter(|(_, len)| *len != 0)
+            .count();
+        stored - self.header.forwarded.len()
+    }
+
+    /// Returns a summary of the page: its slot table with the offset and length of each
+    /// value, and how much of it is free or wasted.
+    pub fn describe(&self) -> PageDescription {
+        let slots = self.slots();
+        let header_size = self.get_header_size();
+        let value_size: usize = slots.iter().map(|slot| slot.len as usize).sum();
+        let values_start = slots
+            .iter()
+            .filter_map(|slot| slot.range.map(|(start, _)| start as usize))
+            .min()
+            .unwrap_or(PAGE_SIZE);
+        let free_space = values_start.saturating_sub(header_size);
+        let dead_slots = slots.len() - self.live_count();
+        let wasted_space =
+            dead_slots * SLOT_ENTRY_SIZE + (PAGE_SIZE - values_start).saturating_sub(value_size);
+        let unused = free_space + wasted_space;
+        PageDescription {
+            page_id: self.header.p_id,
+            open_slot: self.header.open_slot,
+            header_size,
+            value_size,
+            free_space,
+            wasted_space,
+            fragmentation: if unused == 0 {
+                0.0
+            } else {
+                wasted_space as f64 / unused as f64
+            },
+            slots,
+        }
+    }
+
     /// Utility function for comparing the bytes of another page.
     /// Returns a vec  of Offset and byte diff
-    #[allow(dead_code)]
     pub fn compare_page(&self, other_page: Vec<u8>) -> Vec<(Offset, Vec<u8>)> {
         let mut res = Vec::new();
         let bytes = self.to_bytes();
@@ -448,16 +943,44 @@ impl Page {
                 in_diff = false;
             }
         }
+        // a diff running to the end of the page
+        if in_diff {
+            res.push((diff_start as Offset, diff_vec));
+        }
         res
     }
+
+    /// Returns the bytes of the page that differ from before, the bytes of the page as it
+    /// was, as a delta that turns before into the page. Runs separated by fewer bytes than
+    /// it costs to start a run are merged.
+    pub fn delta(&self, before: &[u8]) -> PageDelta {
+        let bytes = self.to_bytes();
+        let mut runs: Vec<(Offset, Vec<u8>)> = Vec::new();
+        for (offset, diff) in self.compare_page(before.to_vec()) {
+            match runs.last_mut() {
+                Some((start, run))
+                    if offset as usize - (*start as usize + run.len()) < DELTA_RUN_HEADER_SIZE =>
+                {
+                    let end = offset as usize + diff.len();
+                    run.extend_from_slice(&bytes[*start as usize + run.len()..end]);
+                }
+                _ => runs.push((offset, diff)),
+            }
+        }
+        PageDelta {
+            page_id: self.get_page_id(),
+            runs,
+        }
+    }
 }
 
 /// The (consuming) iterator struct for a page.
 /// This should iterate through all valid values of the page.
 pub struct PageIntoIter {
     page: Page,
-    next_slot: SlotId,
-    max_slot: SlotId,
+    /// The slot ids holding a value not returned yet, in ascending order. Slot ids are
+    /// not dense, so they are taken from the slot map rather than counted up to its size.
+    slots: std::vec::IntoIter<SlotId>,
 }
 
 /// The implementation of the (consuming) page iterator.
@@ -467,34 +990,17 @@ impl Iterator for PageIntoIter {
     type Item = (Vec<u8>, SlotId);
 
     fn next(&mut self) -> Option<Self::Item> {
-        // if next_slot is greater than max_slot, return None
-        if self.next_slot > self.max_slot {
-            return None;
-        }
-        // otherwise, get the tuple from the slot_map if the second value is not 0
-        // if it is 0, move to next slot and get that tuple unless we exceed max slot
-        let slot_id = self.next_slot;
-        let wrapped_tuple = self.page.header.slot_map.get(&slot_id);
-        // if key is not in slot_map, then we want to skip this slot
-        if wrapped_tuple.is_none() {
-            self.next_slot += 1;
-            return self.next();
-        }
-        // otherwise, if it is in the slotmap, but its deleted then we also want
-        // to skip it
-        let tuple = wrapped_tuple.unwrap();
-        if tuple.1 == 0 {
-            // we want to skip this slot
-            self.next_slot += 1;
-            return self.next();
-        }
-        // if its non-zero, then we have a valid slot and want to return the
-        // byte array for it
-        let val = self.page.get_value(slot_id).unwrap();
-
-        // get next slot id by checkinig the slot map and the prev_slots
-        self.next_slot += 1;
-        Some((val, slot_id))
+        let (val, slot_id) = self.next_ref()?;
+        Some((val.to_vec(), slot_id))
+    }
+}
+
+impl PageIntoIter {
+    /// Like `next`, but borrows the bytes of the value from the page, for callers that
+    /// copy only some of them.
+    pub(crate) fn next_ref(&mut self) -> Option<(&[u8], SlotId)> {
+        let slot_id = self.slots.next()?;
+        Some((self.page.get_value_ref(slot_id).unwrap(), slot_id))
     }
 }
 
@@ -506,10 +1012,15 @@ impl IntoIterator for Page {
     type IntoIter = PageIntoIter;
 
     fn into_iter(self) -> Self::IntoIter {
+        // deleted slots keep their entry with a length of 0, and moved values leave a stub
+        let mut slots: Vec<SlotId> = (self.header.slot_map.iter())
+            .filter(|(slot_id, (_, len))| *len != 0 && !self.header.forwarded.contains(slot_id))
+            .map(|(slot_id, _)| *slot_id)
+            .collect();
+        slots.sort_unstable();
         PageIntoIter {
-            max_slot: self.header.slot_map.len() as SlotId,
             page: self,
-            next_slot: 0,
+            slots: slots.into_iter(),
         }
     }
 }
@@ -588,9 +1099,54 @@ impl fmt::Debug for Page {
     }
 }
 
+impl fmt::Display for PageDescription {
+    /// Prints the sizes of the page, then its slot table.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Page {}: {} slots, {} live, open slot {}",
+            self.page_id,
+            self.slots.len(),
+            self.live_count(),
+            self.open_slot
+                .map_or_else(|| String::from("none"), |slot| slot.to_string())
+        )?;
+        writeln!(
+            f,
+            "header {} bytes, values {} bytes, free {} bytes, wasted {} bytes, fragmentation {:.1}%",
+            self.header_size,
+            self.value_size,
+            self.free_space,
+            self.wasted_space,
+            self.fragmentation * 100.0
+        )?;
+        writeln!(f, "{:>6} {:>6} {:>6} {:>6}", "slot", "start", "end", "len")?;
+        for slot in &self.slots {
+            match (slot.range, slot.forward) {
+                (Some((start, end)), Some((page_id, slot_id))) => writeln!(
+                    f,
+                    "{:>6} {:>6} {:>6} {:>6} moved to page {} slot {}",
+                    slot.slot_id, start, end, slot.len, page_id, slot_id
+                )?,
+                (Some((start, end)), None) => writeln!(
+                    f,
+                    "{:>6} {:>6} {:>6} {:>6}",
+                    slot.slot_id, start, end, slot.len
+                )?,
+                (None, _) => writeln!(
+                    f,
+                    "{:>6} {:>6} {:>6} {:>6} deleted",
+                    slot.slot_id, "-", "-", "-"
+                )?,
+            }
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::VecDeque;
+    use std::collections::{BTreeMap, VecDeque};
     use std::fmt::write;
     use std::fmt::Debug;
 
@@ -701,6 +1257,13 @@ mod tests {
 
         //Check that invalid slot gets None
         assert_eq!(None, p.get_value(2));
+
+        // borrowed values are the same bytes
+        assert_eq!(Some(&tuple_bytes[..]), p.get_value_ref(0));
+        assert_eq!(Some(&tuple_bytes2[..]), p.get_value_ref(1));
+        assert_eq!(None, p.get_value_ref(2));
+        p.delete_value(0).unwrap();
+        assert_eq!(None, p.get_value_ref(0));
     }
 
     #[test]
@@ -780,6 +1343,67 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hs_page_add_values() {
+        init();
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let mut p = Page::new(0);
+            for value in get_random_vec_of_byte_vec(rng.gen_range(0..20), 10, 300) {
+                p.add_value(&value);
+            }
+            for slot_id in 0..20 {
+                if rng.gen_bool(0.3) {
+                    p.delete_value(slot_id);
+                }
+            }
+            let mut values = get_random_vec_of_byte_vec(rng.gen_range(1..40), 1, 600);
+            values.insert(rng.gen_range(0..values.len()), Vec::new());
+            let refs: Vec<&[u8]> = values.iter().map(Vec::as_slice).collect();
+
+            // the same slots and bytes as adding the values one by one
+            let mut one_by_one = p.clone();
+            let expected: Vec<Option<SlotId>> =
+                refs.iter().map(|v| one_by_one.add_value(v)).collect();
+            assert_eq!(expected, p.add_values(&refs));
+            assert_eq!(one_by_one.to_bytes(), p.to_bytes());
+            assert_eq!(one_by_one.get_free_space(), p.get_free_space());
+            for (value, slot_id) in values.iter().zip(&expected) {
+                if let Some(slot_id) = slot_id {
+                    assert_eq!(Some(value), p.get_value(*slot_id).as_ref());
+                }
+            }
+            assert_eq!(one_by_one.add_value(&[1; 10]), p.add_value(&[1; 10]));
+        }
+
+        // nothing fits in a full page
+        let mut p = Page::new(0);
+        assert_eq!(
+            Some(0),
+            p.add_value(&vec![1; p.get_free_space() - SLOT_ENTRY_SIZE])
+        );
+        assert_eq!(vec![None, None], p.add_values(&[&[1], &[2]]));
+    }
+
+    #[test]
+    fn hs_page_fill_factor() {
+        init();
+        let mut p = Page::new(0);
+        let bytes = get_random_byte_vec(PAGE_SIZE / 4);
+        // a page without values takes a value past the target
+        assert_eq!(Some(0), p.add_value_with_fill_factor(&bytes, 10));
+        assert_eq!(None, p.add_value_with_fill_factor(&bytes, 10));
+        // two values and their header fill a bit more than half the page
+        assert_eq!(None, p.add_value_with_fill_factor(&bytes, 50));
+        assert_eq!(Some(1), p.add_value_with_fill_factor(&bytes, 60));
+        let used = PAGE_SIZE - p.get_free_space();
+        assert!(used * 100 <= PAGE_SIZE * 60);
+        // a full fill factor is add_value
+        assert_eq!(Some(2), p.add_value_with_fill_factor(&bytes, 100));
+        assert_eq!(None, p.add_value_with_fill_factor(&bytes, 100));
+        assert_eq!(None, p.add_value(&bytes));
+    }
+
     #[test]
     pub fn hs_page_get_first_free_space() {
         init();
@@ -929,7 +1553,7 @@ mod tests {
         //Get bytes and create from bytes
         let bytes = p.to_bytes();
         println!("{:?}", p);
-        let mut p2 = Page::from_bytes(&bytes);
+        let mut p2 = Page::from_bytes(&bytes).unwrap();
         assert_eq!(0, p2.get_page_id());
 
         //Check reads
@@ -989,14 +1613,14 @@ mod tests {
         assert_eq!(None, iter.next());
 
         //Check another way
-        let p = Page::from_bytes(&page_bytes);
+        let p = Page::from_bytes(&page_bytes).unwrap();
         assert_eq!(Some(tuple_bytes.clone()), p.get_value(0));
 
         for (i, x) in p.into_iter().enumerate() {
             assert_eq!(tup_vec[i], x.0);
         }
 
-        let p = Page::from_bytes(&page_bytes);
+        let p = Page::from_bytes(&page_bytes).unwrap();
         let mut count = 0;
         for _ in p {
             count += 1;
@@ -1004,7 +1628,7 @@ mod tests {
         assert_eq!(count, 4);
 
         //Add a value and check
-        let mut p = Page::from_bytes(&page_bytes);
+        let mut p = Page::from_bytes(&page_bytes).unwrap();
         assert_eq!(Some(4), p.add_value(&tuple_bytes));
         //get the updated bytes
         let page_bytes = p.to_bytes();
@@ -1015,7 +1639,7 @@ mod tests {
         assert_eq!(count, 5);
 
         //Delete
-        let mut p = Page::from_bytes(&page_bytes);
+        let mut p = Page::from_bytes(&page_bytes).unwrap();
         p.delete_value(2);
         let mut iter = p.into_iter();
         assert_eq!(Some((tuple_bytes.clone(), 0)), iter.next());
@@ -1025,6 +1649,53 @@ mod tests {
         assert_eq!(None, iter.next());
     }
 
+    #[test]
+    fn hs_page_iter_delete_insert() {
+        init();
+        let values = get_random_vec_of_byte_vec(12, 20, 100);
+        let mut p = Page::new(0);
+        let mut expected = BTreeMap::new();
+        for value in &values[..8] {
+            let slot_id = p.add_value(value).unwrap();
+            expected.insert(slot_id, value.clone());
+        }
+        // delete low slots, then insert more values than were freed, the last ones
+        // getting slot ids past the number of values stored
+        for slot_id in [0, 2, 3, 6] {
+            p.delete_value(slot_id).unwrap();
+            expected.remove(&slot_id);
+        }
+        for value in &values[8..10] {
+            let slot_id = p.add_value(value).unwrap();
+            expected.insert(slot_id, value.clone());
+        }
+        p.delete_value(7).unwrap();
+        expected.remove(&7);
+        for value in &values[10..] {
+            let slot_id = p.add_value(value).unwrap();
+            expected.insert(slot_id, value.clone());
+        }
+        let check = |p: Page, expected: &BTreeMap<SlotId, Vec<u8>>| {
+            // in ascending slot order
+            let stored: Vec<(Vec<u8>, SlotId)> = p.into_iter().collect();
+            let expected: Vec<(Vec<u8>, SlotId)> =
+                expected.iter().map(|(s, v)| (v.clone(), *s)).collect();
+            assert_eq!(expected, stored);
+        };
+        check(Page::from_bytes(&p.to_bytes()).unwrap(), &expected);
+        check(p.clone(), &expected);
+
+        // slot ids past the number of entries in the slot map are returned too
+        for slot_id in [1, 4] {
+            p.delete_value(slot_id).unwrap();
+            expected.remove(&slot_id);
+        }
+        p.header.slot_map.retain(|_, (_, len)| *len != 0);
+        let last = *expected.keys().last().unwrap();
+        assert!(p.header.slot_map.len() < last as usize);
+        check(p, &expected);
+    }
+
     #[test]
     pub fn hs_page_test_delete_reclaim_same_size() {
         init();
@@ -1044,6 +1715,22 @@ mod tests {
         assert_eq!(values[5], p.get_value(1).unwrap());
     }
 
+    #[test]
+    pub fn hs_page_test_overwrite_value() {
+        init();
+        let values = get_ascending_vec_of_byte_vec_02x(3, 20, 20);
+        let mut p = Page::new(0);
+        assert_eq!(Some(0), p.add_value(&values[0]));
+        assert_eq!(Some(1), p.add_value(&values[1]));
+        assert_eq!(Some(()), p.overwrite_value(0, &values[2]));
+        assert_eq!(values[2], p.get_value(0).unwrap());
+        assert_eq!(values[1], p.get_value(1).unwrap());
+        assert_eq!(None, p.overwrite_value(1, &values[2][..10]));
+        assert_eq!(None, p.overwrite_value(2, &values[2]));
+        let p2 = Page::from_bytes(&p.to_bytes()).unwrap();
+        assert_eq!(values[2], p2.get_value(0).unwrap());
+    }
+
     #[test]
     pub fn hs_page_test_delete_reclaim_larger_size() {
         init();
@@ -1114,7 +1801,7 @@ mod tests {
         assert_eq!(Some(1), p.add_value(&values[1]));
         assert_eq!(Some(2), p.add_value(&values[2]));
         let bytes = p.to_bytes();
-        let mut p2 = Page::from_bytes(&bytes);
+        let mut p2 = Page::from_bytes(&bytes).unwrap();
         assert_eq!(values[0], p2.get_value(0).unwrap());
         assert_eq!(values[1], p2.get_value(1).unwrap());
         assert_eq!(values[2], p2.get_value(2).unwrap());
@@ -1122,7 +1809,7 @@ mod tests {
         assert_eq!(Some(4), p2.add_value(&values[4]));
 
         let bytes2 = p2.to_bytes();
-        let mut p3 = Page::from_bytes(&bytes2);
+        let mut p3 = Page::from_bytes(&bytes2).unwrap();
         assert_eq!(values[0], p3.get_value(0).unwrap());
         assert_eq!(values[1], p3.get_value(1).unwrap());
         assert_eq!(values[2], p3.get_value(2).unwrap());
@@ -1134,13 +1821,240 @@ mod tests {
         assert_eq!(None, p3.add_value(&values[0]));
 
         let bytes3 = p3.to_bytes();
-        let p4 = Page::from_bytes(&bytes3);
+        let p4 = Page::from_bytes(&bytes3).unwrap();
         assert_eq!(values[0], p4.get_value(0).unwrap());
         assert_eq!(values[1], p4.get_value(1).unwrap());
         assert_eq!(values[2], p4.get_value(2).unwrap());
         assert_eq!(values[7], p4.get_value(7).unwrap());
     }
 
+    #[test]
+    pub fn hs_page_describe() {
+        init();
+        let mut p = Page::new(2);
+        p.add_value(&get_random_byte_vec(30)).unwrap();
+        p.add_value(&get_random_byte_vec(20)).unwrap();
+        p.add_value(&get_random_byte_vec(10)).unwrap();
+        p.delete_value(1).unwrap();
+        let d = p.describe();
+        assert_eq!(2, d.page_id);
+        assert_eq!(Some(1), d.open_slot);
+        assert_eq!(p.get_header_size(), d.header_size);
+        assert_eq!(40, d.value_size);
+        assert_eq!(p.get_free_space(), d.free_space);
+        // the entry of the deleted slot is kept
+        assert_eq!(6, d.wasted_space);
+        assert_eq!(6.0 / (6 + d.free_space) as f64, d.fragmentation);
+        let p_end = PAGE_SIZE as Offset - 1;
+        assert_eq!(
+            vec![
+                SlotDescription {
+                    slot_id: 0,
+                    range: Some((p_end - 29, p_end)),
+                    len: 30,
+                    forward: None,
+                },
+                SlotDescription {
+                    slot_id: 1,
+                    range: None,
+                    len: 0,
+                    forward: None,
+                },
+                SlotDescription {
+                    slot_id: 2,
+                    range: Some((p_end - 39, p_end - 30)),
+                    len: 10,
+                    forward: None,
+                },
+            ],
+            d.slots
+        );
+        let json = d.to_json();
+        assert_eq!(3, json["slots"].as_array().unwrap().len());
+        assert_eq!(d, serde_json::from_value(json).unwrap());
+        let table = d.to_string();
+        assert!(table.contains("3 slots, 2 live, open slot 1"));
+        assert!(table.contains("deleted"));
+
+        let d = Page::new(0).describe();
+        assert_eq!(PAGE_SIZE - d.header_size, d.free_space);
+        assert_eq!(0.0, d.fragmentation);
+    }
+
+    #[test]
+    fn hs_page_forward() {
+        init();
+        let mut p = Page::new(0);
+        let vals: Vec<Vec<u8>> = (0..4).map(|_| get_random_byte_vec(20)).collect();
+        for val in &vals {
+            p.add_value(val).unwrap();
+        }
+        let free = p.get_free_space();
+        p.forward(1, (3, 7)).unwrap();
+        assert_eq!(free + 20 - FORWARD_STUB_SIZE, p.get_free_space());
+        assert_eq!(None, p.get_value(1));
+        assert!(p.overwrite_value(1, &[0; FORWARD_STUB_SIZE]).is_none());
+        assert_eq!(Some((3, 7)), p.forwarded_to(1));
+        assert_eq!(None, p.forwarded_to(0));
+        assert_eq!(3, p.live_count());
+        assert_eq!(Some((3, 7)), p.slots()[1].forward);
+        assert!(!p.slots()[1].is_live());
+        let slots: Vec<SlotId> = p.clone().into_iter().map(|(_, slot)| slot).collect();
+        assert_eq!(vec![0, 2, 3], slots);
+        // a stub is pointed elsewhere in place, and a deleted slot cannot forward
+        p.forward(1, (4, 2)).unwrap();
+        assert_eq!(vec![(1, (4, 2))], p.forwards());
+        p.delete_value(3).unwrap();
+        assert!(p.forward(3, (4, 3)).is_none());
+
+        // moved values keep their slot once deleted, until the forwards are cleared
+        let moved = p.add_moved_value(&vals[3], 100).unwrap();
+        assert_eq!(3, moved);
+        p.delete_value(moved).unwrap();
+        assert_eq!(Some(4), p.add_value(&vals[0]));
+
+        // stubs and moved slots survive serialization, and a read of the slot finds no value
+        let bytes = p.to_bytes();
+        let mut p2 = Page::from_bytes(&bytes).unwrap();
+        assert_eq!(bytes, p2.to_bytes());
+        assert_eq!(vec![(1, (4, 2))], p2.forwards());
+        let entry: [u8; SLOT_ENTRY_SIZE] = bytes[slot_entry_offset(1)..slot_entry_offset(2)]
+            .try_into()
+            .unwrap();
+        assert_eq!(None, value_range(&entry, 1, 5).unwrap());
+        assert_eq!(Some(5), p2.add_value(&vals[1]));
+
+        assert_eq!(Some(1), p2.clear_forwards());
+        assert_eq!(None, p2.clear_forwards());
+        assert!(p2.forwards().is_empty());
+        assert_eq!(Some(1), p2.add_value(&vals[1]));
+        assert_eq!(Some(3), p2.add_value(&vals[3]));
+    }
+
+    #[test]
+    fn hs_page_slots() {
+        init();
+        let mut p = Page::new(0);
+        assert!(p.slots().is_empty());
+        assert_eq!(0, p.live_count());
+        for _ in 0..4 {
+            p.add_value(&get_random_byte_vec(20)).unwrap();
+        }
+        p.delete_value(1).unwrap();
+        p.delete_value(3).unwrap();
+        assert_eq!(2, p.live_count());
+        let slots = p.slots();
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            slots.iter().map(|s| s.slot_id).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![true, false, true, false],
+            slots.iter().map(|s| s.is_live()).collect::<Vec<_>>()
+        );
+        // each live slot locates its value
+        for slot in slots.iter().filter(|s| s.is_live()) {
+            let (start, end) = slot.range.unwrap();
+            assert_eq!(slot.len, end - start + 1);
+            assert_eq!(
+                p.get_value(slot.slot_id).unwrap(),
+                p.to_bytes()[start as usize..=end as usize]
+            );
+        }
+
+        // a reused slot is live again, and the counts survive serialization
+        assert_eq!(Some(1), p.add_value(&get_random_byte_vec(5)));
+        let p = Page::from_bytes(&p.to_bytes()).unwrap();
+        assert_eq!(3, p.live_count());
+        assert_eq!(5, p.slots()[1].len);
+        assert_eq!(p.slots(), p.describe().slots);
+        assert_eq!(p.live_count(), p.describe().live_count());
+    }
+
+    #[test]
+    pub fn hs_page_from_bytes_corrupt() {
+        init();
+        let mut p = Page::new(5);
+        p.add_value(&get_random_byte_vec(30)).unwrap();
+        p.add_value(&get_random_byte_vec(20)).unwrap();
+        p.add_value(&get_random_byte_vec(10)).unwrap();
+        p.delete_value(1).unwrap();
+        let bytes = p.to_bytes();
+        assert!(Page::from_bytes(&bytes).is_ok());
+        // slot entries are (id, end index, length) at 7 + 6 * slot
+        let entry = |slot: usize, field: usize| 7 + 6 * slot + 2 * field;
+        let corrupt = |offset: usize, value: u16| {
+            let mut bytes = bytes.clone();
+            bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+            Page::from_bytes(&bytes)
+        };
+
+        assert!(Page::from_bytes(&bytes[..PAGE_SIZE - 1]).is_err());
+        assert!(Page::from_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+        let mut bad_flag = bytes.clone();
+        bad_flag[2] = 2;
+        assert!(Page::from_bytes(&bad_flag).is_err());
+        // more slots than fit in the page
+        assert!(corrupt(5, 1000).is_err());
+        // a slot id past the number of slots, and a slot listed twice
+        assert!(corrupt(entry(2, 0), 3).is_err());
+        assert!(corrupt(entry(2, 0), 0).is_err());
+        // a value ending past the page, and one longer than the bytes before its end
+        assert!(corrupt(entry(0, 1), PAGE_SIZE as u16).is_err());
+        assert!(corrupt(entry(0, 2), PAGE_SIZE as u16).is_err());
+        // values that overlap, leave a gap, or reach into the header
+        assert!(corrupt(entry(2, 1), PAGE_SIZE as u16 - 1).is_err());
+        assert!(corrupt(entry(2, 1), PAGE_SIZE as u16 - 40).is_err());
+        assert!(corrupt(entry(0, 2), PAGE_SIZE as u16 - 20).is_err());
+        // an open slot holding a value, or past the slots
+        assert!(corrupt(3, 0).is_err());
+        assert!(corrupt(3, 4).is_err());
+        assert!(corrupt(3, 3).is_ok());
+    }
+
+    #[test]
+    pub fn hs_page_delta() {
+        init();
+        let mut p = Page::new(3);
+        p.add_value(&get_random_byte_vec(30)).unwrap();
+        p.add_value(&get_random_byte_vec(20)).unwrap();
+        let before = p.to_bytes();
+        assert!(p.delta(&before).runs.is_empty());
+
+        // an overwritten value and the slot entry of a new one differ
+        p.overwrite_value(0, &get_random_byte_vec(30));
+        p.add_value(&get_random_byte_vec(10)).unwrap();
+        let delta = p.delta(&before);
+        assert!(!delta.runs.is_empty());
+        assert!(delta.size() < 100);
+        let mut bytes = before.clone();
+        delta.apply(&mut bytes).unwrap();
+        assert_eq!(p.to_bytes(), bytes);
+        // applying it again changes nothing
+        delta.apply(&mut bytes).unwrap();
+        assert_eq!(p.to_bytes(), bytes);
+        assert_eq!(delta, PageDelta::from_bytes(&delta.to_bytes()).unwrap());
+        assert_eq!(delta.size(), delta.to_bytes().len());
+
+        // a change in the last byte of the page, and runs close together merged
+        let mut after = before.clone();
+        after[PAGE_SIZE - 1] ^= 1;
+        after[PAGE_SIZE - 4] ^= 1;
+        let delta = Page::from_bytes(&after).unwrap().delta(&before);
+        assert_eq!(1, delta.runs.len());
+        assert_eq!(
+            (PAGE_SIZE as Offset - 4, after[PAGE_SIZE - 4..].to_vec()),
+            delta.runs[0]
+        );
+
+        let bytes = delta.to_bytes();
+        assert!(PageDelta::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(PageDelta::from_bytes(&[bytes.clone(), vec![0]].concat()).is_err());
+        let mut past_end = bytes.clone();
+        past_end[4..6].copy_from_slice(&(PAGE_SIZE as u16 - 2).to_le_bytes());
+        assert!(PageDelta::from_bytes(&past_end).is_err());
+    }
+
     #[test]
     pub fn hs_page_stress_test() {
         init();
@@ -1172,7 +2086,7 @@ mod tests {
         }
         // let (check_vals, check_slots): (Vec<Vec<u8>>, Vec<SlotId>) = p.into_iter().map(|(a, b)| (a, b)).unzip();
         let bytes = p.to_bytes();
-        let p_clone = Page::from_bytes(&bytes);
+        let p_clone = Page::from_bytes(&bytes).unwrap();
         let mut check_vals: Vec<Vec<u8>> = p_clone.into_iter().map(|(a, _)| a).collect();
         assert!(compare_unordered_byte_vecs(&stored_vals, check_vals));
         trace!("\n==================\n PAGE LOADED - now going to delete to make room as needed \n =======================");
@@ -1189,7 +2103,7 @@ mod tests {
                         stored_slots.push(new_slot);
                         stored_vals.push(bytes.clone());
                         let bytes = p.to_bytes();
-                        let p_clone = Page::from_bytes(&bytes);
+                        let p_clone = Page::from_bytes(&bytes).unwrap();
                         check_vals = p_clone.into_iter().map(|(a, _)| a).collect();
                         assert!(compare_unordered_byte_vecs(&stored_vals, check_vals));
                         trace!("Added new value ({}) {:?}", new_slot, stored_slots);
@@ -1213,3 +2127,144 @@ mod tests {
         }
     }
 }
+
+/// Property tests: random sequences of adds, deletes and overwrites are applied to a page
+/// and to a model of its slots, and the page is checked against the model after each one.
+#[cfg(test)]
+mod prop_tests {
+    use super::*;
+    use proptest::prelude::*;
+    use std::collections::BTreeMap;
+
+    #[derive(Clone, Debug)]
+    enum Op {
+        Add(Vec<u8>),
+        /// Deletes the value at this position among the stored ones, modulo their number.
+        Delete(usize),
+        /// Overwrites the value at this position with bytes of the same length.
+        Overwrite(usize, u8),
+        /// Deletes a slot that holds no value.
+        DeleteMissing(SlotId),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            // small values fill the header with slots, large ones the body
+            3 => prop::collection::vec(any::<u8>(), 1..64).prop_map(Op::Add),
+            2 => prop::collection::vec(any::<u8>(), 64..1200).prop_map(Op::Add),
+            3 => any::<usize>().prop_map(Op::Delete),
+            1 => (any::<usize>(), any::<u8>()).prop_map(|(i, b)| Op::Overwrite(i, b)),
+            1 => any::<SlotId>().prop_map(Op::DeleteMissing),
+        ]
+    }
+
+    /// Model of a page: the values by slot, and the number of slot ids ever used, which
+    /// keep their header entry after their value is deleted.
+    #[derive(Default)]
+    struct Model {
+        values: BTreeMap<SlotId, Vec<u8>>,
+        slots: usize,
+    }
+
+    impl Model {
+        /// Slot the next added value gets: the lowest freed one, else a new one.
+        fn next_slot(&self) -> SlotId {
+            (0..self.slots as SlotId)
+                .find(|s| !self.values.contains_key(s))
+                .unwrap_or(self.slots as SlotId)
+        }
+
+        fn header_size(&self) -> usize {
+            PAGE_METADATA_SIZE + SLOT_ENTRY_SIZE * self.slots
+        }
+
+        fn free_space(&self) -> usize {
+            PAGE_SIZE - self.header_size() - self.values.values().map(Vec::len).sum::<usize>()
+        }
+
+        fn nth_slot(&self, i: usize) -> Option<SlotId> {
+            let n = self.values.len();
+            (n > 0).then(|| *self.values.keys().nth(i % n).unwrap())
+        }
+    }
+
+    fn apply(page: &mut Page, model: &mut Model, op: Op) -> Result<(), TestCaseError> {
+        match op {
+            Op::Add(bytes) => {
+                let slot_id = model.next_slot();
+                let new_slot = slot_id as usize == model.slots;
+                let needed = bytes.len() + if new_slot { SLOT_ENTRY_SIZE } else { 0 };
+                match page.add_value(&bytes) {
+                    Some(added) => {
+                        prop_assert_eq!(slot_id, added);
+                        prop_assert!(needed <= model.free_space());
+                        if new_slot {
+                            model.slots += 1;
+                        }
+                        model.values.insert(slot_id, bytes);
+                    }
+                    None => prop_assert!(needed > model.free_space()),
+                }
+            }
+            Op::Delete(i) => {
+                if let Some(slot_id) = model.nth_slot(i) {
+                    prop_assert_eq!(Some(()), page.delete_value(slot_id));
+                    model.values.remove(&slot_id);
+                    prop_assert_eq!(None, page.delete_value(slot_id));
+                }
+            }
+            Op::Overwrite(i, byte) => {
+                if let Some(slot_id) = model.nth_slot(i) {
+                    let value = model.values.get_mut(&slot_id).unwrap();
+                    let bytes = vec![byte; value.len()];
+                    prop_assert_eq!(Some(()), page.overwrite_value(slot_id, &bytes));
+                    let mut longer = bytes.clone();
+                    longer.push(byte);
+                    prop_assert_eq!(None, page.overwrite_value(slot_id, &longer));
+                    *value = bytes;
+                }
+            }
+            Op::DeleteMissing(slot_id) => {
+                if !model.values.contains_key(&slot_id) {
+                    prop_assert_eq!(None, page.delete_value(slot_id));
+                    prop_assert_eq!(None, page.get_value(slot_id));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the page against the model, directly and after a round trip through bytes.
+    fn check(page: &Page, model: &Model) -> Result<(), TestCaseError> {
+        prop_assert_eq!(model.header_size(), page.get_header_size());
+        prop_assert_eq!(model.free_space(), page.get_free_space());
+        prop_assert!(page.get_header_size() + page.get_free_space() <= PAGE_SIZE);
+
+        let bytes = page.to_bytes();
+        prop_assert_eq!(PAGE_SIZE, bytes.len());
+        let copy = Page::from_bytes(&bytes).unwrap();
+        prop_assert_eq!(&bytes, &copy.to_bytes());
+        prop_assert_eq!(page.get_page_id(), copy.get_page_id());
+        prop_assert_eq!(page.get_header_size(), copy.get_header_size());
+        prop_assert_eq!(page.get_free_space(), copy.get_free_space());
+        for (slot_id, value) in &model.values {
+            prop_assert_eq!(Some(value.clone()), page.get_value(*slot_id));
+            prop_assert_eq!(Some(value.clone()), copy.get_value(*slot_id));
+        }
+        let stored: BTreeMap<SlotId, Vec<u8>> = copy.into_iter().map(|(v, s)| (s, v)).collect();
+        prop_assert_eq!(&model.values, &stored);
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn hs_page_prop_ops(page_id in any::<PageId>(), ops in prop::collection::vec(op(), 1..200)) {
+            let mut page = Page::new(page_id);
+            let mut model = Model::default();
+            for op in ops {
+                apply(&mut page, &mut model, op)?;
+                check(&page, &model)?;
+            }
+        }
+    }
+}
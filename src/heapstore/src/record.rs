@@ -0,0 +1,333 @@
+//! Per-record header of the values stored by a storage manager with MVCC, or in a container
+//! with a time-to-live or a retention window for deleted values.
+//!
+//! Each stored value is prefixed with a header, inside the page body, that holds:
+//!
+//! | format (1) | flags (1) | creator tid (8) | deleter tid (8) | [insertion time (8)] |
+//! [deletion time (8)] | value |
+//!
+//! The format is the version of the header layout, so later layouts can tell old records
+//! apart. The flags mark a logically deleted record (a tombstone) whose deleter is set; the
+//! deleter bytes of a record that was not deleted are ignored. They also mark a record with
+//! an insertion time, in milliseconds since the Unix epoch, which the records of containers
+//! with a `ContainerConfig::ttl` have, and one with a deletion time, which the records of
+//! containers with a `ContainerConfig::retention` have. The deletion time is 0 until the
+//! record is deleted, so deleting a record keeps its length. The length of the value is the
+//! length of the slot minus the header, so it is not repeated. Readers of the storage
+//! manager get the value without the header, see `Visibility`.
+
+use crate::mvcc::TxnTable;
+use common::ids::TidType;
+use common::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Format of the record headers written.
+pub(crate) const RECORD_FORMAT: u8 = 1;
+/// Serialized size of a record header without an insertion time.
+pub(crate) const RECORD_HEADER_SIZE: usize = 18;
+/// Flag of a record deleted by the deleter.
+const TOMBSTONE: u8 = 0x1;
+/// Flag of a record followed by its insertion time.
+const TIMESTAMPED: u8 = 0x2;
+/// Flag of a record followed by its deletion time.
+const RETAINED: u8 = 0x4;
+
+/// Returns the current time in milliseconds since the Unix epoch.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+/// The transactions that created and deleted a record, and when it was inserted and deleted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RecordHeader {
+    pub creator: TidType,
+    /// None if the record was not deleted.
+    deleter: Option<TidType>,
+    /// Milliseconds since the Unix epoch, None if the record does not expire.
+    inserted: Option<u64>,
+    /// Milliseconds since the Unix epoch, 0 until the record is deleted, None if deleted
+    /// records are not retained.
+    deletion_time: Option<u64>,
+}
+
+impl RecordHeader {
+    /// Header of a record created by tid.
+    pub fn new(tid: TransactionId) -> Self {
+        RecordHeader {
+            creator: tid.id(),
+            deleter: None,
+            inserted: None,
+            deletion_time: None,
+        }
+    }
+
+    /// Returns a copy of the header, inserted at a time in milliseconds since the epoch.
+    pub fn inserted_at(self, millis: u64) -> Self {
+        RecordHeader {
+            inserted: Some(millis),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the header with room for a deletion time, set by `deleted_at`.
+    pub fn retained(self) -> Self {
+        RecordHeader {
+            deletion_time: Some(0),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the header deleted at a time in milliseconds since the epoch, if it
+    /// has room for it, see `retained`. The deleter is set by `deleted_by`.
+    pub fn deleted_at(self, millis: u64) -> Self {
+        RecordHeader {
+            deletion_time: self.deletion_time.map(|_| millis),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the header, deleted by tid.
+    pub fn deleted_by(self, tid: TransactionId) -> Self {
+        RecordHeader {
+            deleter: Some(tid.id()),
+            ..self
+        }
+    }
+
+    /// Returns a copy of the header, not deleted.
+    pub fn undeleted(self) -> Self {
+        RecordHeader {
+            deleter: None,
+            deletion_time: self.deletion_time.map(|_| 0),
+            ..self
+        }
+    }
+
+    /// Returns the deleter, None if the record was not deleted.
+    pub fn deleter(&self) -> Option<TidType> {
+        self.deleter
+    }
+
+    /// Returns the insertion time in milliseconds since the epoch, None if the record does
+    /// not expire.
+    pub fn inserted(&self) -> Option<u64> {
+        self.inserted
+    }
+
+    /// Returns the deletion time in milliseconds since the epoch, None if the record was not
+    /// deleted or deleted records are not retained.
+    pub fn deletion_time(&self) -> Option<u64> {
+        self.deleter.and(self.deletion_time)
+    }
+
+    /// Returns true if the record was deleted after retained_after, which is None when
+    /// deleted records are not retained, so it is kept for `StorageManager::undelete`.
+    pub fn is_retained(&self, retained_after: Option<u64>) -> bool {
+        matches!((self.deletion_time(), retained_after), (Some(deleted), Some(after)) if deleted > after)
+    }
+
+    /// Returns true if the record was inserted at or before expired_until, which is None
+    /// when nothing expires. Records without an insertion time never expire.
+    pub fn is_expired(&self, expired_until: Option<u64>) -> bool {
+        matches!((self.inserted, expired_until), (Some(inserted), Some(until)) if inserted <= until)
+    }
+
+    /// Prefix value with the header.
+    pub fn wrap(&self, value: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_SIZE + 16 + value.len());
+        let mut flags = 0;
+        if self.deleter.is_some() {
+            flags |= TOMBSTONE;
+        }
+        if self.inserted.is_some() {
+            flags |= TIMESTAMPED;
+        }
+        if self.deletion_time.is_some() {
+            flags |= RETAINED;
+        }
+        bytes.push(RECORD_FORMAT);
+        bytes.push(flags);
+        bytes.extend_from_slice(&self.creator.to_le_bytes());
+        bytes.extend_from_slice(&self.deleter.unwrap_or(0).to_le_bytes());
+        if let Some(inserted) = self.inserted {
+            bytes.extend_from_slice(&inserted.to_le_bytes());
+        }
+        if let Some(deletion_time) = self.deletion_time {
+            bytes.extend_from_slice(&deletion_time.to_le_bytes());
+        }
+        bytes.extend_from_slice(value);
+        bytes
+    }
+
+    /// Split a stored record into its header and value, checking the header.
+    pub fn read(bytes: &[u8]) -> Result<(Self, &[u8]), CrustyError> {
+        let corrupt = |message: String| Err(CrustyError::CrustyError(message));
+        if bytes.len() < RECORD_HEADER_SIZE {
+            return corrupt(format!("A record of {} bytes has no header", bytes.len()));
+        }
+        if bytes[0] != RECORD_FORMAT {
+            return corrupt(format!("Unknown record format {}", bytes[0]));
+        }
+        let flags = bytes[1];
+        let read_u64 = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let mut size = RECORD_HEADER_SIZE;
+        let mut times = [None, None];
+        for (time, (flag, name)) in times
+            .iter_mut()
+            .zip([(TIMESTAMPED, "insertion"), (RETAINED, "deletion")])
+        {
+            if flags & flag == 0 {
+                continue;
+            }
+            if bytes.len() < size + 8 {
+                return corrupt(format!(
+                    "A record of {} bytes has no {} time",
+                    bytes.len(),
+                    name
+                ));
+            }
+            *time = Some(read_u64(size));
+            size += 8;
+        }
+        let header = RecordHeader {
+            creator: read_u64(2),
+            deleter: (flags & TOMBSTONE != 0).then(|| read_u64(10)),
+            inserted: times[0],
+            deletion_time: times[1],
+        };
+        Ok((header, &bytes[size..]))
+    }
+
+    /// Split a record the storage manager stored into its header and value.
+    pub fn unwrap(bytes: &[u8]) -> (Self, &[u8]) {
+        Self::read(bytes).unwrap()
+    }
+}
+
+/// Filter applied by the readers of a storage manager to the records of a container with
+/// record headers: the ones in the snapshot of the reader with MVCC, or not deleted without
+/// it, and not expired in a container with a time-to-live. Values are returned without
+/// their headers.
+#[derive(Clone)]
+pub(crate) struct Visibility {
+    /// With MVCC, the transactions, the reader and its snapshot.
+    pub snapshot: Option<(Arc<TxnTable>, TidType, u64)>,
+    /// Records inserted at or before this time have expired, see `RecordHeader::is_expired`.
+    pub expired_until: Option<u64>,
+}
+
+impl Visibility {
+    /// Returns the filter for a container whose records expire after ttl, if it has one.
+    pub fn new(snapshot: Option<(Arc<TxnTable>, TidType, u64)>, ttl: Option<Duration>) -> Self {
+        Visibility {
+            snapshot,
+            expired_until: ttl.map(expired_until),
+        }
+    }
+
+    /// Returns the value of a stored record, without its header, if it is visible.
+    pub fn value<'a>(&self, bytes: &'a [u8]) -> Option<&'a [u8]> {
+        let (header, value) = RecordHeader::unwrap(bytes);
+        if header.is_expired(self.expired_until) {
+            return None;
+        }
+        let visible = match &self.snapshot {
+            Some((txns, tid, snapshot)) => txns.is_visible(&header, *tid, *snapshot),
+            None => header.deleter().is_none(),
+        };
+        visible.then_some(value)
+    }
+
+    /// Like `value`, but takes the stored record and strips its header in place.
+    pub fn read(&self, mut bytes: Vec<u8>) -> Option<Vec<u8>> {
+        let len = self.value(&bytes)?.len();
+        bytes.drain(..bytes.len() - len);
+        Some(bytes)
+    }
+}
+
+/// Returns the time up to which the records of a container with a time-to-live of ttl
+/// have expired, now.
+pub(crate) fn expired_until(ttl: Duration) -> u64 {
+    now_millis().saturating_sub(ttl.as_millis() as u64)
+}
+
+/// Returns the time after which the records deleted in a container with a retention window
+/// of retention are retained, now.
+pub(crate) fn retained_after(retention: Duration) -> u64 {
+    now_millis().saturating_sub(retention.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hs_record_header_bytes() {
+        let tid = TransactionId::new();
+        let header = RecordHeader::new(tid);
+        assert_eq!(None, header.deleter());
+        let bytes = header.wrap(&[1, 2, 3]);
+        assert_eq!(RECORD_HEADER_SIZE + 3, bytes.len());
+        assert_eq!((RECORD_FORMAT, 0), (bytes[0], bytes[1]));
+        assert_eq!((header, &[1, 2, 3][..]), RecordHeader::unwrap(&bytes));
+
+        let deleted = header.deleted_by(tid);
+        assert_eq!(Some(tid.id()), deleted.deleter());
+        let bytes = deleted.wrap(&[1, 2, 3]);
+        assert_eq!(TOMBSTONE, bytes[1]);
+        assert_eq!((deleted, &[1, 2, 3][..]), RecordHeader::unwrap(&bytes));
+        assert_eq!(header, deleted.undeleted());
+
+        // an empty value, a header cut short and an unknown format
+        assert_eq!(RECORD_HEADER_SIZE, header.wrap(&[]).len());
+        assert!(RecordHeader::read(&bytes[..RECORD_HEADER_SIZE - 1]).is_err());
+        let mut bytes = bytes;
+        bytes[0] = RECORD_FORMAT + 1;
+        assert!(RecordHeader::read(&bytes).is_err());
+
+        // an insertion time follows the deleter
+        let inserted = header.inserted_at(1000).deleted_by(tid);
+        assert_eq!(Some(1000), inserted.inserted());
+        let bytes = inserted.wrap(&[1, 2, 3]);
+        assert_eq!(RECORD_HEADER_SIZE + 8 + 3, bytes.len());
+        assert_eq!(TOMBSTONE | TIMESTAMPED, bytes[1]);
+        assert_eq!((inserted, &[1, 2, 3][..]), RecordHeader::unwrap(&bytes));
+        assert!(RecordHeader::read(&bytes[..RECORD_HEADER_SIZE + 7]).is_err());
+
+        // expiry
+        assert!(inserted.is_expired(Some(1000)));
+        assert!(!inserted.is_expired(Some(999)));
+        assert!(!inserted.is_expired(None));
+        assert!(!header.is_expired(Some(u64::MAX)));
+
+        // a deletion time follows the insertion time, and is kept when the record is deleted
+        let retained = header.inserted_at(1000).retained();
+        let bytes = retained.wrap(&[1, 2, 3]);
+        assert_eq!(RECORD_HEADER_SIZE + 16 + 3, bytes.len());
+        assert_eq!(TIMESTAMPED | RETAINED, bytes[1]);
+        assert_eq!(None, retained.deletion_time());
+        let deleted = retained.deleted_by(tid).deleted_at(2000);
+        assert_eq!(Some(2000), deleted.deletion_time());
+        assert_eq!(Some(1000), deleted.inserted());
+        let bytes = deleted.wrap(&[1, 2, 3]);
+        assert_eq!(RECORD_HEADER_SIZE + 16 + 3, bytes.len());
+        assert_eq!((deleted, &[1, 2, 3][..]), RecordHeader::unwrap(&bytes));
+        assert!(RecordHeader::read(&bytes[..RECORD_HEADER_SIZE + 15]).is_err());
+        assert_eq!(retained, deleted.undeleted());
+        assert_eq!(
+            None,
+            header.deleted_by(tid).deleted_at(2000).deletion_time()
+        );
+
+        // retention
+        assert!(deleted.is_retained(Some(1999)));
+        assert!(!deleted.is_retained(Some(2000)));
+        assert!(!deleted.is_retained(None));
+        assert!(!retained.is_retained(Some(0)));
+    }
+}
@@ -0,0 +1,195 @@
+//! Physical replication of a storage manager with a WAL to a standby.
+//!
+//! The primary ships its WAL entries, page writes included, to a standby storage manager
+//! that applies them as they arrive, so the standby's heap files stay byte-for-byte copies
+//! of the primary's. A standby is set up by:
+//!
+//! 1. subscribing to the primary's entries with `StorageManager::replication_stream`,
+//! 2. backing the primary up with `StorageManager::backup`,
+//! 3. restoring the backup on the standby with `StorageManager::start_standby`,
+//! 4. shipping the entries to the standby, with `apply` when both are in the same process,
+//!    or with `send` and `receive` over a socket.
+//!
+//! Subscribing before the backup means no entry is missed; the ones logged before the
+//! backup are skipped by the standby. The standby rejects other changes until it is failed
+//! over to with `StorageManager::promote`.
+
+use crate::storage_manager::StorageManager;
+use crate::wal::Lsn;
+use common::CrustyError;
+use std::io::{ErrorKind, Read, Write};
+use std::sync::mpsc::Receiver;
+
+/// Apply entries received from a primary's `replication_stream` to a standby, e.g. the
+/// ones available in the channel with `try_iter`, or, with `iter`, all of them until the
+/// primary is dropped. Returns the LSN of the last entry applied, None if there were none.
+pub fn apply(
+    entries: impl IntoIterator<Item = Vec<u8>>,
+    standby: &StorageManager,
+) -> Result<Option<Lsn>, CrustyError> {
+    let mut lsn = None;
+    for entry in entries {
+        lsn = Some(standby.apply_replicated(&entry)?);
+    }
+    Ok(lsn)
+}
+
+/// Write the entries received from a primary's `replication_stream` to a socket or other
+/// writer, for a standby to read with `receive`, until the primary is dropped.
+pub fn send(entries: &Receiver<Vec<u8>>, writer: &mut impl Write) -> Result<(), CrustyError> {
+    for entry in entries.iter() {
+        writer.write_all(&entry)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Read the entries written by `send` from a socket or other reader and apply them to a
+/// standby, until the writer is closed. Returns the LSN of the last entry applied, None if
+/// there were none.
+pub fn receive(
+    reader: &mut impl Read,
+    standby: &StorageManager,
+) -> Result<Option<Lsn>, CrustyError> {
+    let mut lsn = None;
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(lsn),
+            Err(e) => return Err(e.into()),
+        }
+        let mut entry = vec![0; 4 + u32::from_le_bytes(len) as usize];
+        entry[..4].copy_from_slice(&len);
+        reader.read_exact(&mut entry[4..])?;
+        lsn = Some(standby.apply_replicated(&entry)?);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage_manager::container_path;
+    use crate::wal::WalConfig;
+    use common::ids::{ContainerId, Permissions, TransactionId, ValueId};
+    use common::storage_trait::StorageTrait;
+    use common::testutil::*;
+    use std::fs;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    const RO: Permissions = Permissions::ReadOnly;
+
+    /// Asserts the standby holds the primary's containers, with the same heap file bytes.
+    fn assert_replicated(primary: &StorageManager, standby: &StorageManager) {
+        let ids = |sm: &StorageManager| -> Vec<ContainerId> {
+            let containers = sm.list_containers().unwrap();
+            containers.iter().map(|c| c.container_id).collect()
+        };
+        assert_eq!(ids(primary), ids(standby));
+        for cid in ids(primary) {
+            let bytes = fs::read(container_path(&primary.storage_path, cid)).unwrap();
+            let standby_bytes = fs::read(container_path(&standby.storage_path, cid)).unwrap();
+            assert!(bytes == standby_bytes);
+        }
+    }
+
+    /// Changes made to the primary after the standby's base backup.
+    fn change(sm: &StorageManager, vals: &[Vec<u8>]) {
+        let t = TransactionId::new();
+        let ids: Vec<ValueId> = vals[..100]
+            .iter()
+            .map(|v| sm.insert_value(1, v.clone(), t).unwrap())
+            .collect();
+        for id in &ids[..30] {
+            sm.delete_value(*id, t).unwrap();
+        }
+        sm.update_value(vals[100].clone(), ids[50], t).unwrap();
+        sm.create_table(2).unwrap();
+        sm.insert_values(2, vals[100..].to_vec(), t).unwrap();
+    }
+
+    #[test]
+    fn hs_replication_channel() {
+        init();
+        let primary = StorageManager::new_test_sm()
+            .with_wal(WalConfig::default())
+            .unwrap();
+        let standby = StorageManager::new_test_sm();
+        let t = TransactionId::new();
+        let vals = get_random_vec_of_byte_vec(200, 50, 100);
+        assert!(standby.replication_stream().is_err());
+        assert!(standby.apply_replicated(&[0; 8]).is_err());
+
+        primary.create_table(1).unwrap();
+        primary.insert_values(1, vals[..50].to_vec(), t).unwrap();
+        let entries = primary.replication_stream().unwrap();
+        let backup_path = gen_random_test_sm_dir();
+        primary.backup(&backup_path).unwrap();
+        standby.start_standby(&backup_path).unwrap();
+        assert!(standby.is_standby());
+        assert_replicated(&primary, &standby);
+
+        change(&primary, &vals);
+        let lsn = apply(entries.try_iter(), &standby).unwrap();
+        assert_eq!(primary.wal_lsn(), lsn);
+        assert_replicated(&primary, &standby);
+        assert_eq!(None, apply(entries.try_iter(), &standby).unwrap());
+
+        // A standby only takes the primary's changes, until it is promoted
+        assert!(standby.insert_value(1, vals[0].clone(), t).is_err());
+        assert!(standby.create_table(3).is_err());
+        standby.promote().unwrap();
+        assert!(!standby.is_standby());
+        assert!(standby.promote().is_err());
+        standby.insert_value(1, vals[0].clone(), t).unwrap();
+        assert_eq!(
+            primary.get_iterator(1, t, RO).count() + 1,
+            standby.get_iterator(1, t, RO).count()
+        );
+        fs::remove_dir_all(backup_path).unwrap();
+    }
+
+    #[test]
+    fn hs_replication_tcp() {
+        init();
+        let primary = StorageManager::new_test_sm()
+            .with_wal(WalConfig::default())
+            .unwrap();
+        let standby = StorageManager::new_test_sm();
+        let t = TransactionId::new();
+        let vals = get_random_vec_of_byte_vec(200, 50, 100);
+        primary.create_table(1).unwrap();
+        primary.insert_values(1, vals[..50].to_vec(), t).unwrap();
+        let entries = primary.replication_stream().unwrap();
+        let backup_path = gen_random_test_sm_dir();
+        primary.backup(&backup_path).unwrap();
+        standby.start_standby(&backup_path).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sender = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            send(&entries, &mut stream).unwrap();
+        });
+        let (mut stream, _) = listener.accept().unwrap();
+        change(&primary, &vals);
+        let lsn = primary.wal_lsn();
+
+        // Dropping the primary ends the stream, so keep a copy of its heap files
+        let copy = StorageManager::new_test_sm();
+        let copy_path = gen_random_test_sm_dir();
+        primary.backup(&copy_path).unwrap();
+        copy.restore(&copy_path).unwrap();
+        drop(primary);
+        sender.join().unwrap();
+        assert_eq!(lsn, receive(&mut stream, &standby).unwrap());
+        assert_replicated(&copy, &standby);
+
+        standby.promote().unwrap();
+        standby.insert_value(2, vals[0].clone(), t).unwrap();
+        assert_eq!(101, standby.get_iterator(2, t, RO).count());
+        fs::remove_dir_all(backup_path).unwrap();
+        fs::remove_dir_all(copy_path).unwrap();
+    }
+}
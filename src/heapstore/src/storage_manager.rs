@@ -1,23 +1,370 @@
-use crate::heapfile::HeapFile;
+use crate::buffer_pool::{BufferPool, BufferPoolStats, EvictionPolicy, PageGuard};
+use crate::cdc::{self, Change, ChangeEvent, ChangeLog};
+use crate::heapfile::{segment_path, segment_paths, HeapFile};
 use crate::heapfileiter::HeapFileIterator;
-use crate::page::Page;
+use crate::mvcc::{TxnTable, VacuumAction};
+use crate::page::{Page, PageDescription, FORWARD_STUB_SIZE};
+use crate::record::{self, RecordHeader, Visibility};
+use crate::wal::{self, Lsn, RecoveryTarget, Wal, WalConfig, WalEntry, WalRecord};
+#[cfg(feature = "arrow")]
+use common::arrow_batch::TupleBatches;
+use common::bloom::BloomFilters;
+use common::csv_export::{self, CsvExportOptions};
+use common::csv_import::{self, CsvImportOptions, CsvImportReport};
+use common::ids::SegmentId;
+use common::metrics::{Metrics, MetricsRegistry};
+use common::ndjson::{self, RowErrorPolicy};
+#[cfg(feature = "parquet")]
+use common::parquet_io::{self, ParquetExportOptions};
 use common::prelude::*;
-use common::storage_trait::StorageTrait;
+use common::statistics::ModificationCounters;
+use common::storage_trait::{validate_database_name, PageSampler, StorageTrait, DATABASES_DIR};
+use common::temp_container::{is_temp_container, TempContainers};
 use common::testutil::gen_random_test_sm_dir;
-use common::PAGE_SIZE;
+use common::traits::transaction_manager_trait::IsolationLevel;
+use common::{ContainerConfig, PAGE_SIZE};
+use log::{Level, LevelFilter};
+use serde::{Deserialize, Serialize};
 use std::borrow::BorrowMut;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{btree_map, BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::io::{BufRead, BufReader, Read, Write};
-use std::path::PathBuf;
-use std::sync::atomic::Ordering;
-use std::sync::{Arc, RwLock};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex, RwLock};
 
 /*
 StorageManager is a hashmap from container ids to heapfile structs
 heapfiles should hold file contents in memory
 */
 
+/// Log a diagnostic of a storage manager if its verbosity, set with
+/// `StorageManager::set_log_level`, includes the level.
+macro_rules! sm_log {
+    ($sm:expr, $level:expr, $($arg:tt)+) => {
+        if $level <= $sm.log_level() {
+            log!($level, $($arg)+);
+        }
+    };
+}
+
+/// Levels of the diagnostics, in the order of their `LevelFilter` values.
+const LOG_LEVELS: [LevelFilter; 6] = [
+    LevelFilter::Off,
+    LevelFilter::Error,
+    LevelFilter::Warn,
+    LevelFilter::Info,
+    LevelFilter::Debug,
+    LevelFilter::Trace,
+];
+
+/// Path of the heapfile of a container in a storage directory.
+pub(crate) fn container_path(dir: &Path, container_id: ContainerId) -> PathBuf {
+    dir.join(String::from("c") + &container_id.to_string())
+}
+
+/// Remove the files of the heap file at path, with the ones of its further segments.
+fn remove_heap_file(path: &Path) -> Result<(), CrustyError> {
+    for path in segment_paths(path) {
+        fs::remove_file(&path).map_err(|e| CrustyError::io_with_path(&path, e))?;
+    }
+    Ok(())
+}
+
+/// Version of the catalog record persisted in `c_map`. Bump it when the record changes.
+const CATALOG_VERSION: u32 = 1;
+
+/// Number of pages sequential scans read ahead by default, see
+/// `StorageManager::with_read_ahead`.
+pub const DEFAULT_READ_AHEAD: PageId = 8;
+
+/// The containers of a storage path, persisted in `c_map` by `shutdown`. The checksum
+/// covers the container ids, so a torn or edited record is detected instead of opening the
+/// wrong heap files.
+#[derive(Serialize, Deserialize)]
+struct CatalogRecord {
+    version: u32,
+    container_ids: Vec<ContainerId>,
+    checksum: u64,
+}
+
+impl CatalogRecord {
+    fn new(container_ids: Vec<ContainerId>) -> Self {
+        let checksum = catalog_checksum(&container_ids);
+        CatalogRecord {
+            version: CATALOG_VERSION,
+            container_ids,
+            checksum,
+        }
+    }
+}
+
+/// 64-bit FNV-1a hash of the container ids of a catalog record.
+fn catalog_checksum(container_ids: &[ContainerId]) -> u64 {
+    container_ids
+        .iter()
+        .flat_map(|c| c.to_le_bytes())
+        .fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// Read the container ids persisted in dir by `shutdown`. Errors if the catalog is missing,
+/// truncated, of another version, or does not match its checksum. A catalog written before
+/// records were versioned, the number of containers followed by their ids, is read too.
+fn read_catalog(dir: &Path) -> Result<Vec<ContainerId>, CrustyError> {
+    let bytes = fs::read(dir.join("c_map")).map_err(|e| {
+        CrustyError::IOError(format!(
+            "No containers were persisted in {}: {}",
+            dir.to_string_lossy(),
+            e
+        ))
+    })?;
+    if let Ok(record) = serde_json::from_slice::<CatalogRecord>(&bytes) {
+        if record.version != CATALOG_VERSION {
+            return Err(CrustyError::CrustyError(format!(
+                "c_map has catalog version {}, expected {}",
+                record.version, CATALOG_VERSION
+            )));
+        }
+        if record.checksum != catalog_checksum(&record.container_ids) {
+            return Err(CrustyError::CrustyError(String::from(
+                "c_map does not match its checksum",
+            )));
+        }
+        return Ok(record.container_ids);
+    }
+    let buffer: Vec<ContainerId> = serde_json::from_slice(&bytes)
+        .map_err(|e| CrustyError::CrustyError(format!("Cannot read c_map: {}", e)))?;
+    match buffer.split_first() {
+        Some((cnt, container_ids)) if *cnt as usize == container_ids.len() => {
+            Ok(container_ids.to_vec())
+        }
+        _ => Err(CrustyError::CrustyError(String::from(
+            "c_map does not hold the number of containers it lists",
+        ))),
+    }
+}
+
+/// Read the container ids and the dependencies between them persisted in dir by `shutdown`.
+pub(crate) fn read_metadata(
+    dir: &Path,
+) -> Result<(Vec<ContainerId>, HashMap<ContainerId, Vec<ContainerId>>), CrustyError> {
+    let container_ids = read_catalog(dir)?;
+    Ok((container_ids, read_dependencies(dir)?))
+}
+
+/// Read the dependencies between the containers persisted in dir by `shutdown`, if any.
+fn read_dependencies(dir: &Path) -> Result<HashMap<ContainerId, Vec<ContainerId>>, CrustyError> {
+    match fs::read(dir.join("dependencies")) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| CrustyError::CrustyError(format!("Cannot read dependencies: {}", e))),
+        Err(_) => Ok(HashMap::new()),
+    }
+}
+
+/// Returns the containers of the heap files in dir, named as by `container_path`.
+pub(crate) fn find_heap_files(dir: &Path) -> Result<Vec<ContainerId>, CrustyError> {
+    let entries = fs::read_dir(dir).map_err(|e| CrustyError::io_with_path(dir, e))?;
+    Ok(entries
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name();
+            name.to_str()?.strip_prefix('c')?.parse().ok()
+        })
+        .collect())
+}
+
+/// A transaction prepared with `StorageManager::prepare_transaction`, persisted by global id
+/// in the `prepared_txns` file of the storage path.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PreparedTxn {
+    tid: TidType,
+    /// Containers it wrote to.
+    containers: Vec<ContainerId>,
+}
+
+/// Read the transactions prepared in dir, by global id, if any.
+fn read_prepared(dir: &Path) -> Result<HashMap<String, PreparedTxn>, CrustyError> {
+    match fs::read(dir.join("prepared_txns")) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| CrustyError::CrustyError(format!("Cannot read prepared_txns: {}", e))),
+        Err(_) => Ok(HashMap::new()),
+    }
+}
+
+/// Write bytes to the file name in dir. They are written and synced to a temporary file
+/// renamed over the previous one, so a crash leaves one or the other.
+fn write_atomic(dir: &Path, name: &str, bytes: &[u8]) -> Result<(), CrustyError> {
+    let tmp_path = dir.join(format!("{}.tmp", name));
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, dir.join(name))?;
+    Ok(())
+}
+
+/// Read the options of the containers persisted in dir by `shutdown`. Containers without
+/// any have the default ones.
+pub(crate) fn read_configs(
+    dir: &Path,
+) -> Result<HashMap<ContainerId, ContainerConfig>, CrustyError> {
+    match fs::read(dir.join("container_configs")) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map_err(|e| CrustyError::CrustyError(format!("Cannot read container_configs: {}", e))),
+        Err(_) => Ok(HashMap::new()),
+    }
+}
+
+/// Persist the container ids, the dependencies between them and their options in dir, in
+/// the format read by `StorageManager::new`. Only the options other than the default are
+/// persisted. Each file is replaced atomically, see `write_atomic`. Containers in memory
+/// are left out.
+fn write_metadata(
+    dir: &Path,
+    c_map: &HashMap<ContainerId, Arc<HeapFile>>,
+    dependencies: &HashMap<ContainerId, Vec<ContainerId>>,
+) -> Result<(), CrustyError> {
+    let c_map: HashMap<ContainerId, &Arc<HeapFile>> = c_map
+        .iter()
+        .filter(|(_, hf)| !hf.is_in_memory())
+        .map(|(container_id, hf)| (*container_id, hf))
+        .collect();
+    let dependencies: HashMap<&ContainerId, &Vec<ContainerId>> = dependencies
+        .iter()
+        .filter(|(container_id, _)| c_map.contains_key(container_id))
+        .collect();
+    let record = CatalogRecord::new(c_map.keys().copied().collect());
+    let serialized = serde_json::to_string(&record).unwrap();
+    debug!("Persisting c_map {} in {:?}", serialized, dir);
+    write_atomic(dir, "c_map", serialized.as_bytes())?;
+    write_atomic(
+        dir,
+        "dependencies",
+        &serde_json::to_vec(&dependencies).unwrap(),
+    )?;
+    let configs: HashMap<ContainerId, ContainerConfig> = c_map
+        .iter()
+        .map(|(container_id, hf)| (*container_id, hf.config()))
+        .filter(|(_, config)| *config != ContainerConfig::default())
+        .collect();
+    write_atomic(
+        dir,
+        "container_configs",
+        &serde_json::to_vec(&configs).unwrap(),
+    )?;
+    Ok(())
+}
+
+/// Errors if a container cannot be created with config: an option is out of range, or
+/// heap files do not support it.
+fn check_config(config: &ContainerConfig) -> Result<(), CrustyError> {
+    config.validate()?;
+    if config.in_memory && config.segment_pages.is_some() {
+        return Err(CrustyError::ValidationError(String::from(
+            "Containers in memory have no files to split into segments",
+        )));
+    }
+    Ok(())
+}
+
+/// Read the WAL position persisted in dir: the LSN of the last WAL entry whose change the
+/// heap files in dir hold. It is the checkpoint of a storage path, and the point a backup
+/// was made at. None if dir has none.
+fn read_wal_lsn(dir: &Path) -> Result<Option<Lsn>, CrustyError> {
+    match fs::read_to_string(dir.join("wal_lsn")) {
+        Ok(lsn) => Ok(Some(lsn.trim().parse().map_err(|e| {
+            CrustyError::CrustyError(format!("Cannot read wal_lsn: {}", e))
+        })?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Persist the WAL position of the heap files in dir, see `write_atomic`.
+fn write_wal_lsn(dir: &Path, lsn: Lsn) -> Result<(), CrustyError> {
+    write_atomic(dir, "wal_lsn", lsn.to_string().as_bytes())
+}
+
+/// A container of a storage manager and its size, see `StorageManager::list_containers`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContainerInfo {
+    pub container_id: ContainerId,
+    pub num_pages: PageId,
+    /// Size of the heap file in bytes.
+    pub file_size: u64,
+    /// Containers it depends on.
+    pub dependencies: Vec<ContainerId>,
+    /// Whether it is a temporary container.
+    pub is_temp: bool,
+    /// Options it was created with.
+    pub config: ContainerConfig,
+}
+
+/// Rename the heap file of a container to the one of a new container id and open it,
+/// keeping the options of the container.
+fn rename_heap_file(
+    dir: &Path,
+    hf: &HeapFile,
+    new_container_id: ContainerId,
+) -> Result<HeapFile, CrustyError> {
+    let path = container_path(dir, hf.container_id);
+    let new_path = container_path(dir, new_container_id);
+    for (segment, path) in segment_paths(&path).into_iter().enumerate() {
+        fs::rename(&path, segment_path(&new_path, segment as SegmentId))
+            .map_err(|e| CrustyError::io_with_path(&path, e))?;
+    }
+    let new_hf = HeapFile::new(new_path, new_container_id)?.with_config(hf.config())?;
+    Ok(match &hf.buffer_pool {
+        Some(buffer_pool) => new_hf.with_buffer_pool(buffer_pool.clone()),
+        None => new_hf,
+    })
+}
+
+/// Move the dependencies of a renamed container to its new id, and replace its old id in
+/// the dependencies of the other containers.
+fn rename_dependencies(
+    dependencies: &mut HashMap<ContainerId, Vec<ContainerId>>,
+    container_id: ContainerId,
+    new_container_id: ContainerId,
+) {
+    if let Some(deps) = dependencies.remove(&container_id) {
+        dependencies.insert(new_container_id, deps);
+    }
+    for deps in dependencies.values_mut() {
+        for dep in deps.iter_mut().filter(|dep| **dep == container_id) {
+            *dep = new_container_id;
+        }
+    }
+}
+
+/// Open the storage managers of the databases created in storage_path by
+/// `create_database`, read-only if read_only.
+fn open_databases(
+    storage_path: &Path,
+    read_only: bool,
+) -> Result<HashMap<String, Arc<StorageManager>>, CrustyError> {
+    let dir = storage_path.join(DATABASES_DIR);
+    let mut databases = HashMap::new();
+    if !dir.is_dir() {
+        return Ok(databases);
+    }
+    for entry in fs::read_dir(&dir).map_err(|e| CrustyError::io_with_path(&dir, e))? {
+        let path = entry?.path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if path.is_dir() => name.to_string(),
+            _ => continue,
+        };
+        let sm = if read_only {
+            StorageManager::open_read_only(path)?
+        } else {
+            StorageManager::new(path)
+        };
+        databases.insert(name, Arc::new(sm));
+    }
+    Ok(databases)
+}
+
 /// The StorageManager struct
 // #[derive(Serialize, Deserialize)]
 pub struct StorageManager {
@@ -27,30 +374,1882 @@ pub struct StorageManager {
     c_map: Arc<RwLock<HashMap<ContainerId, Arc<HeapFile>>>>,
     /// Indicates if this is a temp StorageManager (for testing)
     is_temp: bool,
+    /// Map from container id to the containers it depends on. A container cannot be
+    /// removed while another depends on it.
+    dependencies: Arc<RwLock<HashMap<ContainerId, Vec<ContainerId>>>>,
+    /// Indicates if this StorageManager was opened with `open_read_only`
+    read_only: bool,
+    /// Write-ahead log of the changes to the containers, if enabled with `with_wal`
+    wal: Option<Wal>,
+    /// WAL position of the backup loaded by the last `restore`, or of the last entry
+    /// replayed onto it by `recover_to`. None if the backup was made without a WAL.
+    recovery_lsn: RwLock<Option<Lsn>>,
+    /// Pages logged in full in the WAL since the last checkpoint. Later writes of them are
+    /// logged as deltas, see `log_page`
+    logged_pages: Mutex<HashSet<(ContainerId, PageId)>>,
+    /// Whether the storage manager is a standby applying the changes of a primary, see
+    /// `start_standby`
+    standby: AtomicBool,
+    /// States of the transactions, if values are stored as versions; see `with_mvcc`
+    txns: Option<Arc<TxnTable>>,
+    /// Bloom filters over a key column of some containers
+    bloom_filters: BloomFilters,
+    /// Temporary containers and the transactions they belong to
+    temp_containers: TempContainers,
+    /// Metrics of the operators run against the containers
+    metrics: Arc<MetricsRegistry>,
+    /// Values changed in each container since its table was last analyzed
+    modifications: ModificationCounters,
+    /// Most verbose level of the diagnostics logged, as a `LevelFilter` value
+    log_level: AtomicUsize,
+    /// Pages read with `get_page`, kept in memory
+    buffer_pool: Arc<BufferPool>,
+    /// Storage managers of the databases, by name, see `create_database`
+    databases: RwLock<HashMap<String, Arc<StorageManager>>>,
+    /// Whether temporary containers are created in memory, see
+    /// `with_in_memory_temp_containers`
+    temp_in_memory: bool,
+    /// Pages sequential scans read ahead, see `with_read_ahead`
+    read_ahead: PageId,
+    /// Logs of the changes to the containers they are captured for, see `subscribe_changes`
+    change_logs: RwLock<HashMap<ContainerId, Arc<ChangeLog>>>,
+    /// Changes to captured containers made by transactions that have not committed, with
+    /// MVCC
+    pending_changes: Mutex<HashMap<TidType, Vec<(ContainerId, Change)>>>,
+    /// Transactions prepared for a two-phase commit, by global id, see
+    /// `prepare_transaction`
+    prepared: Mutex<HashMap<String, PreparedTxn>>,
 }
 
-/// The required functions in HeapStore's StorageManager that are specific for HeapFiles
-impl StorageManager {
-    /// Get a page if exists for a given container.
-    pub(crate) fn get_page(
+/// The required functions in HeapStore's StorageManager that are specific for HeapFiles
+impl StorageManager {
+    /// Open the containers persisted by `shutdown` in storage_path for reading only, e.g. to
+    /// serve analytics against a copy of a database directory. The heap files are opened
+    /// without write permission, nothing is created in storage_path, and inserts, deletes,
+    /// updates and container changes fail. Shutting it down persists nothing.
+    pub fn open_read_only(storage_path: PathBuf) -> Result<Self, CrustyError> {
+        let (container_ids, dependencies) = read_metadata(&storage_path)?;
+        let configs = read_configs(&storage_path)?;
+        let buffer_pool = Arc::new(BufferPool::default());
+        let mut c_map = HashMap::new();
+        for container_id in container_ids {
+            let hf = HeapFile::open_read_only(
+                container_path(&storage_path, container_id),
+                container_id,
+            )?
+            .with_config(configs.get(&container_id).copied().unwrap_or_default())?
+            .with_buffer_pool(buffer_pool.clone());
+            c_map.insert(container_id, Arc::new(hf));
+        }
+        let bloom_filters = BloomFilters::load(&storage_path.join("bloom_filters"))?;
+        let databases = open_databases(&storage_path, true)?;
+        Ok(StorageManager {
+            storage_path,
+            c_map: Arc::new(RwLock::new(c_map)),
+            is_temp: false,
+            dependencies: Arc::new(RwLock::new(dependencies)),
+            read_only: true,
+            wal: None,
+            recovery_lsn: RwLock::new(None),
+            logged_pages: Mutex::new(HashSet::new()),
+            standby: AtomicBool::new(false),
+            txns: None,
+            bloom_filters,
+            temp_containers: TempContainers::default(),
+            metrics: Arc::default(),
+            modifications: ModificationCounters::default(),
+            log_level: AtomicUsize::new(LevelFilter::Trace as usize),
+            buffer_pool,
+            databases: RwLock::new(databases),
+            temp_in_memory: false,
+            read_ahead: DEFAULT_READ_AHEAD,
+            change_logs: RwLock::new(HashMap::new()),
+            pending_changes: Mutex::new(HashMap::new()),
+            prepared: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Back up the containers to dest_path while the storage manager stays in use. The
+    /// backup can be loaded with `restore`, or opened with `new` or `open_read_only`.
+    ///
+    /// Inserts, deletes and updates are held off while the heapfiles are flushed and copied,
+    /// so the backup holds every change made before it started, none made after it finished,
+    /// and no partially made one, such as an update that deleted a value to move it to
+    /// another page but did not insert it yet. It is made in a temporary directory next to dest_path and
+    /// renamed to dest_path once complete, so dest_path never holds a partial backup.
+    ///
+    /// # Arguments
+    ///
+    /// * `dest_path` - Directory to back up to. It must not exist.
+    pub fn backup(&self, dest_path: &Path) -> Result<(), CrustyError> {
+        if dest_path.exists() {
+            return Err(CrustyError::IOError(format!(
+                "Backup destination {} already exists",
+                dest_path.to_string_lossy()
+            )));
+        }
+        let mut tmp_name = dest_path
+            .file_name()
+            .ok_or_else(|| CrustyError::IOError(String::from("Backup destination has no name")))?
+            .to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = dest_path.with_file_name(tmp_name);
+        if tmp_path.exists() {
+            fs::remove_dir_all(&tmp_path)?;
+        }
+        fs::create_dir_all(&tmp_path)?;
+        match self.copy_containers(&tmp_path) {
+            Ok(()) => {
+                fs::rename(&tmp_path, dest_path)?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_dir_all(&tmp_path);
+                Err(e)
+            }
+        }
+    }
+
+    /// Copy the heapfiles and metadata to dir, holding off changes while doing so.
+    fn copy_containers(&self, dir: &Path) -> Result<(), CrustyError> {
+        {
+            // Changes hold their heap file's modify lock from their first page write to
+            // their last, so holding every one keeps changes that write several pages, such
+            // as updates moving a value, out of the backup or in it whole. Changes take it
+            // before the c_map lock, so it is taken first here too, in container order.
+            let mut heap_files: Vec<_> = self.c_map.read().unwrap().values().cloned().collect();
+            heap_files.sort_by_key(|hf| hf.container_id);
+            let _modify: Vec<_> = heap_files
+                .iter()
+                .map(|hf| hf.modify_lock.lock().unwrap())
+                .collect();
+            // page writes hold the c_map write lock, so holding it fences them
+            let c_map = self.c_map.write().unwrap();
+            for (container_id, hf) in c_map.iter().filter(|(_, hf)| !hf.is_in_memory()) {
+                hf.sync()?;
+                let dest = container_path(dir, *container_id);
+                for (segment, path) in
+                    segment_paths(&container_path(&self.storage_path, *container_id))
+                        .into_iter()
+                        .enumerate()
+                {
+                    fs::copy(path, segment_path(&dest, segment as SegmentId))?;
+                }
+            }
+            write_metadata(dir, &c_map, &self.dependencies.read().unwrap())?;
+            // WAL entries are logged under the c_map lock too, so the backup holds exactly
+            // the changes up to the last one
+            if let Some(wal) = &self.wal {
+                wal.flush()?;
+                write_wal_lsn(dir, wal.last_lsn())?;
+            }
+        }
+        // make the copies durable before they are published
+        for entry in fs::read_dir(dir)? {
+            fs::File::open(entry?.path())?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Replace the containers with the ones backed up to src_path by `backup`. Values
+    /// written since the backup are lost, unless they are replayed with `recover_to`.
+    /// Iterators opened before keep reading the replaced containers.
+    ///
+    /// # Arguments
+    ///
+    /// * `src_path` - Directory of the backup.
+    pub fn restore(&self, src_path: &Path) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let (container_ids, dependencies) = read_metadata(src_path)?;
+        let configs = read_configs(src_path)?;
+        if let Some(missing) = container_ids
+            .iter()
+            .find(|c| !container_path(src_path, **c).exists())
+        {
+            return Err(CrustyError::IOError(format!(
+                "Backup {} has no file for container {}",
+                src_path.to_string_lossy(),
+                missing
+            )));
+        }
+        let mut c_map = self.c_map.write().unwrap();
+        let mut current_dependencies = self.dependencies.write().unwrap();
+        for (container_id, hf) in c_map.iter() {
+            if !hf.is_in_memory() {
+                remove_heap_file(&container_path(&self.storage_path, *container_id))?;
+            }
+        }
+        c_map.clear();
+        self.buffer_pool.clear();
+        fs::create_dir_all(&self.storage_path)?;
+        for container_id in container_ids {
+            let path = container_path(&self.storage_path, container_id);
+            for (segment, src) in segment_paths(&container_path(src_path, container_id))
+                .into_iter()
+                .enumerate()
+            {
+                fs::copy(src, segment_path(&path, segment as SegmentId))?;
+            }
+            let hf = HeapFile::new(path, container_id)?
+                .with_config(configs.get(&container_id).copied().unwrap_or_default())?
+                .with_buffer_pool(self.buffer_pool.clone());
+            c_map.insert(container_id, Arc::new(hf));
+        }
+        *current_dependencies = dependencies;
+        // the filters may not match the restored containers
+        self.bloom_filters.clear();
+        *self.recovery_lsn.write().unwrap() = read_wal_lsn(src_path)?;
+        write_metadata(&self.storage_path, &c_map, &current_dependencies)?;
+        // the changes logged before must not be replayed onto the restored containers
+        self.checkpoint(&c_map)
+    }
+
+    /// Log the changes to the containers in a write-ahead log in the `wal` directory of the
+    /// storage path, so backups made from now on can be rolled forward with `recover_to`.
+    /// Logging continues after the entries already in the WAL and its archive.
+    ///
+    /// The changes logged after the last checkpoint, taken by `shutdown`, are replayed first,
+    /// so a storage manager that crashed recovers the changes it logged. If any are, the
+    /// bloom filters are dropped, as they may miss values, and so are temporary containers.
+    pub fn with_wal(mut self, config: WalConfig) -> Result<Self, CrustyError> {
+        self.check_writable()?;
+        self.wal = Some(Wal::open(self.storage_path.join("wal"), config)?);
+        self.recover()?;
+        Ok(self)
+    }
+
+    /// Replay the WAL entries logged after the checkpoint, then checkpoint.
+    fn recover(&self) -> Result<(), CrustyError> {
+        let wal = match &self.wal {
+            Some(wal) => wal,
+            None => return Ok(()),
+        };
+        let mut c_map = self.c_map.write().unwrap();
+        let mut dependencies = self.dependencies.write().unwrap();
+        // a checkpoint past the end of the WAL was taken with another WAL, e.g. in a backup
+        let checkpoint = read_wal_lsn(&self.storage_path)?
+            .unwrap_or(0)
+            .min(wal.last_lsn());
+        if checkpoint < wal.last_lsn() {
+            let mut last_lsn = checkpoint;
+            for segment in wal.all_segments()? {
+                for entry in wal::read_segment(&segment)? {
+                    // entries before the checkpoint, or logged twice
+                    if entry.lsn <= last_lsn {
+                        continue;
+                    }
+                    if entry.lsn != last_lsn + 1 {
+                        return Err(CrustyError::IOError(format!(
+                            "WAL entries {} to {} are missing",
+                            last_lsn + 1,
+                            entry.lsn - 1
+                        )));
+                    }
+                    self.replay(entry, &mut c_map, &mut dependencies)?;
+                    last_lsn += 1;
+                }
+            }
+            sm_log!(
+                self,
+                Level::Info,
+                "Replayed WAL entries {} to {} in {:?}",
+                checkpoint + 1,
+                last_lsn,
+                self.storage_path
+            );
+            self.bloom_filters.clear();
+            let temp: Vec<ContainerId> = c_map
+                .keys()
+                .copied()
+                .filter(|c| is_temp_container(*c))
+                .collect();
+            for container_id in temp {
+                dependencies.remove(&container_id);
+                if !c_map.remove(&container_id).unwrap().is_in_memory() {
+                    remove_heap_file(&container_path(&self.storage_path, container_id))?;
+                }
+            }
+            write_metadata(&self.storage_path, &c_map, &dependencies)?;
+        }
+        self.checkpoint(&c_map)
+    }
+
+    /// Sync the heap files and persist the LSN of the last WAL entry, so recovery replays
+    /// the entries after it. Callers hold the c_map lock, so no page is written meanwhile.
+    fn checkpoint(&self, c_map: &HashMap<ContainerId, Arc<HeapFile>>) -> Result<(), CrustyError> {
+        if let Some(wal) = &self.wal {
+            for hf in c_map.values() {
+                hf.sync()?;
+            }
+            write_wal_lsn(&self.storage_path, wal.last_lsn())?;
+            // the pages are on disk, so a crash cannot leave them cut short
+            self.logged_pages.lock().unwrap().clear();
+        }
+        Ok(())
+    }
+
+    /// Returns the LSN of the last change logged, None if there is no WAL.
+    pub fn wal_lsn(&self) -> Option<Lsn> {
+        self.wal.as_ref().map(|wal| wal.last_lsn())
+    }
+
+    /// End the current WAL segment and move it to the archive directory, so every change
+    /// logged so far can be recovered from the archive.
+    pub fn archive_wal(&self) -> Result<(), CrustyError> {
+        match &self.wal {
+            Some(wal) => wal.archive(),
+            None => Err(CrustyError::ExecutionError(String::from(
+                "Storage manager has no WAL to archive",
+            ))),
+        }
+    }
+
+    /// Returns a channel receiving the WAL entries logged from now on, serialized as in the
+    /// WAL segments, for a standby to apply with `apply_replicated`; see the `replication`
+    /// module. Errors without a WAL.
+    pub fn replication_stream(&self) -> Result<Receiver<Vec<u8>>, CrustyError> {
+        match &self.wal {
+            Some(wal) => Ok(wal.subscribe()),
+            None => Err(CrustyError::ExecutionError(String::from(
+                "Storage manager has no WAL to replicate",
+            ))),
+        }
+    }
+
+    /// Make the storage manager a standby of the primary a backup was made of, restoring
+    /// the backup. Until `promote`, the only changes it accepts are the primary's WAL
+    /// entries logged after the backup, applied with `apply_replicated`.
+    pub fn start_standby(&self, base_backup: &Path) -> Result<(), CrustyError> {
+        self.restore(base_backup)?;
+        if self.recovery_lsn.read().unwrap().is_none() {
+            return Err(CrustyError::ExecutionError(String::from(
+                "The base backup of a standby must be made with a WAL",
+            )));
+        }
+        self.standby.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns true between `start_standby` and `promote`.
+    pub fn is_standby(&self) -> bool {
+        self.standby.load(Ordering::Relaxed)
+    }
+
+    /// Apply a WAL entry of the primary, as received from its `replication_stream`, to a
+    /// standby. Entries up to the last one applied, e.g. logged before the base backup, are
+    /// skipped; a later entry must follow it. Returns the LSN of the last entry applied.
+    ///
+    /// Applied entries are not logged in the standby's WAL, and are lost if the standby
+    /// stops before it is promoted.
+    pub fn apply_replicated(&self, entry: &[u8]) -> Result<Lsn, CrustyError> {
+        if !self.is_standby() {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Only a standby applies replicated WAL entries",
+            )));
+        }
+        let entry = wal::read_entry(entry)?;
+        let mut recovery_lsn = self.recovery_lsn.write().unwrap();
+        let last_lsn = recovery_lsn.unwrap();
+        if entry.lsn <= last_lsn {
+            return Ok(last_lsn);
+        }
+        if entry.lsn != last_lsn + 1 {
+            return Err(CrustyError::IOError(format!(
+                "Replicated WAL entries {} to {} are missing",
+                last_lsn + 1,
+                entry.lsn - 1
+            )));
+        }
+        let catalog_change = !matches!(
+            entry.record,
+            WalRecord::Page { .. }
+                | WalRecord::PageDelta { .. }
+                | WalRecord::Load { .. }
+                | WalRecord::Commit { .. }
+        );
+        let mut c_map = self.c_map.write().unwrap();
+        let mut dependencies = self.dependencies.write().unwrap();
+        self.replay(entry, &mut c_map, &mut dependencies)?;
+        if catalog_change {
+            write_metadata(&self.storage_path, &c_map, &dependencies)?;
+        }
+        *recovery_lsn = Some(last_lsn + 1);
+        Ok(last_lsn + 1)
+    }
+
+    /// Turn a standby into a storage manager accepting changes, e.g. to fail over when its
+    /// primary is lost. The containers are made durable as of the last entry applied.
+    pub fn promote(&self) -> Result<(), CrustyError> {
+        if !self.is_standby() {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Storage manager is not a standby",
+            )));
+        }
+        let c_map = self.c_map.write().unwrap();
+        write_metadata(
+            &self.storage_path,
+            &c_map,
+            &self.dependencies.read().unwrap(),
+        )?;
+        // the applied entries did not maintain the filters
+        self.bloom_filters.clear();
+        self.checkpoint(&c_map)?;
+        self.standby.store(false, Ordering::Relaxed);
+        sm_log!(
+            self,
+            Level::Info,
+            "Promoted standby {:?} at WAL entry {}",
+            self.storage_path,
+            self.recovery_lsn.read().unwrap().unwrap_or(0)
+        );
+        Ok(())
+    }
+
+    /// Roll the backup loaded by the last `restore` forward to a point in time, by replaying
+    /// the changes archived in archive_dir after the backup was made. Can be called again
+    /// with a later target to roll further. Returns the LSN of the last change replayed.
+    ///
+    /// The replayed changes are not logged in this storage manager's WAL.
+    ///
+    /// # Arguments
+    ///
+    /// * `archive_dir` - Directory the WAL segments were archived to.
+    /// * `target` - Last change to replay.
+    pub fn recover_to(
+        &self,
+        archive_dir: &Path,
+        target: RecoveryTarget,
+    ) -> Result<Lsn, CrustyError> {
+        self.check_writable()?;
+        let mut recovery_lsn = self.recovery_lsn.write().unwrap();
+        let mut last_lsn = recovery_lsn.ok_or_else(|| {
+            CrustyError::ExecutionError(String::from("No backup made with a WAL has been restored"))
+        })?;
+        let mut c_map = self.c_map.write().unwrap();
+        let mut dependencies = self.dependencies.write().unwrap();
+        'segments: for segment in wal::segments(archive_dir)? {
+            for entry in wal::read_segment(&segment)? {
+                if entry.lsn <= last_lsn {
+                    continue;
+                }
+                if entry.is_after(&target) {
+                    break 'segments;
+                }
+                if entry.lsn != last_lsn + 1 {
+                    return Err(CrustyError::IOError(format!(
+                        "WAL entries {} to {} are missing from {}",
+                        last_lsn + 1,
+                        entry.lsn - 1,
+                        archive_dir.to_string_lossy()
+                    )));
+                }
+                self.replay(entry, &mut c_map, &mut dependencies)?;
+                last_lsn += 1;
+                *recovery_lsn = Some(last_lsn);
+            }
+        }
+        write_metadata(&self.storage_path, &c_map, &dependencies)?;
+        // the replayed changes are not in this storage manager's WAL
+        self.checkpoint(&c_map)?;
+        Ok(last_lsn)
+    }
+
+    /// Apply a change read from a WAL to the containers.
+    fn replay(
+        &self,
+        entry: WalEntry,
+        c_map: &mut HashMap<ContainerId, Arc<HeapFile>>,
+        dependencies: &mut HashMap<ContainerId, Vec<ContainerId>>,
+    ) -> Result<(), CrustyError> {
+        let missing = |container_id| {
+            CrustyError::CrustyError(format!(
+                "WAL entry {} changes container {}, which does not exist",
+                entry.lsn, container_id
+            ))
+        };
+        match entry.record {
+            WalRecord::Page {
+                container_id,
+                ref page,
+            } => c_map
+                .get(&container_id)
+                .ok_or_else(|| missing(container_id))?
+                .write_page_to_file(Page::from_bytes(page)?),
+            WalRecord::PageDelta {
+                container_id,
+                ref delta,
+            } => {
+                let hf = c_map
+                    .get(&container_id)
+                    .ok_or_else(|| missing(container_id))?;
+                let mut bytes = hf.read_page_from_file(delta.page_id)?.to_bytes();
+                delta.apply(&mut bytes)?;
+                hf.write_page_to_file(Page::from_bytes(&bytes)?)
+            }
+            WalRecord::CreateContainer {
+                container_id,
+                dependencies: ref deps,
+                config,
+            } => {
+                let hf = self.new_heap_file(container_id, config)?;
+                c_map.insert(container_id, Arc::new(hf));
+                if !deps.is_empty() {
+                    dependencies.insert(container_id, deps.clone());
+                }
+                Ok(())
+            }
+            WalRecord::Load {
+                container_id,
+                ref pages,
+            } => {
+                let hf = c_map
+                    .get(&container_id)
+                    .ok_or_else(|| missing(container_id))?;
+                for page in pages {
+                    hf.write_page_to_file(Page::from_bytes(page)?)?;
+                }
+                Ok(())
+            }
+            // the versions of the transaction are already in the replayed pages
+            WalRecord::Commit { .. } => Ok(()),
+            WalRecord::RemoveContainer { container_id } => {
+                let hf = c_map
+                    .remove(&container_id)
+                    .ok_or_else(|| missing(container_id))?;
+                dependencies.remove(&container_id);
+                if !hf.is_in_memory() {
+                    remove_heap_file(&container_path(&self.storage_path, container_id))?;
+                }
+                Ok(())
+            }
+            WalRecord::RenameContainer {
+                container_id,
+                new_container_id,
+            } => {
+                let hf = c_map
+                    .remove(&container_id)
+                    .ok_or_else(|| missing(container_id))?;
+                let new_path = container_path(&self.storage_path, new_container_id);
+                let new_hf = if new_path.exists() {
+                    // the file was renamed before a crash, and the one under the old id
+                    // was created empty when the containers were opened
+                    remove_heap_file(&container_path(&self.storage_path, container_id))?;
+                    HeapFile::new(new_path, new_container_id)?
+                        .with_config(hf.config())?
+                        .with_buffer_pool(self.buffer_pool.clone())
+                } else {
+                    rename_heap_file(&self.storage_path, &hf, new_container_id)?
+                };
+                c_map.insert(new_container_id, Arc::new(new_hf));
+                rename_dependencies(dependencies, container_id, new_container_id);
+                Ok(())
+            }
+            WalRecord::TruncateContainer { container_id } => c_map
+                .get(&container_id)
+                .ok_or_else(|| missing(container_id))?
+                .truncate(|| Ok(())),
+            WalRecord::TruncateSegment {
+                container_id,
+                segment,
+            } => c_map
+                .get(&container_id)
+                .ok_or_else(|| missing(container_id))?
+                .truncate_segment(segment, || Ok(())),
+            WalRecord::SetConfig {
+                container_id,
+                config,
+            } => {
+                c_map
+                    .get(&container_id)
+                    .ok_or_else(|| missing(container_id))?
+                    .set_config(config);
+                Ok(())
+            }
+        }
+    }
+
+    /// Store values as versions tagged with the transactions that created and deleted them,
+    /// so each transaction reads a consistent snapshot without blocking writers. See the
+    /// `mvcc` module for the visibility rules. Transactions end with `commit_transaction`
+    /// or `abort_transaction`, and `vacuum` purges the versions no transaction can see.
+    ///
+    /// Stored values get a record header, so this must be used on every open of a storage
+    /// path, starting from an empty one.
+    ///
+    /// The transactions prepared with `prepare_transaction` before the storage path was
+    /// last closed are prepared again.
+    pub fn with_mvcc(mut self) -> Self {
+        let txns = TxnTable::default();
+        let prepared = read_prepared(&self.storage_path).unwrap_or_else(|e| {
+            error!("{}, dropping the prepared transactions", e);
+            HashMap::new()
+        });
+        for txn in prepared.values() {
+            txns.restore_prepared(txn.tid, &txn.containers);
+        }
+        self.txns = Some(Arc::new(txns));
+        self.prepared = Mutex::new(prepared);
+        self
+    }
+
+    /// Returns the transaction table, erroring if the storage manager does not use MVCC.
+    fn txns(&self) -> Result<&Arc<TxnTable>, CrustyError> {
+        self.txns.as_ref().ok_or_else(|| {
+            CrustyError::ExecutionError(String::from("Storage manager does not use MVCC"))
+        })
+    }
+
+    /// Returns the records tid can read in a container, None if the container has no record
+    /// headers, see `has_headers`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if tid has committed or aborted.
+    fn visibility(&self, tid: TransactionId, hf: &HeapFile) -> Option<Visibility> {
+        if !self.has_headers(hf) {
+            return None;
+        }
+        let snapshot = self.txns.as_ref().map(|txns| {
+            let snapshot = txns.snapshot(tid).unwrap();
+            txns.record_read(tid, hf.container_id);
+            (txns.clone(), tid.id(), snapshot)
+        });
+        Some(Visibility::new(snapshot, hf.config().ttl))
+    }
+
+    /// Returns true if the values of a container are stored with a record header: with MVCC,
+    /// if they expire, see `ContainerConfig::ttl`, or if they are retained once deleted, see
+    /// `ContainerConfig::retention`.
+    fn has_headers(&self, hf: &HeapFile) -> bool {
+        let config = hf.config();
+        self.txns.is_some() || config.ttl.is_some() || config.retention.is_some()
+    }
+
+    /// Returns the page holding a value and its slot, following the forwarding stub left at
+    /// its id if `compact` moved it. Errors if the id has no page or slot, or its page does
+    /// not exist.
+    fn value_page(&self, hf: &HeapFile, id: &ValueId) -> Result<(Page, SlotId), CrustyError> {
+        let (page_id, slot_id) = match (id.page_id, id.slot_id) {
+            (Some(page_id), Some(slot_id)) if page_id < hf.num_pages() => (page_id, slot_id),
+            _ => return Err(CrustyError::invalid_slot(id)),
+        };
+        let page = hf.read_page_from_file(page_id)?;
+        match page.forwarded_to(slot_id) {
+            Some((page_id, slot_id)) => Ok((hf.read_page_from_file(page_id)?, slot_id)),
+            None => Ok((page, slot_id)),
+        }
+    }
+
+    /// Begin a transaction at an isolation level. Transactions that are not begun explicitly
+    /// run at RepeatableRead from their first read or write.
+    pub fn begin_transaction(
+        &self,
+        tid: TransactionId,
+        level: IsolationLevel,
+    ) -> Result<(), CrustyError> {
+        self.txns()?.begin(tid, level)
+    }
+
+    /// Commit a transaction, making its changes visible to the transactions that start
+    /// after it. A Serializable transaction that would make the execution non-serializable
+    /// is aborted instead and gets `CrustyError::TransactionRollback`; see the `mvcc` module.
+    ///
+    /// With a WAL, the commit of a transaction that wrote something is logged and synced
+    /// first, sharing the sync with concurrent commits (see `WalConfig::group_commit_window`).
+    /// Its changes to containers whose changes are captured are then recorded, see
+    /// `subscribe_changes`.
+    pub fn commit_transaction(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        let txns = self.txns()?;
+        if let (Some(wal), true) = (&self.wal, txns.has_writes(tid)) {
+            let lsn = wal.append(WalRecord::Commit { tid: tid.id() })?;
+            wal.flush_to(lsn)?;
+        }
+        if let Err(e) = txns.commit(tid) {
+            self.pending_changes.lock().unwrap().remove(&tid.id());
+            return Err(e);
+        }
+        self.record_changes(tid.id())
+    }
+
+    /// Abort a transaction, discarding its changes.
+    pub fn abort_transaction(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        self.pending_changes.lock().unwrap().remove(&tid.id());
+        self.txns()?.abort(tid)
+    }
+
+    /// Prepare a transaction for a two-phase commit run by an external coordinator across
+    /// storage managers, under a global id the coordinator gives it. Once this returns Ok
+    /// the transaction can make no more changes, its changes are durable, and it is sure to
+    /// commit with `commit_prepared` until it is aborted with `rollback_prepared`, even
+    /// after a restart. A Serializable transaction that fails validation is aborted instead
+    /// and gets `CrustyError::TransactionRollback`.
+    ///
+    /// The changes it makes to containers whose changes are captured are recorded if it
+    /// commits before the storage manager is closed.
+    pub fn prepare_transaction(&self, tid: TransactionId, gid: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let txns = self.txns()?;
+        let mut prepared = self.prepared.lock().unwrap();
+        if prepared.contains_key(gid) {
+            return Err(CrustyError::ExecutionError(format!(
+                "A transaction is already prepared as {}",
+                gid
+            )));
+        }
+        if let Err(e) = txns.prepare(tid) {
+            self.pending_changes.lock().unwrap().remove(&tid.id());
+            return Err(e);
+        }
+        let containers = txns.writes(tid.id());
+        match &self.wal {
+            Some(wal) => wal.flush()?,
+            None => {
+                for container_id in &containers {
+                    self.heap_file(*container_id)?.sync()?;
+                }
+            }
+        }
+        let txn = PreparedTxn {
+            tid: tid.id(),
+            containers,
+        };
+        prepared.insert(gid.to_string(), txn);
+        self.write_prepared(&prepared)?;
+        sm_log!(
+            self,
+            Level::Info,
+            "Prepared transaction {:?} as {}",
+            tid,
+            gid
+        );
+        Ok(())
+    }
+
+    /// Commit the transaction prepared as gid, making its changes visible to the
+    /// transactions that start after it.
+    pub fn commit_prepared(&self, gid: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let txns = self.txns()?;
+        let mut prepared = self.prepared.lock().unwrap();
+        let txn = Self::prepared_txn(&prepared, gid)?;
+        if let (Some(wal), false) = (&self.wal, txn.containers.is_empty()) {
+            let lsn = wal.append(WalRecord::Commit { tid: txn.tid })?;
+            wal.flush_to(lsn)?;
+        }
+        txns.commit_prepared(txn.tid)?;
+        prepared.remove(gid);
+        self.write_prepared(&prepared)?;
+        self.record_changes(txn.tid)
+    }
+
+    /// Abort the transaction prepared as gid, discarding its changes.
+    pub fn rollback_prepared(&self, gid: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let txns = self.txns()?;
+        let mut prepared = self.prepared.lock().unwrap();
+        let txn = Self::prepared_txn(&prepared, gid)?;
+        txns.abort_prepared(txn.tid)?;
+        self.pending_changes.lock().unwrap().remove(&txn.tid);
+        prepared.remove(gid);
+        self.write_prepared(&prepared)
+    }
+
+    /// Returns the global ids of the prepared transactions, e.g. for a coordinator to
+    /// finish the ones it was running after a restart.
+    pub fn prepared_transactions(&self) -> Vec<String> {
+        let mut gids: Vec<String> = self.prepared.lock().unwrap().keys().cloned().collect();
+        gids.sort();
+        gids
+    }
+
+    fn prepared_txn(
+        prepared: &HashMap<String, PreparedTxn>,
+        gid: &str,
+    ) -> Result<PreparedTxn, CrustyError> {
+        prepared.get(gid).cloned().ok_or_else(|| {
+            CrustyError::ExecutionError(format!("No transaction is prepared as {}", gid))
+        })
+    }
+
+    /// Persist the prepared transactions, see `read_prepared`.
+    fn write_prepared(&self, prepared: &HashMap<String, PreparedTxn>) -> Result<(), CrustyError> {
+        write_atomic(
+            &self.storage_path,
+            "prepared_txns",
+            &serde_json::to_vec(prepared).unwrap(),
+        )
+    }
+
+    /// Purge the versions of values in a container that no transaction can see anymore:
+    /// the ones deleted before the snapshot of every active transaction, and the ones
+    /// created by aborted transactions, with MVCC, the expired ones in a container with a
+    /// time-to-live, and the deleted ones in a container with a retention window once it
+    /// has passed. Returns the number of versions purged. Errors for a container without a
+    /// time-to-live or a retention window if the storage manager does not use MVCC.
+    pub fn vacuum(&self, container_id: ContainerId) -> Result<usize, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let config = hf.config();
+        if config.ttl.is_none() && config.retention.is_none() {
+            self.txns()?;
+        }
+        let txns = self.txns.as_ref();
+        let expired_until = config.ttl.map(record::expired_until);
+        let retained_after = config.retention.map(record::retained_after);
+        let _modify = hf.modify_lock.lock().unwrap();
+        // hold off the other page writes while pages are rewritten
+        let _c_map = self.c_map.write().unwrap();
+        let mut removed = 0;
+        for page_id in 0..hf.num_pages() {
+            let mut page = hf.read_page_from_file(page_id)?;
+            let mut changed = false;
+            let mut purged = Vec::new();
+            for (bytes, slot_id) in hf.read_page_from_file(page_id)? {
+                let (header, value) = RecordHeader::unwrap(&bytes);
+                let action = match txns {
+                    _ if header.is_expired(expired_until) => VacuumAction::Remove,
+                    _ if header.is_retained(retained_after) => VacuumAction::Keep,
+                    Some(txns) => txns.vacuum_action(&header),
+                    None if header.deleter().is_some() => VacuumAction::Remove,
+                    None => VacuumAction::Keep,
+                };
+                match action {
+                    VacuumAction::Keep => continue,
+                    VacuumAction::Remove => {
+                        page.delete_value(slot_id);
+                        purged.push(value.to_vec());
+                        removed += 1;
+                    }
+                    VacuumAction::Undelete => {
+                        page.overwrite_value(slot_id, &header.undeleted().wrap(value));
+                    }
+                }
+                changed = true;
+            }
+            if changed {
+                self.log_page(container_id, &hf, &page)?;
+                hf.write_page_to_file(page)?;
+            }
+            for value in purged {
+                self.bloom_filters.remove(container_id, &value);
+            }
+        }
+        if let Some(txns) = txns {
+            txns.prune();
+        }
+        Ok(removed)
+    }
+
+    /// Merge the values of a container into its first pages, to reclaim the space deletes
+    /// left in them. Values are moved from the last pages into the free space of the first
+    /// ones, up to the container's fill factor, until the two meet. Each moved value leaves
+    /// a forwarding stub at its old page and slot, so the ids handed out before, e.g. the
+    /// ones in indexes, still find it: reads, updates and deletes follow the stub, one hop
+    /// at most, as the stubs of a value moved again are pointed at its new place. Scans
+    /// return moved values with their new ids. Once the indexes are rewritten with the ids
+    /// of `forwards`, `drop_forwards` deletes the stubs. Returns the number of values moved.
+    ///
+    /// Scans running meanwhile may miss moved values or return them twice.
+    pub fn compact(&self, container_id: ContainerId) -> Result<usize, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        // hold off the other page writes while pages are rewritten
+        let _c_map = self.c_map.write().unwrap();
+        let fill_factor = hf.config().fill_factor;
+        // the stub forwarding to each value moved before, if any
+        let mut stubs: HashMap<(PageId, SlotId), (PageId, SlotId)> = HashMap::new();
+        for page_id in 0..hf.num_pages() {
+            for (slot_id, to) in hf.read_page_from_file(page_id)?.forwards() {
+                stubs.insert(to, (page_id, slot_id));
+            }
+        }
+        // the pages changed, written back at the end
+        let mut pages: BTreeMap<PageId, Page> = BTreeMap::new();
+        let read = |pages: &mut BTreeMap<PageId, Page>, page_id: PageId| {
+            if let btree_map::Entry::Vacant(entry) = pages.entry(page_id) {
+                entry.insert(hf.read_page_from_file(page_id)?);
+            }
+            Ok::<_, CrustyError>(())
+        };
+        let mut moved = 0;
+        let mut target_id = 0;
+        let mut source_id = hf.num_pages();
+        'sources: while source_id > target_id + 1 {
+            source_id -= 1;
+            read(&mut pages, source_id)?;
+            let slots: Vec<SlotId> = pages[&source_id]
+                .slots()
+                .iter()
+                .filter(|slot| slot.is_live())
+                .map(|slot| slot.slot_id)
+                .collect();
+            for slot_id in slots {
+                let value = pages[&source_id].get_value(slot_id).unwrap();
+                // a value shorter than its stub is not worth moving
+                if value.len() < FORWARD_STUB_SIZE {
+                    continue;
+                }
+                let to = loop {
+                    if target_id >= source_id {
+                        break 'sources;
+                    }
+                    read(&mut pages, target_id)?;
+                    let target = pages.get_mut(&target_id).unwrap();
+                    if let Some(to) = target.add_moved_value(&value, fill_factor) {
+                        break (target_id, to);
+                    }
+                    target_id += 1;
+                };
+                pages.get_mut(&source_id).unwrap().forward(slot_id, to);
+                if let Some((page_id, stub)) = stubs.remove(&(source_id, slot_id)) {
+                    read(&mut pages, page_id)?;
+                    pages.get_mut(&page_id).unwrap().forward(stub, to);
+                }
+                moved += 1;
+            }
+        }
+        for page in pages.into_values() {
+            self.log_page(container_id, &hf, &page)?;
+            hf.write_page_to_file(page)?;
+        }
+        Ok(moved)
+    }
+
+    /// Returns the values of a container moved by `compact` whose stubs were not dropped:
+    /// the ids they were moved from and the ids they were moved to, in the order of the
+    /// old ids. The values deleted since are left out.
+    pub fn forwards(
+        &self,
+        container_id: ContainerId,
+    ) -> Result<Vec<(ValueId, ValueId)>, CrustyError> {
+        let hf = self.heap_file(container_id)?;
+        let id = |page_id, slot_id| hf.value_id(page_id, slot_id);
+        let mut forwards = Vec::new();
+        for page_id in 0..hf.num_pages() {
+            for (slot_id, (to_page, to_slot)) in hf.read_page_from_file(page_id)?.forwards() {
+                if hf
+                    .read_page_from_file(to_page)?
+                    .get_value_ref(to_slot)
+                    .is_some()
+                {
+                    forwards.push((id(page_id, slot_id), id(to_page, to_slot)));
+                }
+            }
+        }
+        Ok(forwards)
+    }
+
+    /// Delete the forwarding stubs `compact` left in a container, once nothing refers to the
+    /// moved values by their old ids, e.g. after their index entries were rewritten with
+    /// `forwards`. The old ids then no longer find the values. Returns the number of stubs
+    /// deleted.
+    pub fn drop_forwards(&self, container_id: ContainerId) -> Result<usize, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        let _c_map = self.c_map.write().unwrap();
+        let mut dropped = 0;
+        for page_id in 0..hf.num_pages() {
+            let mut page = hf.read_page_from_file(page_id)?;
+            if let Some(stubs) = page.clear_forwards() {
+                dropped += stubs;
+                self.log_page(container_id, &hf, &page)?;
+                hf.write_page_to_file(page)?;
+            }
+        }
+        Ok(dropped)
+    }
+
+    /// Restore a value deleted from a container with a retention window, before the window
+    /// passes and vacuum reclaims it, see `ContainerConfig::retention`. Returns the id of the
+    /// restored value: the same one, or with MVCC a new version created by tid, as the
+    /// deleted version stays deleted for the snapshots that saw it so. The restore is
+    /// captured as an insert. Errors if the value is not deleted, or with MVCC if tid does
+    /// not see it deleted, and if it was deleted before the retention window.
+    pub fn undelete(&self, id: ValueId, tid: TransactionId) -> Result<ValueId, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(id.container_id)?;
+        let retention = hf.config().retention.ok_or_else(|| {
+            CrustyError::ExecutionError(format!(
+                "Container {} does not retain deleted values",
+                id.container_id
+            ))
+        })?;
+        let modify = hf.modify_lock.lock().unwrap();
+        let (mut page, slot_id) = self.value_page(&hf, &id)?;
+        let bytes = page
+            .get_value(slot_id)
+            .ok_or_else(|| CrustyError::invalid_slot(&id))?;
+        let (header, value) = RecordHeader::unwrap(&bytes);
+        let deleted = match &self.txns {
+            Some(txns) => {
+                let snapshot = txns.snapshot(tid)?;
+                txns.is_visible(&header.undeleted(), tid.id(), snapshot)
+                    && !txns.is_visible(&header, tid.id(), snapshot)
+            }
+            None => header.deleter().is_some(),
+        };
+        if !deleted {
+            return Err(CrustyError::ExecutionError(format!(
+                "Value {:?} is not deleted",
+                id
+            )));
+        }
+        if !header.is_retained(Some(record::retained_after(retention))) {
+            return Err(CrustyError::ExecutionError(format!(
+                "Value {:?} was deleted more than {:?} ago",
+                id, retention
+            )));
+        }
+        let value = value.to_vec();
+        if self.txns.is_some() {
+            drop(modify);
+            return self.insert_value(id.container_id, value, tid);
+        }
+        page.overwrite_value(slot_id, &header.undeleted().wrap(&value));
+        self.write_page(id.container_id, page, tid)?;
+        drop(modify);
+        let after = Some((id, value));
+        self.capture(
+            id.container_id,
+            tid,
+            Change {
+                before: None,
+                after,
+            },
+        )?;
+        Ok(id)
+    }
+
+    /// Start capturing the inserts, updates and deletes made to a container, for external
+    /// systems to read with `read_changes`; see the `cdc` module. The capture goes on after
+    /// a restart, until `unsubscribe_changes`. Does nothing if the changes are already
+    /// captured.
+    pub fn subscribe_changes(&self, container_id: ContainerId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        // the container is not changed while the capture starts
+        let _modify = hf.modify_lock.lock().unwrap();
+        let mut change_logs = self.change_logs.write().unwrap();
+        if let Entry::Vacant(entry) = change_logs.entry(container_id) {
+            entry.insert(Arc::new(ChangeLog::create(
+                &self.storage_path,
+                container_id,
+            )?));
+        }
+        Ok(())
+    }
+
+    /// Stop capturing the changes made to a container, dropping the ones not discarded yet.
+    pub fn unsubscribe_changes(&self, container_id: ContainerId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        self.change_log(container_id)?;
+        match self.change_logs.write().unwrap().remove(&container_id) {
+            Some(log) => log.remove(),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns up to max of the changes captured for a container, from the one numbered
+    /// from on, in the order they were made. Consumers pass the sequence number after the
+    /// last one they processed. Errors if the changes to the container are not captured.
+    pub fn read_changes(
+        &self,
+        container_id: ContainerId,
+        from: u64,
+        max: usize,
+    ) -> Result<Vec<ChangeEvent>, CrustyError> {
+        let mut events = self.change_log(container_id)?.read(from, max)?;
+        // the log keeps the page and slot of the ids, their segment follows from the page
+        let hf = self.heap_file(container_id)?;
+        for event in events.iter_mut() {
+            for (id, _) in event.before.iter_mut().chain(event.after.iter_mut()) {
+                id.segment_id = id.page_id.and_then(|page_id| hf.segment_of(page_id));
+            }
+        }
+        Ok(events)
+    }
+
+    /// Discard the changes captured for a container up to the one numbered through,
+    /// inclusive, once consumers processed them. Later changes keep their numbers.
+    pub fn ack_changes(&self, container_id: ContainerId, through: u64) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        self.change_log(container_id)?.discard(through)
+    }
+
+    /// Returns the change log of a container, erroring if its changes are not captured.
+    fn change_log(&self, container_id: ContainerId) -> Result<Arc<ChangeLog>, CrustyError> {
+        self.change_logs
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .cloned()
+            .ok_or_else(|| {
+                CrustyError::ExecutionError(format!(
+                    "Changes to container {} are not captured",
+                    container_id
+                ))
+            })
+    }
+
+    /// Returns the value in a slot of a page, without its record header, if the changes to
+    /// the container are captured, for the event of a change to it. None for a value
+    /// already deleted in a container with a retention window, without MVCC.
+    fn captured_value(&self, page: &Page, hf: &HeapFile, slot_id: SlotId) -> Option<Vec<u8>> {
+        if !self
+            .change_logs
+            .read()
+            .unwrap()
+            .contains_key(&hf.container_id)
+        {
+            return None;
+        }
+        let bytes = page.get_value_ref(slot_id)?;
+        match self.has_headers(hf) {
+            true => {
+                let (header, value) = RecordHeader::unwrap(bytes);
+                (self.txns.is_some() || header.deleter().is_none()).then(|| value.to_vec())
+            }
+            false => Some(bytes.to_vec()),
+        }
+    }
+
+    /// Record a change to a container, if its changes are captured: with MVCC once the
+    /// transaction commits, otherwise right away.
+    fn capture(
+        &self,
+        container_id: ContainerId,
+        tid: TransactionId,
+        change: Change,
+    ) -> Result<(), CrustyError> {
+        let log = match self.change_logs.read().unwrap().get(&container_id) {
+            Some(log) => log.clone(),
+            None => return Ok(()),
+        };
+        match &self.txns {
+            Some(_) => self
+                .pending_changes
+                .lock()
+                .unwrap()
+                .entry(tid.id())
+                .or_default()
+                .push((container_id, change)),
+            None => log.append(tid.id(), [change])?,
+        }
+        Ok(())
+    }
+
+    /// Record the changes of a committed transaction and sync the logs they are in.
+    fn record_changes(&self, tid: TidType) -> Result<(), CrustyError> {
+        let changes = match self.pending_changes.lock().unwrap().remove(&tid) {
+            Some(changes) => changes,
+            None => return Ok(()),
+        };
+        let mut containers: BTreeMap<ContainerId, Vec<Change>> = BTreeMap::new();
+        for (container_id, change) in changes {
+            containers.entry(container_id).or_default().push(change);
+        }
+        let change_logs = self.change_logs.read().unwrap();
+        for (container_id, changes) in containers {
+            // the capture may have stopped since
+            if let Some(log) = change_logs.get(&container_id) {
+                log.append(tid, changes)?;
+                log.sync()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sync the changes recorded in the change logs to disk.
+    fn sync_change_logs(&self) -> Result<(), CrustyError> {
+        for log in self.change_logs.read().unwrap().values() {
+            log.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Insert a value like `insert_value`, without capturing the change.
+    fn add_value(
+        &self,
+        container_id: ContainerId,
+        value: &[u8],
+        tid: TransactionId,
+    ) -> Result<ValueId, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let stored = self.stored_value(&hf, value, tid)?;
+        // keep other inserts and deletes from rewriting the page between reading and
+        // writing it back
+        let _modify = hf.modify_lock.lock().unwrap();
+        self.place_value(&hf, &stored, value, tid)
+    }
+
+    /// Stores the bytes of a value in the first page of a container with room for them, or
+    /// a new page at the end. The caller holds the container's modify lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `hf` - Heap file of the container.
+    /// * `stored` - Bytes to store, as returned by `stored_value`.
+    /// * `value` - Value the bytes hold, added to the container's bloom filter.
+    /// * `tid` - Transaction writing the value.
+    fn place_value(
+        &self,
+        hf: &HeapFile,
+        stored: &[u8],
+        value: &[u8],
+        tid: TransactionId,
+    ) -> Result<ValueId, CrustyError> {
+        let container_id = hf.container_id;
+        // starting with the smallest p_id, iterate through all pages until you
+        // find a page that can hold the value
+        // if no page can hold the value, create a new page at the end of the file
+        // leave room in the existing pages as the container's fill factor asks
+        let fill_factor = hf.config().fill_factor;
+        let num_pages = hf.num_pages();
+        let mut p_id = 0;
+        let (page, slot_id) = loop {
+            if p_id >= num_pages {
+                let mut new_page = Page::new(p_id);
+                let slot_id = new_page.add_value(stored).ok_or(CrustyError::PageFull {
+                    container_id,
+                    page_id: p_id,
+                })?;
+                break (new_page, slot_id);
+            }
+            let mut pg = hf.read_page_from_file(p_id)?;
+            if let Some(slot_id) = pg.add_value_with_fill_factor(stored, fill_factor) {
+                break (pg, slot_id);
+            }
+            // increment p_id to try next page
+            p_id += 1;
+        };
+        // write the page to the hf and return the ValueID
+        self.write_page(container_id, page, tid)?;
+        self.bloom_filters.insert(container_id, value);
+        Ok(hf.value_id(p_id, slot_id))
+    }
+
+    /// Returns the bytes stored for a value written by tid: prefixed with a record header
+    /// if the container has them (see `has_headers`), holding tid as its creator, in a
+    /// container with a time-to-live the current time, and in a container with a retention
+    /// window room for the deletion time.
+    fn stored_value(
+        &self,
+        hf: &HeapFile,
+        value: &[u8],
+        tid: TransactionId,
+    ) -> Result<Vec<u8>, CrustyError> {
+        if let Some(txns) = &self.txns {
+            txns.record_write(tid, hf.container_id)?;
+        }
+        Ok(self.new_record(hf, value, tid))
+    }
+
+    /// Returns the bytes stored for a value written by tid, like `stored_value`, without
+    /// recording the write with MVCC.
+    fn new_record(&self, hf: &HeapFile, value: &[u8], tid: TransactionId) -> Vec<u8> {
+        if !self.has_headers(hf) {
+            return value.to_vec();
+        }
+        let config = hf.config();
+        let mut header = RecordHeader::new(tid);
+        if config.ttl.is_some() {
+            header = header.inserted_at(record::now_millis());
+        }
+        if config.retention.is_some() {
+            header = header.retained();
+        }
+        header.wrap(value)
+    }
+
+    /// Delete the value in a slot of a page the caller writes back. With MVCC the version is
+    /// marked deleted by tid instead, keeping it for older snapshots, and so is the value of
+    /// a container with a retention window, keeping it for `undelete`. Returns the value
+    /// removed from the page, whose key the caller removes from the bloom filter: None if it
+    /// was marked deleted, as vacuum removes it once the value is purged, or if the slot was
+    /// empty.
+    fn delete_in_page(
+        &self,
+        page: &mut Page,
+        hf: &HeapFile,
+        slot_id: SlotId,
+        tid: TransactionId,
+    ) -> Result<Option<Vec<u8>>, CrustyError> {
+        let now = record::now_millis();
+        match (&self.txns, page.get_value(slot_id)) {
+            (Some(txns), Some(bytes)) => {
+                let (header, value) = RecordHeader::unwrap(&bytes);
+                txns.check_delete(&header, tid)?;
+                txns.record_write(tid, hf.container_id)?;
+                let deleted = header.deleted_by(tid).deleted_at(now);
+                page.overwrite_value(slot_id, &deleted.wrap(value));
+                Ok(None)
+            }
+            (None, Some(bytes)) if hf.config().retention.is_some() => {
+                let (header, value) = RecordHeader::unwrap(&bytes);
+                if header.deleter().is_none() {
+                    let deleted = header.deleted_by(tid).deleted_at(now);
+                    page.overwrite_value(slot_id, &deleted.wrap(value));
+                }
+                Ok(None)
+            }
+            (_, bytes) => {
+                page.delete_value(slot_id);
+                match bytes {
+                    Some(bytes) if self.has_headers(hf) => {
+                        Ok(Some(RecordHeader::unwrap(&bytes).1.to_vec()))
+                    }
+                    bytes => Ok(bytes),
+                }
+            }
+        }
+    }
+
+    /// Log a change in the WAL, if there is one. Callers hold the c_map write lock, so
+    /// changes are logged in the order they are made.
+    fn log(&self, record: WalRecord) -> Result<(), CrustyError> {
+        if let Some(wal) = &self.wal {
+            wal.append(record)?;
+        }
+        Ok(())
+    }
+
+    /// Log a page write in the WAL, if there is one, before the page is written. The write
+    /// is logged as a `PageDelta` of the bytes it changes if that is smaller than the page
+    /// and the page was logged in full since the last checkpoint, so a page a crash cuts
+    /// short is restored before its deltas are replayed. Otherwise the page is logged in
+    /// full. Callers hold the c_map write lock, so the file holds the page being replaced.
+    fn log_page(
+        &self,
+        container_id: ContainerId,
+        hf: &HeapFile,
+        page: &Page,
+    ) -> Result<(), CrustyError> {
+        if self.wal.is_none() {
+            return Ok(());
+        }
+        let page_id = page.get_page_id();
+        let mut logged_pages = self.logged_pages.lock().unwrap();
+        if page_id < hf.num_pages() && logged_pages.contains(&(container_id, page_id)) {
+            let delta = page.delta(&hf.read_page_from_file(page_id)?.to_bytes());
+            if delta.size() < PAGE_SIZE {
+                return self.log(WalRecord::PageDelta {
+                    container_id,
+                    delta,
+                });
+            }
+        }
+        logged_pages.insert((container_id, page_id));
+        self.log(WalRecord::Page {
+            container_id,
+            page: page.to_bytes(),
+        })
+    }
+
+    /// Log a change in the WAL, if there is one, and wait until it is on disk.
+    fn log_synced(&self, record: WalRecord) -> Result<(), CrustyError> {
+        if let Some(wal) = &self.wal {
+            let lsn = wal.append(record)?;
+            wal.flush_to(lsn)?;
+        }
+        Ok(())
+    }
+
+    /// Removes temporary containers that are no longer needed.
+    fn remove_temp_containers(&self, container_ids: Vec<ContainerId>) {
+        for container_id in container_ids {
+            if let Err(e) = self.remove_container(container_id) {
+                sm_log!(
+                    self,
+                    Level::Warn,
+                    "Cannot remove temp container {}: {:?}",
+                    container_id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Set the most verbose level of the diagnostics this storage manager logs. The logger
+    /// may filter them further. Logs every level by default.
+    pub fn set_log_level(&self, level: LevelFilter) {
+        self.log_level.store(level as usize, Ordering::Relaxed);
+    }
+
+    /// Returns the most verbose level of the diagnostics this storage manager logs.
+    pub fn log_level(&self) -> LevelFilter {
+        LOG_LEVELS[self.log_level.load(Ordering::Relaxed)]
+    }
+
+    /// Returns true if this StorageManager was opened with `open_read_only`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Returns the containers with their sizes, in order of id.
+    pub fn list_containers(&self) -> Result<Vec<ContainerInfo>, CrustyError> {
+        let c_map = self.c_map.read().unwrap();
+        let dependencies = self.dependencies.read().unwrap();
+        let mut containers = c_map
+            .iter()
+            .map(|(container_id, hf)| {
+                Ok(ContainerInfo {
+                    container_id: *container_id,
+                    num_pages: hf.num_pages(),
+                    file_size: hf.file_size()?,
+                    dependencies: dependencies.get(container_id).cloned().unwrap_or_default(),
+                    is_temp: is_temp_container(*container_id),
+                    config: hf.config(),
+                })
+            })
+            .collect::<Result<Vec<_>, CrustyError>>()?;
+        containers.sort_unstable_by_key(|c| c.container_id);
+        Ok(containers)
+    }
+
+    /// Creates a new container whose pages are written with the options of config. The
+    /// options are persisted with the other metadata of the containers by `shutdown`, and
+    /// logged with the creation of the container in the WAL. Errors if an option is out of
+    /// range, or if config keeps the container in memory split into segments. Without a compression feature, pages are not compressed
+    /// whatever `ContainerConfig::compression` is.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Id of the new container.
+    /// * `config` - Options of the container.
+    /// * `dependencies` - Containers the new container depends on, see `create_container`.
+    pub fn create_container_with_config(
+        &self,
+        container_id: ContainerId,
+        config: ContainerConfig,
+        dependencies: Option<Vec<ContainerId>>,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        check_config(&config)?;
+        // the containers this one depends on must exist
+        if let Some(dependencies) = &dependencies {
+            let c_map = self.c_map.read().unwrap();
+            if let Some(missing) = dependencies.iter().find(|c| !c_map.contains_key(c)) {
+                return Err(CrustyError::ExecutionError(format!(
+                    "Dependency container {} does not exist",
+                    missing
+                )));
+            }
+            self.dependencies
+                .write()
+                .unwrap()
+                .insert(container_id, dependencies.clone());
+        }
+        let hf = self.new_heap_file(container_id, config)?;
+
+        let mut c_map = self.c_map.write().unwrap();
+        self.log(WalRecord::CreateContainer {
+            container_id,
+            dependencies: dependencies.unwrap_or_default(),
+            config,
+        })?;
+        c_map.insert(container_id, Arc::new(hf));
+        Ok(())
+    }
+
+    /// Create the heap file of a new container with the options of config, in memory if
+    /// `ContainerConfig::in_memory` is set.
+    fn new_heap_file(
+        &self,
+        container_id: ContainerId,
+        config: ContainerConfig,
+    ) -> Result<HeapFile, CrustyError> {
+        let hf = if config.in_memory {
+            HeapFile::new_in_memory(container_id)
+        } else {
+            HeapFile::new(
+                container_path(&self.storage_path, container_id),
+                container_id,
+            )?
+        };
+        Ok(hf
+            .with_config(config)?
+            .with_buffer_pool(self.buffer_pool.clone()))
+    }
+
+    /// Returns the options of a container.
+    pub fn container_config(
+        &self,
+        container_id: ContainerId,
+    ) -> Result<ContainerConfig, CrustyError> {
+        Ok(self.heap_file(container_id)?.config())
+    }
+
+    /// Change the options of a container, e.g. to raise its quota. They apply to the pages
+    /// written from now on: lowering the quota below the pages a container has keeps them,
+    /// but it cannot grow. The change is logged in the WAL, and persisted with the other
+    /// options by `shutdown`. Errors like `create_container_with_config` for options heap
+    /// files do not support, and if the container would move between memory and a file, or
+    /// gain or lose a time-to-live or a retention window, or change its segments.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to change.
+    /// * `config` - New options of the container.
+    pub fn set_container_config(
+        &self,
+        container_id: ContainerId,
+        config: ContainerConfig,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        check_config(&config)?;
+        let c_map = self.c_map.write().unwrap();
+        let hf = c_map
+            .get(&container_id)
+            .ok_or(CrustyError::ContainerMissing(container_id))?;
+        if config.in_memory != hf.is_in_memory() {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot move container {} between memory and a file",
+                container_id
+            )));
+        }
+        // the values of a container with a time-to-live are stored with their insertion time,
+        // and the ones of a container with a retention window with their deletion time
+        if config.ttl.is_some() != hf.config().ttl.is_some() {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot add or remove the time-to-live of container {}",
+                container_id
+            )));
+        }
+        if config.retention.is_some() != hf.config().retention.is_some() {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot add or remove the retention window of container {}",
+                container_id
+            )));
+        }
+        // pages are stored in the segment their id falls in
+        if config.segment_pages != hf.config().segment_pages {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot change the segments of container {}",
+                container_id
+            )));
+        }
+        self.log(WalRecord::SetConfig {
+            container_id,
+            config,
+        })?;
+        hf.set_config(config);
+        Ok(())
+    }
+
+    /// Errors with `CrustyError::QuotaExceeded` if adding new_pages pages to a heap file
+    /// would take it past the page limit of its container.
+    fn check_quota(&self, hf: &HeapFile, new_pages: usize) -> Result<(), CrustyError> {
+        match hf.config().page_limit() {
+            Some(max_pages) if hf.num_pages() as usize + new_pages > max_pages as usize => {
+                Err(CrustyError::QuotaExceeded {
+                    container_id: hf.container_id,
+                    max_pages,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Give a container a new id, renaming its heap file and updating the containers that
+    /// depend on it. The rename is logged in the WAL, which is synced before the file is
+    /// renamed, so a storage manager that crashes recovers it. Like the other container
+    /// changes, it is persisted in the metadata of the storage path by `shutdown`. Iterators
+    /// opened before keep reading the container under its old id.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to rename.
+    /// * `new_container_id` - New id of the container. No container may have it, and
+    ///   neither id may be one of a temporary container.
+    pub fn rename_container(
+        &self,
+        container_id: ContainerId,
+        new_container_id: ContainerId,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        if is_temp_container(container_id) || is_temp_container(new_container_id) {
+            return Err(CrustyError::ExecutionError(format!(
+                "Cannot rename container {} to {}: temporary containers cannot be renamed",
+                container_id, new_container_id
+            )));
+        }
+        let hf = self.heap_file(container_id)?;
+        // no page of the container is being changed while the file is renamed. Changes
+        // take the modify lock before the c_map lock, so it is taken first here too.
+        let modify = hf.modify_lock.lock().unwrap();
+        let mut c_map = self.c_map.write().unwrap();
+        let mut dependencies = self.dependencies.write().unwrap();
+        if !c_map
+            .get(&container_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &hf))
+        {
+            return Err(CrustyError::ContainerMissing(container_id));
+        }
+        if hf.is_in_memory() {
+            return Err(CrustyError::ExecutionError(format!(
+                "Cannot rename container {}: containers in memory cannot be renamed",
+                container_id
+            )));
+        }
+        if c_map.contains_key(&new_container_id) {
+            return Err(CrustyError::ExecutionError(format!(
+                "Cannot rename container {} to {}, which already exists",
+                container_id, new_container_id
+            )));
+        }
+        self.log_synced(WalRecord::RenameContainer {
+            container_id,
+            new_container_id,
+        })?;
+        let new_hf = rename_heap_file(&self.storage_path, &hf, new_container_id)?;
+        drop(modify);
+        c_map.remove(&container_id);
+        c_map.insert(new_container_id, Arc::new(new_hf));
+        self.buffer_pool.discard_container(container_id);
+        rename_dependencies(&mut dependencies, container_id, new_container_id);
+        self.bloom_filters
+            .rename_container(container_id, new_container_id);
+        // the changes captured go on under the new id
+        let mut change_logs = self.change_logs.write().unwrap();
+        if change_logs.remove(&container_id).is_some() {
+            let path = cdc::log_path(&self.storage_path, container_id);
+            fs::rename(&path, cdc::log_path(&self.storage_path, new_container_id))
+                .map_err(|e| CrustyError::io_with_path(&path, e))?;
+            let log = ChangeLog::open(&self.storage_path, new_container_id)?;
+            change_logs.insert(new_container_id, Arc::new(log));
+        }
+        drop(change_logs);
+        sm_log!(
+            self,
+            Level::Info,
+            "Renamed container {} to {}",
+            container_id,
+            new_container_id
+        );
+        Ok(())
+    }
+
+    /// Drop all the values of a container, keeping the container, its dependencies and its
+    /// bloom filter, which is emptied. The truncation is logged in the WAL and synced like a
+    /// rename, see `rename_container`. It is not part of a transaction: it takes effect at once for every transaction and cannot be rolled
+    /// back. Errors if an iterator over a snapshot of the container is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to truncate.
+    pub fn truncate_container(&self, container_id: ContainerId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        hf.truncate(|| self.log_synced(WalRecord::TruncateContainer { container_id }))?;
+        self.bloom_filters.clear_container(container_id);
+        sm_log!(self, Level::Info, "Truncated container {}", container_id);
+        Ok(())
+    }
+
+    /// Drop all the values of a segment of a container at once, by truncating its file,
+    /// e.g. to discard the oldest values of a container that is only appended to. See
+    /// `ContainerConfig::segment_pages`. Like `truncate_container`, it is logged, not part of
+    /// a transaction, and leaves the ids of the dropped values in indexes. The pages of the
+    /// segment are empty afterwards, and filled again by later inserts. Errors if the
+    /// container is not split into segments, for its last segment, and if an iterator over
+    /// a snapshot of the container is open.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the segment.
+    /// * `segment` - Segment to truncate.
+    pub fn truncate_segment(
+        &self,
+        container_id: ContainerId,
+        segment: SegmentId,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        hf.truncate_segment(segment, || {
+            self.log_synced(WalRecord::TruncateSegment {
+                container_id,
+                segment,
+            })
+        })?;
+        sm_log!(
+            self,
+            Level::Info,
+            "Truncated segment {} of container {}",
+            segment,
+            container_id
+        );
+        Ok(())
+    }
+
+    /// Imports the records of a Parquet file into a container and returns the number of
+    /// records imported. Error if a column of the table is missing from the file or has a
+    /// Parquet type that cannot be imported.
+    #[cfg(feature = "parquet")]
+    pub fn import_parquet(
+        &self,
+        table: &Table,
+        path: String,
+        tid: TransactionId,
+        container_id: ContainerId,
+    ) -> Result<usize, CrustyError> {
+        self.check_writable()?;
+        sm_log!(self, Level::Debug, "Importing parquet file {}", path);
+        let imported = parquet_io::import_parquet(&table.schema, Path::new(&path), |tuple| {
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        sm_log!(self, Level::Info, "Num records imported: {:?}", imported);
+        Ok(imported)
+    }
+
+    /// Writes the visible records of a container to a Parquet file in row groups of
+    /// `options.row_group_size` records, and returns the number of records written.
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        options: &ParquetExportOptions,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        self.heap_file(container_id)?;
+        sm_log!(
+            self,
+            Level::Debug,
+            "Exporting container {} to {}",
+            container_id,
+            path
+        );
+        let mut iter = self.get_iterator(container_id, tid, Permissions::ReadOnly);
+        let values = iter.by_ref().map(|(bytes, _)| bytes);
+        let exported = parquet_io::export_parquet(values, schema, Path::new(&path), options)?;
+        if let Some(e) = iter.error() {
+            return Err(e.clone());
+        }
+        sm_log!(self, Level::Info, "Num records exported: {:?}", exported);
+        Ok(exported)
+    }
+
+    /// Returns the visible records of a container as Arrow record batches, which are built as
+    /// the container is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to scan.
+    /// * `schema` - Schema of the records.
+    /// * `tid` - Transaction scanning the container.
+    /// * `batch_rows` - Records per batch; the last batch may have fewer.
+    #[cfg(feature = "arrow")]
+    pub fn scan_record_batches(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        tid: TransactionId,
+        batch_rows: usize,
+    ) -> Result<TupleBatches<impl Iterator<Item = Result<Tuple, CrustyError>>>, CrustyError> {
+        self.heap_file(container_id)?;
+        let mut iter = self.get_iterator(container_id, tid, Permissions::ReadOnly);
+        let mut ended = false;
+        // a page that cannot be read ends the scan with its error
+        let tuples = std::iter::from_fn(move || match iter.next() {
+            Some((bytes, _)) => Some(Ok(Tuple::from_bytes(&bytes))),
+            None if !ended => {
+                ended = true;
+                iter.error().cloned().map(Err)
+            }
+            None => None,
+        });
+        TupleBatches::new(schema.clone(), tuples, batch_rows)
+    }
+
+    /// Errors if this StorageManager was opened with `open_read_only`.
+    fn check_writable(&self) -> Result<(), CrustyError> {
+        if self.read_only {
+            return Err(CrustyError::ExecutionError(format!(
+                "Storage manager at {} is open read-only",
+                self.storage_path.to_string_lossy()
+            )));
+        }
+        if self.is_standby() {
+            return Err(CrustyError::ExecutionError(format!(
+                "Storage manager at {} is a standby until it is promoted",
+                self.storage_path.to_string_lossy()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the heapfile of a container, erroring if the container does not exist.
+    fn heap_file(&self, container_id: ContainerId) -> Result<Arc<HeapFile>, CrustyError> {
+        self.c_map
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .cloned()
+            .ok_or(CrustyError::ContainerMissing(container_id))
+    }
+
+    /// Get a page of a container through the buffer pool. If pin, the page stays in the
+    /// pool until the returned guard is dropped, so it can be read again, e.g. to fetch
+    /// more values from it, without reading the file.
+    pub fn get_page(
         &self,
         container_id: ContainerId,
         page_id: PageId,
         _tid: TransactionId,
         _perm: Permissions,
-        _pin: bool,
-    ) -> Option<Page> {
+        pin: bool,
+    ) -> Result<PageGuard, CrustyError> {
+        self.heap_file(container_id)?.fetch_page(page_id, pin)
+    }
+
+    /// Create temporary containers, such as the ones operators spill to, in memory instead
+    /// of in files, for datasets that fit in memory. See `ContainerConfig::in_memory`.
+    pub fn with_in_memory_temp_containers(mut self) -> Self {
+        self.temp_in_memory = true;
+        self
+    }
+
+    /// Set the number of pages a sequential scan reads past the page it is on with the same
+    /// read, `DEFAULT_READ_AHEAD` by default, 0 to read one page at a time.
+    pub fn with_read_ahead(mut self, pages: PageId) -> Self {
+        self.read_ahead = pages;
+        self
+    }
+
+    /// Set the number of pages the buffer pool holds, `buffer_pool::DEFAULT_CAPACITY` by
+    /// default. Pages are evicted if it holds more.
+    pub fn with_buffer_pool_capacity(self, pages: usize) -> Self {
+        self.buffer_pool.set_capacity(pages);
+        self
+    }
+
+    /// Set the order the buffer pool evicts pages in, `EvictionPolicy::Lru` by default.
+    pub fn with_eviction_policy(self, policy: EvictionPolicy) -> Self {
+        self.buffer_pool.set_policy(policy);
+        self
+    }
+
+    /// Keep up to this many pages evicted from the buffer pool compressed in memory, so
+    /// reading them again does not go to the file. None are kept by default.
+    pub fn with_cold_pages(self, pages: usize) -> Self {
+        self.buffer_pool.set_cold_capacity(pages);
+        self
+    }
+
+    /// Returns the hit, miss and eviction counters of the buffer pool for the containers
+    /// whose pages were read with `get_page`, to tune its capacity and policy.
+    pub fn buffer_pool_stats(&self) -> HashMap<ContainerId, BufferPoolStats> {
         let c_map = self.c_map.read().unwrap();
-        if !(c_map.contains_key(&container_id)) {
-            println!("Container ID not found in StorageManager's c_map");
-            return None;
-        }
-        // otherwise we get the specified container and read the page
-        let hf = &c_map[&container_id];
-        match hf.read_page_from_file(page_id) {
-            Ok(page) => Some(page),
-            Err(_) => None,
-        }
+        let mut stats = self.buffer_pool.stats();
+        stats.retain(|container_id, _| c_map.contains_key(container_id));
+        stats
     }
 
     /// Write a page
@@ -61,35 +2260,57 @@ impl StorageManager {
         _tid: TransactionId,
     ) -> Result<(), CrustyError> {
         let c_map = self.c_map.write().unwrap();
-        if !(c_map.contains_key(&container_id)) {
-            return Err(CrustyError::CrustyError(String::from("Container ID not found in StorageManager's c_map")));
+        let hf = c_map
+            .get(&container_id)
+            .ok_or(CrustyError::ContainerMissing(container_id))?;
+        // pages are appended in page id order, so a page past the last one is new
+        if page.get_page_id() >= hf.num_pages() {
+            self.check_quota(hf, 1)?;
         }
-        // otherwise we get the specified container and write the page
-        let hf = &c_map[&container_id];
+        // log the page before it is written
+        self.log_page(container_id, hf, &page)?;
         hf.write_page_to_file(page)
     }
 
+    /// Get an iterator over a snapshot of a container taken now. Unlike `get_iterator`,
+    /// inserts, deletes and updates made while iterating do not change what is returned;
+    /// see `HeapFileIterator::new_snapshot` for the exact visibility rules.
+    pub fn get_snapshot_iterator(
+        &self,
+        container_id: ContainerId,
+        tid: TransactionId,
+        _perm: Permissions,
+    ) -> HeapFileIterator {
+        let hf = self.c_map.read().unwrap()[&container_id].clone();
+        let visibility = self.visibility(tid, &hf);
+        HeapFileIterator::new_snapshot(tid, hf).with_visibility(visibility)
+    }
+
     /// Get the number of pages for a container
-    fn get_num_pages(&self, container_id: ContainerId) -> PageId {
+    #[cfg(test)]
+    pub(crate) fn get_num_pages(&self, container_id: ContainerId) -> PageId {
         self.c_map.read().unwrap()[&container_id].num_pages()
     }
 
-
-    /// Test utility function for counting reads and writes served by the heap file.
-    /// Can return 0,0 for invalid container_ids
-    #[allow(dead_code)]
-    pub(crate) fn get_hf_read_write_count(&self, container_id: ContainerId) -> (u16, u16) {
-        let c_map = self.c_map.read().unwrap();
-        if !(c_map.contains_key(&container_id)) {
-            return (0, 0);
-        }
-        let hf = &c_map[&container_id];
-        let read_count = hf.read_count.load(Ordering::Relaxed);
-        let write_count = hf.write_count.load(Ordering::Relaxed);
-        ( read_count, write_count)
+    /// Get a summary of a page of a container, with its slot table and free space. See
+    /// `PageDescription::to_json` to serialize it.
+    pub fn get_page_description(
+        &self,
+        container_id: ContainerId,
+        page_id: PageId,
+    ) -> Result<PageDescription, CrustyError> {
+        Ok(self
+            .get_page(
+                container_id,
+                page_id,
+                TransactionId::new(),
+                Permissions::ReadOnly,
+                false,
+            )?
+            .describe())
     }
 
-    /// For testing
+    /// For testing. The slot table of the page, then a hex dump of its bytes
     pub fn get_page_debug(&self, container_id: ContainerId, page_id: PageId) -> String {
         match self.get_page(
             container_id,
@@ -98,10 +2319,10 @@ impl StorageManager {
             Permissions::ReadOnly,
             false,
         ) {
-            Some(p) => {
-                format!("{:?}", p)
+            Ok(p) => {
+                format!("{}{:?}", p.describe(), p.page())
             }
-            None => String::new(),
+            Err(_) => String::new(),
         }
     }
 
@@ -114,8 +2335,8 @@ impl StorageManager {
             Permissions::ReadOnly,
             false,
         ) {
-            Some(p) => p.to_bytes(),
-            None => Vec::new(),
+            Ok(p) => p.to_bytes(),
+            Err(_) => Vec::new(),
         }
     }
 }
@@ -130,57 +2351,104 @@ impl StorageTrait for StorageManager {
     /// use to populate this instance of the SM. Otherwise create a new one.
     fn new(storage_path: PathBuf) -> Self {
         // check the c_map file for data persisted in shutdown()
-        let mut path = PathBuf::from(storage_path.clone());
-        path = path.join(String::from("c_map"));
-        let mut f = fs::File::open(path);
-        // if the file doesn't exist, return a new storage manager
-        if f.is_err() {
-            println!("File not found");
-            return StorageManager { storage_path, c_map: Arc::new(RwLock::new(HashMap::new())), is_temp: false}
-        }
-        let f = f.unwrap();
-        // read the file into a byte buffer
-        let mut reader = BufReader::new(f);
-
-        // deserialize the reader from serde_json
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer).unwrap();
-        let buffer: Vec<u16> = serde_json::from_slice(&buffer).unwrap();
-
-        // get the length of the c_map
-        let cnt = buffer[0];
-
-        // if there are no containers, return a new storage manager
-        if cnt == 0 {
-            return StorageManager { storage_path, c_map: Arc::new(RwLock::new(HashMap::new())), is_temp: false}
-        }
-        // otherwise, create a new hashmap to hold the container id and heapfile pairs
+        let container_ids = if !storage_path.join("c_map").exists() {
+            debug!(
+                "No containers persisted in {:?}, starting empty",
+                storage_path
+            );
+            Vec::new()
+        } else {
+            // a damaged catalog must not brick the database: the heap files are still there
+            read_catalog(&storage_path).unwrap_or_else(|e| {
+                error!("{}, opening the heap files in {:?}", e, storage_path);
+                find_heap_files(&storage_path).unwrap()
+            })
+        };
+        let configs = read_configs(&storage_path).unwrap_or_else(|e| {
+            error!("{}, opening the containers with the default options", e);
+            HashMap::new()
+        });
+        let buffer_pool = Arc::new(BufferPool::default());
         let mut c_map = HashMap::new();
-        for idx in 1..cnt + 1 {
-            
-            // convert the bytes to a container id
-            let container_id = buffer[idx as usize];
-            // create a path for the heapfile based on the c_id
-            let mut file_path = storage_path.clone();
-            // use push to add the c_id to the path
-            file_path.push(String::from("c") + &container_id.to_string());
-            // create a new heapfile with the path specified
-            let hf = HeapFile::new(file_path.clone(), container_id).unwrap();
-
-            // add the heapfile to the c_map
+        for container_id in container_ids {
+            // open the heapfile of the container with the options it was created with
+            let hf = HeapFile::new(container_path(&storage_path, container_id), container_id)
+                .unwrap()
+                .with_config(configs.get(&container_id).copied().unwrap_or_default())
+                .unwrap()
+                .with_buffer_pool(buffer_pool.clone());
             c_map.insert(container_id, Arc::new(hf));
         }
-        StorageManager { storage_path, c_map: Arc::new(RwLock::new(c_map)), is_temp: false }
-        // move through the buff reading every 2 bytes into a container_id. The first
-        // two bytes are the length, and the filepath for a given container is given
-        // by joining the storage path with 'c' + container_id
+        // read the dependencies between the containers, if any were persisted
+        let dependencies = read_dependencies(&storage_path).unwrap_or_else(|e| {
+            error!("{}, dropping the dependencies between containers", e);
+            HashMap::new()
+        });
+        let bloom_filters = BloomFilters::load(&storage_path.join("bloom_filters")).unwrap();
+        let databases = open_databases(&storage_path, false).unwrap();
+        let mut change_logs = HashMap::new();
+        for container_id in cdc::find_logs(&storage_path).unwrap() {
+            match ChangeLog::open(&storage_path, container_id) {
+                Ok(log) => {
+                    change_logs.insert(container_id, Arc::new(log));
+                }
+                Err(e) => error!("{}, not capturing the changes of {}", e, container_id),
+            }
+        }
+        StorageManager {
+            storage_path,
+            c_map: Arc::new(RwLock::new(c_map)),
+            is_temp: false,
+            dependencies: Arc::new(RwLock::new(dependencies)),
+            read_only: false,
+            wal: None,
+            recovery_lsn: RwLock::new(None),
+            logged_pages: Mutex::new(HashSet::new()),
+            standby: AtomicBool::new(false),
+            txns: None,
+            bloom_filters,
+            temp_containers: TempContainers::default(),
+            metrics: Arc::default(),
+            modifications: ModificationCounters::default(),
+            log_level: AtomicUsize::new(LevelFilter::Trace as usize),
+            buffer_pool,
+            databases: RwLock::new(databases),
+            temp_in_memory: false,
+            read_ahead: DEFAULT_READ_AHEAD,
+            change_logs: RwLock::new(change_logs),
+            pending_changes: Mutex::new(HashMap::new()),
+            prepared: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Create a new storage manager for testing. There is no startup/shutdown logic here: it
     /// should simply create a fresh SM and set is_temp to true
     fn new_test_sm() -> Self {
         let storage_path = gen_random_test_sm_dir();
-        StorageManager { storage_path, c_map: Arc::new(RwLock::new(HashMap::new())), is_temp: true }
+        StorageManager {
+            storage_path,
+            c_map: Arc::new(RwLock::new(HashMap::new())),
+            is_temp: true,
+            dependencies: Arc::new(RwLock::new(HashMap::new())),
+            read_only: false,
+            wal: None,
+            recovery_lsn: RwLock::new(None),
+            logged_pages: Mutex::new(HashSet::new()),
+            standby: AtomicBool::new(false),
+            txns: None,
+            bloom_filters: BloomFilters::default(),
+            temp_containers: TempContainers::default(),
+            metrics: Arc::default(),
+            modifications: ModificationCounters::default(),
+            log_level: AtomicUsize::new(LevelFilter::Trace as usize),
+            buffer_pool: Arc::new(BufferPool::default()),
+            databases: RwLock::new(HashMap::new()),
+            temp_in_memory: false,
+            read_ahead: DEFAULT_READ_AHEAD,
+            change_logs: RwLock::new(HashMap::new()),
+            pending_changes: Mutex::new(HashMap::new()),
+            prepared: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Insert some bytes into a container for a particular value (e.g. record).
@@ -188,67 +2456,25 @@ impl StorageTrait for StorageManager {
     /// Returns the value id associated with the stored value.
     /// Function will need to find the first page that can hold the value.
     /// A new page may need to be created if no space on existing pages can be found.
+    /// Errors with `CrustyError::QuotaExceeded` if the container cannot have one more page.
     fn insert_value(
         &self,
         container_id: ContainerId,
         value: Vec<u8>,
         tid: TransactionId,
-    ) -> ValueId {
-        if value.len() > PAGE_SIZE {
-            panic!("Cannot handle inserting a value larger than the page size");
-        }
-        // if the container has no pages, make one and insert the value
-        if self.get_num_pages(container_id) == 0 {
-            let mut new_page = Page::new(0);
-            new_page.add_value(&value);
-            self.write_page(container_id, new_page, tid).unwrap();
-            return ValueId {
-                container_id,
-                segment_id: None,
-                page_id: Some(0),
-                slot_id: Some(0),
-            }
-        }
-
-        // starting with the smallest p_id, iterate through all pages until you
-        // find a page that can hold the value
-        // if no page can hold the value, create a new page and insert the value
-
-        let mut p_id = 0;
-        loop {
-            let mut pg = self.get_page(container_id, p_id, tid, Permissions::ReadWrite, false).unwrap();
-            match pg.add_value(&value) {
-                Some(slot_id) => {
-                    // if the addition is successful, write the page to the hf
-                    // and return the ValueID
-                    self.write_page(container_id, pg, tid).unwrap();
-                    return ValueId {
-                        container_id,
-                        segment_id: None,
-                        slot_id: Some(slot_id),
-                        page_id: Some(p_id),
-                    }
-                }
-                None => {
-                    // increment p_id to try next page
-                    p_id += 1;
-                    // if we are at the end of the file, append and return v_id
-                    if p_id >= self.c_map.read().unwrap()[&container_id].num_pages() {
-                        // create a new page with the page_id and append it to the file
-                        let mut new_page = Page::new(p_id);
-                        let slot_id = new_page.add_value(&value).unwrap();
-                        self.write_page(container_id, new_page, tid).unwrap();
-                        return ValueId {
-                            container_id,
-                            segment_id: None,
-                            page_id: Some(p_id),
-                            slot_id: Some(slot_id),
-                        }
-                    }
-
-                }
-            }
-        }
+    ) -> Result<ValueId, CrustyError> {
+        let id = self.add_value(container_id, &value, tid)?;
+        self.modifications.add(container_id, 1);
+        let after = Some((id, value));
+        self.capture(
+            container_id,
+            tid,
+            Change {
+                before: None,
+                after,
+            },
+        )?;
+        Ok(id)
     }
 
     /// Insert some bytes into a container for vector of values (e.g. record).
@@ -259,48 +2485,286 @@ impl StorageTrait for StorageManager {
         container_id: ContainerId,
         values: Vec<Vec<u8>>,
         tid: TransactionId,
-    ) -> Vec<ValueId> {
-        let mut ret = Vec::new();
-        for v in values {
-            ret.push(self.insert_value(container_id, v, tid));
+    ) -> Result<Vec<ValueId>, CrustyError> {
+        values
+            .into_iter()
+            .map(|v| self.insert_value(container_id, v, tid))
+            .collect()
+    }
+
+    /// Load values into a container, packing them into new pages after the existing ones.
+    /// The pages are filled in order up to the fill factor of the container, without
+    /// searching the existing pages for space, and are logged as one WAL entry before any is
+    /// written, so the load is replayed as a whole. The packed pages are held in memory until the end of the load.
+    fn bulk_load(
+        &self,
+        container_id: ContainerId,
+        values: impl Iterator<Item = Vec<u8>>,
+        tid: TransactionId,
+    ) -> Result<Vec<ValueId>, CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        let fill_factor = hf.config().fill_factor;
+        let capture = self.change_logs.read().unwrap().contains_key(&container_id);
+        let mut p_id = hf.num_pages();
+        let mut pages = Vec::new();
+        let mut page = Page::new(p_id);
+        let mut ids = Vec::new();
+        let mut changes = Vec::new();
+        for value in values {
+            let stored = self.new_record(&hf, &value, tid);
+            let slot_id = match page.add_value_with_fill_factor(&stored, fill_factor) {
+                Some(slot_id) => slot_id,
+                None => {
+                    p_id += 1;
+                    let full = std::mem::replace(&mut page, Page::new(p_id));
+                    pages.push(full);
+                    page.add_value(&stored).ok_or(CrustyError::PageFull {
+                        container_id,
+                        page_id: p_id,
+                    })?
+                }
+            };
+            self.bloom_filters.insert(container_id, &value);
+            let id = hf.value_id(p_id, slot_id);
+            ids.push(id);
+            if capture {
+                let after = Some((id, value));
+                changes.push(Change {
+                    before: None,
+                    after,
+                });
+            }
+        }
+        if ids.is_empty() {
+            return Ok(ids);
+        }
+        pages.push(page);
+        self.check_quota(&hf, pages.len())?;
+        if let Some(txns) = &self.txns {
+            txns.record_write(tid, container_id)?;
+        }
+        sm_log!(
+            self,
+            Level::Debug,
+            "Bulk loading {} values into {} pages of container {}",
+            ids.len(),
+            pages.len(),
+            container_id
+        );
+        let _c_map = self.c_map.write().unwrap();
+        // log the pages before they are written
+        self.log(WalRecord::Load {
+            container_id,
+            pages: pages.iter().map(Page::to_bytes).collect(),
+        })?;
+        for page in pages {
+            hf.write_page_to_file(page)?;
+        }
+        self.modifications.add(container_id, ids.len());
+        for change in changes {
+            self.capture(container_id, tid, change)?;
+        }
+        Ok(ids)
+    }
+
+    /// Delete the data for a value. If the valueID is not found it returns Ok() still.
+    fn delete_value(&self, id: ValueId, tid: TransactionId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(id.container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        // get the page from the value id
+        let (mut page, slot_id) = self.value_page(&hf, &id)?;
+        let before = self.captured_value(&page, &hf, slot_id);
+        let deleted = self.delete_in_page(&mut page, &hf, slot_id, tid)?;
+        // write the page back to the heapfile
+        self.write_page(id.container_id, page, tid)?;
+        if let Some(value) = deleted {
+            self.bloom_filters.remove(id.container_id, &value);
+            self.modifications.add(id.container_id, 1);
+        }
+        if let Some(value) = before {
+            let before = Some((id, value));
+            self.capture(
+                id.container_id,
+                tid,
+                Change {
+                    before,
+                    after: None,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Delete the data for many values. The ids are grouped by page, so each page is read
+    /// and written once, with all its deletions, in the order of the containers and pages.
+    /// Error if an id has no page or slot, or its page does not exist; the values of the
+    /// pages before it stay deleted. The values moved by `compact` are deleted last, from
+    /// the pages their stubs forward to.
+    fn delete_values(&self, ids: Vec<ValueId>, tid: TransactionId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let mut forwarded = Vec::new();
+        // the slots to delete in each page of each container
+        let mut containers: BTreeMap<ContainerId, BTreeMap<PageId, Vec<SlotId>>> = BTreeMap::new();
+        for id in &ids {
+            match (id.page_id, id.slot_id) {
+                (Some(page_id), Some(slot_id)) => containers
+                    .entry(id.container_id)
+                    .or_default()
+                    .entry(page_id)
+                    .or_default()
+                    .push(slot_id),
+                _ => return Err(CrustyError::invalid_slot(id)),
+            }
+        }
+        for (container_id, pages) in containers {
+            let hf = self.heap_file(container_id)?;
+            let _modify = hf.modify_lock.lock().unwrap();
+            if let Some((&page_id, slots)) = pages.range(hf.num_pages()..).next() {
+                return Err(CrustyError::invalid_slot(&hf.value_id(page_id, slots[0])));
+            }
+            for (page_id, slots) in pages {
+                let mut page = hf.read_page_from_file(page_id)?;
+                let mut deleted = Vec::new();
+                let mut changes = Vec::new();
+                for slot_id in slots {
+                    if let Some((page_id, slot_id)) = page.forwarded_to(slot_id) {
+                        forwarded.push(hf.value_id(page_id, slot_id));
+                        continue;
+                    }
+                    if let Some(value) = self.captured_value(&page, &hf, slot_id) {
+                        let id = hf.value_id(page_id, slot_id);
+                        let before = Some((id, value));
+                        changes.push(Change {
+                            before,
+                            after: None,
+                        });
+                    }
+                    deleted.extend(self.delete_in_page(&mut page, &hf, slot_id, tid)?);
+                }
+                self.write_page(container_id, page, tid)?;
+                self.modifications.add(container_id, deleted.len());
+                for value in deleted {
+                    self.bloom_filters.remove(container_id, &value);
+                }
+                for change in changes {
+                    self.capture(container_id, tid, change)?;
+                }
+            }
+        }
+        // a stub forwards to a value, never to another stub
+        if !forwarded.is_empty() {
+            self.delete_values(forwarded, tid)?;
         }
-        ret
-    }
-
-    /// Delete the data for a value. If the valueID is not found it returns Ok() still.
-    fn delete_value(&self, id: ValueId, tid: TransactionId) -> Result<(), CrustyError> {
-        // get the page from the value id
-        let mut page = self.get_page(id.container_id, id.page_id.unwrap(), tid, Permissions::ReadWrite, false).unwrap();
-        // delete the value from the page
-        page.delete_value(id.slot_id.unwrap());
-        // write the page back to the heapfile
-        self.write_page(id.container_id, page, tid).unwrap();
         Ok(())
     }
 
     /// Updates a value. Returns valueID on update (which may have changed). Error on failure
     /// Any process that needs to determine if a value changed will need to compare the return valueId against
     /// the sent value.
+    ///
+    /// The page of the value is read and written once: without MVCC a value of the same
+    /// length is overwritten in its slot, and otherwise the old value is deleted and the new
+    /// one added to the same page. Only if it does not fit there is it inserted like
+    /// `insert_value`, after the page is written.
     fn update_value(
         &self,
         value: Vec<u8>,
         id: ValueId,
-        _tid: TransactionId,
+        tid: TransactionId,
     ) -> Result<ValueId, CrustyError> {
-        // delete the old value
-        match self.delete_value(id, _tid) {
-            Ok(_) => (),
-            Err(e) => return Err(e),
-        } 
-        // add the new value
-        Ok(self.insert_value(id.container_id, value, _tid))
+        self.check_writable()?;
+        let hf = self.heap_file(id.container_id)?;
+        // held until the value is stored again, so a backup has the update whole or not at all
+        let _modify = hf.modify_lock.lock().unwrap();
+        let (mut page, slot_id) = self.value_page(&hf, &id)?;
+        let before = self
+            .captured_value(&page, &hf, slot_id)
+            .map(|old| (id, old));
+        let (deleted, new_slot) = match page.get_value(slot_id) {
+            Some(old) if !self.has_headers(&hf) && old.len() == value.len() => {
+                page.overwrite_value(slot_id, &value);
+                (Some(old), Some(slot_id))
+            }
+            _ => {
+                let deleted = self.delete_in_page(&mut page, &hf, slot_id, tid)?;
+                let stored = self.stored_value(&hf, &value, tid)?;
+                (deleted, page.add_value(&stored))
+            }
+        };
+        let page_id = page.get_page_id();
+        self.write_page(id.container_id, page, tid)?;
+        self.modifications.add(id.container_id, 1);
+        if let Some(old) = deleted {
+            self.bloom_filters.remove(id.container_id, &old);
+        }
+        let new_id = match new_slot {
+            Some(slot_id) => {
+                self.bloom_filters.insert(id.container_id, &value);
+                hf.value_id(page_id, slot_id)
+            }
+            None => {
+                let stored = self.stored_value(&hf, &value, tid)?;
+                self.place_value(&hf, &stored, &value, tid)?
+            }
+        };
+        if before.is_some() {
+            let after = Some((new_id, value));
+            self.capture(id.container_id, tid, Change { before, after })?;
+        }
+        Ok(new_id)
+    }
+
+    /// Replaces the bytes of a value in its slot, keeping its record header.
+    fn overwrite_value(
+        &self,
+        value: Vec<u8>,
+        id: ValueId,
+        tid: TransactionId,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(id.container_id)?;
+        let _modify = hf.modify_lock.lock().unwrap();
+        let (mut page, slot_id) = self.value_page(&hf, &id)?;
+        let old = page
+            .get_value(slot_id)
+            .ok_or_else(|| CrustyError::invalid_slot(&id))?;
+        let (old_value, new_bytes) = match self.has_headers(&hf) {
+            true => {
+                let (header, old_value) = RecordHeader::unwrap(&old);
+                (old_value.to_vec(), header.wrap(&value))
+            }
+            false => (old, value.clone()),
+        };
+        if page.overwrite_value(slot_id, &new_bytes).is_none() {
+            return Err(CrustyError::CrustyError(String::from(
+                "Overwrite must keep the length of the value",
+            )));
+        }
+        self.write_page(id.container_id, page, tid)?;
+        self.modifications.add(id.container_id, 1);
+        self.bloom_filters.remove(id.container_id, &old_value);
+        self.bloom_filters.insert(id.container_id, &value);
+        if self
+            .change_logs
+            .read()
+            .unwrap()
+            .contains_key(&id.container_id)
+        {
+            let (before, after) = (Some((id, old_value)), Some((id, value)));
+            self.capture(id.container_id, tid, Change { before, after })?;
+        }
+        Ok(())
     }
 
     /// Create a new container to be stored.
     /// fn create_container(&self, name: String) -> ContainerId;
-    /// Creates a new container object.
+    /// Creates a new container object with the default `ContainerConfig`, see
+    /// `create_container_with_config`.
     /// For this milestone you will not need to utilize
-    /// the container_config, name, container_type, or dependencies
+    /// the name or container_type
     ///
     ///
     /// # Arguments
@@ -311,18 +2775,9 @@ impl StorageTrait for StorageManager {
         container_id: ContainerId,
         _name: Option<String>,
         _container_type: common::ids::StateType,
-        _dependencies: Option<Vec<ContainerId>>,
+        dependencies: Option<Vec<ContainerId>>,
     ) -> Result<(), CrustyError> {
-        // create a new path for the heapfile based on the storage path using
-        // Path::new and .join()
-        let mut path = PathBuf::from(self.storage_path.clone());
-        // creating a new path for the container (heapfile)
-        path = path.join(String::from("c") + &container_id.to_string());
-        // create a new heapfile with the path specified
-        let hf = HeapFile::new(path, container_id).unwrap();
-
-        self.c_map.write().unwrap().insert(container_id, Arc::new(hf));
-        Ok(())
+        self.create_container_with_config(container_id, ContainerConfig::default(), dependencies)
     }
 
     /// A wrapper function to call create container
@@ -330,19 +2785,113 @@ impl StorageTrait for StorageManager {
         self.create_container(container_id, None, common::ids::StateType::BaseTable, None)
     }
 
+    fn create_temp_container(&self, tid: TransactionId) -> Result<ContainerId, CrustyError> {
+        let container_id = self.temp_containers.allocate(tid)?;
+        let config = ContainerConfig {
+            in_memory: self.temp_in_memory,
+            ..Default::default()
+        };
+        if let Err(e) = self.create_container_with_config(container_id, config, None) {
+            self.temp_containers.release(container_id);
+            return Err(e);
+        }
+        Ok(container_id)
+    }
+
     /// Remove the container and all stored values in the container.
     /// If the container is persisted remove the underlying files
     fn remove_container(&self, container_id: ContainerId) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        // lock c_map before dependencies, in the same order as the other methods
+        let mut c_map = self.c_map.write().unwrap();
+        // refuse to remove a container others depend on
+        let mut dependencies = self.dependencies.write().unwrap();
+        let dependents: Vec<String> = dependencies
+            .iter()
+            .filter(|(c_id, deps)| **c_id != container_id && deps.contains(&container_id))
+            .map(|(c_id, _)| c_id.to_string())
+            .collect();
+        if !dependents.is_empty() {
+            return Err(CrustyError::ExecutionError(format!(
+                "Container {} is a dependency of containers {}",
+                container_id,
+                dependents.join(", ")
+            )));
+        }
         // get the path to the container
         let mut path = PathBuf::from(self.storage_path.clone());
         path = path.join(String::from("c") + &container_id.to_string());
-        // delete the file
-        fs::remove_file(path)?;
+        let hf = c_map
+            .get(&container_id)
+            .ok_or(CrustyError::ContainerMissing(container_id))?;
+        // delete the file, with the ones of its further segments
+        if !hf.is_in_memory() {
+            remove_heap_file(&path)?;
+        }
+        self.log(WalRecord::RemoveContainer { container_id })?;
         // update the c_map
-        self.c_map.write().unwrap().remove(&container_id);
+        c_map.remove(&container_id);
+        dependencies.remove(&container_id);
+        self.buffer_pool.discard_container(container_id);
+        self.bloom_filters.remove_container(container_id);
+        self.modifications.reset(container_id);
+        self.temp_containers.release(container_id);
+        if let Some(log) = self.change_logs.write().unwrap().remove(&container_id) {
+            log.remove()?;
+        }
         Ok(())
     }
 
+    /// Creates the storage path of the database and persists its empty metadata, so the
+    /// database is opened by `new` and `open_read_only` even if it is not shut down.
+    fn create_database(&self, name: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        validate_database_name(name)?;
+        let mut databases = self.databases.write().unwrap();
+        if databases.contains_key(name) {
+            return Err(CrustyError::CrustyError(format!(
+                "Database {} already exists",
+                name
+            )));
+        }
+        let path = self.storage_path.join(DATABASES_DIR).join(name);
+        fs::create_dir_all(&path).map_err(|e| CrustyError::io_with_path(&path, e))?;
+        write_metadata(&path, &HashMap::new(), &HashMap::new())?;
+        sm_log!(self, Level::Info, "Created database {}", name);
+        databases.insert(name.to_string(), Arc::new(StorageManager::new(path)));
+        Ok(())
+    }
+
+    fn drop_database(&self, name: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let db = self
+            .databases
+            .write()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| CrustyError::CrustyError(format!("Database {} does not exist", name)))?;
+        db.reset()?;
+        fs::remove_dir_all(&db.storage_path)
+            .map_err(|e| CrustyError::io_with_path(&db.storage_path, e))?;
+        sm_log!(self, Level::Info, "Dropped database {}", name);
+        Ok(())
+    }
+
+    fn use_database(&self, name: &str) -> Result<Arc<Self>, CrustyError> {
+        self.databases
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CrustyError::CrustyError(format!("Database {} does not exist", name)))
+    }
+
+    fn list_databases(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.databases.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
     /// Get an iterator that returns all valid records
     fn get_iterator(
         &self,
@@ -351,8 +2900,25 @@ impl StorageTrait for StorageManager {
         _perm: Permissions,
     ) -> Self::ValIterator {
         //create an iterator for the specified container
-        let hf = self.c_map.write().unwrap()[&container_id].clone();
+        let hf = self.c_map.read().unwrap()[&container_id].clone();
+        let visibility = self.visibility(tid, &hf);
         HeapFileIterator::new(tid, hf)
+            .with_read_ahead(self.read_ahead)
+            .with_visibility(visibility)
+    }
+
+    /// Get an iterator over a Bernoulli sample of the pages of a container
+    fn sample_iterator(
+        &self,
+        container_id: ContainerId,
+        fraction: f64,
+        seed: u64,
+        tid: TransactionId,
+    ) -> Self::ValIterator {
+        let hf = self.c_map.read().unwrap()[&container_id].clone();
+        let visibility = self.visibility(tid, &hf);
+        HeapFileIterator::new_sample(tid, hf, PageSampler::new(fraction, seed))
+            .with_visibility(visibility)
     }
 
     /// Get the data for a particular ValueId. Error if does not exists
@@ -360,20 +2926,111 @@ impl StorageTrait for StorageManager {
         &self,
         id: ValueId,
         tid: TransactionId,
-        perm: Permissions,
+        _perm: Permissions,
     ) -> Result<Vec<u8>, CrustyError> {
         // use the value id to get the right container, page, and slot and return
         // either the matching data or an error if the data can't be found
-        let page = self.get_page(id.container_id, id.page_id.unwrap(), tid, perm, false).unwrap();
-        match page.get_value(id.slot_id.unwrap()) {
-            Some(val) => Ok(val),
-            None => Err(CrustyError::CrustyError(String::from("Unable to get value"))),
+        let hf = self.heap_file(id.container_id)?;
+        let (page_id, slot_id) = match (id.page_id, id.slot_id) {
+            (Some(page_id), Some(slot_id)) if page_id < hf.num_pages() => (page_id, slot_id),
+            _ => return Err(CrustyError::invalid_slot(&id)),
+        };
+        // read only the slot, not the whole page, unless it holds a forwarding stub
+        let val = match hf.read_value(page_id, slot_id)? {
+            None => match hf.read_page_from_file(page_id)?.forwarded_to(slot_id) {
+                Some((page_id, slot_id)) => hf.read_value(page_id, slot_id)?,
+                None => None,
+            },
+            val => val,
+        };
+        // with MVCC, only the versions in the snapshot of tid can be read, and expired
+        // values cannot be read
+        let val = match (self.visibility(tid, &hf), val) {
+            (Some(visibility), Some(bytes)) => visibility.read(bytes),
+            (_, val) => val,
+        };
+        val.ok_or_else(|| CrustyError::invalid_slot(&id))
+    }
+
+    /// Create a bloom filter over a key column of a container and add the keys of its
+    /// values, including the versions not purged by vacuum yet
+    fn create_bloom_filter(
+        &self,
+        container_id: ContainerId,
+        key_column: usize,
+        expected_keys: usize,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let hf = self.heap_file(container_id)?;
+        // keep values from being inserted or deleted while the filter is filled
+        let _modify = hf.modify_lock.lock().unwrap();
+        self.bloom_filters
+            .create(container_id, key_column, expected_keys);
+        let headers = self.has_headers(&hf);
+        let mut iter = HeapFileIterator::new(TransactionId::new(), hf.clone());
+        for (bytes, _) in iter.by_ref() {
+            match headers {
+                true => self
+                    .bloom_filters
+                    .insert(container_id, RecordHeader::unwrap(&bytes).1),
+                false => self.bloom_filters.insert(container_id, &bytes),
+            }
         }
+        match iter.error() {
+            Some(e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn bloom_filter_column(&self, container_id: ContainerId) -> Option<usize> {
+        self.bloom_filters.key_column(container_id)
+    }
+
+    fn may_contain(&self, container_id: ContainerId, key: &Field) -> bool {
+        self.bloom_filters.may_contain(container_id, key)
     }
 
     /// Notify the storage manager that the transaction is finished so that any held resources can be released.
+    /// With MVCC this commits the transaction, otherwise there is nothing to release.
     fn transaction_finished(&self, tid: TransactionId) {
-        panic!("TODO milestone tm");
+        if let Err(e) = self.transaction_committed(tid) {
+            sm_log!(
+                self,
+                Level::Warn,
+                "Cannot commit transaction {:?}: {}",
+                tid,
+                e
+            );
+        }
+    }
+
+    /// With MVCC, the transaction is committed with `commit_transaction`.
+    fn transaction_committed(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        self.remove_temp_containers(self.temp_containers.take_transaction(tid));
+        if let Err(e) = self.sync_change_logs() {
+            sm_log!(self, Level::Warn, "Cannot sync the change logs: {}", e);
+        }
+        if self.txns.is_some() {
+            self.commit_transaction(tid)?;
+        }
+        Ok(())
+    }
+
+    /// With MVCC, the transaction is aborted with `abort_transaction`. Without it, its
+    /// changes are already in place and stay.
+    fn transaction_aborted(&self, tid: TransactionId) {
+        self.remove_temp_containers(self.temp_containers.take_transaction(tid));
+        if self.txns.is_some() {
+            if let Err(e) = self.abort_transaction(tid) {
+                sm_log!(
+                    self,
+                    Level::Warn,
+                    "Cannot abort transaction {:?}: {}",
+                    tid,
+                    e
+                );
+            }
+        }
     }
 
     /// Testing utility to reset all state associated the storage manager. Deletes all data in
@@ -382,16 +3039,57 @@ impl StorageTrait for StorageManager {
     ///
     /// Clear any data structures in the SM you add
     fn reset(&self) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        for (_, db) in self.databases.write().unwrap().drain() {
+            db.reset()?;
+        }
         fs::remove_dir_all(self.storage_path.clone())?;
         fs::create_dir_all(self.storage_path.clone()).unwrap();
         // delete cmap
         self.c_map.write().unwrap().clear();
+        self.buffer_pool.clear();
+        self.dependencies.write().unwrap().clear();
+        self.bloom_filters.clear();
+        self.temp_containers.take_all();
+        self.change_logs.write().unwrap().clear();
+        self.pending_changes.lock().unwrap().clear();
+        self.prepared.lock().unwrap().clear();
+        self.metrics.reset();
+        self.modifications.clear();
+        *self.recovery_lsn.write().unwrap() = None;
+        if let Some(wal) = &self.wal {
+            wal.reset()?;
+            // archived entries are about the removed containers
+            write_wal_lsn(&self.storage_path, wal.last_lsn())?;
+        }
         Ok(())
     }
 
     /// If there is a buffer pool or cache it should be cleared/reset.
     /// Otherwise do nothing.
     fn clear_cache(&self) {
+        self.buffer_pool.clear();
+    }
+
+    fn metrics(&self) -> &Arc<MetricsRegistry> {
+        &self.metrics
+    }
+
+    fn modifications(&self) -> &ModificationCounters {
+        &self.modifications
+    }
+
+    /// Returns the page counters of the heap files of the containers, and the metrics of
+    /// the operators.
+    fn get_metrics(&self) -> Metrics {
+        let files = self
+            .c_map
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(container_id, hf)| (*container_id, hf.metrics.counters()))
+            .collect();
+        self.metrics.snapshot(files)
     }
 
     /// Shutdown the storage manager. Should be safe to call multiple times. You can assume this
@@ -400,87 +3098,133 @@ impl StorageTrait for StorageManager {
     /// can be read by StorageManager::new.
     /// HINT: Heapfile won't be serializable/deserializable. You'll want to serialize information
     /// that can be used to create a HeapFile object pointing to the same data. You don't need to
-    /// worry about recreating read_count or write_count.
+    /// worry about recreating the metrics.
     fn shutdown(&self) {
-        // serialize c_map to disk
-        let mut path = PathBuf::from(self.storage_path.clone());
-        path = path.join(String::from("c_map"));
-        let mut f = fs::File::create(path).unwrap();
+        // a read-only StorageManager has nothing to persist
+        if self.read_only {
+            return;
+        }
+        self.remove_temp_containers(self.temp_containers.take_all());
+        // serialize the c_map and the dependencies between the containers to disk
         let c_map = self.c_map.read().unwrap();
-        let len: u16 = c_map.len() as u16;
-
-        // create a vector to hold the length of the c_map and all c_id's
-        let mut buffer = Vec::new();
-        // push the length of the c_map to the buffer
-        buffer.push(len);
-        // iterate through the c_map and push each c_id to the buffer
-        for (c_id, _) in c_map.iter() {
-            buffer.push(*c_id);
+        write_metadata(
+            &self.storage_path,
+            &c_map,
+            &self.dependencies.read().unwrap(),
+        )
+        .unwrap();
+        self.bloom_filters
+            .save(&self.storage_path.join("bloom_filters"))
+            .unwrap();
+        if let Some(wal) = &self.wal {
+            wal.flush().unwrap();
+        }
+        self.checkpoint(&c_map).unwrap();
+        self.sync_change_logs().unwrap();
+        for db in self.databases.read().unwrap().values() {
+            db.shutdown();
         }
-        // use serde to serialize the buffer to json
-        let serialized = serde_json::to_string(&buffer).unwrap();
-        println!("serialized = {}", serialized);
-        // write this to the specified file
-        f.write_all(serialized.as_bytes()).unwrap();
     }
 
     fn import_csv(
         &self,
         table: &Table,
         path: String,
-        _tid: TransactionId,
+        tid: TransactionId,
         container_id: ContainerId,
-    ) -> Result<(), CrustyError> {
-        // Err(CrustyError::CrustyError(String::from("TODO")))
-        // Convert path into an absolute path.
-        let path = fs::canonicalize(path)?;
-        debug!("server::csv_utils trying to open file, path: {:?}", path);
-        let file = fs::File::open(path)?;
-        // Create csv reader.
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(file);
-
-        // Iterate through csv records.
-        let mut inserted_records = 0;
-        for result in rdr.records() {
-            #[allow(clippy::single_match)]
-            match result {
-                Ok(rec) => {
-                    // Build tuple and infer types from schema.
-                    let mut tuple = Tuple::new(Vec::new());
-                    for (field, attr) in rec.iter().zip(table.schema.attributes()) {
-                        // TODO: Type mismatch between attributes and record data>
-                        match &attr.dtype() {
-                            DataType::Int => {
-                                let value: i32 = field.parse::<i32>().unwrap();
-                                tuple.field_vals.push(Field::IntField(value));
-                            }
-                            DataType::String => {
-                                let value: String = field.to_string().clone();
-                                tuple.field_vals.push(Field::StringField(value));
-                            }
-                        }
-                    }
-                    //TODO: How should individual row insertion errors be handled?
-                    debug!(
-                        "server::csv_utils about to insert tuple into container_id: {:?}",
-                        &container_id
-                    );
-                    self.insert_value(container_id, tuple.to_bytes(), _tid);
-                    inserted_records += 1;
-                }
-                _ => {
-                    // FIXME: get error from csv reader
-                    error!("Could not read row from CSV");
-                    return Err(CrustyError::IOError(
-                        "Could not read row from CSV".to_string(),
-                    ));
-                }
-            }
+        options: &CsvImportOptions,
+    ) -> Result<CsvImportReport, CrustyError> {
+        self.check_writable()?;
+        sm_log!(self, Level::Debug, "Importing csv file {}", path);
+        let report = csv_import::import_csv(&table.schema, Path::new(&path), options, |tuple| {
+            sm_log!(
+                self,
+                Level::Trace,
+                "Importing a record into container {}",
+                container_id
+            );
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        sm_log!(
+            self,
+            Level::Info,
+            "Num records imported: {:?}, skipped: {:?}",
+            report.imported,
+            report.skipped
+        );
+        Ok(report)
+    }
+
+    /// Streams the visible records of a container through the heap file iterator.
+    fn export_csv(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        options: &CsvExportOptions,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        self.heap_file(container_id)?;
+        sm_log!(
+            self,
+            Level::Debug,
+            "Exporting container {} to {}",
+            container_id,
+            path
+        );
+        let mut iter = self.get_iterator(container_id, tid, Permissions::ReadOnly);
+        let values = iter.by_ref().map(|(bytes, _)| bytes);
+        let exported = csv_export::export_csv(values, schema, Path::new(&path), options)?;
+        if let Some(e) = iter.error() {
+            return Err(e.clone());
         }
-        info!("Num records imported: {:?}", inserted_records);
-        Ok(())
+        sm_log!(self, Level::Info, "Num records exported: {:?}", exported);
+        Ok(exported)
+    }
+
+    fn import_json(
+        &self,
+        table: &Table,
+        path: String,
+        tid: TransactionId,
+        container_id: ContainerId,
+        on_error: RowErrorPolicy,
+    ) -> Result<usize, CrustyError> {
+        self.check_writable()?;
+        sm_log!(self, Level::Debug, "Importing json file {}", path);
+        let imported = ndjson::import_json(&table.schema, Path::new(&path), on_error, |tuple| {
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        sm_log!(self, Level::Info, "Num records imported: {:?}", imported);
+        Ok(imported)
+    }
+
+    /// Streams the visible records of a container through the heap file iterator.
+    fn export_json(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        self.heap_file(container_id)?;
+        sm_log!(
+            self,
+            Level::Debug,
+            "Exporting container {} to {}",
+            container_id,
+            path
+        );
+        let mut iter = self.get_iterator(container_id, tid, Permissions::ReadOnly);
+        let values = iter.by_ref().map(|(bytes, _)| bytes);
+        let exported = ndjson::export_json(values, schema, Path::new(&path))?;
+        if let Some(e) = iter.error() {
+            return Err(e.clone());
+        }
+        sm_log!(self, Level::Info, "Num records exported: {:?}", exported);
+        Ok(exported)
     }
 }
 
@@ -489,10 +3233,15 @@ impl Drop for StorageManager {
     // if temp SM this clears the storage path entirely when it leaves scope; used for testing
     fn drop(&mut self) {
         if self.is_temp {
-            debug!("Removing storage path on drop {:?}", self.storage_path);
+            sm_log!(
+                self,
+                Level::Debug,
+                "Removing storage path on drop {:?}",
+                self.storage_path
+            );
             let remove_all = fs::remove_dir_all(self.storage_path.clone());
             if let Err(e) = remove_all {
-                println!("Error on removing temp dir {}", e);
+                sm_log!(self, Level::Warn, "Error on removing temp dir {}", e);
             }
         }
     }
@@ -505,8 +3254,9 @@ mod test {
     use crate::storage_manager::StorageManager;
     use common::storage_trait::StorageTrait;
     use common::testutil::*;
+    use common::PAGE_SIZE;
     #[test]
-    fn hs_sm_basic_read_write(){
+    fn hs_sm_basic_read_write() {
         init();
         let sm = StorageManager::new_test_sm();
         let cid = 1;
@@ -518,17 +3268,19 @@ mod test {
 
         let mut page = Page::new(page_id);
         page.add_value(&bytes);
-        
+
         // write a page with the storage manager into the only container
         sm.write_page(cid, page, tid);
-        
+
         // check that the page we get from the heap file matches the original page
-        let page2 = sm.get_page(cid, 0, tid, Permissions::ReadOnly, false)
+        let page2 = sm
+            .get_page(cid, 0, tid, Permissions::ReadOnly, false)
             .expect("Unable to get page from heapfile");
         assert_eq!(bytes, page2.get_value(0).unwrap());
     }
     #[test]
-    fn hs_sm_a_insert() { // currently overwriting page data instead of adding to it
+    fn hs_sm_a_insert() {
+        // currently overwriting page data instead of adding to it
         init();
         let sm = StorageManager::new_test_sm();
         let cid = 1;
@@ -537,7 +3289,7 @@ mod test {
         let bytes = get_random_byte_vec(40);
         let tid = TransactionId::new();
 
-        let val1 = sm.insert_value(cid, bytes.clone(), tid);
+        let val1 = sm.insert_value(cid, bytes.clone(), tid).unwrap();
         assert_eq!(1, sm.get_num_pages(cid));
         assert_eq!(0, val1.page_id.unwrap());
         assert_eq!(0, val1.slot_id.unwrap());
@@ -546,7 +3298,7 @@ mod test {
             .get_page(cid, 0, tid, Permissions::ReadOnly, false)
             .unwrap();
 
-        let val2 = sm.insert_value(cid, [1].to_vec(), tid);
+        let val2 = sm.insert_value(cid, [1].to_vec(), tid).unwrap();
         assert_eq!(1, sm.get_num_pages(cid));
         assert_eq!(0, val2.page_id.unwrap());
         assert_eq!(1, val2.slot_id.unwrap());
@@ -559,6 +3311,606 @@ mod test {
         assert_ne!(p1.to_bytes()[..], p2.to_bytes()[..]);
     }
 
+    #[test]
+    fn hs_sm_log_level() {
+        let sm = StorageManager::new_test_sm();
+        assert_eq!(LevelFilter::Trace, sm.log_level());
+        sm.set_log_level(LevelFilter::Warn);
+        assert_eq!(LevelFilter::Warn, sm.log_level());
+        sm.set_log_level(LevelFilter::Off);
+        assert_eq!(LevelFilter::Off, sm.log_level());
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn hs_sm_parquet() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let (cid, imported) = (1, 2);
+        sm.create_table(cid).unwrap();
+        sm.create_table(imported).unwrap();
+        let tid = TransactionId::new();
+        let table = gen_table_for_test_tuples(String::from("t"));
+        let tuples = gen_test_tuples(100);
+        for tuple in &tuples {
+            sm.insert_value(cid, tuple.to_bytes(), tid).unwrap();
+        }
+        let path = sm.storage_path.join("t.parquet");
+        let path = path.to_string_lossy().to_string();
+        let options = ParquetExportOptions::default().with_row_group_size(30);
+
+        assert_eq!(
+            Ok(100),
+            sm.export_parquet(cid, &table.schema, path.clone(), &options, tid)
+        );
+        assert_eq!(
+            Ok(100),
+            sm.import_parquet(&table, path.clone(), tid, imported)
+        );
+        let scan = |container_id| {
+            let mut tuples: Vec<Vec<u8>> = sm
+                .get_iterator(container_id, tid, Permissions::ReadOnly)
+                .map(|(bytes, _)| bytes)
+                .collect();
+            tuples.sort();
+            tuples
+        };
+        assert_eq!(scan(cid), scan(imported));
+        assert_eq!(
+            Err(CrustyError::ContainerMissing(3)),
+            sm.export_parquet(3, &table.schema, path, &options, tid)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "arrow")]
+    fn hs_sm_arrow_scan() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        let table = gen_table_for_test_tuples(String::from("t"));
+        for tuple in gen_test_tuples(100) {
+            sm.insert_value(cid, tuple.to_bytes(), tid).unwrap();
+        }
+
+        let batches = sm.scan_record_batches(cid, &table.schema, tid, 40).unwrap();
+        let rows: Vec<usize> = batches.map(|b| b.unwrap().num_rows()).collect();
+        assert_eq!(vec![40, 40, 20], rows);
+        assert!(sm.scan_record_batches(2, &table.schema, tid, 40).is_err());
+    }
+
+    #[test]
+    fn hs_sm_insert_page_full() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        sm.insert_value(cid, get_random_byte_vec(40), tid).unwrap();
+
+        // A value that does not fit in an empty page is refused without adding a page.
+        assert_eq!(
+            Err(CrustyError::PageFull {
+                container_id: cid,
+                page_id: 1
+            }),
+            sm.insert_value(cid, get_random_byte_vec(PAGE_SIZE), tid)
+        );
+        assert_eq!(1, sm.get_num_pages(cid));
+    }
+
+    #[test]
+    fn hs_sm_rename_truncate_containers() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let tid = TransactionId::new();
+        sm.create_table(1).unwrap();
+        sm.create_container(2, None, common::ids::StateType::BaseTable, Some(vec![1]))
+            .unwrap();
+        let vals = get_random_vec_of_byte_vec(300, 50, 100);
+        let ids: Vec<ValueId> = vals
+            .iter()
+            .map(|v| sm.insert_value(1, v.clone(), tid).unwrap())
+            .collect();
+        let containers = sm.list_containers().unwrap();
+        assert_eq!(2, containers.len());
+        assert_eq!(1, containers[0].container_id);
+        assert!(containers[0].num_pages > 1);
+        if !crate::compression::ENABLED {
+            assert_eq!(
+                containers[0].num_pages as u64 * PAGE_SIZE as u64,
+                containers[0].file_size
+            );
+        }
+        assert_eq!(vec![1], containers[1].dependencies);
+        assert!(!containers[1].is_temp);
+
+        assert!(sm.rename_container(1, 2).is_err());
+        assert!(sm.rename_container(5, 6).is_err());
+        sm.rename_container(1, 7).unwrap();
+        let containers = sm.list_containers().unwrap();
+        assert_eq!(
+            vec![2, 7],
+            containers
+                .iter()
+                .map(|c| c.container_id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(vec![7], containers[0].dependencies);
+        assert!(!sm.storage_path.join("c1").exists());
+        for (id, val) in ids.iter().zip(&vals) {
+            let id = ValueId {
+                container_id: 7,
+                ..*id
+            };
+            assert_eq!(*val, sm.get_value(id, tid, Permissions::ReadOnly).unwrap());
+        }
+        assert!(sm.get_value(ids[0], tid, Permissions::ReadOnly).is_err());
+        // the dependency moved with the container
+        assert!(sm.remove_container(7).is_err());
+
+        sm.truncate_container(7).unwrap();
+        assert_eq!(0, sm.get_num_pages(7));
+        assert_eq!(0, sm.get_iterator(7, tid, Permissions::ReadOnly).count());
+        assert_eq!(0, sm.list_containers().unwrap()[1].file_size);
+        sm.insert_value(7, vals[0].clone(), tid).unwrap();
+        assert_eq!(1, sm.get_iterator(7, tid, Permissions::ReadOnly).count());
+        assert!(sm.truncate_container(1).is_err());
+
+        // a snapshot iterator could not read the truncated pages
+        let iter = sm.get_snapshot_iterator(7, tid, Permissions::ReadOnly);
+        assert!(sm.truncate_container(7).is_err());
+        drop(iter);
+        sm.truncate_container(7).unwrap();
+        sm.reset().unwrap();
+    }
+
+    #[test]
+    fn hs_sm_page_description() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        let id = sm.insert_value(cid, get_random_byte_vec(40), tid).unwrap();
+        sm.insert_value(cid, get_random_byte_vec(60), tid).unwrap();
+        sm.delete_value(id, tid).unwrap();
+
+        let d = sm.get_page_description(cid, 0).unwrap();
+        assert_eq!(60, d.value_size);
+        assert_eq!(Some(0), d.open_slot);
+        assert_eq!(2, d.slots.len());
+        assert!(sm.get_page_debug(cid, 0).starts_with(&d.to_string()));
+        assert!(sm.get_page_description(cid, 1).is_err());
+    }
+
+    #[test]
+    fn hs_sm_container_config() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let tid = TransactionId::new();
+        let half_full = ContainerConfig {
+            fill_factor: 50,
+            ..Default::default()
+        };
+        sm.create_table(1).unwrap();
+        sm.create_container_with_config(2, half_full, None).unwrap();
+        assert_eq!(ContainerConfig::default(), sm.container_config(1).unwrap());
+        assert_eq!(half_full, sm.container_config(2).unwrap());
+        assert_eq!(half_full, sm.list_containers().unwrap()[1].config);
+
+        let vals = get_random_vec_of_byte_vec(100, 80, 100);
+        sm.insert_values(1, vals.clone(), tid).unwrap();
+        sm.insert_values(2, vals.clone(), tid).unwrap();
+        sm.bulk_load(2, vals.iter().cloned(), tid).unwrap();
+        // twice the values in half full pages
+        assert!(sm.get_num_pages(2) > 2 * sm.get_num_pages(1));
+        for page_id in 0..sm.get_num_pages(2) {
+            let d = sm.get_page_description(2, page_id).unwrap();
+            assert!(d.free_space * 2 >= PAGE_SIZE);
+        }
+
+        // options out of range
+        let empty_pages = ContainerConfig {
+            fill_factor: 0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            sm.create_container_with_config(3, empty_pages, None),
+            Err(CrustyError::ValidationError(_))
+        ));
+        assert!(sm.container_config(3).is_err());
+
+        // the options move with a renamed container
+        sm.rename_container(2, 4).unwrap();
+        assert_eq!(half_full, sm.container_config(4).unwrap());
+        sm.reset().unwrap();
+    }
+
+    #[test]
+    fn hs_sm_container_quota() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let tid = TransactionId::new();
+        let quota = ContainerConfig {
+            max_pages: Some(3),
+            max_bytes: Some(2 * PAGE_SIZE as u64 + 100),
+            ..Default::default()
+        };
+        assert_eq!(Some(2), quota.page_limit());
+        sm.create_container_with_config(1, quota, None).unwrap();
+
+        // inserts fill two pages, then fail
+        let vals = get_random_vec_of_byte_vec(200, 80, 100);
+        let mut ids = Vec::new();
+        let err = loop {
+            match sm.insert_value(1, vals[ids.len()].clone(), tid) {
+                Ok(id) => ids.push(id),
+                Err(e) => break e,
+            }
+        };
+        assert_eq!(
+            CrustyError::QuotaExceeded {
+                container_id: 1,
+                max_pages: 2
+            },
+            err
+        );
+        assert_eq!(2, sm.get_num_pages(1));
+        assert!(sm.bulk_load(1, vals.iter().cloned(), tid).is_err());
+        assert_eq!(2, sm.get_num_pages(1));
+        assert_eq!(
+            ids.len(),
+            sm.get_iterator(1, tid, Permissions::ReadOnly).count()
+        );
+
+        // the pages it has can still be changed
+        sm.delete_value(ids[0], tid).unwrap();
+        sm.insert_value(1, vals[0].clone(), tid).unwrap();
+        sm.update_value(vals[1].clone(), ids[1], tid).unwrap();
+
+        // raise the quota at runtime
+        let raised = ContainerConfig {
+            max_pages: Some(10),
+            max_bytes: None,
+            ..quota
+        };
+        sm.set_container_config(1, raised).unwrap();
+        assert_eq!(raised, sm.container_config(1).unwrap());
+        sm.insert_value(1, vals[ids.len()].clone(), tid).unwrap();
+        assert_eq!(3, sm.get_num_pages(1));
+        // lowering it keeps the pages
+        sm.set_container_config(1, quota).unwrap();
+        assert_eq!(3, sm.get_num_pages(1));
+        assert!(sm.insert_values(1, vals.clone(), tid).is_err());
+        assert!(sm.set_container_config(2, quota).is_err());
+        sm.reset().unwrap();
+    }
+
+    #[test]
+    fn hs_sm_in_memory_containers() {
+        init();
+        let path = gen_random_test_sm_dir();
+        let sm = StorageManager::new(path.clone()).with_in_memory_temp_containers();
+        let tid = TransactionId::new();
+        let in_memory = ContainerConfig {
+            in_memory: true,
+            ..Default::default()
+        };
+        sm.create_table(1).unwrap();
+        sm.create_container_with_config(2, in_memory, Some(vec![1]))
+            .unwrap();
+        assert!(!container_path(&path, 2).exists());
+        let vals = get_random_vec_of_byte_vec(200, 80, 100);
+        let ids = sm.insert_values(2, vals.clone(), tid).unwrap();
+        assert!(sm.get_num_pages(2) > 2);
+        sm.update_value(vals[1].clone(), ids[0], tid).unwrap();
+        sm.delete_value(ids[1], tid).unwrap();
+        assert_eq!(
+            vals.len() - 1,
+            sm.get_iterator(2, tid, Permissions::ReadOnly).count()
+        );
+        assert_eq!(
+            vals[2],
+            sm.get_value(ids[2], tid, Permissions::ReadOnly).unwrap()
+        );
+        let info = sm.list_containers().unwrap();
+        assert_eq!(0, info[1].file_size);
+        assert!(info[1].config.in_memory);
+
+        // it stays in memory, and keeps its id
+        assert!(sm.rename_container(2, 3).is_err());
+        assert!(sm
+            .set_container_config(2, ContainerConfig::default())
+            .is_err());
+        assert!(sm.set_container_config(1, in_memory).is_err());
+
+        // temporary containers are in memory too
+        let temp = sm.create_temp_container(tid).unwrap();
+        assert!(sm.container_config(temp).unwrap().in_memory);
+        sm.insert_values(temp, vals.clone(), tid).unwrap();
+        assert!(!container_path(&path, temp).exists());
+        sm.transaction_finished(tid);
+        assert!(sm.container_config(temp).is_err());
+
+        // containers in memory are not persisted, nor are their dependencies
+        sm.shutdown();
+        drop(sm);
+        let sm = StorageManager::new(path.clone());
+        assert_eq!(
+            vec![1],
+            sm.list_containers()
+                .unwrap()
+                .iter()
+                .map(|c| c.container_id)
+                .collect::<Vec<_>>()
+        );
+        sm.remove_container(1).unwrap();
+        sm.create_container_with_config(2, in_memory, None).unwrap();
+        sm.remove_container(2).unwrap();
+        drop(sm);
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn hs_sm_update_single_pass() {
+        init();
+        for sm in [
+            StorageManager::new_test_sm(),
+            StorageManager::new_test_sm().with_mvcc(),
+        ] {
+            let cid = 1;
+            sm.create_table(cid).unwrap();
+            let tid = TransactionId::new();
+            let vals = get_random_vec_of_byte_vec(20, 80, 100);
+            let ids = sm.insert_values(cid, vals, tid).unwrap();
+            #[cfg(feature = "profile")]
+            let hf = sm.heap_file(cid).unwrap();
+
+            // a value of the same length, or a longer one that fits, stays in its page
+            let mut id = ids[3];
+            for len in [80, 90, 150] {
+                #[cfg(feature = "profile")]
+                let before = hf.metrics.counters();
+                let value = get_random_byte_vec(len);
+                let new_id = sm.update_value(value.clone(), id, tid).unwrap();
+                #[cfg(feature = "profile")]
+                {
+                    let after = hf.metrics.counters();
+                    assert_eq!(1, after.reads - before.reads);
+                    assert_eq!(1, after.writes - before.writes);
+                }
+                assert_eq!(id.page_id, new_id.page_id);
+                // without MVCC, a value of the same length is overwritten in place
+                if len == 80 && sm.txns.is_none() {
+                    assert_eq!(id, new_id);
+                }
+                assert_eq!(
+                    value,
+                    sm.get_value(new_id, tid, Permissions::ReadOnly).unwrap()
+                );
+                id = new_id;
+            }
+
+            // a value that does not fit in the page moves to another one
+            let value = get_random_byte_vec(PAGE_SIZE - 200);
+            let id = sm.update_value(value.clone(), ids[5], tid).unwrap();
+            assert_ne!(ids[5].page_id, id.page_id);
+            assert_eq!(value, sm.get_value(id, tid, Permissions::ReadOnly).unwrap());
+            assert!(sm.get_value(ids[5], tid, Permissions::ReadOnly).is_err());
+            assert_eq!(20, sm.get_iterator(cid, tid, Permissions::ReadOnly).count());
+            assert!(sm.update_value(value, ValueId::new(2), tid).is_err());
+            sm.reset().unwrap();
+        }
+    }
+
+    #[test]
+    fn hs_sm_delete_values() {
+        init();
+        for sm in [
+            StorageManager::new_test_sm(),
+            StorageManager::new_test_sm().with_mvcc(),
+        ] {
+            let tid = TransactionId::new();
+            sm.create_table(1).unwrap();
+            sm.create_table(2).unwrap();
+            let vals = get_random_vec_of_byte_vec(200, 80, 100);
+            let ids1 = sm.insert_values(1, vals.clone(), tid).unwrap();
+            let ids2 = sm.insert_values(2, vals.clone(), tid).unwrap();
+            assert!(sm.get_num_pages(1) > 2);
+
+            // every other value of container 1, and a value of container 2
+            let deleted: Vec<ValueId> = ids1
+                .iter()
+                .step_by(2)
+                .rev()
+                .chain([&ids2[7]])
+                .copied()
+                .collect();
+            #[cfg(feature = "profile")]
+            let before = sm.heap_file(1).unwrap().metrics.counters();
+            sm.delete_values(deleted.clone(), tid).unwrap();
+            #[cfg(feature = "profile")]
+            {
+                // each page is written once
+                let after = sm.heap_file(1).unwrap().metrics.counters();
+                let pages = sm.get_num_pages(1) as u64;
+                assert_eq!(pages, after.writes - before.writes);
+                assert_eq!(pages, after.reads - before.reads);
+            }
+            for id in &deleted {
+                assert!(sm.get_value(*id, tid, Permissions::ReadOnly).is_err());
+            }
+            assert_eq!(100, sm.get_iterator(1, tid, Permissions::ReadOnly).count());
+            assert_eq!(199, sm.get_iterator(2, tid, Permissions::ReadOnly).count());
+            assert_eq!(
+                vals[1],
+                sm.get_value(ids1[1], tid, Permissions::ReadOnly).unwrap()
+            );
+
+            // a page that does not exist
+            let missing = ValueId {
+                page_id: Some(sm.get_num_pages(2)),
+                ..ids2[0]
+            };
+            assert_eq!(
+                Err(CrustyError::invalid_slot(&missing)),
+                sm.delete_values(vec![ids2[0], missing], tid)
+            );
+            assert!(sm.delete_values(vec![ValueId::new(2)], tid).is_err());
+            sm.reset().unwrap();
+        }
+    }
+
+    #[test]
+    fn hs_sm_page_pinning() {
+        init();
+        let sm = StorageManager::new_test_sm().with_buffer_pool_capacity(1);
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        let vals = get_random_vec_of_byte_vec(100, 80, 100);
+        let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+        assert!(sm.get_num_pages(cid) > 2);
+
+        // a pinned page stays in the pool while other pages are read
+        let pinned = sm
+            .get_page(cid, 0, tid, Permissions::ReadOnly, true)
+            .unwrap();
+        assert!(pinned.is_pinned());
+        assert_eq!(1, sm.buffer_pool.pins(cid, 0));
+        for page_id in 1..sm.get_num_pages(cid) {
+            assert!(!sm
+                .get_page(cid, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap()
+                .is_pinned());
+        }
+        #[cfg(feature = "profile")]
+        let faults = sm.heap_file(cid).unwrap().metrics.counters().page_faults;
+        let again = sm
+            .get_page(cid, 0, tid, Permissions::ReadOnly, true)
+            .unwrap();
+        #[cfg(feature = "profile")]
+        assert_eq!(
+            faults,
+            sm.heap_file(cid).unwrap().metrics.counters().page_faults
+        );
+        assert_eq!(2, sm.buffer_pool.pins(cid, 0));
+        drop(again);
+        assert_eq!(vals[0], pinned.get_value(0).unwrap());
+
+        // a written page is read again, and the guard keeps the page it was given. The new
+        // value has the length of the old one, so it is written in place.
+        let new_val = get_random_byte_vec(vals[0].len());
+        let new_id = sm.update_value(new_val.clone(), ids[0], tid).unwrap();
+        assert_eq!(ids[0], new_id);
+        assert_eq!(
+            new_val,
+            sm.get_value(new_id, tid, Permissions::ReadOnly).unwrap()
+        );
+        let page = sm
+            .get_page(cid, 0, tid, Permissions::ReadOnly, false)
+            .unwrap();
+        assert_eq!(Some(&new_val[..]), page.get_value_ref(0));
+        assert_eq!(vals[0], pinned.get_value(0).unwrap());
+        assert_eq!(Some(&vals[0][..]), pinned.get_value_ref(0));
+        drop(pinned);
+        assert_eq!(0, sm.buffer_pool.pins(cid, 0));
+        assert_eq!(1, sm.buffer_pool.len());
+
+        // truncating drops the pages
+        sm.truncate_container(cid).unwrap();
+        assert!(sm
+            .get_page(cid, 0, tid, Permissions::ReadOnly, false)
+            .is_err());
+        sm.clear_cache();
+        assert_eq!(0, sm.buffer_pool.len());
+        sm.reset().unwrap();
+    }
+
+    #[test]
+    fn hs_sm_tiered_buffer_pool() {
+        init();
+        let sm = StorageManager::new_test_sm()
+            .with_buffer_pool_capacity(2)
+            .with_eviction_policy(EvictionPolicy::TwoQueue)
+            .with_cold_pages(4);
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        sm.insert_values(cid, get_random_vec_of_byte_vec(400, 80, 100), tid)
+            .unwrap();
+        let num_pages = sm.get_num_pages(cid);
+        assert!(num_pages > 6);
+
+        for page_id in 0..num_pages {
+            sm.get_page(cid, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap();
+        }
+        let stats = sm.buffer_pool_stats()[&cid];
+        assert_eq!(num_pages as u64, stats.misses);
+        assert_eq!(num_pages as usize - 2, stats.evictions as usize);
+        assert_eq!(2, stats.resident);
+        assert_eq!(4, stats.cold);
+
+        // the last pages read are still in memory, resident or compressed
+        #[cfg(feature = "profile")]
+        let faults = sm.heap_file(cid).unwrap().metrics.counters().page_faults;
+        for page_id in num_pages - 6..num_pages {
+            sm.get_page(cid, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap();
+        }
+        #[cfg(feature = "profile")]
+        assert_eq!(
+            faults,
+            sm.heap_file(cid).unwrap().metrics.counters().page_faults
+        );
+        let stats = sm.buffer_pool_stats()[&cid];
+        assert_eq!(6, stats.hits);
+        // reading the cold pages evicted the resident ones into the cold tier
+        assert_eq!(6, stats.cold_hits);
+
+        sm.remove_container(cid).unwrap();
+        assert!(sm.buffer_pool_stats().is_empty());
+        sm.reset().unwrap();
+    }
+
+    #[test]
+    fn hs_sm_scans_keep_working_set() {
+        init();
+        let sm = StorageManager::new_test_sm().with_buffer_pool_capacity(4);
+        let (hot, big) = (1, 2);
+        sm.create_table(hot).unwrap();
+        sm.create_table(big).unwrap();
+        let tid = TransactionId::new();
+        sm.insert_values(hot, get_random_vec_of_byte_vec(100, 80, 100), tid)
+            .unwrap();
+        sm.insert_values(big, get_random_vec_of_byte_vec(400, 80, 100), tid)
+            .unwrap();
+        let hot_pages = sm.get_num_pages(hot);
+        assert!(hot_pages <= 3);
+        for page_id in 0..hot_pages {
+            sm.get_page(hot, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap();
+        }
+
+        // a scan of a larger table leaves the pages point reads use in the pool
+        assert_eq!(
+            400,
+            sm.get_iterator(big, tid, Permissions::ReadOnly).count()
+        );
+        for page_id in 0..hot_pages {
+            sm.get_page(hot, page_id, tid, Permissions::ReadOnly, false)
+                .unwrap();
+        }
+        let stats = sm.buffer_pool_stats();
+        assert_eq!(hot_pages as u64, stats[&hot].hits);
+        assert!(stats[&big].evictions > 0);
+        sm.reset().unwrap();
+    }
+
     // #[test]
     // fn hs_sm_insertalization() { // currently overwriting page data instead of adding to it
     //     init();
@@ -569,7 +3921,7 @@ mod test {
     //     let bytes = get_random_byte_vec(40);
     //     let tid = TransactionId::new();
 
-    //     let val1 = sm.insert_value(cid, bytes.clone(), tid);
+    //     let val1 = sm.insert_value(cid, bytes.clone(), tid).unwrap();
     //     assert_eq!(1, sm.get_num_pages(cid));
     //     assert_eq!(0, val1.page_id.unwrap());
     //     assert_eq!(0, val1.slot_id.unwrap());
@@ -578,14 +3930,14 @@ mod test {
     //         .get_page(cid, 0, tid, Permissions::ReadOnly, false)
     //         .unwrap();
 
-    //     let val2 = sm.insert_value(cid, [1].to_vec(), tid);
+    //     let val2 = sm.insert_value(cid, [1].to_vec(), tid).unwrap();
     //     assert_eq!(1, sm.get_num_pages(cid));
     //     assert_eq!(0, val2.page_id.unwrap());
     //     assert_eq!(1, val2.slot_id.unwrap());
 
     //     // insert 25 more values into page2
     //     for _ in 0..1000 {
-    //         sm.insert_value(cid, [1].to_vec(), tid);
+    //         sm.insert_value(cid, [1].to_vec(), tid).unwrap();
     //     }
 
     //     // this should cause a third page to be created, check that it exists
@@ -600,7 +3952,6 @@ mod test {
     //     //print the valueid's to see if they are different
     //     assert_eq!(p1.to_bytes()[..], p2.to_bytes()[..]);
 
-
     // }
 
     #[test]
@@ -618,7 +3969,7 @@ mod test {
             get_random_byte_vec(400),
         ];
         for val in &byte_vec {
-            sm.insert_value(cid, val.clone(), tid);
+            sm.insert_value(cid, val.clone(), tid).unwrap();
         }
         let iter = sm.get_iterator(cid, tid, Permissions::ReadOnly);
         for (i, x) in iter.enumerate() {
@@ -634,7 +3985,7 @@ mod test {
         ];
 
         for val in &byte_vec2 {
-            sm.insert_value(cid, val.clone(), tid);
+            sm.insert_value(cid, val.clone(), tid).unwrap();
         }
         byte_vec.append(&mut byte_vec2);
 
@@ -651,7 +4002,7 @@ mod test {
         ];
 
         for val in &byte_vec2 {
-            sm.insert_value(cid, val.clone(), tid);
+            sm.insert_value(cid, val.clone(), tid).unwrap();
         }
         byte_vec.append(&mut byte_vec2);
 
@@ -661,6 +4012,53 @@ mod test {
         }
     }
 
+    #[test]
+    fn hs_sm_snapshot_iter() {
+        init();
+        let sm = StorageManager::new_test_sm();
+        let cid = 1;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+
+        // Three pages of values
+        let vals = get_random_vec_of_byte_vec(24, 400, 400);
+        let ids = sm.insert_values(cid, vals.clone(), tid).unwrap();
+        let mut snap_iter = sm.get_snapshot_iterator(cid, tid, Permissions::ReadOnly);
+        let first = snap_iter.next().unwrap();
+        assert_eq!((vals[0].clone(), ids[0]), first);
+
+        // Writes after the snapshot: delete, update, and inserts into old and new pages
+        sm.delete_value(ids[1], tid).unwrap();
+        sm.delete_value(ids[12], tid).unwrap();
+        sm.update_value(get_random_byte_vec(100), ids[20], tid)
+            .unwrap();
+        let new_vals = get_random_vec_of_byte_vec(20, 300, 300);
+        sm.insert_values(cid, new_vals.clone(), tid).unwrap();
+
+        // The snapshot sees exactly the values live when it was taken, with their ids
+        let mut seen = vec![first];
+        seen.extend(snap_iter);
+        let expected: Vec<(Vec<u8>, ValueId)> =
+            vals.iter().cloned().zip(ids.iter().cloned()).collect();
+        assert_eq!(expected, seen);
+
+        // A new iterator sees the current state
+        let current: Vec<Vec<u8>> = sm
+            .get_iterator(cid, tid, Permissions::ReadOnly)
+            .map(|(v, _)| v)
+            .collect();
+        assert_eq!(vals.len() - 2 + new_vals.len(), current.len());
+        assert!(!current.contains(&vals[1]));
+        assert!(!current.contains(&vals[20]));
+
+        // Once released, a second snapshot reads the current state
+        let snap: Vec<Vec<u8>> = sm
+            .get_snapshot_iterator(cid, tid, Permissions::ReadOnly)
+            .map(|(v, _)| v)
+            .collect();
+        assert_eq!(current, snap);
+    }
+
     #[test]
     #[ignore]
     fn hs_sm_b_iter_large() {
@@ -672,7 +4070,7 @@ mod test {
         let tid = TransactionId::new();
 
         let vals = get_random_vec_of_byte_vec(1000, 40, 400);
-        sm.insert_values(cid, vals, tid);
+        sm.insert_values(cid, vals, tid).unwrap();
         let mut count = 0;
         for _ in sm.get_iterator(cid, tid, Permissions::ReadOnly) {
             count += 1;
@@ -60,10 +60,16 @@ pub fn bench_page_insert(vals: &[Vec<u8>]) {
     }
 }
 
+pub fn bench_page_insert_many(vals: &[Vec<u8>]) {
+    let mut p = Page::new(0);
+    let vals: Vec<&[u8]> = vals.iter().map(Vec::as_slice).collect();
+    assert!(p.add_values(&vals).iter().all(Option::is_some));
+}
+
 pub fn bench_sm_insert(sm: &StorageManager, to_insert: &[Vec<u8>]) {
     let cid = 1;
     let tid = TransactionId::new();
     for x in to_insert {
-        sm.insert_value(cid, x.to_vec(), tid);
+        sm.insert_value(cid, x.to_vec(), tid).unwrap();
     }
 }
@@ -0,0 +1,785 @@
+//! Write-ahead log of the changes made to a storage manager's containers, used for
+//! point-in-time recovery and to recover from crashes.
+//!
+//! A page write is logged as the full image of the page after the write, or as a
+//! `PageDelta` of the bytes it changed when that is smaller and the page was already logged
+//! in full since the last checkpoint, a bulk load as the images of all the pages it wrote, and creating, removing, renaming or truncating
+//! a container or changing its options is logged too, so replaying the log from a backup onward
+//! reproduces the containers at any later point. Entries are numbered by a log sequence
+//! number (LSN) and stamped with the time they were logged.
+//!
+//! The log is a sequence of segment files in the `wal` directory of the storage path, named
+//! after the LSN of their first entry. Once a segment reaches `WalConfig::segment_size` bytes
+//! a new one is started and the full one is moved to the archive directory, if there is one.
+//! Each entry is written as
+//!
+//! | length (4) | lsn (8) | timestamp (8) | kind (1) | container id (2) | payload |
+//!
+//! where length counts the bytes after it. A page entry's payload is the page, a page
+//! delta's is the serialized `PageDelta`, a load's is
+//! its pages one after the other, a container creation's is the number of containers it
+//! depends on (2) and their ids, followed by its `ContainerConfig` as json unless it is the
+//! default, a rename's is the new container id (2), a change of options' is the new
+//! `ContainerConfig` as json, and a commit's is the transaction id (8).
+//!
+//! Entries are written to the segment file as they are logged but only synced to disk by
+//! `Wal::flush`, when a segment is full, by backups and shutdown, and when a transaction
+//! commits. Commits use group commit: the first committing transaction waits
+//! `WalConfig::group_commit_window` for others to log their commits, and one sync then makes
+//! all of them durable. Transactions committing while a sync is in progress wait for it and
+//! for the next one, so under load there is about one sync per window instead of one per
+//! commit.
+//!
+//! A storage manager opened with a WAL replays the entries logged after its last
+//! checkpoint, the LSN its heap files were last synced at, so the pages a crash left
+//! unwritten or cut short are written again. Replaying an entry twice is harmless, as
+//! entries hold whole pages or the new bytes of pages. A page cut short by a crash is
+//! restored by its full image, logged before any delta of it after the checkpoint. An entry
+//! cut short by the crash is dropped from its segment.
+
+use crate::fault::{self, FaultPoint};
+use crate::page::PageDelta;
+use common::ids::{ContainerId, SegmentId, TidType};
+use common::{ContainerConfig, CrustyError, PAGE_SIZE};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Log sequence number of a WAL entry. The first entry has LSN 1.
+pub type Lsn = u64;
+
+/// Extension of segment files.
+const SEGMENT_EXTENSION: &str = "wal";
+/// Serialized size of an entry header after the length: lsn, timestamp, kind, container id.
+const ENTRY_HEADER_SIZE: usize = 19;
+
+const KIND_PAGE: u8 = 0;
+const KIND_CREATE: u8 = 1;
+const KIND_REMOVE: u8 = 2;
+const KIND_COMMIT: u8 = 3;
+const KIND_LOAD: u8 = 4;
+const KIND_RENAME: u8 = 5;
+const KIND_TRUNCATE: u8 = 6;
+const KIND_CONFIG: u8 = 7;
+const KIND_PAGE_DELTA: u8 = 8;
+const KIND_TRUNCATE_SEGMENT: u8 = 9;
+
+/// Configuration of the WAL of a storage manager.
+#[derive(Clone, Debug)]
+pub struct WalConfig {
+    /// Directory full segments are moved to. Without one they stay in the `wal` directory.
+    pub archive_dir: Option<PathBuf>,
+    /// Size in bytes after which a segment is full.
+    pub segment_size: u64,
+    /// Time a commit waits for concurrent commits to share its sync. Zero syncs right away,
+    /// batching only the commits made while a sync is in progress.
+    pub group_commit_window: Duration,
+}
+
+impl Default for WalConfig {
+    fn default() -> Self {
+        WalConfig {
+            archive_dir: None,
+            segment_size: 16 * 1024 * 1024,
+            group_commit_window: Duration::ZERO,
+        }
+    }
+}
+
+/// The point `StorageManager::recover_to` replays the log up to, inclusive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecoveryTarget {
+    /// The entry with this LSN.
+    Lsn(Lsn),
+    /// The last entry logged at or before this time.
+    Time(SystemTime),
+}
+
+/// A change logged in the WAL.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum WalRecord {
+    /// A page was written, with its bytes after the write.
+    Page {
+        container_id: ContainerId,
+        page: Vec<u8>,
+    },
+    /// A page was written, with the bytes the write changed.
+    PageDelta {
+        container_id: ContainerId,
+        delta: PageDelta,
+    },
+    /// A container was created.
+    CreateContainer {
+        container_id: ContainerId,
+        dependencies: Vec<ContainerId>,
+        config: ContainerConfig,
+    },
+    /// A container was removed.
+    RemoveContainer { container_id: ContainerId },
+    /// A transaction committed.
+    Commit { tid: TidType },
+    /// Pages were bulk loaded, with their bytes.
+    Load {
+        container_id: ContainerId,
+        pages: Vec<Vec<u8>>,
+    },
+    /// A container was given a new id.
+    RenameContainer {
+        container_id: ContainerId,
+        new_container_id: ContainerId,
+    },
+    /// All the pages of a container were dropped.
+    TruncateContainer { container_id: ContainerId },
+    /// The pages of a segment of a container were dropped.
+    TruncateSegment {
+        container_id: ContainerId,
+        segment: SegmentId,
+    },
+    /// The options of a container were changed, with the new ones.
+    SetConfig {
+        container_id: ContainerId,
+        config: ContainerConfig,
+    },
+}
+
+/// An entry of the WAL.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct WalEntry {
+    pub lsn: Lsn,
+    /// Microseconds since the Unix epoch when the entry was logged.
+    pub timestamp: u64,
+    pub record: WalRecord,
+}
+
+impl WalEntry {
+    /// Returns true if the entry is past the recovery target.
+    pub fn is_after(&self, target: &RecoveryTarget) -> bool {
+        match target {
+            RecoveryTarget::Lsn(lsn) => self.lsn > *lsn,
+            RecoveryTarget::Time(time) => self.timestamp > micros_since_epoch(*time),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let (kind, container_id) = match &self.record {
+            WalRecord::Page { container_id, .. } => (KIND_PAGE, container_id),
+            WalRecord::PageDelta { container_id, .. } => (KIND_PAGE_DELTA, container_id),
+            WalRecord::CreateContainer { container_id, .. } => (KIND_CREATE, container_id),
+            WalRecord::RemoveContainer { container_id } => (KIND_REMOVE, container_id),
+            WalRecord::Commit { .. } => (KIND_COMMIT, &0),
+            WalRecord::Load { container_id, .. } => (KIND_LOAD, container_id),
+            WalRecord::RenameContainer { container_id, .. } => (KIND_RENAME, container_id),
+            WalRecord::TruncateContainer { container_id } => (KIND_TRUNCATE, container_id),
+            WalRecord::TruncateSegment { container_id, .. } => {
+                (KIND_TRUNCATE_SEGMENT, container_id)
+            }
+            WalRecord::SetConfig { container_id, .. } => (KIND_CONFIG, container_id),
+        };
+        let mut bytes = vec![0; 4];
+        bytes.extend_from_slice(&self.lsn.to_le_bytes());
+        bytes.extend_from_slice(&self.timestamp.to_le_bytes());
+        bytes.push(kind);
+        bytes.extend_from_slice(&container_id.to_le_bytes());
+        match &self.record {
+            WalRecord::Page { page, .. } => bytes.extend_from_slice(page),
+            WalRecord::PageDelta { delta, .. } => bytes.extend_from_slice(&delta.to_bytes()),
+            WalRecord::CreateContainer {
+                dependencies,
+                config,
+                ..
+            } => {
+                bytes.extend_from_slice(&(dependencies.len() as u16).to_le_bytes());
+                for dependency in dependencies {
+                    bytes.extend_from_slice(&dependency.to_le_bytes());
+                }
+                if *config != ContainerConfig::default() {
+                    bytes.extend_from_slice(&serde_json::to_vec(config).unwrap());
+                }
+            }
+            WalRecord::RemoveContainer { .. } => {}
+            WalRecord::Commit { tid } => bytes.extend_from_slice(&tid.to_le_bytes()),
+            WalRecord::Load { pages, .. } => {
+                for page in pages {
+                    bytes.extend_from_slice(page);
+                }
+            }
+            WalRecord::RenameContainer {
+                new_container_id, ..
+            } => bytes.extend_from_slice(&new_container_id.to_le_bytes()),
+            WalRecord::TruncateContainer { .. } => {}
+            WalRecord::TruncateSegment { segment, .. } => bytes.push(*segment),
+            WalRecord::SetConfig { config, .. } => {
+                bytes.extend_from_slice(&serde_json::to_vec(config).unwrap())
+            }
+        }
+        let len = (bytes.len() - 4) as u32;
+        bytes[0..4].copy_from_slice(&len.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes an entry written by `to_bytes`, without its length.
+    fn from_bytes(bytes: &[u8]) -> Result<Self, CrustyError> {
+        if bytes.len() < ENTRY_HEADER_SIZE {
+            return Err(corrupt("entry is shorter than its header"));
+        }
+        let lsn = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let timestamp = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let container_id = ContainerId::from_le_bytes([bytes[17], bytes[18]]);
+        let payload = &bytes[ENTRY_HEADER_SIZE..];
+        let record = match bytes[16] {
+            KIND_PAGE => WalRecord::Page {
+                container_id,
+                page: payload.to_vec(),
+            },
+            KIND_PAGE_DELTA => WalRecord::PageDelta {
+                container_id,
+                delta: PageDelta::from_bytes(payload)
+                    .map_err(|_| corrupt("page delta is invalid"))?,
+            },
+            KIND_CREATE => {
+                if payload.len() < 2 {
+                    return Err(corrupt("container creation has no dependency count"));
+                }
+                let count = u16::from_le_bytes([payload[0], payload[1]]) as usize;
+                if payload.len() < 2 + 2 * count {
+                    return Err(corrupt("container creation has a wrong dependency count"));
+                }
+                let dependencies = payload[2..2 + 2 * count]
+                    .chunks(2)
+                    .map(|c| ContainerId::from_le_bytes([c[0], c[1]]))
+                    .collect();
+                let config = match &payload[2 + 2 * count..] {
+                    [] => ContainerConfig::default(),
+                    json => serde_json::from_slice(json)
+                        .map_err(|_| corrupt("container creation has an invalid config"))?,
+                };
+                WalRecord::CreateContainer {
+                    container_id,
+                    dependencies,
+                    config,
+                }
+            }
+            KIND_REMOVE => WalRecord::RemoveContainer { container_id },
+            KIND_COMMIT => WalRecord::Commit {
+                tid: TidType::from_le_bytes(
+                    payload
+                        .try_into()
+                        .map_err(|_| corrupt("commit has no transaction id"))?,
+                ),
+            },
+            KIND_LOAD => {
+                if !payload.len().is_multiple_of(PAGE_SIZE) {
+                    return Err(corrupt("load is not a whole number of pages"));
+                }
+                WalRecord::Load {
+                    container_id,
+                    pages: payload.chunks(PAGE_SIZE).map(<[u8]>::to_vec).collect(),
+                }
+            }
+            KIND_RENAME => WalRecord::RenameContainer {
+                container_id,
+                new_container_id: ContainerId::from_le_bytes(
+                    payload
+                        .try_into()
+                        .map_err(|_| corrupt("rename has no new container id"))?,
+                ),
+            },
+            KIND_TRUNCATE => WalRecord::TruncateContainer { container_id },
+            KIND_TRUNCATE_SEGMENT => WalRecord::TruncateSegment {
+                container_id,
+                segment: match payload {
+                    [segment] => *segment,
+                    _ => return Err(corrupt("segment truncation has no segment")),
+                },
+            },
+            KIND_CONFIG => WalRecord::SetConfig {
+                container_id,
+                config: serde_json::from_slice(payload)
+                    .map_err(|_| corrupt("change of options has an invalid config"))?,
+            },
+            kind => return Err(corrupt(&format!("unknown entry kind {}", kind))),
+        };
+        Ok(WalEntry {
+            lsn,
+            timestamp,
+            record,
+        })
+    }
+}
+
+fn corrupt(reason: &str) -> CrustyError {
+    CrustyError::CrustyError(format!("Corrupt WAL segment: {}", reason))
+}
+
+fn micros_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_micros() as u64
+}
+
+/// Path of the segment starting with the entry `first_lsn` in dir.
+fn segment_path(dir: &Path, first_lsn: Lsn) -> PathBuf {
+    dir.join(format!("{:020}.{}", first_lsn, SEGMENT_EXTENSION))
+}
+
+/// Returns the segment files in dir, in LSN order.
+pub(crate) fn segments(dir: &Path) -> Result<Vec<PathBuf>, CrustyError> {
+    let mut paths = Vec::new();
+    if !dir.exists() {
+        return Ok(paths);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) == Some(SEGMENT_EXTENSION) {
+            paths.push(path);
+        }
+    }
+    // names are zero padded LSNs, so they sort in LSN order
+    paths.sort();
+    Ok(paths)
+}
+
+/// Reads the entries of a segment file. An entry cut short at the end of the file, as left
+/// by a crash while it was written, is ignored.
+pub(crate) fn read_segment(path: &Path) -> Result<Vec<WalEntry>, CrustyError> {
+    read_entries(&fs::read(path)?).map(|(entries, _)| entries)
+}
+
+/// Reads an entry serialized as in a segment, with its length, e.g. one received from
+/// `Wal::subscribe`.
+pub(crate) fn read_entry(bytes: &[u8]) -> Result<WalEntry, CrustyError> {
+    match read_entries(bytes)? {
+        (mut entries, len) if entries.len() == 1 && len == bytes.len() => Ok(entries.remove(0)),
+        _ => Err(corrupt("expected a single whole entry")),
+    }
+}
+
+/// Reads the entries of the bytes of a segment, returning them and the number of bytes of
+/// the whole entries.
+fn read_entries(bytes: &[u8]) -> Result<(Vec<WalEntry>, usize), CrustyError> {
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while offset + 4 <= bytes.len() {
+        let len = u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let end = offset + 4 + len;
+        if end > bytes.len() {
+            break;
+        }
+        entries.push(WalEntry::from_bytes(&bytes[offset + 4..end])?);
+        offset = end;
+    }
+    Ok((entries, offset))
+}
+
+/// Cuts an entry cut short at the end of a segment file off the file, so entries appended
+/// to the segment later are read after the whole ones. Returns the LSN of its last entry,
+/// None if it has no whole entry.
+fn truncate_segment(path: &Path) -> Result<Option<Lsn>, CrustyError> {
+    let bytes = fs::read(path)?;
+    let (entries, len) = read_entries(&bytes)?;
+    if len < bytes.len() {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(len as u64)?;
+        file.sync_all()?;
+    }
+    Ok(entries.iter().map(|e| e.lsn).max())
+}
+
+/// The segment being written.
+struct Segment {
+    file: File,
+    path: PathBuf,
+    len: u64,
+}
+
+struct WalState {
+    /// LSN of the last entry logged, 0 if none was.
+    last_lsn: Lsn,
+    /// The segment being written, opened when the first entry after a rotation is logged.
+    segment: Option<Segment>,
+    /// Channels the entries are sent to as they are logged, see `Wal::subscribe`.
+    subscribers: Vec<Sender<Vec<u8>>>,
+}
+
+/// Progress of the syncs made for commits.
+#[derive(Default)]
+struct GroupCommit {
+    /// The entries up to this LSN are on disk.
+    flushed_lsn: Lsn,
+    /// Whether a commit is waiting out the window or syncing.
+    flushing: bool,
+    /// Number of syncs made for commits.
+    syncs: u64,
+}
+
+/// The write-ahead log of a storage manager.
+pub(crate) struct Wal {
+    /// Directory of the segments not archived yet.
+    dir: PathBuf,
+    config: WalConfig,
+    state: Mutex<WalState>,
+    group: Mutex<GroupCommit>,
+    /// Notified when a sync for commits finishes.
+    synced: Condvar,
+}
+
+impl Wal {
+    /// Open the WAL in dir. Logging continues after the last entry in dir and in the
+    /// archive directory, in a new segment, and the segments left in dir are archived.
+    pub(crate) fn open(dir: PathBuf, config: WalConfig) -> Result<Self, CrustyError> {
+        fs::create_dir_all(&dir)?;
+        if let Some(archive_dir) = &config.archive_dir {
+            fs::create_dir_all(archive_dir)?;
+        }
+        let mut last_lsn = 0;
+        for segment_dir in std::iter::once(&dir).chain(&config.archive_dir) {
+            // a crash may have left the last segments without a whole entry
+            for segment in segments(segment_dir)?.iter().rev() {
+                if let Some(lsn) = truncate_segment(segment)? {
+                    last_lsn = last_lsn.max(lsn);
+                    break;
+                }
+            }
+        }
+        let wal = Wal {
+            dir,
+            config,
+            state: Mutex::new(WalState {
+                last_lsn,
+                segment: None,
+                subscribers: Vec::new(),
+            }),
+            group: Mutex::new(GroupCommit::default()),
+            synced: Condvar::new(),
+        };
+        for path in segments(&wal.dir)? {
+            wal.archive_segment(&path)?;
+        }
+        Ok(wal)
+    }
+
+    /// Returns the segments of the WAL, archived or not, in LSN order.
+    pub(crate) fn all_segments(&self) -> Result<Vec<PathBuf>, CrustyError> {
+        let mut paths = segments(&self.dir)?;
+        if let Some(archive_dir) = &self.config.archive_dir {
+            paths.extend(segments(archive_dir)?);
+        }
+        paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+        Ok(paths)
+    }
+
+    /// Returns the LSN of the last entry logged, 0 if none was.
+    pub(crate) fn last_lsn(&self) -> Lsn {
+        self.state.lock().unwrap().last_lsn
+    }
+
+    /// Log a change, returning the LSN of its entry.
+    pub(crate) fn append(&self, record: WalRecord) -> Result<Lsn, CrustyError> {
+        let mut state = self.state.lock().unwrap();
+        let entry = WalEntry {
+            lsn: state.last_lsn + 1,
+            timestamp: micros_since_epoch(SystemTime::now()),
+            record,
+        };
+        if state.segment.is_none() {
+            let path = segment_path(&self.dir, entry.lsn);
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            state.segment = Some(Segment { file, path, len: 0 });
+        }
+        let bytes = entry.to_bytes();
+        let segment = state.segment.as_mut().unwrap();
+        fault::write(FaultPoint::WalWrite, &bytes, |bytes| {
+            segment.file.write_all(bytes)
+        })?;
+        segment.len += bytes.len() as u64;
+        let full = segment.len >= self.config.segment_size;
+        state.last_lsn = entry.lsn;
+        // subscribers that dropped their receiver are forgotten
+        state
+            .subscribers
+            .retain(|subscriber| subscriber.send(bytes.clone()).is_ok());
+        if full {
+            self.rotate(&mut state)?;
+        }
+        Ok(entry.lsn)
+    }
+
+    /// Returns a channel receiving every entry logged from now on, serialized as in the
+    /// segments, in LSN order.
+    pub(crate) fn subscribe(&self) -> Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel();
+        self.state.lock().unwrap().subscribers.push(sender);
+        receiver
+    }
+
+    /// Sync the entries logged so far to disk.
+    pub(crate) fn flush(&self) -> Result<(), CrustyError> {
+        self.sync().map(|_| ())
+    }
+
+    /// Sync the current segment, returning the LSN of the last entry synced. Full segments
+    /// were synced when they were rotated.
+    fn sync(&self) -> Result<Lsn, CrustyError> {
+        let (lsn, file) = {
+            let state = self.state.lock().unwrap();
+            let file = match &state.segment {
+                Some(segment) => Some(segment.file.try_clone()?),
+                None => None,
+            };
+            (state.last_lsn, file)
+        };
+        // sync outside the lock so entries can be logged meanwhile
+        if let Some(file) = file {
+            fault::sync(FaultPoint::WalSync, || file.sync_all())?;
+        }
+        Ok(lsn)
+    }
+
+    /// Wait until the entries up to lsn are on disk, syncing them together with the ones
+    /// logged by concurrent callers; see the module documentation.
+    pub(crate) fn flush_to(&self, lsn: Lsn) -> Result<(), CrustyError> {
+        let mut group = self.group.lock().unwrap();
+        loop {
+            if group.flushed_lsn >= lsn {
+                return Ok(());
+            }
+            if group.flushing {
+                group = self.synced.wait(group).unwrap();
+                continue;
+            }
+            // lead the next sync
+            group.flushing = true;
+            drop(group);
+            if !self.config.group_commit_window.is_zero() {
+                thread::sleep(self.config.group_commit_window);
+            }
+            let synced = self.sync();
+            group = self.group.lock().unwrap();
+            group.flushing = false;
+            self.synced.notify_all();
+            let synced = synced?;
+            group.syncs += 1;
+            group.flushed_lsn = group.flushed_lsn.max(synced);
+        }
+    }
+
+    /// Returns the number of syncs made for commits.
+    #[allow(dead_code)]
+    pub(crate) fn commit_syncs(&self) -> u64 {
+        self.group.lock().unwrap().syncs
+    }
+
+    /// End the current segment, archiving it, so the next entry starts a new one.
+    pub(crate) fn archive(&self) -> Result<(), CrustyError> {
+        self.rotate(&mut self.state.lock().unwrap())
+    }
+
+    fn rotate(&self, state: &mut WalState) -> Result<(), CrustyError> {
+        let segment = match state.segment.take() {
+            Some(segment) => segment,
+            None => return Ok(()),
+        };
+        fault::sync(FaultPoint::WalSync, || segment.file.sync_all())?;
+        self.archive_segment(&segment.path)
+    }
+
+    /// Move a segment that is no longer written to the archive directory, if there is one.
+    fn archive_segment(&self, path: &Path) -> Result<(), CrustyError> {
+        if let Some(archive_dir) = &self.config.archive_dir {
+            let archived = archive_dir.join(path.file_name().unwrap());
+            fs::copy(path, &archived)?;
+            File::open(&archived)?.sync_all()?;
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the segments not archived yet, e.g. when the storage path is reset.
+    pub(crate) fn reset(&self) -> Result<(), CrustyError> {
+        let mut state = self.state.lock().unwrap();
+        state.segment = None;
+        for path in segments(&self.dir)? {
+            fs::remove_file(path)?;
+        }
+        fs::create_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::testutil::*;
+    use temp_testdir::TempDir;
+
+    fn page_record(container_id: ContainerId, byte: u8) -> WalRecord {
+        WalRecord::Page {
+            container_id,
+            page: vec![byte; 100],
+        }
+    }
+
+    #[test]
+    fn hs_wal_entry_bytes() {
+        let records = vec![
+            page_record(3, 7),
+            WalRecord::PageDelta {
+                container_id: 3,
+                delta: PageDelta {
+                    page_id: 2,
+                    runs: vec![(10, vec![1, 2, 3]), (4000, vec![9; 96])],
+                },
+            },
+            WalRecord::CreateContainer {
+                container_id: 4,
+                dependencies: vec![1, 2],
+                config: ContainerConfig::default(),
+            },
+            WalRecord::CreateContainer {
+                container_id: 4,
+                dependencies: vec![],
+                config: ContainerConfig {
+                    fill_factor: 70,
+                    ..Default::default()
+                },
+            },
+            WalRecord::RemoveContainer { container_id: 5 },
+            WalRecord::Commit { tid: 77 },
+            WalRecord::Load {
+                container_id: 6,
+                pages: vec![vec![1; PAGE_SIZE], vec![2; PAGE_SIZE]],
+            },
+            WalRecord::RenameContainer {
+                container_id: 7,
+                new_container_id: 300,
+            },
+            WalRecord::TruncateContainer { container_id: 8 },
+            WalRecord::TruncateSegment {
+                container_id: 8,
+                segment: 3,
+            },
+            WalRecord::SetConfig {
+                container_id: 9,
+                config: ContainerConfig {
+                    max_pages: Some(12),
+                    ..Default::default()
+                },
+            },
+        ];
+        for record in records {
+            let entry = WalEntry {
+                lsn: 42,
+                timestamp: 1234,
+                record,
+            };
+            let bytes = entry.to_bytes();
+            assert_eq!(entry, WalEntry::from_bytes(&bytes[4..]).unwrap());
+        }
+    }
+
+    #[test]
+    fn hs_wal_rotate_and_reopen() {
+        init();
+        let tdir = TempDir::new(gen_random_test_sm_dir(), true);
+        let dir = tdir.join("wal");
+        let config = WalConfig {
+            archive_dir: Some(tdir.join("archive")),
+            segment_size: 240,
+            ..Default::default()
+        };
+        let wal = Wal::open(dir.clone(), config.clone()).unwrap();
+        for i in 0..5 {
+            assert_eq!(i as Lsn + 1, wal.append(page_record(1, i)).unwrap());
+        }
+        // Entries are 123 bytes, so segments hold two and the last one is not archived yet
+        let archived = segments(&tdir.join("archive")).unwrap();
+        assert_eq!(2, archived.len());
+        assert_eq!(1, segments(&dir).unwrap().len());
+        let entries: Vec<WalEntry> = archived
+            .iter()
+            .chain(&segments(&dir).unwrap())
+            .flat_map(|s| read_segment(s).unwrap())
+            .collect();
+        assert_eq!(
+            vec![1, 2, 3, 4, 5],
+            entries.iter().map(|e| e.lsn).collect::<Vec<_>>()
+        );
+        assert_eq!(page_record(1, 3), entries[3].record);
+
+        wal.flush().unwrap();
+        drop(wal);
+        let wal = Wal::open(dir.clone(), config).unwrap();
+        assert_eq!(5, wal.last_lsn());
+        assert!(segments(&dir).unwrap().is_empty());
+        assert_eq!(6, wal.append(page_record(1, 5)).unwrap());
+        wal.archive().unwrap();
+        assert!(segments(&dir).unwrap().is_empty());
+        assert_eq!(4, segments(&tdir.join("archive")).unwrap().len());
+    }
+
+    #[test]
+    fn hs_wal_torn_entry() {
+        init();
+        let tdir = TempDir::new(gen_random_test_sm_dir(), true);
+        let wal = Wal::open(tdir.join("wal"), WalConfig::default()).unwrap();
+        wal.append(page_record(1, 1)).unwrap();
+        wal.append(page_record(1, 2)).unwrap();
+        wal.flush().unwrap();
+        let path = segments(&tdir.join("wal")).unwrap().remove(0);
+        let len = fs::metadata(&path).unwrap().len();
+        OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 10)
+            .unwrap();
+        assert_eq!(1, read_segment(&path).unwrap().len());
+    }
+
+    #[test]
+    fn hs_wal_group_commit() {
+        init();
+        let tdir = TempDir::new(gen_random_test_sm_dir(), true);
+        let config = WalConfig {
+            group_commit_window: Duration::from_millis(20),
+            ..Default::default()
+        };
+        let wal = Wal::open(tdir.join("wal"), config).unwrap();
+        // Concurrent commits share syncs
+        thread::scope(|s| {
+            for tid in 0..8 {
+                let wal = &wal;
+                s.spawn(move || {
+                    let lsn = wal.append(WalRecord::Commit { tid }).unwrap();
+                    wal.flush_to(lsn).unwrap();
+                });
+            }
+        });
+        let syncs = wal.commit_syncs();
+        assert!((1..8).contains(&syncs), "{} syncs", syncs);
+        // Entries already synced need no sync
+        wal.flush_to(8).unwrap();
+        assert_eq!(syncs, wal.commit_syncs());
+        let lsn = wal.append(WalRecord::Commit { tid: 8 }).unwrap();
+        wal.flush_to(lsn).unwrap();
+        assert_eq!(syncs + 1, wal.commit_syncs());
+    }
+
+    #[test]
+    fn hs_wal_recovery_target() {
+        let entry = WalEntry {
+            lsn: 10,
+            timestamp: 2_000_000,
+            record: WalRecord::RemoveContainer { container_id: 1 },
+        };
+        assert!(!entry.is_after(&RecoveryTarget::Lsn(10)));
+        assert!(entry.is_after(&RecoveryTarget::Lsn(9)));
+        let at = |secs| RecoveryTarget::Time(UNIX_EPOCH + Duration::from_secs(secs));
+        assert!(!entry.is_after(&at(2)));
+        assert!(entry.is_after(&at(1)));
+    }
+}
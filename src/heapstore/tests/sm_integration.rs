@@ -6,8 +6,16 @@ use std::fs;
 use common::prelude::*;
 use common::storage_trait::StorageTrait;
 use common::testutil::*;
+use common::traits::transaction_manager_trait::IsolationLevel;
+use common::ContainerConfig;
 use rand::{thread_rng, Rng};
+use sm::cdc::ChangeKind;
 use sm::storage_manager::StorageManager;
+use sm::wal::{RecoveryTarget, WalConfig};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 const RO: Permissions = Permissions::ReadOnly;
 
@@ -20,7 +28,7 @@ fn sm_inserts() {
         let vals1 = get_random_vec_of_byte_vec(i, 50, 100);
         let cid = i as ContainerId;
         sm.create_table(cid).unwrap();
-        sm.insert_values(cid, vals1.clone(), t);
+        sm.insert_values(cid, vals1.clone(), t).unwrap();
         let check_vals: Vec<Vec<u8>> = sm.get_iterator(cid, t, RO).map(|(a, _)| a).collect();
         assert!(
             compare_unordered_byte_vecs(&vals1, check_vals),
@@ -38,7 +46,7 @@ fn sm_insert_delete() {
     let mut vals1 = get_random_vec_of_byte_vec(100, 50, 100);
     let cid = 1;
     sm.create_table(cid).unwrap();
-    let mut val_ids = sm.insert_values(cid, vals1.clone(), t);
+    let mut val_ids = sm.insert_values(cid, vals1.clone(), t).unwrap();
     for _ in 0..10 {
         let idx_to_del = rng.gen_range(0..vals1.len());
         sm.delete_value(val_ids[idx_to_del], t).unwrap();
@@ -56,7 +64,7 @@ fn sm_no_container() {
     let sm = StorageManager::new_test_sm();
     let t = TransactionId::new();
     let vals1 = get_random_vec_of_byte_vec(100, 50, 100);
-    sm.insert_values(1, vals1, t);
+    sm.insert_values(1, vals1, t).unwrap();
 }
 
 #[test]
@@ -68,7 +76,7 @@ fn sm_test_shutdown() {
     let vals1 = get_random_vec_of_byte_vec(100, 50, 100);
     let cid = 1;
     sm.create_table(cid).unwrap();
-    let _val_ids = sm.insert_values(cid, vals1.clone(), t);
+    let _val_ids = sm.insert_values(cid, vals1.clone(), t).unwrap();
     sm.shutdown();
 
     let sm2 = StorageManager::new(path.clone());
@@ -79,3 +87,1349 @@ fn sm_test_shutdown() {
     fs::remove_dir_all(path).unwrap();
 }
 
+#[test]
+fn sm_catalog_recovery() {
+    let path = gen_random_test_sm_dir();
+    let t = TransactionId::new();
+    let vals1 = get_random_vec_of_byte_vec(100, 50, 100);
+    let cid = 1;
+    let sm = StorageManager::new(path.clone());
+    sm.create_table(cid).unwrap();
+    sm.insert_values(cid, vals1.clone(), t).unwrap();
+    sm.shutdown();
+    drop(sm);
+    let catalog = fs::read_to_string(path.join("c_map")).unwrap();
+    assert!(catalog.contains("\"version\":1"));
+    assert!(!path.join("c_map.tmp").exists());
+
+    // a truncated catalog is reported, and new opens the heap files instead
+    fs::write(path.join("c_map"), &catalog[..catalog.len() / 2]).unwrap();
+    assert!(StorageManager::open_read_only(path.clone()).is_err());
+    let sm = StorageManager::new(path.clone());
+    let check_vals: Vec<Vec<u8>> = sm.get_iterator(cid, t, RO).map(|(a, _)| a).collect();
+    assert!(compare_unordered_byte_vecs(&vals1, check_vals));
+    drop(sm);
+
+    // as is a catalog of another version, or not matching its checksum
+    fs::write(
+        path.join("c_map"),
+        catalog.replace("\"version\":1", "\"version\":9"),
+    )
+    .unwrap();
+    let e = StorageManager::open_read_only(path.clone()).err().unwrap();
+    assert!(e.to_string().contains("version 9"));
+    fs::write(
+        path.join("c_map"),
+        catalog.replace("\"container_ids\":[1]", "\"container_ids\":[2]"),
+    )
+    .unwrap();
+    assert!(StorageManager::open_read_only(path.clone()).is_err());
+
+    // the catalog of earlier versions is still read
+    fs::write(path.join("c_map"), "[1,1]").unwrap();
+    let ro = StorageManager::open_read_only(path.clone()).unwrap();
+    assert_eq!(vals1.len(), ro.get_iterator(cid, t, RO).count());
+    drop(ro);
+    fs::write(path.join("c_map"), "[2,1]").unwrap();
+    assert!(StorageManager::open_read_only(path.clone()).is_err());
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_container_config_persisted() {
+    let path = gen_random_test_sm_dir();
+    let t = TransactionId::new();
+    let config = ContainerConfig {
+        fill_factor: 40,
+        compression: false,
+        ..Default::default()
+    };
+    let sm = StorageManager::new(path.clone());
+    sm.create_container_with_config(1, config, None).unwrap();
+    sm.create_table(2).unwrap();
+    sm.shutdown();
+    drop(sm);
+
+    let sm = StorageManager::new(path.clone());
+    assert_eq!(config, sm.container_config(1).unwrap());
+    assert_eq!(ContainerConfig::default(), sm.container_config(2).unwrap());
+    sm.insert_values(1, get_random_vec_of_byte_vec(100, 50, 100), t)
+        .unwrap();
+    let ro = StorageManager::open_read_only(path.clone()).unwrap();
+    assert_eq!(config, ro.container_config(1).unwrap());
+    drop(ro);
+
+    // a container created after the last shutdown gets its options from the WAL
+    let sm = sm.with_wal(WalConfig::default()).unwrap();
+    sm.create_container_with_config(3, config, None).unwrap();
+    drop(sm);
+    let sm = StorageManager::new(path.clone())
+        .with_wal(WalConfig::default())
+        .unwrap();
+    assert_eq!(config, sm.container_config(3).unwrap());
+
+    // and so do the options changed since
+    let quota = ContainerConfig {
+        max_pages: Some(1),
+        ..config
+    };
+    sm.set_container_config(1, quota).unwrap();
+    drop(sm);
+    let sm = StorageManager::new(path.clone())
+        .with_wal(WalConfig::default())
+        .unwrap();
+    assert_eq!(quota, sm.container_config(1).unwrap());
+    sm.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_read_only() {
+    let path = gen_random_test_sm_dir();
+    let t = TransactionId::new();
+    let vals1 = get_random_vec_of_byte_vec(100, 50, 100);
+    let cid = 1;
+    let sm = StorageManager::new(path.clone());
+    sm.create_table(cid).unwrap();
+    let val_ids = sm.insert_values(cid, vals1.clone(), t).unwrap();
+    sm.shutdown();
+    drop(sm);
+
+    let ro = StorageManager::open_read_only(path.clone()).unwrap();
+    assert!(ro.is_read_only());
+    let check_vals: Vec<Vec<u8>> = ro.get_iterator(cid, t, RO).map(|(a, _)| a).collect();
+    assert!(compare_unordered_byte_vecs(&vals1, check_vals));
+    assert_eq!(vals1[3], ro.get_value(val_ids[3], t, RO).unwrap());
+
+    // Mutations fail and leave the files as they were
+    assert!(ro.delete_value(val_ids[0], t).is_err());
+    assert!(ro.update_value(vec![1], val_ids[0], t).is_err());
+    assert!(ro.create_table(2).is_err());
+    assert!(ro.remove_container(cid).is_err());
+    assert!(ro.reset().is_err());
+    assert!(!path.join("c2").exists());
+    ro.shutdown();
+    drop(ro);
+
+    let sm2 = StorageManager::new(path.clone());
+    assert_eq!(vals1.len(), sm2.get_iterator(cid, t, RO).count());
+    sm2.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_read_only_insert() {
+    let path = gen_random_test_sm_dir();
+    let sm = StorageManager::new(path.clone());
+    sm.create_table(1).unwrap();
+    sm.shutdown();
+    let ro = StorageManager::open_read_only(path.clone()).unwrap();
+    // insert_value cannot return an error, so it panics
+    let insert = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        ro.insert_value(1, vec![1], TransactionId::new()).unwrap()
+    }));
+    assert!(insert.is_err());
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_read_only_missing_dir() {
+    let path = gen_random_test_sm_dir();
+    assert!(StorageManager::open_read_only(path.clone()).is_err());
+    assert!(!path.exists());
+}
+#[test]
+fn sm_backup_restore() {
+    let sm = Arc::new(StorageManager::new_test_sm());
+    let t = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let vals = get_random_vec_of_byte_vec(600, 50, 100);
+    sm.insert_values(cid, vals[..200].to_vec(), t).unwrap();
+
+    // Back up while the remaining values are inserted
+    let writer = {
+        let sm = sm.clone();
+        let rest = vals[200..].to_vec();
+        thread::spawn(move || {
+            for val in rest {
+                sm.insert_value(cid, val, t).unwrap();
+            }
+        })
+    };
+    while sm.get_iterator(cid, t, RO).count() < 300 {
+        thread::yield_now();
+    }
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+    writer.join().unwrap();
+    assert!(sm.backup(&backup_path).is_err());
+
+    // The backup holds the values inserted before it and some of the concurrent ones
+    let restored_sm = StorageManager::new_test_sm();
+    restored_sm.restore(&backup_path).unwrap();
+    let restored: HashSet<Vec<u8>> = restored_sm
+        .get_iterator(cid, t, RO)
+        .map(|(a, _)| a)
+        .collect();
+    let all: HashSet<Vec<u8>> = vals.iter().cloned().collect();
+    assert!(restored.is_subset(&all));
+    assert!(vals[..200].iter().all(|v| restored.contains(v)));
+
+    // Restoring the original drops the values inserted since the backup
+    sm.restore(&backup_path).unwrap();
+    let current: HashSet<Vec<u8>> = sm.get_iterator(cid, t, RO).map(|(a, _)| a).collect();
+    assert_eq!(restored, current);
+    sm.insert_value(cid, vec![1; 10], t).unwrap();
+    assert_eq!(restored.len() + 1, sm.get_iterator(cid, t, RO).count());
+    fs::remove_dir_all(backup_path).unwrap();
+}
+
+#[test]
+fn sm_backup_concurrent_updates() {
+    let sm = Arc::new(StorageManager::new_test_sm());
+    let t = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let ids = sm
+        .insert_values(cid, get_random_vec_of_byte_vec(200, 50, 50), t)
+        .unwrap();
+
+    // Updates growing values move them to other pages, deleting them before inserting them
+    let writer = {
+        let sm = sm.clone();
+        thread::spawn(move || {
+            let mut ids = ids;
+            for i in 0..2000 {
+                let slot = i % ids.len();
+                ids[slot] = sm
+                    .update_value(get_random_byte_vec(50 + i % 50), ids[slot], t)
+                    .unwrap();
+            }
+        })
+    };
+    // Every backup holds each update whole or not at all
+    while !writer.is_finished() {
+        let backup_path = gen_random_test_sm_dir();
+        sm.backup(&backup_path).unwrap();
+        let restored_sm = StorageManager::new_test_sm();
+        restored_sm.restore(&backup_path).unwrap();
+        assert_eq!(200, restored_sm.get_iterator(cid, t, RO).count());
+        fs::remove_dir_all(backup_path).unwrap();
+    }
+    writer.join().unwrap();
+}
+
+#[test]
+fn sm_restore_missing_backup() {
+    let sm = StorageManager::new_test_sm();
+    let t = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.insert_value(1, vec![1, 2, 3], t).unwrap();
+    assert!(sm.restore(&gen_random_test_sm_dir()).is_err());
+    assert_eq!(1, sm.get_iterator(1, t, RO).count());
+}
+
+fn values(sm: &StorageManager, container_id: ContainerId) -> HashSet<Vec<u8>> {
+    sm.get_iterator(container_id, TransactionId::new(), RO)
+        .map(|(a, _)| a)
+        .collect()
+}
+
+#[test]
+fn sm_point_in_time_recovery() {
+    let archive_dir = gen_random_test_sm_dir();
+    let config = WalConfig {
+        archive_dir: Some(archive_dir.clone()),
+        segment_size: 4 * common::PAGE_SIZE as u64,
+        ..Default::default()
+    };
+    let sm = StorageManager::new_test_sm().with_wal(config).unwrap();
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(300, 50, 100);
+    sm.create_table(1).unwrap();
+    sm.insert_values(1, vals[..100].to_vec(), t).unwrap();
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+
+    // Changes after the backup, in two steps
+    sm.insert_values(1, vals[100..200].to_vec(), t).unwrap();
+    sm.create_table(2).unwrap();
+    sm.insert_values(2, vals[..10].to_vec(), t).unwrap();
+    let lsn = sm.wal_lsn().unwrap();
+    thread::sleep(Duration::from_millis(10));
+    let time = SystemTime::now();
+    thread::sleep(Duration::from_millis(10));
+    sm.insert_values(1, vals[200..].to_vec(), t).unwrap();
+    sm.remove_container(2).unwrap();
+    sm.archive_wal().unwrap();
+
+    let restored = StorageManager::new_test_sm();
+    assert!(restored
+        .recover_to(&archive_dir, RecoveryTarget::Lsn(lsn))
+        .is_err());
+    restored.restore(&backup_path).unwrap();
+    assert_eq!(values(&sm, 1).len(), 300);
+    assert_eq!(100, values(&restored, 1).len());
+
+    // Roll forward to the end of the first step, by LSN and then by time
+    assert_eq!(
+        lsn,
+        restored
+            .recover_to(&archive_dir, RecoveryTarget::Lsn(lsn))
+            .unwrap()
+    );
+    let expected: HashSet<Vec<u8>> = vals[..200].iter().cloned().collect();
+    assert_eq!(expected, values(&restored, 1));
+    assert_eq!(10, values(&restored, 2).len());
+    assert_eq!(
+        lsn,
+        restored
+            .recover_to(&archive_dir, RecoveryTarget::Time(time))
+            .unwrap()
+    );
+
+    // Roll forward to the last change archived
+    assert_eq!(
+        sm.wal_lsn().unwrap(),
+        restored
+            .recover_to(&archive_dir, RecoveryTarget::Lsn(u64::MAX))
+            .unwrap()
+    );
+    assert_eq!(values(&sm, 1), values(&restored, 1));
+    assert!(restored
+        .create_container(3, None, StateType::BaseTable, Some(vec![2]))
+        .is_err());
+    fs::remove_dir_all(backup_path).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+}
+
+#[test]
+fn sm_bulk_load_recovery() {
+    let archive_dir = gen_random_test_sm_dir();
+    let config = WalConfig {
+        archive_dir: Some(archive_dir.clone()),
+        ..Default::default()
+    };
+    let sm = StorageManager::new_test_sm().with_wal(config).unwrap();
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(1000, 50, 100);
+    sm.create_table(1).unwrap();
+    sm.insert_values(1, vals[..10].to_vec(), t).unwrap();
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+
+    // The whole load is one WAL entry
+    let lsn = sm.wal_lsn().unwrap();
+    let ids = sm.bulk_load(1, vals[10..].iter().cloned(), t).unwrap();
+    assert_eq!(lsn + 1, sm.wal_lsn().unwrap());
+    assert!(ids.iter().all(|id| id.page_id.unwrap() > 0));
+    sm.archive_wal().unwrap();
+
+    let restored = StorageManager::new_test_sm();
+    restored.restore(&backup_path).unwrap();
+    restored
+        .recover_to(&archive_dir, RecoveryTarget::Lsn(u64::MAX))
+        .unwrap();
+    let expected: HashSet<Vec<u8>> = vals.iter().cloned().collect();
+    assert_eq!(expected, values(&restored, 1));
+    fs::remove_dir_all(backup_path).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+}
+
+#[test]
+fn sm_page_delta_recovery() {
+    let archive_dir = gen_random_test_sm_dir();
+    let config = WalConfig {
+        archive_dir: Some(archive_dir.clone()),
+        ..Default::default()
+    };
+    let sm = StorageManager::new_test_sm().with_wal(config).unwrap();
+    let t = TransactionId::new();
+    sm.create_table(1).unwrap();
+    let mut ids = sm
+        .insert_values(1, get_random_vec_of_byte_vec(20, 50, 50), t)
+        .unwrap();
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+
+    // the page was logged in full, so the updates are logged as the bytes they change
+    for i in 0..100 {
+        let slot = i % ids.len();
+        ids[slot] = sm
+            .update_value(get_random_byte_vec(50), ids[slot], t)
+            .unwrap();
+    }
+    sm.archive_wal().unwrap();
+    let logged: u64 = fs::read_dir(&archive_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().metadata().unwrap().len())
+        .sum();
+    assert!(logged < 10 * common::PAGE_SIZE as u64);
+
+    let restored = StorageManager::new_test_sm();
+    restored.restore(&backup_path).unwrap();
+    restored
+        .recover_to(&archive_dir, RecoveryTarget::Lsn(u64::MAX))
+        .unwrap();
+    assert_eq!(values(&sm, 1), values(&restored, 1));
+    fs::remove_dir_all(backup_path).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+}
+
+#[test]
+fn sm_recover_without_wal_backup() {
+    let sm = StorageManager::new_test_sm();
+    let t = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.insert_value(1, vec![1, 2, 3], t).unwrap();
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+    sm.restore(&backup_path).unwrap();
+    assert!(sm
+        .recover_to(&gen_random_test_sm_dir(), RecoveryTarget::Lsn(1))
+        .is_err());
+    assert!(sm.archive_wal().is_err());
+    fs::remove_dir_all(backup_path).unwrap();
+}
+
+#[test]
+fn sm_mvcc_snapshot_reads() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    let vals = get_random_vec_of_byte_vec(20, 10, 50);
+    let loader = TransactionId::new();
+    sm.create_table(1).unwrap();
+    let ids = sm.insert_values(1, vals[..10].to_vec(), loader).unwrap();
+    sm.commit_transaction(loader).unwrap();
+
+    // The reader's snapshot is taken before the writer changes anything
+    let reader = TransactionId::new();
+    assert_eq!(10, sm.get_iterator(1, reader, RO).count());
+    let writer = TransactionId::new();
+    for id in &ids[..3] {
+        sm.delete_value(*id, writer).unwrap();
+    }
+    let updated = sm.update_value(vals[10].clone(), ids[3], writer).unwrap();
+    sm.insert_values(1, vals[11..15].to_vec(), writer).unwrap();
+
+    let original: HashSet<Vec<u8>> = vals[..10].iter().cloned().collect();
+    let mut changed: HashSet<Vec<u8>> = vals[3..15].iter().cloned().collect();
+    changed.remove(&vals[3]);
+    let read = |tid| -> HashSet<Vec<u8>> { sm.get_iterator(1, tid, RO).map(|(a, _)| a).collect() };
+    assert_eq!(original, read(reader));
+    assert_eq!(changed, read(writer));
+    assert_eq!(vals[10], sm.get_value(updated, writer, RO).unwrap());
+    assert!(sm.get_value(updated, reader, RO).is_err());
+
+    // Committing does not change what the reader sees, but later readers see the changes
+    sm.commit_transaction(writer).unwrap();
+    assert_eq!(original, read(reader));
+    assert_eq!(vals[0], sm.get_value(ids[0], reader, RO).unwrap());
+    let later = TransactionId::new();
+    assert_eq!(changed, read(later));
+    assert!(sm.get_value(ids[0], later, RO).is_err());
+    assert!(sm.commit_transaction(writer).is_err());
+}
+
+#[test]
+fn sm_mvcc_write_conflict() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    let loader = TransactionId::new();
+    sm.create_table(1).unwrap();
+    let id = sm.insert_value(1, vec![1; 10], loader).unwrap();
+    sm.commit_transaction(loader).unwrap();
+
+    // The first transaction to update a value wins
+    let (first, second) = (TransactionId::new(), TransactionId::new());
+    sm.update_value(vec![2; 10], id, first).unwrap();
+    assert!(matches!(
+        sm.update_value(vec![3; 10], id, second),
+        Err(CrustyError::TransactionRollback(_))
+    ));
+    // An aborted update is discarded and no longer conflicts
+    sm.abort_transaction(first).unwrap();
+    let id = sm.update_value(vec![3; 10], id, second).unwrap();
+    sm.commit_transaction(second).unwrap();
+    let reader = TransactionId::new();
+    let values: Vec<Vec<u8>> = sm.get_iterator(1, reader, RO).map(|(a, _)| a).collect();
+    assert_eq!(vec![vec![3; 10]], values);
+
+    // So does an update committed after the snapshot of the updater
+    let (late, early) = (TransactionId::new(), TransactionId::new());
+    assert_eq!(1, sm.get_iterator(1, early, RO).count());
+    sm.delete_value(id, late).unwrap();
+    sm.commit_transaction(late).unwrap();
+    assert!(matches!(
+        sm.delete_value(id, early),
+        Err(CrustyError::TransactionRollback(_))
+    ));
+}
+
+#[test]
+fn sm_mvcc_vacuum() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    let vals = get_random_vec_of_byte_vec(10, 10, 50);
+    let loader = TransactionId::new();
+    sm.create_table(1).unwrap();
+    let ids = sm.insert_values(1, vals.clone(), loader).unwrap();
+    sm.commit_transaction(loader).unwrap();
+
+    let reader = TransactionId::new();
+    assert_eq!(10, sm.get_iterator(1, reader, RO).count());
+    let (deleter, aborted) = (TransactionId::new(), TransactionId::new());
+    for id in &ids[..4] {
+        sm.delete_value(*id, deleter).unwrap();
+    }
+    sm.commit_transaction(deleter).unwrap();
+    sm.insert_values(1, vals[..2].to_vec(), aborted).unwrap();
+    sm.delete_value(ids[9], aborted).unwrap();
+    sm.abort_transaction(aborted).unwrap();
+
+    // The reader can still see the deleted versions, so only the aborted inserts go
+    assert_eq!(2, sm.vacuum(1).unwrap());
+    assert_eq!(10, sm.get_iterator(1, reader, RO).count());
+    sm.commit_transaction(reader).unwrap();
+    assert_eq!(4, sm.vacuum(1).unwrap());
+    assert_eq!(0, sm.vacuum(1).unwrap());
+    let current: HashSet<Vec<u8>> = sm
+        .get_iterator(1, TransactionId::new(), RO)
+        .map(|(a, _)| a)
+        .collect();
+    assert_eq!(vals[4..].iter().cloned().collect::<HashSet<_>>(), current);
+    assert!(StorageManager::new_test_sm().vacuum(1).is_err());
+}
+
+#[test]
+fn sm_container_ttl() {
+    let ttl = ContainerConfig {
+        ttl: Some(Duration::from_millis(300)),
+        ..Default::default()
+    };
+    let zero = ContainerConfig {
+        ttl: Some(Duration::ZERO),
+        ..Default::default()
+    };
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(10, 10, 50);
+    for sm in [
+        StorageManager::new_test_sm(),
+        StorageManager::new_test_sm().with_mvcc(),
+    ] {
+        assert!(sm.create_container_with_config(1, zero, None).is_err());
+        sm.create_container_with_config(1, ttl, None).unwrap();
+        let ids = sm.insert_values(1, vals[..5].to_vec(), t).unwrap();
+        sm.commit_transaction(t).ok();
+        let reader = TransactionId::new();
+        assert_eq!(vals[0], sm.get_value(ids[0], reader, RO).unwrap());
+        assert_eq!(5, sm.get_iterator(1, reader, RO).count());
+        sm.commit_transaction(reader).ok();
+        assert_eq!(0, sm.vacuum(1).unwrap());
+
+        // The time-to-live can be changed, but not removed
+        assert!(sm
+            .set_container_config(1, ContainerConfig::default())
+            .is_err());
+        assert!(sm.set_container_config(1, zero).is_err());
+        sm.set_container_config(1, ttl).unwrap();
+
+        // Expired values are no longer read, and vacuum reclaims them
+        thread::sleep(Duration::from_millis(400));
+        let writer = TransactionId::new();
+        let fresh = sm.insert_values(1, vals[5..].to_vec(), writer).unwrap();
+        sm.commit_transaction(writer).ok();
+        let reader = TransactionId::new();
+        assert!(sm.get_value(ids[0], reader, RO).is_err());
+        assert_eq!(vals[5], sm.get_value(fresh[0], reader, RO).unwrap());
+        let current: HashSet<Vec<u8>> = sm.get_iterator(1, reader, RO).map(|(a, _)| a).collect();
+        assert_eq!(vals[5..].iter().cloned().collect::<HashSet<_>>(), current);
+        assert_eq!(5, sm.vacuum(1).unwrap());
+        assert_eq!(0, sm.vacuum(1).unwrap());
+        assert_eq!(5, sm.get_iterator(1, reader, RO).count());
+    }
+}
+
+#[test]
+fn sm_soft_delete_undelete() {
+    let retained = ContainerConfig {
+        retention: Some(Duration::from_millis(300)),
+        ..Default::default()
+    };
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(10, 10, 50);
+    let sm = StorageManager::new_test_sm();
+    sm.create_table(2).unwrap();
+    let id = sm.insert_value(2, vals[0].clone(), t).unwrap();
+    sm.delete_value(id, t).unwrap();
+    assert!(sm.undelete(id, t).is_err());
+    assert!(sm
+        .create_container_with_config(
+            1,
+            ContainerConfig {
+                retention: Some(Duration::ZERO),
+                ..Default::default()
+            },
+            None
+        )
+        .is_err());
+
+    sm.create_container_with_config(1, retained, None).unwrap();
+    sm.subscribe_changes(1).unwrap();
+    let ids = sm.insert_values(1, vals[..5].to_vec(), t).unwrap();
+    sm.delete_values(ids[..3].to_vec(), t).unwrap();
+    sm.delete_value(ids[0], t).unwrap();
+    assert!(sm.get_value(ids[0], t, RO).is_err());
+    assert_eq!(2, sm.get_iterator(1, t, RO).count());
+    assert!(sm.undelete(ids[3], t).is_err());
+    assert!(sm
+        .set_container_config(1, ContainerConfig::default())
+        .is_err());
+
+    // Deleted values are kept until the retention window passes
+    assert_eq!(0, sm.vacuum(1).unwrap());
+    assert_eq!(ids[0], sm.undelete(ids[0], t).unwrap());
+    assert_eq!(vals[0], sm.get_value(ids[0], t, RO).unwrap());
+    assert_eq!(3, sm.get_iterator(1, t, RO).count());
+    let events = sm.read_changes(1, 1, 20).unwrap();
+    let kinds: Vec<ChangeKind> = events.iter().map(|e| e.kind).collect();
+    let mut expected = vec![ChangeKind::Insert; 5];
+    expected.extend([ChangeKind::Delete; 3]);
+    expected.push(ChangeKind::Insert);
+    assert_eq!(expected, kinds);
+    assert_eq!(Some((ids[0], vals[0].clone())), events[8].after);
+
+    thread::sleep(Duration::from_millis(400));
+    assert!(sm.undelete(ids[1], t).is_err());
+    assert_eq!(2, sm.vacuum(1).unwrap());
+    assert!(sm.undelete(ids[1], t).is_err());
+    assert_eq!(3, sm.get_iterator(1, t, RO).count());
+
+    // With MVCC, the restored value is a new version
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    sm.create_container_with_config(1, retained, None).unwrap();
+    let ids = sm.insert_values(1, vals[..2].to_vec(), t).unwrap();
+    sm.commit_transaction(t).unwrap();
+    let (deleter, reader) = (TransactionId::new(), TransactionId::new());
+    assert_eq!(2, sm.get_iterator(1, reader, RO).count());
+    sm.delete_value(ids[0], deleter).unwrap();
+    sm.commit_transaction(deleter).unwrap();
+    let restorer = TransactionId::new();
+    assert!(sm.undelete(ids[1], restorer).is_err());
+    let restored = sm.undelete(ids[0], restorer).unwrap();
+    assert_ne!(ids[0], restored);
+    // the reader began before the delete and still sees the value, so it cannot restore it
+    assert!(sm.undelete(ids[0], reader).is_err());
+    sm.commit_transaction(restorer).unwrap();
+    assert_eq!(2, sm.get_iterator(1, reader, RO).count());
+    assert_eq!(
+        vals[0],
+        sm.get_value(restored, TransactionId::new(), RO).unwrap()
+    );
+}
+
+/// Reads the values of container 1 as tid.
+fn read_all(sm: &StorageManager, tid: TransactionId) -> HashSet<Vec<u8>> {
+    sm.get_iterator(1, tid, RO).map(|(a, _)| a).collect()
+}
+
+#[test]
+fn sm_isolation_non_repeatable_read() {
+    for level in [
+        IsolationLevel::ReadCommitted,
+        IsolationLevel::RepeatableRead,
+    ] {
+        let sm = StorageManager::new_test_sm().with_mvcc();
+        let loader = TransactionId::new();
+        sm.create_table(1).unwrap();
+        let id = sm.insert_value(1, vec![1; 10], loader).unwrap();
+        sm.commit_transaction(loader).unwrap();
+
+        let reader = TransactionId::new();
+        sm.begin_transaction(reader, level).unwrap();
+        assert!(sm.begin_transaction(reader, level).is_err());
+        assert_eq!(vec![1; 10], sm.get_value(id, reader, RO).unwrap());
+        let writer = TransactionId::new();
+        sm.update_value(vec![2; 10], id, writer).unwrap();
+        // Uncommitted changes are never seen
+        assert_eq!(HashSet::from([vec![1; 10]]), read_all(&sm, reader));
+        sm.commit_transaction(writer).unwrap();
+
+        // Only ReadCommitted sees the committed update when reading again
+        let expected = match level {
+            IsolationLevel::ReadCommitted => vec![2; 10],
+            _ => vec![1; 10],
+        };
+        assert_eq!(HashSet::from([expected]), read_all(&sm, reader));
+        sm.commit_transaction(reader).unwrap();
+    }
+}
+
+#[test]
+fn sm_isolation_write_skew() {
+    for level in [IsolationLevel::RepeatableRead, IsolationLevel::Serializable] {
+        // Two values, and each transaction deletes one if it sees both
+        let sm = StorageManager::new_test_sm().with_mvcc();
+        let loader = TransactionId::new();
+        sm.create_table(1).unwrap();
+        let ids = sm
+            .insert_values(1, vec![vec![1; 10], vec![2; 10]], loader)
+            .unwrap();
+        sm.commit_transaction(loader).unwrap();
+
+        let (t1, t2) = (TransactionId::new(), TransactionId::new());
+        sm.begin_transaction(t1, level).unwrap();
+        sm.begin_transaction(t2, level).unwrap();
+        assert_eq!(2, read_all(&sm, t1).len());
+        assert_eq!(2, read_all(&sm, t2).len());
+        sm.delete_value(ids[0], t1).unwrap();
+        sm.delete_value(ids[1], t2).unwrap();
+        sm.commit_transaction(t1).unwrap();
+
+        let check = TransactionId::new();
+        match level {
+            // Snapshot isolation lets both commit, leaving no values
+            IsolationLevel::RepeatableRead => {
+                sm.commit_transaction(t2).unwrap();
+                assert!(read_all(&sm, check).is_empty());
+            }
+            // Each read the container the other wrote, t1 -> t2 -> t1, and t1 committed first
+            _ => {
+                assert!(matches!(
+                    sm.commit_transaction(t2),
+                    Err(CrustyError::TransactionRollback(_))
+                ));
+                assert_eq!(HashSet::from([vec![2; 10]]), read_all(&sm, check));
+            }
+        }
+    }
+}
+
+#[test]
+fn sm_isolation_serializable_disjoint() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    let loader = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.create_table(2).unwrap();
+    sm.insert_value(1, vec![1; 10], loader).unwrap();
+    sm.commit_transaction(loader).unwrap();
+
+    // Writes to containers a Serializable transaction did not read do not conflict
+    let (reader, writer) = (TransactionId::new(), TransactionId::new());
+    sm.begin_transaction(reader, IsolationLevel::Serializable)
+        .unwrap();
+    assert_eq!(1, read_all(&sm, reader).len());
+    sm.insert_value(2, vec![2; 10], writer).unwrap();
+    sm.commit_transaction(writer).unwrap();
+    sm.insert_value(1, vec![3; 10], reader).unwrap();
+    sm.commit_transaction(reader).unwrap();
+    assert_eq!(2, read_all(&sm, TransactionId::new()).len());
+}
+
+#[test]
+fn sm_isolation_serializable_single_conflict() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    let loader = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.create_table(2).unwrap();
+    sm.insert_value(1, vec![1; 10], loader).unwrap();
+    sm.commit_transaction(loader).unwrap();
+
+    // The reader misses the writer's change, which is serializable with the reader first
+    let (reader, writer) = (TransactionId::new(), TransactionId::new());
+    sm.begin_transaction(reader, IsolationLevel::Serializable)
+        .unwrap();
+    sm.begin_transaction(writer, IsolationLevel::Serializable)
+        .unwrap();
+    assert_eq!(1, read_all(&sm, reader).len());
+    sm.insert_value(1, vec![2; 10], writer).unwrap();
+    sm.commit_transaction(writer).unwrap();
+    assert_eq!(1, read_all(&sm, reader).len());
+    sm.insert_value(2, vec![3; 10], reader).unwrap();
+    sm.commit_transaction(reader).unwrap();
+}
+
+#[test]
+fn sm_group_commit_recovery() {
+    let archive_dir = gen_random_test_sm_dir();
+    let config = WalConfig {
+        archive_dir: Some(archive_dir.clone()),
+        group_commit_window: Duration::from_millis(5),
+        ..Default::default()
+    };
+    let sm = StorageManager::new_test_sm()
+        .with_wal(config)
+        .unwrap()
+        .with_mvcc();
+    sm.create_table(1).unwrap();
+    let backup_path = gen_random_test_sm_dir();
+    sm.backup(&backup_path).unwrap();
+
+    // Commits from several threads are logged, and replayed as no-ops
+    let tids: Vec<TransactionId> = (0..4)
+        .map(|i| {
+            let tid = TransactionId::new();
+            sm.insert_value(1, vec![i; 10], tid).unwrap();
+            tid
+        })
+        .collect();
+    thread::scope(|s| {
+        for tid in tids {
+            let sm = &sm;
+            s.spawn(move || sm.commit_transaction(tid).unwrap());
+        }
+    });
+    // A read-only transaction logs nothing
+    let lsn = sm.wal_lsn().unwrap();
+    let reader = TransactionId::new();
+    assert_eq!(4, read_all(&sm, reader).len());
+    sm.commit_transaction(reader).unwrap();
+    assert_eq!(lsn, sm.wal_lsn().unwrap());
+    sm.archive_wal().unwrap();
+
+    let restored = StorageManager::new_test_sm().with_mvcc();
+    restored.restore(&backup_path).unwrap();
+    restored
+        .recover_to(&archive_dir, RecoveryTarget::Lsn(lsn))
+        .unwrap();
+    assert_eq!(
+        read_all(&sm, TransactionId::new()),
+        read_all(&restored, TransactionId::new())
+    );
+    fs::remove_dir_all(backup_path).unwrap();
+    fs::remove_dir_all(archive_dir).unwrap();
+}
+
+#[test]
+fn sm_concurrent_inserts() {
+    let sm = StorageManager::new_test_sm();
+    sm.create_table(1).unwrap();
+    let threads: u8 = 8;
+    let per_thread: u8 = 100;
+    let expected: HashSet<Vec<u8>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..threads)
+            .map(|t| {
+                let sm = &sm;
+                s.spawn(move || {
+                    let tid = TransactionId::new();
+                    let mut kept = HashSet::new();
+                    for i in 0..per_thread {
+                        let value = vec![t, i, (i as usize % 40) as u8 + 10];
+                        let id = sm.insert_value(1, value.clone(), tid).unwrap();
+                        // half the threads also delete some of their own records
+                        if t % 2 == 0 && i % 3 == 0 {
+                            sm.delete_value(id, tid).unwrap();
+                        } else {
+                            kept.insert(value);
+                        }
+                    }
+                    kept
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    });
+    let tid = TransactionId::new();
+    let found: Vec<Vec<u8>> = sm
+        .get_iterator(1, tid, Permissions::ReadOnly)
+        .map(|(value, _)| value)
+        .collect();
+    assert_eq!(expected.len(), found.len());
+    assert_eq!(expected, found.into_iter().collect::<HashSet<_>>());
+}
+
+mod conformance {
+    common::storage_conformance_tests!(heapstore::storage_manager::StorageManager);
+}
+
+#[test]
+fn sm_hash_index() {
+    use common::hash_index;
+    use common::index::{IndexInfo, IndexKind};
+
+    let path = gen_random_test_sm_dir();
+    let sm = StorageManager::new(path.clone());
+    let tid = TransactionId::new();
+    let index = IndexInfo {
+        name: String::from("h"),
+        container_id: 2,
+        table_id: 1,
+        columns: vec![0],
+        unique: false,
+        constraint: false,
+        kind: IndexKind::Hash,
+    };
+    sm.create_table(index.container_id).unwrap();
+    hash_index::create(&sm, &index, tid).unwrap();
+    // two records per key, and many records of one key so its bucket overflows
+    let record = |i: u16| ValueId {
+        container_id: 1,
+        segment_id: None,
+        page_id: Some(i / 100),
+        slot_id: Some(i % 100),
+    };
+    let key = |k: i32| vec![Field::IntField(k), Field::StringField(format!("key{}", k))];
+    let mut entries: Vec<(Vec<Field>, ValueId)> = (0..4000)
+        .map(|i| (key(i as i32 % 2000), record(i)))
+        .collect();
+    entries.extend((4000..4300).map(|i| (key(-1), record(i))));
+    hash_index::insert(&sm, &index, entries, tid).unwrap();
+    sm.shutdown();
+
+    let sm = StorageManager::new(path.clone());
+    for k in 0..2000 {
+        let found: HashSet<ValueId> = hash_index::lookup(&sm, &index, &key(k), tid)
+            .unwrap()
+            .into_iter()
+            .collect();
+        let expected: HashSet<ValueId> = [record(k as u16), record(k as u16 + 2000)].into();
+        assert_eq!(expected, found, "key {}", k);
+    }
+    assert_eq!(
+        300,
+        hash_index::lookup(&sm, &index, &key(-1), tid)
+            .unwrap()
+            .len()
+    );
+    assert!(hash_index::lookup(&sm, &index, &key(2000), tid)
+        .unwrap()
+        .is_empty());
+
+    let removed: HashSet<ValueId> = (0..2000).chain(4000..4100).map(record).collect();
+    assert_eq!(
+        2100,
+        hash_index::remove(&sm, &index, &removed, tid).unwrap()
+    );
+    assert_eq!(
+        vec![record(2007)],
+        hash_index::lookup(&sm, &index, &key(7), tid).unwrap()
+    );
+    assert_eq!(
+        200,
+        hash_index::lookup(&sm, &index, &key(-1), tid)
+            .unwrap()
+            .len()
+    );
+    sm.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_compact_forwards() {
+    use common::index::{self, IndexInfo, IndexKind};
+    use common::table::Table;
+
+    let sm = StorageManager::new_test_sm();
+    let t = TransactionId::new();
+    let table = Table::new(String::from("t"), get_int_table_schema(2));
+    let index = IndexInfo {
+        name: String::from("h"),
+        container_id: 2,
+        table_id: 1,
+        columns: vec![0],
+        unique: true,
+        constraint: false,
+        kind: IndexKind::Hash,
+    };
+    sm.create_table(1).unwrap();
+    sm.create_table(2).unwrap();
+    let record = |i: i32| int_vec_to_tuple(vec![i, i * 10]).to_bytes();
+    let ids = sm
+        .insert_values(1, (0..600).map(record).collect(), t)
+        .unwrap();
+    // keep every fourth record, so the pages are mostly empty
+    let deleted: Vec<ValueId> = (0..600).filter(|i| i % 4 != 0).map(|i| ids[i]).collect();
+    sm.delete_values(deleted, t).unwrap();
+    assert_eq!(150, index::build(&sm, &table, &index, t).unwrap());
+    let lookup = |i: i32| index::lookup(&sm, &index, &[Field::IntField(i)], t).unwrap();
+
+    let moved = sm.compact(1).unwrap();
+    assert!(moved > 0);
+    assert_eq!(0, sm.compact(1).unwrap());
+    let forwards = sm.forwards(1).unwrap();
+    assert_eq!(moved, forwards.len());
+    // the old ids still find the records, and scans find each record once at its new id
+    for i in (0..600).step_by(4) {
+        assert_eq!(record(i as i32), sm.get_value(ids[i], t, RO).unwrap());
+        assert_eq!(vec![ids[i]], lookup(i as i32));
+    }
+    let scanned: Vec<(Vec<u8>, ValueId)> = sm.get_iterator(1, t, RO).collect();
+    assert_eq!(150, scanned.len());
+    for (old, new) in &forwards {
+        assert!(scanned.contains(&(sm.get_value(*old, t, RO).unwrap(), *new)));
+    }
+
+    // changes through an old id go to the moved record
+    let (old, new) = forwards[0];
+    let (updated, removed) = (forwards[1].0, forwards[2].0);
+    sm.update_value(record(-1), updated, t).unwrap();
+    assert_eq!(record(-1), sm.get_value(updated, t, RO).unwrap());
+    sm.delete_value(removed, t).unwrap();
+    assert!(sm.get_value(removed, t, RO).is_err());
+    assert!(sm.get_value(forwards[2].1, t, RO).is_err());
+    assert_eq!(149, sm.get_iterator(1, t, RO).count());
+    // the slot of a deleted record is kept while a stub forwards to it
+    sm.insert_value(1, record(1000), t).unwrap();
+    assert!(sm.get_value(removed, t, RO).is_err());
+
+    // once the index points at the new ids, the stubs are dropped
+    let forwards = sm.forwards(1).unwrap();
+    assert_eq!(moved - 1, forwards.len());
+    let key = |id: ValueId| {
+        let bytes = sm.get_value(id, t, RO).unwrap();
+        Tuple::from_bytes(&bytes).get_field(0).unwrap().clone()
+    };
+    let old_key = key(old);
+    assert_eq!(
+        moved - 1,
+        index::remap_entries(&sm, &table, &index, &forwards, t).unwrap()
+    );
+    assert_eq!(moved, sm.drop_forwards(1).unwrap());
+    assert_eq!(0, sm.drop_forwards(1).unwrap());
+    assert!(sm.forwards(1).unwrap().is_empty());
+    assert!(sm.get_value(old, t, RO).is_err());
+    let looked_up = index::lookup(&sm, &index, &[old_key], t).unwrap();
+    assert_eq!(vec![new], looked_up);
+    assert_eq!(150, sm.get_iterator(1, t, RO).count());
+}
+
+#[test]
+fn sm_compact_twice() {
+    let sm = StorageManager::new_test_sm();
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(400, 40, 60);
+    sm.create_table(1).unwrap();
+    let ids = sm.insert_values(1, vals.clone(), t).unwrap();
+    let (kept, deleted): (Vec<usize>, Vec<usize>) = (0..400).partition(|i| i % 3 == 0);
+    sm.delete_values(deleted.iter().map(|i| ids[*i]).collect(), t)
+        .unwrap();
+    let first = sm.compact(1).unwrap();
+    assert!(first > 0);
+
+    // emptying the first page makes room for values that were already moved
+    let first_page: Vec<usize> = kept
+        .iter()
+        .copied()
+        .filter(|i| ids[*i].page_id == Some(0))
+        .collect();
+    let remaining: Vec<usize> = kept
+        .iter()
+        .copied()
+        .filter(|i| !first_page.contains(i))
+        .collect();
+    sm.delete_values(first_page.iter().map(|i| ids[*i]).collect(), t)
+        .unwrap();
+    let before = sm.forwards(1).unwrap();
+    assert!(sm.compact(1).unwrap() > 0);
+    // some values moved again, and their stubs point at their new place
+    let forwards = sm.forwards(1).unwrap();
+    assert!(before.iter().any(|(old, new)| {
+        let (_, now) = forwards.iter().find(|(id, _)| id == old).unwrap();
+        now != new
+    }));
+    // every old id is one hop from its value
+    for i in remaining {
+        assert_eq!(vals[i], sm.get_value(ids[i], t, RO).unwrap());
+    }
+}
+
+#[test]
+fn sm_container_segments() {
+    let segmented = ContainerConfig {
+        segment_pages: Some(2),
+        ..Default::default()
+    };
+    let num_pages = |sm: &StorageManager| sm.list_containers().unwrap()[0].num_pages;
+    let path = gen_random_test_sm_dir();
+    let sm = StorageManager::new(path.clone());
+    let t = TransactionId::new();
+    let vals = get_random_vec_of_byte_vec(300, 50, 100);
+    let no_pages = ContainerConfig {
+        segment_pages: Some(0),
+        ..Default::default()
+    };
+    assert!(sm.create_container_with_config(1, no_pages, None).is_err());
+    sm.create_container_with_config(1, segmented, None).unwrap();
+    let ids = sm.insert_values(1, vals.clone(), t).unwrap();
+    let pages = num_pages(&sm);
+    assert!(pages > 4);
+
+    // Each segment has its own file, and ids carry the segment of their page
+    assert!(path.join("c1.1").exists() && path.join("c1.2").exists());
+    for id in &ids {
+        assert_eq!(Some((id.page_id.unwrap() / 2) as u8), id.segment_id);
+    }
+    let scanned: HashSet<ValueId> = sm.get_iterator(1, t, RO).map(|(_, id)| id).collect();
+    assert_eq!(ids.iter().copied().collect::<HashSet<_>>(), scanned);
+    assert!(sm
+        .set_container_config(1, ContainerConfig::default())
+        .is_err());
+
+    // The segments are opened again with the container
+    sm.shutdown();
+    let sm = StorageManager::new(path.clone());
+    assert_eq!(pages, num_pages(&sm));
+    for (id, val) in ids.iter().zip(&vals) {
+        assert_eq!(*val, sm.get_value(*id, t, RO).unwrap());
+    }
+
+    // Truncating an old segment drops its values at once
+    let last = ids.last().unwrap().segment_id.unwrap();
+    assert!(sm.truncate_segment(1, last).is_err());
+    sm.truncate_segment(1, 0).unwrap();
+    let kept: Vec<Vec<u8>> = (0..vals.len())
+        .filter(|i| ids[*i].segment_id != Some(0))
+        .map(|i| vals[i].clone())
+        .collect();
+    let scanned: Vec<Vec<u8>> = sm.get_iterator(1, t, RO).map(|(a, _)| a).collect();
+    assert!(compare_unordered_byte_vecs(&kept, scanned));
+    assert!(sm.get_value(ids[0], t, RO).is_err());
+
+    // and inserts fill its pages again
+    let id = sm.insert_value(1, vals[0].clone(), t).unwrap();
+    assert_eq!(Some(0), id.segment_id);
+    assert_eq!(vals[0], sm.get_value(id, t, RO).unwrap());
+    assert_eq!(pages, num_pages(&sm));
+    sm.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_bitmap_index() {
+    use common::bitmap_index::{self, BitmapQuery};
+    use common::index::{IndexInfo, IndexKind, KeyRange};
+
+    let path = gen_random_test_sm_dir();
+    let sm = StorageManager::new(path.clone());
+    let tid = TransactionId::new();
+    let index = |container_id, column| IndexInfo {
+        name: format!("b{}", column),
+        container_id,
+        table_id: 1,
+        columns: vec![column],
+        unique: false,
+        constraint: false,
+        kind: IndexKind::Bitmap,
+    };
+    let (color, size) = (index(2, 0), index(3, 1));
+    sm.create_table(color.container_id).unwrap();
+    sm.create_table(size.container_id).unwrap();
+    // 200 records on each of 100 pages
+    let record = |i: u16| ValueId {
+        container_id: 1,
+        segment_id: None,
+        page_id: Some(i / 200),
+        slot_id: Some(i % 200),
+    };
+    let key = |k: u16| vec![Field::IntField(k as i32)];
+    let records: Vec<u16> = (0..20000).collect();
+    bitmap_index::insert(
+        &sm,
+        &color,
+        records.iter().map(|i| (key(i % 3), record(*i))).collect(),
+        tid,
+    )
+    .unwrap();
+    bitmap_index::insert(
+        &sm,
+        &size,
+        records
+            .iter()
+            .map(|i| (key(i / 5000), record(*i)))
+            .collect(),
+        tid,
+    )
+    .unwrap();
+    sm.shutdown();
+
+    let sm = StorageManager::new(path.clone());
+    let equals = |index: &IndexInfo, k| BitmapQuery::Keys(index.clone(), KeyRange::exact(key(k)));
+    let query = BitmapQuery::And(vec![
+        equals(&color, 1),
+        BitmapQuery::Or(vec![equals(&size, 0), equals(&size, 3)]),
+    ]);
+    let expected: Vec<ValueId> = records
+        .iter()
+        .filter(|&&i| i % 3 == 1 && !(5000..15000).contains(&i))
+        .map(|i| record(*i))
+        .collect();
+    assert_eq!(expected, query.evaluate(&sm, 1, tid).unwrap().value_ids());
+
+    let removed: HashSet<ValueId> = (0..5000).map(record).collect();
+    assert_eq!(
+        5000,
+        bitmap_index::remove(&sm, &size, &removed, tid).unwrap()
+    );
+    assert!(
+        bitmap_index::scan(&sm, &size, &KeyRange::exact(key(0)), tid)
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(3, bitmap_index::keys(&sm, &size, tid).unwrap().len());
+    assert_eq!(
+        expected.len() - 1667,
+        query.evaluate(&sm, 1, tid).unwrap().len()
+    );
+    sm.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_refresh_statistics() {
+    use common::statistics::{self, AUTO_ANALYZE_BASE};
+    use common::table::Table;
+
+    let sm = StorageManager::new_test_sm();
+    let tid = TransactionId::new();
+    let cid = 1;
+    sm.create_table(cid).unwrap();
+    let table = Table::new("t".to_string(), get_int_table_schema(2));
+    let tuples = (0..200).map(|i| int_vec_to_tuple(vec![i, i % 10]).to_bytes());
+    let ids = sm.bulk_load(cid, tuples, tid).unwrap();
+    assert_eq!(200, sm.modifications().get(cid));
+
+    // Never analyzed, the table changed more than the base number of times.
+    let stats = statistics::refresh(&sm, cid, &table, None, tid)
+        .unwrap()
+        .unwrap();
+    assert_eq!(200, stats.row_count);
+    assert_eq!(0, sm.modifications().get(cid));
+
+    // Changes to fewer than the base number and a fraction of the rows keep the stats.
+    let threshold = AUTO_ANALYZE_BASE as usize + 20;
+    for id in &ids[..threshold / 2] {
+        sm.delete_value(*id, tid).unwrap();
+    }
+    for id in &ids[threshold / 2..threshold] {
+        let tuple = int_vec_to_tuple(vec![-1, -1]).to_bytes();
+        sm.update_value(tuple, *id, tid).unwrap();
+    }
+    assert_eq!(threshold as u64, sm.modifications().get(cid));
+    assert!(statistics::refresh(&sm, cid, &table, Some(&stats), tid)
+        .unwrap()
+        .is_none());
+
+    sm.delete_values(ids[threshold..threshold + 1].to_vec(), tid)
+        .unwrap();
+    let refreshed = statistics::refresh(&sm, cid, &table, Some(&stats), tid)
+        .unwrap()
+        .unwrap();
+    assert_eq!(200 - threshold as u64 / 2 - 1, refreshed.row_count);
+    sm.reset().unwrap();
+}
+
+#[test]
+fn sm_change_capture() {
+    let path = gen_random_test_sm_dir();
+    let sm = StorageManager::new(path.clone());
+    let t = TransactionId::new();
+    sm.create_table(1).unwrap();
+    sm.create_table(2).unwrap();
+    let before = sm.insert_value(1, vec![0; 10], t).unwrap();
+    assert!(sm.read_changes(1, 1, 10).is_err());
+    sm.subscribe_changes(1).unwrap();
+    assert!(sm.subscribe_changes(3).is_err());
+
+    let id = sm.insert_value(1, vec![1; 10], t).unwrap();
+    let new_id = sm.update_value(vec![2; 20], id, t).unwrap();
+    sm.delete_value(before, t).unwrap();
+    sm.insert_value(2, vec![3; 10], t).unwrap();
+    let events = sm.read_changes(1, 1, 10).unwrap();
+    let kinds: Vec<ChangeKind> = events.iter().map(|e| e.kind).collect();
+    assert_eq!(
+        vec![ChangeKind::Insert, ChangeKind::Update, ChangeKind::Delete],
+        kinds
+    );
+    assert!(events.iter().all(|e| e.tid == t.id()));
+    assert_eq!(Some((id, vec![1; 10])), events[0].after);
+    assert_eq!(Some((id, vec![1; 10])), events[1].before);
+    assert_eq!(Some((new_id, vec![2; 20])), events[1].after);
+    assert_eq!(Some((before, vec![0; 10])), events[2].before);
+    assert_eq!(None, events[2].after);
+
+    // The events consumers did not acknowledge survive a restart, and the capture goes on
+    sm.ack_changes(1, 1).unwrap();
+    sm.shutdown();
+    let sm = StorageManager::new(path.clone());
+    assert_eq!(events[1..], sm.read_changes(1, 0, 10).unwrap());
+    sm.delete_value(new_id, t).unwrap();
+    assert_eq!(4, sm.read_changes(1, 4, 10).unwrap()[0].seq);
+    sm.unsubscribe_changes(1).unwrap();
+    assert!(sm.read_changes(1, 1, 10).is_err());
+    sm.reset().unwrap();
+    fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn sm_change_capture_transactions() {
+    let sm = StorageManager::new_test_sm().with_mvcc();
+    sm.create_table(1).unwrap();
+    sm.subscribe_changes(1).unwrap();
+    let (committed, aborted) = (TransactionId::new(), TransactionId::new());
+    let id = sm.insert_value(1, vec![1; 10], committed).unwrap();
+    sm.insert_value(1, vec![2; 10], aborted).unwrap();
+    sm.abort_transaction(aborted).unwrap();
+    assert!(sm.read_changes(1, 1, 10).unwrap().is_empty());
+    sm.commit_transaction(committed).unwrap();
+
+    // Changes are recorded in commit order, with the values of the versions
+    let (first, second) = (TransactionId::new(), TransactionId::new());
+    let id = sm.update_value(vec![3; 10], id, second).unwrap();
+    sm.bulk_load(1, vec![vec![4; 10]].into_iter(), first)
+        .unwrap();
+    sm.commit_transaction(first).unwrap();
+    sm.commit_transaction(second).unwrap();
+    let events = sm.read_changes(1, 1, 10).unwrap();
+    let tids: Vec<TidType> = events.iter().map(|e| e.tid).collect();
+    assert_eq!(vec![committed.id(), first.id(), second.id()], tids);
+    assert_eq!(vec![4; 10], events[1].after.as_ref().unwrap().1);
+    assert_eq!(ChangeKind::Update, events[2].kind);
+    assert_eq!(vec![1; 10], events[2].before.as_ref().unwrap().1);
+    assert_eq!(Some((id, vec![3; 10])), events[2].after);
+}
+
+#[test]
+fn sm_two_phase_commit() {
+    let paths = [gen_random_test_sm_dir(), gen_random_test_sm_dir()];
+    let sms: Vec<StorageManager> = paths
+        .iter()
+        .map(|path| StorageManager::new(path.clone()).with_mvcc())
+        .collect();
+    let (committed, rolled_back) = (TransactionId::new(), TransactionId::new());
+    for sm in &sms {
+        sm.create_table(1).unwrap();
+        sm.insert_value(1, vec![1; 10], committed).unwrap();
+        sm.insert_value(1, vec![2; 10], rolled_back).unwrap();
+        sm.prepare_transaction(committed, "a").unwrap();
+        sm.prepare_transaction(rolled_back, "b").unwrap();
+        assert!(sm.prepare_transaction(TransactionId::new(), "a").is_err());
+    }
+    // A prepared transaction cannot change anything more
+    assert!(sms[0].insert_value(1, vec![3; 10], committed).is_err());
+    assert!(sms[0].abort_transaction(committed).is_err());
+
+    // The second participant restarts before the coordinator decides
+    let mut sms = sms.into_iter();
+    let first = sms.next().unwrap();
+    sms.next().unwrap().shutdown();
+    let second = StorageManager::new(paths[1].clone()).with_mvcc();
+    assert_eq!(vec!["a", "b"], second.prepared_transactions());
+    let participants = [first, second];
+    for sm in &participants {
+        assert_eq!(0, sm.get_iterator(1, TransactionId::new(), RO).count());
+        sm.commit_prepared("a").unwrap();
+        sm.rollback_prepared("b").unwrap();
+        assert!(sm.commit_prepared("b").is_err());
+        assert!(sm.prepared_transactions().is_empty());
+        let values: Vec<Vec<u8>> = sm
+            .get_iterator(1, TransactionId::new(), RO)
+            .map(|(a, _)| a)
+            .collect();
+        assert_eq!(vec![vec![1; 10]], values);
+    }
+    for (sm, path) in participants.iter().zip(paths) {
+        sm.reset().unwrap();
+        fs::remove_dir_all(path).unwrap();
+    }
+}
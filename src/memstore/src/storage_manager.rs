@@ -1,17 +1,37 @@
+#[cfg(feature = "arrow")]
+use common::arrow_batch::TupleBatches;
+use common::bloom::BloomFilters;
+use common::csv_export::{self, CsvExportOptions};
+use common::csv_import::{self, CsvImportOptions, CsvImportReport};
+use common::metrics::{Metrics, MetricsRegistry};
+use common::ndjson::{self, RowErrorPolicy};
+#[cfg(feature = "parquet")]
+use common::parquet_io::{self, ParquetExportOptions};
 use common::prelude::*;
-use common::storage_trait::StorageTrait;
+use common::statistics::ModificationCounters;
+use common::storage_trait::{validate_database_name, PageSampler, StorageTrait, DATABASES_DIR};
+use common::temp_container::TempContainers;
 
 use std::ffi::OsString;
 
 use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
 /// This is the basic data structure a container that maps a value ID to bytes
 type ContainerMap = Arc<RwLock<HashMap<ValueId, Vec<u8>>>>;
 
+/// Memstore has no pages, so sampling selects runs of this many consecutive slots instead.
+const SAMPLE_RUN_SLOTS: u16 = 64;
+
+/// File persisting the dependencies between containers.
+const DEPENDENCIES_FILE: &str = "dependencies.cbor";
+
+/// File persisting the bloom filters of the containers.
+const BLOOM_FILTERS_FILE: &str = "bloom_filters.cbor";
+
 /// The MemStore StorageManager. A map for storing containers, a map for tracking the next insert ID,
 /// and where to persist on shutdown/startup
 pub struct StorageManager {
@@ -19,6 +39,21 @@ pub struct StorageManager {
     last_insert: Arc<RwLock<HashMap<ContainerId, ValueId>>>,
     persist_path: PathBuf,
     container_names: Arc<RwLock<HashMap<String, ContainerId>>>,
+    /// The containers each container depends on, such as the parent tables of a table with
+    /// foreign keys. A container cannot be removed while another depends on it.
+    dependencies: Arc<RwLock<HashMap<ContainerId, Vec<ContainerId>>>>,
+    /// Bloom filters over a key column of some containers.
+    bloom_filters: BloomFilters,
+    /// Temporary containers and the transactions they belong to.
+    temp_containers: TempContainers,
+    /// Metrics of the operators run against the containers.
+    metrics: Arc<MetricsRegistry>,
+    /// Values changed in each container since its table was last analyzed.
+    modifications: ModificationCounters,
+    /// Storage managers of the databases, by name, see `create_database`.
+    databases: Arc<RwLock<HashMap<String, Arc<StorageManager>>>>,
+    /// Indicates if this StorageManager was opened with `open_read_only`.
+    read_only: bool,
 }
 
 impl Drop for StorageManager {
@@ -37,7 +72,7 @@ impl StorageTrait for StorageManager {
                 "Initializing memstore::storage_manager from path: {:?}",
                 &storage_path
             );
-            StorageManager::load(storage_path)
+            StorageManager::load(storage_path, false)
         } else {
             info!(
                 "Creating new memstore::storage_manager with path: {:?}",
@@ -48,6 +83,13 @@ impl StorageTrait for StorageManager {
                 last_insert: Arc::new(RwLock::new(HashMap::new())),
                 persist_path: storage_path,
                 container_names: Arc::new(RwLock::new(HashMap::new())),
+                dependencies: Arc::new(RwLock::new(HashMap::new())),
+                bloom_filters: BloomFilters::default(),
+                temp_containers: TempContainers::default(),
+                metrics: Arc::default(),
+                modifications: ModificationCounters::default(),
+                databases: Arc::new(RwLock::new(HashMap::new())),
+                read_only: false,
             }
         }
     }
@@ -63,7 +105,8 @@ impl StorageTrait for StorageManager {
         container_id: ContainerId,
         value: Vec<u8>,
         _tid: TransactionId,
-    ) -> ValueId {
+    ) -> Result<ValueId, CrustyError> {
+        self.check_writable()?;
         // Get the container
         let mut containers = self.containers.write().unwrap();
         // Find key to insert
@@ -71,7 +114,7 @@ impl StorageTrait for StorageManager {
         // Get the container map to allow the insert
         let mut vals = containers
             .get_mut(&container_id)
-            .expect("Container ID Missing on insert")
+            .ok_or(CrustyError::ContainerMissing(container_id))?
             .write()
             .unwrap();
         let next_slot = match last_insert.get(&container_id) {
@@ -89,9 +132,11 @@ impl StorageTrait for StorageManager {
             "memstore:storage_manager insert key: {:?} value: {:?}",
             &rid, &value
         );
+        self.bloom_filters.insert(container_id, &value);
+        self.modifications.add(container_id, 1);
         vals.insert(rid, value);
         last_insert.insert(container_id, rid);
-        rid
+        Ok(rid)
     }
 
     /// Insert multiple values
@@ -100,34 +145,56 @@ impl StorageTrait for StorageManager {
         container_id: ContainerId,
         values: Vec<Vec<u8>>,
         tid: TransactionId,
-    ) -> Vec<ValueId> {
-        let mut ret = Vec::new();
-        for x in values {
-            ret.push(self.insert_value(container_id, x, tid));
+    ) -> Result<Vec<ValueId>, CrustyError> {
+        values
+            .into_iter()
+            .map(|x| self.insert_value(container_id, x, tid))
+            .collect()
+    }
+
+    /// Load values into a container. Values are kept in memory, so there are no pages to
+    /// pack and this is the same as inserting them.
+    fn bulk_load(
+        &self,
+        container_id: ContainerId,
+        values: impl Iterator<Item = Vec<u8>>,
+        tid: TransactionId,
+    ) -> Result<Vec<ValueId>, CrustyError> {
+        if !self.containers.read().unwrap().contains_key(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
         }
-        ret
+        values
+            .map(|x| self.insert_value(container_id, x, tid))
+            .collect()
     }
 
     /// Remove the value from the container
     fn delete_value(&self, id: ValueId, _tid: TransactionId) -> Result<(), CrustyError> {
+        self.check_writable()?;
         let containers = self.containers.write().unwrap();
         if containers.contains_key(&id.container_id) {
             let mut table_map = containers.get(&id.container_id).unwrap().write().unwrap();
-            if table_map.contains_key(&id) {
-                table_map.remove(&id);
+            if let Some(value) = table_map.remove(&id) {
+                self.bloom_filters.remove(id.container_id, &value);
+                self.modifications.add(id.container_id, 1);
                 Ok(())
             } else {
                 //Key not found, no need to delete.
                 Ok(())
             }
         } else {
-            Err(CrustyError::CrustyError(String::from(
-                "File ID not found for recordID",
-            )))
+            Err(CrustyError::ContainerMissing(id.container_id))
         }
     }
 
+    /// Remove the values from their containers
+    fn delete_values(&self, ids: Vec<ValueId>, tid: TransactionId) -> Result<(), CrustyError> {
+        ids.into_iter()
+            .try_for_each(|id| self.delete_value(id, tid))
+    }
+
     /// Updates a value. Returns record ID on update (which may have changed). Error on failure
+    /// The update is a delete and an insert, and is counted as two modifications.
     fn update_value(
         &self,
         value: Vec<u8>,
@@ -135,7 +202,36 @@ impl StorageTrait for StorageManager {
         _tid: TransactionId,
     ) -> Result<ValueId, CrustyError> {
         self.delete_value(id, _tid)?;
-        Ok(self.insert_value(id.container_id, value, _tid))
+        self.insert_value(id.container_id, value, _tid)
+    }
+
+    /// Replaces the bytes of a value in place.
+    fn overwrite_value(
+        &self,
+        value: Vec<u8>,
+        id: ValueId,
+        _tid: TransactionId,
+    ) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let containers = self.containers.read().unwrap();
+        let mut table_map = containers
+            .get(&id.container_id)
+            .ok_or(CrustyError::ContainerMissing(id.container_id))?
+            .write()
+            .unwrap();
+        match table_map.get_mut(&id) {
+            Some(old) if old.len() == value.len() => {
+                self.bloom_filters.remove(id.container_id, old);
+                self.bloom_filters.insert(id.container_id, &value);
+                self.modifications.add(id.container_id, 1);
+                *old = value;
+                Ok(())
+            }
+            Some(_) => Err(CrustyError::CrustyError(String::from(
+                "Overwrite must keep the length of the value",
+            ))),
+            None => Err(CrustyError::invalid_slot(&id)),
+        }
     }
 
     /// Add a new container
@@ -146,6 +242,7 @@ impl StorageTrait for StorageManager {
         _container_type: StateType,
         dependencies: Option<Vec<ContainerId>>,
     ) -> Result<(), CrustyError> {
+        self.check_writable()?;
         if let Some(c_name) = name {
             let mut map = self.container_names.write().unwrap();
             if let std::collections::hash_map::Entry::Vacant(e) = map.entry(c_name) {
@@ -157,11 +254,19 @@ impl StorageTrait for StorageManager {
             }
         }
 
-        if dependencies.is_some() {
-            //FIXME add meta tracking
-            unimplemented!();
-        }
         let mut containers = self.containers.write().unwrap();
+        if let Some(dependencies) = dependencies {
+            if let Some(missing) = dependencies.iter().find(|c| !containers.contains_key(c)) {
+                return Err(CrustyError::ExecutionError(format!(
+                    "Dependency container {} does not exist",
+                    missing
+                )));
+            }
+            self.dependencies
+                .write()
+                .unwrap()
+                .insert(container_id, dependencies);
+        }
         if containers.contains_key(&container_id) {
             debug!(
                 "memstore::create_container container_id: {:?} already exists",
@@ -181,9 +286,19 @@ impl StorageTrait for StorageManager {
         self.create_container(container_id, None, StateType::BaseTable, None)
     }
 
+    fn create_temp_container(&self, tid: TransactionId) -> Result<ContainerId, CrustyError> {
+        let container_id = self.temp_containers.allocate(tid)?;
+        if let Err(e) = self.create_container(container_id, None, StateType::Spill, None) {
+            self.temp_containers.release(container_id);
+            return Err(e);
+        }
+        Ok(container_id)
+    }
+
     /// Remove the container and all stored values in the container.
     /// If the container is persisted remove the underlying files
     fn remove_container(&self, container_id: ContainerId) -> Result<(), CrustyError> {
+        self.check_writable()?;
         let mut containers = self.containers.write().unwrap();
         if !containers.contains_key(&container_id) {
             debug!(
@@ -192,14 +307,84 @@ impl StorageTrait for StorageManager {
             );
             return Ok(());
         }
+        let mut dependencies = self.dependencies.write().unwrap();
+        let dependents: Vec<String> = dependencies
+            .iter()
+            .filter(|(c_id, deps)| **c_id != container_id && deps.contains(&container_id))
+            .map(|(c_id, _)| c_id.to_string())
+            .collect();
+        if !dependents.is_empty() {
+            return Err(CrustyError::ExecutionError(format!(
+                "Container {} is a dependency of containers {}",
+                container_id,
+                dependents.join(", ")
+            )));
+        }
         debug!(
             "memstore::remove_container container_id: {:?} exists. dropping",
             &container_id
         );
         containers.remove(&container_id).unwrap();
+        dependencies.remove(&container_id);
+        self.bloom_filters.remove_container(container_id);
+        self.modifications.reset(container_id);
+        self.temp_containers.release(container_id);
         Ok(())
     }
 
+    /// Databases of a storage manager that is not persisted are not persisted either.
+    fn create_database(&self, name: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        validate_database_name(name)?;
+        let mut databases = self.databases.write().unwrap();
+        if databases.contains_key(name) {
+            return Err(CrustyError::CrustyError(format!(
+                "Database {} already exists",
+                name
+            )));
+        }
+        let path = if self.persist_path.to_string_lossy().is_empty() {
+            PathBuf::new()
+        } else {
+            // create the persist path now so the database is loaded even if it is empty
+            let path = self.persist_path.join(DATABASES_DIR).join(name);
+            fs::create_dir_all(&path)?;
+            path
+        };
+        databases.insert(name.to_string(), Arc::new(StorageManager::new(path)));
+        Ok(())
+    }
+
+    fn drop_database(&self, name: &str) -> Result<(), CrustyError> {
+        self.check_writable()?;
+        let db = self
+            .databases
+            .write()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| CrustyError::CrustyError(format!("Database {} does not exist", name)))?;
+        db.reset()?;
+        if db.persist_path.exists() {
+            fs::remove_dir_all(&db.persist_path)?;
+        }
+        Ok(())
+    }
+
+    fn use_database(&self, name: &str) -> Result<Arc<Self>, CrustyError> {
+        self.databases
+            .read()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CrustyError::CrustyError(format!("Database {} does not exist", name)))
+    }
+
+    fn list_databases(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.databases.read().unwrap().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
     /// Get an iterator for a container
     fn get_iterator(
         &self,
@@ -224,6 +409,19 @@ impl StorageTrait for StorageManager {
         ValueIterator::new(table_map, container_id, max)
     }
 
+    /// Get an iterator over a Bernoulli sample of runs of slots of a container
+    fn sample_iterator(
+        &self,
+        container_id: ContainerId,
+        fraction: f64,
+        seed: u64,
+        tid: TransactionId,
+    ) -> Self::ValIterator {
+        let mut iter = self.get_iterator(container_id, tid, Permissions::ReadOnly);
+        iter.sampler = Some(PageSampler::new(fraction, seed));
+        iter
+    }
+
     /// Get the bytes for a given value if found
     fn get_value(
         &self,
@@ -234,33 +432,66 @@ impl StorageTrait for StorageManager {
         let containers = self.containers.read().unwrap();
         if containers.contains_key(&id.container_id) {
             let map = containers.get(&id.container_id).unwrap().read().unwrap();
-            if map.contains_key(&id) {
-                Ok(map.get(&id).unwrap().clone())
-            } else {
-                Err(CrustyError::ExecutionError(format!(
-                    "Record ID not found {:?}",
-                    id
-                )))
-            }
+            map.get(&id)
+                .cloned()
+                .ok_or_else(|| CrustyError::invalid_slot(&id))
         } else {
-            Err(CrustyError::ExecutionError(format!(
-                "File ID not found {:?}",
-                id
-            )))
+            Err(CrustyError::ContainerMissing(id.container_id))
         }
     }
 
-    fn transaction_finished(&self, _tid: TransactionId) {
-        panic!("Not implemented");
+    /// Create a bloom filter over a key column of a container and add its existing keys
+    fn create_bloom_filter(
+        &self,
+        container_id: ContainerId,
+        key_column: usize,
+        expected_keys: usize,
+    ) -> Result<(), CrustyError> {
+        let containers = self.containers.read().unwrap();
+        let vals = containers
+            .get(&container_id)
+            .ok_or(CrustyError::ContainerMissing(container_id))?
+            .read()
+            .unwrap();
+        self.bloom_filters
+            .create(container_id, key_column, expected_keys);
+        for value in vals.values() {
+            self.bloom_filters.insert(container_id, value);
+        }
+        Ok(())
+    }
+
+    fn bloom_filter_column(&self, container_id: ContainerId) -> Option<usize> {
+        self.bloom_filters.key_column(container_id)
+    }
+
+    fn may_contain(&self, container_id: ContainerId, key: &Field) -> bool {
+        self.bloom_filters.may_contain(container_id, key)
+    }
+
+    fn transaction_finished(&self, tid: TransactionId) {
+        self.remove_temp_containers(self.temp_containers.take_transaction(tid));
     }
 
     fn reset(&self) -> Result<(), CrustyError> {
+        self.check_writable()?;
         let mut containers = self.containers.write().unwrap();
         let mut last_inserts = self.last_insert.write().unwrap();
         let mut container_names = self.container_names.write().unwrap();
         containers.clear();
         last_inserts.clear();
         container_names.clear();
+        self.dependencies.write().unwrap().clear();
+        self.bloom_filters.clear();
+        self.temp_containers.take_all();
+        self.metrics.reset();
+        self.modifications.clear();
+        for (_, db) in self.databases.write().unwrap().drain() {
+            db.reset()?;
+            if db.persist_path.exists() {
+                fs::remove_dir_all(&db.persist_path)?;
+            }
+        }
         Ok(())
     }
 
@@ -268,8 +499,27 @@ impl StorageTrait for StorageManager {
         // No cache here
     }
 
+    fn metrics(&self) -> &Arc<MetricsRegistry> {
+        &self.metrics
+    }
+
+    fn modifications(&self) -> &ModificationCounters {
+        &self.modifications
+    }
+
+    /// Returns the metrics of the operators. The containers are kept in memory, so there
+    /// are no file counters.
+    fn get_metrics(&self) -> Metrics {
+        self.metrics.snapshot(HashMap::new())
+    }
+
     fn shutdown(&self) {
         info!("Shutting down and persisting containers");
+        self.remove_temp_containers(self.temp_containers.take_all());
+        // a read-only StorageManager has nothing to persist
+        if self.read_only {
+            return;
+        }
         if self.persist_path.to_string_lossy().is_empty() {
             info!("Test SM or no path, not persisting");
             return;
@@ -288,6 +538,16 @@ impl StorageTrait for StorageManager {
                 .expect("Failed to create file");
             serde_cbor::to_writer(file, &*vals).expect("Failed on persisting container");
         }
+        let file =
+            File::create(self.persist_path.join(DEPENDENCIES_FILE)).expect("Failed to create file");
+        let dependencies = self.dependencies.read().unwrap();
+        serde_cbor::to_writer(file, &*dependencies).expect("Failed on persisting dependencies");
+        self.bloom_filters
+            .save(&self.persist_path.join(BLOOM_FILTERS_FILE))
+            .expect("Failed on persisting bloom filters");
+        for db in self.databases.read().unwrap().values() {
+            db.shutdown();
+        }
     }
 
     fn import_csv(
@@ -296,59 +556,189 @@ impl StorageTrait for StorageManager {
         path: String,
         tid: TransactionId,
         container_id: ContainerId,
-    ) -> Result<(), CrustyError> {
-        // Convert path into an absolute path.
-        let path = fs::canonicalize(path)?;
-        debug!("server::csv_utils trying to open file, path: {:?}", path);
-        let file = File::open(path)?;
-        // Create csv reader.
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(false)
-            .from_reader(file);
-
-        // Iterate through csv records.
-        let mut inserted_records = 0;
-        for result in rdr.records() {
-            #[allow(clippy::single_match)]
-            match result {
-                Ok(rec) => {
-                    // Build tuple and infer types from schema.
-                    let mut tuple = Tuple::new(Vec::new());
-                    for (field, attr) in rec.iter().zip(table.schema.attributes()) {
-                        // TODO: Type mismatch between attributes and record data>
-                        match &attr.dtype() {
-                            DataType::Int => {
-                                let value: i32 = field.parse::<i32>().unwrap();
-                                tuple.field_vals.push(Field::IntField(value));
-                            }
-                            DataType::String => {
-                                let value: String = field.to_string().clone();
-                                tuple.field_vals.push(Field::StringField(value));
-                            }
-                        }
-                    }
-                    //TODO: How should individual row insertion errors be handled?
-                    debug!(
-                        "server::csv_utils about to insert tuple into container_id: {:?}",
-                        &container_id
-                    );
-                    self.insert_value(container_id, tuple.to_bytes(), tid);
-                    inserted_records += 1;
-                }
-                _ => {
-                    // FIXME: get error from csv reader
-                    error!("Could not read row from CSV");
-                }
-            }
+        options: &CsvImportOptions,
+    ) -> Result<CsvImportReport, CrustyError> {
+        debug!("memstore::import_csv path: {:?}", path);
+        let report = csv_import::import_csv(&table.schema, Path::new(&path), options, |tuple| {
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        info!(
+            "Num records imported: {:?}, skipped: {:?}",
+            report.imported, report.skipped
+        );
+        Ok(report)
+    }
+
+    fn export_csv(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        options: &CsvExportOptions,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        if !self.containers.read().unwrap().contains_key(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
         }
-        info!("Num records imported: {:?}", inserted_records);
-        Ok(())
+        debug!("memstore::export_csv container_id: {:?}", &container_id);
+        let values = self
+            .get_iterator(container_id, tid, Permissions::ReadOnly)
+            .map(|(bytes, _)| bytes);
+        let exported = csv_export::export_csv(values, schema, Path::new(&path), options)?;
+        info!("Num records exported: {:?}", exported);
+        Ok(exported)
+    }
+
+    fn import_json(
+        &self,
+        table: &Table,
+        path: String,
+        tid: TransactionId,
+        container_id: ContainerId,
+        on_error: RowErrorPolicy,
+    ) -> Result<usize, CrustyError> {
+        debug!("memstore::import_json path: {:?}", path);
+        let imported = ndjson::import_json(&table.schema, Path::new(&path), on_error, |tuple| {
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        info!("Num records imported: {:?}", imported);
+        Ok(imported)
+    }
+
+    fn export_json(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        if !self.containers.read().unwrap().contains_key(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
+        }
+        debug!("memstore::export_json container_id: {:?}", &container_id);
+        let values = self
+            .get_iterator(container_id, tid, Permissions::ReadOnly)
+            .map(|(bytes, _)| bytes);
+        let exported = ndjson::export_json(values, schema, Path::new(&path))?;
+        info!("Num records exported: {:?}", exported);
+        Ok(exported)
     }
 }
 
 impl StorageManager {
-    /// Create a Memstore SM from a file path and populate from the files
-    fn load(path: PathBuf) -> Self {
+    /// Open the containers persisted by `shutdown` in storage_path for reading only, e.g. to
+    /// query the directory of another instance. Nothing is created in storage_path, and
+    /// inserts, deletes, updates and container changes fail. Shutting it down persists
+    /// nothing.
+    pub fn open_read_only(storage_path: PathBuf) -> Result<Self, CrustyError> {
+        if !storage_path.is_dir() {
+            return Err(CrustyError::IOError(format!(
+                "Cannot open {} read-only, it is not a directory",
+                storage_path.to_string_lossy()
+            )));
+        }
+        Ok(StorageManager::load(storage_path, true))
+    }
+
+    /// Returns true if this StorageManager was opened with `open_read_only`.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Errors if this StorageManager was opened with `open_read_only`.
+    fn check_writable(&self) -> Result<(), CrustyError> {
+        if self.read_only {
+            return Err(CrustyError::ExecutionError(format!(
+                "Storage manager at {} is open read-only",
+                self.persist_path.to_string_lossy()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Imports the records of a Parquet file into a container and returns the number of
+    /// records imported. Error if a column of the table is missing from the file or has a
+    /// Parquet type that cannot be imported.
+    #[cfg(feature = "parquet")]
+    pub fn import_parquet(
+        &self,
+        table: &Table,
+        path: String,
+        tid: TransactionId,
+        container_id: ContainerId,
+    ) -> Result<usize, CrustyError> {
+        debug!("memstore::import_parquet path: {:?}", path);
+        let imported = parquet_io::import_parquet(&table.schema, Path::new(&path), |tuple| {
+            self.insert_value(container_id, tuple.to_bytes(), tid)
+                .map(|_| ())
+        })?;
+        info!("Num records imported: {:?}", imported);
+        Ok(imported)
+    }
+
+    /// Writes the records of a container to a Parquet file in row groups of
+    /// `options.row_group_size` records, and returns the number of records written.
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        path: String,
+        options: &ParquetExportOptions,
+        tid: TransactionId,
+    ) -> Result<usize, CrustyError> {
+        if !self.containers.read().unwrap().contains_key(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
+        }
+        debug!("memstore::export_parquet container_id: {:?}", &container_id);
+        let values = self
+            .get_iterator(container_id, tid, Permissions::ReadOnly)
+            .map(|(bytes, _)| bytes);
+        let exported = parquet_io::export_parquet(values, schema, Path::new(&path), options)?;
+        info!("Num records exported: {:?}", exported);
+        Ok(exported)
+    }
+
+    /// Returns the records of a container as Arrow record batches, which are built as
+    /// the container is scanned.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container to scan.
+    /// * `schema` - Schema of the records.
+    /// * `tid` - Transaction scanning the container.
+    /// * `batch_rows` - Records per batch; the last batch may have fewer.
+    #[cfg(feature = "arrow")]
+    pub fn scan_record_batches(
+        &self,
+        container_id: ContainerId,
+        schema: &TableSchema,
+        tid: TransactionId,
+        batch_rows: usize,
+    ) -> Result<TupleBatches<impl Iterator<Item = Result<Tuple, CrustyError>>>, CrustyError> {
+        if !self.containers.read().unwrap().contains_key(&container_id) {
+            return Err(CrustyError::ContainerMissing(container_id));
+        }
+        let tuples = self
+            .get_iterator(container_id, tid, Permissions::ReadOnly)
+            .map(|(bytes, _)| Ok(Tuple::from_bytes(&bytes)));
+        TupleBatches::new(schema.clone(), tuples, batch_rows)
+    }
+
+    /// Removes temporary containers that are no longer needed.
+    fn remove_temp_containers(&self, container_ids: Vec<ContainerId>) {
+        for container_id in container_ids {
+            if let Err(e) = self.remove_container(container_id) {
+                warn!("Cannot remove temp container {}: {:?}", container_id, e);
+            }
+        }
+    }
+
+    /// Create a Memstore SM from a file path and populate from the files, read-only if
+    /// read_only
+    fn load(path: PathBuf, read_only: bool) -> Self {
         let mut container_map = HashMap::new();
         let mut last_ins = HashMap::new();
         // Find the files that end with .ms
@@ -396,11 +786,40 @@ impl StorageManager {
             last_ins.insert(cid, max_val);
         }
 
+        let dependencies: HashMap<ContainerId, Vec<ContainerId>> =
+            match File::open(path.join(DEPENDENCIES_FILE)) {
+                Ok(file) => serde_cbor::from_reader(file).expect("cannot read file"),
+                Err(_) => HashMap::new(),
+            };
+
+        let bloom_filters =
+            BloomFilters::load(&path.join(BLOOM_FILTERS_FILE)).expect("cannot read file");
+        // each directory of the databases directory is the persist path of a database
+        let mut databases = HashMap::new();
+        if let Ok(entries) = fs::read_dir(path.join(DATABASES_DIR)) {
+            for entry in entries.filter_map(Result::ok) {
+                if let (true, Some(name)) = (entry.path().is_dir(), entry.file_name().to_str()) {
+                    let db = if read_only {
+                        StorageManager::load(entry.path(), true)
+                    } else {
+                        StorageManager::new(entry.path())
+                    };
+                    databases.insert(name.to_string(), Arc::new(db));
+                }
+            }
+        }
         StorageManager {
             containers: Arc::new(RwLock::new(container_map)),
             last_insert: Arc::new(RwLock::new(last_ins)),
             persist_path: path,
             container_names: Arc::new(RwLock::new(HashMap::new())),
+            dependencies: Arc::new(RwLock::new(dependencies)),
+            bloom_filters,
+            temp_containers: TempContainers::default(),
+            metrics: Arc::default(),
+            modifications: ModificationCounters::default(),
+            databases: Arc::new(RwLock::new(databases)),
+            read_only,
         }
     }
 }
@@ -411,6 +830,7 @@ pub struct ValueIterator {
     max: u16,
     table_map: ContainerMap,
     current: u16,
+    sampler: Option<PageSampler>,
 }
 
 impl ValueIterator {
@@ -424,6 +844,7 @@ impl ValueIterator {
             max,
             table_map,
             current: 0,
+            sampler: None,
         }
     }
 }
@@ -432,11 +853,20 @@ impl Iterator for ValueIterator {
     type Item = (Vec<u8>, ValueId);
     fn next(&mut self) -> Option<Self::Item> {
         while self.current <= self.max {
+            // at the start of each run of slots, skip the run if it is not sampled
+            if let Some(sampler) = &mut self.sampler {
+                if self.current.is_multiple_of(SAMPLE_RUN_SLOTS) && !sampler.select() {
+                    self.current = self.current.saturating_add(SAMPLE_RUN_SLOTS);
+                    self.tracker.slot_id = Some(self.current);
+                    continue;
+                }
+            }
             match self.table_map.read().unwrap().get(&self.tracker) {
                 Some(res) => {
+                    let id = self.tracker;
                     self.tracker.slot_id = Some(self.tracker.slot_id.unwrap() + 1);
                     self.current += 1;
-                    return Some((res.clone(), self.tracker));
+                    return Some((res.clone(), id));
                 }
                 None => {
                     self.tracker.slot_id = Some(self.tracker.slot_id.unwrap() + 1);
@@ -465,7 +895,9 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
+        let rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
         let check_bytes = sm.get_value(rid, tid, Permissions::ReadOnly).unwrap();
         let check_tuple: Tuple = serde_cbor::from_slice(&check_bytes).unwrap();
         assert_eq!(tuple_bytes, check_bytes);
@@ -482,8 +914,12 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
-        let rid2 = sm.insert_value(container_id, tuple_bytes2.clone(), tid);
+        let rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
+        let rid2 = sm
+            .insert_value(container_id, tuple_bytes2.clone(), tid)
+            .unwrap();
         let mut check_bytes = sm.get_value(rid, tid, Permissions::ReadOnly).unwrap();
         let mut check_tuple: Tuple = serde_cbor::from_slice(&check_bytes).unwrap();
         assert_eq!(tuple_bytes, check_bytes);
@@ -505,7 +941,7 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_values(container_id, byte_vec, tid);
+        let rid = sm.insert_values(container_id, byte_vec, tid).unwrap();
         let mut check_bytes = sm
             .get_value(*rid.get(0).unwrap(), tid, Permissions::ReadOnly)
             .unwrap();
@@ -528,7 +964,9 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
+        let rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
         let check_bytes = sm.get_value(rid, tid, Permissions::ReadOnly).unwrap();
         let check_tuple: Tuple = serde_cbor::from_slice(&check_bytes).unwrap();
         assert_eq!(tuple_bytes, check_bytes);
@@ -550,8 +988,12 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let _rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
-        let _rid2 = sm.insert_value(container_id, tuple_bytes2.clone(), tid);
+        let _rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
+        let _rid2 = sm
+            .insert_value(container_id, tuple_bytes2.clone(), tid)
+            .unwrap();
         let mut iter = sm.get_iterator(container_id, tid, Permissions::ReadOnly);
 
         let mut check_bytes = iter.next().unwrap();
@@ -576,8 +1018,12 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
-        let _rid2 = sm.insert_value(container_id, tuple_bytes2.clone(), tid);
+        let rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
+        let _rid2 = sm
+            .insert_value(container_id, tuple_bytes2.clone(), tid)
+            .unwrap();
         let mut iter = sm.get_iterator(container_id, tid, Permissions::ReadOnly);
 
         let mut check_bytes = iter.next().unwrap();
@@ -607,7 +1053,9 @@ mod tests {
         let container_id = 1;
         sm.create_table(container_id).unwrap();
         let tid = TransactionId::new();
-        let rid = sm.insert_value(container_id, tuple_bytes.clone(), tid);
+        let rid = sm
+            .insert_value(container_id, tuple_bytes.clone(), tid)
+            .unwrap();
         let check_bytes = sm.get_value(rid, tid, Permissions::ReadOnly).unwrap();
         let check_tuple: Tuple = serde_cbor::from_slice(&check_bytes).unwrap();
 
@@ -640,10 +1088,10 @@ mod tests {
         let bytes1 = get_random_byte_vec(100);
         let bytes2 = get_random_byte_vec(300);
         let bytes3 = get_random_byte_vec(100);
-        let vid1 = sm.insert_value(container_id, bytes1.clone(), tid);
-        let vid2 = sm.insert_value(container_id, bytes2.clone(), tid);
-        let vid3 = sm.insert_value(2, bytes3.clone(), tid);
-        let vid4 = sm.insert_value(container_id, bytes2.clone(), tid);
+        let vid1 = sm.insert_value(container_id, bytes1.clone(), tid).unwrap();
+        let vid2 = sm.insert_value(container_id, bytes2.clone(), tid).unwrap();
+        let vid3 = sm.insert_value(2, bytes3.clone(), tid).unwrap();
+        let vid4 = sm.insert_value(container_id, bytes2.clone(), tid).unwrap();
         sm.delete_value(vid4, tid).unwrap();
         sm.shutdown();
 
@@ -663,9 +1111,39 @@ mod tests {
                 .expect("Can't get value")[..]
         );
 
-        let vid5 = sm.insert_value(container_id, bytes2, tid);
+        let vid5 = sm.insert_value(container_id, bytes2, tid).unwrap();
         assert_eq!(vid4.slot_id.unwrap() + 1, vid5.slot_id.unwrap());
 
         fs::remove_dir_all(persist).unwrap();
     }
+
+    #[test]
+    fn test_open_read_only() {
+        init();
+        let persist = gen_random_test_sm_dir();
+        let sm = StorageManager::new(persist.clone());
+        sm.create_table(1).unwrap();
+        sm.create_database("db").unwrap();
+        let tid = TransactionId::new();
+        let bytes = get_random_byte_vec(100);
+        let vid = sm.insert_value(1, bytes.clone(), tid).unwrap();
+        sm.shutdown();
+
+        let ro = StorageManager::open_read_only(persist.clone()).unwrap();
+        assert!(ro.is_read_only());
+        assert_eq!(
+            bytes,
+            ro.get_value(vid, tid, Permissions::ReadOnly).unwrap()
+        );
+        assert!(ro.insert_value(1, bytes.clone(), tid).is_err());
+        assert!(ro.delete_value(vid, tid).is_err());
+        assert!(ro.create_table(2).is_err());
+        assert!(ro.remove_container(1).is_err());
+        assert!(ro.reset().is_err());
+        assert!(ro.use_database("db").unwrap().create_table(2).is_err());
+        ro.shutdown();
+        assert!(StorageManager::open_read_only(persist.join("missing")).is_err());
+
+        fs::remove_dir_all(persist).unwrap();
+    }
 }
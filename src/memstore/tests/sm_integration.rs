@@ -18,7 +18,7 @@ fn sm_inserts() {
         let vals1 = get_random_vec_of_byte_vec(i, 50, 100);
         let cid = i as ContainerId;
         sm.create_table(cid).unwrap();
-        sm.insert_values(cid, vals1.clone(), t);
+        sm.insert_values(cid, vals1.clone(), t).unwrap();
         let check_vals: Vec<Vec<u8>> = sm.get_iterator(cid, t, RO).map(|(a, _)| a).collect();
         assert!(
             compare_unordered_byte_vecs(&vals1, check_vals),
@@ -36,7 +36,7 @@ fn sm_insert_delete() {
     let mut vals1 = get_random_vec_of_byte_vec(100, 50, 100);
     let cid = 1;
     sm.create_table(cid).unwrap();
-    let mut val_ids = sm.insert_values(cid, vals1.clone(), t);
+    let mut val_ids = sm.insert_values(cid, vals1.clone(), t).unwrap();
     for _ in 0..10 {
         let idx_to_del = rng.gen_range(0..vals1.len());
         sm.delete_value(val_ids[idx_to_del], t).unwrap();
@@ -56,7 +56,7 @@ fn sm_insert_updates() {
     let mut vals1 = get_random_vec_of_byte_vec(100, 50, 100);
     let cid = 1;
     sm.create_table(cid).unwrap();
-    let mut val_ids = sm.insert_values(cid, vals1.clone(), t);
+    let mut val_ids = sm.insert_values(cid, vals1.clone(), t).unwrap();
     for _ in 0..10 {
         let idx_to_upd = rng.gen_range(0..vals1.len());
         let new_bytes = get_random_byte_vec(15);
@@ -77,5 +77,9 @@ fn sm_no_container() {
     let sm = StorageManager::new_test_sm();
     let t = TransactionId::new();
     let vals1 = get_random_vec_of_byte_vec(100, 50, 100);
-    sm.insert_values(1, vals1, t);
+    sm.insert_values(1, vals1, t).unwrap();
+}
+
+mod conformance {
+    common::storage_conformance_tests!(memstore::storage_manager::StorageManager);
 }
@@ -52,6 +52,7 @@ impl Optimizer {
                     hash_table_key: group_by.clone(),
                     fields,
                     group_by,
+                    sorted_groups: false,
                 }))
             }
             LogicalOp::Join(JoinNode {
@@ -87,6 +88,12 @@ impl Optimizer {
             LogicalOp::Filter(FilterNode { table, predicate }) => {
                 Ok(PhysicalOp::Filter(PhysicalFilterNode { table, predicate }))
             }
+            LogicalOp::Sort(SortNode { fields }) => {
+                Ok(PhysicalOp::Sort(PhysicalSortNode { fields }))
+            }
+            LogicalOp::Limit(LimitNode { limit, offset }) => {
+                Ok(PhysicalOp::Limit(PhysicalLimitNode { limit, offset }))
+            }
             LogicalOp::Update(UpdateNode {
                 alias,
                 container_id,
@@ -96,6 +103,20 @@ impl Optimizer {
                 container_id,
                 assignments,
             })),
+            LogicalOp::Insert(InsertNode {
+                alias,
+                container_id,
+            }) => Ok(PhysicalOp::Insert(PhysicalInsertNode {
+                alias,
+                container_id,
+            })),
+            LogicalOp::Delete(DeleteNode {
+                alias,
+                container_id,
+            }) => Ok(PhysicalOp::Delete(PhysicalDeleteNode {
+                alias,
+                container_id,
+            })),
             //not currently covering read delta and write delta logical ops
             _ => todo!(),
         }
@@ -1,21 +1,50 @@
 use crate::StorageManager;
-use common::{prelude::*, storage_trait::StorageTrait, ConversionError, ConvertedResult};
+use common::columnar;
+use common::foreign_key::{self, ForeignKeys};
+use common::index::{self, IndexInfo};
+use common::partition;
+use common::table::TableLayout;
+use common::{
+    get_attr, negative_number, parse_typed_string, prelude::*, storage_trait::StorageTrait,
+    ConversionError, ConvertedResult,
+};
 use sqlparser::ast::{Value, Values};
-use std::{fmt::Display, fs, path::Path};
+use std::{collections::HashSet, fmt::Display, fs, path::Path};
 
 pub(crate) fn insert_validated_tuples(
     table_id: ContainerId,
+    layout: &TableLayout,
     tuples: Vec<Tuple>,
+    indexes: &[IndexInfo],
+    foreign_keys: &ForeignKeys,
     txn_id: TransactionId,
-    sm: &'static StorageManager,
+    sm: &StorageManager,
 ) -> Result<usize, CrustyError> {
-    let mut tuples_bytes = Vec::new();
-    warn!("Not using TM or indexes with inserting new tuples");
-    for t in &tuples {
-        tuples_bytes.push(t.to_bytes());
+    for index in indexes {
+        index::check_unique(sm, index, &tuples, &HashSet::new(), txn_id)?;
     }
-    let inserted = sm.insert_values(table_id, tuples_bytes, txn_id);
+    foreign_key::check_parents(sm, foreign_keys, &tuples, txn_id)?;
+    warn!("Not using TM with inserting new tuples");
+    let inserted = match layout {
+        TableLayout::Row => {
+            let mut tuples_bytes = Vec::new();
+            for t in &tuples {
+                tuples_bytes.push(t.to_bytes());
+            }
+            sm.insert_values(table_id, tuples_bytes, txn_id)?
+        }
+        TableLayout::Column(columns) => columnar::insert(sm, table_id, columns, &tuples, txn_id)?,
+        TableLayout::Partitioned(partitioning) => {
+            partition::insert(sm, partitioning, &tuples, txn_id)?
+        }
+    };
     let insert_count = inserted.len();
+    if !indexes.is_empty() {
+        let records: Vec<(Tuple, ValueId)> = tuples.iter().cloned().zip(inserted).collect();
+        for index in indexes {
+            index::insert_entries(sm, index, &records, txn_id)?;
+        }
+    }
     if insert_count == tuples.len() {
         Ok(insert_count)
     } else {
@@ -27,6 +56,40 @@ pub(crate) fn insert_validated_tuples(
     }
 }
 
+/// Loads validated records into a table with `StorageTrait::bulk_load`, for initial loads.
+/// Constraints are checked against all the records before any is stored, and the entries
+/// of the indexes are added once the records are loaded rather than record by record.
+/// Tables with a column or partitioned layout are stored as by `insert_validated_tuples`.
+pub(crate) fn bulk_load_tuples(
+    table_id: ContainerId,
+    layout: &TableLayout,
+    tuples: Vec<Tuple>,
+    indexes: &[IndexInfo],
+    foreign_keys: &ForeignKeys,
+    txn_id: TransactionId,
+    sm: &StorageManager,
+) -> Result<usize, CrustyError> {
+    for index in indexes {
+        index::check_unique(sm, index, &tuples, &HashSet::new(), txn_id)?;
+    }
+    foreign_key::check_parents(sm, foreign_keys, &tuples, txn_id)?;
+    let loaded = match layout {
+        TableLayout::Row => sm.bulk_load(table_id, tuples.iter().map(Tuple::to_bytes), txn_id)?,
+        TableLayout::Column(columns) => columnar::insert(sm, table_id, columns, &tuples, txn_id)?,
+        TableLayout::Partitioned(partitioning) => {
+            partition::insert(sm, partitioning, &tuples, txn_id)?
+        }
+    };
+    let load_count = loaded.len();
+    if !indexes.is_empty() {
+        let records: Vec<(Tuple, ValueId)> = tuples.into_iter().zip(loaded).collect();
+        for index in indexes {
+            index::insert_entries(sm, index, &records, txn_id)?;
+        }
+    }
+    Ok(load_count)
+}
+
 /// Check new or updated records to ensure that they do not break any constraints
 pub(crate) fn validate_tuples(
     _table_id: &ContainerId,
@@ -40,46 +103,70 @@ pub(crate) fn validate_tuples(
             "Col ordering not supported",
         )));
     }
-    let mut values_to_remove: Vec<(usize, Vec<ConversionError>)> = Vec::new();
-    warn!("PK, FK, Unique constaints not checked");
-    for (i, rec) in values.converted.iter().enumerate() {
-        for (j, (field, attr)) in (rec.field_vals()).zip(schema.attributes()).enumerate() {
+    let mut invalid: Vec<(usize, Vec<ConversionError>)> = Vec::new();
+    warn!("FK constaints not checked");
+    for (i, rec) in values.converted.iter_mut().enumerate() {
+        let mut errors = Vec::new();
+        for (j, (field, attr)) in rec
+            .field_vals
+            .iter_mut()
+            .zip(schema.attributes())
+            .enumerate()
+        {
+            *field = field.clone().coerce(&attr.dtype);
             if let Field::Null = field {
-                match attr.constraint {
-                    common::Constraint::NotNull
-                    | common::Constraint::UniqueNotNull
-                    | common::Constraint::PrimaryKey
-                    | common::Constraint::NotNullFKey(_) => {
-                        values_to_remove.push((i, vec![ConversionError::NullFieldNotAllowed(j)]));
-                    }
-                    _ => continue, // Null value so nothing to check
-                }
-            }
-            match &attr.dtype {
-                DataType::Int => {
-                    if let Field::IntField(_v) = field {
-                        // Nothing for now
-                    } else {
-                        values_to_remove.push((i, vec![ConversionError::WrongType]));
-                    }
-                }
-                DataType::String => {
-                    if let Field::StringField(_v) = field {
-                        // Nothing for now
-                    } else {
-                        values_to_remove.push((i, vec![ConversionError::WrongType]));
-                    }
+                if !attr.is_nullable() {
+                    errors.push(ConversionError::NullFieldNotAllowed(j));
                 }
+            } else if !field.has_type(&attr.dtype) {
+                // Coercion leaves values that do not fit the column unchanged
+                errors.push(ConversionError::WrongType);
             }
         }
+        if !errors.is_empty() {
+            invalid.push((i, errors));
+        }
     }
     // Remove in reverse order records that were invalid
-    for i in values_to_remove.iter().rev() {
-        values.converted.remove(i.0);
+    for (i, _) in invalid.iter().rev() {
+        values.converted.remove(*i);
     }
+    values.unconverted.extend(invalid);
     Ok(values)
 }
 
+/// Returns an error describing the records that could not be converted or validated, if
+/// there are any. Null values in NOT NULL columns are reported as constraint violations.
+///
+/// # Arguments
+///
+/// * `values` - Converted and validated records.
+/// * `schema` - Schema of the table the records are stored in.
+pub(crate) fn check_unconverted(
+    values: &ConvertedResult,
+    schema: &TableSchema,
+) -> Result<(), CrustyError> {
+    for (i, errors) in &values.unconverted {
+        for error in errors {
+            if let ConversionError::NullFieldNotAllowed(j) = error {
+                let column = schema.get_attribute(*j).map_or("", |attr| attr.name());
+                return Err(CrustyError::ConstraintViolation(format!(
+                    "Record {} has a null value in NOT NULL column {}",
+                    i, column
+                )));
+            }
+        }
+    }
+    if values.unconverted.is_empty() {
+        Ok(())
+    } else {
+        Err(CrustyError::ValidationError(format!(
+            "Some records were not valid: {:?}",
+            values.unconverted
+        )))
+    }
+}
+
 /// convert data from CSV into internal representation
 pub(crate) fn convert_csv_data<P: AsRef<Path> + Display>(
     path: P,
@@ -109,10 +196,10 @@ pub(crate) fn convert_csv_data<P: AsRef<Path> + Display>(
                     if field.eq("null") {
                         tuple.field_vals.push(Field::Null);
                     } else {
-                        let value = field.parse::<i32>();
+                        let value = Field::from_number(field);
                         match value {
-                            Ok(num) => tuple.field_vals.push(Field::IntField(num)),
-                            Err(_) => tuple.field_vals.push(Field::StringField(field.to_owned())),
+                            Some(num) => tuple.field_vals.push(num),
+                            None => tuple.field_vals.push(Field::StringField(field.to_owned())),
                         }
                     }
                 }
@@ -148,12 +235,10 @@ pub(crate) fn convert_insert_vals(values: &Values) -> Result<ConvertedResult, Cr
                             res.unconverted
                                 .push((i, vec![ConversionError::UnsupportedType]))
                         } else {
-                            let f = val.parse::<i32>();
+                            let f = Field::from_number(val);
                             match f {
-                                Ok(converted_field) => {
-                                    fields.push(Field::IntField(converted_field))
-                                }
-                                Err(_) => {
+                                Some(converted_field) => fields.push(converted_field),
+                                None => {
                                     res.unconverted.push((i, vec![ConversionError::ParseError]))
                                 }
                             };
@@ -162,6 +247,9 @@ pub(crate) fn convert_insert_vals(values: &Values) -> Result<ConvertedResult, Cr
                     Value::DoubleQuotedString(val) | Value::SingleQuotedString(val) => {
                         fields.push(Field::StringField(val.to_string()));
                     }
+                    Value::Boolean(val) => {
+                        fields.push(Field::BoolField(*val));
+                    }
                     Value::Null => {
                         fields.push(Field::Null);
                     }
@@ -170,9 +258,41 @@ pub(crate) fn convert_insert_vals(values: &Values) -> Result<ConvertedResult, Cr
                             .push((i, vec![ConversionError::UnsupportedType]));
                     }
                 }
+            } else if let Some(converted_field) = negative_number(field) {
+                fields.push(converted_field);
+            } else if let sqlparser::ast::Expr::TypedString { data_type, value } = field {
+                match parse_typed_string(data_type, value) {
+                    Ok(converted_field) => fields.push(converted_field),
+                    Err(_) => res.unconverted.push((i, vec![ConversionError::ParseError])),
+                }
+            } else if let sqlparser::ast::Expr::Cast { expr, data_type } = field {
+                match cast_literal(expr, data_type) {
+                    Some(converted_field) => fields.push(converted_field),
+                    None => res.unconverted.push((i, vec![ConversionError::ParseError])),
+                }
+            } else {
+                res.unconverted
+                    .push((i, vec![ConversionError::UnsupportedType]));
             }
         }
         res.converted.push(Tuple::new(fields));
     }
     Ok(res)
 }
+
+/// Returns the value of `CAST(literal AS type)` in an insert, or None if the expression is
+/// not a literal or the type cannot hold it.
+fn cast_literal(
+    expr: &sqlparser::ast::Expr,
+    data_type: &sqlparser::ast::DataType,
+) -> Option<Field> {
+    let field = match expr {
+        sqlparser::ast::Expr::Value(Value::Number(val, false)) => Field::from_number(val)?,
+        sqlparser::ast::Expr::Value(Value::SingleQuotedString(val)) => {
+            Field::StringField(val.to_string())
+        }
+        sqlparser::ast::Expr::Value(Value::Null) => Field::Null,
+        _ => return None,
+    };
+    field.cast(&get_attr(data_type).ok()?).ok()
+}
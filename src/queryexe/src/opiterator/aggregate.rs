@@ -1,8 +1,13 @@
-use super::{OpIterator, TupleIterator};
-use common::{AggOp, Attribute, CrustyError, DataType, Field, TableSchema, Tuple};
-use std::cmp::{max, min};
+use super::explain::column_name;
+use super::{Expression, MaterializeIterator, MemoryBudget, OpIterator, PlanNode, TupleIterator};
+use crate::StorageManager;
+use common::decimal::MAX_PRECISION;
+use common::ids::TransactionId;
+use common::{AggOp, Attribute, CrustyError, DataType, Decimal, Field, Float, TableSchema, Tuple};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::num;
+use std::mem::discriminant;
+use std::sync::Arc;
 
 /// Contains the index of the field to aggregate and the operator to apply to the column of each group. (You can add any other fields that you think are neccessary)
 #[derive(Clone)]
@@ -13,70 +18,179 @@ pub struct AggregateField {
     pub op: AggOp,
 }
 
-// HELPER: merge
-    // DESC: uses the enum for the aggregatefield operator to determine merge protocol and
-    //       return the new field
-    fn merge(aggregator: AggregateField, run: Option<Field>, new: Field, hash: Vec<Field>, group_tupes: &HashMap<Vec<Field>, Vec<Tuple>>, attr: usize) -> Field {
-        // use a match on the aggregator's operator to determine the merge protocol
-        let mut item = true;
-        let mut running = Field::IntField(0);
-
-        // use group tupes with hash and attr to find the total sum of the group
-        // use group tupes with hash to find the total count of the group
-        let mut sum = 0;
-        let mut cnt = 0;
-        for tuple in &group_tupes[&hash].clone() {
-            let f = tuple.get_field(attr).unwrap();
-            if let Field::IntField(n) = f {
-                sum += n;
+/// Returns the sum of two numeric fields. Integers are added with overflow checks, the sum
+/// is a float if either field is a float, and decimals are added exactly.
+///
+/// # Arguments
+///
+/// * `a` - First field.
+/// * `b` - Second field.
+fn add_fields(a: &Field, b: &Field) -> Result<Field, CrustyError> {
+    let sum = match (a, b) {
+        (Field::IntField(x), Field::IntField(y)) => {
+            Field::IntField(x.checked_add(*y).ok_or_else(|| {
+                CrustyError::ExecutionError(String::from("Integer overflow in sum"))
+            })?)
+        }
+        (Field::DecimalField(x), Field::DecimalField(y)) => Field::DecimalField(*x + *y),
+        (Field::IntField(x), Field::DecimalField(y))
+        | (Field::DecimalField(y), Field::IntField(x)) => {
+            Field::DecimalField(Decimal::new(*x as i64, 0) + *y)
+        }
+        _ => match (to_f64(a), to_f64(b)) {
+            (Some(x), Some(y)) => Field::FloatField(Float(x + y)),
+            _ => {
+                return Err(CrustyError::ExecutionError(format!(
+                    "Cannot add {} and {}",
+                    a, b
+                )))
             }
-            cnt += 1;
+        },
+    };
+    Ok(sum)
+}
+
+/// Returns the value of a numeric field as a float.
+fn to_f64(field: &Field) -> Option<f64> {
+    match field {
+        Field::IntField(i) => Some(*i as f64),
+        Field::FloatField(f) => Some(f.0),
+        Field::DecimalField(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// Returns the type of the values of an aggregate over values of a type. Counts are
+/// integers, sums and averages of decimals keep their scale with the largest precision, and
+/// the other aggregates have the type of the values. Averages of integers are integers.
+///
+/// # Arguments
+///
+/// * `op` - Aggregate operation.
+/// * `dtype` - Type of the aggregated values.
+pub(crate) fn output_dtype(op: AggOp, dtype: &DataType) -> DataType {
+    match (op, dtype) {
+        (AggOp::Count, _) => DataType::Int,
+        (AggOp::Sum | AggOp::Avg, DataType::Decimal(_, scale)) => {
+            DataType::Decimal(MAX_PRECISION, *scale)
         }
+        (_, dtype) => dtype.clone(),
+    }
+}
 
-        if run.is_some() {
-            running = run.clone().unwrap();
+/// Running value of an aggregate over the values of a group. Counts count every value,
+/// and the other aggregates skip NULLs and are NULL if all values are.
+#[derive(Clone)]
+enum Accumulator {
+    Count(i32),
+    /// Sum of the values so far.
+    Sum(Option<Field>),
+    /// Sum and number of the values so far.
+    Avg(Option<Field>, i64),
+    Min(Option<Field>),
+    Max(Option<Field>),
+}
+
+impl Accumulator {
+    /// Returns the accumulator of an aggregate operation before any value.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - Aggregate operation.
+    fn new(op: AggOp) -> Self {
+        match op {
+            AggOp::Count => Accumulator::Count(0),
+            AggOp::Sum => Accumulator::Sum(None),
+            AggOp::Avg => Accumulator::Avg(None, 0),
+            AggOp::Min => Accumulator::Min(None),
+            AggOp::Max => Accumulator::Max(None),
         }
+    }
 
-        match aggregator.op {
-            AggOp::Count => {
-                // if the operator is count, then increment the running field by 1
-                running = Field::IntField(cnt);
-                item = false;
-            }
-            AggOp::Sum => {
-                // if the operator is sum, then add the new field to the running field
-                running = Field::IntField(running.unwrap_int_field() + new.unwrap_int_field());
-            }
-            AggOp::Max => {
-                // if the operator is max, then compare the running field to the new field
-                // and set the running field to the max of the two
-                running = max(running, new.clone());
+    /// Adds a value to the aggregate. Sums and averages are only defined over numbers, and
+    /// minimums and maximums over values of one type or of numeric types.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Value to add.
+    fn update(&mut self, value: &Field) -> Result<(), CrustyError> {
+        if let Accumulator::Count(count) = self {
+            *count += 1;
+            return Ok(());
+        }
+        if *value == Field::Null {
+            return Ok(());
+        }
+        match self {
+            Accumulator::Count(_) => {}
+            Accumulator::Sum(sum) => add_to_sum(sum, value)?,
+            Accumulator::Avg(sum, count) => {
+                add_to_sum(sum, value)?;
+                *count += 1;
             }
-            AggOp::Min => {
-                // if the operator is min, then compare the running field to the new field
-                // and set the running field to the min of the two
-                running = min(running, new.clone());
+            Accumulator::Min(current) => {
+                let less = |c: &Field| compare_values(value, c).map(Ordering::is_lt);
+                if current.as_ref().map_or(Ok(true), less)? {
+                    *current = Some(value.clone());
+                }
             }
-            AggOp::Avg => {
-                // if the operator is avg, then add the new field to the running field
-                // and increment the running count by 1
-                running = Field::IntField( sum / cnt);
+            Accumulator::Max(current) => {
+                let greater = |c: &Field| compare_values(value, c).map(Ordering::is_gt);
+                if current.as_ref().map_or(Ok(true), greater)? {
+                    *current = Some(value.clone());
+                }
             }
         }
+        Ok(())
+    }
 
-        if run.is_some() {
-            // return the new running field
-            running
-        }
-        else {
-            // if item is true, return the field of the new tuple, otherwise,
-            // return 
-            if item {
-                return new
+    /// Returns the value of the aggregate. Averages of integers are truncated to integers,
+    /// and averages of decimals keep the scale of the values, rounded half away from zero.
+    fn finish(&self) -> Field {
+        match self {
+            Accumulator::Count(count) => Field::IntField(*count),
+            Accumulator::Sum(value) | Accumulator::Min(value) | Accumulator::Max(value) => {
+                value.clone().unwrap_or(Field::Null)
             }
-            Field::IntField(1)
+            Accumulator::Avg(sum, count) => match sum {
+                Some(Field::IntField(sum)) => Field::IntField((*sum as i64 / count) as i32),
+                Some(Field::DecimalField(sum)) => Field::DecimalField(sum.div_count(*count)),
+                Some(sum) => Field::FloatField(Float(to_f64(sum).unwrap_or(0.0) / *count as f64)),
+                None => Field::Null,
+            },
         }
     }
+}
+
+/// Adds a non-null value to a running sum, None before the first value.
+fn add_to_sum(sum: &mut Option<Field>, value: &Field) -> Result<(), CrustyError> {
+    if to_f64(value).is_none() {
+        return Err(CrustyError::ExecutionError(format!(
+            "Cannot sum or average non-numeric value {}",
+            value
+        )));
+    }
+    *sum = Some(match sum {
+        Some(sum) => add_fields(sum, value)?,
+        None => value.clone(),
+    });
+    Ok(())
+}
+
+/// Orders two non-null values of an aggregated column. Numbers of different types are
+/// compared by value, and values of other different types cannot be compared.
+fn compare_values(a: &Field, b: &Field) -> Result<Ordering, CrustyError> {
+    if discriminant(a) == discriminant(b) {
+        return Ok(a.cmp(b));
+    }
+    match (to_f64(a), to_f64(b)) {
+        (Some(x), Some(y)) => Ok(x.total_cmp(&y)),
+        _ => Err(CrustyError::ExecutionError(format!(
+            "Cannot compare {} and {}",
+            a, b
+        ))),
+    }
+}
 
 /// Computes an aggregation function over multiple columns and grouped by multiple fields. (You can add any other fields that you think are neccessary)
 struct Aggregator {
@@ -86,10 +200,10 @@ struct Aggregator {
     groupby_fields: Vec<usize>,
     /// Schema of the output.
     schema: TableSchema,
-    /// Map of group by fields to the accumulated value of the aggregation (a single tuple).
-    group_aggs: HashMap<Vec<Field>, Tuple>,
-    /// store a vector of tuples for each field
-    group_tupes: HashMap<Vec<Field>, Vec<Tuple>>,
+    /// Map of group by fields to the running value of each aggregate of the group.
+    groups: HashMap<Vec<Field>, Vec<Accumulator>>,
+    /// Whether the results are sorted by their group by fields.
+    sorted_groups: bool,
 }
 
 impl Aggregator {
@@ -105,100 +219,65 @@ impl Aggregator {
         groupby_fields: Vec<usize>,
         schema: &TableSchema,
     ) -> Self {
-        // initialize hashmaps to be empty
-        let group_aggs = HashMap::new();
-        let group_tupes = HashMap::new();
-        Self { agg_fields, groupby_fields, schema: schema.clone(), group_aggs, group_tupes }
+        Self {
+            agg_fields,
+            groupby_fields,
+            schema: schema.clone(),
+            groups: HashMap::new(),
+            sorted_groups: false,
+        }
     }
 
+    /// Returns the results sorted by their group by fields instead of in hash order.
+    fn with_sorted_groups(mut self) -> Self {
+        self.sorted_groups = true;
+        self
+    }
 
-    /// Handles the creation of groups for aggregation.
-    ///
-    /// If a group exists, then merge the tuple into the group's accumulated value.
-    /// Otherwise, create a new group aggregate result.
+    /// Adds a tuple to the aggregates of its group, creating the group if it is the first
+    /// tuple of the group.
     ///
     /// # Arguments
     ///
     /// * `tuple` - Tuple to add to a group.
-    pub fn merge_tuple_into_group(&mut self, tuple: &Tuple) {
-        // use the groupby fields to create a key for the hashmap
-        let mut groupby_fields = Vec::new();
-        for i in &self.groupby_fields {
-            groupby_fields.push(tuple.get_field(*i).unwrap().clone());
-        }
-        // update group tupes
-        if self.group_tupes.contains_key(&groupby_fields) {
-            let mut v = self.group_tupes[&groupby_fields].clone();
-            v.push(tuple.clone());
-            self.group_tupes.insert(groupby_fields.clone(), v);
-        }
-        else {
-            self.group_tupes.insert(groupby_fields.clone(), vec![tuple.clone()]);
-        }
-        // modify the aggregate tuple
-        // use the groupby_fields as a key, if its in the hm, then a group exits
-        if self.group_aggs.contains_key(&groupby_fields) {
-            // get a mutable reference to the matching aggregate tuple
-            let mut agg_tup = self.group_aggs[&groupby_fields].clone();
-            // if the group exists, then merge the current tuples using the aggregateField structs
-            for (i, comp_field) in self.agg_fields.clone().into_iter().enumerate() {
-                // update the group sums
-                let num = tuple.get_field(comp_field.field).unwrap();
-                // get the matching field from the tuple and the aggregate tuple
-                let field = tuple.get_field(comp_field.field).unwrap();
-                let agg_field = agg_tup.get_field(i).unwrap();
-                // merge these fields based on the comp_field operator
-                let res_field = merge(comp_field.clone(), Some(agg_field.clone()), field.clone(), groupby_fields.clone(), &self.group_tupes, comp_field.field);
-                // update the aggregate tuple with the new field
-                agg_tup.set_field(i, res_field);
-            }
-            // update the aggregate tuple in the hashmap
-            self.group_aggs.insert(groupby_fields, agg_tup);
-        } else {
-            // create and insert the aggregate tuple from the schema and the input tuple
-            // first create placeholder tuple from schema with arbitrary values
-            let mut placeholder_vec: Vec<Field> = Vec::new();
-            // we should use the aggregate_fields
-            for agfield in self.agg_fields.clone() {
-                let num = tuple.get_field(agfield.field).unwrap();
-                //append attribute to the placeholder vec
-                let x = tuple.get_field(agfield.field).unwrap().clone();
-                placeholder_vec.push(x);
-            }
-            // get a tuple from the vector
-            let mut agg_tup = Tuple::new(placeholder_vec);
-
-            // now we merge
-            for (i, comp_field) in self.agg_fields.clone().into_iter().enumerate() {
-                // get the matching field from the tuple and the aggregate tuple
-                let field = tuple.get_field(comp_field.field).unwrap();
-                let res_field = merge(comp_field.clone(), None, field.clone(), groupby_fields.clone(), &self.group_tupes, comp_field.field);
-                // merge these fields based on the comp_field operator
-                // update the aggregate tuple with the new field
-                agg_tup.set_field(i, res_field);
-            }
-            // update the aggregate tuple in the hashmap
-            self.group_aggs.insert(groupby_fields, agg_tup);
+    pub fn merge_tuple_into_group(&mut self, tuple: &Tuple) -> Result<(), CrustyError> {
+        let field = |i: usize| {
+            tuple.get_field(i).ok_or_else(|| {
+                CrustyError::ExecutionError(format!("Aggregate reads missing field {}", i))
+            })
+        };
+        let key = self
+            .groupby_fields
+            .iter()
+            .map(|i| field(*i).cloned())
+            .collect::<Result<Vec<Field>, CrustyError>>()?;
+        let accumulators = self.groups.entry(key).or_insert_with(|| {
+            self.agg_fields
+                .iter()
+                .map(|a| Accumulator::new(a.op))
+                .collect()
+        });
+        for (accumulator, agg_field) in accumulators.iter_mut().zip(&self.agg_fields) {
+            accumulator.update(field(agg_field.field)?)?;
         }
-        // merge based on each aggregate field's operator
+        Ok(())
     }
 
     /// Returns a `TupleIterator` over the results.
     ///
     /// Resulting tuples must be of the form: (group by fields ..., aggregate fields ...)
     pub fn iterator(&self) -> TupleIterator {
-        // use the hashmap to create a vector of tuples, then return a tuple iterator
-        let mut tuples = Vec::new();
-        for (key, value) in &self.group_aggs {
-            let mut tuple = Vec::new();
-            for field in key {
-                tuple.push(field.clone());
-            }
-            for field in value.field_vals() {
-                tuple.push(field.clone());
-            }
-            tuples.push(Tuple::new(tuple));
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        if self.sorted_groups {
+            groups.sort_by_key(|(key, _)| *key);
         }
+        let tuples = groups
+            .into_iter()
+            .map(|(key, accumulators)| {
+                let aggregates = accumulators.iter().map(Accumulator::finish);
+                Tuple::new(key.iter().cloned().chain(aggregates).collect())
+            })
+            .collect();
         TupleIterator::new(tuples, self.schema.clone())
     }
 }
@@ -222,6 +301,13 @@ pub struct Aggregate {
     prior_tuple: Option<Tuple>,
     tuples: Vec<Tuple>,
     tuple_idx: usize,
+    /// Results moved out of memory by `spill_results`, returned instead of `tuples` when set.
+    results: Option<MaterializeIterator>,
+    /// Expressions computed from each child tuple, whose values are grouped and aggregated
+    /// in place of the child's fields when set.
+    inputs: Option<Vec<Expression>>,
+    /// Whether groups are returned sorted by their group by fields.
+    sorted_groups: bool,
 }
 
 impl Aggregate {
@@ -243,37 +329,105 @@ impl Aggregate {
         ops: Vec<AggOp>,
         child: Box<dyn OpIterator>,
     ) -> Self {
+        let input_dtypes = child
+            .get_schema()
+            .attributes()
+            .map(|a| a.dtype().clone())
+            .collect();
+        Self::with_input_dtypes(
+            groupby_indices,
+            groupby_names,
+            agg_indices,
+            agg_names,
+            ops,
+            input_dtypes,
+            child,
+        )
+    }
+
+    /// Creates an aggregate whose group by keys and aggregated values are expressions over
+    /// the fields of the child's tuples.
+    ///
+    /// # Arguments
+    ///
+    /// * `groupby_exprs` - the expressions to group by
+    /// * `groupby_names` - the names of the group_by fields in the final aggregation
+    /// * `agg_exprs` - the expressions to aggregate
+    /// * `agg_names` - the names of the aggreagte fields in the final aggregation
+    /// * `ops` - Aggregate operations, 1:1 correspondence with the expressions in agg_exprs
+    /// * `child` - child operator to get the input data from.
+    pub fn new_with_expressions(
+        groupby_exprs: Vec<Expression>,
+        groupby_names: Vec<&str>,
+        agg_exprs: Vec<Expression>,
+        agg_names: Vec<&str>,
+        ops: Vec<AggOp>,
+        child: Box<dyn OpIterator>,
+    ) -> Self {
+        // the aggregator reads the values of the expressions, group by keys first
+        let groups = groupby_exprs.len();
+        let inputs: Vec<Expression> = groupby_exprs.into_iter().chain(agg_exprs).collect();
+        let input_dtypes = inputs.iter().map(|e| e.dtype(child.get_schema())).collect();
+        let mut aggregate = Self::with_input_dtypes(
+            (0..groups).collect(),
+            groupby_names,
+            (groups..inputs.len()).collect(),
+            agg_names,
+            ops,
+            input_dtypes,
+            child,
+        );
+        aggregate.inputs = Some(inputs);
+        aggregate
+    }
+
+    /// Creates an aggregate over input tuples with fields of the given types. Group by keys
+    /// have the type of their field and aggregates the type of their results.
+    ///
+    /// # Arguments
+    ///
+    /// * `groupby_indices` - the indices of the group by fields
+    /// * `groupby_names` - the names of the group_by fields in the final aggregation
+    /// * `agg_indices` - the indices of the aggregate fields
+    /// * `agg_names` - the names of the aggreagte fields in the final aggregation
+    /// * `ops` - Aggregate operations, 1:1 correspondence with the indices in agg_indices
+    /// * `input_dtypes` - Types of the fields of the input tuples.
+    /// * `child` - child operator to get the input data from.
+    fn with_input_dtypes(
+        groupby_indices: Vec<usize>,
+        groupby_names: Vec<&str>,
+        agg_indices: Vec<usize>,
+        agg_names: Vec<&str>,
+        ops: Vec<AggOp>,
+        input_dtypes: Vec<DataType>,
+        child: Box<dyn OpIterator>,
+    ) -> Self {
+        let input_dtype = |i: usize| input_dtypes.get(i).cloned().unwrap_or(DataType::Int);
         // create a vector of aggregate fields
         let mut agg_fields = Vec::new();
         for i in 0..agg_indices.len() {
-            agg_fields.push(AggregateField { field: agg_indices[i], op: ops[i] });
-        }
-        // create groupby fields
-        let mut groupby_fields = Vec::new();
-        for g in groupby_indices {
-            groupby_fields.push(g);
+            agg_fields.push(AggregateField {
+                field: agg_indices[i],
+                op: ops[i],
+            });
         }
         // create a vector of attributes for creating the schema
         let mut attributes = Vec::new();
-        for g in groupby_names {
-            attributes.push(Attribute::new(g.to_string(), DataType::Int));
+        for (g, i) in groupby_names.iter().zip(&groupby_indices) {
+            attributes.push(Attribute::new(g.to_string(), input_dtype(*i)));
         }
-        for agg in agg_names {
-            attributes.push(Attribute::new(agg.to_string(), DataType::Int));
+        for (agg, f) in agg_names.iter().zip(&agg_fields) {
+            let dtype = output_dtype(f.op, &input_dtype(f.field));
+            attributes.push(Attribute::new(agg.to_string(), dtype));
         }
         // create the schema
         let schema = TableSchema::new(attributes);
-        // create aggregator
+        let groupby_fields = groupby_indices;
         let agg = Aggregator::new(agg_fields.clone(), groupby_fields.clone(), &schema);
-        // create the agregate itterater
-        let agg_iter = agg.iterator();
-         // if there is no next child tuple, then return none
-        
-        
-        let mut res = Self {
+        Self {
             groupby_fields,
             agg_fields,
-            agg_iter: Some(agg_iter),
+            agg_iter: None,
             schema,
             open: false,
             child,
@@ -281,39 +435,123 @@ impl Aggregate {
             prior_tuple: None,
             tuples: Vec::new(),
             tuple_idx: 0,
-        };
-        // open the child
-        res.child.open().unwrap();
-        // get all children tuples and aggregate them
-        while let Some(child_tuple) = res.child.next().unwrap() {
-            res.agg.merge_tuple_into_group(&child_tuple);
-        }
-        // get a new iterator
-        res.agg_iter = Some(res.agg.iterator());
-        // get a vector of tuples from the agg_iter
+            results: None,
+            inputs: None,
+            sorted_groups: false,
+        }
+    }
+
+    /// Returns groups sorted by their group by fields, so the output order does not depend
+    /// on hashing and is the same on every run.
+    ///
+    /// # Arguments
+    ///
+    /// * `sorted` - Whether to sort the groups.
+    pub fn with_sorted_groups(mut self, sorted: bool) -> Self {
+        self.sorted_groups = sorted;
+        self
+    }
+
+    /// Returns an aggregator without any groups.
+    fn aggregator(&self) -> Aggregator {
+        let agg = Aggregator::new(
+            self.agg_fields.clone(),
+            self.groupby_fields.clone(),
+            &self.schema,
+        );
+        if self.sorted_groups {
+            agg.with_sorted_groups()
+        } else {
+            agg
+        }
+    }
+
+    /// Aggregates the tuples of the child, which must be open, replacing the results of
+    /// earlier runs. Runs each time the aggregate is opened, so reopening a plan whose
+    /// parameters were rebound aggregates the tuples of the new run.
+    fn aggregate(&mut self) -> Result<(), CrustyError> {
+        self.agg = self.aggregator();
+        while let Some(child_tuple) = self.child.next()? {
+            match &self.inputs {
+                Some(inputs) => {
+                    let fields = inputs
+                        .iter()
+                        .map(|e| e.evaluate(&child_tuple))
+                        .collect::<Result<Vec<Field>, CrustyError>>()?;
+                    self.agg.merge_tuple_into_group(&Tuple::new(fields))?;
+                }
+                None => self.agg.merge_tuple_into_group(&child_tuple)?,
+            }
+        }
+        let mut agg_iter = self.agg.iterator();
+        agg_iter.open()?;
         let mut tuples = Vec::new();
-        // open the iterator
-        res.agg_iter.as_mut().unwrap().open().unwrap();
-        while let Some(tuple) = res.agg_iter.as_mut().unwrap().next().unwrap() {
-            tuples.push(tuple.clone());
-        }
-        // set the tuples field
-        res.tuples = tuples;
-        res
+        while let Some(tuple) = agg_iter.next()? {
+            tuples.push(tuple);
+        }
+        self.tuple_idx = 0;
+        match self.results.as_mut() {
+            Some(results) => {
+                results.clear()?;
+                for tuple in tuples {
+                    results.push(tuple)?;
+                }
+                // The groups are not needed once the results are materialized.
+                self.agg = self.aggregator();
+            }
+            None => {
+                self.tuples = tuples;
+                self.agg_iter = Some(agg_iter);
+            }
+        }
+        Ok(())
     }
 
+    /// Keeps the results in a `MaterializeIterator` that holds at most `memory_limit` bytes
+    /// of them in memory and spills the rest to a temporary container, and frees the groups
+    /// once the results are materialized.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_manager` - Storage manager to spill results to.
+    /// * `memory_limit` - Bytes of results to keep in memory.
+    /// * `budget` - Memory budget the results kept in memory count against.
+    /// * `tid` - Transaction running the aggregate.
+    pub fn spill_results(
+        mut self,
+        storage_manager: Arc<StorageManager>,
+        memory_limit: usize,
+        budget: &MemoryBudget,
+        tid: TransactionId,
+    ) -> Result<Self, CrustyError> {
+        let mut results =
+            MaterializeIterator::new(storage_manager, self.schema.clone(), memory_limit, tid)
+                .with_memory_grant(budget.grant(true));
+        for tuple in std::mem::take(&mut self.tuples) {
+            results.push(tuple)?;
+        }
+        self.agg = self.aggregator();
+        self.agg_iter = None;
+        if self.open {
+            results.open()?;
+        }
+        self.results = Some(results);
+        Ok(self)
+    }
 }
 
 impl OpIterator for Aggregate {
     fn open(&mut self) -> Result<(), CrustyError> {
         //check if its open
         if self.open {
-            return Ok(())
+            return Ok(());
         }
-        // open the agg_iter
-        // self.agg_iter.as_mut().unwrap().open()?;
-        // open the child
+        // open the child and aggregate its tuples
         self.child.open()?;
+        self.aggregate()?;
+        if let Some(results) = self.results.as_mut() {
+            results.open()?;
+        }
         // set the open boolean to true
         self.open = true;
         Ok(())
@@ -323,6 +561,9 @@ impl OpIterator for Aggregate {
         if !self.open {
             panic!("Operator has not been opened")
         }
+        if let Some(results) = self.results.as_mut() {
+            return results.next();
+        }
         // return the tuple at the tuple idx then increment the idx
         if self.tuple_idx < self.tuples.len() {
             let tuple = self.tuples[self.tuple_idx].clone();
@@ -341,7 +582,12 @@ impl OpIterator for Aggregate {
         self.tuple_idx = 0;
         self.prior_tuple = None;
         // close the agg_iter
-        self.agg_iter.as_mut().unwrap().close()?;
+        if let Some(agg_iter) = self.agg_iter.as_mut() {
+            agg_iter.close()?;
+        }
+        if let Some(results) = self.results.as_mut() {
+            results.close()?;
+        }
         // close the child
         self.child.close()?;
         // set the open boolean to false
@@ -356,7 +602,12 @@ impl OpIterator for Aggregate {
         }
         // rewind the child
         self.child.rewind()?;
-        self.agg_iter.as_mut().unwrap().rewind()?;
+        if let Some(agg_iter) = self.agg_iter.as_mut() {
+            agg_iter.rewind()?;
+        }
+        if let Some(results) = self.results.as_mut() {
+            results.rewind()?;
+        }
         // set the tuple idx to 0
         self.tuple_idx = 0;
         // set the prior tuple to none
@@ -367,6 +618,29 @@ impl OpIterator for Aggregate {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let child_schema = self.child.get_schema();
+        let input = |i: usize| match &self.inputs {
+            Some(inputs) => inputs[i].describe(child_schema),
+            None => column_name(child_schema, i),
+        };
+        let group_by: Vec<String> = self.groupby_fields.iter().map(|i| input(*i)).collect();
+        let aggregates: Vec<String> = self
+            .agg_fields
+            .iter()
+            .map(|a| format!("{}({})", a.op, input(a.field)))
+            .collect();
+        let mut node = PlanNode::new("Aggregate");
+        if !group_by.is_empty() {
+            node = node.param("group by", group_by.join(", "));
+        }
+        if let Some(results) = self.results.as_ref().filter(|r| r.spilled() > 0) {
+            node = node.param("spilled rows", results.spilled());
+        }
+        node.param("aggregates", aggregates.join(", "))
+            .child(self.child.as_ref())
+    }
 }
 
 #[cfg(test)]
@@ -426,7 +700,7 @@ mod test {
 
     mod aggregator {
         use super::*;
-        use common::{DataType, Field};
+        use common::{DataType, Date, Field};
 
         /// Set up testing aggregations without grouping.
         ///
@@ -440,7 +714,7 @@ mod test {
             let mut agg = Aggregator::new(vec![AggregateField { field, op }], Vec::new(), &schema);
             let ti = tuples();
             for t in &ti {
-                agg.merge_tuple_into_group(t);
+                agg.merge_tuple_into_group(t)?;
             }
 
             let mut ai = agg.iterator();
@@ -479,9 +753,190 @@ mod test {
         }
 
         #[test]
-        #[should_panic]
-        fn test_merge_tuples_not_int() {
-            let _ = test_no_group(AggOp::Avg, 3, 3);
+        fn test_merge_tuples_not_numeric() {
+            let schema = TableSchema::new(vec![Attribute::new("agg".to_string(), DataType::Int)]);
+            for op in [AggOp::Sum, AggOp::Avg] {
+                let field = AggregateField { field: 3, op };
+                let mut agg = Aggregator::new(vec![field], Vec::new(), &schema);
+                let err = agg.merge_tuple_into_group(&tuples()[0]).unwrap_err();
+                assert!(err.to_string().contains("non-numeric"), "{}", err);
+            }
+        }
+
+        /// Returns the minimum and maximum of a column of values.
+        ///
+        /// # Arguments
+        ///
+        /// * `values` - Values to aggregate.
+        fn min_max(values: Vec<Field>) -> Result<Tuple, CrustyError> {
+            let schema = TableSchema::new(vec![
+                Attribute::new("min".to_string(), DataType::Int),
+                Attribute::new("max".to_string(), DataType::Int),
+            ]);
+            let mut agg = Aggregator::new(
+                vec![
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Min,
+                    },
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Max,
+                    },
+                ],
+                Vec::new(),
+                &schema,
+            );
+            for value in values {
+                agg.merge_tuple_into_group(&Tuple::new(vec![value]))?;
+            }
+            let mut ai = agg.iterator();
+            ai.open()?;
+            Ok(ai.next()?.unwrap())
+        }
+
+        #[test]
+        fn test_merge_tuples_min_max_types() -> Result<(), CrustyError> {
+            let string = |s: &str| Field::StringField(s.to_string());
+            let date = |d| Field::DateField(Date::from_ymd(2024, 1, d).unwrap());
+            let float = |x| Field::FloatField(Float(x));
+            for (values, min, max) in [
+                (
+                    vec![string("pear"), string("apple"), string("zucchini")],
+                    string("apple"),
+                    string("zucchini"),
+                ),
+                (vec![date(15), date(3), date(31)], date(3), date(31)),
+                (
+                    vec![float(2.5), float(-1.25), float(0.0)],
+                    float(-1.25),
+                    float(2.5),
+                ),
+            ] {
+                assert_eq!(Tuple::new(vec![min, max]), min_max(values)?);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_merge_tuples_nulls() -> Result<(), CrustyError> {
+            let schema = TableSchema::new(vec![
+                Attribute::new("count".to_string(), DataType::Int),
+                Attribute::new("min".to_string(), DataType::Int),
+                Attribute::new("avg".to_string(), DataType::Int),
+            ]);
+            let fields = [AggOp::Count, AggOp::Min, AggOp::Avg]
+                .into_iter()
+                .map(|op| AggregateField { field: 1, op })
+                .collect();
+            let mut agg = Aggregator::new(fields, vec![0], &schema).with_sorted_groups();
+            for (group, value) in [
+                (1, Field::Null),
+                (1, Field::IntField(4)),
+                (1, Field::IntField(2)),
+                (2, Field::Null),
+            ] {
+                agg.merge_tuple_into_group(&Tuple::new(vec![Field::IntField(group), value]))?;
+            }
+            let mut ai = agg.iterator();
+            ai.open()?;
+            let mut rows = Vec::new();
+            while let Some(tuple) = ai.next()? {
+                rows.push(tuple.field_vals().cloned().collect::<Vec<Field>>());
+            }
+            let expected = vec![
+                vec![
+                    Field::IntField(1),
+                    Field::IntField(3),
+                    Field::IntField(2),
+                    Field::IntField(3),
+                ],
+                vec![
+                    Field::IntField(2),
+                    Field::IntField(1),
+                    Field::Null,
+                    Field::Null,
+                ],
+            ];
+            assert_eq!(expected, rows);
+            Ok(())
+        }
+
+        #[test]
+        fn test_merge_tuples_mixed_types() {
+            assert!(min_max(vec![
+                Field::StringField("a".to_string()),
+                Field::IntField(1)
+            ])
+            .is_err());
+        }
+
+        #[test]
+        fn test_merge_tuples_float() -> Result<(), CrustyError> {
+            let schema = TableSchema::new(vec![
+                Attribute::new("sum".to_string(), DataType::Float),
+                Attribute::new("avg".to_string(), DataType::Float),
+            ]);
+            let mut agg = Aggregator::new(
+                vec![
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Sum,
+                    },
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Avg,
+                    },
+                ],
+                Vec::new(),
+                &schema,
+            );
+            for x in [0.5, 1.0, 3.0] {
+                agg.merge_tuple_into_group(&Tuple::new(vec![Field::FloatField(Float(x))]))?;
+            }
+
+            let expected = vec![Field::FloatField(Float(4.5)), Field::FloatField(Float(1.5))];
+            let mut ai = agg.iterator();
+            ai.open()?;
+            assert_eq!(Tuple::new(expected), ai.next()?.unwrap());
+            Ok(())
+        }
+
+        #[test]
+        fn test_merge_tuples_decimal() -> Result<(), CrustyError> {
+            let schema = TableSchema::new(vec![
+                Attribute::new("sum".to_string(), DataType::Decimal(10, 2)),
+                Attribute::new("avg".to_string(), DataType::Decimal(10, 2)),
+            ]);
+            let mut agg = Aggregator::new(
+                vec![
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Sum,
+                    },
+                    AggregateField {
+                        field: 0,
+                        op: AggOp::Avg,
+                    },
+                ],
+                Vec::new(),
+                &schema,
+            );
+            for x in [10, 20, 20] {
+                let cents = Field::DecimalField(Decimal::new(x, 2));
+                agg.merge_tuple_into_group(&Tuple::new(vec![cents]))?;
+            }
+
+            let expected = vec![
+                Field::DecimalField(Decimal::new(50, 2)),
+                Field::DecimalField(Decimal::new(17, 2)),
+            ];
+            let mut ai = agg.iterator();
+            ai.open()?;
+            let result = ai.next()?.unwrap();
+            assert_eq!(Tuple::new(expected), result);
+            assert_eq!("0.50", result.get_field(0).unwrap().to_string());
+            Ok(())
         }
 
         #[test]
@@ -508,7 +963,7 @@ mod test {
 
             let ti = tuples();
             for t in &ti {
-                agg.merge_tuple_into_group(t);
+                agg.merge_tuple_into_group(t)?;
             }
 
             let expected = vec![Field::IntField(6), Field::IntField(6)];
@@ -535,7 +990,7 @@ mod test {
 
             let ti = tuples();
             for t in &ti {
-                agg.merge_tuple_into_group(t);
+                agg.merge_tuple_into_group(t)?;
             }
 
             let mut ai = agg.iterator();
@@ -579,7 +1034,7 @@ mod test {
 
             let ti = tuples();
             for t in &ti {
-                agg.merge_tuple_into_group(t);
+                agg.merge_tuple_into_group(t)?;
             }
 
             let mut ai = agg.iterator();
@@ -593,6 +1048,7 @@ mod test {
     mod aggregate {
         use super::super::TupleIterator;
         use super::*;
+        use common::storage_trait::StorageTrait;
         use common::{DataType, Field};
 
         fn tuple_iterator() -> TupleIterator {
@@ -703,9 +1159,9 @@ mod test {
                 vec!["count", "max"],
                 vec![AggOp::Count, AggOp::Max],
                 Box::new(ti),
-            );
-            let mut result = iter_to_vec(&mut ai)?;
-            result.sort();
+            )
+            .with_sorted_groups(true);
+            let result = iter_to_vec(&mut ai)?;
             let expected = vec![
                 vec![
                     Field::IntField(1),
@@ -740,6 +1196,72 @@ mod test {
             Ok(())
         }
 
+        #[test]
+        fn test_expressions() -> Result<(), CrustyError> {
+            use common::logical_plan::ArithmeticOp;
+            let arithmetic = |field, op, value| {
+                Expression::Arithmetic(
+                    Box::new(Expression::Field(field)),
+                    op,
+                    Box::new(Expression::Literal(Field::IntField(value))),
+                )
+            };
+            let mut ai = Aggregate::new_with_expressions(
+                vec![arithmetic(0, ArithmeticOp::Modulo, 2)],
+                vec!["parity"],
+                vec![
+                    arithmetic(1, ArithmeticOp::Multiply, 10),
+                    Expression::Field(3),
+                ],
+                vec!["sum", "count"],
+                vec![AggOp::Sum, AggOp::Count],
+                Box::new(tuple_iterator()),
+            );
+            let explain = ai.describe().to_string();
+            assert!(explain.contains("group by: 1 % 2"), "{}", explain);
+            assert!(explain.contains("sum(2 * 10), count(4)"), "{}", explain);
+            let mut result = iter_to_vec(&mut ai)?;
+            result.sort();
+            let expected = vec![
+                vec![Field::IntField(0), Field::IntField(50), Field::IntField(3)],
+                vec![Field::IntField(1), Field::IntField(40), Field::IntField(3)],
+            ];
+            assert_eq!(expected, result);
+            Ok(())
+        }
+
+        #[test]
+        fn test_spill_results() -> Result<(), CrustyError> {
+            let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+            let groups = |ti| {
+                Aggregate::new(
+                    vec![2],
+                    vec!["group"],
+                    vec![0],
+                    vec!["sum"],
+                    vec![AggOp::Sum],
+                    Box::new(ti),
+                )
+            };
+            let mut expected = iter_to_vec(&mut groups(tuple_iterator()))?;
+            expected.sort();
+            let mut ai = groups(tuple_iterator()).spill_results(
+                sm,
+                0,
+                &MemoryBudget::new(0),
+                TransactionId::new(),
+            )?;
+            let mut result = iter_to_vec(&mut ai)?;
+            assert_eq!(3, ai.results.as_ref().unwrap().spilled());
+            result.sort();
+            assert_eq!(expected, result);
+            ai.open()?;
+            num_tuples(&mut ai)?;
+            ai.rewind()?;
+            assert_eq!(3, num_tuples(&mut ai)?);
+            Ok(())
+        }
+
         #[test]
         #[should_panic]
         fn test_next_not_open() {
@@ -844,5 +1366,50 @@ mod test {
                 assert_eq!(DataType::Int, *attr.dtype());
             }
         }
+
+        #[test]
+        fn test_sorted_string_groups() -> Result<(), CrustyError> {
+            let mut ai = Aggregate::new(
+                vec![3],
+                vec!["group"],
+                vec![0],
+                vec!["count"],
+                vec![AggOp::Count],
+                Box::new(tuple_iterator()),
+            )
+            .with_sorted_groups(true);
+            let expected = vec![
+                vec![Field::StringField("A".to_string()), Field::IntField(1)],
+                vec![Field::StringField("E".to_string()), Field::IntField(1)],
+                vec![Field::StringField("G".to_string()), Field::IntField(4)],
+            ];
+            for _ in 0..2 {
+                assert_eq!(expected, iter_to_vec(&mut ai)?);
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn test_typed_schema() {
+            let ai = Aggregate::new(
+                vec![3],
+                vec!["group"],
+                vec![3, 3, 0, 0],
+                vec!["min", "count", "avg", "max"],
+                vec![AggOp::Min, AggOp::Count, AggOp::Avg, AggOp::Max],
+                Box::new(tuple_iterator()),
+            );
+            let dtypes: Vec<&DataType> = ai.get_schema().attributes().map(|a| a.dtype()).collect();
+            assert_eq!(
+                vec![
+                    &DataType::String,
+                    &DataType::String,
+                    &DataType::Int,
+                    &DataType::Int,
+                    &DataType::Int
+                ],
+                dtypes
+            );
+        }
     }
 }
@@ -0,0 +1,195 @@
+use super::{OpIterator, PlanNode, SeqScan};
+use crate::StorageManager;
+use common::bitmap_index::BitmapQuery;
+use common::ids::{ContainerId, Permissions, TransactionId, ValueId};
+use common::storage_trait::StorageTrait;
+use common::table::*;
+use common::{CrustyError, TableSchema, Tuple};
+use std::sync::{Arc, RwLock};
+
+/// Bitmap scan operator. Combines the bitmaps of bitmap indexes over a table into the set of
+/// records that satisfy a query, then fetches those records in storage order.
+pub struct BitmapScan {
+    schema: TableSchema,
+    /// Scanned table, used to pad records written under older schema versions.
+    table: Table,
+    table_id: ContainerId,
+    alias: String,
+    query: BitmapQuery,
+    /// Records that satisfy the query, found when the scan is opened.
+    value_ids: Vec<ValueId>,
+    /// Position of the next record in value_ids.
+    next: usize,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    transaction_id: TransactionId,
+}
+
+impl BitmapScan {
+    /// Constructor for the bitmap scan operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Table to scan over.
+    /// * `table_id` - Container of the table.
+    /// * `table_alias` - Table alias given by the user.
+    /// * `query` - Key ranges of bitmap indexes over the table the records satisfy.
+    /// * `tid` - Transaction used to read the indexes and the table.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        table: Arc<RwLock<Table>>,
+        table_id: ContainerId,
+        table_alias: &str,
+        query: BitmapQuery,
+        tid: TransactionId,
+    ) -> Self {
+        let table = table.read().unwrap().clone();
+        Self {
+            schema: SeqScan::schema(&table.schema, table_alias),
+            table,
+            table_id,
+            alias: table_alias.to_string(),
+            query,
+            value_ids: Vec::new(),
+            next: 0,
+            open: false,
+            storage_manager,
+            transaction_id: tid,
+        }
+    }
+}
+
+impl OpIterator for BitmapScan {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        let records = self.query.evaluate(
+            self.storage_manager.as_ref(),
+            self.table_id,
+            self.transaction_id,
+        )?;
+        self.value_ids = records.value_ids();
+        self.next = 0;
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        let value_id = match self.value_ids.get(self.next) {
+            Some(value_id) => *value_id,
+            None => return Ok(None),
+        };
+        self.next += 1;
+        let bytes =
+            self.storage_manager
+                .get_value(value_id, self.transaction_id, Permissions::ReadOnly)?;
+        let mut tuple = self.table.read_tuple(&bytes);
+        tuple.value_id = Some(value_id);
+        Ok(Some(tuple))
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.open = false;
+        self.value_ids.clear();
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.next = 0;
+        Ok(())
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("BitmapScan")
+            .param("table", &self.alias)
+            .param("query", &self.query)
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use common::index::{IndexInfo, KeyRange};
+    use common::testutil::get_int_table_schema;
+    use common::Field;
+    use std::ops::Bound;
+
+    const TABLE: &str = "BitmapScan";
+
+    /// Scans a table of (i % 4, i % 5, i) for i in 0..40 with bitmap indexes on its first two
+    /// columns.
+    fn get_scan(query: impl Fn(&IndexInfo, &IndexInfo) -> BitmapQuery) -> BitmapScan {
+        let rows = (0..40).map(|i| vec![i % 4, i % 5, i]).collect();
+        let (sm, _) = test_table(1, rows);
+        let a = test_bitmap_index(&sm, 1, 2, vec![0]);
+        let b = test_bitmap_index(&sm, 1, 3, vec![1]);
+        let table = Table::new(TABLE.to_string(), get_int_table_schema(3));
+        BitmapScan::new(
+            sm,
+            Arc::new(RwLock::new(table)),
+            1,
+            TABLE,
+            query(&a, &b),
+            TransactionId::new(),
+        )
+    }
+
+    fn equals(index: &IndexInfo, value: i32) -> BitmapQuery {
+        BitmapQuery::Keys(index.clone(), KeyRange::exact(vec![Field::IntField(value)]))
+    }
+
+    fn scanned(scan: &mut BitmapScan) -> Result<Vec<i32>, CrustyError> {
+        let mut values = Vec::new();
+        while let Some(tuple) = scan.next()? {
+            values.push(match tuple.get_field(2) {
+                Some(Field::IntField(i)) => *i,
+                field => panic!("Unexpected field {:?}", field),
+            });
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_and() -> Result<(), CrustyError> {
+        let mut scan = get_scan(|a, b| BitmapQuery::And(vec![equals(a, 1), equals(b, 2)]));
+        scan.open()?;
+        assert_eq!(vec![17, 37], scanned(&mut scan)?);
+        scan.rewind()?;
+        assert_eq!(vec![17, 37], scanned(&mut scan)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_or_range() -> Result<(), CrustyError> {
+        let mut scan = get_scan(|a, b| {
+            let high = KeyRange {
+                prefix: Vec::new(),
+                lower: Bound::Excluded(Field::IntField(3)),
+                upper: Bound::Unbounded,
+            };
+            BitmapQuery::Or(vec![equals(a, 0), BitmapQuery::Keys(b.clone(), high)])
+        });
+        scan.open()?;
+        let expected: Vec<i32> = (0..40).filter(|i| i % 4 == 0 || i % 5 == 4).collect();
+        assert_eq!(expected, scanned(&mut scan)?);
+        assert!(scan.describe().to_string().contains("BitmapScan"));
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut scan = get_scan(|a, _| equals(a, 1));
+        scan.next();
+    }
+}
@@ -0,0 +1,207 @@
+use super::{OpIterator, PlanNode, SeqScan};
+use crate::StorageManager;
+use common::columnar::{self, RowGroup};
+use common::ids::Permissions;
+use common::ids::{ContainerId, TransactionId};
+use common::storage_trait::StorageTrait;
+use common::table::Table;
+use common::{CrustyError, TableSchema, Tuple};
+use std::sync::{Arc, RwLock};
+use std::vec;
+
+/// Scan operator over a columnar table. Reads only the chunks of the requested columns;
+/// the other columns of the returned tuples are null.
+pub struct ColumnScan {
+    group_iter: <StorageManager as StorageTrait>::ValIterator,
+    /// Tuples of the current row group not returned yet.
+    rows: vec::IntoIter<Tuple>,
+    schema: TableSchema,
+    /// Indices of the columns to read.
+    columns: Vec<usize>,
+    alias: String,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    container_id: ContainerId,
+    transaction_id: TransactionId,
+}
+
+impl ColumnScan {
+    /// Constructor for the columnar scan operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Table to scan over.
+    /// * `table_alias` - Table alias given by the user.
+    /// * `container_id` - Container holding the row groups of the table.
+    /// * `columns` - Indices of the columns to read.
+    /// * `tid` - Transaction used to read the table.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        table: Arc<RwLock<Table>>,
+        table_alias: &str,
+        container_id: &ContainerId,
+        columns: Vec<usize>,
+        tid: TransactionId,
+    ) -> Self {
+        let table_ref = table.read().unwrap();
+        let group_iter = storage_manager.get_iterator(*container_id, tid, Permissions::ReadOnly);
+        Self {
+            group_iter,
+            rows: Vec::new().into_iter(),
+            schema: SeqScan::schema(&table_ref.schema, table_alias),
+            columns,
+            alias: table_alias.to_string(),
+            open: false,
+            storage_manager,
+            container_id: *container_id,
+            transaction_id: tid,
+        }
+    }
+}
+
+impl OpIterator for ColumnScan {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        loop {
+            if let Some(tuple) = self.rows.next() {
+                return Ok(Some(tuple));
+            }
+            let (bytes, value_id) = match self.group_iter.next() {
+                Some(group) => group,
+                None => return Ok(None),
+            };
+            let mut rows = columnar::read_group(
+                self.storage_manager.as_ref(),
+                &RowGroup::from_bytes(&bytes),
+                &self.columns,
+                self.transaction_id,
+            )?;
+            for tuple in rows.iter_mut() {
+                tuple.value_id = Some(value_id);
+            }
+            self.rows = rows.into_iter();
+        }
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.group_iter = self.storage_manager.get_iterator(
+            self.container_id,
+            self.transaction_id,
+            Permissions::ReadOnly,
+        );
+        self.rows = Vec::new().into_iter();
+        Ok(())
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        let columns: Vec<&str> = self
+            .columns
+            .iter()
+            .filter_map(|i| self.schema.get_attribute(*i))
+            .map(|a| a.name())
+            .collect();
+        PlanNode::new("ColumnScan")
+            .param("table", &self.alias)
+            .param("container", self.container_id)
+            .param("columns", columns.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::sum_int_fields;
+    use common::columnar::CHUNK_ROWS;
+    use common::ids::StateType;
+    use common::table::TableLayout;
+    use common::testutil::*;
+    use common::Field;
+
+    const TABLE: &str = "ColumnScan";
+    const ROWS: usize = 2 * CHUNK_ROWS + 5;
+
+    /// Creates a columnar table with three int columns holding 1, 2 and 3 in every row.
+    fn get_table() -> (Arc<StorageManager>, Arc<RwLock<Table>>, ContainerId) {
+        let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+        let cid = 0;
+        let columns = vec![1, 2, 3];
+        for column in &columns {
+            sm.create_container(*column, None, StateType::Column, None)
+                .unwrap();
+        }
+        sm.create_container(cid, None, StateType::BaseTable, Some(columns.clone()))
+            .unwrap();
+        let mut table = Table::new(TABLE.to_string(), get_int_table_schema(3));
+        table.layout = TableLayout::Column(columns.clone());
+        let tuples: Vec<Tuple> = (0..ROWS).map(|_| int_vec_to_tuple(vec![1, 2, 3])).collect();
+        let ids =
+            columnar::insert(sm.as_ref(), cid, &columns, &tuples, TransactionId::new()).unwrap();
+        assert_eq!(ROWS, ids.len());
+        (sm, Arc::new(RwLock::new(table)), cid)
+    }
+
+    #[test]
+    fn test_next() -> Result<(), CrustyError> {
+        let (sm, table, cid) = get_table();
+        let tid = TransactionId::new();
+        let mut scan = ColumnScan::new(sm, table, TABLE, &cid, vec![0, 1, 2], tid);
+        scan.open()?;
+        assert_eq!(6 * ROWS as i32, sum_int_fields(&mut scan)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_requested_columns() -> Result<(), CrustyError> {
+        let (sm, table, cid) = get_table();
+        let tid = TransactionId::new();
+        let mut scan = ColumnScan::new(sm, table, TABLE, &cid, vec![1], tid);
+        scan.open()?;
+        let mut count = 0;
+        while let Some(tuple) = scan.next()? {
+            assert_eq!(
+                vec![Field::Null, Field::IntField(2), Field::Null],
+                tuple.field_vals
+            );
+            count += 1;
+        }
+        assert_eq!(ROWS, count);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewind() -> Result<(), CrustyError> {
+        let (sm, table, cid) = get_table();
+        let tid = TransactionId::new();
+        let mut scan = ColumnScan::new(sm, table, TABLE, &cid, Vec::new(), tid);
+        scan.open()?;
+        let mut count = 0;
+        while scan.next()?.is_some() {
+            count += 1;
+        }
+        scan.rewind()?;
+        while scan.next()?.is_some() {
+            count += 1;
+        }
+        assert_eq!(2 * ROWS, count);
+        Ok(())
+    }
+}
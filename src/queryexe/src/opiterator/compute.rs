@@ -0,0 +1,247 @@
+use super::{Expression, OpIterator, PlanNode};
+use common::{Attribute, CrustyError, Field, TableSchema, Tuple};
+
+/// Compute operator, which evaluates expressions over each tuple of its child, such as the
+/// `a + b` and `upper(c)` of `SELECT a + b, upper(c) FROM t`.
+///
+/// The value of each expression is appended to the tuple or replaces one of its fields.
+/// Expressions read the fields of the child's tuple, not the values computed before them.
+pub struct Compute {
+    /// Expressions to evaluate, with the index of the field each replaces or None if its
+    /// value is appended.
+    columns: Vec<(Option<usize>, Expression)>,
+    /// Schema of the child with the computed columns.
+    schema: TableSchema,
+    /// Boolean determining if iterator is open.
+    open: bool,
+    /// Child operator passing data into the operator.
+    child: Box<dyn OpIterator>,
+}
+
+impl Compute {
+    /// Compute constructor. Returns the tuples of the child unchanged until columns are
+    /// added with `with_column` and `with_replacement`.
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - Child OpIterator passing data into the operator.
+    pub fn new(child: Box<dyn OpIterator>) -> Self {
+        Self {
+            columns: Vec::new(),
+            schema: child.get_schema().clone(),
+            open: false,
+            child,
+        }
+    }
+
+    /// Appends the value of an expression to each tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the computed column.
+    /// * `expr` - Expression over the fields of the child's tuples.
+    pub fn with_column(mut self, name: &str, expr: Expression) -> Self {
+        let dtype = expr.dtype(self.child.get_schema());
+        let mut attributes: Vec<Attribute> = self.schema.attributes().cloned().collect();
+        attributes.push(Attribute::new(name.to_string(), dtype));
+        self.schema = TableSchema::new(attributes);
+        self.columns.push((None, expr));
+        self
+    }
+
+    /// Replaces a field of each tuple with the value of an expression, keeping the name of
+    /// the field.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the field to replace.
+    /// * `expr` - Expression over the fields of the child's tuples.
+    pub fn with_replacement(mut self, index: usize, expr: Expression) -> Self {
+        let dtype = expr.dtype(self.child.get_schema());
+        let mut attributes: Vec<Attribute> = self.schema.attributes().cloned().collect();
+        attributes[index] = Attribute::new(attributes[index].name().to_string(), dtype);
+        self.schema = TableSchema::new(attributes);
+        self.columns.push((Some(index), expr));
+        self
+    }
+}
+
+impl OpIterator for Compute {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.child.open()?;
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        let tuple = match self.child.next()? {
+            Some(tuple) => tuple,
+            None => return Ok(None),
+        };
+        let mut fields: Vec<Field> = tuple.field_vals().cloned().collect();
+        for (index, expr) in &self.columns {
+            let value = expr.evaluate(&tuple)?;
+            match index {
+                Some(i) => fields[*i] = value,
+                None => fields.push(value),
+            }
+        }
+        Ok(Some(Tuple::new(fields)))
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.close()?;
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.rewind()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        let child_schema = self.child.get_schema();
+        let mut appended = child_schema.size();
+        let mut columns = Vec::new();
+        for (index, expr) in &self.columns {
+            let i = index.unwrap_or_else(|| {
+                appended += 1;
+                appended - 1
+            });
+            let name = self.schema.get_attribute(i).map_or("", |a| a.name());
+            columns.push(format!("{} = {}", name, expr.describe(child_schema)));
+        }
+        PlanNode::new("Compute")
+            .param("columns", columns.join(", "))
+            .child(self.child.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TupleIterator;
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use common::logical_plan::{ArithmeticOp, ScalarFunction};
+    use common::testutil::*;
+    use common::{DataType, SimplePredicateOp};
+
+    fn compute() -> Compute {
+        let schema = TableSchema::from_vecs(
+            vec!["a", "b", "c"],
+            vec![DataType::Int, DataType::Int, DataType::String],
+        );
+        let tuples = vec![
+            Tuple::new(vec![
+                Field::IntField(1),
+                Field::IntField(2),
+                Field::StringField("x".to_string()),
+            ]),
+            Tuple::new(vec![
+                Field::IntField(5),
+                Field::IntField(3),
+                Field::StringField("y".to_string()),
+            ]),
+        ];
+        let field = |i| Box::new(Expression::Field(i));
+        Compute::new(Box::new(TupleIterator::new(tuples, schema)))
+            .with_column(
+                "sum",
+                Expression::Arithmetic(field(0), ArithmeticOp::Add, field(1)),
+            )
+            .with_column(
+                "bigger",
+                Expression::Compare(field(0), SimplePredicateOp::GreaterThan, field(1)),
+            )
+            .with_replacement(
+                2,
+                Expression::Function(ScalarFunction::Upper, vec![Expression::Field(2)]),
+            )
+    }
+
+    #[test]
+    fn test_compute() -> Result<(), CrustyError> {
+        let mut op = compute();
+        let schema = op.get_schema();
+        let dtypes: Vec<&DataType> = schema.attributes().map(|a| a.dtype()).collect();
+        assert_eq!(
+            vec![
+                &DataType::Int,
+                &DataType::Int,
+                &DataType::String,
+                &DataType::Int,
+                &DataType::Boolean
+            ],
+            dtypes
+        );
+        assert_eq!("c", schema.get_attribute(2).unwrap().name());
+        op.open()?;
+        assert_eq!(
+            Tuple::new(vec![
+                Field::IntField(1),
+                Field::IntField(2),
+                Field::StringField("X".to_string()),
+                Field::IntField(3),
+                Field::BoolField(false),
+            ]),
+            op.next()?.unwrap()
+        );
+        assert_eq!(
+            vec![
+                Field::IntField(5),
+                Field::IntField(3),
+                Field::StringField("Y".to_string()),
+                Field::IntField(8),
+                Field::BoolField(true),
+            ],
+            op.next()?
+                .unwrap()
+                .field_vals()
+                .cloned()
+                .collect::<Vec<Field>>()
+        );
+        assert_eq!(None, op.next()?);
+        op.rewind()?;
+        assert_eq!(2, num_tuples(&mut op)?);
+        op.close()
+    }
+
+    #[test]
+    fn test_describe() {
+        let explain = compute().describe().to_string();
+        assert!(
+            explain.contains("sum = a + b, bigger = a > b, c = upper(c)"),
+            "{}",
+            explain
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut op = compute();
+        op.next().unwrap();
+    }
+
+    #[test]
+    fn test_empty() -> Result<(), CrustyError> {
+        let ti = TupleIterator::new(Vec::new(), get_int_table_schema(1));
+        let mut op = Compute::new(Box::new(ti)).with_column("a", Expression::Field(0));
+        op.open()?;
+        assert_eq!(None, op.next()?);
+        op.close()
+    }
+}
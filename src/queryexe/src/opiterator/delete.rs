@@ -0,0 +1,322 @@
+use super::{OpIterator, PlanNode};
+use crate::StorageManager;
+use common::foreign_key::{self, ForeignKeys};
+use common::index::{self, IndexInfo};
+use common::prelude::*;
+use common::storage_trait::StorageTrait;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Delete operator. Deletes the tuples of its child from a table and returns the number of
+/// deleted tuples.
+pub struct Delete {
+    /// Schema of the result, a single count column.
+    schema: TableSchema,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    container_id: ContainerId,
+    /// Indexes of the table, which lose the entries of the deleted tuples.
+    indexes: Vec<IndexInfo>,
+    /// Foreign keys referring to the table, which restrict the deletes or cascade them.
+    foreign_keys: ForeignKeys,
+    tid: TransactionId,
+    child: Box<dyn OpIterator>,
+    /// Number of deleted tuples, once they are deleted.
+    count: Option<usize>,
+    /// Whether the count was returned since the operator was opened.
+    done: bool,
+}
+
+impl Delete {
+    /// Constructor for the delete operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_manager` - Storage manager holding the table.
+    /// * `container_id` - Container of the table to delete from.
+    /// * `indexes` - Indexes of the table to maintain.
+    /// * `foreign_keys` - Foreign keys of the table to enforce.
+    /// * `tid` - Transaction used to delete the tuples.
+    /// * `child` - Child operator producing the tuples to delete. The tuples must have their
+    ///   value ids set, as the tuples of a scan do.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        container_id: &ContainerId,
+        indexes: Vec<IndexInfo>,
+        foreign_keys: ForeignKeys,
+        tid: TransactionId,
+        child: Box<dyn OpIterator>,
+    ) -> Self {
+        Self {
+            schema: TableSchema::from_vecs(vec!["count"], vec![DataType::Int]),
+            open: false,
+            storage_manager,
+            container_id: *container_id,
+            indexes,
+            foreign_keys,
+            tid,
+            child,
+            count: None,
+            done: false,
+        }
+    }
+
+    /// Deletes all the tuples of the child and returns their count.
+    ///
+    /// The child is read to the end before anything is deleted, so a child scanning the
+    /// table is not affected by the deletes. The records of child tables the deletes cascade
+    /// to are deleted first, and are not counted.
+    fn delete(&mut self) -> Result<usize, CrustyError> {
+        let mut ids = Vec::new();
+        let mut tuples = Vec::new();
+        while let Some(tuple) = self.child.next()? {
+            match tuple.value_id {
+                Some(id) => ids.push(id),
+                None => {
+                    return Err(CrustyError::CrustyError(
+                        "No value id set for record. Cannot delete".to_string(),
+                    ));
+                }
+            }
+            if !self.foreign_keys.children.is_empty() {
+                tuples.push(tuple);
+            }
+        }
+        foreign_key::delete_children(
+            self.storage_manager.as_ref(),
+            &self.foreign_keys,
+            &tuples,
+            self.tid,
+        )?;
+        self.storage_manager.delete_values(ids.clone(), self.tid)?;
+        let deleted: HashSet<ValueId> = ids.iter().copied().collect();
+        for index in &self.indexes {
+            index::remove_entries(self.storage_manager.as_ref(), index, &deleted, self.tid)?;
+        }
+        Ok(ids.len())
+    }
+}
+
+impl OpIterator for Delete {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.open = true;
+        self.done = false;
+        self.child.open()
+    }
+
+    /// Returns the number of deleted tuples, deleting them on the first call.
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let count = match self.count {
+            Some(count) => count,
+            None => self.delete()?,
+        };
+        self.count = Some(count);
+        self.done = true;
+        Ok(Some(Tuple::new(vec![Field::IntField(count as i32)])))
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.child.close()?;
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.rewind()?;
+        self.close()?;
+        self.open()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Delete")
+            .param("container", self.container_id)
+            .child(self.child.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use crate::opiterator::{Filter, TupleIterator};
+    use common::foreign_key::OnDelete;
+    use common::testutil::*;
+    use common::SimplePredicateOp;
+
+    /// Deletes the rows of table 1 whose first column equals `a`.
+    fn delete_where_a(
+        sm: Arc<StorageManager>,
+        foreign_keys: ForeignKeys,
+        a: i32,
+    ) -> Result<Option<Tuple>, CrustyError> {
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let scan = TupleIterator::new(stored_tuples(&sm, 1), schema);
+        let filter = Filter::new(
+            SimplePredicateOp::Equals,
+            0,
+            Field::IntField(a),
+            Box::new(scan),
+        );
+        let mut delete = Delete::new(
+            sm,
+            &1,
+            Vec::new(),
+            foreign_keys,
+            TransactionId::new(),
+            Box::new(filter),
+        );
+        delete.open()?;
+        delete.next()
+    }
+
+    #[test]
+    fn test_delete() -> Result<(), CrustyError> {
+        let (sm, _) = test_table(1, vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let scan = TupleIterator::new(stored_tuples(&sm, 1), schema);
+        let filter = Filter::new(
+            SimplePredicateOp::GreaterThan,
+            0,
+            Field::IntField(1),
+            Box::new(scan),
+        );
+        let mut delete = Delete::new(
+            sm.clone(),
+            &1,
+            Vec::new(),
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(filter),
+        );
+        delete.open()?;
+        assert_eq!(Some(int_vec_to_tuple(vec![2])), delete.next()?);
+        assert_eq!(None, delete.next()?);
+        delete.rewind()?;
+        assert_eq!(Some(int_vec_to_tuple(vec![2])), delete.next()?);
+        delete.close()?;
+
+        let stored = stored_tuples(&sm, 1);
+        assert_eq!(1, stored.len());
+        assert_eq!(
+            vec![Field::IntField(1), Field::IntField(2)],
+            stored[0].field_vals
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_without_value_ids() {
+        let (sm, _) = test_table(1, vec![vec![1, 2]]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let child = TupleIterator::new(create_tuple_list(vec![vec![1, 2]]), schema);
+        let mut delete = Delete::new(
+            sm.clone(),
+            &1,
+            Vec::new(),
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(child),
+        );
+        delete.open().unwrap();
+        assert!(delete.next().is_err());
+        assert_eq!(1, stored_tuples(&sm, 1).len());
+    }
+
+    #[test]
+    fn test_delete_maintains_index() -> Result<(), CrustyError> {
+        let (sm, _) = test_table(1, vec![vec![1, 2], vec![3, 4], vec![5, 4]]);
+        let index = test_index(&sm, 1, 2, vec![1]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let scan = TupleIterator::new(stored_tuples(&sm, 1), schema);
+        let filter = Filter::new(
+            SimplePredicateOp::Equals,
+            0,
+            Field::IntField(3),
+            Box::new(scan),
+        );
+        let mut delete = Delete::new(
+            sm.clone(),
+            &1,
+            vec![index.clone()],
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(filter),
+        );
+        delete.open()?;
+        delete.next()?;
+        delete.close()?;
+
+        let found = index_lookup(&sm, &index, vec![4]);
+        assert_eq!(1, found.len());
+        assert_eq!(int_vec_to_tuple(vec![5, 4]).field_vals, found[0].field_vals);
+        assert_eq!(1, index_lookup(&sm, &index, vec![2]).len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_restrict() -> Result<(), CrustyError> {
+        let (sm, _) = test_table(1, vec![vec![1, 10], vec![2, 20]]);
+        let mut parent_index = test_index(&sm, 1, 2, vec![0]);
+        parent_index.unique = true;
+        let (_, foreign_keys) = test_foreign_key(
+            &sm,
+            &parent_index,
+            3,
+            vec![vec![7, 1], vec![8, 1]],
+            1,
+            OnDelete::Restrict,
+        );
+        assert!(matches!(
+            delete_where_a(sm.clone(), foreign_keys.clone(), 1),
+            Err(CrustyError::ConstraintViolation(_))
+        ));
+        assert_eq!(2, stored_tuples(&sm, 1).len());
+        assert_eq!(2, stored_tuples(&sm, 3).len());
+
+        assert_eq!(
+            Some(int_vec_to_tuple(vec![1])),
+            delete_where_a(sm.clone(), foreign_keys, 2)?
+        );
+        assert_eq!(1, stored_tuples(&sm, 1).len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_cascade() -> Result<(), CrustyError> {
+        let (sm, _) = test_table(1, vec![vec![1, 10], vec![2, 20]]);
+        let mut parent_index = test_index(&sm, 1, 2, vec![0]);
+        parent_index.unique = true;
+        let rows = vec![vec![7, 1], vec![8, 2], vec![9, 1]];
+        let (_, mut foreign_keys) =
+            test_foreign_key(&sm, &parent_index, 3, rows, 1, OnDelete::Cascade);
+        let child_index = test_index(&sm, 3, 4, vec![1]);
+        foreign_keys.children[0].indexes.push(child_index.clone());
+        // The child rows cascaded to are not counted.
+        assert_eq!(
+            Some(int_vec_to_tuple(vec![1])),
+            delete_where_a(sm.clone(), foreign_keys, 1)?
+        );
+        let stored = stored_tuples(&sm, 3);
+        assert_eq!(1, stored.len());
+        assert_eq!(
+            int_vec_to_tuple(vec![8, 2]).field_vals,
+            stored[0].field_vals
+        );
+        assert!(index_lookup(&sm, &child_index, vec![1]).is_empty());
+        assert_eq!(1, index_lookup(&sm, &child_index, vec![2]).len());
+        Ok(())
+    }
+}
@@ -0,0 +1,368 @@
+use super::OpIterator;
+use common::metrics::MetricsRegistry;
+use common::{CrustyError, TableSchema, Tuple};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Description of an operator in a plan, as printed by EXPLAIN.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlanNode {
+    /// Name of the operator.
+    pub name: String,
+    /// Parameters of the operator as (name, value) pairs.
+    pub params: Vec<(String, String)>,
+    /// Number of rows the planner expects the operator to produce, if known.
+    pub estimated_rows: Option<u64>,
+    /// What the operator did while it ran, if it was profiled.
+    pub counters: Option<OpCounters>,
+    /// Descriptions of the operator's children.
+    pub children: Vec<PlanNode>,
+}
+
+/// Runtime counters of an operator.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OpCounters {
+    /// Number of tuples returned by next().
+    pub rows: u64,
+    /// Time spent in next(), including the time spent in the children's next(). Operators
+    /// that read their input outside of next(), such as hash joins when they are built and
+    /// aggregates when they are opened, do not count that work, but their children do.
+    pub next_time: Duration,
+}
+
+impl PlanNode {
+    /// Creates a description with no parameters or children.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the operator.
+    pub fn new(name: &str) -> Self {
+        PlanNode {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds a parameter to the description.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the parameter.
+    /// * `value` - Value of the parameter.
+    pub fn param<V: fmt::Display>(mut self, name: &str, value: V) -> Self {
+        self.params.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds a child to the description.
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - Operator whose description is added.
+    pub fn child(mut self, child: &dyn OpIterator) -> Self {
+        self.children.push(child.describe());
+        self
+    }
+
+    /// Removes the runtime counters from the description and its children.
+    pub fn clear_counters(&mut self) {
+        self.counters = None;
+        for child in self.children.iter_mut() {
+            child.clear_counters();
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        if depth > 0 {
+            write!(f, "{:width$}-> ", "", width = 2 * (depth - 1))?;
+        }
+        write!(f, "{}", self.name)?;
+        if !self.params.is_empty() {
+            let params: Vec<String> = self
+                .params
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            write!(f, " ({})", params.join(", "))?;
+        }
+        if let Some(rows) = self.estimated_rows {
+            write!(f, " [estimated rows: {}]", rows)?;
+        }
+        if let Some(counters) = self.counters {
+            write!(
+                f,
+                " [actual rows: {}, time: {:.3} ms]",
+                counters.rows,
+                counters.next_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        for child in &self.children {
+            writeln!(f)?;
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PlanNode {
+    /// Prints the plan as an indented tree, one operator per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Returns the name of a column for plan descriptions.
+///
+/// # Arguments
+///
+/// * `schema` - Schema the column belongs to.
+/// * `index` - Index of the column.
+pub(crate) fn column_name(schema: &TableSchema, index: usize) -> String {
+    match schema.get_attribute(index) {
+        Some(attr) if !attr.name().is_empty() => attr.name().to_string(),
+        _ => format!("#{}", index),
+    }
+}
+
+/// How the operators of a plan are profiled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Profiling {
+    /// Operators are profiled only with the `profile` feature, which records their runs in
+    /// the metrics registry.
+    #[default]
+    Metrics,
+    /// Operators keep the rows the planner expects them to produce, for EXPLAIN.
+    Explain,
+    /// Operators also count the time spent producing their rows, for EXPLAIN ANALYZE.
+    Analyze,
+}
+
+impl Profiling {
+    /// Returns whether the operators are wrapped in a `Profile`.
+    pub fn profiles(self) -> bool {
+        self != Profiling::Metrics || cfg!(feature = "profile")
+    }
+
+    /// Wraps an operator in a `Profile` if the operators are profiled.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - Operator to profile.
+    /// * `estimated_rows` - Number of rows the planner expects the operator to produce.
+    /// * `metrics` - Registry the runs of the operator are recorded in with the `profile` feature.
+    pub fn wrap(
+        self,
+        op: Box<dyn OpIterator>,
+        estimated_rows: Option<f64>,
+        metrics: Option<&Arc<MetricsRegistry>>,
+    ) -> Box<dyn OpIterator> {
+        if !self.profiles() {
+            return op;
+        }
+        let mut profile = Profile::new(op, estimated_rows);
+        if self == Profiling::Analyze {
+            profile = profile.with_timing();
+        }
+        match metrics {
+            Some(registry) if cfg!(feature = "profile") => {
+                Box::new(profile.with_metrics(registry.clone()))
+            }
+            _ => Box::new(profile),
+        }
+    }
+}
+
+/// Operator that counts the tuples its child returns and, if it is timed, the time spent
+/// producing them.
+///
+/// The executor wraps the operators it builds in a `Profile` when it explains a plan, so
+/// that the description of the plan includes the estimated and actual rows of each
+/// operator, and with the `profile` feature, so that each run of the operator is added to
+/// a metrics registry when it is closed. See [`Profiling`].
+pub struct Profile {
+    /// Profiled operator.
+    child: Box<dyn OpIterator>,
+    /// Number of rows the planner expects the child to produce, if known.
+    estimated_rows: Option<u64>,
+    /// Counters accumulated since the operator was created.
+    counters: OpCounters,
+    /// Registry the runs are recorded in, with the name of the operator.
+    metrics: Option<(Arc<MetricsRegistry>, String)>,
+    /// Counters when the operator was opened, if it was not closed since.
+    opened: Option<OpCounters>,
+    /// Whether the time spent in next() is counted.
+    timed: bool,
+}
+
+impl Profile {
+    /// Creates a profiled operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `child` - Operator to profile.
+    /// * `estimated_rows` - Number of rows the planner expects the child to produce.
+    pub fn new(child: Box<dyn OpIterator>, estimated_rows: Option<f64>) -> Self {
+        Profile {
+            child,
+            estimated_rows: estimated_rows.map(|rows| rows.round() as u64),
+            counters: OpCounters::default(),
+            metrics: None,
+            opened: None,
+            timed: false,
+        }
+    }
+
+    /// Counts the time spent in next(), which reads the clock twice per tuple.
+    pub fn with_timing(mut self) -> Self {
+        self.timed = true;
+        self
+    }
+
+    /// Records the rows and time of each run of the operator in a registry. The operator
+    /// is timed.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry of the storage manager the plan runs against.
+    pub fn with_metrics(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        self.metrics = Some((registry, self.child.describe().name));
+        self.with_timing()
+    }
+}
+
+impl OpIterator for Profile {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.opened = Some(self.counters);
+        self.child.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        let start = self.timed.then(Instant::now);
+        let res = self.child.next();
+        if let Some(start) = start {
+            self.counters.next_time += start.elapsed();
+        }
+        if let Ok(Some(_)) = res {
+            self.counters.rows += 1;
+        }
+        res
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        if let (Some((registry, name)), Some(opened)) = (&self.metrics, self.opened.take()) {
+            registry.record_operator(
+                name,
+                self.counters.rows - opened.rows,
+                self.counters.next_time - opened.next_time,
+            );
+        }
+        self.child.close()
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        self.child.rewind()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.child.get_schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        let mut node = self.child.describe();
+        node.estimated_rows = self.estimated_rows.or(node.estimated_rows);
+        node.counters = Some(self.counters);
+        node
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::{Filter, TupleIterator};
+    use common::testutil::*;
+    use common::{DataType, Field, SimplePredicateOp};
+
+    fn scan() -> Box<dyn OpIterator> {
+        let tuples = create_tuple_list(vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        Box::new(TupleIterator::new(tuples, schema))
+    }
+
+    #[test]
+    fn test_profile_counts_rows() -> Result<(), CrustyError> {
+        let scan = Box::new(Profile::new(scan(), Some(3.0)));
+        let filter = Filter::new(SimplePredicateOp::GreaterThan, 0, Field::IntField(1), scan);
+        let mut op = Profile::new(Box::new(filter), Some(1.4));
+        op.open()?;
+        while op.next()?.is_some() {}
+        op.close()?;
+
+        let plan = op.describe();
+        assert_eq!("Filter", plan.name);
+        assert_eq!(Some(1), plan.estimated_rows);
+        assert_eq!(2, plan.counters.unwrap().rows);
+        assert_eq!(
+            vec![("predicate".to_string(), "t.a > 1".to_string())],
+            plan.params
+        );
+        let child = &plan.children[0];
+        assert_eq!(Some(3), child.estimated_rows);
+        assert_eq!(3, child.counters.unwrap().rows);
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_records_metrics() -> Result<(), CrustyError> {
+        let registry: Arc<MetricsRegistry> = Arc::default();
+        let mut op = Profile::new(scan(), None).with_metrics(registry.clone());
+        for _ in 0..2 {
+            op.open()?;
+            while op.next()?.is_some() {}
+            op.close()?;
+        }
+        // Closing again without opening is not another run.
+        op.close()?;
+
+        let metrics = registry.snapshot(Default::default());
+        let scan = metrics.operators["TupleIterator"];
+        assert_eq!(2, scan.runs);
+        assert_eq!(6, scan.rows);
+        assert_eq!(op.describe().counters.unwrap().next_time, scan.time);
+        Ok(())
+    }
+
+    #[test]
+    fn test_profiling() -> Result<(), CrustyError> {
+        let mut op = Profiling::Explain.wrap(scan(), Some(3.0), None);
+        op.open()?;
+        while op.next()?.is_some() {}
+        op.close()?;
+        let plan = op.describe();
+        assert_eq!(Some(3), plan.estimated_rows);
+        // Only EXPLAIN ANALYZE reads the clock.
+        let counters = plan.counters.unwrap();
+        assert_eq!(3, counters.rows);
+        assert_eq!(Duration::ZERO, counters.next_time);
+
+        // Plans that are not explained are profiled only to record their runs.
+        let op = Profiling::Metrics.wrap(scan(), Some(3.0), None);
+        assert_eq!(cfg!(feature = "profile"), op.describe().counters.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_display() {
+        let plan = PlanNode::new("Join")
+            .param("predicate", "a.x = b.x")
+            .child(scan().as_ref())
+            .child(&Profile::new(scan(), Some(3.0)));
+        let text = plan.to_string();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!("Join (predicate: a.x = b.x)", lines[0]);
+        assert_eq!("-> TupleIterator (rows: 3)", lines[1]);
+        assert!(lines[2]
+            .starts_with("-> TupleIterator (rows: 3) [estimated rows: 3] [actual rows: 0, time: "));
+    }
+}
@@ -0,0 +1,503 @@
+use super::explain::column_name;
+use common::decimal::MAX_PRECISION;
+use common::logical_plan::{ArithmeticOp, ScalarFunction};
+use common::{CrustyError, DataType, Decimal, Field, Float, SimplePredicateOp, TableSchema, Tuple};
+
+/// Scalar expression evaluated over the fields of a tuple, such as `a % 2`, `UPPER(name)` or
+/// `CASE WHEN a > 3 THEN 'high' ELSE 'low' END`.
+///
+/// Arithmetic and functions of NULL are NULL, and so is a CASE without a matching branch or
+/// an else expression.
+#[derive(Clone)]
+pub enum Expression {
+    /// Field of the tuple at an index.
+    Field(usize),
+    Literal(Field),
+    /// Arithmetic on two numeric expressions. Integers combined with floats are widened to
+    /// floats, and decimals are added, subtracted and multiplied exactly.
+    Arithmetic(Box<Expression>, ArithmeticOp, Box<Expression>),
+    /// String function applied to its arguments.
+    Function(ScalarFunction, Vec<Expression>),
+    /// Comparison of two expressions, a boolean or NULL if either side is NULL.
+    Compare(Box<Expression>, SimplePredicateOp, Box<Expression>),
+    /// Result of the first branch whose condition holds, else the else expression.
+    Case(Vec<When>, Option<Box<Expression>>),
+    /// Value of an expression converted to a type, an error if the type cannot hold it.
+    Cast(Box<Expression>, DataType),
+}
+
+/// Branch of a CASE expression: `WHEN left op right THEN result`. The condition is false
+/// if either side is NULL.
+#[derive(Clone)]
+pub struct When {
+    pub left: Expression,
+    pub op: SimplePredicateOp,
+    pub right: Expression,
+    pub result: Expression,
+}
+
+impl Expression {
+    /// Evaluates the expression over a tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Tuple whose fields the expression reads.
+    pub fn evaluate(&self, tuple: &Tuple) -> Result<Field, CrustyError> {
+        match self {
+            Expression::Field(i) => tuple.get_field(*i).cloned().ok_or_else(|| {
+                CrustyError::ExecutionError(format!("Expression reads missing field {}", i))
+            }),
+            Expression::Literal(field) => Ok(field.clone()),
+            Expression::Arithmetic(left, op, right) => {
+                arithmetic(*op, left.evaluate(tuple)?, right.evaluate(tuple)?)
+            }
+            Expression::Function(function, args) => {
+                let args = args
+                    .iter()
+                    .map(|a| a.evaluate(tuple))
+                    .collect::<Result<Vec<Field>, CrustyError>>()?;
+                apply(*function, args)
+            }
+            Expression::Compare(left, op, right) => {
+                let left = left.evaluate(tuple)?;
+                let right = right.evaluate(tuple)?;
+                if left == Field::Null || right == Field::Null {
+                    return Ok(Field::Null);
+                }
+                Ok(Field::BoolField(compare(*op, &left, &right)))
+            }
+            Expression::Case(branches, otherwise) => {
+                for branch in branches {
+                    let left = branch.left.evaluate(tuple)?;
+                    let right = branch.right.evaluate(tuple)?;
+                    if compare(branch.op, &left, &right) {
+                        return branch.result.evaluate(tuple);
+                    }
+                }
+                match otherwise {
+                    Some(otherwise) => otherwise.evaluate(tuple),
+                    None => Ok(Field::Null),
+                }
+            }
+            Expression::Cast(expr, dtype) => expr.evaluate(tuple)?.cast(dtype),
+        }
+    }
+
+    /// Describes the expression for EXPLAIN, naming fields by the columns of a schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the tuples the expression reads.
+    pub fn describe(&self, schema: &TableSchema) -> String {
+        match self {
+            Expression::Field(i) => column_name(schema, *i),
+            Expression::Literal(Field::StringField(s)) => format!("'{}'", s),
+            Expression::Literal(field) => field.to_string(),
+            Expression::Arithmetic(left, op, right) => {
+                let operand = |e: &Expression| match e {
+                    Expression::Arithmetic(..) => format!("({})", e.describe(schema)),
+                    _ => e.describe(schema),
+                };
+                format!("{} {} {}", operand(left), op, operand(right))
+            }
+            Expression::Function(function, args) => {
+                let args: Vec<String> = args.iter().map(|a| a.describe(schema)).collect();
+                format!("{}({})", function, args.join(", "))
+            }
+            Expression::Compare(left, op, right) => {
+                format!(
+                    "{} {} {}",
+                    left.describe(schema),
+                    op,
+                    right.describe(schema)
+                )
+            }
+            Expression::Case(branches, otherwise) => {
+                let mut text = String::from("CASE");
+                for b in branches {
+                    text.push_str(&format!(
+                        " WHEN {} {} {} THEN {}",
+                        b.left.describe(schema),
+                        b.op,
+                        b.right.describe(schema),
+                        b.result.describe(schema)
+                    ));
+                }
+                if let Some(otherwise) = otherwise {
+                    text.push_str(&format!(" ELSE {}", otherwise.describe(schema)));
+                }
+                text + " END"
+            }
+            Expression::Cast(expr, dtype) => {
+                format!("CAST({} AS {})", expr.describe(schema), dtype)
+            }
+        }
+    }
+
+    /// Returns the type of the values of the expression.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the tuples the expression reads.
+    pub fn dtype(&self, schema: &TableSchema) -> DataType {
+        match self {
+            Expression::Field(i) => schema
+                .get_attribute(*i)
+                .map_or(DataType::Int, |attr| attr.dtype().clone()),
+            Expression::Literal(field) => match field {
+                Field::StringField(_) => DataType::String,
+                Field::FloatField(_) => DataType::Float,
+                Field::DecimalField(d) => DataType::Decimal(MAX_PRECISION, d.scale),
+                Field::DateField(_) => DataType::Date,
+                Field::TimestampField(_) => DataType::Timestamp,
+                Field::BoolField(_) => DataType::Boolean,
+                Field::IntField(_) | Field::Null => DataType::Int,
+            },
+            Expression::Arithmetic(left, op, right) => {
+                // Integers are decimals without digits after the point.
+                let scale = |dtype| match dtype {
+                    DataType::Int => Some(0),
+                    DataType::Decimal(_, scale) => Some(scale),
+                    _ => None,
+                };
+                let (left, right) = (left.dtype(schema), right.dtype(schema));
+                if left == DataType::Int && right == DataType::Int {
+                    return DataType::Int;
+                }
+                match (scale(left), scale(right), op) {
+                    (Some(a), Some(b), ArithmeticOp::Add | ArithmeticOp::Subtract) => {
+                        DataType::Decimal(MAX_PRECISION, a.max(b))
+                    }
+                    (Some(a), Some(b), ArithmeticOp::Multiply) => {
+                        DataType::Decimal(MAX_PRECISION, a + b)
+                    }
+                    _ => DataType::Float,
+                }
+            }
+            Expression::Function(ScalarFunction::Length, _) => DataType::Int,
+            Expression::Function(..) => DataType::String,
+            Expression::Compare(..) => DataType::Boolean,
+            Expression::Case(branches, otherwise) => branches
+                .first()
+                .map(|b| &b.result)
+                .or(otherwise.as_deref())
+                .map_or(DataType::Int, |e| e.dtype(schema)),
+            Expression::Cast(_, dtype) => dtype.clone(),
+        }
+    }
+}
+
+/// Compares two values, false if either is NULL. Integers compared to floats are compared
+/// as floats.
+///
+/// # Arguments
+///
+/// * `op` - Comparison to apply.
+/// * `left` - Left value.
+/// * `right` - Right value.
+pub(crate) fn compare(op: SimplePredicateOp, left: &Field, right: &Field) -> bool {
+    match (left, right) {
+        (Field::Null, _) | (_, Field::Null) => false,
+        (Field::IntField(i), Field::FloatField(_)) => {
+            op.compare(&Field::FloatField(Float(*i as f64)), right)
+        }
+        (Field::FloatField(_), Field::IntField(i)) => {
+            op.compare(left, &Field::FloatField(Float(*i as f64)))
+        }
+        (left, right) => op.compare(left, right),
+    }
+}
+
+/// Returns the value of a numeric field as a float.
+fn to_f64(field: &Field) -> Option<f64> {
+    match field {
+        Field::IntField(i) => Some(*i as f64),
+        Field::FloatField(f) => Some(f.0),
+        Field::DecimalField(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// Returns the value of an integer or decimal field as a decimal.
+fn to_decimal(field: &Field) -> Option<Decimal> {
+    match field {
+        Field::IntField(i) => Some(Decimal::new(*i as i64, 0)),
+        Field::DecimalField(d) => Some(*d),
+        _ => None,
+    }
+}
+
+/// Applies an arithmetic operator to two values.
+///
+/// # Arguments
+///
+/// * `op` - Operator to apply.
+/// * `left` - Left operand.
+/// * `right` - Right operand.
+fn arithmetic(op: ArithmeticOp, left: Field, right: Field) -> Result<Field, CrustyError> {
+    let overflow = || CrustyError::ExecutionError(format!("Overflow in {} {} {}", left, op, right));
+    let zero = || CrustyError::ExecutionError(String::from("Division by zero"));
+    match (&left, &right) {
+        (Field::Null, _) | (_, Field::Null) => Ok(Field::Null),
+        (Field::IntField(a), Field::IntField(b)) => {
+            let result = match op {
+                ArithmeticOp::Add => a.checked_add(*b),
+                ArithmeticOp::Subtract => a.checked_sub(*b),
+                ArithmeticOp::Multiply => a.checked_mul(*b),
+                ArithmeticOp::Divide | ArithmeticOp::Modulo if *b == 0 => return Err(zero()),
+                ArithmeticOp::Divide => a.checked_div(*b),
+                ArithmeticOp::Modulo => a.checked_rem(*b),
+            };
+            result.map(Field::IntField).ok_or_else(overflow)
+        }
+        (Field::DecimalField(_), Field::IntField(_) | Field::DecimalField(_))
+        | (Field::IntField(_), Field::DecimalField(_))
+            if matches!(
+                op,
+                ArithmeticOp::Add | ArithmeticOp::Subtract | ArithmeticOp::Multiply
+            ) =>
+        {
+            let a = to_decimal(&left).unwrap();
+            let b = to_decimal(&right).unwrap();
+            let result = match op {
+                ArithmeticOp::Add => Some(a + b),
+                ArithmeticOp::Subtract => Some(a + Decimal::new(-b.value, b.scale)),
+                _ => a
+                    .value
+                    .checked_mul(b.value)
+                    .map(|value| Decimal::new(value, a.scale + b.scale)),
+            };
+            result.map(Field::DecimalField).ok_or_else(overflow)
+        }
+        _ => {
+            let (a, b) = match (to_f64(&left), to_f64(&right)) {
+                (Some(a), Some(b)) => (a, b),
+                _ => {
+                    return Err(CrustyError::ExecutionError(format!(
+                        "Cannot apply {} to {} and {}",
+                        op, left, right
+                    )))
+                }
+            };
+            let result = match op {
+                ArithmeticOp::Add => a + b,
+                ArithmeticOp::Subtract => a - b,
+                ArithmeticOp::Multiply => a * b,
+                ArithmeticOp::Divide | ArithmeticOp::Modulo if b == 0.0 => return Err(zero()),
+                ArithmeticOp::Divide => a / b,
+                ArithmeticOp::Modulo => a % b,
+            };
+            Ok(Field::FloatField(Float(result)))
+        }
+    }
+}
+
+/// Applies a string function to its arguments.
+///
+/// # Arguments
+///
+/// * `function` - Function to apply.
+/// * `args` - Values of the arguments.
+fn apply(function: ScalarFunction, args: Vec<Field>) -> Result<Field, CrustyError> {
+    if args.contains(&Field::Null) {
+        return Ok(Field::Null);
+    }
+    let invalid = || {
+        CrustyError::ExecutionError(format!(
+            "Invalid arguments to {}: {}",
+            function,
+            args.iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))
+    };
+    match (function, args.as_slice()) {
+        (ScalarFunction::Upper, [Field::StringField(s)]) => {
+            Ok(Field::StringField(s.to_uppercase()))
+        }
+        (ScalarFunction::Lower, [Field::StringField(s)]) => {
+            Ok(Field::StringField(s.to_lowercase()))
+        }
+        (ScalarFunction::Length, [Field::StringField(s)]) => {
+            Ok(Field::IntField(s.chars().count() as i32))
+        }
+        (ScalarFunction::Trim, [Field::StringField(s)]) => {
+            Ok(Field::StringField(s.trim().to_string()))
+        }
+        (ScalarFunction::Concat, args) if !args.is_empty() => Ok(Field::StringField(
+            args.iter().map(|a| a.to_string()).collect(),
+        )),
+        (ScalarFunction::Substring, [Field::StringField(s), Field::IntField(start), rest @ ..])
+            if rest.len() <= 1 =>
+        {
+            // Positions start at 1, and a start before the string still counts its length
+            // from the start.
+            let start = *start as i64;
+            let end = match rest {
+                [Field::IntField(length)] if *length >= 0 => Some(start + *length as i64),
+                [] => None,
+                _ => return Err(invalid()),
+            };
+            let substring = s
+                .chars()
+                .zip(1..)
+                .filter(|(_, position)| *position >= start && end.is_none_or(|e| *position < e))
+                .map(|(c, _)| c)
+                .collect();
+            Ok(Field::StringField(substring))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::testutil::int_vec_to_tuple;
+
+    fn field(i: usize) -> Box<Expression> {
+        Box::new(Expression::Field(i))
+    }
+
+    fn literal(f: Field) -> Expression {
+        Expression::Literal(f)
+    }
+
+    #[test]
+    fn test_arithmetic() -> Result<(), CrustyError> {
+        let tuple = int_vec_to_tuple(vec![7, 2, 0]);
+        let cases = [
+            (ArithmeticOp::Add, Field::IntField(9)),
+            (ArithmeticOp::Subtract, Field::IntField(5)),
+            (ArithmeticOp::Multiply, Field::IntField(14)),
+            (ArithmeticOp::Divide, Field::IntField(3)),
+            (ArithmeticOp::Modulo, Field::IntField(1)),
+        ];
+        for (op, expected) in cases {
+            assert_eq!(
+                expected,
+                Expression::Arithmetic(field(0), op, field(1)).evaluate(&tuple)?
+            );
+        }
+        let divide = Expression::Arithmetic(field(0), ArithmeticOp::Divide, field(2));
+        assert!(divide.evaluate(&tuple).is_err());
+
+        // Integers are widened to floats and decimals are exact.
+        let half = Box::new(literal(Field::FloatField(Float(0.5))));
+        let sum = Expression::Arithmetic(field(0), ArithmeticOp::Add, half);
+        assert_eq!(Field::FloatField(Float(7.5)), sum.evaluate(&tuple)?);
+        let price = Box::new(literal(Field::DecimalField(Decimal::new(125, 2))));
+        let product = Expression::Arithmetic(price, ArithmeticOp::Multiply, field(1));
+        assert_eq!(
+            Field::DecimalField(Decimal::new(250, 2)),
+            product.evaluate(&tuple)?
+        );
+
+        let null = Box::new(literal(Field::Null));
+        let sum = Expression::Arithmetic(field(0), ArithmeticOp::Add, null);
+        assert_eq!(Field::Null, sum.evaluate(&tuple)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_functions() -> Result<(), CrustyError> {
+        let tuple = Tuple::new(vec![
+            Field::StringField(String::from("Crusty")),
+            Field::IntField(2),
+        ]);
+        let name = || Expression::Field(0);
+        let cases = [
+            (
+                ScalarFunction::Upper,
+                vec![name()],
+                Field::StringField("CRUSTY".into()),
+            ),
+            (
+                ScalarFunction::Lower,
+                vec![name()],
+                Field::StringField("crusty".into()),
+            ),
+            (ScalarFunction::Length, vec![name()], Field::IntField(6)),
+            (
+                ScalarFunction::Concat,
+                vec![name(), Expression::Field(1)],
+                Field::StringField("Crusty2".into()),
+            ),
+            (
+                ScalarFunction::Substring,
+                vec![name(), Expression::Field(1), literal(Field::IntField(3))],
+                Field::StringField("rus".into()),
+            ),
+            (
+                ScalarFunction::Substring,
+                vec![name(), literal(Field::IntField(4))],
+                Field::StringField("sty".into()),
+            ),
+            (
+                ScalarFunction::Trim,
+                vec![literal(Field::StringField(" a b \t".into()))],
+                Field::StringField("a b".into()),
+            ),
+        ];
+        for (function, args, expected) in cases {
+            assert_eq!(
+                expected,
+                Expression::Function(function, args).evaluate(&tuple)?
+            );
+        }
+        let length = Expression::Function(ScalarFunction::Length, vec![Expression::Field(1)]);
+        assert!(length.evaluate(&tuple).is_err());
+
+        // LIKE is false for NULL and non-string operands.
+        for (pattern, expected) in [
+            (Field::StringField("Cr%y".into()), Field::BoolField(true)),
+            (Field::StringField("c%".into()), Field::BoolField(false)),
+            (Field::IntField(2), Field::BoolField(false)),
+            (Field::Null, Field::Null),
+        ] {
+            let like = Expression::Compare(
+                Box::new(name()),
+                SimplePredicateOp::Like,
+                Box::new(literal(pattern)),
+            );
+            assert_eq!(expected, like.evaluate(&tuple)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_case() -> Result<(), CrustyError> {
+        let size = Expression::Case(
+            vec![
+                When {
+                    left: Expression::Field(0),
+                    op: SimplePredicateOp::GreaterThan,
+                    right: literal(Field::IntField(10)),
+                    result: literal(Field::StringField("large".into())),
+                },
+                When {
+                    left: Expression::Field(0),
+                    op: SimplePredicateOp::GreaterThan,
+                    right: literal(Field::FloatField(Float(4.5))),
+                    result: literal(Field::StringField("medium".into())),
+                },
+            ],
+            None,
+        );
+        for (value, expected) in [
+            (Field::IntField(20), Field::StringField("large".into())),
+            (Field::IntField(5), Field::StringField("medium".into())),
+            (Field::IntField(1), Field::Null),
+            (Field::Null, Field::Null),
+        ] {
+            assert_eq!(expected, size.evaluate(&Tuple::new(vec![value]))?);
+        }
+        let schema = TableSchema::from_vecs(vec!["t.a"], vec![common::DataType::Int]);
+        assert_eq!(
+            "CASE WHEN t.a > 10 THEN 'large' WHEN t.a > 4.5 THEN 'medium' END",
+            size.describe(&schema)
+        );
+        Ok(())
+    }
+}
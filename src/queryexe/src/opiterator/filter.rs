@@ -1,6 +1,20 @@
-use super::OpIterator;
+use super::explain::column_name;
+use super::expression::compare;
+use super::{OpIterator, Parameters, PlanNode, Subquery};
 use common::{CrustyError, Field, SimplePredicateOp, TableSchema, Tuple};
 
+/// Value a predicate compares a field of each tuple to.
+enum Operand {
+    /// Literal.
+    Literal(Field),
+    /// Parameter of the plan, with the value read when the filter was last opened.
+    Parameter(usize, Parameters, Field),
+    /// Another field of the tuple.
+    Column(usize),
+    /// Value of a subquery for the tuple.
+    Subquery(Subquery),
+}
+
 /// Compares the fields of tuples.
 pub struct FilterPredicate {
     /// Operation used to compare.
@@ -8,7 +22,7 @@ pub struct FilterPredicate {
     /// Index of the field to filter.
     field_ind: usize,
     /// Operand to compare against.
-    operand: Field,
+    operand: Operand,
 }
 
 impl FilterPredicate {
@@ -19,29 +33,229 @@ impl FilterPredicate {
     /// * `op` - The operation to apply (as defined in common-old::SimplePredicateOp)
     /// * `field_ind` - Field index to compare against
     /// * `operand` - Field value to compare passed in tuples to    
-    fn new(op: SimplePredicateOp, field_ind: usize, operand: Field) -> Self {
+    pub fn new(op: SimplePredicateOp, field_ind: usize, operand: Field) -> Self {
         Self {
             op,
             field_ind,
-            operand,
+            operand: Operand::Literal(operand),
         }
     }
 
-    /// Apply the predicate to the specified tuple.
+    /// Creates a predicate comparing a field to a parameter of the plan.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to apply.
+    /// * `field_ind` - Field index to compare against.
+    /// * `index` - Position of the parameter.
+    /// * `parameters` - Parameters of the plan.
+    pub fn parameter(
+        op: SimplePredicateOp,
+        field_ind: usize,
+        index: usize,
+        parameters: Parameters,
+    ) -> Self {
+        Self {
+            op,
+            field_ind,
+            operand: Operand::Parameter(index, parameters, Field::Null),
+        }
+    }
+
+    /// Creates a predicate comparing two fields of each tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to apply.
+    /// * `field_ind` - Field index to compare.
+    /// * `other_ind` - Field index to compare against.
+    pub fn column(op: SimplePredicateOp, field_ind: usize, other_ind: usize) -> Self {
+        Self {
+            op,
+            field_ind,
+            operand: Operand::Column(other_ind),
+        }
+    }
+
+    /// Creates a predicate comparing a field to the value of a subquery for each tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - The operation to apply.
+    /// * `field_ind` - Field index to compare.
+    /// * `subquery` - Subquery to compare against.
+    pub fn subquery(op: SimplePredicateOp, field_ind: usize, subquery: Subquery) -> Self {
+        Self {
+            op,
+            field_ind,
+            operand: Operand::Subquery(subquery),
+        }
+    }
+
+    /// Apply the predicate to the specified tuple. Comparisons to other fields and to
+    /// subqueries are false if either value is NULL, and integers compared to floats are
+    /// compared as floats.
     ///
     /// # Arguments
     ///
     /// * `tuple` - Tuple to apply the filter to.
-    fn filter(&self, tuple: &Tuple) -> bool {
+    fn filter(&mut self, tuple: &Tuple) -> Result<bool, CrustyError> {
         let field = tuple.get_field(self.field_ind).unwrap();
-        self.op.compare(field, &self.operand)
+        let operand = match &mut self.operand {
+            Operand::Literal(operand) | Operand::Parameter(_, _, operand) => {
+                return Ok(self.op.compare(field, operand))
+            }
+            Operand::Column(i) => tuple.get_field(*i).unwrap().clone(),
+            Operand::Subquery(subquery) => subquery.value(tuple)?,
+        };
+        Ok(compare(self.op, field, &operand))
+    }
+}
+
+/// Boolean combination of comparisons of the fields of tuples.
+pub enum BooleanPredicate {
+    /// Comparison of a field to an operand.
+    Compare(FilterPredicate),
+    /// True if all of the predicates are true.
+    And(Vec<BooleanPredicate>),
+    /// True if any of the predicates is true.
+    Or(Vec<BooleanPredicate>),
+    /// True if the predicate is false.
+    Not(Box<BooleanPredicate>),
+}
+
+impl BooleanPredicate {
+    /// Apply the predicate to the specified tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Tuple to apply the filter to.
+    fn filter(&mut self, tuple: &Tuple) -> Result<bool, CrustyError> {
+        match self {
+            BooleanPredicate::Compare(predicate) => predicate.filter(tuple),
+            BooleanPredicate::And(predicates) => {
+                for predicate in predicates {
+                    if !predicate.filter(tuple)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            BooleanPredicate::Or(predicates) => {
+                for predicate in predicates {
+                    if predicate.filter(tuple)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            BooleanPredicate::Not(predicate) => Ok(!predicate.filter(tuple)?),
+        }
+    }
+
+    /// Reads the values of the parameters the predicate compares to, and forgets the values
+    /// of its subqueries from earlier runs.
+    fn bind(&mut self) -> Result<(), CrustyError> {
+        match self {
+            BooleanPredicate::Compare(predicate) => {
+                match &mut predicate.operand {
+                    Operand::Parameter(index, parameters, value) => {
+                        *value = parameters.get(*index)?;
+                    }
+                    Operand::Subquery(subquery) => subquery.reset(),
+                    Operand::Literal(_) | Operand::Column(_) => {}
+                }
+                Ok(())
+            }
+            BooleanPredicate::And(predicates) | BooleanPredicate::Or(predicates) => {
+                predicates.iter_mut().try_for_each(|p| p.bind())
+            }
+            BooleanPredicate::Not(predicate) => predicate.bind(),
+        }
+    }
+
+    /// Closes the plans of the subqueries the predicate compares to.
+    fn close(&mut self) -> Result<(), CrustyError> {
+        match self {
+            BooleanPredicate::Compare(FilterPredicate {
+                operand: Operand::Subquery(subquery),
+                ..
+            }) => subquery.close(),
+            BooleanPredicate::Compare(_) => Ok(()),
+            BooleanPredicate::And(predicates) | BooleanPredicate::Or(predicates) => {
+                predicates.iter_mut().try_for_each(|p| p.close())
+            }
+            BooleanPredicate::Not(predicate) => predicate.close(),
+        }
+    }
+
+    /// Returns the subqueries the predicate compares to.
+    fn subqueries(&self) -> Vec<&Subquery> {
+        match self {
+            BooleanPredicate::Compare(FilterPredicate {
+                operand: Operand::Subquery(subquery),
+                ..
+            }) => vec![subquery],
+            BooleanPredicate::Compare(_) => Vec::new(),
+            BooleanPredicate::And(predicates) | BooleanPredicate::Or(predicates) => {
+                predicates.iter().flat_map(|p| p.subqueries()).collect()
+            }
+            BooleanPredicate::Not(predicate) => predicate.subqueries(),
+        }
+    }
+
+    /// Formats the predicate with the column names of a schema, parenthesizing nested
+    /// conjunctions and disjunctions.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the filtered tuples.
+    fn describe(&self, schema: &TableSchema) -> String {
+        let join = |predicates: &[BooleanPredicate], sep: &str| {
+            predicates
+                .iter()
+                .map(|p| p.describe_operand(schema))
+                .collect::<Vec<_>>()
+                .join(sep)
+        };
+        match self {
+            BooleanPredicate::Compare(predicate) => {
+                let operand = match &predicate.operand {
+                    Operand::Literal(field) => field.to_string(),
+                    Operand::Parameter(index, _, _) => format!("${}", index + 1),
+                    Operand::Column(i) => column_name(schema, *i),
+                    Operand::Subquery(_) => String::from("(subquery)"),
+                };
+                format!(
+                    "{} {} {}",
+                    column_name(schema, predicate.field_ind),
+                    predicate.op,
+                    operand
+                )
+            }
+            BooleanPredicate::And(predicates) => join(predicates, " AND "),
+            BooleanPredicate::Or(predicates) => join(predicates, " OR "),
+            BooleanPredicate::Not(predicate) => {
+                format!("NOT {}", predicate.describe_operand(schema))
+            }
+        }
+    }
+
+    /// Formats the predicate as an operand of another one.
+    fn describe_operand(&self, schema: &TableSchema) -> String {
+        match self {
+            BooleanPredicate::And(_) | BooleanPredicate::Or(_) => {
+                format!("({})", self.describe(schema))
+            }
+            _ => self.describe(schema),
+        }
     }
 }
 
 /// Filter oeprator.
 pub struct Filter {
     /// Predicate to filter by.
-    predicate: FilterPredicate,
+    predicate: BooleanPredicate,
     /// Schema of the child.
     schema: TableSchema,
     /// Boolean determining if iterator is open.
@@ -63,8 +277,21 @@ impl Filter {
         operand: Field,
         child: Box<dyn OpIterator>,
     ) -> Self {
+        Self::with_predicate(
+            BooleanPredicate::Compare(FilterPredicate::new(op, field_ind, operand)),
+            child,
+        )
+    }
+
+    /// Creates a filter by a combination of comparisons.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - Predicate to filter by.
+    /// * `child` - Child OpIterator passing data into the operator.
+    pub fn with_predicate(predicate: BooleanPredicate, child: Box<dyn OpIterator>) -> Self {
         Self {
-            predicate: FilterPredicate::new(op, field_ind, operand),
+            predicate,
             schema: child.get_schema().clone(),
             open: false,
             child,
@@ -74,6 +301,7 @@ impl Filter {
 
 impl OpIterator for Filter {
     fn open(&mut self) -> Result<(), CrustyError> {
+        self.predicate.bind()?;
         self.open = true;
         self.child.open()
     }
@@ -85,7 +313,7 @@ impl OpIterator for Filter {
 
         let mut res = None;
         while let Some(t) = self.child.next()? {
-            if self.predicate.filter(&t) {
+            if self.predicate.filter(&t)? {
                 res = Some(t);
                 break;
             }
@@ -95,6 +323,7 @@ impl OpIterator for Filter {
 
     fn close(&mut self) -> Result<(), CrustyError> {
         self.child.close()?;
+        self.predicate.close()?;
         self.open = false;
         Ok(())
     }
@@ -111,6 +340,16 @@ impl OpIterator for Filter {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let mut node = PlanNode::new("Filter")
+            .param("predicate", self.predicate.describe(&self.schema))
+            .child(self.child.as_ref());
+        for subquery in self.predicate.subqueries() {
+            node.children.push(subquery.describe());
+        }
+        node
+    }
 }
 
 #[cfg(test)]
@@ -245,4 +484,80 @@ mod test {
         assert!(filter.next()?.is_none());
         Ok(())
     }
+
+    fn compare(field_num: usize, op: SimplePredicateOp, operand: i32) -> BooleanPredicate {
+        BooleanPredicate::Compare(FilterPredicate::new(
+            op,
+            field_num,
+            Field::IntField(operand),
+        ))
+    }
+
+    #[test]
+    fn test_compound_predicate() -> Result<(), CrustyError> {
+        // x < -3 OR NOT (x < 2 AND x != 0)
+        let predicate = BooleanPredicate::Or(vec![
+            compare(0, SimplePredicateOp::LessThan, -3),
+            BooleanPredicate::Not(Box::new(BooleanPredicate::And(vec![
+                compare(1, SimplePredicateOp::LessThan, 2),
+                compare(2, SimplePredicateOp::NotEq, 0),
+            ]))),
+        ]);
+        let mut filter = Filter::with_predicate(predicate, Box::new(mock_ti(-5, 5, WIDTH)));
+        filter.open()?;
+        let mut actual = Vec::new();
+        while let Some(t) = filter.next()? {
+            actual.push(t.get_field(0).unwrap().unwrap_int_field());
+        }
+        assert_eq!(vec![-5, -4, 0, 2, 3, 4], actual);
+        filter.close()
+    }
+
+    #[test]
+    fn test_parameter() -> Result<(), CrustyError> {
+        let parameters = Parameters::new();
+        parameters.declare(0, &common::DataType::Int)?;
+        let predicate = BooleanPredicate::Compare(FilterPredicate::parameter(
+            SimplePredicateOp::GreaterThan,
+            0,
+            0,
+            parameters.clone(),
+        ));
+        let mut filter = Filter::with_predicate(predicate, Box::new(mock_ti(-5, 5, WIDTH)));
+        assert!(filter.open().is_err());
+        assert!(filter.describe().to_string().contains("$1"));
+
+        for (value, count) in [(2, 2), (-3, 7)] {
+            parameters.bind(vec![Field::IntField(value)])?;
+            filter.open()?;
+            let mut actual = 0;
+            while filter.next()?.is_some() {
+                actual += 1;
+            }
+            assert_eq!(count, actual);
+            filter.close()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_bool_field() -> Result<(), CrustyError> {
+        let tuples = vec![
+            Tuple::new(vec![Field::IntField(1), Field::BoolField(true)]),
+            Tuple::new(vec![Field::IntField(2), Field::BoolField(false)]),
+            Tuple::new(vec![Field::IntField(3), Field::Null]),
+        ];
+        let schema = get_int_table_schema(2);
+        let ti = TupleIterator::new(tuples, schema);
+        let mut filter = Filter::new(
+            SimplePredicateOp::Equals,
+            1,
+            Field::BoolField(true),
+            Box::new(ti),
+        );
+        filter.open()?;
+        assert_eq!(Field::IntField(1), filter.next()?.unwrap().field_vals[0]);
+        assert!(filter.next()?.is_none());
+        filter.close()
+    }
 }
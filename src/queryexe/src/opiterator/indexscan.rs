@@ -0,0 +1,195 @@
+use super::{OpIterator, PlanNode, SeqScan};
+use crate::StorageManager;
+use common::ids::{Permissions, TransactionId, ValueId};
+use common::index::{self, IndexInfo, KeyRange};
+use common::storage_trait::StorageTrait;
+use common::table::*;
+use common::{CrustyError, TableSchema, Tuple};
+use std::sync::{Arc, RwLock};
+
+/// Index scan operator. Returns the records of a table whose keys in an index are in a
+/// range, in key order.
+pub struct IndexScan {
+    schema: TableSchema,
+    /// Scanned table, used to pad records written under older schema versions.
+    table: Table,
+    alias: String,
+    index: IndexInfo,
+    range: KeyRange,
+    /// Records in the range, read from the index when the scan is opened.
+    value_ids: Vec<ValueId>,
+    /// Position of the next record in value_ids.
+    next: usize,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    transaction_id: TransactionId,
+}
+
+impl IndexScan {
+    /// Constructor for the index scan operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Table to scan over.
+    /// * `table_alias` - Table alias given by the user.
+    /// * `index` - Index over the table.
+    /// * `range` - Keys of the records to return.
+    /// * `tid` - Transaction used to read the index and the table.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        table: Arc<RwLock<Table>>,
+        table_alias: &str,
+        index: IndexInfo,
+        range: KeyRange,
+        tid: TransactionId,
+    ) -> Self {
+        let table = table.read().unwrap().clone();
+        Self {
+            schema: SeqScan::schema(&table.schema, table_alias),
+            table,
+            alias: table_alias.to_string(),
+            index,
+            range,
+            value_ids: Vec::new(),
+            next: 0,
+            open: false,
+            storage_manager,
+            transaction_id: tid,
+        }
+    }
+}
+
+impl OpIterator for IndexScan {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.value_ids = index::scan(
+            self.storage_manager.as_ref(),
+            &self.index,
+            &self.range,
+            self.transaction_id,
+        )?;
+        self.next = 0;
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        let value_id = match self.value_ids.get(self.next) {
+            Some(value_id) => *value_id,
+            None => return Ok(None),
+        };
+        self.next += 1;
+        let bytes =
+            self.storage_manager
+                .get_value(value_id, self.transaction_id, Permissions::ReadOnly)?;
+        let mut tuple = self.table.read_tuple(&bytes);
+        tuple.value_id = Some(value_id);
+        Ok(Some(tuple))
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.open = false;
+        self.value_ids.clear();
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.next = 0;
+        Ok(())
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("IndexScan")
+            .param("table", &self.alias)
+            .param("index", &self.index.name)
+            .param("range", &self.range)
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use common::testutil::get_int_table_schema;
+    use common::Field;
+    use std::ops::Bound;
+
+    const TABLE: &str = "IndexScan";
+
+    fn get_scan(range: KeyRange) -> IndexScan {
+        let rows = (0..20).rev().map(|i| vec![i % 4, i, 100 + i]).collect();
+        let (sm, _) = test_table(1, rows);
+        let index = test_index(&sm, 1, 2, vec![0, 1]);
+        let table = Table::new(TABLE.to_string(), get_int_table_schema(3));
+        IndexScan::new(
+            sm,
+            Arc::new(RwLock::new(table)),
+            TABLE,
+            index,
+            range,
+            TransactionId::new(),
+        )
+    }
+
+    fn scanned(scan: &mut IndexScan) -> Result<Vec<i32>, CrustyError> {
+        let mut values = Vec::new();
+        while let Some(tuple) = scan.next()? {
+            values.push(match tuple.get_field(1) {
+                Some(Field::IntField(i)) => *i,
+                field => panic!("Unexpected field {:?}", field),
+            });
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_exact_key() -> Result<(), CrustyError> {
+        let mut scan = get_scan(KeyRange::exact(vec![
+            Field::IntField(1),
+            Field::IntField(9),
+        ]));
+        scan.open()?;
+        assert_eq!(vec![9], scanned(&mut scan)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix_range() -> Result<(), CrustyError> {
+        let mut scan = get_scan(KeyRange {
+            prefix: vec![Field::IntField(2)],
+            lower: Bound::Excluded(Field::IntField(2)),
+            upper: Bound::Included(Field::IntField(14)),
+        });
+        scan.open()?;
+        assert_eq!(vec![6, 10, 14], scanned(&mut scan)?);
+        scan.rewind()?;
+        assert_eq!(vec![6, 10, 14], scanned(&mut scan)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_prefix() -> Result<(), CrustyError> {
+        let mut scan = get_scan(KeyRange::exact(vec![Field::IntField(3)]));
+        scan.open()?;
+        assert_eq!(vec![3, 7, 11, 15, 19], scanned(&mut scan)?);
+        assert!(scan.describe().to_string().contains("IndexScan"));
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut scan = get_scan(KeyRange::exact(vec![Field::IntField(3)]));
+        scan.next();
+    }
+}
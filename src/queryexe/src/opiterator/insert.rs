@@ -0,0 +1,462 @@
+use super::{OpIterator, PlanNode};
+use crate::mutator;
+use crate::{StorageManager, TransactionManager};
+use common::columnar;
+use common::foreign_key::{self, ForeignKeys};
+use common::index::{self, IndexInfo};
+use common::prelude::*;
+use common::storage_trait::StorageTrait;
+use common::table::{Table, TableLayout};
+use common::traits::transaction_manager_trait::TransactionManagerTrait;
+use common::ConvertedResult;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Insert operator. Inserts the tuples of its child into a table and returns the number of
+/// inserted tuples.
+pub struct Insert {
+    /// Schema of the result, a single count column.
+    schema: TableSchema,
+    /// The table, whose schema validates the tuples and whose layout says how to store them.
+    table: Table,
+    /// Indexes of the table, which get an entry for every inserted tuple.
+    indexes: Vec<IndexInfo>,
+    /// Foreign keys of the table, whose parent keys the inserted tuples must refer to.
+    foreign_keys: ForeignKeys,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    transaction_manager: &'static TransactionManager,
+    container_id: ContainerId,
+    tid: TransactionId,
+    child: Box<dyn OpIterator>,
+    /// Number of inserted tuples, once they are inserted.
+    count: Option<usize>,
+    /// Whether the count was returned since the operator was opened.
+    done: bool,
+}
+
+impl Insert {
+    /// Constructor for the insert operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_manager` - Storage manager holding the table.
+    /// * `transaction_manager` - Transaction manager notified of the inserts.
+    /// * `container_id` - Container of the table to insert into.
+    /// * `table` - The table to insert into.
+    /// * `indexes` - Indexes of the table to maintain.
+    /// * `foreign_keys` - Foreign keys of the table to enforce.
+    /// * `tid` - Transaction used to insert the tuples.
+    /// * `child` - Child operator producing the tuples to insert.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        transaction_manager: &'static TransactionManager,
+        container_id: &ContainerId,
+        table: Table,
+        indexes: Vec<IndexInfo>,
+        foreign_keys: ForeignKeys,
+        tid: TransactionId,
+        child: Box<dyn OpIterator>,
+    ) -> Self {
+        Self {
+            schema: TableSchema::from_vecs(vec!["count"], vec![DataType::Int]),
+            table,
+            indexes,
+            foreign_keys,
+            open: false,
+            storage_manager,
+            transaction_manager,
+            container_id: *container_id,
+            tid,
+            child,
+            count: None,
+            done: false,
+        }
+    }
+
+    /// Inserts all the tuples of the child and returns their count.
+    ///
+    /// The child is read to the end before anything is inserted, so a child reading the
+    /// same table does not see the new tuples.
+    fn insert(&mut self) -> Result<usize, CrustyError> {
+        let mut values = ConvertedResult {
+            converted: Vec::new(),
+            unconverted: Vec::new(),
+        };
+        while let Some(tuple) = self.child.next()? {
+            if tuple.size() != self.table.schema.size() {
+                return Err(CrustyError::ValidationError(format!(
+                    "Inserting {} values into a table with {} columns",
+                    tuple.size(),
+                    self.table.schema.size()
+                )));
+            }
+            values.converted.push(Tuple::new(tuple.field_vals));
+        }
+        let count = values.converted.len();
+        let values = mutator::validate_tuples(
+            &self.container_id,
+            &self.table.schema,
+            None,
+            values,
+            &self.tid,
+        )?;
+        mutator::check_unconverted(&values, &self.table.schema)?;
+        for index in &self.indexes {
+            index::check_unique(
+                self.storage_manager.as_ref(),
+                index,
+                &values.converted,
+                &HashSet::new(),
+                self.tid,
+            )?;
+        }
+        foreign_key::check_parents(
+            self.storage_manager.as_ref(),
+            &self.foreign_keys,
+            &values.converted,
+            self.tid,
+        )?;
+
+        let mut inserted = Vec::with_capacity(count);
+        let mut tuples = values.converted;
+        for tuple in tuples.iter_mut() {
+            self.transaction_manager
+                .pre_insert_record(tuple, self.tid)?;
+        }
+        let ids: Vec<ValueId> = match &self.table.layout {
+            TableLayout::Row => tuples
+                .iter()
+                .map(|tuple| {
+                    self.storage_manager
+                        .insert_value(self.container_id, tuple.to_bytes(), self.tid)
+                })
+                .collect::<Result<_, _>>()?,
+            TableLayout::Column(columns) => columnar::insert(
+                self.storage_manager.as_ref(),
+                self.container_id,
+                columns,
+                &tuples,
+                self.tid,
+            )?,
+            TableLayout::Partitioned(partitioning) => tuples
+                .iter()
+                .map(|tuple| {
+                    self.storage_manager.insert_value(
+                        partitioning.partition_of(tuple),
+                        tuple.to_bytes(),
+                        self.tid,
+                    )
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        for (mut tuple, id) in tuples.into_iter().zip(ids) {
+            self.transaction_manager
+                .post_insert_record(&mut tuple, id, self.tid)?;
+            inserted.push((tuple, id));
+        }
+        for index in &self.indexes {
+            index::insert_entries(self.storage_manager.as_ref(), index, &inserted, self.tid)?;
+        }
+        Ok(count)
+    }
+}
+
+impl OpIterator for Insert {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.open = true;
+        self.done = false;
+        self.child.open()
+    }
+
+    /// Returns the number of inserted tuples, inserting them on the first call.
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let count = match self.count {
+            Some(count) => count,
+            None => self.insert()?,
+        };
+        self.count = Some(count);
+        self.done = true;
+        Ok(Some(Tuple::new(vec![Field::IntField(count as i32)])))
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.child.close()?;
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.rewind()?;
+        self.close()?;
+        self.open()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Insert")
+            .param("container", self.container_id)
+            .child(self.child.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use crate::opiterator::TupleIterator;
+    use common::foreign_key::OnDelete;
+    use common::testutil::*;
+    use common::{Attribute, Constraint};
+
+    fn get_insert(rows: Vec<Vec<i32>>) -> (Arc<StorageManager>, Insert) {
+        let (sm, tm) = test_table(1, Vec::new());
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let child = TupleIterator::new(create_tuple_list(rows), schema.clone());
+        let insert = Insert::new(
+            sm.clone(),
+            tm,
+            &1,
+            Table::new("t".to_string(), schema),
+            Vec::new(),
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(child),
+        );
+        (sm, insert)
+    }
+
+    #[test]
+    fn test_insert() -> Result<(), CrustyError> {
+        let (sm, mut insert) = get_insert(vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        insert.open()?;
+        assert_eq!(Some(int_vec_to_tuple(vec![3])), insert.next()?);
+        assert_eq!(None, insert.next()?);
+        insert.close()?;
+
+        let stored: Vec<Vec<Field>> = stored_tuples(&sm, 1)
+            .into_iter()
+            .map(|t| t.field_vals)
+            .collect();
+        assert_eq!(3, stored.len());
+        assert!(stored.contains(&int_vec_to_tuple(vec![3, 4]).field_vals));
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_wrong_arity() {
+        let (sm, mut insert) = get_insert(vec![vec![1, 2, 3]]);
+        insert.open().unwrap();
+        assert!(insert.next().is_err());
+        assert!(stored_tuples(&sm, 1).is_empty());
+    }
+
+    #[test]
+    fn test_insert_null_into_not_null() {
+        let (sm, tm) = test_table(1, Vec::new());
+        let schema = TableSchema::new(vec![
+            Attribute::new_pk("t.a".to_string(), DataType::Int),
+            Attribute::new_with_constraint("t.b".to_string(), DataType::Int, Constraint::NotNull),
+        ]);
+        let rows = vec![
+            Tuple::new(vec![Field::IntField(1), Field::IntField(2)]),
+            Tuple::new(vec![Field::IntField(3), Field::Null]),
+        ];
+        let child = TupleIterator::new(rows, schema.clone());
+        let mut insert = Insert::new(
+            sm.clone(),
+            tm,
+            &1,
+            Table::new("t".to_string(), schema),
+            Vec::new(),
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(child),
+        );
+        insert.open().unwrap();
+        match insert.next() {
+            Err(CrustyError::ConstraintViolation(msg)) => assert!(msg.contains("t.b")),
+            other => panic!("Expected a constraint violation, got {:?}", other),
+        }
+        assert!(stored_tuples(&sm, 1).is_empty());
+    }
+
+    #[test]
+    fn test_insert_duplicate_key() {
+        let (sm, tm) = test_table(1, vec![vec![1, 2]]);
+        let mut index = test_index(&sm, 1, 2, vec![0]);
+        index.unique = true;
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        for rows in [vec![vec![3, 4], vec![1, 5]], vec![vec![3, 4], vec![3, 5]]] {
+            let child = TupleIterator::new(create_tuple_list(rows), schema.clone());
+            let mut insert = Insert::new(
+                sm.clone(),
+                tm,
+                &1,
+                Table::new("t".to_string(), schema.clone()),
+                vec![index.clone()],
+                ForeignKeys::default(),
+                TransactionId::new(),
+                Box::new(child),
+            );
+            insert.open().unwrap();
+            assert!(matches!(insert.next(), Err(CrustyError::DuplicateKey(_))));
+            assert_eq!(1, stored_tuples(&sm, 1).len());
+        }
+    }
+
+    #[test]
+    fn test_insert_duplicate_key_bloom_filter() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2]]);
+        sm.create_bloom_filter(1, 0, 100)?;
+        let mut index = test_index(&sm, 1, 2, vec![0]);
+        index.unique = true;
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let insert_rows = |rows| {
+            let child = TupleIterator::new(create_tuple_list(rows), schema.clone());
+            let mut insert = Insert::new(
+                sm.clone(),
+                tm,
+                &1,
+                Table::new("t".to_string(), schema.clone()),
+                vec![index.clone()],
+                ForeignKeys::default(),
+                TransactionId::new(),
+                Box::new(child),
+            );
+            insert.open()?;
+            insert.next()
+        };
+        // The filter rules out the new keys, and does not hide the existing ones.
+        insert_rows(vec![vec![3, 4], vec![5, 6]])?;
+        assert!(sm.may_contain(1, &Field::IntField(5)));
+        assert!(matches!(
+            insert_rows(vec![vec![7, 8], vec![5, 9]]),
+            Err(CrustyError::DuplicateKey(_))
+        ));
+        assert_eq!(3, stored_tuples(&sm, 1).len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_missing_parent() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 10], vec![2, 20]]);
+        let mut parent_index = test_index(&sm, 1, 2, vec![0]);
+        parent_index.unique = true;
+        let (foreign_keys, _) =
+            test_foreign_key(&sm, &parent_index, 3, Vec::new(), 1, OnDelete::Restrict);
+        let schema = TableSchema::from_vecs(vec!["c.a", "c.b"], vec![DataType::Int; 2]);
+        let insert_rows = |rows| {
+            let child = TupleIterator::new(rows, schema.clone());
+            let mut insert = Insert::new(
+                sm.clone(),
+                tm,
+                &3,
+                Table::new("t".to_string(), schema.clone()),
+                Vec::new(),
+                foreign_keys.clone(),
+                TransactionId::new(),
+                Box::new(child),
+            );
+            insert.open()?;
+            insert.next()
+        };
+        insert_rows(create_tuple_list(vec![vec![7, 1], vec![8, 2], vec![9, 1]]))?;
+        // A null key refers to no parent row.
+        insert_rows(vec![Tuple::new(vec![Field::IntField(6), Field::Null])])?;
+        assert!(matches!(
+            insert_rows(create_tuple_list(vec![vec![10, 2], vec![11, 3]])),
+            Err(CrustyError::ConstraintViolation(_))
+        ));
+        assert_eq!(4, stored_tuples(&sm, 3).len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_maintains_index() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2]]);
+        let index = test_index(&sm, 1, 2, vec![1]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let rows = create_tuple_list(vec![vec![3, 2], vec![5, 6]]);
+        let child = TupleIterator::new(rows, schema.clone());
+        let mut insert = Insert::new(
+            sm.clone(),
+            tm,
+            &1,
+            Table::new("t".to_string(), schema),
+            vec![index.clone()],
+            ForeignKeys::default(),
+            TransactionId::new(),
+            Box::new(child),
+        );
+        insert.open()?;
+        insert.next()?;
+        insert.close()?;
+
+        let mut found: Vec<Vec<Field>> = index_lookup(&sm, &index, vec![2])
+            .into_iter()
+            .map(|t| t.field_vals)
+            .collect();
+        found.sort();
+        let expected: Vec<Vec<Field>> = create_tuple_list(vec![vec![1, 2], vec![3, 2]])
+            .into_iter()
+            .map(|t| t.field_vals)
+            .collect();
+        assert_eq!(expected, found);
+        assert_eq!(1, index_lookup(&sm, &index, vec![6]).len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_hash_index() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2], vec![2, 4]]);
+        let mut index = test_hash_index(&sm, 1, 2, vec![1]);
+        index.unique = true;
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let insert_rows = |rows| {
+            let child = TupleIterator::new(rows, schema.clone());
+            let mut insert = Insert::new(
+                sm.clone(),
+                tm,
+                &1,
+                Table::new("t".to_string(), schema.clone()),
+                vec![index.clone()],
+                ForeignKeys::default(),
+                TransactionId::new(),
+                Box::new(child),
+            );
+            insert.open()?;
+            insert.next()
+        };
+        insert_rows(create_tuple_list(
+            (3..200).map(|i| vec![i, 2 * i]).collect(),
+        ))?;
+        assert!(matches!(
+            insert_rows(create_tuple_list(vec![vec![500, 501], vec![501, 8]])),
+            Err(CrustyError::DuplicateKey(_))
+        ));
+        assert_eq!(199, stored_tuples(&sm, 1).len());
+        for i in 1..200 {
+            let found = index_lookup(&sm, &index, vec![2 * i]);
+            assert_eq!(
+                vec![Field::IntField(i), Field::IntField(2 * i)],
+                found[0].field_vals
+            );
+        }
+        assert!(index_lookup(&sm, &index, vec![3]).is_empty());
+        Ok(())
+    }
+}
@@ -1,6 +1,12 @@
-use super::{OpIterator, TupleIterator};
-use common::{CrustyError, Field, SimplePredicateOp, TableSchema, Tuple};
+use super::explain::column_name;
+use super::{MemoryGrant, OpIterator, PlanNode, TupleIterator};
+use common::{Attribute, CrustyError, Field, SimplePredicateOp, TableSchema, Tuple};
 use std::collections::HashMap;
+use std::mem::discriminant;
+
+/// Test for keys of the build side of a hash join, such as the bloom filter over the key
+/// column of its table. It returns false only for keys the build side cannot have.
+pub type KeyFilter = Box<dyn Fn(&Field) -> bool>;
 
 /// Compares the fields of two tuples using a predicate. (You can add any other fields that you think are neccessary)
 pub struct JoinPredicate {
@@ -27,6 +33,58 @@ impl JoinPredicate {
             right_index,
         }
     }
+
+    /// Checks that the compared fields are columns of the inputs of the join and hold values
+    /// of the same type, and returns the schema of the joined tuples: the columns of the
+    /// left input followed by those of the right input.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - Schema of the left input.
+    /// * `right` - Schema of the right input.
+    fn validate(
+        &self,
+        left: &TableSchema,
+        right: &TableSchema,
+    ) -> Result<TableSchema, CrustyError> {
+        fn attribute<'a>(
+            schema: &'a TableSchema,
+            index: usize,
+            side: &str,
+        ) -> Result<&'a Attribute, CrustyError> {
+            schema.get_attribute(index).ok_or_else(|| {
+                CrustyError::ValidationError(format!(
+                    "Join field {} is not a column of the {} input, which has {} columns",
+                    index,
+                    side,
+                    schema.size()
+                ))
+            })
+        }
+        let l = attribute(left, self.left_index, "left")?;
+        let r = attribute(right, self.right_index, "right")?;
+        if discriminant(l.dtype()) != discriminant(r.dtype()) {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot join {} of type {:?} with {} of type {:?}",
+                l.name(),
+                l.dtype(),
+                r.name(),
+                r.dtype()
+            )));
+        }
+        let attributes = left.attributes().chain(right.attributes()).cloned();
+        Ok(TableSchema::new(attributes.collect()))
+    }
+
+    /// Describes the predicate with the names of the compared columns.
+    fn describe(&self, left: &dyn OpIterator, right: &dyn OpIterator) -> String {
+        format!(
+            "{} {} {}",
+            column_name(left.get_schema(), self.left_index),
+            self.op,
+            column_name(right.get_schema(), self.right_index)
+        )
+    }
 }
 
 /// Nested loop join implementation. (You can add any other fields that you think are neccessary)
@@ -46,7 +104,8 @@ pub struct Join {
 }
 
 impl Join {
-    /// Join constructor. Creates a new node for a nested-loop join.
+    /// Join constructor. Creates a new node for a nested-loop join. Fails if the compared
+    /// fields are not columns of the children or hold values of different types.
     ///
     /// # Arguments
     ///
@@ -61,30 +120,18 @@ impl Join {
         right_index: usize,
         left_child: Box<dyn OpIterator>,
         right_child: Box<dyn OpIterator>,
-    ) -> Self {
-        // iterate through the fields of the left and right child to create the schema of the result
-        let mut attributes = Vec::new();
-        let left_schema = left_child.get_schema();
-        let right_schema = right_child.get_schema();
-        // add the fields of the left child using the .attributes iterator
-        for attr in left_schema.attributes() {
-            attributes.push(attr.clone());
-        }
-        // add the fields of the right child using the .attributes iterator
-        for attr in right_schema.attributes() {
-            attributes.push(attr.clone());
-        }
-        let schema = TableSchema::new(attributes);
-        // create the predicate
+    ) -> Result<Self, CrustyError> {
+        // create the predicate, and the schema of the result from those of the children
         let predicate = JoinPredicate::new(op, left_index, right_index);
-        Join {
+        let schema = predicate.validate(left_child.get_schema(), right_child.get_schema())?;
+        Ok(Join {
             predicate,
             left_child,
             right_child,
             schema,
             open: false,
             out_tup: None,
-        }
+        })
     }
 }
 
@@ -168,6 +215,43 @@ impl OpIterator for Join {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let predicate = self
+            .predicate
+            .describe(self.left_child.as_ref(), self.right_child.as_ref());
+        PlanNode::new("Join")
+            .param("predicate", predicate)
+            .child(self.left_child.as_ref())
+            .child(self.right_child.as_ref())
+    }
+}
+
+/// Input of a hash join read into its hash table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuildSide {
+    Left,
+    Right,
+    /// The input with fewer estimated rows, or the right input if either is not estimated.
+    Smaller,
+}
+
+impl BuildSide {
+    /// Resolves `Smaller` to the input with fewer estimated rows.
+    fn resolve(self, left: &dyn OpIterator, right: &dyn OpIterator) -> Self {
+        match self {
+            BuildSide::Smaller => {
+                match (
+                    left.describe().estimated_rows,
+                    right.describe().estimated_rows,
+                ) {
+                    (Some(l), Some(r)) if l < r => BuildSide::Left,
+                    _ => BuildSide::Right,
+                }
+            }
+            side => side,
+        }
+    }
 }
 
 /// Hash equi-join implementation. (You can add any other fields that you think are neccessary)
@@ -178,13 +262,25 @@ pub struct HashEqJoin {
     right_child: Box<dyn OpIterator>,
 
     schema: TableSchema,
-    // inner relation hash table
+    // child read into the hash table, Left or Right; the other child probes it
+    build_side: BuildSide,
+    // build side hash table
     hash_table: HashMap<Field, Vec<Tuple>>,
     open: bool,
+    // joined tuples of the current probe tuple that have not been returned yet
+    pending: Vec<Tuple>,
+    // rules out probe keys before they are hashed
+    key_filter: Option<KeyFilter>,
+    // encoded size of the tuples in the hash table
+    build_bytes: usize,
+    // memory of the hash table, if it counts against a memory budget
+    grant: Option<MemoryGrant>,
 }
 
 impl HashEqJoin {
-    /// Constructor for a hash equi-join operator.
+    /// Constructor for a hash equi-join operator that builds the hash table over the right
+    /// child. Fails if the operation is not an equality, or if the compared fields are not
+    /// columns of the children or hold values of different types.
     ///
     /// # Arguments
     ///
@@ -193,141 +289,224 @@ impl HashEqJoin {
     /// * `right_index` - Index of the right field in join condition.
     /// * `left_child` - Left child of join operator.
     /// * `right_child` - Left child of join operator.
-    #[allow(dead_code)]
     pub fn new(
         op: SimplePredicateOp,
         left_index: usize,
         right_index: usize,
         left_child: Box<dyn OpIterator>,
         right_child: Box<dyn OpIterator>,
-    ) -> Self {
-        // we first create the schema by iterating through the fields of the
-        // left and right children
-        // iterate through the fields of the left and right child to create the schema of the result
-        let mut attributes = Vec::new();
-        let left_schema = left_child.get_schema();
-        let right_schema = right_child.get_schema();
-        // add the fields of the left child using the .attributes iterator
-        for attr in left_schema.attributes() {
-            attributes.push(attr.clone());
-        }
-        // add the fields of the right child using the .attributes iterator
-        for attr in right_schema.attributes() {
-            attributes.push(attr.clone());
-        }
-        // now we create our joined schema
-        let schema = TableSchema::new(attributes);
-        // now we make the predicate
+    ) -> Result<Self, CrustyError> {
+        Self::new_with_build_side(
+            op,
+            left_index,
+            right_index,
+            left_child,
+            right_child,
+            BuildSide::Right,
+        )
+    }
+
+    /// Constructor for a hash equi-join operator that builds the hash table over the given
+    /// child and probes it with the other. The joined tuples hold the columns of the left
+    /// child first either way.
+    ///
+    /// The hash table is built when the join is opened, so that the build child reads the
+    /// parameters bound for that run.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - Operation in join condition.
+    /// * `left_index` - Index of the left field in join condition.
+    /// * `right_index` - Index of the right field in join condition.
+    /// * `left_child` - Left child of join operator.
+    /// * `right_child` - Left child of join operator.
+    /// * `build_side` - Child to build the hash table over.
+    pub fn new_with_build_side(
+        op: SimplePredicateOp,
+        left_index: usize,
+        right_index: usize,
+        left_child: Box<dyn OpIterator>,
+        right_child: Box<dyn OpIterator>,
+        build_side: BuildSide,
+    ) -> Result<Self, CrustyError> {
+        if !matches!(op, SimplePredicateOp::Equals) {
+            return Err(CrustyError::ValidationError(format!(
+                "A hash join cannot evaluate the join predicate {}",
+                op
+            )));
+        }
+        // we make the predicate, and the joined schema from those of the children
         let predicate = JoinPredicate::new(op, left_index, right_index);
-        // build a hashtable for one of the children, we will arbitrarily choose right
-        let hash_table: HashMap<Field, Vec<Tuple>> = HashMap::new();
-        // now we create the base struct with this empty hash map
-        let mut res = HashEqJoin {
+        let schema = predicate.validate(left_child.get_schema(), right_child.get_schema())?;
+        let build_side = build_side.resolve(left_child.as_ref(), right_child.as_ref());
+        Ok(HashEqJoin {
             predicate,
             left_child,
             right_child,
             schema,
-            hash_table,
+            build_side,
+            hash_table: HashMap::new(),
             open: false,
+            pending: Vec::new(),
+            key_filter: None,
+            build_bytes: 0,
+            grant: None,
+        })
+    }
+
+    /// Reads the build child into the hash table, keyed by its join field, replacing the
+    /// table of the previous run.
+    fn build(&mut self) -> Result<(), CrustyError> {
+        self.hash_table.clear();
+        if let Some(grant) = &self.grant {
+            grant.release(self.build_bytes);
+        }
+        self.build_bytes = 0;
+        let (build, key_index) = match self.build_side {
+            BuildSide::Left => (&mut self.left_child, self.predicate.left_index),
+            _ => (&mut self.right_child, self.predicate.right_index),
         };
-        // populaet the hash table
-        // open the right child
-        res.right_child.open().unwrap();
-
-        // iterate through the right child
-        while let Some(tuple) = res.right_child.next().unwrap() {
-            // get the field we are joining on
-            let field = tuple.get_field(right_index).unwrap();
-            // get the hash of the field
-            let hash = field;
-            if res.hash_table.contains_key(hash) {
-                // if the hash is already in the hash table, we append the tuple to the vector
-                res.hash_table.get_mut(hash).unwrap().push(tuple.clone());
-            } else {
-                // if the hash is not in the hash table, we create a new vector and insert the tuple
-                let vec = vec![tuple.clone()];
-                res.hash_table.insert(hash.clone(), vec);
-            }
+        build.open()?;
+        while let Some(tuple) = build.next()? {
+            let key = tuple.get_field(key_index).unwrap().clone();
+            self.build_bytes += tuple.to_bytes().len();
+            self.hash_table.entry(key).or_default().push(tuple);
         }
-        // reset and close the right child
-        res.right_child.rewind().unwrap();
-        res.right_child.close().unwrap();
-        res
+        build.close()?;
+        if let Some(grant) = &self.grant {
+            grant.request(self.build_bytes);
+        }
+        Ok(())
+    }
+
+    /// Child that probes the hash table.
+    fn probe_child(&mut self) -> &mut Box<dyn OpIterator> {
+        match self.build_side {
+            BuildSide::Left => &mut self.right_child,
+            _ => &mut self.left_child,
+        }
+    }
+
+    /// Skips the probes of outer tuples whose key the filter rules out for the build side.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Test for the keys of the build child.
+    pub fn with_key_filter(mut self, filter: KeyFilter) -> Self {
+        self.key_filter = Some(filter);
+        self
+    }
+
+    /// Counts the hash table against a memory budget. The hash table cannot be spilled, so
+    /// the operators that can spill make room for it.
+    ///
+    /// # Arguments
+    ///
+    /// * `grant` - Grant of the budget.
+    pub fn with_memory_grant(mut self, grant: MemoryGrant) -> Self {
+        self.grant = Some(grant);
+        self
     }
 }
 
 impl OpIterator for HashEqJoin {
     fn open(&mut self) -> Result<(), CrustyError> {
-        // exact same as nested loop join
-        self.left_child.open()?;
-        self.right_child.open()?;
+        // the build child is read into the hash table and closed, so only the probe child
+        // stays open
+        self.build()?;
+        self.probe_child().open()?;
         self.open = true;
         Ok(())
     }
 
     fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
-        // easier than the nested loop join, as we can just use a hashmap
-        // to store the tuples based on the attribute we are joining on
-        // and iterate through the tuples in the hashmap
-
         // first we must check that the operator is open
         if !self.open {
             panic!("Operator has not been opened");
         }
-        // now we iterate through the left and compare each element with the
-        // hash table, if it is in the hashtable, we join the tuples
-        if let Some(ltuple) = self.left_child.next().unwrap()  {
-            // compare it with the HashTable
-            let field = ltuple.get_field(self.predicate.left_index).unwrap();
-            let hash = field;
-            if self.hash_table.contains_key(hash) {
-                // if the hash is in the hash table, we append the fields in the left tuple to the vector
-                // and then we iterate through the tuples in the hash table
-                if let Some(tuple) = self.hash_table.get(hash).unwrap().iter().next() {
-                    // create a new tuple with the fields of the left and right child
-                    let mut new_field_vals = Vec::new();
-                    for i in 0..ltuple.size() {
-                        new_field_vals.push(ltuple.get_field(i).unwrap().clone());
-                    }
-                    for i in 0..tuple.size() {
-                        new_field_vals.push(tuple.get_field(i).unwrap().clone());
-                    }
-                    return Ok(Some(Tuple::new(new_field_vals)));
+        // return the remaining matches of the previous probe tuple first
+        if let Some(tuple) = self.pending.pop() {
+            return Ok(Some(tuple));
+        }
+        // now we iterate through the probe child and look up each tuple in the hash table,
+        // joining it with every match
+        let build_left = self.build_side == BuildSide::Left;
+        let (probe, probe_index) = match self.build_side {
+            BuildSide::Left => (&mut self.right_child, self.predicate.right_index),
+            _ => (&mut self.left_child, self.predicate.left_index),
+        };
+        while let Some(ptuple) = probe.next()? {
+            let field = ptuple.get_field(probe_index).unwrap();
+            if self
+                .key_filter
+                .as_ref()
+                .is_some_and(|filter| !filter(field))
+            {
+                continue;
+            }
+            if let Some(matches) = self.hash_table.get(field) {
+                // matches are pushed in reverse so they are popped in build order
+                for btuple in matches.iter().rev() {
+                    let (ltuple, rtuple) = if build_left {
+                        (btuple, &ptuple)
+                    } else {
+                        (&ptuple, btuple)
+                    };
+                    let mut new_field_vals = ltuple.field_vals().cloned().collect::<Vec<_>>();
+                    new_field_vals.extend(rtuple.field_vals().cloned());
+                    self.pending.push(Tuple::new(new_field_vals));
                 }
+                return Ok(self.pending.pop());
             }
-            else {
-                // otherwise, the hash is not in the hash table, so we iterate the left child again
-                return self.next();
-            }            
         }
         Ok(None)
     }
 
     fn close(&mut self) -> Result<(), CrustyError> {
-        // close the children, then set open to false
-        self.left_child.close()?;
-        self.right_child.close()?;
+        // close the probe child, then set open to false
+        self.probe_child().close()?;
+        self.pending.clear();
         self.open = false;
         Ok(())
     }
 
     fn rewind(&mut self) -> Result<(), CrustyError> {
-        // rewind the children
-        self.left_child.rewind()?;
-        self.right_child.rewind()?;
+        if !self.open {
+            panic!("Operator has not been opened");
+        }
+        // keep the hash table and restart probing from the first probe tuple
+        self.probe_child().rewind()?;
+        self.pending.clear();
         Ok(())
     }
 
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let predicate = self
+            .predicate
+            .describe(self.left_child.as_ref(), self.right_child.as_ref());
+        let mut node = PlanNode::new("HashEqJoin").param("predicate", predicate);
+        if self.build_side == BuildSide::Left {
+            node = node.param("build", "left");
+        }
+        if self.key_filter.is_some() {
+            node = node.param("key_filter", "bloom");
+        }
+        node.child(self.left_child.as_ref())
+            .child(self.right_child.as_ref())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::opiterator::testutil::*;
+    use crate::opiterator::SeqScan;
     use common::testutil::*;
+    use common::DataType;
 
     const WIDTH1: usize = 2;
     const WIDTH2: usize = 3;
@@ -336,22 +515,21 @@ mod test {
         HashEq,
     }
 
-    pub fn scan1() -> TupleIterator {
-        let tuples = create_tuple_list(vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7, 8]]);
-        let ts = get_int_table_schema(WIDTH1);
-        TupleIterator::new(tuples, ts)
+    pub fn scan1() -> SeqScan {
+        test_scan("s1", vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7, 8]])
     }
 
-    pub fn scan2() -> TupleIterator {
-        let tuples = create_tuple_list(vec![
-            vec![1, 2, 3],
-            vec![2, 3, 4],
-            vec![3, 4, 5],
-            vec![4, 5, 6],
-            vec![5, 6, 7],
-        ]);
-        let ts = get_int_table_schema(WIDTH2);
-        TupleIterator::new(tuples, ts)
+    pub fn scan2() -> SeqScan {
+        test_scan(
+            "s2",
+            vec![
+                vec![1, 2, 3],
+                vec![2, 3, 4],
+                vec![3, 4, 5],
+                vec![4, 5, 6],
+                vec![5, 6, 7],
+            ],
+        )
     }
 
     pub fn eq_join() -> TupleIterator {
@@ -420,14 +598,18 @@ mod test {
         let s1 = Box::new(scan1());
         let s2 = Box::new(scan2());
         match ty {
-            JoinType::NestedLoop => Box::new(Join::new(op, left_index, right_index, s1, s2)),
-            JoinType::HashEq => Box::new(HashEqJoin::new(op, left_index, right_index, s1, s2)),
+            JoinType::NestedLoop => {
+                Box::new(Join::new(op, left_index, right_index, s1, s2).unwrap())
+            }
+            JoinType::HashEq => {
+                Box::new(HashEqJoin::new(op, left_index, right_index, s1, s2).unwrap())
+            }
         }
     }
 
     fn test_get_schema(join_type: JoinType) {
         let op = construct_join(join_type, SimplePredicateOp::Equals, 0, 0);
-        let expected = get_int_table_schema(WIDTH1 + WIDTH2);
+        let expected = scan1().get_schema().merge(scan2().get_schema());
         let actual = op.get_schema();
         assert_eq!(&expected, actual);
     }
@@ -462,6 +644,19 @@ mod test {
         Ok(())
     }
 
+    fn test_rewind_mid_stream(join_type: JoinType) -> Result<(), CrustyError> {
+        let mut op = construct_join(join_type, SimplePredicateOp::Equals, 0, 0);
+        op.open()?;
+        let first = op.next()?;
+        op.next()?;
+        op.rewind()?;
+        assert_eq!(first, op.next()?);
+        op.rewind()?;
+        let mut eq_join = eq_join();
+        eq_join.open()?;
+        match_all_tuples(op, Box::new(eq_join))
+    }
+
     fn test_eq_join(join_type: JoinType) -> Result<(), CrustyError> {
         let mut op = construct_join(join_type, SimplePredicateOp::Equals, 0, 0);
         let mut eq_join = eq_join();
@@ -494,6 +689,48 @@ mod test {
         match_all_tuples(op, Box::new(lt_or_eq_join))
     }
 
+    fn test_eq_join_duplicates(join_type: JoinType) -> Result<(), CrustyError> {
+        let left = create_tuple_list(vec![vec![1, 1], vec![2, 2], vec![1, 3]]);
+        let right = create_tuple_list(vec![vec![1, 4], vec![1, 5], vec![3, 6]]);
+        let s1 = Box::new(TupleIterator::new(left, get_int_table_schema(2)));
+        let s2 = Box::new(TupleIterator::new(right, get_int_table_schema(2)));
+        let mut op: Box<dyn OpIterator> = match join_type {
+            JoinType::NestedLoop => Box::new(Join::new(SimplePredicateOp::Equals, 0, 0, s1, s2)?),
+            JoinType::HashEq => Box::new(HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, s1, s2)?),
+        };
+        let expected = create_tuple_list(vec![
+            vec![1, 1, 1, 4],
+            vec![1, 1, 1, 5],
+            vec![1, 3, 1, 4],
+            vec![1, 3, 1, 5],
+        ]);
+        let mut expected = TupleIterator::new(expected, get_int_table_schema(4));
+        op.open()?;
+        expected.open()?;
+        match_all_tuples(op, Box::new(expected))
+    }
+
+    fn test_invalid_join(join_type: JoinType) {
+        let construct = |left_index, right_index, right_schema: TableSchema| {
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(TupleIterator::new(Vec::new(), right_schema));
+            let op = SimplePredicateOp::Equals;
+            match join_type {
+                JoinType::NestedLoop => Join::new(op, left_index, right_index, s1, s2).err(),
+                JoinType::HashEq => HashEqJoin::new(op, left_index, right_index, s1, s2).err(),
+            }
+        };
+        let ints = get_int_table_schema(WIDTH2);
+        assert!(construct(WIDTH1, 0, ints.clone()).is_some());
+        assert!(construct(0, WIDTH2, ints.clone()).is_some());
+        assert!(construct(0, 0, ints).is_none());
+        let strings = TableSchema::from_vecs(vec!["s"], vec![DataType::String]);
+        match construct(0, 0, strings) {
+            Some(CrustyError::ValidationError(message)) => assert!(message.contains("String")),
+            other => panic!("Expected a type mismatch, got {:?}", other),
+        }
+    }
+
     mod join {
         use super::*;
 
@@ -530,6 +767,11 @@ mod test {
             test_eq_join(JoinType::NestedLoop)
         }
 
+        #[test]
+        fn eq_join_duplicates() -> Result<(), CrustyError> {
+            test_eq_join_duplicates(JoinType::NestedLoop)
+        }
+
         #[test]
         fn gt_join() -> Result<(), CrustyError> {
             test_gt_join(JoinType::NestedLoop)
@@ -544,10 +786,18 @@ mod test {
         fn lt_or_eq_join() -> Result<(), CrustyError> {
             test_lt_or_eq_join(JoinType::NestedLoop)
         }
+
+        #[test]
+        fn invalid_join() {
+            test_invalid_join(JoinType::NestedLoop);
+        }
     }
 
     mod hash_join {
         use super::*;
+        use crate::opiterator::MemoryBudget;
+        use std::cell::Cell;
+        use std::rc::Rc;
 
         #[test]
         fn get_schema() {
@@ -575,5 +825,165 @@ mod test {
         fn eq_join() -> Result<(), CrustyError> {
             test_eq_join(JoinType::HashEq)
         }
+
+        #[test]
+        fn eq_join_duplicates() -> Result<(), CrustyError> {
+            test_eq_join_duplicates(JoinType::HashEq)
+        }
+
+        #[test]
+        fn rewind_mid_stream() -> Result<(), CrustyError> {
+            test_rewind_mid_stream(JoinType::HashEq)
+        }
+
+        #[test]
+        fn rewind_keeps_hash_table() -> Result<(), CrustyError> {
+            // Matches of an outer tuple left pending by the rewind are not returned again.
+            let left = create_tuple_list(vec![vec![1, 1], vec![2, 2]]);
+            let right = create_tuple_list(vec![vec![1, 4], vec![1, 5], vec![2, 6]]);
+            let s1 = Box::new(TupleIterator::new(left, get_int_table_schema(2)));
+            let s2 = Box::new(TupleIterator::new(right, get_int_table_schema(2)));
+            let mut op = HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, s1, s2)?;
+            op.open()?;
+            // The build side is not read again, so emptying it changes nothing.
+            op.right_child = Box::new(TupleIterator::new(Vec::new(), get_int_table_schema(2)));
+            assert_eq!(Some(int_vec_to_tuple(vec![1, 1, 1, 4])), op.next()?);
+            op.rewind()?;
+            let expected =
+                create_tuple_list(vec![vec![1, 1, 1, 4], vec![1, 1, 1, 5], vec![2, 2, 2, 6]]);
+            let mut expected = TupleIterator::new(expected, get_int_table_schema(4));
+            expected.open()?;
+            match_all_tuples(Box::new(op), Box::new(expected))
+        }
+
+        #[test]
+        fn invalid_join() {
+            test_invalid_join(JoinType::HashEq);
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            assert!(HashEqJoin::new(SimplePredicateOp::LessThan, 0, 0, s1, s2).is_err());
+        }
+
+        #[test]
+        fn key_filter() -> Result<(), CrustyError> {
+            // 7 is the only key of the left side missing from the right side
+            let probes = Rc::new(Cell::new(0));
+            let counter = probes.clone();
+            let filter: KeyFilter = Box::new(move |key| {
+                counter.set(counter.get() + 1);
+                *key != Field::IntField(7)
+            });
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            let mut op =
+                HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, s1, s2)?.with_key_filter(filter);
+            let mut expected = super::eq_join();
+            op.open()?;
+            expected.open()?;
+            match_all_tuples(Box::new(op), Box::new(expected))?;
+            assert_eq!(4, probes.get());
+            Ok(())
+        }
+
+        #[test]
+        fn memory_grant() {
+            let budget = MemoryBudget::new(usize::MAX);
+            let materialize = budget.grant(true);
+            assert!(materialize.request(1));
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            let mut op = HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, s1, s2)
+                .unwrap()
+                .with_memory_grant(budget.grant(false));
+            assert_eq!(1, budget.used());
+            op.open().unwrap();
+            assert!(budget.used() > 1);
+            // Reopening rebuilds the hash table in place of the previous one.
+            let used = budget.used();
+            op.close().unwrap();
+            op.open().unwrap();
+            assert_eq!(used, budget.used());
+            // Over the limit, the spillable operator is told to make room for the hash table.
+            budget.set_limit(budget.used() - 1);
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            let mut other = HashEqJoin::new(SimplePredicateOp::Equals, 0, 0, s1, s2)
+                .unwrap()
+                .with_memory_grant(budget.grant(false));
+            other.open().unwrap();
+            assert!(materialize.should_spill());
+            drop(op);
+            drop(other);
+            assert_eq!(1, budget.used());
+        }
+    }
+
+    mod hash_join_build_left {
+        use super::*;
+        use crate::opiterator::Profile;
+
+        /// Tuples of an open operator, sorted: probing the left side hashed changes the order.
+        fn sorted(op: &mut dyn OpIterator) -> Result<Vec<Vec<Field>>, CrustyError> {
+            let mut tuples = Vec::new();
+            while let Some(tuple) = op.next()? {
+                tuples.push(tuple.field_vals().cloned().collect::<Vec<_>>());
+            }
+            tuples.sort();
+            Ok(tuples)
+        }
+
+        fn build_left() -> Result<HashEqJoin, CrustyError> {
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            let op = SimplePredicateOp::Equals;
+            HashEqJoin::new_with_build_side(op, 0, 0, s1, s2, BuildSide::Left)
+        }
+
+        #[test]
+        fn eq_join() -> Result<(), CrustyError> {
+            let mut op = build_left()?;
+            assert_eq!(
+                &scan1().get_schema().merge(scan2().get_schema()),
+                op.get_schema()
+            );
+            let mut expected = super::eq_join();
+            op.open()?;
+            expected.open()?;
+            assert_eq!(sorted(&mut expected)?, sorted(&mut op)?);
+            Ok(())
+        }
+
+        #[test]
+        fn rewind_mid_stream() -> Result<(), CrustyError> {
+            let mut op = build_left()?;
+            op.open()?;
+            op.next()?;
+            op.rewind()?;
+            let mut expected = super::eq_join();
+            expected.open()?;
+            assert_eq!(sorted(&mut expected)?, sorted(&mut op)?);
+            Ok(())
+        }
+
+        #[test]
+        fn smaller_build_side() -> Result<(), CrustyError> {
+            let construct = |left_rows: f64, right_rows: f64| {
+                let s1 = Box::new(Profile::new(Box::new(scan1()), Some(left_rows)));
+                let s2 = Box::new(Profile::new(Box::new(scan2()), Some(right_rows)));
+                let op = SimplePredicateOp::Equals;
+                HashEqJoin::new_with_build_side(op, 0, 0, s1, s2, BuildSide::Smaller)
+            };
+            let op = construct(10.0, 1000.0)?;
+            assert_eq!(BuildSide::Left, op.build_side);
+            assert!(op.describe().to_string().contains("build: left"));
+            assert_eq!(BuildSide::Right, construct(1000.0, 10.0)?.build_side);
+            // Without estimates the right side is built.
+            let s1 = Box::new(scan1());
+            let s2 = Box::new(scan2());
+            let op = SimplePredicateOp::Equals;
+            let op = HashEqJoin::new_with_build_side(op, 0, 0, s1, s2, BuildSide::Smaller)?;
+            assert_eq!(BuildSide::Right, op.build_side);
+            Ok(())
+        }
     }
 }
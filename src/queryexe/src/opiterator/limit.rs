@@ -0,0 +1,166 @@
+use super::{OpIterator, PlanNode};
+use common::{CrustyError, TableSchema, Tuple};
+
+/// Limit operator, which skips the first tuples of its child and returns at most a number
+/// of the following ones, such as the rows of `SELECT * FROM t LIMIT 10 OFFSET 5`.
+///
+/// The child is not read past the last tuple returned.
+pub struct Limit {
+    /// Maximum number of tuples to return, or None to return all of them.
+    limit: Option<usize>,
+    /// Number of tuples of the child to skip.
+    offset: usize,
+    /// Number of tuples returned since the operator was opened or rewound.
+    returned: usize,
+    /// Boolean determining if the offset was skipped since the operator was opened or rewound.
+    skipped: bool,
+    /// Boolean determining if iterator is open.
+    open: bool,
+    /// Child operator passing data into the operator.
+    child: Box<dyn OpIterator>,
+}
+
+impl Limit {
+    /// Limit constructor.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Maximum number of tuples to return, or None to return all of them.
+    /// * `offset` - Number of tuples of the child to skip.
+    /// * `child` - Child OpIterator passing data into the operator.
+    pub fn new(limit: Option<usize>, offset: usize, child: Box<dyn OpIterator>) -> Self {
+        Self {
+            limit,
+            offset,
+            returned: 0,
+            skipped: false,
+            open: false,
+            child,
+        }
+    }
+}
+
+impl OpIterator for Limit {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.child.open()?;
+        self.returned = 0;
+        self.skipped = false;
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        if self.limit.is_some_and(|limit| self.returned >= limit) {
+            return Ok(None);
+        }
+        if !self.skipped {
+            self.skipped = true;
+            for _ in 0..self.offset {
+                if self.child.next()?.is_none() {
+                    break;
+                }
+            }
+        }
+        let tuple = self.child.next()?;
+        if tuple.is_some() {
+            self.returned += 1;
+        }
+        Ok(tuple)
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.close()?;
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.rewind()?;
+        self.returned = 0;
+        self.skipped = false;
+        Ok(())
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.child.get_schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        let mut node = PlanNode::new("Limit");
+        if let Some(limit) = self.limit {
+            node = node.param("limit", limit);
+        }
+        if self.offset > 0 {
+            node = node.param("offset", self.offset);
+        }
+        node.child(self.child.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::test_scan;
+
+    fn limit(limit: Option<usize>, offset: usize) -> Limit {
+        let scan = test_scan("t", (0..5).map(|i| vec![i]).collect());
+        Limit::new(limit, offset, Box::new(scan))
+    }
+
+    /// Returns the values of the first field of the tuples of an operator.
+    fn values(op: &mut dyn OpIterator) -> Result<Vec<i32>, CrustyError> {
+        let mut values = Vec::new();
+        while let Some(tuple) = op.next()? {
+            values.push(tuple.get_field(0).unwrap().unwrap_int_field());
+        }
+        Ok(values)
+    }
+
+    #[test]
+    fn test_limit() -> Result<(), CrustyError> {
+        for (limit, offset, expected) in [
+            (Some(2), 0, vec![0, 1]),
+            (Some(2), 2, vec![2, 3]),
+            (Some(10), 3, vec![3, 4]),
+            (None, 1, vec![1, 2, 3, 4]),
+            (Some(0), 0, vec![]),
+            (Some(2), 7, vec![]),
+        ] {
+            let mut op = self::limit(limit, offset);
+            op.open()?;
+            assert_eq!(expected, values(&mut op)?, "{:?} {}", limit, offset);
+            op.rewind()?;
+            assert_eq!(expected, values(&mut op)?, "{:?} {}", limit, offset);
+            op.close()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_describe() {
+        let explain = limit(Some(3), 2).describe().to_string();
+        assert!(
+            explain.contains("Limit (limit: 3, offset: 2)"),
+            "{}",
+            explain
+        );
+        let explain = limit(None, 2).describe().to_string();
+        assert!(explain.contains("Limit (offset: 2)"), "{}", explain);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut op = limit(Some(1), 0);
+        op.next().unwrap();
+    }
+}
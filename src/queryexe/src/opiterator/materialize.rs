@@ -0,0 +1,364 @@
+use super::{MemoryGrant, OpIterator, PlanNode};
+use crate::StorageManager;
+use common::ids::{ContainerId, Permissions, TransactionId};
+use common::storage_trait::StorageTrait;
+use common::{CrustyError, TableSchema, Tuple};
+use std::sync::Arc;
+
+/// Bytes of tuples a `MaterializeIterator` keeps in memory by default before spilling.
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// Iterator over a set of tuples that keeps at most `memory_limit` bytes of them in memory and
+/// spills the rest to a temporary container, so results larger than memory can be buffered.
+/// Tuples are returned in the order they were added.
+///
+/// The container is created on the first spill and removed when the iterator is dropped, or
+/// when its transaction finishes. With a memory grant, the iterator also spills once the
+/// memory budget refuses more tuples or tells it to spill the ones it keeps.
+pub struct MaterializeIterator {
+    /// The first tuples added, kept in memory.
+    tuples: Vec<Tuple>,
+    /// Encoded size of the tuples kept in memory.
+    memory_used: usize,
+    /// Maximum encoded size of the tuples kept in memory.
+    memory_limit: usize,
+    /// Number of tuples spilled to the container.
+    spilled: usize,
+    /// Schema of the output.
+    schema: TableSchema,
+    storage_manager: Arc<StorageManager>,
+    /// Temporary container holding the spilled tuples, once created.
+    container_id: Option<ContainerId>,
+    transaction_id: TransactionId,
+    /// Index of the next in-memory tuple, None if the iterator is closed.
+    index: Option<usize>,
+    /// Iterator over the spilled tuples, once the in-memory ones are returned.
+    spill_iter: Option<<StorageManager as StorageTrait>::ValIterator>,
+    /// Memory of the tuples kept in memory, if they count against a memory budget.
+    grant: Option<MemoryGrant>,
+}
+
+impl MaterializeIterator {
+    /// Create an empty materialize iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_manager` - Storage manager to spill tuples to.
+    /// * `schema` - Schema of the output results.
+    /// * `memory_limit` - Bytes of tuples to keep in memory before spilling.
+    /// * `tid` - Transaction spilling the tuples.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        schema: TableSchema,
+        memory_limit: usize,
+        tid: TransactionId,
+    ) -> Self {
+        Self {
+            tuples: Vec::new(),
+            memory_used: 0,
+            memory_limit,
+            spilled: 0,
+            schema,
+            storage_manager,
+            container_id: None,
+            transaction_id: tid,
+            index: None,
+            spill_iter: None,
+            grant: None,
+        }
+    }
+
+    /// Counts the tuples kept in memory against a memory budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `grant` - Spillable grant of the budget.
+    pub fn with_memory_grant(mut self, grant: MemoryGrant) -> Self {
+        self.grant = Some(grant);
+        self
+    }
+
+    /// Adds a tuple after the ones already added, spilling it if the memory limit is reached.
+    pub fn push(&mut self, tuple: Tuple) -> Result<(), CrustyError> {
+        if self.grant.as_ref().is_some_and(|g| g.should_spill()) && !self.tuples.is_empty() {
+            self.spill_memory()?;
+        }
+        let bytes = tuple.to_bytes();
+        if self.spilled == 0
+            && self.memory_used + bytes.len() <= self.memory_limit
+            && self.grant.as_ref().is_none_or(|g| g.request(bytes.len()))
+        {
+            self.memory_used += bytes.len();
+            self.tuples.push(tuple);
+            return Ok(());
+        }
+        self.spill(bytes)
+    }
+
+    /// Moves the tuples kept in memory to the container, ahead of the tuples added later.
+    fn spill_memory(&mut self) -> Result<(), CrustyError> {
+        for tuple in std::mem::take(&mut self.tuples) {
+            self.spill(tuple.to_bytes())?;
+        }
+        if let Some(grant) = &self.grant {
+            grant.release(self.memory_used);
+        }
+        self.memory_used = 0;
+        Ok(())
+    }
+
+    /// Adds an encoded tuple to the container, creating it on the first spill.
+    fn spill(&mut self, bytes: Vec<u8>) -> Result<(), CrustyError> {
+        let container_id = match self.container_id {
+            Some(container_id) => container_id,
+            None => {
+                let container_id = self
+                    .storage_manager
+                    .create_temp_container(self.transaction_id)?;
+                *self.container_id.insert(container_id)
+            }
+        };
+        self.storage_manager
+            .insert_value(container_id, bytes, self.transaction_id)?;
+        self.spilled += 1;
+        Ok(())
+    }
+
+    /// Adds all the tuples returned by an iterator, which must be open.
+    pub fn push_all(&mut self, child: &mut dyn OpIterator) -> Result<(), CrustyError> {
+        while let Some(tuple) = child.next()? {
+            self.push(tuple)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the tuples added, releasing their memory and the spill container, so the
+    /// iterator can be filled again.
+    pub fn clear(&mut self) -> Result<(), CrustyError> {
+        self.tuples.clear();
+        if let Some(grant) = &self.grant {
+            grant.release(self.memory_used);
+        }
+        self.memory_used = 0;
+        self.spilled = 0;
+        self.spill_iter = None;
+        if let Some(container_id) = self.container_id.take() {
+            self.storage_manager.remove_container(container_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of tuples added.
+    pub fn len(&self) -> usize {
+        self.tuples.len() + self.spilled
+    }
+
+    /// Returns true if no tuples were added.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of tuples spilled to the container.
+    pub fn spilled(&self) -> usize {
+        self.spilled
+    }
+}
+
+impl OpIterator for MaterializeIterator {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.index = Some(0);
+        self.spill_iter = None;
+        Ok(())
+    }
+
+    /// Retrieves the next tuple, first from memory and then from the container.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator has not been opened.
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        let i = match self.index {
+            None => panic!("Operator has not been opened"),
+            Some(i) => i,
+        };
+        if let Some(tuple) = self.tuples.get(i) {
+            self.index = Some(i + 1);
+            return Ok(Some(tuple.clone()));
+        }
+        let container_id = match self.container_id {
+            Some(container_id) => container_id,
+            None => return Ok(None),
+        };
+        let (storage_manager, tid) = (&self.storage_manager, self.transaction_id);
+        let spill_iter = self.spill_iter.get_or_insert_with(|| {
+            storage_manager.get_iterator(container_id, tid, Permissions::ReadOnly)
+        });
+        match spill_iter.next() {
+            Some((bytes, _)) => Ok(Some(common::encoding::decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.index = None;
+        self.spill_iter = None;
+        Ok(())
+    }
+
+    /// Make iterator point to the first tuple again.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the iterator has not been opened.
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if self.index.is_none() {
+            panic!("Operator has not been opened")
+        }
+        self.close()?;
+        self.open()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Materialize")
+            .param("rows", self.len())
+            .param("spilled", self.spilled)
+    }
+}
+
+impl Drop for MaterializeIterator {
+    fn drop(&mut self) {
+        if let Some(container_id) = self.container_id {
+            if let Err(e) = self.storage_manager.remove_container(container_id) {
+                warn!("Cannot remove spill container {}: {:?}", container_id, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::sum_int_fields;
+    use crate::opiterator::MemoryBudget;
+    use common::ids::StateType;
+    use common::temp_container::is_temp_container;
+    use common::testutil::*;
+
+    const ROWS: i32 = 100;
+
+    /// Creates a materialize iterator holding the ints 0..ROWS, keeping `in_memory` of them
+    /// in memory.
+    fn get_iterator(in_memory: usize) -> Result<MaterializeIterator, CrustyError> {
+        let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+        let tuple_size = int_vec_to_tuple(vec![0]).to_bytes().len();
+        let mut mi = MaterializeIterator::new(
+            sm.clone(),
+            get_int_table_schema(1),
+            in_memory * tuple_size,
+            TransactionId::new(),
+        );
+        for i in 0..ROWS {
+            mi.push(int_vec_to_tuple(vec![i]))?;
+        }
+        Ok(mi)
+    }
+
+    #[test]
+    fn test_in_memory() -> Result<(), CrustyError> {
+        let mut mi = get_iterator(ROWS as usize)?;
+        assert_eq!(0, mi.spilled());
+        assert_eq!(ROWS as usize, mi.len());
+        mi.open()?;
+        assert_eq!((0..ROWS).sum::<i32>(), sum_int_fields(&mut mi)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spill_keeps_order() -> Result<(), CrustyError> {
+        let mut mi = get_iterator(10)?;
+        assert_eq!(ROWS as usize - 10, mi.spilled());
+        assert_eq!(ROWS as usize, mi.len());
+        mi.open()?;
+        for i in 0..ROWS {
+            assert_eq!(Some(int_vec_to_tuple(vec![i])), mi.next()?);
+        }
+        assert_eq!(None, mi.next()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewind() -> Result<(), CrustyError> {
+        let mut mi = get_iterator(0)?;
+        assert_eq!(ROWS as usize, mi.spilled());
+        mi.open()?;
+        let sum = sum_int_fields(&mut mi)?;
+        mi.rewind()?;
+        assert_eq!(sum, sum_int_fields(&mut mi)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_removes_container() -> Result<(), CrustyError> {
+        let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+        let mut mi =
+            MaterializeIterator::new(sm.clone(), get_int_table_schema(1), 0, TransactionId::new());
+        mi.push(int_vec_to_tuple(vec![1]))?;
+        let container_id = mi.container_id.unwrap();
+        assert!(is_temp_container(container_id));
+        drop(mi);
+        // A container can only depend on containers that exist.
+        assert!(sm
+            .create_container(1, None, StateType::BaseTable, Some(vec![container_id]))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_grant() -> Result<(), CrustyError> {
+        let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+        let tuple_size = int_vec_to_tuple(vec![0]).to_bytes().len();
+        let budget = MemoryBudget::new(10 * tuple_size);
+        let materialize = || {
+            let mi = MaterializeIterator::new(
+                sm.clone(),
+                get_int_table_schema(1),
+                DEFAULT_MEMORY_LIMIT,
+                TransactionId::new(),
+            );
+            mi.with_memory_grant(budget.grant(true))
+        };
+        let (mut first, mut second) = (materialize(), materialize());
+        for i in 0..6 {
+            second.push(int_vec_to_tuple(vec![i]))?;
+        }
+        // The second iterator was registered last, so it is told to spill.
+        for i in 0..5 {
+            first.push(int_vec_to_tuple(vec![i]))?;
+        }
+        assert_eq!(0, first.spilled());
+        assert_eq!(0, second.spilled());
+        second.push(int_vec_to_tuple(vec![6]))?;
+        assert_eq!(7, second.spilled());
+        assert_eq!(5 * tuple_size, budget.used());
+
+        second.open()?;
+        for i in 0..7 {
+            assert_eq!(Some(int_vec_to_tuple(vec![i])), second.next()?);
+        }
+        assert_eq!(None, second.next()?);
+        drop(first);
+        assert_eq!(0, budget.used());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut mi = get_iterator(ROWS as usize).unwrap();
+        mi.next().unwrap();
+    }
+}
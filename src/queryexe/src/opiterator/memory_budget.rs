@@ -0,0 +1,207 @@
+use std::sync::{Arc, Mutex};
+
+/// Memory shared by the operators of the plans run against it.
+///
+/// Operators that buffer tuples register a `MemoryGrant` and request memory from it before
+/// buffering more. While the memory granted stays within the limit every request succeeds.
+/// A request that would exceed the limit tells the most recently registered operator that
+/// can spill to do so: if that is the requesting operator the request is refused, otherwise
+/// it succeeds and the budget is over the limit until the other operator spills. Operators
+/// that cannot spill, such as the build side of a hash join, still have their memory
+/// counted so that the operators that can spill make room for it.
+#[derive(Clone, Debug)]
+pub struct MemoryBudget {
+    inner: Arc<Mutex<Grants>>,
+}
+
+#[derive(Debug)]
+struct Grants {
+    /// Bytes the operators may hold before they are told to spill.
+    limit: usize,
+    /// Registered grants, in the order they were registered.
+    grants: Vec<GrantState>,
+    /// Id of the next registered grant.
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct GrantState {
+    id: u64,
+    /// Bytes held by the operator.
+    used: usize,
+    /// Whether the operator can spill its memory.
+    spillable: bool,
+    /// Whether the operator was told to spill.
+    spill: bool,
+}
+
+impl MemoryBudget {
+    /// Creates a budget without any grants.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - Bytes the operators may hold before they are told to spill.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Grants {
+                limit,
+                grants: Vec::new(),
+                next_id: 0,
+            })),
+        }
+    }
+
+    /// Returns the bytes the operators may hold before they are told to spill.
+    pub fn limit(&self) -> usize {
+        self.inner.lock().unwrap().limit
+    }
+
+    /// Changes the limit, which applies to the next requests.
+    pub fn set_limit(&self, limit: usize) {
+        self.inner.lock().unwrap().limit = limit;
+    }
+
+    /// Returns the bytes held by all the operators.
+    pub fn used(&self) -> usize {
+        self.inner.lock().unwrap().used()
+    }
+
+    /// Registers an operator, which holds no memory until it requests some.
+    ///
+    /// # Arguments
+    ///
+    /// * `spillable` - Whether the operator can spill its memory when told to.
+    pub fn grant(&self, spillable: bool) -> MemoryGrant {
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.grants.push(GrantState {
+            id,
+            used: 0,
+            spillable,
+            spill: false,
+        });
+        MemoryGrant {
+            budget: self.clone(),
+            id,
+        }
+    }
+}
+
+impl Grants {
+    fn used(&self) -> usize {
+        self.grants.iter().map(|g| g.used).sum()
+    }
+
+    fn get_mut(&mut self, id: u64) -> &mut GrantState {
+        self.grants.iter_mut().find(|g| g.id == id).unwrap()
+    }
+}
+
+/// Memory of one operator, released when the grant is dropped.
+#[derive(Debug)]
+pub struct MemoryGrant {
+    budget: MemoryBudget,
+    id: u64,
+}
+
+impl MemoryGrant {
+    /// Requests memory for more tuples. Returns false if the operator must spill them
+    /// instead, in which case every later request is refused as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes the operator is about to hold.
+    pub fn request(&self, bytes: usize) -> bool {
+        let mut inner = self.budget.inner.lock().unwrap();
+        if inner.get_mut(self.id).spill {
+            return false;
+        }
+        if inner.used() + bytes > inner.limit {
+            let spilled = inner
+                .grants
+                .iter_mut()
+                .rev()
+                .find(|g| g.spillable && !g.spill && (g.used > 0 || g.id == self.id));
+            if let Some(grant) = spilled {
+                debug!("Memory budget exceeded, spilling grant {}", grant.id);
+                grant.spill = true;
+                if grant.id == self.id {
+                    return false;
+                }
+            }
+        }
+        inner.get_mut(self.id).used += bytes;
+        true
+    }
+
+    /// Returns true if the operator was told to spill the memory it holds.
+    pub fn should_spill(&self) -> bool {
+        let mut inner = self.budget.inner.lock().unwrap();
+        inner.get_mut(self.id).spill
+    }
+
+    /// Returns memory the operator no longer holds, such as tuples it spilled.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Bytes released.
+    pub fn release(&self, bytes: usize) {
+        let mut inner = self.budget.inner.lock().unwrap();
+        let grant = inner.get_mut(self.id);
+        grant.used = grant.used.saturating_sub(bytes);
+    }
+
+    /// Returns the bytes held by the operator.
+    pub fn used(&self) -> usize {
+        let mut inner = self.budget.inner.lock().unwrap();
+        inner.get_mut(self.id).used
+    }
+}
+
+impl Drop for MemoryGrant {
+    fn drop(&mut self) {
+        let mut inner = self.budget.inner.lock().unwrap();
+        inner.grants.retain(|g| g.id != self.id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_within_limit() {
+        let budget = MemoryBudget::new(100);
+        let a = budget.grant(true);
+        let b = budget.grant(false);
+        assert!(a.request(40));
+        assert!(b.request(60));
+        assert_eq!(100, budget.used());
+        assert!(!a.should_spill());
+        drop(b);
+        assert_eq!(40, budget.used());
+    }
+
+    #[test]
+    fn test_most_recent_spills() {
+        let budget = MemoryBudget::new(100);
+        let first = budget.grant(true);
+        let second = budget.grant(true);
+        let build = budget.grant(false);
+        assert!(first.request(30));
+        assert!(second.request(30));
+        // The most recently registered operator that can spill is told to.
+        assert!(build.request(50));
+        assert!(second.should_spill());
+        assert!(!first.should_spill());
+        assert!(!second.request(1));
+        second.release(30);
+        assert_eq!(80, budget.used());
+
+        // Exceeding the limit again refuses the requester once it is the most recent.
+        assert!(!first.request(30));
+        assert!(first.should_spill());
+        assert_eq!(80, budget.used());
+    }
+}
@@ -1,18 +1,49 @@
+pub(crate) use self::aggregate::output_dtype;
 pub use self::aggregate::Aggregate;
-pub use self::filter::{Filter, FilterPredicate};
-pub use self::join::{HashEqJoin, Join, JoinPredicate};
+pub use self::bitmapscan::BitmapScan;
+pub use self::columnscan::ColumnScan;
+pub use self::compute::Compute;
+pub use self::delete::Delete;
+pub use self::explain::{OpCounters, PlanNode, Profile, Profiling};
+pub use self::expression::{Expression, When};
+pub use self::filter::{BooleanPredicate, Filter, FilterPredicate};
+pub use self::indexscan::IndexScan;
+pub use self::insert::Insert;
+pub use self::join::{BuildSide, HashEqJoin, Join, JoinPredicate, KeyFilter};
+pub use self::limit::Limit;
+pub use self::materialize::{MaterializeIterator, DEFAULT_MEMORY_LIMIT};
+pub use self::memory_budget::{MemoryBudget, MemoryGrant};
+pub use self::parameters::Parameters;
 pub use self::project::ProjectIterator;
+pub use self::sample::Sample;
 pub use self::seqscan::SeqScan;
+pub use self::sort::Sort;
+pub use self::subquery::Subquery;
 pub use self::tuple_iterator::TupleIterator;
 pub use self::update::Update;
 use common::{CrustyError, TableSchema, Tuple};
 
 mod aggregate;
+mod bitmapscan;
+mod columnscan;
+mod compute;
+mod delete;
+mod explain;
+mod expression;
 mod filter;
+mod indexscan;
+mod insert;
 mod join;
+mod limit;
+mod materialize;
+mod memory_budget;
+mod parameters;
 mod project;
+mod sample;
 mod seqscan;
-mod testutil;
+mod sort;
+mod subquery;
+pub(crate) mod testutil;
 mod tuple_iterator;
 mod update;
 
@@ -43,4 +74,7 @@ pub trait OpIterator {
 
     /// Returns the schema associated with this OpIterator.
     fn get_schema(&self) -> &TableSchema;
+
+    /// Describes the operator and its children for EXPLAIN.
+    fn describe(&self) -> PlanNode;
 }
@@ -0,0 +1,154 @@
+use common::{CrustyError, DataType, Field};
+use std::sync::{Arc, RwLock};
+
+/// Values of the parameters of a plan, shared by the operators that refer to them.
+///
+/// A plan with parameters is built once, and each run binds the values of its parameters
+/// before opening the plan. Operators read the values when they are opened or rewound, so
+/// rebinding the parameters and rewinding the plan runs it with new constants. Parameter `i`
+/// is written `$i+1` in plans.
+#[derive(Clone, Debug, Default)]
+pub struct Parameters {
+    inner: Arc<RwLock<ParameterValues>>,
+}
+
+#[derive(Debug, Default)]
+struct ParameterValues {
+    /// Type of each parameter, from the columns it is compared to. None for the parameters
+    /// no operator refers to.
+    types: Vec<Option<DataType>>,
+    /// Bound value of each parameter.
+    values: Vec<Field>,
+}
+
+impl Parameters {
+    /// Creates a set of parameters without any declared.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares that a parameter is compared to a column of a type.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the parameter.
+    /// * `dtype` - Type of the column.
+    pub fn declare(&self, index: usize, dtype: &DataType) -> Result<(), CrustyError> {
+        let mut inner = self.inner.write().unwrap();
+        if inner.types.len() <= index {
+            inner.types.resize(index + 1, None);
+        }
+        match &inner.types[index] {
+            Some(declared) if declared != dtype => Err(CrustyError::ValidationError(format!(
+                "Parameter ${} is compared to columns of types {:?} and {:?}",
+                index + 1,
+                declared,
+                dtype
+            ))),
+            _ => {
+                inner.types[index] = Some(dtype.clone());
+                Ok(())
+            }
+        }
+    }
+
+    /// Number of parameters, including the ones no operator refers to before the last
+    /// declared.
+    pub fn len(&self) -> usize {
+        self.inner.read().unwrap().types.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the type of each parameter.
+    pub fn types(&self) -> Vec<Option<DataType>> {
+        self.inner.read().unwrap().types.clone()
+    }
+
+    /// Binds the values of the parameters for the next runs of the plan. Values are
+    /// converted to the types of their parameters like literals compared to columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - Value of each parameter, in order.
+    pub fn bind(&self, values: Vec<Field>) -> Result<(), CrustyError> {
+        let mut inner = self.inner.write().unwrap();
+        if values.len() != inner.types.len() {
+            return Err(CrustyError::ValidationError(format!(
+                "Expected {} parameters, got {}",
+                inner.types.len(),
+                values.len()
+            )));
+        }
+        let values = values
+            .into_iter()
+            .zip(&inner.types)
+            .enumerate()
+            .map(|(i, (value, dtype))| match dtype {
+                Some(dtype) => {
+                    let value = value.coerce(dtype);
+                    if value.has_type(dtype) {
+                        Ok(value)
+                    } else {
+                        Err(CrustyError::ValidationError(format!(
+                            "Parameter ${} must be of type {:?}, got {}",
+                            i + 1,
+                            dtype,
+                            value
+                        )))
+                    }
+                }
+                None => Ok(value),
+            })
+            .collect::<Result<_, _>>()?;
+        inner.values = values;
+        Ok(())
+    }
+
+    /// Returns the bound value of a parameter.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the parameter.
+    pub fn get(&self, index: usize) -> Result<Field, CrustyError> {
+        self.inner
+            .read()
+            .unwrap()
+            .values
+            .get(index)
+            .cloned()
+            .ok_or_else(|| {
+                CrustyError::ExecutionError(format!("Parameter ${} is not bound", index + 1))
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::Float;
+
+    #[test]
+    fn test_bind() -> Result<(), CrustyError> {
+        let params = Parameters::new();
+        params.declare(1, &DataType::Float)?;
+        params.declare(0, &DataType::Int)?;
+        params.declare(0, &DataType::Int)?;
+        assert!(params.declare(1, &DataType::String).is_err());
+        assert_eq!(2, params.len());
+        assert!(params.get(0).is_err());
+
+        params.bind(vec![Field::IntField(3), Field::IntField(4)])?;
+        assert_eq!(Field::IntField(3), params.get(0)?);
+        assert_eq!(Field::FloatField(Float(4.0)), params.get(1)?);
+
+        assert!(params.bind(vec![Field::IntField(3)]).is_err());
+        let wrong = vec![Field::StringField("a".to_string()), Field::IntField(4)];
+        assert!(params.bind(wrong).is_err());
+        // A failed bind keeps the values bound before.
+        assert_eq!(Field::IntField(3), params.get(0)?);
+        Ok(())
+    }
+}
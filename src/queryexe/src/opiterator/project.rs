@@ -1,4 +1,4 @@
-use super::OpIterator;
+use super::{OpIterator, PlanNode};
 use common::{CrustyError, TableSchema, Tuple};
 
 /// Projection operator.
@@ -109,22 +109,23 @@ impl OpIterator for ProjectIterator {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let columns: Vec<&str> = self.schema.attributes().map(|a| a.name()).collect();
+        PlanNode::new("Project")
+            .param("columns", columns.join(", "))
+            .child(self.child.as_ref())
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::super::TupleIterator;
     use super::*;
     use crate::opiterator::testutil::*;
 
-    use common::testutil::*;
-    const WIDTH: usize = 3;
-
     fn get_project(fields: Vec<usize>) -> ProjectIterator {
-        let tuples = create_tuple_list(vec![vec![0, 1, 2], vec![0, 1, 2], vec![0, 1, 2]]);
-        let schema = get_int_table_schema(WIDTH);
-        let ti = TupleIterator::new(tuples.to_vec(), schema);
-        ProjectIterator::new(fields, Box::new(ti))
+        let scan = test_scan("t", vec![vec![0, 1, 2]; 3]);
+        ProjectIterator::new(fields, Box::new(scan))
     }
 
     #[test]
@@ -0,0 +1,176 @@
+use super::{OpIterator, PlanNode, SeqScan};
+use crate::StorageManager;
+use common::ids::{ContainerId, TransactionId};
+use common::storage_trait::StorageTrait;
+use common::table::*;
+use common::{CrustyError, TableSchema, Tuple};
+use std::sync::{Arc, RwLock};
+
+/// Sampling scan operator. Returns the tuples of a random sample of the pages of a table,
+/// so that approximate answers can be computed without reading the whole table.
+pub struct Sample {
+    file_iter: <StorageManager as StorageTrait>::ValIterator,
+    schema: TableSchema,
+    /// Sampled table, used to pad records written under older schema versions.
+    table: Table,
+    alias: String,
+    open: bool,
+    storage_manager: Arc<StorageManager>,
+    container_id: ContainerId,
+    fraction: f64,
+    seed: u64,
+    transaction_id: TransactionId,
+}
+
+impl Sample {
+    /// Constructor for the sampling scan operator.
+    ///
+    /// # Arguments
+    ///
+    /// * `table` - Table to sample.
+    /// * `table_alias` - Table alias given by the user.
+    /// * `fraction` - Probability with which each page is included in the sample.
+    /// * `seed` - Seed selecting the sampled pages. Rewinding returns the same sample.
+    /// * `tid` - Transaction used to read the table.
+    pub fn new(
+        storage_manager: Arc<StorageManager>,
+        table: Arc<RwLock<Table>>,
+        table_alias: &str,
+        container_id: &ContainerId,
+        fraction: f64,
+        seed: u64,
+        tid: TransactionId,
+    ) -> Self {
+        let table_ref = table.read().unwrap();
+        let file_iter = storage_manager.sample_iterator(*container_id, fraction, seed, tid);
+        Self {
+            file_iter,
+            schema: SeqScan::schema(&table_ref.schema, table_alias),
+            table: table_ref.clone(),
+            alias: table_alias.to_string(),
+            open: false,
+            storage_manager,
+            container_id: *container_id,
+            fraction,
+            seed,
+            transaction_id: tid,
+        }
+    }
+
+    /// Returns the fraction of pages sampled. Dividing a count over the sample by this
+    /// estimates the count over the whole table.
+    pub fn fraction(&self) -> f64 {
+        self.fraction
+    }
+}
+
+impl OpIterator for Sample {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        match self.file_iter.next() {
+            Some((bytes, value_id)) => {
+                let mut tuple = self.table.read_tuple(&bytes);
+                tuple.value_id = Some(value_id);
+                Ok(Some(tuple))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.file_iter = self.storage_manager.sample_iterator(
+            self.container_id,
+            self.fraction,
+            self.seed,
+            self.transaction_id,
+        );
+        Ok(())
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("Sample")
+            .param("table", &self.alias)
+            .param("container", self.container_id)
+            .param("fraction", self.fraction)
+            .param("seed", self.seed)
+    }
+}
+
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::num_tuples;
+    use common::testutil::*;
+
+    const WIDTH: usize = 2;
+    const ROWS: i32 = 1000;
+    const TABLE: &str = "Sample";
+
+    fn get_sample(fraction: f64) -> Sample {
+        let schema = get_int_table_schema(WIDTH);
+        let table = Arc::new(RwLock::new(Table::new(TABLE.to_string(), schema)));
+        let sm: Arc<StorageManager> = Arc::new(StorageManager::new_test_sm());
+        let cid = 0;
+        sm.create_table(cid).unwrap();
+        let tid = TransactionId::new();
+        for i in 0..ROWS {
+            sm.insert_value(cid, int_vec_to_tuple(vec![i, i]).to_bytes(), tid)
+                .unwrap();
+        }
+        Sample::new(sm, table, TABLE, &cid, fraction, 11, tid)
+    }
+
+    #[test]
+    fn test_sample_bounds() -> Result<(), CrustyError> {
+        let mut all = get_sample(1.0);
+        all.open()?;
+        assert_eq!(ROWS as u32, num_tuples(&mut all)?);
+
+        let mut none = get_sample(0.0);
+        none.open()?;
+        assert_eq!(0, num_tuples(&mut none)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_rewind() -> Result<(), CrustyError> {
+        let mut sample = get_sample(0.5);
+        sample.open()?;
+        let count = num_tuples(&mut sample)?;
+        assert!(count > 0 && count < ROWS as u32);
+        sample.rewind()?;
+        assert_eq!(count, num_tuples(&mut sample)?);
+        assert_eq!(
+            "Sample.",
+            sample.get_schema().get_attribute(0).unwrap().name()
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut sample = get_sample(0.5);
+        sample.next();
+    }
+}
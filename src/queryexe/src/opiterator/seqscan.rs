@@ -1,4 +1,4 @@
-use super::OpIterator;
+use super::{OpIterator, PlanNode};
 use crate::StorageManager;
 use common::ids::Permissions;
 use common::ids::{ContainerId, TransactionId};
@@ -9,11 +9,20 @@ use std::sync::{Arc, RwLock};
 
 /// Sequential scan operator
 pub struct SeqScan {
-    file_iter: <StorageManager as StorageTrait>::ValIterator,
+    /// Iterator over the container being read, None before the first one.
+    file_iter: Option<<StorageManager as StorageTrait>::ValIterator>,
     schema: TableSchema,
+    /// Scanned table, used to pad records written under older schema versions.
+    table: Table,
+    alias: String,
     open: bool,
-    storage_manager: &'static StorageManager,
+    storage_manager: Arc<StorageManager>,
     container_id: ContainerId,
+    /// Containers read in turn: the partitions of a partitioned table, or the table's
+    /// own container.
+    containers: Vec<ContainerId>,
+    /// Index in `containers` of the next container to read.
+    next_container: usize,
     transaction_id: TransactionId,
 }
 
@@ -22,11 +31,13 @@ impl SeqScan {
     ///
     /// # Arguments
     ///
+    /// * `storage_manager` - Storage manager holding the table.
     /// * `table` - Table to scan over.
     /// * `table_alias` - Table alias given by the user.
+    /// * `container_id` - Container of the table.
     /// * `tid` - Transaction used to read the table.
     pub fn new(
-        storage_manager: &'static StorageManager,
+        storage_manager: Arc<StorageManager>,
         table: Arc<RwLock<Table>>,
         table_alias: &str,
         container_id: &ContainerId,
@@ -34,23 +45,38 @@ impl SeqScan {
     ) -> Self {
         let table_ref = table.read().unwrap();
         let schema = table_ref.schema.clone();
-        let file_iter = storage_manager.get_iterator(*container_id, tid, Permissions::ReadOnly);
+        let containers = table_ref.layout.record_containers(*container_id);
         Self {
-            file_iter,
+            file_iter: None,
             schema: Self::schema(&schema, table_alias),
+            table: table_ref.clone(),
+            alias: table_alias.to_string(),
             open: false,
             storage_manager,
             container_id: *container_id,
+            containers,
+            next_container: 0,
             transaction_id: tid,
         }
     }
 
+    /// Reads only some partitions of a partitioned table, such as those left after pruning
+    /// with `Partitioning::prune`.
+    ///
+    /// # Arguments
+    ///
+    /// * `partitions` - Containers of the partitions to read, in partition order.
+    pub fn with_partitions(mut self, partitions: Vec<ContainerId>) -> Self {
+        self.containers = partitions;
+        self
+    }
+
     /// Returns the schema of the table with aliases.
     ///
     /// # Arguments
     /// * `src_schema` - Schema of the source.
     /// * `alias` - Alias of the table.
-    fn schema(src_schema: &TableSchema, alias: &str) -> TableSchema {
+    pub(crate) fn schema(src_schema: &TableSchema, alias: &str) -> TableSchema {
         let mut attrs = Vec::new();
         for a in src_schema.attributes() {
             let new_name = format!("{}.{}", alias, a.name());
@@ -66,6 +92,9 @@ impl SeqScan {
 
 impl OpIterator for SeqScan {
     fn open(&mut self) -> Result<(), CrustyError> {
+        // A scan opened again after being closed reads the table from the start.
+        self.file_iter = None;
+        self.next_container = 0;
         self.open = true;
         Ok(())
     }
@@ -74,22 +103,32 @@ impl OpIterator for SeqScan {
         if !self.open {
             panic!("Operator has not been opened")
         }
-        match self.file_iter.next() {
-            Some((bytes, value_id)) => {
+        loop {
+            if let Some((bytes, value_id)) = self.file_iter.as_mut().and_then(|it| it.next()) {
                 // Create the tuple
-                let mut tuple = Tuple::from_bytes(&bytes);
+                let mut tuple = self.table.read_tuple(&bytes);
                 // Record where it came from
                 tuple.value_id = Some(value_id);
-                Ok(Some(tuple))
+                return Ok(Some(tuple));
+            }
+            match self.containers.get(self.next_container) {
+                Some(container_id) => {
+                    self.file_iter = Some(self.storage_manager.get_iterator(
+                        *container_id,
+                        self.transaction_id,
+                        Permissions::ReadOnly,
+                    ));
+                    self.next_container += 1;
+                }
+                None => return Ok(None),
             }
-            None => Ok(None),
         }
     }
 
     fn close(&mut self) -> Result<(), CrustyError> {
         // close the iterator
         self.open = false;
-        
+
         Ok(())
     }
 
@@ -97,56 +136,40 @@ impl OpIterator for SeqScan {
         if !self.open {
             panic!("Operator has not been opened")
         }
-        self.file_iter = self.storage_manager.get_iterator(
-            self.container_id,
-            self.transaction_id,
-            Permissions::ReadOnly,
-        );
+        self.file_iter = None;
+        self.next_container = 0;
         Ok(())
     }
 
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let node = PlanNode::new("SeqScan")
+            .param("table", &self.alias)
+            .param("container", self.container_id);
+        if self.containers == [self.container_id] {
+            node
+        } else {
+            node.param("partitions", format!("{:?}", self.containers))
+        }
+    }
 }
 
 #[cfg(test)]
 #[allow(unused_must_use)]
 mod test {
     use super::*;
-    use crate::opiterator::testutil::sum_int_fields;
-    use common::ids::TransactionId;
+    use crate::opiterator::testutil::{sum_int_fields, test_scan};
     use common::testutil::get_int_table_schema;
 
-    use common::testutil::*;
-
     const CHECKSUM: i32 = 18;
     const WIDTH: usize = 3;
     const TABLE: &str = "SeqScan";
 
     fn get_scan() -> Result<SeqScan, CrustyError> {
-        // Create test table
-        let schema = get_int_table_schema(WIDTH);
-        let table = Arc::new(RwLock::new(Table::new(TABLE.to_string(), schema)));
-        // Create test SM with a container
-        let smb = Box::new(StorageManager::new_test_sm());
-        let sm: &'static StorageManager = Box::leak(smb);
-        let cid = 0;
-        sm.create_table(cid).unwrap();
-        // Create test data
-        let tuple = int_vec_to_tuple(vec![1, 2, 3]);
-        let tuple2 = int_vec_to_tuple(vec![1, 2, 3]);
-        let tuple3 = int_vec_to_tuple(vec![1, 2, 3]);
-        let tuple_bytes = serde_cbor::to_vec(&tuple).unwrap();
-        let tuple_bytes2 = serde_cbor::to_vec(&tuple2).unwrap();
-        let tuple_bytes3 = serde_cbor::to_vec(&tuple3).unwrap();
-
-        let tid = TransactionId::new();
-        let _rid = sm.insert_value(cid, tuple_bytes, tid);
-        let _rid2 = sm.insert_value(cid, tuple_bytes2, tid);
-        let _rid3 = sm.insert_value(cid, tuple_bytes3, tid);
-
-        Ok(SeqScan::new(sm, table, TABLE, &cid, tid))
+        Ok(test_scan(TABLE, vec![vec![1, 2, 3]; 3]))
     }
 
     #[test]
@@ -201,6 +224,17 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_reopen() -> Result<(), CrustyError> {
+        let mut scan = test_scan(TABLE, vec![vec![1, 2], vec![3, 4]]);
+        scan.open()?;
+        assert_eq!(sum_int_fields(&mut scan)?, 10);
+        scan.close()?;
+        scan.open()?;
+        assert_eq!(sum_int_fields(&mut scan)?, 10);
+        Ok(())
+    }
+
     #[test]
     fn test_get_schema() {
         let scan = get_scan().unwrap();
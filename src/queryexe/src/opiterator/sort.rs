@@ -0,0 +1,203 @@
+use super::{OpIterator, PlanNode};
+use crate::opiterator::explain::column_name;
+use common::{CrustyError, TableSchema, Tuple};
+
+/// Sort operator, which returns the tuples of its child ordered by some of their fields,
+/// such as the rows of `SELECT * FROM t ORDER BY a DESC, b`.
+///
+/// The child is read when the operator is opened, and its tuples are held in memory. Tuples
+/// equal on every key keep the order of the child. Nulls sort after every other value.
+pub struct Sort {
+    /// Fields to sort by, in order, each with true if it is sorted in ascending order.
+    keys: Vec<(usize, bool)>,
+    /// Sorted tuples of the child.
+    tuples: Vec<Tuple>,
+    /// Index of the next tuple to return.
+    next: usize,
+    /// Boolean determining if iterator is open.
+    open: bool,
+    /// Child operator passing data into the operator.
+    child: Box<dyn OpIterator>,
+}
+
+impl Sort {
+    /// Sort constructor.
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Fields to sort by, in order, each with true if it is sorted in ascending order.
+    /// * `child` - Child OpIterator passing data into the operator.
+    pub fn new(keys: Vec<(usize, bool)>, child: Box<dyn OpIterator>) -> Self {
+        Self {
+            keys,
+            tuples: Vec::new(),
+            next: 0,
+            open: false,
+            child,
+        }
+    }
+
+    /// Reads the tuples of the child and sorts them.
+    fn sort(&mut self) -> Result<(), CrustyError> {
+        self.tuples.clear();
+        while let Some(tuple) = self.child.next()? {
+            self.tuples.push(tuple);
+        }
+        let keys = &self.keys;
+        self.tuples.sort_by(|a, b| {
+            keys.iter()
+                .map(|(i, ascending)| {
+                    let order = a.get_field(*i).cmp(&b.get_field(*i));
+                    if *ascending {
+                        order
+                    } else {
+                        order.reverse()
+                    }
+                })
+                .find(|order| order.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        self.next = 0;
+        Ok(())
+    }
+}
+
+impl OpIterator for Sort {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.child.open()?;
+        self.sort()?;
+        self.open = true;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        let tuple = self.tuples.get(self.next).cloned();
+        if tuple.is_some() {
+            self.next += 1;
+        }
+        Ok(tuple)
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.close()?;
+        self.tuples.clear();
+        self.open = false;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        self.child.rewind()?;
+        self.sort()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.child.get_schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        let keys: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(i, ascending)| {
+                let name = column_name(self.child.get_schema(), *i);
+                if *ascending {
+                    name
+                } else {
+                    format!("{} DESC", name)
+                }
+            })
+            .collect();
+        PlanNode::new("Sort")
+            .param("keys", keys.join(", "))
+            .child(self.child.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::TupleIterator;
+    use super::*;
+    use common::testutil::*;
+    use common::Field;
+
+    fn sort(keys: Vec<(usize, bool)>) -> Sort {
+        let tuples = create_tuple_list(vec![
+            vec![1, 2],
+            vec![2, 1],
+            vec![1, 3],
+            vec![3, 1],
+            vec![2, 2],
+        ]);
+        let ti = TupleIterator::new(tuples, get_int_table_schema(2));
+        Sort::new(keys, Box::new(ti))
+    }
+
+    /// Returns the tuples of an operator as rows of ints.
+    fn rows(op: &mut dyn OpIterator) -> Result<Vec<Vec<i32>>, CrustyError> {
+        let mut rows = Vec::new();
+        while let Some(tuple) = op.next()? {
+            rows.push(tuple.field_vals().map(|f| f.unwrap_int_field()).collect());
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_sort() -> Result<(), CrustyError> {
+        let mut op = sort(vec![(0, true), (1, false)]);
+        op.open()?;
+        let expected = vec![vec![1, 3], vec![1, 2], vec![2, 2], vec![2, 1], vec![3, 1]];
+        assert_eq!(expected, rows(&mut op)?);
+        op.rewind()?;
+        assert_eq!(expected, rows(&mut op)?);
+        op.close()
+    }
+
+    #[test]
+    fn test_stable_and_nulls_last() -> Result<(), CrustyError> {
+        let tuples = vec![
+            Tuple::new(vec![Field::Null, Field::IntField(0)]),
+            Tuple::new(vec![Field::IntField(5), Field::IntField(1)]),
+            Tuple::new(vec![Field::IntField(5), Field::IntField(2)]),
+            Tuple::new(vec![Field::IntField(4), Field::IntField(3)]),
+        ];
+        let ti = TupleIterator::new(tuples, get_int_table_schema(2));
+        let mut op = Sort::new(vec![(0, true)], Box::new(ti));
+        op.open()?;
+        let order: Vec<Field> = (0..4)
+            .map(|_| op.next().unwrap().unwrap().get_field(1).unwrap().clone())
+            .collect();
+        assert_eq!(
+            vec![
+                Field::IntField(3),
+                Field::IntField(1),
+                Field::IntField(2),
+                Field::IntField(0)
+            ],
+            order
+        );
+        assert_eq!(None, op.next()?);
+        op.close()
+    }
+
+    #[test]
+    fn test_describe() {
+        let explain = sort(vec![(1, false), (0, true)]).describe().to_string();
+        assert!(explain.contains("Sort (keys: #1 DESC, #0)"), "{}", explain);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_next_not_open() {
+        let mut op = sort(vec![(0, true)]);
+        op.next().unwrap();
+    }
+}
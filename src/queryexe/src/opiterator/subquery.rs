@@ -0,0 +1,163 @@
+use super::{OpIterator, Parameters, PlanNode};
+use common::{CrustyError, Field, TableSchema, Tuple};
+
+/// Scalar subquery compared to the tuples of a filter, such as the subquery of
+/// `b = (SELECT MAX(u.v) FROM u WHERE u.k = t.a)`.
+///
+/// Fields of the filtered tuples the subquery refers to are parameters of its plan. For each
+/// tuple the parameters are bound to the tuple's fields and the plan is reopened, and its
+/// single value is the operand of the comparison. A subquery returning no rows is NULL, and
+/// one returning more than one row is an error. The value of the last binding is kept, so
+/// a subquery that does not refer to the filtered tuples runs once per filter run.
+pub struct Subquery {
+    /// Plan of the subquery, returning one column.
+    plan: Box<dyn OpIterator>,
+    /// Parameters of the plan.
+    parameters: Parameters,
+    /// Fields of the filtered tuples bound to the parameters, in order.
+    correlated: Vec<usize>,
+    /// Boolean determining if the plan is open.
+    open: bool,
+    /// Values bound to the parameters in the last run and the value of the subquery.
+    last: Option<(Vec<Field>, Field)>,
+}
+
+impl Subquery {
+    /// Subquery constructor.
+    ///
+    /// # Arguments
+    ///
+    /// * `plan` - Plan of the subquery, returning one column.
+    /// * `parameters` - Parameters of the plan.
+    /// * `correlated` - Fields of the filtered tuples bound to the parameters, in order.
+    pub fn new(
+        plan: Box<dyn OpIterator>,
+        parameters: Parameters,
+        correlated: Vec<usize>,
+    ) -> Result<Self, CrustyError> {
+        if plan.get_schema().size() != 1 {
+            return Err(CrustyError::ValidationError(format!(
+                "Subquery must return one column, got {}",
+                plan.get_schema().size()
+            )));
+        }
+        Ok(Self {
+            plan,
+            parameters,
+            correlated,
+            open: false,
+            last: None,
+        })
+    }
+
+    /// Returns the schema of the subquery's single column.
+    pub fn get_schema(&self) -> &TableSchema {
+        self.plan.get_schema()
+    }
+
+    /// Returns the value of the subquery for a filtered tuple.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuple` - Tuple whose fields are bound to the parameters of the plan.
+    pub fn value(&mut self, tuple: &Tuple) -> Result<Field, CrustyError> {
+        let bindings: Vec<Field> = self
+            .correlated
+            .iter()
+            .map(|i| tuple.get_field(*i).cloned().unwrap_or(Field::Null))
+            .collect();
+        if let Some((last, value)) = &self.last {
+            if *last == bindings {
+                return Ok(value.clone());
+            }
+        }
+        self.parameters.bind(bindings.clone())?;
+        if self.open {
+            self.plan.close()?;
+        }
+        self.plan.open()?;
+        self.open = true;
+        let value = match self.plan.next()? {
+            Some(row) => row.get_field(0).cloned().unwrap_or(Field::Null),
+            None => Field::Null,
+        };
+        if self.plan.next()?.is_some() {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Subquery used as a value returned more than one row",
+            )));
+        }
+        self.last = Some((bindings, value.clone()));
+        Ok(value)
+    }
+
+    /// Forgets the value of the last run, so the next value reads the tables again.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+
+    /// Closes the plan of the subquery if it is open.
+    pub fn close(&mut self) -> Result<(), CrustyError> {
+        if self.open {
+            self.plan.close()?;
+            self.open = false;
+        }
+        Ok(())
+    }
+
+    /// Describes the plan of the subquery.
+    pub fn describe(&self) -> PlanNode {
+        self.plan.describe()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{BooleanPredicate, Filter, FilterPredicate, ProjectIterator, TupleIterator};
+    use super::*;
+    use common::testutil::*;
+    use common::{DataType, SimplePredicateOp};
+
+    /// Returns the subquery `SELECT b FROM (k, b) WHERE k = $1` over the given rows.
+    fn lookup(rows: Vec<Vec<i32>>) -> Subquery {
+        let parameters = Parameters::new();
+        let ti = TupleIterator::new(create_tuple_list(rows), get_int_table_schema(2));
+        let filter = Filter::with_predicate(
+            BooleanPredicate::Compare(FilterPredicate::parameter(
+                SimplePredicateOp::Equals,
+                0,
+                0,
+                parameters.clone(),
+            )),
+            Box::new(ti),
+        );
+        parameters.declare(0, &DataType::Int).unwrap();
+        let plan = ProjectIterator::new(vec![1], Box::new(filter));
+        Subquery::new(Box::new(plan), parameters, vec![0]).unwrap()
+    }
+
+    #[test]
+    fn test_correlated_value() -> Result<(), CrustyError> {
+        let mut subquery = lookup(vec![vec![1, 10], vec![2, 20]]);
+        for (k, expected) in [
+            (2, Field::IntField(20)),
+            (1, Field::IntField(10)),
+            (3, Field::Null),
+        ] {
+            let tuple = int_vec_to_tuple(vec![k]);
+            assert_eq!(expected, subquery.value(&tuple)?);
+        }
+        subquery.close()
+    }
+
+    #[test]
+    fn test_more_than_one_row() {
+        let mut subquery = lookup(vec![vec![1, 10], vec![1, 11]]);
+        assert!(subquery.value(&int_vec_to_tuple(vec![1])).is_err());
+    }
+
+    #[test]
+    fn test_one_column() {
+        let ti = TupleIterator::new(create_tuple_list(vec![vec![1, 2]]), get_int_table_schema(2));
+        assert!(Subquery::new(Box::new(ti), Parameters::new(), Vec::new()).is_err());
+    }
+}
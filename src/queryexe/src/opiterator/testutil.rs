@@ -1,6 +1,14 @@
-use crate::opiterator::OpIterator;
-use common::CrustyError;
-use common::Field;
+use crate::opiterator::{OpIterator, SeqScan};
+use crate::{StorageManager, TransactionManager};
+use common::foreign_key::{ChildKey, ForeignKey, ForeignKeys, OnDelete, ParentKey};
+use common::ids::StateType;
+use common::index::{self, IndexInfo, IndexKind};
+use common::prelude::*;
+use common::storage_trait::StorageTrait;
+use common::table::Table;
+use common::testutil::{gen_random_test_sm_dir, get_int_table_schema, int_vec_to_tuple};
+use common::traits::transaction_manager_trait::TransactionManagerTrait;
+use std::sync::{Arc, RwLock};
 
 #[allow(dead_code)]
 /// Returns the count of the number of tuples in an OpIterator.
@@ -50,3 +58,217 @@ pub fn match_all_tuples(
     assert!(iter2.next()?.is_none());
     Ok(())
 }
+
+#[allow(dead_code)]
+/// Creates a test storage manager holding an int table with the given rows, and a
+/// transaction manager.
+///
+/// # Arguments
+///
+/// * `container_id` - Container of the table.
+/// * `rows` - Rows to insert.
+pub fn test_table(
+    container_id: ContainerId,
+    rows: Vec<Vec<i32>>,
+) -> (Arc<StorageManager>, &'static TransactionManager) {
+    let sm = Arc::new(StorageManager::new_test_sm());
+    let tm: &'static TransactionManager =
+        Box::leak(Box::new(TransactionManager::new(&gen_random_test_sm_dir())));
+    sm.create_table(container_id).unwrap();
+    let tid = TransactionId::new();
+    for row in rows {
+        sm.insert_value(container_id, int_vec_to_tuple(row).to_bytes(), tid)
+            .unwrap();
+    }
+    (sm, tm)
+}
+
+#[allow(dead_code)]
+/// Returns a sequential scan over an int table with the given rows, stored in a test
+/// storage manager.
+///
+/// # Arguments
+///
+/// * `alias` - Alias of the scanned table.
+/// * `rows` - Rows of the table, all of the same width.
+pub fn test_scan(alias: &str, rows: Vec<Vec<i32>>) -> SeqScan {
+    let width = rows.first().map_or(0, |row| row.len());
+    let container_id = 0;
+    let (sm, _) = test_table(container_id, rows);
+    let table = Table::new(alias.to_string(), get_int_table_schema(width));
+    SeqScan::new(
+        sm,
+        Arc::new(RwLock::new(table)),
+        alias,
+        &container_id,
+        TransactionId::new(),
+    )
+}
+
+#[allow(dead_code)]
+/// Returns the tuples stored in a container, with their value ids set.
+pub fn stored_tuples(sm: &StorageManager, container_id: ContainerId) -> Vec<Tuple> {
+    sm.get_iterator(container_id, TransactionId::new(), Permissions::ReadOnly)
+        .map(|(bytes, id)| {
+            let mut tuple = Tuple::from_bytes(&bytes);
+            tuple.value_id = Some(id);
+            tuple
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+/// Creates an index over int columns of a table and bulk loads it.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `table_id` - Container of the table.
+/// * `container_id` - Container to create for the index.
+/// * `columns` - Indexed columns.
+pub fn test_index(
+    sm: &StorageManager,
+    table_id: ContainerId,
+    container_id: ContainerId,
+    columns: Vec<usize>,
+) -> IndexInfo {
+    build_test_index(sm, table_id, container_id, columns, IndexKind::Heap)
+}
+
+#[allow(dead_code)]
+/// Creates a hash index over int columns of a table and bulk loads it.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `table_id` - Container of the table.
+/// * `container_id` - Container to create for the index.
+/// * `columns` - Indexed columns.
+pub fn test_hash_index(
+    sm: &StorageManager,
+    table_id: ContainerId,
+    container_id: ContainerId,
+    columns: Vec<usize>,
+) -> IndexInfo {
+    build_test_index(sm, table_id, container_id, columns, IndexKind::Hash)
+}
+
+#[allow(dead_code)]
+/// Creates a bitmap index over int columns of a table and bulk loads it.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the table.
+/// * `table_id` - Container of the table.
+/// * `container_id` - Container to create for the index.
+/// * `columns` - Indexed columns.
+pub fn test_bitmap_index(
+    sm: &StorageManager,
+    table_id: ContainerId,
+    container_id: ContainerId,
+    columns: Vec<usize>,
+) -> IndexInfo {
+    build_test_index(sm, table_id, container_id, columns, IndexKind::Bitmap)
+}
+
+fn build_test_index(
+    sm: &StorageManager,
+    table_id: ContainerId,
+    container_id: ContainerId,
+    columns: Vec<usize>,
+    kind: IndexKind,
+) -> IndexInfo {
+    let index = IndexInfo {
+        name: format!("index{}", container_id),
+        container_id,
+        table_id,
+        columns,
+        unique: false,
+        constraint: false,
+        kind,
+    };
+    sm.create_container(container_id, None, StateType::Index, None)
+        .unwrap();
+    // The test records are never padded, so the table needs no schema.
+    let table = Table::new(String::from("t"), TableSchema::new(Vec::new()));
+    index::build(sm, &table, &index, TransactionId::new()).unwrap();
+    index
+}
+
+#[allow(dead_code)]
+/// Creates an int table whose column `column` refers to the first column of a parent table,
+/// and returns the foreign keys a statement on the table and on the parent enforce.
+///
+/// # Arguments
+///
+/// * `sm` - Storage manager holding the parent table.
+/// * `parent_index` - Unique index over the first column of the parent table.
+/// * `container_id` - Container to create for the table.
+/// * `rows` - Rows of the table.
+/// * `column` - Referring column.
+/// * `on_delete` - Action on the rows when a referenced parent row is deleted.
+pub fn test_foreign_key(
+    sm: &StorageManager,
+    parent_index: &IndexInfo,
+    container_id: ContainerId,
+    rows: Vec<Vec<i32>>,
+    column: usize,
+    on_delete: OnDelete,
+) -> (ForeignKeys, ForeignKeys) {
+    let parent_id = parent_index.table_id;
+    sm.create_container(
+        container_id,
+        None,
+        StateType::BaseTable,
+        Some(vec![parent_id]),
+    )
+    .unwrap();
+    let tid = TransactionId::new();
+    for row in rows {
+        sm.insert_value(container_id, int_vec_to_tuple(row).to_bytes(), tid)
+            .unwrap();
+    }
+    let foreign_key = ForeignKey {
+        name: format!("fkey{}", container_id),
+        columns: vec![column],
+        parent_id,
+        parent_columns: vec![0],
+        on_delete,
+    };
+    let child = ForeignKeys {
+        table_id: container_id,
+        parents: vec![ParentKey {
+            foreign_key: foreign_key.clone(),
+            index: parent_index.clone(),
+        }],
+        children: Vec::new(),
+    };
+    let parent = ForeignKeys {
+        table_id: parent_id,
+        parents: Vec::new(),
+        children: vec![ChildKey {
+            foreign_key,
+            table_id: container_id,
+            // The test records are never padded, so the table needs no schema.
+            table: Table::new(String::from("c"), TableSchema::new(Vec::new())),
+            indexes: Vec::new(),
+        }],
+    };
+    (child, parent)
+}
+
+#[allow(dead_code)]
+/// Returns the tuples an index maps an int key to.
+pub fn index_lookup(sm: &StorageManager, index: &IndexInfo, key: Vec<i32>) -> Vec<Tuple> {
+    let key: Vec<Field> = key.into_iter().map(Field::IntField).collect();
+    index::lookup(sm, index, &key, TransactionId::new())
+        .unwrap()
+        .into_iter()
+        .map(|id| {
+            Tuple::from_bytes(
+                &sm.get_value(id, TransactionId::new(), Permissions::ReadOnly)
+                    .unwrap(),
+            )
+        })
+        .collect()
+}
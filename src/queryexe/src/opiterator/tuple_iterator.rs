@@ -1,4 +1,4 @@
-use super::OpIterator;
+use super::{OpIterator, PlanNode};
 use common::{CrustyError, TableSchema, Tuple};
 
 /// Iterator over a Vec of tuples, mainly used for testing.
@@ -72,6 +72,10 @@ impl OpIterator for TupleIterator {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("TupleIterator").param("rows", self.tuples.len())
+    }
 }
 
 #[cfg(test)]
@@ -1,65 +1,93 @@
-use super::OpIterator;
+use super::explain::column_name;
+use super::{OpIterator, PlanNode};
 use crate::{StorageManager, TransactionManager};
+use common::foreign_key::{self, ForeignKeys};
 use common::ids::TupleAssignments;
+use common::index::{self, IndexInfo};
 use common::prelude::*;
 use common::storage_trait::StorageTrait;
 use common::traits::transaction_manager_trait::TransactionManagerTrait;
+use std::collections::HashSet;
+use std::sync::Arc;
 
-/// Sequential scan operator
+/// Update operator. Applies assignments to the tuples of its child and returns the number
+/// of updated tuples.
 pub struct Update {
+    /// Schema of the result, a single count column.
     schema: TableSchema,
     open: bool,
-    storage_manager: &'static StorageManager,
+    storage_manager: Arc<StorageManager>,
     transaction_manager: &'static TransactionManager,
-    _container_id: ContainerId,
+    container_id: ContainerId,
+    /// Indexes of the table, whose entries follow the updated tuples.
+    indexes: Vec<IndexInfo>,
+    /// Foreign keys of the table and referring to it, checked when their keys are assigned.
+    foreign_keys: ForeignKeys,
     tid: TransactionId,
     assignments: TupleAssignments,
     child: Box<dyn OpIterator>,
-    count: usize,
+    /// Number of updated tuples, once they are updated.
+    count: Option<usize>,
+    /// Whether the count was returned since the operator was opened.
+    done: bool,
 }
 
 impl Update {
-    /// Constructor for the sequential scan operator.
+    /// Constructor for the update operator.
     ///
     /// # Arguments
     ///
-    /// * `table` - Table to scan over.
-    /// * `table_alias` - Table alias given by the user.
-    /// * `tid` - Transaction used to read the table.
+    /// * `storage_manager` - Storage manager holding the table.
+    /// * `transaction_manager` - Transaction manager notified of the updates.
+    /// * `container_id` - Container of the table to update.
+    /// * `indexes` - Indexes of the table to maintain.
+    /// * `foreign_keys` - Foreign keys of the table to enforce.
+    /// * `tid` - Transaction used to update the tuples.
+    /// * `assignments` - Indices of the fields to set and their new values.
+    /// * `child` - Child operator producing the tuples to update. The tuples must have their
+    ///   value ids set, as the tuples of a scan do.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        storage_manager: &'static StorageManager,
+        storage_manager: Arc<StorageManager>,
         transaction_manager: &'static TransactionManager,
         container_id: &ContainerId,
+        indexes: Vec<IndexInfo>,
+        foreign_keys: ForeignKeys,
         tid: TransactionId,
         assignments: TupleAssignments,
         child: Box<dyn OpIterator>,
     ) -> Self {
         Self {
-            schema: child.get_schema().clone(),
+            schema: TableSchema::from_vecs(vec!["count"], vec![DataType::Int]),
             open: false,
             storage_manager,
             transaction_manager,
-            _container_id: *container_id,
+            container_id: *container_id,
+            indexes,
+            foreign_keys,
             tid,
             assignments,
             child,
-            count: 0,
+            count: None,
+            done: false,
         }
     }
-}
-
-impl OpIterator for Update {
-    fn open(&mut self) -> Result<(), CrustyError> {
-        self.open = true;
-        self.child.open()
-    }
 
-    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
-        if !self.open {
-            panic!("Operator has not been opened")
+    /// Updates all the tuples of the child and returns their count.
+    ///
+    /// The child is read to the end before anything is updated, so a child scanning the
+    /// table does not see tuples that moved when they were updated.
+    fn update(&mut self) -> Result<usize, CrustyError> {
+        let mut tuples = Vec::new();
+        while let Some(tuple) = self.child.next()? {
+            tuples.push(tuple);
         }
-        let next = self.child.next()?;
-        if let Some(mut tuple) = next {
+        let count = tuples.len();
+        self.check_unique(&tuples)?;
+        self.check_foreign_keys(&tuples)?;
+        // Old value id, new value id, and new tuple of every updated record.
+        let mut updated = Vec::with_capacity(count);
+        for mut tuple in tuples {
             let id = match tuple.value_id {
                 Some(id) => id,
                 None => {
@@ -69,7 +97,7 @@ impl OpIterator for Update {
                 }
             };
 
-            //TODO determine should check for constaints and maintain indexes
+            //TODO determine should check for constaints
 
             // Update values
             self.transaction_manager.pre_update_record(
@@ -82,34 +110,172 @@ impl OpIterator for Update {
                 tuple.set_field(*field_idx, new_value.clone());
             }
             // Persist change
-            let res = self
+            let new_value_id = self
                 .storage_manager
-                .update_value(tuple.to_bytes(), id, self.tid);
-            //Check result
-            match res {
-                Ok(new_value_id) => {
-                    // notify txn manager
-                    self.transaction_manager.post_update_record(
-                        &mut tuple,
-                        &new_value_id,
-                        &id,
-                        &self.tid,
-                        &self.assignments,
-                    )?;
-                    if new_value_id != id {
-                        // The record moved. Update index if not using PK
-                        debug!("record moved on update");
-                    }
-                    // update indexes for values that changed
-                    self.count += 1;
-                }
-                Err(e) => {
-                    return Err(e);
-                }
+                .update_value(tuple.to_bytes(), id, self.tid)?;
+            // notify txn manager
+            self.transaction_manager.post_update_record(
+                &mut tuple,
+                &new_value_id,
+                &id,
+                &self.tid,
+                &self.assignments,
+            )?;
+            if new_value_id != id {
+                debug!("record moved on update");
+            }
+            updated.push((id, new_value_id, tuple));
+        }
+        for index in &self.indexes {
+            self.update_index(index, &updated)?;
+        }
+        Ok(count)
+    }
+
+    /// Checks that the updated records keep the keys of the unique indexes of the table
+    /// unique. Only indexes over assigned columns are probed.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuples` - Records to update, before the assignments.
+    fn check_unique(&self, tuples: &[Tuple]) -> Result<(), CrustyError> {
+        let mut new_tuples = None;
+        for index in &self.indexes {
+            if !index.unique
+                || !self
+                    .assignments
+                    .iter()
+                    .any(|(field_idx, _)| index.columns.contains(field_idx))
+            {
+                continue;
             }
-            return Ok(Some(tuple));
+            let (new_tuples, old_ids) = new_tuples.get_or_insert_with(|| {
+                let old_ids: HashSet<ValueId> = tuples.iter().filter_map(|t| t.value_id).collect();
+                (self.assign(tuples), old_ids)
+            });
+            index::check_unique(
+                self.storage_manager.as_ref(),
+                index,
+                new_tuples,
+                old_ids,
+                self.tid,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the updated records still refer to existing parent records, and that no
+    /// child records refer to parent keys the update changes. Only foreign keys over
+    /// assigned columns are checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `tuples` - Records to update, before the assignments.
+    fn check_foreign_keys(&self, tuples: &[Tuple]) -> Result<(), CrustyError> {
+        let assigned = |columns: &[usize]| {
+            self.assignments
+                .iter()
+                .any(|(field_idx, _)| columns.contains(field_idx))
+        };
+        let parents_changed = self
+            .foreign_keys
+            .parents
+            .iter()
+            .any(|parent| assigned(&parent.foreign_key.columns));
+        let keys_changed = self.foreign_keys.children.iter().any(|child| {
+            child.foreign_key.parent_id == self.container_id
+                && assigned(&child.foreign_key.parent_columns)
+        });
+        if !parents_changed && !keys_changed {
+            return Ok(());
+        }
+        let new_tuples = self.assign(tuples);
+        if parents_changed {
+            foreign_key::check_parents(
+                self.storage_manager.as_ref(),
+                &self.foreign_keys,
+                &new_tuples,
+                self.tid,
+            )?;
+        }
+        if keys_changed {
+            foreign_key::check_unreferenced(
+                self.storage_manager.as_ref(),
+                &self.foreign_keys,
+                tuples,
+                &new_tuples,
+                self.tid,
+            )?;
         }
-        Ok(next)
+        Ok(())
+    }
+
+    /// Returns copies of records with the assignments applied.
+    fn assign(&self, tuples: &[Tuple]) -> Vec<Tuple> {
+        tuples
+            .iter()
+            .map(|tuple| {
+                let mut tuple = tuple.clone();
+                for (field_idx, new_value) in &self.assignments {
+                    tuple.set_field(*field_idx, new_value.clone());
+                }
+                tuple
+            })
+            .collect()
+    }
+
+    /// Replaces the index entries of updated records whose key changed or that moved.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index to maintain.
+    /// * `updated` - Old value id, new value id, and new tuple of every updated record.
+    fn update_index(
+        &self,
+        index: &IndexInfo,
+        updated: &[(ValueId, ValueId, Tuple)],
+    ) -> Result<(), CrustyError> {
+        let key_changed = self
+            .assignments
+            .iter()
+            .any(|(field_idx, _)| index.columns.contains(field_idx));
+        let stale: Vec<&(ValueId, ValueId, Tuple)> = updated
+            .iter()
+            .filter(|(old_id, new_id, _)| key_changed || old_id != new_id)
+            .collect();
+        let old_ids: HashSet<ValueId> = stale.iter().map(|(old_id, _, _)| *old_id).collect();
+        index::remove_entries(self.storage_manager.as_ref(), index, &old_ids, self.tid)?;
+        let records: Vec<(Tuple, ValueId)> = stale
+            .into_iter()
+            .map(|(_, new_id, tuple)| (tuple.clone(), *new_id))
+            .collect();
+        index::insert_entries(self.storage_manager.as_ref(), index, &records, self.tid)?;
+        Ok(())
+    }
+}
+
+impl OpIterator for Update {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.open = true;
+        self.done = false;
+        self.child.open()
+    }
+
+    /// Returns the number of updated tuples, updating them on the first call.
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        if !self.open {
+            panic!("Operator has not been opened")
+        }
+        if self.done {
+            return Ok(None);
+        }
+        let count = match self.count {
+            Some(count) => count,
+            None => self.update()?,
+        };
+        self.count = Some(count);
+        self.done = true;
+        Ok(Some(Tuple::new(vec![Field::IntField(count as i32)])))
     }
 
     fn close(&mut self) -> Result<(), CrustyError> {
@@ -130,11 +296,196 @@ impl OpIterator for Update {
     fn get_schema(&self) -> &TableSchema {
         &self.schema
     }
+
+    fn describe(&self) -> PlanNode {
+        let assignments: Vec<String> = self
+            .assignments
+            .iter()
+            .map(|(i, f)| format!("{} = {}", column_name(self.child.get_schema(), *i), f))
+            .collect();
+        PlanNode::new("Update")
+            .param("container", self.container_id)
+            .param("set", assignments.join(", "))
+            .child(self.child.as_ref())
+    }
 }
 
 #[cfg(test)]
-#[allow(unused_must_use)]
 mod test {
-    //use super::*;
-    //use common::ids::TransactionId;
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use crate::opiterator::{Filter, TupleIterator};
+    use common::foreign_key::OnDelete;
+    use common::testutil::*;
+    use common::SimplePredicateOp;
+
+    #[test]
+    fn test_update() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2], vec![3, 4], vec![5, 6]]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let scan = TupleIterator::new(stored_tuples(&sm, 1), schema);
+        let filter = Filter::new(
+            SimplePredicateOp::GreaterThan,
+            0,
+            Field::IntField(1),
+            Box::new(scan),
+        );
+        let assignments = vec![(1, Field::IntField(0))];
+        let mut update = Update::new(
+            sm.clone(),
+            tm,
+            &1,
+            Vec::new(),
+            ForeignKeys::default(),
+            TransactionId::new(),
+            assignments,
+            Box::new(filter),
+        );
+        update.open()?;
+        assert_eq!(Some(int_vec_to_tuple(vec![2])), update.next()?);
+        assert_eq!(None, update.next()?);
+        update.close()?;
+
+        let mut stored: Vec<Vec<Field>> = stored_tuples(&sm, 1)
+            .into_iter()
+            .map(|t| t.field_vals)
+            .collect();
+        stored.sort();
+        let expected: Vec<Vec<Field>> = create_tuple_list(vec![vec![1, 2], vec![3, 0], vec![5, 0]])
+            .into_iter()
+            .map(|t| t.field_vals)
+            .collect();
+        assert_eq!(expected, stored);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_duplicate_key() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2], vec![3, 4]]);
+        let mut index = test_index(&sm, 1, 2, vec![0]);
+        index.unique = true;
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let update_where_a = |a: i32, assignments| {
+            let scan = TupleIterator::new(stored_tuples(&sm, 1), schema.clone());
+            let filter = Filter::new(
+                SimplePredicateOp::Equals,
+                0,
+                Field::IntField(a),
+                Box::new(scan),
+            );
+            let mut update = Update::new(
+                sm.clone(),
+                tm,
+                &1,
+                vec![index.clone()],
+                ForeignKeys::default(),
+                TransactionId::new(),
+                assignments,
+                Box::new(filter),
+            );
+            update.open()?;
+            update.next()
+        };
+        assert!(matches!(
+            update_where_a(1, vec![(0, Field::IntField(3))]),
+            Err(CrustyError::DuplicateKey(_))
+        ));
+        // Setting a record's key to its own value does not conflict with its old entry.
+        update_where_a(3, vec![(0, Field::IntField(3)), (1, Field::IntField(0))])?;
+        assert_eq!(
+            vec![int_vec_to_tuple(vec![3, 0])],
+            index_lookup(&sm, &index, vec![3])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_foreign_key() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 10], vec![2, 20]]);
+        let mut parent_index = test_index(&sm, 1, 2, vec![0]);
+        parent_index.unique = true;
+        let (child_keys, parent_keys) = test_foreign_key(
+            &sm,
+            &parent_index,
+            3,
+            vec![vec![7, 1]],
+            1,
+            OnDelete::Restrict,
+        );
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let update_where_a = |table, foreign_keys, a: i32, assignments| {
+            let scan = TupleIterator::new(stored_tuples(&sm, table), schema.clone());
+            let filter = Filter::new(
+                SimplePredicateOp::Equals,
+                0,
+                Field::IntField(a),
+                Box::new(scan),
+            );
+            let mut update = Update::new(
+                sm.clone(),
+                tm,
+                &table,
+                Vec::new(),
+                foreign_keys,
+                TransactionId::new(),
+                assignments,
+                Box::new(filter),
+            );
+            update.open()?;
+            update.next()
+        };
+        // A child row must keep referring to a parent row.
+        assert!(matches!(
+            update_where_a(3, child_keys.clone(), 7, vec![(1, Field::IntField(3))]),
+            Err(CrustyError::ConstraintViolation(_))
+        ));
+        update_where_a(3, child_keys, 7, vec![(1, Field::IntField(2))])?;
+        // A referenced parent key cannot change, an unreferenced one can.
+        assert!(matches!(
+            update_where_a(1, parent_keys.clone(), 2, vec![(0, Field::IntField(5))]),
+            Err(CrustyError::ConstraintViolation(_))
+        ));
+        update_where_a(1, parent_keys, 1, vec![(0, Field::IntField(5))])?;
+        assert_eq!(Field::IntField(2), stored_tuples(&sm, 3)[0].field_vals[1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_maintains_index() -> Result<(), CrustyError> {
+        let (sm, tm) = test_table(1, vec![vec![1, 2], vec![3, 4]]);
+        let by_b = test_index(&sm, 1, 2, vec![1]);
+        let by_a = test_index(&sm, 1, 3, vec![0]);
+        let schema = TableSchema::from_vecs(vec!["t.a", "t.b"], vec![DataType::Int; 2]);
+        let scan = TupleIterator::new(stored_tuples(&sm, 1), schema);
+        let filter = Filter::new(
+            SimplePredicateOp::Equals,
+            0,
+            Field::IntField(3),
+            Box::new(scan),
+        );
+        let mut update = Update::new(
+            sm.clone(),
+            tm,
+            &1,
+            vec![by_b.clone(), by_a.clone()],
+            ForeignKeys::default(),
+            TransactionId::new(),
+            vec![(1, Field::IntField(9))],
+            Box::new(filter),
+        );
+        update.open()?;
+        update.next()?;
+        update.close()?;
+
+        let updated = int_vec_to_tuple(vec![3, 9]).field_vals;
+        assert!(index_lookup(&sm, &by_b, vec![4]).is_empty());
+        let found = index_lookup(&sm, &by_b, vec![9]);
+        assert_eq!(1, found.len());
+        assert_eq!(updated, found[0].field_vals);
+        // The key of by_a did not change, but its entry must follow the record if it moved.
+        let found = index_lookup(&sm, &by_a, vec![3]);
+        assert_eq!(1, found.len());
+        assert_eq!(updated, found[0].field_vals);
+        Ok(())
+    }
 }
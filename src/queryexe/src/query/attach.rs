@@ -0,0 +1,386 @@
+use crate::StorageManager;
+use common::catalog::Catalog;
+use common::database::Database;
+use common::ids::{StateType, CONTAINER_COUNTER};
+use common::index::IndexInfo;
+use common::prelude::*;
+use common::statistics::TableStats;
+use common::storage_trait::StorageTrait;
+use common::table::Table;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, RwLock};
+
+/// Directory under a storage path that holds the serialized database catalogs.
+const DB_DIR: &str = "dbs";
+
+/// A table from an attached storage directory.
+struct AttachedTable {
+    /// Alias the storage directory was attached under.
+    alias: String,
+    /// Table metadata, named `alias.table`.
+    table: Arc<RwLock<Table>>,
+    /// Read-only storage manager holding the table, shared by the tables of the same
+    /// database and dropped with the last of them when the directory is detached.
+    storage_manager: Arc<StorageManager>,
+    /// Container id of the table inside the attached storage manager.
+    container_id: ContainerId,
+    /// Statistics stored in the attached catalog, if the table was analyzed.
+    stats: Option<TableStats>,
+}
+
+/// Storage directories attached to a session with `ATTACH 'path' AS alias`.
+///
+/// Every attached table is given a fresh container id so it cannot collide with the ids
+/// of the session's own database. The binder sees these tables through a
+/// `FederatedCatalog` as `alias.table`, and the executor uses `route` to map the fresh
+/// id back to the storage manager and container that hold the data.
+#[derive(Default)]
+pub struct Attachments {
+    /// Path of each attached storage directory, by alias.
+    paths: RwLock<HashMap<String, PathBuf>>,
+    /// Attached tables by their session container id.
+    tables: RwLock<HashMap<ContainerId, AttachedTable>>,
+}
+
+impl Attachments {
+    /// Creates an empty set of attachments.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches the storage directory at `path` under `alias`.
+    ///
+    /// Opens a read-only storage manager on the directory and registers the base tables of
+    /// every database catalog stored in it. Returns the qualified names of the attached
+    /// tables. A directory can only be attached once per session.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Storage directory of another crusty instance.
+    /// * `alias` - Name used to qualify the attached tables.
+    pub fn attach(&self, path: &str, alias: &str) -> Result<Vec<String>, CrustyError> {
+        if alias.is_empty() || alias.contains('.') {
+            return Err(CrustyError::ValidationError(format!(
+                "Invalid alias for attached database: \"{}\"",
+                alias
+            )));
+        }
+        let mut paths = self.paths.write().unwrap();
+        if paths.contains_key(alias) {
+            return Err(CrustyError::CrustyError(format!(
+                "A database is already attached as {}",
+                alias
+            )));
+        }
+        let storage_path = fs::canonicalize(path)
+            .map_err(|_| CrustyError::IOError(format!("No crusty databases found in {}", path)))?;
+        if let Some((attached, _)) = paths.iter().find(|(_, p)| **p == storage_path) {
+            return Err(CrustyError::CrustyError(format!(
+                "{} is already attached as {}",
+                path, attached
+            )));
+        }
+        let mut db_dir = storage_path.clone();
+        db_dir.push(DB_DIR);
+        if !db_dir.is_dir() {
+            return Err(CrustyError::IOError(format!(
+                "No crusty databases found in {}",
+                path
+            )));
+        }
+
+        // Read every catalog before creating anything so a bad file attaches nothing.
+        let mut databases = Vec::new();
+        for entry in fs::read_dir(&db_dir)? {
+            let db_path = entry?.path();
+            debug!("Loading attached catalog from {:?}", db_path);
+            let file = fs::File::open(&db_path)?;
+            let database: Database = serde_json::from_reader(file).map_err(|e| {
+                CrustyError::IOError(format!("Bad catalog {}: {}", db_path.display(), e))
+            })?;
+            databases.push(database);
+        }
+
+        let mut new_tables = HashMap::new();
+        for database in databases {
+            let named = database.named_containers.read().unwrap();
+            let stats = database.table_stats.read().unwrap();
+            for (container_id, table) in database.tables.read().unwrap().iter() {
+                if !matches!(named.get(container_id), Some((_, StateType::BaseTable))) {
+                    continue;
+                }
+                let table = table.read().unwrap();
+                let name = format!("{}.{}", alias, table.name);
+                if new_tables.contains_key(&name) {
+                    return Err(CrustyError::CrustyError(format!(
+                        "Table {} is defined by more than one database in {}",
+                        name, path
+                    )));
+                }
+                new_tables.insert(
+                    name,
+                    (
+                        database.name.clone(),
+                        *container_id,
+                        table.schema.clone(),
+                        table.layout.clone(),
+                        stats.get(container_id).cloned(),
+                    ),
+                );
+            }
+        }
+
+        let root = Arc::new(StorageManager::open_read_only(storage_path.clone())?);
+        // the containers of a database are in its own storage manager, unless it was
+        // persisted before databases had one
+        let mut storage_managers: HashMap<String, Arc<StorageManager>> = HashMap::new();
+        let mut tables = self.tables.write().unwrap();
+        let mut names: Vec<String> = new_tables.keys().cloned().collect();
+        names.sort();
+        for (name, (db_name, container_id, schema, layout, stats)) in new_tables {
+            let storage_manager = storage_managers
+                .entry(db_name)
+                .or_insert_with_key(|db_name| {
+                    root.use_database(db_name).unwrap_or_else(|_| root.clone())
+                })
+                .clone();
+            let session_id = CONTAINER_COUNTER.fetch_add(1, Ordering::SeqCst);
+            // Column containers of a columnar table and partitions of a partitioned table keep
+            // their ids in the attached directory.
+            let mut table = Table::new(name, schema);
+            table.layout = layout;
+            tables.insert(
+                session_id,
+                AttachedTable {
+                    alias: alias.to_string(),
+                    table: Arc::new(RwLock::new(table)),
+                    storage_manager,
+                    container_id,
+                    stats,
+                },
+            );
+        }
+        paths.insert(alias.to_string(), storage_path);
+        info!("Attached {} as {} with tables {:?}", path, alias, names);
+        Ok(names)
+    }
+
+    /// Detaches the storage directory attached under `alias`.
+    ///
+    /// # Arguments
+    ///
+    /// * `alias` - Alias given when the directory was attached.
+    pub fn detach(&self, alias: &str) -> Result<(), CrustyError> {
+        let mut paths = self.paths.write().unwrap();
+        if paths.remove(alias).is_none() {
+            return Err(CrustyError::CrustyError(format!(
+                "No database attached as {}",
+                alias
+            )));
+        }
+        self.tables
+            .write()
+            .unwrap()
+            .retain(|_, attached| attached.alias != alias);
+        Ok(())
+    }
+
+    /// Lists the attached aliases with their storage paths, ordered by alias.
+    pub fn list(&self) -> Vec<(String, PathBuf)> {
+        let paths = self.paths.read().unwrap();
+        let mut res: Vec<(String, PathBuf)> = paths
+            .iter()
+            .map(|(alias, path)| (alias.clone(), path.clone()))
+            .collect();
+        res.sort();
+        res
+    }
+
+    /// Returns the storage manager and container holding an attached table, or None if
+    /// the container id does not belong to an attached table.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Session container id of the table.
+    pub fn route(&self, container_id: ContainerId) -> Option<(Arc<StorageManager>, ContainerId)> {
+        self.tables
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .map(|attached| (attached.storage_manager.clone(), attached.container_id))
+    }
+
+    /// Returns a catalog that resolves attached tables in addition to those of `local`.
+    ///
+    /// # Arguments
+    ///
+    /// * `local` - Catalog of the session's own database.
+    pub fn catalog<'a, C: Catalog>(&'a self, local: &'a C) -> FederatedCatalog<'a, C> {
+        FederatedCatalog {
+            local,
+            attachments: self,
+        }
+    }
+}
+
+/// Catalog over a session's database and the directories attached to the session.
+///
+/// Attached tables are only visible by their qualified `alias.table` name. New containers
+/// are always allocated in the local catalog.
+pub struct FederatedCatalog<'a, C: Catalog> {
+    local: &'a C,
+    attachments: &'a Attachments,
+}
+
+impl<'a, C: Catalog> Catalog for FederatedCatalog<'a, C> {
+    fn get_tables(&self) -> Arc<RwLock<HashMap<ContainerId, Arc<RwLock<Table>>>>> {
+        let attached = self.attachments.tables.read().unwrap();
+        if attached.is_empty() {
+            return self.local.get_tables();
+        }
+        let mut tables = self.local.get_tables().read().unwrap().clone();
+        for (id, table) in attached.iter() {
+            tables.insert(*id, table.table.clone());
+        }
+        Arc::new(RwLock::new(tables))
+    }
+
+    fn get_table_id(&self, name: &str) -> Option<ContainerId> {
+        if name.contains('.') {
+            let attached = self.attachments.tables.read().unwrap();
+            for (id, table) in attached.iter() {
+                if table.table.read().unwrap().name == name {
+                    return Some(*id);
+                }
+            }
+        }
+        self.local.get_table_id(name)
+    }
+
+    fn get_new_container_id(
+        &self,
+        state_type: StateType,
+        name: Option<String>,
+    ) -> Result<ContainerId, CrustyError> {
+        self.local.get_new_container_id(state_type, name)
+    }
+
+    fn get_table_stats(&self, table_id: ContainerId) -> Option<TableStats> {
+        match self.attachments.tables.read().unwrap().get(&table_id) {
+            Some(attached) => attached.stats.clone(),
+            None => self.local.get_table_stats(table_id),
+        }
+    }
+
+    fn get_indexes(&self, table_id: ContainerId) -> Vec<IndexInfo> {
+        if self
+            .attachments
+            .tables
+            .read()
+            .unwrap()
+            .contains_key(&table_id)
+        {
+            return Vec::new();
+        }
+        self.local.get_indexes(table_id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::statistics::analyze;
+    use common::testutil::*;
+    use common::{Attribute, DataType, TableSchema};
+
+    /// Writes a storage directory holding one database with a single int table.
+    fn write_storage_dir(db_name: &str, table_name: &str, rows: Vec<Vec<i32>>) -> PathBuf {
+        let path = gen_random_test_sm_dir();
+        let sm = StorageManager::new(path.clone());
+        let db = Database::new(db_name.to_string());
+        let container_id = db
+            .get_new_container_id(StateType::BaseTable, Some(table_name.to_string()))
+            .unwrap();
+        let schema = TableSchema::new(vec![
+            Attribute::new("a".to_string(), DataType::Int),
+            Attribute::new("b".to_string(), DataType::Int),
+        ]);
+        let table = Table::new(table_name.to_string(), schema);
+        db.tables
+            .write()
+            .unwrap()
+            .insert(container_id, Arc::new(RwLock::new(table.clone())));
+        sm.create_table(container_id).unwrap();
+        let tid = TransactionId::new();
+        for row in rows {
+            let bytes = int_vec_to_tuple(row).to_bytes();
+            sm.insert_value(container_id, bytes, tid).unwrap();
+        }
+        db.set_table_stats(
+            container_id,
+            analyze(&sm, container_id, &table, 1.0, tid).unwrap(),
+        );
+        sm.shutdown();
+        let mut db_dir = path.clone();
+        db_dir.push(DB_DIR);
+        fs::create_dir_all(&db_dir).unwrap();
+        db_dir.push(db_name);
+        serde_json::to_writer(fs::File::create(db_dir).unwrap(), &db).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_attach_resolves_qualified_names() {
+        let path = write_storage_dir("remote", "orders", vec![vec![1, 2], vec![3, 4]]);
+        let attachments = Attachments::new();
+        let names = attachments.attach(path.to_str().unwrap(), "ext").unwrap();
+        assert_eq!(vec!["ext.orders".to_string()], names);
+
+        let local = Database::new("local".to_string());
+        let catalog = attachments.catalog(&local);
+        assert_eq!(None, catalog.get_table_id("orders"));
+        let id = catalog.get_table_id("ext.orders").unwrap();
+        assert!(catalog.is_valid_table(id));
+        assert!(catalog.is_valid_column(id, "a"));
+        assert_eq!("ext.orders", catalog.get_table_name(id).unwrap());
+        assert_eq!(2, catalog.get_table_stats(id).unwrap().row_count);
+
+        let (sm, container_id) = attachments.route(id).unwrap();
+        let tid = TransactionId::new();
+        assert_eq!(
+            2,
+            sm.get_iterator(container_id, tid, Permissions::ReadOnly)
+                .count()
+        );
+        let bytes = int_vec_to_tuple(vec![5, 6]).to_bytes();
+        assert!(sm.insert_value(container_id, bytes, tid).is_err());
+
+        attachments.detach("ext").unwrap();
+        assert_eq!(None, catalog.get_table_id("ext.orders"));
+        assert!(attachments.route(id).is_none());
+        // The storage manager is no longer held by the attachments.
+        assert_eq!(1, Arc::strong_count(&sm));
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn test_attach_errors() {
+        let path = write_storage_dir("remote", "t", vec![vec![1, 1]]);
+        let attachments = Attachments::new();
+        let path_str = path.to_str().unwrap();
+        assert!(attachments.attach(path_str, "bad.alias").is_err());
+        assert!(attachments.attach("/no/such/crusty/dir", "x").is_err());
+        attachments.attach(path_str, "x").unwrap();
+        assert!(attachments.attach(path_str, "x").is_err());
+        assert!(attachments.attach(path_str, "y").is_err());
+        assert!(attachments.detach("y").is_err());
+        assert_eq!(
+            vec![("x".to_string(), fs::canonicalize(&path).unwrap())],
+            attachments.list()
+        );
+        fs::remove_dir_all(path).unwrap();
+    }
+}
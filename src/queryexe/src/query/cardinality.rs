@@ -0,0 +1,405 @@
+//! Cardinality estimation.
+//!
+//! Estimates the fraction of rows predicates keep and the rows joins and groupings return,
+//! from the statistics `ANALYZE` collects (see `common::statistics`). A comparison of a
+//! column to a literal is estimated from the column's histogram, bounds and null count;
+//! equality from its number of distinct values. The simple predicates of a filter and the
+//! sides of a join are assumed independent. Anything the statistics cannot estimate falls
+//! back to `DEFAULT_SELECTIVITY`.
+//!
+//! The join planner orders joins by these estimates, and EXPLAIN prints them next to the
+//! rows the operators actually return.
+
+use common::logical_plan::{
+    CompoundPredicate, CompoundPredicateOp, FieldIdentifier, NestedPredicate, PredExpr, Predicate,
+    SimplePredicate,
+};
+use common::statistics::{ColumnStats, Histogram};
+use common::{Field, SimplePredicateOp};
+use std::cmp::Ordering;
+use std::mem::discriminant;
+
+/// Selectivity assumed for predicates the statistics cannot estimate.
+pub const DEFAULT_SELECTIVITY: f64 = 1.0 / 3.0;
+
+/// Estimates the fraction of a table's rows that satisfy `column op constant`, for a
+/// constant that is not known when planning.
+///
+/// # Arguments
+///
+/// * `stats` - Statistics of the filtered column, if the table was analyzed.
+/// * `op` - Comparison of the filter.
+pub fn filter_selectivity(stats: Option<&ColumnStats>, op: SimplePredicateOp) -> f64 {
+    comparison_selectivity(stats, op, None)
+}
+
+/// Estimates the fraction of a table's rows that satisfy `column op value`. Nulls never
+/// satisfy a comparison. Range comparisons to a known value are estimated from the
+/// histogram, interpolating within the bucket holding the value; equality assumes the
+/// distinct values are equally frequent, and keeps nothing outside the column's bounds.
+/// Pattern matches keep a fixed fraction of the non-null rows.
+///
+/// # Arguments
+///
+/// * `stats` - Statistics of the filtered column, if the table was analyzed.
+/// * `op` - Comparison of the filter.
+/// * `value` - Value the column is compared to, if known.
+pub fn comparison_selectivity(
+    stats: Option<&ColumnStats>,
+    op: SimplePredicateOp,
+    value: Option<&Field>,
+) -> f64 {
+    let stats = match (op, stats) {
+        (SimplePredicateOp::All, _) => return 1.0,
+        (_, Some(stats)) => stats,
+        (_, None) => return DEFAULT_SELECTIVITY,
+    };
+    if stats.distinct_count == 0 {
+        return 0.0;
+    }
+    let value = value.filter(|v| **v != Field::Null);
+    let equal = match value {
+        Some(v) if outside(stats, v) => 0.0,
+        _ => 1.0 / stats.distinct_count as f64,
+    };
+    let selectivity = match (op, value) {
+        (SimplePredicateOp::Equals, _) => equal,
+        (SimplePredicateOp::NotEq, _) => 1.0 - equal,
+        (SimplePredicateOp::Like, _) => DEFAULT_SELECTIVITY,
+        (SimplePredicateOp::NotLike, _) => 1.0 - DEFAULT_SELECTIVITY,
+        (_, Some(v)) => {
+            let at_most = match fraction_at_most(&stats.histogram, stats.min.as_ref(), v) {
+                Some(at_most) => at_most,
+                None => return DEFAULT_SELECTIVITY,
+            };
+            match op {
+                SimplePredicateOp::LessThanOrEq => at_most,
+                SimplePredicateOp::LessThan => at_most - equal,
+                SimplePredicateOp::GreaterThan => 1.0 - at_most,
+                _ => 1.0 - at_most + equal,
+            }
+        }
+        (_, None) => return DEFAULT_SELECTIVITY,
+    };
+    selectivity.clamp(0.0, 1.0) * non_null_fraction(stats)
+}
+
+/// Estimates the fraction of rows a predicate keeps, treating the simple predicates it is
+/// composed of as independent. Comparisons of two columns, or of columns without
+/// statistics, are estimated with `DEFAULT_SELECTIVITY`.
+///
+/// # Arguments
+///
+/// * `predicate` - Predicate of the filter.
+/// * `column_stats` - Returns the statistics of a column the predicate refers to, if its
+///   table was analyzed.
+pub fn predicate_selectivity(
+    predicate: &Predicate,
+    column_stats: &dyn Fn(&FieldIdentifier) -> Option<ColumnStats>,
+) -> f64 {
+    let combine = |op: &CompoundPredicateOp, selectivities: Vec<f64>| match op {
+        CompoundPredicateOp::And => selectivities.iter().product(),
+        CompoundPredicateOp::Or => 1.0 - selectivities.iter().map(|s| 1.0 - s).product::<f64>(),
+    };
+    match predicate {
+        Predicate::SimplePredicate(SimplePredicate { left, op, right }) => {
+            let (ident, op, value) = match (left, right) {
+                (PredExpr::Ident(i), PredExpr::Literal(v)) => (i, *op, Some(v)),
+                (PredExpr::Literal(v), PredExpr::Ident(i)) => (i, op.flip(), Some(v)),
+                (PredExpr::Ident(i), PredExpr::Parameter(_) | PredExpr::Subquery(_)) => {
+                    (i, *op, None)
+                }
+                (PredExpr::Parameter(_) | PredExpr::Subquery(_), PredExpr::Ident(i)) => {
+                    (i, op.flip(), None)
+                }
+                _ => return comparison_selectivity(None, *op, None),
+            };
+            comparison_selectivity(column_stats(ident).as_ref(), op, value)
+        }
+        Predicate::CompoundPredicate(CompoundPredicate {
+            op,
+            simple_predicates,
+        }) => combine(
+            op,
+            simple_predicates
+                .iter()
+                .map(|p| {
+                    let p = Predicate::SimplePredicate(p.clone());
+                    predicate_selectivity(&p, column_stats)
+                })
+                .collect(),
+        ),
+        Predicate::NestedPredicate(NestedPredicate { op, predicates }) => combine(
+            op,
+            predicates
+                .iter()
+                .map(|p| predicate_selectivity(p, column_stats))
+                .collect(),
+        ),
+        Predicate::Not(predicate) => 1.0 - predicate_selectivity(predicate, column_stats),
+    }
+}
+
+/// Estimates the fraction of the pairs of rows of two relations a join predicate keeps.
+/// An equi-join matches each value of the side with fewer distinct values to a value of
+/// the other side.
+///
+/// # Arguments
+///
+/// * `op` - Comparison of the join predicate.
+/// * `left_distinct` - Estimated number of distinct values of the left join column.
+/// * `right_distinct` - Estimated number of distinct values of the right join column.
+pub fn join_selectivity(op: SimplePredicateOp, left_distinct: f64, right_distinct: f64) -> f64 {
+    match op {
+        SimplePredicateOp::Equals => 1.0 / left_distinct.max(right_distinct).max(1.0),
+        SimplePredicateOp::All => 1.0,
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+/// Estimates the number of groups of a grouping, the product of the distinct values of the
+/// grouped columns, at most the number of grouped rows. Columns without statistics leave
+/// the estimate at the number of grouped rows.
+///
+/// # Arguments
+///
+/// * `rows` - Estimated number of grouped rows.
+/// * `distinct` - Number of distinct values of each grouped column, if known.
+pub fn group_rows(rows: f64, distinct: &[Option<f64>]) -> f64 {
+    let groups = distinct
+        .iter()
+        .try_fold(1.0, |groups, d| d.map(|d| groups * d.max(1.0)))
+        .unwrap_or(rows);
+    groups.min(rows)
+}
+
+/// Fraction of the rows of a column that are not null.
+fn non_null_fraction(stats: &ColumnStats) -> f64 {
+    let non_null = stats.histogram.total() as f64;
+    let rows = non_null + stats.null_count as f64;
+    if rows == 0.0 {
+        1.0
+    } else {
+        non_null / rows
+    }
+}
+
+/// Whether a value is below the smallest or above the largest value of a column.
+fn outside(stats: &ColumnStats, value: &Field) -> bool {
+    let below = stats
+        .min
+        .as_ref()
+        .and_then(|min| compare(value, min))
+        .is_some_and(|o| o == Ordering::Less);
+    let above = stats
+        .max
+        .as_ref()
+        .and_then(|max| compare(value, max))
+        .is_some_and(|o| o == Ordering::Greater);
+    below || above
+}
+
+/// Estimates the fraction of the values of a histogram that are at most `value`. Returns
+/// None if the histogram is empty or its bounds cannot be compared to the value.
+///
+/// # Arguments
+///
+/// * `histogram` - Histogram of the non-null values of a column.
+/// * `min` - Smallest value of the column, the lower bound of the first bucket.
+/// * `value` - Value to compare to.
+fn fraction_at_most(histogram: &Histogram, min: Option<&Field>, value: &Field) -> Option<f64> {
+    let total = histogram.total();
+    if total == 0 {
+        return None;
+    }
+    let mut at_most = 0.0;
+    let mut lower = min;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        let count = *count as f64;
+        if compare(value, bound)? != Ordering::Less {
+            at_most += count;
+            lower = Some(bound);
+            continue;
+        }
+        // The value falls in this bucket, or below the first one.
+        if let Some(lower) = lower {
+            if compare(value, lower)? != Ordering::Less {
+                at_most += count * interpolate(lower, bound, value);
+            }
+        }
+        break;
+    }
+    Some(at_most / total as f64)
+}
+
+/// Estimates the fraction of the values of a bucket from `lower` to `upper` that are at
+/// most `value`, assuming the values are spread evenly. Values that are not numbers, dates
+/// or timestamps are assumed to be in the middle of the bucket.
+fn interpolate(lower: &Field, upper: &Field, value: &Field) -> f64 {
+    match (position(lower), position(upper), position(value)) {
+        (Some(l), Some(u), Some(v)) if u > l => ((v - l) / (u - l)).clamp(0.0, 1.0),
+        _ => 0.5,
+    }
+}
+
+/// Position of a value on a number line, for interpolation.
+fn position(field: &Field) -> Option<f64> {
+    match field {
+        Field::DateField(d) => Some(d.0 as f64),
+        Field::TimestampField(t) => Some(t.0 as f64),
+        _ => number(field),
+    }
+}
+
+/// Value of a number field.
+fn number(field: &Field) -> Option<f64> {
+    match field {
+        Field::IntField(i) => Some(*i as f64),
+        Field::FloatField(f) => Some(f.0),
+        Field::DecimalField(d) => Some(d.to_f64()),
+        _ => None,
+    }
+}
+
+/// Compares two values of the same type, or two numbers. Returns None for other values.
+fn compare(a: &Field, b: &Field) -> Option<Ordering> {
+    if discriminant(a) == discriminant(b) {
+        return Some(a.cmp(b));
+    }
+    number(a)?.partial_cmp(&number(b)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::statistics::TableStats;
+    use common::testutil::*;
+    use common::{Float, Tuple};
+
+    /// Statistics of a column holding 0..100 once each and `nulls` nulls.
+    fn column(nulls: usize) -> ColumnStats {
+        let schema = get_int_table_schema(1);
+        let mut tuples: Vec<Tuple> = (0..100).map(|i| int_vec_to_tuple(vec![i])).collect();
+        tuples.extend((0..nulls).map(|_| Tuple::new(vec![Field::Null])));
+        TableStats::from_tuples(&schema, tuples, 1.0)
+            .columns
+            .remove(0)
+    }
+
+    fn close(expected: f64, actual: f64) -> bool {
+        (expected - actual).abs() < 0.02
+    }
+
+    #[test]
+    fn test_filter_selectivity() {
+        let mut column = column(0);
+        column.distinct_count = 20;
+        let eq = filter_selectivity(Some(&column), SimplePredicateOp::Equals);
+        assert!((eq - 0.05).abs() < 1e-9);
+        let not_eq = filter_selectivity(Some(&column), SimplePredicateOp::NotEq);
+        assert!((not_eq - 0.95).abs() < 1e-9);
+        assert_eq!(
+            DEFAULT_SELECTIVITY,
+            filter_selectivity(None, SimplePredicateOp::Equals)
+        );
+        assert_eq!(
+            DEFAULT_SELECTIVITY,
+            filter_selectivity(Some(&column), SimplePredicateOp::LessThan)
+        );
+    }
+
+    #[test]
+    fn test_range_selectivity() {
+        let column = column(0);
+        let range = |op, v: Field| comparison_selectivity(Some(&column), op, Some(&v));
+        assert!(close(
+            0.25,
+            range(SimplePredicateOp::LessThan, Field::IntField(25))
+        ));
+        assert!(close(
+            0.6,
+            range(SimplePredicateOp::GreaterThanOrEq, Field::IntField(40))
+        ));
+        assert!(close(
+            0.5,
+            range(
+                SimplePredicateOp::LessThanOrEq,
+                Field::FloatField(Float(49.5))
+            )
+        ));
+        assert_eq!(
+            0.0,
+            range(SimplePredicateOp::LessThan, Field::IntField(-10))
+        );
+        assert_eq!(
+            1.0,
+            range(SimplePredicateOp::LessThan, Field::IntField(500))
+        );
+        assert_eq!(
+            DEFAULT_SELECTIVITY,
+            range(SimplePredicateOp::LessThan, Field::StringField("x".into()))
+        );
+    }
+
+    #[test]
+    fn test_equality_and_nulls() {
+        let column = column(100);
+        let eq = |v| comparison_selectivity(Some(&column), SimplePredicateOp::Equals, Some(&v));
+        assert!((eq(Field::IntField(5)) - 0.005).abs() < 1e-9);
+        assert_eq!(0.0, eq(Field::IntField(100)));
+        let greater = comparison_selectivity(
+            Some(&column),
+            SimplePredicateOp::GreaterThan,
+            Some(&Field::IntField(-1)),
+        );
+        assert!(close(0.5, greater));
+    }
+
+    #[test]
+    fn test_predicate_selectivity() {
+        let simple = |op, v| {
+            Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new("t", "a")),
+                op,
+                right: PredExpr::Literal(Field::IntField(v)),
+            })
+        };
+        let stats = |_: &FieldIdentifier| Some(column(0));
+        let and = Predicate::NestedPredicate(NestedPredicate {
+            op: CompoundPredicateOp::And,
+            predicates: vec![
+                simple(SimplePredicateOp::GreaterThanOrEq, 50),
+                simple(SimplePredicateOp::LessThan, 50),
+            ],
+        });
+        assert!(close(0.25, predicate_selectivity(&and, &stats)));
+        let or = Predicate::NestedPredicate(NestedPredicate {
+            op: CompoundPredicateOp::Or,
+            predicates: vec![
+                simple(SimplePredicateOp::LessThan, 50),
+                simple(SimplePredicateOp::LessThan, 50),
+            ],
+        });
+        assert!(close(0.75, predicate_selectivity(&or, &stats)));
+        let not = Predicate::Not(Box::new(simple(SimplePredicateOp::LessThan, 10)));
+        assert!(close(0.9, predicate_selectivity(&not, &stats)));
+        let unknown = |_: &FieldIdentifier| None;
+        let eq = simple(SimplePredicateOp::Equals, 1);
+        assert_eq!(DEFAULT_SELECTIVITY, predicate_selectivity(&eq, &unknown));
+    }
+
+    #[test]
+    fn test_join_and_group_rows() {
+        assert_eq!(
+            0.01,
+            join_selectivity(SimplePredicateOp::Equals, 100.0, 10.0)
+        );
+        assert_eq!(
+            DEFAULT_SELECTIVITY,
+            join_selectivity(SimplePredicateOp::LessThan, 100.0, 10.0)
+        );
+        assert_eq!(20.0, group_rows(1000.0, &[Some(4.0), Some(5.0)]));
+        assert_eq!(30.0, group_rows(30.0, &[Some(4.0), Some(50.0)]));
+        assert_eq!(1000.0, group_rows(1000.0, &[Some(4.0), None]));
+    }
+}
@@ -1,6 +1,21 @@
+pub use attach::{Attachments, FederatedCatalog};
+pub use cardinality::{
+    comparison_selectivity, filter_selectivity, group_rows, join_selectivity,
+    predicate_selectivity, DEFAULT_SELECTIVITY,
+};
 pub use executor::Executor;
+pub use planner::{JoinPlanner, JoinRelation};
+pub use result_cache::ResultCache;
+pub use rewrite::{
+    collapse_projections, push_aggregates_below_joins, push_filters_below_joins, rewrite_plan,
+};
 pub use translate_and_validate::TranslateAndValidate;
+mod attach;
+mod cardinality;
 mod executor;
+mod planner;
+mod result_cache;
+mod rewrite;
 mod translate_and_validate;
 
 // Notes on Query Optimization
@@ -0,0 +1,752 @@
+use super::cardinality::join_selectivity;
+use crate::opiterator::{
+    BuildSide, HashEqJoin, Join, KeyFilter, MemoryBudget, OpIterator, Profiling, ProjectIterator,
+};
+use common::metrics::MetricsRegistry;
+use common::statistics::TableStats;
+use common::{CrustyError, SimplePredicateOp};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Rows assumed for a relation whose table has not been analyzed.
+const DEFAULT_ROW_COUNT: f64 = 1000.0;
+/// Largest join ordered exhaustively; joins of more relations are ordered greedily.
+const DP_RELATION_LIMIT: usize = 10;
+/// Cost of inserting a tuple into a hash table, relative to reading a tuple.
+const HASH_BUILD_COST: f64 = 2.0;
+
+/// An input of a join, with the estimates the planner orders joins by.
+pub struct JoinRelation {
+    /// Operator producing the relation.
+    op: Box<dyn OpIterator>,
+    /// Estimated number of rows.
+    rows: f64,
+    /// Estimated number of distinct values of each column, if known.
+    distinct: Vec<Option<f64>>,
+    /// Filter over the keys of one column, used when the relation is hashed on that column.
+    key_filter: Option<(usize, KeyFilter)>,
+}
+
+impl JoinRelation {
+    /// Creates a join input from an operator reading a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `op` - Operator producing the relation. Its columns must be those of the table.
+    /// * `stats` - Statistics of the table, if it was analyzed.
+    /// * `selectivity` - Fraction of the table's rows the operator is expected to return.
+    pub fn new(op: Box<dyn OpIterator>, stats: Option<&TableStats>, selectivity: f64) -> Self {
+        let width = op.get_schema().size();
+        let (rows, distinct) = match stats {
+            Some(stats) => {
+                let rows = stats.row_count as f64 * selectivity;
+                let distinct = (0..width)
+                    .map(|i| {
+                        stats
+                            .columns
+                            .get(i)
+                            .map(|c| (c.distinct_count as f64).min(rows).max(1.0))
+                    })
+                    .collect();
+                (rows, distinct)
+            }
+            None => (DEFAULT_ROW_COUNT * selectivity, vec![None; width]),
+        };
+        JoinRelation {
+            op,
+            rows,
+            distinct,
+            key_filter: None,
+        }
+    }
+
+    /// Lets hash joins that build on this relation skip the probes the filter rules out.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - Column whose keys the filter tests.
+    /// * `filter` - Test returning false only for keys the column does not have, such as
+    ///   the bloom filter of the table.
+    pub fn with_key_filter(mut self, column: usize, filter: KeyFilter) -> Self {
+        self.key_filter = Some((column, filter));
+        self
+    }
+}
+
+/// Join algorithm chosen for a join.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum JoinMethod {
+    NestedLoop,
+    /// Hash join building its hash table over the given side.
+    Hash(BuildSide),
+}
+
+/// A column of one of the planner's relations.
+#[derive(Clone, Copy, Debug)]
+struct ColumnRef {
+    relation: usize,
+    column: usize,
+}
+
+/// A join predicate `left op right` between two relations.
+struct JoinEdge {
+    left: ColumnRef,
+    op: SimplePredicateOp,
+    right: ColumnRef,
+}
+
+/// A join order for a set of relations.
+#[derive(Clone, Debug)]
+enum PlanTree {
+    Relation(usize),
+    Join {
+        method: JoinMethod,
+        edge: usize,
+        /// Estimated number of rows of the join.
+        rows: f64,
+        left: Box<PlanTree>,
+        right: Box<PlanTree>,
+    },
+}
+
+impl PlanTree {
+    /// Relations of the tree in output column order.
+    fn relations(&self, out: &mut Vec<usize>) {
+        match self {
+            PlanTree::Relation(r) => out.push(*r),
+            PlanTree::Join { left, right, .. } => {
+                left.relations(out);
+                right.relations(out);
+            }
+        }
+    }
+}
+
+/// Best known plan for a set of relations.
+#[derive(Clone)]
+struct Candidate {
+    /// Bitmask of the relations joined.
+    relations: u64,
+    tree: PlanTree,
+    rows: f64,
+    cost: f64,
+}
+
+/// Cost-based join planner.
+///
+/// Given the inputs of a join and the predicates between them, the planner picks the join
+/// order and, for every join, a hash or nested-loop join, minimizing the estimated number
+/// of tuples read, hashed and compared. Joins of up to `DP_RELATION_LIMIT` relations are
+/// ordered exhaustively with dynamic programming over subsets (bushy plans included);
+/// larger joins are built greedily by repeatedly making the cheapest join.
+///
+/// The planned operator returns the columns in the order the relations were added, so the
+/// result can replace a join tree built in the order the query listed the tables.
+#[derive(Default)]
+pub struct JoinPlanner {
+    relations: Vec<JoinRelation>,
+    edges: Vec<JoinEdge>,
+    /// Budget the hash tables of the hash joins count against, if any.
+    memory_budget: Option<MemoryBudget>,
+    /// Registry the planned joins record their runs in, if any.
+    metrics: Option<Arc<MetricsRegistry>>,
+    /// How the planned joins are profiled.
+    profiling: Profiling,
+}
+
+impl JoinPlanner {
+    /// Creates a planner with no relations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts the hash tables of the planned hash joins against a memory budget.
+    ///
+    /// # Arguments
+    ///
+    /// * `budget` - Budget shared with the other operators of the plan.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// Profiles the planned joins.
+    ///
+    /// # Arguments
+    ///
+    /// * `profiling` - How the other operators of the plan are profiled.
+    pub fn with_profiling(mut self, profiling: Profiling) -> Self {
+        self.profiling = profiling;
+        self
+    }
+
+    /// Records the runs of the planned joins in a metrics registry, with the `profile`
+    /// feature.
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - Registry of the storage manager the plan runs against.
+    pub fn with_metrics(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        self.metrics = Some(registry);
+        self
+    }
+
+    /// Adds an input of the join and returns its index.
+    ///
+    /// # Arguments
+    ///
+    /// * `relation` - Input to add.
+    pub fn add_relation(&mut self, relation: JoinRelation) -> usize {
+        self.relations.push(relation);
+        self.relations.len() - 1
+    }
+
+    /// Adds the join predicate `left op right`.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - Name of a column of one of the relations.
+    /// * `op` - Comparison of the predicate.
+    /// * `right` - Name of a column of another relation.
+    pub fn add_predicate(
+        &mut self,
+        left: &str,
+        op: SimplePredicateOp,
+        right: &str,
+    ) -> Result<(), CrustyError> {
+        let left = self.resolve(left)?;
+        let right = self.resolve(right)?;
+        if left.relation == right.relation {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Join predicate must compare columns of two different tables",
+            )));
+        }
+        self.edges.push(JoinEdge { left, op, right });
+        Ok(())
+    }
+
+    /// Finds the relation and index of a column.
+    fn resolve(&self, name: &str) -> Result<ColumnRef, CrustyError> {
+        for (relation, input) in self.relations.iter().enumerate() {
+            if let Some(column) = input.op.get_schema().get_field_index(name) {
+                return Ok(ColumnRef {
+                    relation,
+                    column: *column,
+                });
+            }
+        }
+        Err(CrustyError::ExecutionError(format!(
+            "Join column {} not found",
+            name
+        )))
+    }
+
+    /// Plans the join and returns its operator.
+    pub fn plan(self) -> Result<Box<dyn OpIterator>, CrustyError> {
+        let tree = self.best_tree()?;
+        let mut order = Vec::new();
+        tree.relations(&mut order);
+        let widths: Vec<usize> = self
+            .relations
+            .iter()
+            .map(|r| r.op.get_schema().size())
+            .collect();
+        let (mut ops, mut key_filters): (Vec<_>, Vec<_>) = self
+            .relations
+            .into_iter()
+            .map(|r| (Some(r.op), r.key_filter))
+            .unzip();
+        let budget = self.memory_budget.as_ref();
+        let profile = |op, rows| self.profiling.wrap(op, rows, self.metrics.as_ref());
+        let op = Self::build(
+            &tree,
+            &self.edges,
+            &widths,
+            &mut ops,
+            &mut key_filters,
+            budget,
+            &profile,
+        )?;
+
+        // Put the columns back in the order the relations were added.
+        if order.windows(2).all(|w| w[0] < w[1]) {
+            return Ok(op);
+        }
+        let rows = op.describe().estimated_rows.map(|rows| rows as f64);
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for r in order {
+            offsets.insert(r, offset);
+            offset += widths[r];
+        }
+        let fields = (0..widths.len())
+            .flat_map(|r| offsets[&r]..offsets[&r] + widths[r])
+            .collect();
+        let project = Box::new(ProjectIterator::new(fields, op));
+        Ok(profile(project, rows))
+    }
+
+    /// Finds the cheapest join order.
+    fn best_tree(&self) -> Result<PlanTree, CrustyError> {
+        let n = self.relations.len();
+        if n == 0 {
+            return Err(CrustyError::ExecutionError(String::from(
+                "Join has no inputs",
+            )));
+        }
+        let best = if n <= DP_RELATION_LIMIT {
+            self.dynamic_programming()
+        } else {
+            self.greedy()
+        };
+        best.map(|c| c.tree).ok_or_else(|| {
+            CrustyError::ExecutionError(String::from("No join order applies every join predicate"))
+        })
+    }
+
+    /// Orders the joins by dynamic programming over subsets of relations.
+    fn dynamic_programming(&self) -> Option<Candidate> {
+        let n = self.relations.len();
+        let full = (1u64 << n) - 1;
+        let mut best: HashMap<u64, Candidate> = HashMap::new();
+        for r in 0..n {
+            best.insert(1 << r, self.leaf(r));
+        }
+        // Visiting the sets in increasing order visits every subset before its supersets.
+        for set in 1..=full {
+            if set.count_ones() < 2 {
+                continue;
+            }
+            let mut left = (set - 1) & set;
+            while left > 0 {
+                let right = set & !left;
+                if let (Some(l), Some(r)) = (best.get(&left), best.get(&right)) {
+                    if let Some(candidate) = self.join(l, r) {
+                        if best.get(&set).is_none_or(|b| candidate.cost < b.cost) {
+                            best.insert(set, candidate);
+                        }
+                    }
+                }
+                left = (left - 1) & set;
+            }
+        }
+        best.remove(&full)
+    }
+
+    /// Orders the joins greedily, always making the cheapest available join next.
+    fn greedy(&self) -> Option<Candidate> {
+        let mut plans: Vec<Candidate> = (0..self.relations.len()).map(|r| self.leaf(r)).collect();
+        while plans.len() > 1 {
+            let mut best: Option<(usize, usize, Candidate)> = None;
+            for i in 0..plans.len() {
+                for j in 0..plans.len() {
+                    if i == j {
+                        continue;
+                    }
+                    if let Some(candidate) = self.join(&plans[i], &plans[j]) {
+                        if best
+                            .as_ref()
+                            .is_none_or(|(_, _, b)| candidate.cost < b.cost)
+                        {
+                            best = Some((i, j, candidate));
+                        }
+                    }
+                }
+            }
+            let (i, j, candidate) = best?;
+            plans.remove(i.max(j));
+            plans.remove(i.min(j));
+            plans.push(candidate);
+        }
+        plans.pop()
+    }
+
+    /// Plan reading a single relation.
+    fn leaf(&self, r: usize) -> Candidate {
+        let rows = self.relations[r].rows;
+        Candidate {
+            relations: 1 << r,
+            tree: PlanTree::Relation(r),
+            rows,
+            cost: rows,
+        }
+    }
+
+    /// Cheapest way to join `left` (the outer or probe side) with `right`, or None if the
+    /// two are not connected by exactly one predicate.
+    fn join(&self, left: &Candidate, right: &Candidate) -> Option<Candidate> {
+        let in_set = |c: ColumnRef, set: u64| set & (1 << c.relation) != 0;
+        let mut crossing = self.edges.iter().enumerate().filter(|(_, e)| {
+            (in_set(e.left, left.relations) && in_set(e.right, right.relations))
+                || (in_set(e.right, left.relations) && in_set(e.left, right.relations))
+        });
+        let (edge_idx, edge) = crossing.next()?;
+        // A second predicate between the two sides would be dropped by a single join.
+        if crossing.next().is_some() {
+            return None;
+        }
+
+        let distinct = |c: ColumnRef, rows: f64| {
+            self.relations[c.relation].distinct[c.column]
+                .map_or(rows, |d| d.min(rows))
+                .max(1.0)
+        };
+        let (l, r) = if in_set(edge.left, left.relations) {
+            (edge.left, edge.right)
+        } else {
+            (edge.right, edge.left)
+        };
+        let selectivity =
+            join_selectivity(edge.op, distinct(l, left.rows), distinct(r, right.rows));
+        let rows = left.rows * right.rows * selectivity;
+
+        // The inner side of a nested-loop join is read once per outer tuple; a hash join
+        // reads both sides once and hashes the smaller side.
+        let nested_loop = left.cost + left.rows.max(1.0) * right.cost;
+        let build_side = if left.rows < right.rows {
+            BuildSide::Left
+        } else {
+            BuildSide::Right
+        };
+        let hash = left.cost + right.cost + HASH_BUILD_COST * left.rows.min(right.rows);
+        let (method, cost) = if matches!(edge.op, SimplePredicateOp::Equals) && hash < nested_loop {
+            (JoinMethod::Hash(build_side), hash)
+        } else {
+            (JoinMethod::NestedLoop, nested_loop)
+        };
+        Some(Candidate {
+            relations: left.relations | right.relations,
+            tree: PlanTree::Join {
+                method,
+                edge: edge_idx,
+                rows,
+                left: Box::new(left.tree.clone()),
+                right: Box::new(right.tree.clone()),
+            },
+            rows,
+            cost: cost + rows,
+        })
+    }
+
+    /// Builds the operators of a plan tree.
+    fn build(
+        tree: &PlanTree,
+        edges: &[JoinEdge],
+        widths: &[usize],
+        ops: &mut Vec<Option<Box<dyn OpIterator>>>,
+        key_filters: &mut Vec<Option<(usize, KeyFilter)>>,
+        budget: Option<&MemoryBudget>,
+        profile: &dyn Fn(Box<dyn OpIterator>, Option<f64>) -> Box<dyn OpIterator>,
+    ) -> Result<Box<dyn OpIterator>, CrustyError> {
+        match tree {
+            PlanTree::Relation(r) => Ok(ops[*r].take().unwrap()),
+            PlanTree::Join {
+                method,
+                edge,
+                rows,
+                left,
+                right,
+            } => {
+                let mut left_order = Vec::new();
+                left.relations(&mut left_order);
+                let mut right_order = Vec::new();
+                right.relations(&mut right_order);
+                // Index of a column in the output of a subtree.
+                let index = |c: ColumnRef, order: &[usize]| {
+                    order
+                        .iter()
+                        .take_while(|r| **r != c.relation)
+                        .map(|r| widths[*r])
+                        .sum::<usize>()
+                        + c.column
+                };
+                let edge = &edges[*edge];
+                let (op, left_index, right_index) = if left_order.contains(&edge.left.relation) {
+                    (
+                        edge.op,
+                        index(edge.left, &left_order),
+                        index(edge.right, &right_order),
+                    )
+                } else {
+                    (
+                        edge.op.flip(),
+                        index(edge.right, &left_order),
+                        index(edge.left, &right_order),
+                    )
+                };
+                let left_child =
+                    Self::build(left, edges, widths, ops, key_filters, budget, profile)?;
+                let right_child =
+                    Self::build(right, edges, widths, ops, key_filters, budget, profile)?;
+                debug!(
+                    "Planned {:?} join of {:?} and {:?}",
+                    method, left_order, right_order
+                );
+                let join: Box<dyn OpIterator> = match method {
+                    JoinMethod::Hash(build_side) => {
+                        let mut join = HashEqJoin::new_with_build_side(
+                            op,
+                            left_index,
+                            right_index,
+                            left_child,
+                            right_child,
+                            *build_side,
+                        )?;
+                        if let Some(budget) = budget {
+                            join = join.with_memory_grant(budget.grant(false));
+                        }
+                        // A relation hashed on its filtered column can rule out probe keys.
+                        let (build, build_index) = match build_side {
+                            BuildSide::Left => (left, left_index),
+                            _ => (right, right_index),
+                        };
+                        let filter = match build.as_ref() {
+                            PlanTree::Relation(r) => key_filters[*r]
+                                .take_if(|(column, _)| *column == build_index)
+                                .map(|(_, filter)| filter),
+                            _ => None,
+                        };
+                        match filter {
+                            Some(filter) => Box::new(join.with_key_filter(filter)),
+                            None => Box::new(join),
+                        }
+                    }
+                    JoinMethod::NestedLoop => Box::new(Join::new(
+                        op,
+                        left_index,
+                        right_index,
+                        left_child,
+                        right_child,
+                    )?),
+                };
+                Ok(profile(join, Some(*rows)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::TupleIterator;
+    use common::testutil::*;
+    use common::{DataType, Field, TableSchema, Tuple};
+
+    /// Int relation `name` with columns `name.a` and `name.b`.
+    fn relation(name: &str, rows: Vec<Vec<i32>>) -> Box<dyn OpIterator> {
+        let a = format!("{}.a", name);
+        let b = format!("{}.b", name);
+        let schema = TableSchema::from_vecs(vec![&a, &b], vec![DataType::Int, DataType::Int]);
+        Box::new(TupleIterator::new(create_tuple_list(rows), schema))
+    }
+
+    fn stats(rows: u64) -> TableStats {
+        let schema = get_int_table_schema(2);
+        let mut stats = TableStats::from_tuples(&schema, Vec::new(), 1.0);
+        stats.row_count = rows;
+        for column in stats.columns.iter_mut() {
+            column.distinct_count = rows;
+        }
+        stats
+    }
+
+    fn collect(mut op: Box<dyn OpIterator>) -> Vec<Tuple> {
+        op.open().unwrap();
+        let mut res = Vec::new();
+        while let Some(t) = op.next().unwrap() {
+            res.push(t);
+        }
+        op.close().unwrap();
+        res.sort_by_key(|t| format!("{:?}", t));
+        res
+    }
+
+    /// Plans `big JOIN mid ON big.a = mid.a JOIN small ON mid.b = small.b`.
+    fn three_way(big_rows: u64) -> JoinPlanner {
+        let mut planner = JoinPlanner::new();
+        let big = relation("big", (0..6).map(|i| vec![i, i * 10]).collect());
+        let mid = relation("mid", vec![vec![1, 7], vec![2, 8], vec![3, 9]]);
+        let small = relation("small", vec![vec![0, 8]]);
+        planner.add_relation(JoinRelation::new(big, Some(&stats(big_rows)), 1.0));
+        planner.add_relation(JoinRelation::new(mid, Some(&stats(100)), 1.0));
+        planner.add_relation(JoinRelation::new(small, Some(&stats(1)), 1.0));
+        planner
+            .add_predicate("big.a", SimplePredicateOp::Equals, "mid.a")
+            .unwrap();
+        planner
+            .add_predicate("small.b", SimplePredicateOp::Equals, "mid.b")
+            .unwrap();
+        planner
+    }
+
+    #[test]
+    fn test_plan_joins_small_relations_first() {
+        let planner = three_way(1_000_000);
+        let tree = planner.best_tree().unwrap();
+        // The selective join of mid and small is done before joining the big table.
+        match tree {
+            PlanTree::Join { left, right, .. } => {
+                let mut left_order = Vec::new();
+                left.relations(&mut left_order);
+                let mut right_order = Vec::new();
+                right.relations(&mut right_order);
+                assert!(left_order == vec![0] || right_order == vec![0]);
+            }
+            PlanTree::Relation(_) => panic!("expected a join"),
+        }
+    }
+
+    #[test]
+    fn test_plan_result_matches_query_order() {
+        for big_rows in [1, 1_000_000] {
+            let op = three_way(big_rows).plan().unwrap();
+            let names: Vec<&str> = op.get_schema().attributes().map(|a| a.name()).collect();
+            assert_eq!(
+                vec!["big.a", "big.b", "mid.a", "mid.b", "small.a", "small.b"],
+                names
+            );
+            assert_eq!(
+                create_tuple_list(vec![vec![2, 20, 2, 8, 0, 8]]),
+                collect(op)
+            );
+        }
+    }
+
+    #[test]
+    fn test_plan_chooses_join_method() {
+        let mut planner = JoinPlanner::new();
+        let l = relation("l", vec![vec![1, 1], vec![2, 2]]);
+        let r = relation("r", vec![vec![1, 5], vec![2, 0]]);
+        planner.add_relation(JoinRelation::new(l, Some(&stats(10_000)), 1.0));
+        planner.add_relation(JoinRelation::new(r, Some(&stats(10_000)), 1.0));
+        planner
+            .add_predicate("l.a", SimplePredicateOp::Equals, "r.a")
+            .unwrap();
+        match planner.best_tree().unwrap() {
+            PlanTree::Join { method, .. } => {
+                assert_eq!(JoinMethod::Hash(BuildSide::Right), method)
+            }
+            PlanTree::Relation(_) => panic!("expected a join"),
+        }
+
+        // Only nested loops evaluate inequalities.
+        let mut planner = JoinPlanner::new();
+        let l = relation("l", vec![vec![1, 1], vec![2, 2]]);
+        let r = relation("r", vec![vec![1, 5], vec![2, 0]]);
+        planner.add_relation(JoinRelation::new(l, Some(&stats(10_000)), 1.0));
+        planner.add_relation(JoinRelation::new(r, Some(&stats(10_000)), 1.0));
+        planner
+            .add_predicate("l.b", SimplePredicateOp::LessThan, "r.b")
+            .unwrap();
+        match planner.best_tree().unwrap() {
+            PlanTree::Join { method, .. } => assert_eq!(JoinMethod::NestedLoop, method),
+            PlanTree::Relation(_) => panic!("expected a join"),
+        }
+        assert_eq!(
+            create_tuple_list(vec![vec![1, 1, 1, 5], vec![2, 2, 1, 5]]),
+            collect(planner.plan().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_plan_hash_join_key_filter() {
+        // Each relation rules out the keys it does not have.
+        let filter = |keys: Vec<i32>| -> KeyFilter {
+            Box::new(move |key| matches!(key, Field::IntField(k) if keys.contains(k)))
+        };
+        let mut planner = JoinPlanner::new();
+        let l = relation("l", vec![vec![1, 1], vec![3, 2]]);
+        let r = relation("r", vec![vec![1, 5], vec![2, 0]]);
+        planner.add_relation(
+            JoinRelation::new(l, Some(&stats(10_000)), 1.0).with_key_filter(0, filter(vec![1, 3])),
+        );
+        planner.add_relation(
+            JoinRelation::new(r, Some(&stats(10_000)), 1.0).with_key_filter(0, filter(vec![1, 2])),
+        );
+        planner
+            .add_predicate("l.a", SimplePredicateOp::Equals, "r.a")
+            .unwrap();
+        let op = planner.plan().unwrap();
+        let mut node = op.describe();
+        while node.name != "HashEqJoin" {
+            node = node.children.remove(0);
+        }
+        assert!(node
+            .params
+            .contains(&("key_filter".to_string(), "bloom".to_string())));
+        assert_eq!(create_tuple_list(vec![vec![1, 1, 1, 5]]), collect(op));
+    }
+
+    #[test]
+    fn test_plan_greedy() {
+        // More relations than dynamic programming handles: a chain t0.a = t1.a = ... .
+        let n = DP_RELATION_LIMIT + 2;
+        let mut planner = JoinPlanner::new();
+        for i in 0..n {
+            let op = relation(&format!("t{}", i), vec![vec![1, i as i32], vec![2, 0]]);
+            planner.add_relation(JoinRelation::new(op, None, 1.0));
+        }
+        for i in 1..n {
+            planner
+                .add_predicate(
+                    &format!("t{}.a", i - 1),
+                    SimplePredicateOp::Equals,
+                    &format!("t{}.a", i),
+                )
+                .unwrap();
+        }
+        let result = collect(planner.plan().unwrap());
+        assert_eq!(2, result.len());
+        let expected: Vec<Field> = (0..n)
+            .flat_map(|i| vec![Field::IntField(1), Field::IntField(i as i32)])
+            .collect();
+        assert!(result.contains(&Tuple::new(expected)));
+    }
+
+    #[test]
+    fn test_plan_hashes_smaller_side() {
+        let mut planner = JoinPlanner::new();
+        let small = relation("small", vec![vec![1, 1], vec![2, 2]]);
+        let big = relation("big", (0..10).map(|i| vec![i % 3, i]).collect());
+        planner.add_relation(JoinRelation::new(small, Some(&stats(10)), 1.0));
+        planner.add_relation(JoinRelation::new(big, Some(&stats(100_000)), 1.0));
+        planner
+            .add_predicate("small.a", SimplePredicateOp::Equals, "big.a")
+            .unwrap();
+        match planner.best_tree().unwrap() {
+            PlanTree::Join {
+                method: JoinMethod::Hash(side),
+                left,
+                right,
+                ..
+            } => {
+                let build = if side == BuildSide::Left { left } else { right };
+                assert!(matches!(*build, PlanTree::Relation(0)));
+            }
+            other => panic!("expected a hash join, got {:?}", other),
+        }
+        let expected: Vec<Vec<i32>> = (0..10)
+            .filter(|i| i % 3 != 0)
+            .map(|i| vec![i % 3, i % 3, i % 3, i])
+            .collect();
+        let mut expected = create_tuple_list(expected);
+        expected.sort_by_key(|t| format!("{:?}", t));
+        assert_eq!(expected, collect(planner.plan().unwrap()));
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let mut planner = JoinPlanner::new();
+        assert!(planner.best_tree().is_err());
+        planner.add_relation(JoinRelation::new(relation("x", vec![]), None, 1.0));
+        planner.add_relation(JoinRelation::new(relation("y", vec![]), None, 1.0));
+        assert!(planner
+            .add_predicate("x.a", SimplePredicateOp::Equals, "z.a")
+            .is_err());
+        assert!(planner
+            .add_predicate("x.a", SimplePredicateOp::Equals, "x.b")
+            .is_err());
+        // Without a predicate the relations cannot be joined.
+        assert!(planner.plan().is_err());
+    }
+}
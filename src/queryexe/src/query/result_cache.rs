@@ -0,0 +1,666 @@
+use super::Attachments;
+use crate::opiterator::{OpIterator, PlanNode};
+use crate::StorageManager;
+use common::ids::{ContainerId, Permissions, StateType, TransactionId, CONTAINER_COUNTER};
+use common::logical_plan::OpIndex;
+use common::physical_plan::{PhysicalOp, PhysicalPlan, PhysicalScanNode};
+use common::storage_trait::StorageTrait;
+use common::{CrustyError, TableSchema, Tuple};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Bytes of tuples above which a result is not cached.
+pub const MAX_CACHED_RESULT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Cache of the results of queries, keyed by a fingerprint of their physical plans.
+///
+/// Every container has a version that is bumped whenever its records change, and a cached
+/// result is only served while the containers its plan scans keep the versions they had
+/// when the result was computed. Results are stored in temporary containers of the storage
+/// manager, which are removed when the result is invalidated or evicted. When the cache is
+/// full, the least recently used result is evicted.
+///
+/// The cache is disabled while its capacity is 0.
+pub struct ResultCache {
+    storage_manager: Arc<StorageManager>,
+    /// Most results kept.
+    capacity: AtomicUsize,
+    /// Version of each container whose records have changed.
+    versions: RwLock<HashMap<ContainerId, u64>>,
+    /// Cached results by the fingerprint of their plans.
+    entries: Mutex<HashMap<u64, CachedEntry>>,
+    /// Incremented on every use of a result, to find the least recently used.
+    clock: AtomicU64,
+}
+
+/// A cached result and what it depends on.
+struct CachedEntry {
+    /// Canonical form of the plan, compared on lookups so colliding fingerprints are misses.
+    plan: String,
+    /// Containers scanned by the plan and their versions when the result was computed.
+    versions: Vec<(ContainerId, u64)>,
+    schema: TableSchema,
+    /// Temporary container holding the tuples of the result, in order.
+    container_id: ContainerId,
+    rows: usize,
+    last_used: u64,
+}
+
+/// Fingerprint, canonical form and scanned containers of a plan.
+struct PlanKey {
+    fingerprint: u64,
+    plan: String,
+    containers: Vec<ContainerId>,
+}
+
+impl ResultCache {
+    /// Creates an empty cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_manager` - Storage manager of the cached tables, which also holds the
+    ///   cached results.
+    /// * `capacity` - Most results kept, 0 to disable the cache.
+    pub fn new(storage_manager: Arc<StorageManager>, capacity: usize) -> Self {
+        ResultCache {
+            storage_manager,
+            capacity: AtomicUsize::new(capacity),
+            versions: RwLock::new(HashMap::new()),
+            entries: Mutex::new(HashMap::new()),
+            clock: AtomicU64::new(0),
+        }
+    }
+
+    /// Changes the number of results kept, evicting results if there are too many.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Ordering::SeqCst);
+        let mut entries = self.entries.lock().unwrap();
+        self.evict(&mut entries, capacity);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity.load(Ordering::SeqCst) > 0
+    }
+
+    /// Number of results cached.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the version of a container.
+    pub fn version(&self, container_id: ContainerId) -> u64 {
+        *self
+            .versions
+            .read()
+            .unwrap()
+            .get(&container_id)
+            .unwrap_or(&0)
+    }
+
+    /// Bumps the version of a container whose records changed, and drops the results that
+    /// depend on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_id` - Container of the changed table.
+    pub fn bump(&self, container_id: ContainerId) {
+        *self
+            .versions
+            .write()
+            .unwrap()
+            .entry(container_id)
+            .or_insert(0) += 1;
+        let mut entries = self.entries.lock().unwrap();
+        let stale: Vec<u64> = entries
+            .iter()
+            .filter(|(_, entry)| entry.versions.iter().any(|(id, _)| *id == container_id))
+            .map(|(fingerprint, _)| *fingerprint)
+            .collect();
+        for fingerprint in stale {
+            let entry = entries.remove(&fingerprint).unwrap();
+            self.remove_container(&entry);
+        }
+    }
+
+    /// Drops every cached result.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        for (_, entry) in entries.drain() {
+            self.remove_container(&entry);
+        }
+    }
+
+    /// Returns an iterator over the cached result of a plan, if there is one computed from
+    /// the current versions of the containers the plan scans.
+    ///
+    /// # Arguments
+    ///
+    /// * `physical_plan` - Plan of the query.
+    /// * `attachments` - Attached storage directories of the session, whose tables are never
+    ///   cached.
+    /// * `tid` - Transaction reading the result.
+    pub fn get(
+        &self,
+        physical_plan: &PhysicalPlan,
+        attachments: &Attachments,
+        tid: TransactionId,
+    ) -> Option<Box<dyn OpIterator>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let key = Self::key(physical_plan, attachments)?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_mut(&key.fingerprint)?;
+        if entry.plan != key.plan || !self.is_current(&entry.versions) {
+            return None;
+        }
+        entry.last_used = self.clock.fetch_add(1, Ordering::SeqCst);
+        debug!(
+            "Serving {} cached rows from container {}",
+            entry.rows, entry.container_id
+        );
+        // The tuples are read while the entry is locked, since invalidating it removes its
+        // container.
+        let tuples = self
+            .storage_manager
+            .get_iterator(entry.container_id, tid, Permissions::ReadOnly)
+            .map(|(bytes, _)| common::encoding::decode(&bytes))
+            .collect::<Result<Vec<Tuple>, CrustyError>>()
+            .ok()?;
+        Some(Box::new(CachedResult {
+            schema: entry.schema.clone(),
+            tuples,
+            index: None,
+        }))
+    }
+
+    /// Wraps the iterator of a plan so that its result is cached once it has been read to the
+    /// end. Returns the iterator unchanged if the cache is disabled or the plan cannot be
+    /// cached, such as a plan that changes a table.
+    ///
+    /// # Arguments
+    ///
+    /// * `physical_plan` - Plan of the query, from which the iterator was built.
+    /// * `attachments` - Attached storage directories of the session.
+    /// * `iterator` - Iterator computing the result.
+    pub fn fill(
+        &'static self,
+        physical_plan: &PhysicalPlan,
+        attachments: &Attachments,
+        iterator: Box<dyn OpIterator>,
+    ) -> Box<dyn OpIterator> {
+        if !self.is_enabled() {
+            return iterator;
+        }
+        let key = match Self::key(physical_plan, attachments) {
+            Some(key) => key,
+            None => return iterator,
+        };
+        // Versions are read before the plan runs, so a result computed while a table
+        // changes is never served.
+        let versions = key
+            .containers
+            .iter()
+            .map(|id| (*id, self.version(*id)))
+            .collect();
+        Box::new(CacheFill {
+            child: iterator,
+            cache: self,
+            key: Some(key),
+            versions,
+            tuples: Vec::new(),
+            size: 0,
+        })
+    }
+
+    /// Stores a result read to the end, evicting the least recently used results if the cache
+    /// is full.
+    fn insert(
+        &self,
+        key: PlanKey,
+        versions: Vec<(ContainerId, u64)>,
+        schema: TableSchema,
+        tuples: Vec<Tuple>,
+        tid: TransactionId,
+    ) -> Result<(), CrustyError> {
+        if !self.is_current(&versions) {
+            return Ok(());
+        }
+        let container_id = CONTAINER_COUNTER.fetch_add(1, Ordering::SeqCst);
+        self.storage_manager
+            .create_container(container_id, None, StateType::CachedResult, None)?;
+        let rows = tuples.len();
+        let values = tuples.iter().map(|tuple| tuple.to_bytes()).collect();
+        self.storage_manager
+            .insert_values(container_id, values, tid)?;
+        let entry = CachedEntry {
+            plan: key.plan,
+            versions,
+            schema,
+            container_id,
+            rows,
+            last_used: self.clock.fetch_add(1, Ordering::SeqCst),
+        };
+        debug!("Caching {} rows in container {}", rows, container_id);
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(old) = entries.insert(key.fingerprint, entry) {
+            self.remove_container(&old);
+        }
+        let capacity = self.capacity.load(Ordering::SeqCst);
+        self.evict(&mut entries, capacity);
+        Ok(())
+    }
+
+    /// Evicts the least recently used results until at most `capacity` are left.
+    fn evict(&self, entries: &mut HashMap<u64, CachedEntry>, capacity: usize) {
+        while entries.len() > capacity {
+            let oldest = *entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .unwrap()
+                .0;
+            let entry = entries.remove(&oldest).unwrap();
+            self.remove_container(&entry);
+        }
+    }
+
+    fn remove_container(&self, entry: &CachedEntry) {
+        if let Err(e) = self.storage_manager.remove_container(entry.container_id) {
+            warn!(
+                "Cannot remove cached result container {}: {:?}",
+                entry.container_id, e
+            );
+        }
+    }
+
+    /// Whether containers still have the given versions.
+    fn is_current(&self, versions: &[(ContainerId, u64)]) -> bool {
+        versions
+            .iter()
+            .all(|(id, version)| self.version(*id) == *version)
+    }
+
+    /// Returns the key of a plan, or None if its result cannot be cached.
+    fn key(physical_plan: &PhysicalPlan, attachments: &Attachments) -> Option<PlanKey> {
+        let root = physical_plan.root()?;
+        let mut containers = Vec::new();
+        let plan = Self::canonical(physical_plan, root, &mut containers)?;
+        if containers.iter().any(|id| attachments.route(*id).is_some()) {
+            return None;
+        }
+        containers.sort_unstable();
+        containers.dedup();
+        let mut hasher = DefaultHasher::new();
+        plan.hash(&mut hasher);
+        Some(PlanKey {
+            fingerprint: hasher.finish(),
+            plan,
+            containers,
+        })
+    }
+
+    /// Returns the canonical form of the subplan rooted at `node` and collects the containers
+    /// it scans, or returns None if its result cannot be cached. The canonical form leaves out
+    /// the containers the planner allocates for hash tables, which differ every time a query
+    /// is planned.
+    fn canonical(
+        physical_plan: &PhysicalPlan,
+        node: OpIndex,
+        containers: &mut Vec<ContainerId>,
+    ) -> Option<String> {
+        let op = physical_plan.get_operator(node)?;
+        match op {
+            PhysicalOp::Scan(PhysicalScanNode { container_id, .. }) => {
+                containers.push(*container_id)
+            }
+            PhysicalOp::Update(_)
+            | PhysicalOp::Insert(_)
+            | PhysicalOp::Delete(_)
+            | PhysicalOp::MaterializedView(_) => return None,
+            _ => {}
+        }
+        let mut value = serde_json::to_value(op).ok()?;
+        strip_hash_tables(&mut value);
+        let mut children = Vec::new();
+        for child in physical_plan.edges(node) {
+            children.push(Self::canonical(physical_plan, child, containers)?);
+        }
+        Some(format!("{}[{}]", value, children.join(",")))
+    }
+}
+
+/// Removes the ids of hash table containers from a serialized operator.
+fn strip_hash_tables(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.remove("hash_table_state_id");
+            map.values_mut().for_each(strip_hash_tables);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_hash_tables),
+        _ => {}
+    }
+}
+
+/// Iterator passing the tuples of its child through and caching them once the child has been
+/// read to the end.
+struct CacheFill {
+    child: Box<dyn OpIterator>,
+    cache: &'static ResultCache,
+    /// Key of the plan, or None once the result is cached or cannot be cached.
+    key: Option<PlanKey>,
+    versions: Vec<(ContainerId, u64)>,
+    /// Tuples returned so far.
+    tuples: Vec<Tuple>,
+    /// Encoded size of the tuples returned so far.
+    size: usize,
+}
+
+impl OpIterator for CacheFill {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.child.open()
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        let next = self.child.next()?;
+        if self.key.is_none() {
+            return Ok(next);
+        }
+        match &next {
+            Some(tuple) => {
+                self.size += tuple.size();
+                if self.size > MAX_CACHED_RESULT_SIZE {
+                    self.key = None;
+                    self.tuples = Vec::new();
+                } else {
+                    self.tuples.push(tuple.clone());
+                }
+            }
+            None => self.cache.insert(
+                self.key.take().unwrap(),
+                std::mem::take(&mut self.versions),
+                self.child.get_schema().clone(),
+                std::mem::take(&mut self.tuples),
+                TransactionId::new(),
+            )?,
+        }
+        Ok(next)
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.child.close()
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        // The tuples already collected would be returned again.
+        self.key = None;
+        self.tuples = Vec::new();
+        self.child.rewind()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        self.child.get_schema()
+    }
+
+    fn describe(&self) -> PlanNode {
+        self.child.describe()
+    }
+}
+
+/// Iterator over a cached result.
+struct CachedResult {
+    schema: TableSchema,
+    tuples: Vec<Tuple>,
+    /// Index of the next tuple, None if the iterator is closed.
+    index: Option<usize>,
+}
+
+impl OpIterator for CachedResult {
+    fn open(&mut self) -> Result<(), CrustyError> {
+        self.index = Some(0);
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Tuple>, CrustyError> {
+        let i = match self.index {
+            None => panic!("Operator has not been opened"),
+            Some(i) => i,
+        };
+        self.index = Some(i + 1);
+        Ok(self.tuples.get(i).cloned())
+    }
+
+    fn close(&mut self) -> Result<(), CrustyError> {
+        self.index = None;
+        Ok(())
+    }
+
+    fn rewind(&mut self) -> Result<(), CrustyError> {
+        if self.index.is_none() {
+            panic!("Operator has not been opened")
+        }
+        self.open()
+    }
+
+    fn get_schema(&self) -> &TableSchema {
+        &self.schema
+    }
+
+    fn describe(&self) -> PlanNode {
+        PlanNode::new("CachedResult").param("rows", self.tuples.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::opiterator::testutil::*;
+    use crate::opiterator::{MemoryBudget, Profiling, DEFAULT_MEMORY_LIMIT};
+    use crate::query::Executor;
+    use common::catalog::Catalog;
+    use common::database::Database;
+    use common::logical_plan::*;
+    use common::physical_plan::{PhysicalFilterNode, PhysicalInsertNode};
+    use common::table::Table;
+    use common::testutil::int_vec_to_tuple;
+    use common::{Attribute, DataType, Field};
+    use std::sync::Arc;
+
+    /// A table t(a, b) holding rows (i, i % 3) for i in 0..10, and a cache over its storage
+    /// manager.
+    struct Fixture {
+        db: Database,
+        table_id: ContainerId,
+        cache: &'static ResultCache,
+    }
+
+    fn fixture(capacity: usize) -> Fixture {
+        let db = Database::new("db".to_string());
+        let table_id = db
+            .get_new_container_id(StateType::BaseTable, Some("t".to_string()))
+            .unwrap();
+        let rows = (0..10).map(|i| vec![i, i % 3]).collect();
+        let (sm, _) = test_table(table_id, rows);
+        let schema = TableSchema::new(
+            ["a", "b"]
+                .iter()
+                .map(|c| Attribute::new(c.to_string(), DataType::Int))
+                .collect(),
+        );
+        db.tables.write().unwrap().insert(
+            table_id,
+            Arc::new(RwLock::new(Table::new("t".to_string(), schema))),
+        );
+        let cache = Box::leak(Box::new(ResultCache::new(sm, capacity)));
+        Fixture {
+            db,
+            table_id,
+            cache,
+        }
+    }
+
+    impl Fixture {
+        /// Plans `SELECT * FROM t WHERE b = <b>`.
+        fn plan(&self, b: i32) -> PhysicalPlan {
+            let mut plan = PhysicalPlan::new();
+            let scan = plan.add_node(PhysicalOp::Scan(PhysicalScanNode {
+                alias: "t".to_string(),
+                container_id: self.table_id,
+            }));
+            let filter = plan.add_node(PhysicalOp::Filter(PhysicalFilterNode {
+                table: "t".to_string(),
+                predicate: Predicate::SimplePredicate(SimplePredicate {
+                    left: PredExpr::Ident(FieldIdentifier::new("t", "t.b")),
+                    op: SimplePredicateOp::Equals,
+                    right: PredExpr::Literal(Field::IntField(b)),
+                }),
+            }));
+            plan.add_edge(filter, scan);
+            plan.set_root(filter).unwrap();
+            plan
+        }
+
+        /// Runs a plan through the cache and returns the first column of its result.
+        fn run(&self, plan: &PhysicalPlan) -> Vec<i32> {
+            let attachments = Attachments::new();
+            let tid = TransactionId::new();
+            let op = match self.cache.get(plan, &attachments, tid) {
+                Some(op) => op,
+                None => {
+                    let (_, tm) = test_table(0, Vec::new());
+                    let op = Executor::physical_plan_to_op_iterator(
+                        &self.cache.storage_manager,
+                        tm,
+                        &self.db,
+                        &attachments,
+                        plan,
+                        &MemoryBudget::new(DEFAULT_MEMORY_LIMIT),
+                        Profiling::default(),
+                        tid,
+                        0,
+                    )
+                    .unwrap();
+                    self.cache.fill(plan, &attachments, op)
+                }
+            };
+            first_column(op)
+        }
+
+        fn is_cached(&self, plan: &PhysicalPlan) -> bool {
+            self.cache
+                .get(plan, &Attachments::new(), TransactionId::new())
+                .is_some()
+        }
+    }
+
+    fn first_column(mut op: Box<dyn OpIterator>) -> Vec<i32> {
+        op.open().unwrap();
+        let mut values = Vec::new();
+        while let Some(tuple) = op.next().unwrap() {
+            match tuple.get_field(0) {
+                Some(Field::IntField(i)) => values.push(*i),
+                field => panic!("Unexpected field {:?}", field),
+            }
+        }
+        op.close().unwrap();
+        values
+    }
+
+    #[test]
+    fn test_cached_result() {
+        let fixture = fixture(4);
+        let plan = fixture.plan(1);
+        assert!(!fixture.is_cached(&plan));
+        assert_eq!(vec![1, 4, 7], fixture.run(&plan));
+        assert_eq!(1, fixture.cache.len());
+
+        let cached = fixture
+            .cache
+            .get(&plan, &Attachments::new(), TransactionId::new())
+            .unwrap();
+        assert!(cached.describe().to_string().contains("CachedResult"));
+        assert_eq!(vec![1, 4, 7], first_column(cached));
+        assert!(!fixture.is_cached(&fixture.plan(2)));
+    }
+
+    #[test]
+    fn test_bump_invalidates() {
+        let fixture = fixture(4);
+        let plan = fixture.plan(0);
+        assert_eq!(vec![0, 3, 6, 9], fixture.run(&plan));
+
+        fixture.cache.bump(fixture.table_id + 1000);
+        assert!(fixture.is_cached(&plan));
+        fixture.cache.bump(fixture.table_id);
+        assert_eq!(1, fixture.cache.version(fixture.table_id));
+        assert!(!fixture.is_cached(&plan));
+        assert!(fixture.cache.is_empty());
+
+        let sm = &fixture.cache.storage_manager;
+        sm.insert_value(
+            fixture.table_id,
+            int_vec_to_tuple(vec![12, 0]).to_bytes(),
+            TransactionId::new(),
+        )
+        .unwrap();
+        fixture.cache.bump(fixture.table_id);
+        assert_eq!(vec![0, 3, 6, 9, 12], fixture.run(&plan));
+        assert!(fixture.is_cached(&plan));
+    }
+
+    #[test]
+    fn test_disabled() {
+        let fixture = fixture(0);
+        let plan = fixture.plan(1);
+        assert_eq!(vec![1, 4, 7], fixture.run(&plan));
+        assert!(fixture.cache.is_empty());
+        assert!(!fixture.is_cached(&plan));
+
+        fixture.cache.set_capacity(1);
+        fixture.run(&plan);
+        assert!(fixture.is_cached(&plan));
+        fixture.cache.set_capacity(0);
+        assert!(fixture.cache.is_empty());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let fixture = fixture(2);
+        let plans: Vec<PhysicalPlan> = (0..3).map(|b| fixture.plan(b)).collect();
+        fixture.run(&plans[0]);
+        fixture.run(&plans[1]);
+        // Using the first result makes the second the least recently used.
+        fixture.run(&plans[0]);
+        fixture.run(&plans[2]);
+        assert_eq!(2, fixture.cache.len());
+        assert!(fixture.is_cached(&plans[0]));
+        assert!(!fixture.is_cached(&plans[1]));
+        assert!(fixture.is_cached(&plans[2]));
+    }
+
+    #[test]
+    fn test_changes_not_cached() {
+        let fixture = fixture(4);
+        // INSERT INTO t SELECT * FROM t
+        let mut plan = PhysicalPlan::new();
+        let scan = plan.add_node(PhysicalOp::Scan(PhysicalScanNode {
+            alias: "t".to_string(),
+            container_id: fixture.table_id,
+        }));
+        let insert = plan.add_node(PhysicalOp::Insert(PhysicalInsertNode {
+            alias: "t".to_string(),
+            container_id: fixture.table_id,
+        }));
+        plan.add_edge(insert, scan);
+        plan.set_root(insert).unwrap();
+        assert!(ResultCache::key(&plan, &Attachments::new()).is_none());
+        assert!(ResultCache::key(&fixture.plan(1), &Attachments::new()).is_some());
+    }
+}
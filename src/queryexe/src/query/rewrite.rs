@@ -0,0 +1,585 @@
+//! Rule-based rewrites of logical plans.
+//!
+//! Each rule rewrites the patterns of the plan it applies to and returns the number of
+//! rewrites it made. `rewrite_plan` applies every rule until it makes no more rewrites,
+//! then removes the nodes the rules bypassed.
+
+use common::logical_plan::{
+    AggregateNode, FieldIdentifier, JoinNode, LogicalOp, LogicalPlan, OpIndex, ProjectIdentifiers,
+    ProjectNode, SimplePredicateOp,
+};
+use common::AggOp;
+
+/// A rewrite rule, returning the number of rewrites it made.
+type Rule = fn(&mut LogicalPlan) -> usize;
+
+/// Rules applied by `rewrite_plan`, in order, with their names. Filters are pushed before
+/// aggregates, so that partial aggregates read filtered inputs.
+const RULES: [(&str, Rule); 3] = [
+    ("push filters below joins", push_filters_below_joins),
+    ("collapse projections", collapse_projections),
+    ("push aggregates below joins", push_aggregates_below_joins),
+];
+
+/// Applies every rewrite rule to a plan until it makes no more rewrites, then removes the
+/// nodes the rewrites bypassed.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan to rewrite.
+pub fn rewrite_plan(plan: &mut LogicalPlan) {
+    for (name, rule) in RULES {
+        let mut rewrites = 0;
+        loop {
+            let applied = rule(plan);
+            if applied == 0 {
+                break;
+            }
+            rewrites += applied;
+        }
+        debug!("Rewrite rule {} applied {} times", name, rewrites);
+    }
+    plan.remove_unreachable();
+}
+
+/// Moves filters over joins below the joins, over the input holding the columns they
+/// compare, so that the join reads only the rows that pass the filter. Returns the number
+/// of filters moved.
+///
+/// A filter moves one join down per call, so that filters over nested joins reach the
+/// scans of their tables over repeated calls. Filters directly over scans may then be
+/// answered by index and partition scans. Filters comparing to subqueries and filters at
+/// the root of the plan are not moved.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan to rewrite.
+pub fn push_filters_below_joins(plan: &mut LogicalPlan) -> usize {
+    let mut pushed = 0;
+    for index in 0..plan.node_count() {
+        let columns: Vec<FieldIdentifier> = match plan.get_operator(index) {
+            Some(LogicalOp::Filter(filter)) if filter.predicate.subqueries().is_empty() => filter
+                .predicate
+                .simple_predicates()
+                .into_iter()
+                .flat_map(|p| [&p.left, &p.right])
+                .filter_map(|expr| expr.ident().cloned())
+                .collect(),
+            _ => continue,
+        };
+        if columns.is_empty() {
+            continue;
+        }
+        let parent = match parents(plan, index)[..] {
+            [parent] => parent,
+            _ => continue,
+        };
+        let join_index = match plan.edges(index).collect::<Vec<OpIndex>>()[..] {
+            [child] if matches!(plan.get_operator(child), Some(LogicalOp::Join(_))) => child,
+            _ => continue,
+        };
+        let inputs: Vec<OpIndex> = plan.edges(join_index).collect();
+        for input in inputs {
+            let mut tables = Vec::new();
+            if !input_tables(plan, input, &mut tables)
+                || !columns
+                    .iter()
+                    .all(|c| tables.iter().any(|t| t == c.table()))
+            {
+                continue;
+            }
+            // parent -> filter -> join -> input becomes parent -> join -> filter -> input.
+            plan.retarget_edge(parent, index, join_index);
+            plan.retarget_edge(join_index, input, index);
+            plan.retarget_edge(index, join_index, input);
+            pushed += 1;
+            break;
+        }
+    }
+    pushed
+}
+
+/// Merges projections over projections into one projection of the inner projection's
+/// input. Returns the number of projections merged.
+///
+/// A projection of all columns is dropped. Otherwise each column of the outer projection
+/// is the column of the inner projection with its name, renamed to the outer name. The
+/// projections are not merged if the outer projection computes expressions or projects a
+/// column the inner projection does not name.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan to rewrite.
+pub fn collapse_projections(plan: &mut LogicalPlan) -> usize {
+    let mut collapsed = 0;
+    for index in 0..plan.node_count() {
+        let outer = match plan.get_operator(index) {
+            Some(LogicalOp::Project(outer)) => outer,
+            _ => continue,
+        };
+        let inner_index = match plan.edges(index).collect::<Vec<OpIndex>>()[..] {
+            [child] => child,
+            _ => continue,
+        };
+        let inner = match plan.get_operator(inner_index) {
+            Some(LogicalOp::Project(inner)) => inner,
+            _ => continue,
+        };
+        let input = match plan.edges(inner_index).collect::<Vec<OpIndex>>()[..] {
+            [input] => input,
+            _ => continue,
+        };
+        let identifiers = match compose_projections(&outer.identifiers, &inner.identifiers) {
+            Some(identifiers) => identifiers,
+            None => continue,
+        };
+        plan.retarget_edge(index, inner_index, input);
+        if let Some(op) = plan.get_operator_mut(index) {
+            *op = LogicalOp::Project(ProjectNode { identifiers });
+        }
+        collapsed += 1;
+    }
+    collapsed
+}
+
+/// Returns the columns of a projection over another projection as one projection of the
+/// inner projection's input, or None if they cannot be merged.
+///
+/// # Arguments
+///
+/// * `outer` - Columns of the outer projection.
+/// * `inner` - Columns of the inner projection.
+fn compose_projections(
+    outer: &ProjectIdentifiers,
+    inner: &ProjectIdentifiers,
+) -> Option<ProjectIdentifiers> {
+    let (outer, inner) = match (outer, inner) {
+        (ProjectIdentifiers::Wildcard, inner) => return Some(inner.clone()),
+        (outer, ProjectIdentifiers::Wildcard) => return Some(outer.clone()),
+        (ProjectIdentifiers::List(outer), ProjectIdentifiers::List(inner)) => (outer, inner),
+    };
+    outer
+        .iter()
+        .map(|field| {
+            if field.expr().is_some() || field.agg_op().is_some() {
+                return None;
+            }
+            let mut column = inner
+                .iter()
+                .find(|f| f.alias().unwrap_or_else(|| f.column()) == field.column())?
+                .clone();
+            column.set_alias(field.alias().unwrap_or_else(|| field.column()).to_string());
+            Some(column)
+        })
+        .collect::<Option<Vec<FieldIdentifier>>>()
+        .map(ProjectIdentifiers::List)
+}
+
+/// Returns the nodes with an edge to a node.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan of the node.
+/// * `node` - Node to get the consumers of.
+fn parents(plan: &LogicalPlan, node: OpIndex) -> Vec<OpIndex> {
+    plan.edge_references()
+        .filter(|edge| edge.target() == node)
+        .map(|edge| edge.source())
+        .collect()
+}
+
+/// Pushes partial aggregates below the joins aggregates read, so that joins read one row
+/// per group of an input instead of all of its rows (eager aggregation). Returns the number
+/// of partial aggregates added.
+///
+/// An aggregate directly over an equality join is split when every value it aggregates is a
+/// column of the same input of the join, none of them is averaged, and the group by keys
+/// include the join key of that input. The input is then grouped by the keys that are its
+/// columns, which include its join key, so each of its rows meets the same rows of the
+/// other input as the group it falls in. The aggregate above the join combines the partial
+/// results: partial counts are summed, and sums, minimums and maximums are aggregated again.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan to rewrite.
+pub fn push_aggregates_below_joins(plan: &mut LogicalPlan) -> usize {
+    let mut pushed = 0;
+    for index in 0..plan.node_count() {
+        let aggregate = match plan.get_operator(index) {
+            Some(LogicalOp::Aggregate(aggregate)) => aggregate.clone(),
+            _ => continue,
+        };
+        let children: Vec<OpIndex> = plan.edges(index).collect();
+        let join_index = match children[..] {
+            [child] => child,
+            _ => continue,
+        };
+        let join = match plan.get_operator(join_index) {
+            Some(LogicalOp::Join(join)) if matches!(join.op, SimplePredicateOp::Equals) => {
+                join.clone()
+            }
+            _ => continue,
+        };
+        let inputs: Vec<OpIndex> = plan.edges(join_index).collect();
+        for input in inputs {
+            let mut tables = Vec::new();
+            if !input_tables(plan, input, &mut tables) {
+                continue;
+            }
+            if let Some((partial, combined)) = split_aggregate(&aggregate, &join, &tables) {
+                let partial = plan.add_node(LogicalOp::Aggregate(partial));
+                plan.add_edge(partial, input);
+                plan.retarget_edge(join_index, input, partial);
+                if let Some(op) = plan.get_operator_mut(index) {
+                    *op = LogicalOp::Aggregate(combined);
+                }
+                pushed += 1;
+                break;
+            }
+        }
+    }
+    pushed
+}
+
+/// Collects the tables an input of a join reads. Returns false if the input is not made of
+/// scans, filters and joins, whose columns are those of the tables.
+///
+/// # Arguments
+///
+/// * `plan` - Logical plan of the input.
+/// * `node` - Top node of the input.
+/// * `tables` - Tables read so far.
+fn input_tables(plan: &LogicalPlan, node: OpIndex, tables: &mut Vec<String>) -> bool {
+    match plan.get_operator(node) {
+        Some(LogicalOp::Scan(scan)) => {
+            tables.push(scan.alias.clone());
+            true
+        }
+        Some(LogicalOp::Filter(filter)) if filter.predicate.subqueries().is_empty() => plan
+            .edges(node)
+            .all(|child| input_tables(plan, child, tables)),
+        Some(LogicalOp::Join(_)) => plan
+            .edges(node)
+            .all(|child| input_tables(plan, child, tables)),
+        _ => false,
+    }
+}
+
+/// Splits an aggregate over a join into a partial aggregate over one input of the join and
+/// the aggregate combining its results above the join, or returns None if the aggregate
+/// cannot be split over that input.
+///
+/// # Arguments
+///
+/// * `aggregate` - Aggregate over the join.
+/// * `join` - Join the aggregate reads.
+/// * `tables` - Tables the input reads.
+fn split_aggregate(
+    aggregate: &AggregateNode,
+    join: &JoinNode,
+    tables: &[String],
+) -> Option<(AggregateNode, AggregateNode)> {
+    let in_input = |f: &FieldIdentifier| tables.iter().any(|t| t == f.table());
+    let same = |a: &FieldIdentifier, b: &FieldIdentifier| {
+        a.table() == b.table() && a.column() == b.column()
+    };
+    if aggregate
+        .fields
+        .iter()
+        .chain(&aggregate.group_by)
+        .any(|f| f.expr().is_some())
+    {
+        return None;
+    }
+    let aggregated: Vec<&FieldIdentifier> = aggregate
+        .fields
+        .iter()
+        .filter(|f| f.agg_op().is_some())
+        .collect();
+    if aggregated.is_empty()
+        || !aggregated
+            .iter()
+            .all(|f| in_input(f) && !matches!(f.agg_op(), Some(AggOp::Avg)))
+    {
+        return None;
+    }
+    let key = match (in_input(&join.left), in_input(&join.right)) {
+        (true, false) => &join.left,
+        (false, true) => &join.right,
+        _ => return None,
+    };
+    if !aggregate.group_by.iter().any(|g| same(g, key)) {
+        return None;
+    }
+
+    // The partial aggregate names its group by columns as written, so the join and the
+    // aggregate above it find them, and its values by the names of the final values.
+    let group_by: Vec<FieldIdentifier> = aggregate
+        .group_by
+        .iter()
+        .filter(|g| in_input(g))
+        .map(|g| FieldIdentifier::new(g.table(), g.column()))
+        .collect();
+    let mut partial_fields = group_by.clone();
+    let mut fields = Vec::new();
+    for field in &aggregate.fields {
+        let op = match field.agg_op() {
+            Some(op) => op,
+            None => {
+                fields.push(field.clone());
+                continue;
+            }
+        };
+        let name = field.alias().unwrap_or_else(|| field.column());
+        let mut partial = FieldIdentifier::new(field.table(), field.column());
+        partial.set_op(op);
+        partial.set_alias(name.to_string());
+        partial_fields.push(partial);
+        let mut combined = FieldIdentifier::new_column_alias(field.table(), name, name);
+        combined.set_op(match op {
+            AggOp::Count => AggOp::Sum,
+            op => op,
+        });
+        fields.push(combined);
+    }
+    Some((
+        AggregateNode {
+            fields: partial_fields,
+            group_by,
+        },
+        AggregateNode {
+            fields,
+            group_by: aggregate.group_by.clone(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use common::logical_plan::{FilterNode, PredExpr, Predicate, ScanNode, SimplePredicate};
+    use common::Field;
+
+    /// Returns a field aggregated with an operation, named as the translator names it.
+    fn aggregated(table: &str, column: &str, op: AggOp) -> FieldIdentifier {
+        let mut field = FieldIdentifier::new(table, column);
+        field.set_op(op);
+        field.default_alias();
+        field
+    }
+
+    /// Returns the plan of an aggregate over the join of t and u on t.a = u.k, and the
+    /// indices of the aggregate and the join.
+    fn join_plan(
+        fields: Vec<FieldIdentifier>,
+        group_by: Vec<FieldIdentifier>,
+    ) -> (LogicalPlan, OpIndex, OpIndex) {
+        let mut plan = LogicalPlan::new();
+        let t = plan.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("t"),
+            container_id: 0,
+        }));
+        let u = plan.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("u"),
+            container_id: 1,
+        }));
+        let join = plan.add_node(LogicalOp::Join(JoinNode {
+            left: FieldIdentifier::new("t", "t.a"),
+            right: FieldIdentifier::new("u", "u.k"),
+            op: SimplePredicateOp::Equals,
+            left_table: Some(String::from("t")),
+            right_table: Some(String::from("u")),
+        }));
+        plan.add_edge(join, u);
+        plan.add_edge(join, t);
+        let aggregate = plan.add_node(LogicalOp::Aggregate(AggregateNode { fields, group_by }));
+        plan.add_edge(aggregate, join);
+        (plan, aggregate, join)
+    }
+
+    #[test]
+    fn test_push_aggregate_below_join() {
+        let group = FieldIdentifier::new("t", "t.a");
+        let (mut plan, aggregate, join) = join_plan(
+            vec![
+                group.clone(),
+                aggregated("t", "t.b", AggOp::Count),
+                aggregated("t", "t.b", AggOp::Max),
+            ],
+            vec![group, FieldIdentifier::new("u", "u.v")],
+        );
+        assert_eq!(1, push_aggregates_below_joins(&mut plan));
+
+        let inputs: Vec<OpIndex> = plan.edges(join).collect();
+        let partial = match plan.get_operator(inputs[0]) {
+            Some(LogicalOp::Aggregate(partial)) => partial,
+            op => panic!("Expected a partial aggregate, got {:?}", op),
+        };
+        assert_eq!(
+            vec!["t.a"],
+            partial
+                .group_by
+                .iter()
+                .map(|g| g.column())
+                .collect::<Vec<_>>()
+        );
+        assert!(matches!(partial.fields[1].agg_op(), Some(AggOp::Count)));
+        assert!(matches!(
+            plan.get_operator(inputs[1]),
+            Some(LogicalOp::Scan(_))
+        ));
+
+        let combined = match plan.get_operator(aggregate) {
+            Some(LogicalOp::Aggregate(combined)) => combined,
+            op => panic!("Expected an aggregate, got {:?}", op),
+        };
+        assert_eq!(2, combined.group_by.len());
+        let ops: Vec<String> = combined
+            .fields
+            .iter()
+            .map(|f| f.agg_op().map_or(String::new(), |op| op.to_string()))
+            .collect();
+        assert_eq!(vec!["", "sum", "max"], ops);
+        assert_eq!(Some("count_t.b"), combined.fields[1].alias());
+        assert_eq!(plan.root(), Some(aggregate));
+    }
+
+    /// Returns a filter comparing a column to a literal.
+    fn filter(table: &str, column: &str) -> LogicalOp {
+        LogicalOp::Filter(FilterNode {
+            table: table.to_string(),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new(table, column)),
+                op: SimplePredicateOp::GreaterThan,
+                right: PredExpr::Literal(Field::IntField(10)),
+            }),
+        })
+    }
+
+    /// Returns a projection of columns renamed to aliases.
+    fn project(columns: &[(&str, &str)]) -> LogicalOp {
+        LogicalOp::Project(ProjectNode {
+            identifiers: ProjectIdentifiers::List(
+                columns
+                    .iter()
+                    .map(|(column, alias)| FieldIdentifier::new_column_alias("t", column, alias))
+                    .collect(),
+            ),
+        })
+    }
+
+    #[test]
+    fn test_push_filters_below_joins() {
+        let (mut plan, aggregate, join) = join_plan(
+            vec![aggregated("t", "t.b", AggOp::Sum)],
+            vec![FieldIdentifier::new("u", "u.v")],
+        );
+        // A filter on t over the join, and one on u over it.
+        let t_filter = plan.add_node(filter("t", "t.b"));
+        let u_filter = plan.add_node(filter("u", "u.v"));
+        plan.retarget_edge(aggregate, join, u_filter);
+        plan.add_edge(u_filter, t_filter);
+        plan.add_edge(t_filter, join);
+        assert_eq!(plan.root(), Some(aggregate));
+
+        // The filter on t moves first, then the filter on u is over the join.
+        assert_eq!(2, push_filters_below_joins(&mut plan));
+        assert_eq!(0, push_filters_below_joins(&mut plan));
+        assert_eq!(vec![join], plan.edges(aggregate).collect::<Vec<_>>());
+        let inputs: Vec<OpIndex> = plan.edges(join).collect();
+        assert_eq!(vec![t_filter, u_filter], inputs);
+        for input in inputs {
+            let scan = plan.edges(input).next().unwrap();
+            assert!(matches!(plan.get_operator(scan), Some(LogicalOp::Scan(_))));
+        }
+        assert!(plan.all_reachable_from_root().unwrap());
+    }
+
+    #[test]
+    fn test_filter_over_both_inputs_not_pushed() {
+        let (mut plan, aggregate, join) = join_plan(
+            vec![aggregated("t", "t.b", AggOp::Sum)],
+            vec![FieldIdentifier::new("u", "u.v")],
+        );
+        let filter = plan.add_node(LogicalOp::Filter(FilterNode {
+            table: String::from("t"),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(FieldIdentifier::new("t", "t.b")),
+                op: SimplePredicateOp::GreaterThan,
+                right: PredExpr::Ident(FieldIdentifier::new("u", "u.v")),
+            }),
+        }));
+        plan.retarget_edge(aggregate, join, filter);
+        plan.add_edge(filter, join);
+        assert_eq!(0, push_filters_below_joins(&mut plan));
+    }
+
+    #[test]
+    fn test_collapse_projections() {
+        let mut plan = LogicalPlan::new();
+        let outer = plan.add_node(project(&[("y", "z"), ("x", "x")]));
+        let wildcard = plan.add_node(LogicalOp::Project(ProjectNode {
+            identifiers: ProjectIdentifiers::Wildcard,
+        }));
+        let inner = plan.add_node(project(&[("t.a", "x"), ("t.b", "y")]));
+        let scan = plan.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("t"),
+            container_id: 0,
+        }));
+        plan.add_edge(outer, wildcard);
+        plan.add_edge(wildcard, inner);
+        plan.add_edge(inner, scan);
+
+        rewrite_plan(&mut plan);
+        assert_eq!(2, plan.node_count());
+        let root = plan.root().unwrap();
+        let fields = match plan.get_operator(root) {
+            Some(LogicalOp::Project(ProjectNode {
+                identifiers: ProjectIdentifiers::List(fields),
+            })) => fields,
+            op => panic!("Expected a projection, got {:?}", op),
+        };
+        let columns: Vec<(&str, Option<&str>)> =
+            fields.iter().map(|f| (f.column(), f.alias())).collect();
+        assert_eq!(vec![("t.b", Some("z")), ("t.a", Some("x"))], columns);
+        assert!(matches!(
+            plan.get_operator(plan.edges(root).next().unwrap()),
+            Some(LogicalOp::Scan(_))
+        ));
+
+        // The outer projection names a column the inner one does not.
+        let mut plan = LogicalPlan::new();
+        let outer = plan.add_node(project(&[("w", "w")]));
+        let inner = plan.add_node(project(&[("t.a", "x")]));
+        let scan = plan.add_node(LogicalOp::Scan(ScanNode {
+            alias: String::from("t"),
+            container_id: 0,
+        }));
+        plan.add_edge(outer, inner);
+        plan.add_edge(inner, scan);
+        assert_eq!(0, collapse_projections(&mut plan));
+    }
+
+    #[test]
+    fn test_aggregate_not_pushed() {
+        let group = FieldIdentifier::new("t", "t.a");
+        // Group by keys without the join key of t.
+        let (mut plan, ..) = join_plan(
+            vec![aggregated("t", "t.b", AggOp::Sum)],
+            vec![FieldIdentifier::new("t", "t.c")],
+        );
+        assert_eq!(0, push_aggregates_below_joins(&mut plan));
+        // Values of both inputs.
+        let (mut plan, ..) = join_plan(
+            vec![
+                aggregated("t", "t.b", AggOp::Sum),
+                aggregated("u", "u.v", AggOp::Sum),
+            ],
+            vec![group.clone()],
+        );
+        assert_eq!(0, push_aggregates_below_joins(&mut plan));
+        // Averages.
+        let (mut plan, ..) = join_plan(vec![aggregated("t", "t.b", AggOp::Avg)], vec![group]);
+        assert_eq!(0, push_aggregates_below_joins(&mut plan));
+    }
+}
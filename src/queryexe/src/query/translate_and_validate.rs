@@ -1,11 +1,18 @@
+use crate::opiterator::output_dtype;
 use common::catalog::Catalog;
+use common::decimal::MAX_PRECISION;
 use common::logical_plan::*;
 use common::prelude::ContainerId;
-use common::{get_name, CrustyError, DataType, Field, SimplePredicateOp};
+use common::{
+    get_attr, get_name, negative_number, parse_typed_string, CrustyError, DataType, Field,
+    SimplePredicateOp,
+};
 use sqlparser::ast::{
     Assignment, BinaryOperator, Expr, Function, FunctionArg, JoinConstraint, JoinOperator,
-    SelectItem, SetExpr, TableFactor, Value,
+    ObjectName, OrderByExpr, SelectItem, SetExpr, Statement, TableFactor, UnaryOperator, Value,
 };
+use sqlparser::dialect::GenericDialect;
+use sqlparser::parser::Parser;
 use std::collections::HashSet;
 
 /// Translates input to a LogicalPlan
@@ -18,6 +25,34 @@ pub struct TranslateAndValidate<'a, T: Catalog> {
     catalog: &'a T,
     /// List of tables encountered. Used for field validation.
     tables: Vec<String>,
+    /// Tables of the query enclosing the subquery being translated, whose columns the
+    /// subquery may refer to.
+    outer: Vec<String>,
+    /// Columns of the enclosing query the subquery being translated refers to. Column `i`
+    /// is parameter `i` of the subquery's plan.
+    correlated: Vec<FieldIdentifier>,
+}
+
+/// Correlated subquery compared to a column, such as `b > (SELECT MAX(u.v) FROM u WHERE
+/// u.k = t.a AND u.c = 1)`, that is rewritten to a join of the enclosing query with the
+/// aggregate of the subquery grouped by its correlated column.
+struct Decorrelation {
+    /// Column of the enclosing query compared to the subquery.
+    column: FieldIdentifier,
+    /// Comparison of the column to the subquery.
+    op: SimplePredicateOp,
+    /// Table the subquery reads.
+    table: String,
+    /// Container of the table the subquery reads.
+    container_id: ContainerId,
+    /// Predicates of the subquery that do not refer to the enclosing query.
+    filter: Option<Predicate>,
+    /// Column of the subquery equal to a column of the enclosing query.
+    key: FieldIdentifier,
+    /// Column of the enclosing query the key is equal to.
+    outer_key: FieldIdentifier,
+    /// Aggregate the subquery selects.
+    aggregate: FieldIdentifier,
 }
 
 impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
@@ -27,6 +62,8 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             plan: LogicalPlan::new(),
             catalog,
             tables: Vec::new(),
+            outer: Vec::new(),
+            correlated: Vec::new(),
         }
     }
 
@@ -35,6 +72,7 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
     /// # Arguments
     ///
     /// * `identifiers` - a list of elements in a multi-part identifier e.g. table.column would be vec!["table", "column"]
+    ///   and a column of an attached table would be vec!["alias", "table", "column"]
     ///
     /// # Returns
     ///
@@ -42,19 +80,21 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
     /// or { table: table, column: table.column} if the full identifier is passed.
     fn disambiguate_name(&self, identifiers: Vec<&str>) -> Result<FieldIdentifier, CrustyError> {
         let orig = identifiers.join(".");
-        if identifiers.len() > 2 {
+        if identifiers.len() > 3 {
             return Err(CrustyError::ValidationError(format!(
-                "No . table names supported in field {}",
+                "Too many . qualifiers in field {}",
                 orig
             )));
         }
-        if identifiers.len() == 2 {
+        if identifiers.len() > 1 {
+            let (column, table) = identifiers.split_last().unwrap();
+            let table = table.join(".");
             let table_id = self
                 .catalog
-                .get_table_id(identifiers[0])
+                .get_table_id(&table)
                 .ok_or_else(|| CrustyError::CrustyError("Missing Table".to_string()))?;
-            if self.catalog.is_valid_column(table_id, identifiers[1]) {
-                return Ok(FieldIdentifier::new(identifiers[0], &orig));
+            if self.catalog.is_valid_column(table_id, column) {
+                return Ok(FieldIdentifier::new(&table, &orig));
             }
             return Err(CrustyError::ValidationError(format!(
                 "The field {} is not present in tables listed in the query",
@@ -101,6 +141,99 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         Ok(translator.plan)
     }
 
+    /// Parses a SQL statement and translates it to a LogicalPlan.
+    ///
+    /// # Arguments
+    ///
+    /// * `sql` - Text of a single SELECT, INSERT ... SELECT, UPDATE or DELETE statement.
+    /// * `catalog` - Catalog for validation.
+    pub fn parse(sql: &str, catalog: &T) -> Result<LogicalPlan, CrustyError> {
+        let mut statements = Parser::parse_sql(&GenericDialect {}, sql)
+            .map_err(|e| CrustyError::ValidationError(e.to_string()))?;
+        if statements.len() != 1 {
+            return Err(CrustyError::ValidationError(format!(
+                "Expected one statement, got {}",
+                statements.len()
+            )));
+        }
+        Self::from_statement(&statements.remove(0), catalog)
+    }
+
+    /// Translates a SELECT, INSERT ... SELECT, UPDATE or DELETE statement to a LogicalPlan.
+    ///
+    /// The table a statement modifies is looked up in the catalog. INSERT ... VALUES is not
+    /// planned, since no logical operator produces literal records: the values are converted
+    /// to the schema of the table and imported with `Executor::import_tuples`.
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - AST to translate.
+    /// * `catalog` - Catalog for validation.
+    pub fn from_statement(statement: &Statement, catalog: &T) -> Result<LogicalPlan, CrustyError> {
+        let table = |name: &ObjectName| {
+            let table_name = Self::get_table_name(name)?;
+            match catalog.get_table_id(&table_name) {
+                Some(table_id) => Ok((table_id, table_name)),
+                None => Err(CrustyError::ValidationError(format!(
+                    "Table {} does not exist",
+                    table_name
+                ))),
+            }
+        };
+        match statement {
+            Statement::Query(query) => Self::from_sql(query, catalog),
+            Statement::Insert {
+                table_name,
+                columns,
+                source,
+                ..
+            } => {
+                if !columns.is_empty() {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Inserts with columns specified are not supported",
+                    )));
+                }
+                if let SetExpr::Values(_) = source.body {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Inserts of values are imported, not planned",
+                    )));
+                }
+                let (table_id, table_name) = table(table_name)?;
+                Self::from_insert(table_id, &table_name, source, catalog)
+            }
+            Statement::Update {
+                table_name,
+                assignments,
+                selection,
+            } => {
+                let (table_id, table_name) = table(table_name)?;
+                Self::from_update(table_id, &table_name, assignments, selection, catalog)
+            }
+            Statement::Delete {
+                table_name,
+                selection,
+            } => {
+                let (table_id, table_name) = table(table_name)?;
+                Self::from_delete(table_id, &table_name, selection, catalog)
+            }
+            _ => Err(CrustyError::ValidationError(String::from(
+                "Only SELECT, INSERT, UPDATE and DELETE statements are planned",
+            ))),
+        }
+    }
+
+    /// Translates an UPDATE statement to a LogicalPlan.
+    ///
+    /// Assignments set columns of the table to literals, which are converted to the types of
+    /// the columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the table to update.
+    /// * `table_name` - Name of the table to update.
+    /// * `assignments` - Columns to set and their values.
+    /// * `selection` - Predicate of the records to update. All records are updated without one.
+    /// * `catalog` - Catalog for validation.
     pub fn from_update(
         table_id: ContainerId,
         table_name: &str,
@@ -113,80 +246,84 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         Ok(translator.plan)
     }
 
-    fn process_update(
-        &mut self,
+    /// Translates a DELETE statement to a LogicalPlan.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the table to delete from.
+    /// * `table_name` - Name of the table to delete from.
+    /// * `selection` - Predicate of the records to delete. All records are deleted without one.
+    /// * `catalog` - Catalog for validation.
+    pub fn from_delete(
         table_id: ContainerId,
         table_name: &str,
-        assignments: &[Assignment],
         selection: &Option<Expr>,
-    ) -> Result<(), CrustyError> {
-        // Table scan
-        let op = ScanNode {
+        catalog: &T,
+    ) -> Result<LogicalPlan, CrustyError> {
+        let mut translator = TranslateAndValidate::new(catalog);
+        let node = translator.process_selection(table_id, table_name, selection)?;
+        let delete_op = DeleteNode {
             alias: table_name.to_string(),
             container_id: table_id,
         };
-        let mut node = Some(self.plan.add_node(LogicalOp::Scan(op)));
-        self.tables.push(table_name.to_string());
+        let idx = translator.plan.add_node(LogicalOp::Delete(delete_op));
+        translator.plan.add_edge(idx, node);
+        Ok(translator.plan)
+    }
 
-        // Selection
-        if let Some(expr) = selection {
-            let predicate = self.process_binary_op(expr)?;
-            // table references in filter
-            let table = match &predicate {
-                Predicate::SimplePredicate(simple_predicate) => {
-                    match (&simple_predicate.left, &simple_predicate.right) {
-                        (PredExpr::Literal(_), PredExpr::Ident(id)) => id.table().to_string(),
-                        (PredExpr::Ident(id), PredExpr::Literal(_)) => id.table().to_string(),
-                        _ => {
-                            return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one identifier and at least one literal are supported")));
-                        }
-                    }
-                }
-                Predicate::CompoundPredicate(compound_predicates) => {
-                    let simple_predicate = &compound_predicates.simple_predicates[0];
-                    let table = match (&simple_predicate.left, &simple_predicate.right) {
-                        (PredExpr::Literal(_), PredExpr::Ident(id)) => id.table(),
-                        (PredExpr::Ident(id), PredExpr::Literal(_)) => id.table(),
-                        _ => {
-                            return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one indentifier and at least one literal are supported")));
-                        }
-                    };
-                    for simple_predicate in &compound_predicates.simple_predicates {
-                        let id = match (&simple_predicate.left, &simple_predicate.right) {
-                            (PredExpr::Literal(_), PredExpr::Ident(id)) => id,
-                            (PredExpr::Ident(id), PredExpr::Literal(_)) => id,
-                            _ => {
-                                return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one indentifier and at least one literal are supported")));
-                            }
-                        };
-                        if id.table() != table {
-                            return Err(CrustyError::ValidationError(String::from(
-                                "Where includes identifiers to columns in multiple tables",
-                            )));
-                        }
-                    }
-                    table.to_string()
-                }
-            };
-            let op = FilterNode { table, predicate };
-            let idx = self.plan.add_node(LogicalOp::Filter(op));
-            self.plan.add_edge(idx, node.unwrap());
-            node = Some(idx);
-        }
+    /// Translates an INSERT statement with a query as its source to a LogicalPlan.
+    ///
+    /// The query must select as many columns as the table has, of types that can be stored
+    /// in the columns of the table. Columns computed by arithmetic, functions, comparisons
+    /// or CASE are checked when their values are inserted.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the table to insert into.
+    /// * `table_name` - Name of the table to insert into.
+    /// * `source` - Query producing the records to insert.
+    /// * `catalog` - Catalog for validation.
+    pub fn from_insert(
+        table_id: ContainerId,
+        table_name: &str,
+        source: &sqlparser::ast::Query,
+        catalog: &T,
+    ) -> Result<LogicalPlan, CrustyError> {
+        let mut translator = TranslateAndValidate::new(catalog);
+        translator.process_query(source)?;
+        let node = translator.plan.root().ok_or_else(|| {
+            CrustyError::ValidationError(String::from("Insert query produced an empty plan"))
+        })?;
+        translator.validate_insert_source(table_id, node)?;
+        let insert_op = InsertNode {
+            alias: table_name.to_string(),
+            container_id: table_id,
+        };
+        let idx = translator.plan.add_node(LogicalOp::Insert(insert_op));
+        translator.plan.add_edge(idx, node);
+        Ok(translator.plan)
+    }
+
+    fn process_update(
+        &mut self,
+        table_id: ContainerId,
+        table_name: &str,
+        assignments: &[Assignment],
+        selection: &Option<Expr>,
+    ) -> Result<(), CrustyError> {
+        let node = self.process_selection(table_id, table_name, selection)?;
         let mut assigns = Vec::new();
         for assignment in assignments.iter() {
             let ident = FieldIdentifier::new(table_name, &assignment.id.value);
             if let Expr::Value(val) = &assignment.value {
                 let field = match val {
-                    Value::Number(s, _) => {
-                        let i = s.parse::<i32>().map_err(|_| {
-                            CrustyError::ValidationError(format!("Unsupported literal {}", s))
-                        })?;
-                        Field::IntField(i)
-                    }
+                    Value::Number(s, _) => Field::from_number(s).ok_or_else(|| {
+                        CrustyError::ValidationError(format!("Unsupported literal {}", s))
+                    })?,
                     Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => {
                         Field::StringField(s.to_string())
                     }
+                    Value::Boolean(b) => Field::BoolField(*b),
                     Value::Null => Field::Null,
                     _ => {
                         return Err(CrustyError::ValidationError(String::from(
@@ -194,6 +331,19 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
                         )))
                     }
                 };
+                let dtype = self.ident_dtype(&ident).ok_or_else(|| {
+                    CrustyError::ValidationError(format!(
+                        "The field {} is not present in table {}",
+                        assignment.id.value, table_name
+                    ))
+                })?;
+                let field = field.coerce(&dtype);
+                if !field.has_type(&dtype) {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Cannot assign {} to {} of type {}",
+                        val, assignment.id.value, dtype
+                    )));
+                }
                 assigns.push((ident, field));
             } else {
                 return Err(CrustyError::ValidationError(
@@ -207,17 +357,105 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             assignments: assigns,
         };
         let idx = self.plan.add_node(LogicalOp::Update(update_op));
-        self.plan.add_edge(idx, node.unwrap());
-        // node = Some(idx);
+        self.plan.add_edge(idx, node);
         Ok(())
     }
 
-    /// Helper function to recursively process sqlparser::ast::Query
+    /// Adds a scan of a table, filtered by an optional selection, to the plan and returns the
+    /// index of its top node. Used for the records that an UPDATE or DELETE modifies.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the table.
+    /// * `table_name` - Name of the table.
+    /// * `selection` - Optional WHERE clause over the table.
+    fn process_selection(
+        &mut self,
+        table_id: ContainerId,
+        table_name: &str,
+        selection: &Option<Expr>,
+    ) -> Result<OpIndex, CrustyError> {
+        // Table scan
+        let op = ScanNode {
+            alias: table_name.to_string(),
+            container_id: table_id,
+        };
+        let mut node = Some(self.plan.add_node(LogicalOp::Scan(op)));
+        self.tables.push(table_name.to_string());
+
+        // Selection
+        if let Some(expr) = selection {
+            let predicate = self.process_predicate(expr)?;
+            node = Some(self.add_filter(predicate, node.unwrap())?);
+        }
+        Ok(node.unwrap())
+    }
+
+    /// Adds a filter over a node to the plan and returns its index. The subqueries the
+    /// predicate compares to are children of the filter, added before its input so that the
+    /// input is the first child.
+    ///
+    /// # Arguments
+    ///
+    /// * `predicate` - Predicate to filter by.
+    /// * `input` - Node whose records are filtered.
+    fn add_filter(&mut self, predicate: Predicate, input: OpIndex) -> Result<OpIndex, CrustyError> {
+        let table = Self::predicate_table(&predicate)?;
+        let subqueries: Vec<OpIndex> = predicate.subqueries().iter().map(|s| s.root).collect();
+        let idx = self
+            .plan
+            .add_node(LogicalOp::Filter(FilterNode { table, predicate }));
+        for root in subqueries {
+            self.plan.add_edge(idx, root);
+        }
+        self.plan.add_edge(idx, input);
+        Ok(idx)
+    }
+
+    /// Helper function to recursively process sqlparser::ast::Query. Returns the index of the
+    /// query's top node.
     ///
     /// # Arguments
     ///
     /// * `query` - AST to process.
-    fn process_query(&mut self, query: &sqlparser::ast::Query) -> Result<(), CrustyError> {
+    fn process_query(&mut self, query: &sqlparser::ast::Query) -> Result<OpIndex, CrustyError> {
+        let mut node = self.process_query_body(query)?;
+        if !query.order_by.is_empty() {
+            self.process_order_by(&query.order_by, node)?;
+        }
+        if query.fetch.is_some() {
+            return Err(CrustyError::ValidationError(String::from(
+                "Fetch not supported",
+            )));
+        }
+        if query.limit.is_some() || query.offset.is_some() {
+            let limit = match &query.limit {
+                Some(expr) => Some(Self::row_count(expr)?),
+                None => None,
+            };
+            let offset = match &query.offset {
+                Some(offset) => Self::row_count(&offset.value)?,
+                None => 0,
+            };
+            let idx = self
+                .plan
+                .add_node(LogicalOp::Limit(LimitNode { limit, offset }));
+            self.plan.add_edge(idx, node);
+            node = idx;
+        }
+        Ok(node)
+    }
+
+    /// Helper function of process_query processing the body of a query, without its ORDER BY
+    /// and LIMIT clauses. Returns the index of the body's top node.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - AST to process.
+    fn process_query_body(
+        &mut self,
+        query: &sqlparser::ast::Query,
+    ) -> Result<OpIndex, CrustyError> {
         match &query.body {
             SetExpr::Select(b) => self.process_select(b),
             SetExpr::Query(_) => {
@@ -252,12 +490,137 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         }
     }
 
-    /// Helper function to recursively process sqlparser::ast::Select
+    /// Adds a sort of the rows a projection reads to the plan, so that the projection returns
+    /// them in the order of an ORDER BY clause.
+    ///
+    /// Keys are columns of the projection's input, which may be named by their aliases in
+    /// the projection. The keys of aggregate queries are group by columns and aggregates.
+    ///
+    /// # Arguments
+    ///
+    /// * `order_by` - Keys of the ORDER BY clause.
+    /// * `project` - Projection of the query.
+    fn process_order_by(
+        &mut self,
+        order_by: &[OrderByExpr],
+        project: OpIndex,
+    ) -> Result<(), CrustyError> {
+        let err = CrustyError::ValidationError(String::from("Malformed query plan"));
+        let input = self.plan.edges(project).next().ok_or_else(|| err.clone())?;
+        let projected = match self.plan.get_operator(project) {
+            Some(LogicalOp::Project(ProjectNode {
+                identifiers: ProjectIdentifiers::List(fields),
+            })) => fields.clone(),
+            Some(LogicalOp::Project(_)) => Vec::new(),
+            _ => return Err(err),
+        };
+        let aggregate = match self.plan.get_operator(input) {
+            Some(LogicalOp::Aggregate(aggregate)) => Some(aggregate.clone()),
+            _ => None,
+        };
+
+        let mut fields = Vec::new();
+        for key in order_by {
+            if key.nulls_first.is_some() {
+                return Err(CrustyError::ValidationError(String::from(
+                    "NULLS FIRST and NULLS LAST not supported",
+                )));
+            }
+            // An alias of the projection names the column the projection renames.
+            let alias = match &key.expr {
+                Expr::Identifier(ident) => projected
+                    .iter()
+                    .find(|f| f.alias().unwrap_or_else(|| f.column()) == ident.value),
+                _ => None,
+            };
+            let field = match alias {
+                Some(field) if field.expr().is_none() => {
+                    FieldIdentifier::new(field.table(), field.column())
+                }
+                Some(_) => {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Order by computed columns not supported",
+                    )))
+                }
+                None => self.order_key(&key.expr, aggregate.as_ref())?,
+            };
+            fields.push((field, key.asc.unwrap_or(true)));
+        }
+        let sort = self.plan.add_node(LogicalOp::Sort(SortNode { fields }));
+        self.plan.retarget_edge(project, input, sort);
+        self.plan.add_edge(sort, input);
+        Ok(())
+    }
+
+    /// Returns the column of the input of a query's projection an ORDER BY key refers to.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Key of the ORDER BY clause.
+    /// * `aggregate` - Aggregate the projection reads, if the query aggregates.
+    fn order_key(
+        &self,
+        expr: &Expr,
+        aggregate: Option<&AggregateNode>,
+    ) -> Result<FieldIdentifier, CrustyError> {
+        let field = self.expr_to_ident(expr)?;
+        if field.expr().is_some() {
+            return Err(CrustyError::ValidationError(String::from(
+                "Order by computed columns not supported",
+            )));
+        }
+        let aggregate = match aggregate {
+            Some(aggregate) => aggregate,
+            None => return Ok(FieldIdentifier::new(field.table(), field.column())),
+        };
+        // The aggregate names its group by columns and values by their aliases.
+        let output = match field.agg_op() {
+            Some(op) => aggregate.fields.iter().find(|f| {
+                f.column() == field.column()
+                    && f.agg_op().map(|o| o.to_string()) == Some(op.to_string())
+            }),
+            None => aggregate
+                .group_by
+                .iter()
+                .find(|g| g.column() == field.column()),
+        };
+        match output {
+            Some(f) => Ok(FieldIdentifier::new(
+                f.table(),
+                f.alias().unwrap_or_else(|| f.column()),
+            )),
+            None => Err(CrustyError::ValidationError(format!(
+                "The order by expression '{}' must be selected or part of the group by",
+                expr
+            ))),
+        }
+    }
+
+    /// Returns the number of rows of a LIMIT or OFFSET clause.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Expression of the clause.
+    fn row_count(expr: &Expr) -> Result<usize, CrustyError> {
+        match expr {
+            Expr::Value(Value::Number(n, _)) => n.parse::<usize>().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            CrustyError::ValidationError(format!(
+                "Limit and offset must be non-negative integers, got {}",
+                expr
+            ))
+        })
+    }
+
+    /// Helper function to recursively process sqlparser::ast::Select. Returns the index of the
+    /// projection of the query.
     ///
     /// # Arguments
     ///
     /// * `query` - AST of a select query to process.
-    fn process_select(&mut self, select: &sqlparser::ast::Select) -> Result<(), CrustyError> {
+    fn process_select(&mut self, select: &sqlparser::ast::Select) -> Result<OpIndex, CrustyError> {
         // Pointer to the current node.
         let mut node = None;
 
@@ -286,51 +649,40 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             }
         }
 
-        // Where
+        // Where. Correlated subqueries that can be are rewritten to joins, applied after the
+        // rest of the predicate.
+        let mut decorrelated = Vec::new();
         if let Some(expr) = &select.selection {
-            let predicate = self.process_binary_op(expr)?;
-            // table references in filter
-            let table = match &predicate {
-                Predicate::SimplePredicate(simple_predicate) => {
-                    match (&simple_predicate.left, &simple_predicate.right) {
-                        (PredExpr::Literal(_), PredExpr::Ident(id)) => id.table().to_string(),
-                        (PredExpr::Ident(id), PredExpr::Literal(_)) => id.table().to_string(),
-                        _ => {
-                            return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one identifier and at least one literal are supported")));
-                        }
-                    }
-                }
-                Predicate::CompoundPredicate(compound_predicates) => {
-                    let simple_predicate = &compound_predicates.simple_predicates[0];
-                    let table = match (&simple_predicate.left, &simple_predicate.right) {
-                        (PredExpr::Literal(_), PredExpr::Ident(id)) => id.table(),
-                        (PredExpr::Ident(id), PredExpr::Literal(_)) => id.table(),
-                        _ => {
-                            return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one indentifier and at least one literal are supported")));
-                        }
-                    };
-                    for simple_predicate in &compound_predicates.simple_predicates {
-                        let id = match (&simple_predicate.left, &simple_predicate.right) {
-                            (PredExpr::Literal(_), PredExpr::Ident(id)) => id,
-                            (PredExpr::Ident(id), PredExpr::Literal(_)) => id,
-                            _ => {
-                                return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one indentifier and at least one literal are supported")));
-                            }
-                        };
-                        if id.table() != table {
-                            return Err(CrustyError::ValidationError(String::from(
-                                "Where includes identifiers to columns in multiple tables",
-                            )));
-                        }
+            let mut conjuncts = Vec::new();
+            Self::conjuncts(expr, &mut conjuncts);
+            let mut rest: Option<Expr> = None;
+            for conjunct in conjuncts {
+                match self.decorrelate(conjunct) {
+                    Some(decorrelation) => decorrelated.push(decorrelation),
+                    None => {
+                        rest = Some(match rest {
+                            None => conjunct.clone(),
+                            Some(left) => Expr::BinaryOp {
+                                left: Box::new(left),
+                                op: BinaryOperator::And,
+                                right: Box::new(conjunct.clone()),
+                            },
+                        })
                     }
-                    table.to_string()
                 }
-            };
-
-            let op = FilterNode { table, predicate };
-            let idx = self.plan.add_node(LogicalOp::Filter(op));
-            self.plan.add_edge(idx, node.unwrap());
-            node = Some(idx);
+            }
+            if let Some(expr) = rest {
+                let predicate = self.process_predicate(&expr)?;
+                node = Some(self.add_filter(predicate, node.unwrap())?);
+            }
+        }
+        let wildcard_columns = if decorrelated.is_empty() {
+            None
+        } else {
+            Some(self.table_columns()?)
+        };
+        for decorrelation in decorrelated {
+            node = Some(self.add_decorrelated(decorrelation, node.unwrap())?);
         }
 
         if select.having.is_some() {
@@ -375,20 +727,17 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         }
 
         // Aggregates and group by
-        if has_agg {
+        if has_agg || !select.group_by.is_empty() {
             let mut group_by = Vec::new();
             {
                 let mut group_set = HashSet::new();
                 for expr in &select.group_by {
-                    let col = match expr {
-                        Expr::Identifier(name) => name,
-                        _ => {
-                            return Err(CrustyError::ValidationError(String::from(
-                                "Group by unsupported expression",
-                            )));
-                        }
-                    };
-                    let field = self.disambiguate_name(vec![&col.value])?;
+                    let field = self.expr_to_ident(expr)?;
+                    if field.agg_op().is_some() {
+                        return Err(CrustyError::ValidationError(String::from(
+                            "Group by unsupported expression",
+                        )));
+                    }
                     group_set.insert(field.column().to_string());
                     group_by.push(field);
                 }
@@ -405,30 +754,323 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             }
             let op = AggregateNode {
                 fields: fields.clone(),
-                group_by,
+                group_by: group_by.clone(),
             };
             let idx = self.plan.add_node(LogicalOp::Aggregate(op));
             self.plan.add_edge(idx, node.unwrap());
             node = Some(idx);
 
-            // Replace field column names with aliases to project
+            // Replace field column names with aliases to project. Group by fields are named
+            // by the group by clause in the output of the aggregate.
             fields = fields
                 .iter()
                 .map(|f| {
                     let name = f.alias().unwrap_or_else(|| f.column());
-                    FieldIdentifier::new(f.table(), name)
+                    let group = group_by
+                        .iter()
+                        .find(|g| f.agg_op().is_none() && g.column() == f.column());
+                    match group {
+                        Some(g) => FieldIdentifier::new_column_alias(
+                            f.table(),
+                            g.alias().unwrap_or_else(|| g.column()),
+                            name,
+                        ),
+                        None => FieldIdentifier::new(f.table(), name),
+                    }
                 })
                 .collect();
         }
-        let identifiers = if wildcard {
-            ProjectIdentifiers::Wildcard
-        } else {
-            ProjectIdentifiers::List(fields)
+        // Joins with decorrelated subqueries add columns that a wildcard does not select.
+        let identifiers = match (wildcard, wildcard_columns) {
+            (true, Some(columns)) => ProjectIdentifiers::List(columns),
+            (true, None) => ProjectIdentifiers::Wildcard,
+            (false, _) => ProjectIdentifiers::List(fields),
         };
         let op = ProjectNode { identifiers };
         let idx = self.plan.add_node(LogicalOp::Project(op));
         self.plan.add_edge(idx, node.unwrap());
-        Ok(())
+        Ok(idx)
+    }
+
+    /// Returns the columns of the tables of the query, in order.
+    fn table_columns(&self) -> Result<Vec<FieldIdentifier>, CrustyError> {
+        let mut columns = Vec::new();
+        for table in &self.tables {
+            let table_id = self
+                .catalog
+                .get_table_id(table)
+                .ok_or_else(|| CrustyError::CrustyError("Missing Table".to_string()))?;
+            let schema = self.catalog.get_table_schema(table_id)?;
+            columns.extend(
+                schema
+                    .attributes()
+                    .map(|attr| FieldIdentifier::new(table, &format!("{}.{}", table, attr.name()))),
+            );
+        }
+        Ok(columns)
+    }
+
+    /// Collects the operands of a chain of conjunctions, so that `a AND (b AND c)` has three.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Expression to split.
+    /// * `conjuncts` - Operands collected so far.
+    fn conjuncts<'b>(expr: &'b Expr, conjuncts: &mut Vec<&'b Expr>) {
+        match expr {
+            Expr::Nested(inner) => Self::conjuncts(inner, conjuncts),
+            Expr::BinaryOp {
+                left,
+                op: BinaryOperator::And,
+                right,
+            } => {
+                Self::conjuncts(left, conjuncts);
+                Self::conjuncts(right, conjuncts);
+            }
+            _ => conjuncts.push(expr),
+        }
+    }
+
+    /// Translates a subquery used as a value. The subquery's tables are its own, and the
+    /// columns of the enclosing query it refers to become parameters of its plan.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - AST of the subquery.
+    fn process_subquery(
+        &mut self,
+        query: &sqlparser::ast::Query,
+    ) -> Result<SubqueryExpr, CrustyError> {
+        match &query.body {
+            SetExpr::Select(select)
+                if select.projection.len() == 1
+                    && !matches!(select.projection[0], SelectItem::Wildcard) => {}
+            _ => {
+                return Err(CrustyError::ValidationError(String::from(
+                    "Subqueries used as values must select one column",
+                )))
+            }
+        }
+        let outer = std::mem::replace(&mut self.outer, std::mem::take(&mut self.tables));
+        let correlated = std::mem::take(&mut self.correlated);
+        let root = self.process_query(query);
+        self.tables = std::mem::replace(&mut self.outer, outer);
+        let parameters = std::mem::replace(&mut self.correlated, correlated);
+        Ok(SubqueryExpr {
+            root: root?,
+            parameters,
+        })
+    }
+
+    /// Returns the rewrite of a conjunct of a WHERE clause to a join, if it compares a column
+    /// to a correlated subquery that selects an aggregate other than COUNT of a single table,
+    /// and whose predicate is a conjunction of comparisons to literals and one equality of
+    /// a column to a column of the enclosing query.
+    ///
+    /// The subquery is NULL for records without a matching group, and comparisons to NULL
+    /// are false, so the inner join keeps the same records. Other conjuncts are translated
+    /// as filters, running the subquery for each record.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Conjunct of the WHERE clause.
+    fn decorrelate(&mut self, expr: &Expr) -> Option<Decorrelation> {
+        let (column, op, query) = match expr {
+            Expr::BinaryOp { left, op, right } => {
+                let op = Self::binary_op_to_simple_predicate_op(op).ok()?;
+                match (left.as_ref(), right.as_ref()) {
+                    (column, Expr::Subquery(query)) => (column, op, query),
+                    (Expr::Subquery(query), column)
+                        if !matches!(op, SimplePredicateOp::Like | SimplePredicateOp::NotLike) =>
+                    {
+                        (column, op.flip(), query)
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if !matches!(column, Expr::Identifier(_) | Expr::CompoundIdentifier(_)) {
+            return None;
+        }
+        let column = self.expr_to_ident(column).ok()?;
+        let select = match &query.body {
+            // A limit could drop the subquery's single row.
+            SetExpr::Select(_) if query.limit.is_some() || query.offset.is_some() => return None,
+            SetExpr::Select(select) => select,
+            _ => return None,
+        };
+        if select.distinct
+            || select.having.is_some()
+            || !select.group_by.is_empty()
+            || select.from.len() != 1
+            || !select.from[0].joins.is_empty()
+            || select.projection.len() != 1
+        {
+            return None;
+        }
+        let table = match &select.from[0].relation {
+            TableFactor::Table { name, .. } => Self::get_table_name(name).ok()?,
+            _ => return None,
+        };
+        // Columns of a table read by both queries would have the same names.
+        if self.tables.contains(&table) {
+            return None;
+        }
+        let container_id = self.catalog.get_table_id(&table)?;
+        let aggregate = match &select.projection[0] {
+            SelectItem::UnnamedExpr(expr) | SelectItem::ExprWithAlias { expr, .. } => expr,
+            _ => return None,
+        };
+        let mut conjuncts = Vec::new();
+        Self::conjuncts(select.selection.as_ref()?, &mut conjuncts);
+
+        let outer = std::mem::replace(&mut self.outer, std::mem::take(&mut self.tables));
+        let correlated = std::mem::take(&mut self.correlated);
+        self.tables = vec![table.clone()];
+        let parts = self.decorrelated_parts(aggregate, &conjuncts);
+        self.tables = std::mem::replace(&mut self.outer, outer);
+        self.correlated = correlated;
+
+        let (aggregate, filter, key, outer_key) = parts?;
+        Some(Decorrelation {
+            column,
+            op,
+            table,
+            container_id,
+            filter,
+            key,
+            outer_key,
+            aggregate,
+        })
+    }
+
+    /// Translates the aggregate and the predicate of a subquery being decorrelated, in the
+    /// scope of the subquery. Returns the aggregate, the predicates not referring to the
+    /// enclosing query, and the pair of columns equal between the subquery and the enclosing
+    /// query, or None if the subquery cannot be decorrelated.
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregate` - Expression the subquery selects.
+    /// * `conjuncts` - Conjuncts of the subquery's predicate.
+    #[allow(clippy::type_complexity)]
+    fn decorrelated_parts(
+        &mut self,
+        aggregate: &Expr,
+        conjuncts: &[&Expr],
+    ) -> Option<(
+        FieldIdentifier,
+        Option<Predicate>,
+        FieldIdentifier,
+        FieldIdentifier,
+    )> {
+        let aggregate = self.expr_to_ident(aggregate).ok()?;
+        if matches!(aggregate.agg_op(), None | Some(AggOp::Count)) {
+            return None;
+        }
+        let mut local = Vec::new();
+        let mut key = None;
+        for conjunct in conjuncts {
+            let operands_are_simple = match conjunct {
+                Expr::BinaryOp { left, right, .. } => [left, right].iter().all(|e| {
+                    matches!(
+                        e.as_ref(),
+                        Expr::Identifier(_)
+                            | Expr::CompoundIdentifier(_)
+                            | Expr::Value(_)
+                            | Expr::TypedString { .. }
+                    )
+                }),
+                _ => false,
+            };
+            if !operands_are_simple {
+                return None;
+            }
+            let predicate = self.process_simple_predicate(conjunct).ok()?;
+            match (&predicate.left, predicate.op, &predicate.right) {
+                (PredExpr::Ident(k), SimplePredicateOp::Equals, PredExpr::Parameter(i))
+                | (PredExpr::Parameter(i), SimplePredicateOp::Equals, PredExpr::Ident(k))
+                    if key.is_none() =>
+                {
+                    key = Some((k.clone(), self.correlated[*i].clone()));
+                }
+                (PredExpr::Parameter(_), _, _) | (_, _, PredExpr::Parameter(_)) => return None,
+                _ => local.push(predicate),
+            }
+        }
+        let (key, outer_key) = key?;
+        let filter = match local.len() {
+            0 => None,
+            1 => Some(Predicate::SimplePredicate(local.pop().unwrap())),
+            _ => Some(Predicate::CompoundPredicate(CompoundPredicate {
+                op: CompoundPredicateOp::And,
+                simple_predicates: local,
+            })),
+        };
+        if let Some(predicate) = &filter {
+            Self::predicate_table(predicate).ok()?;
+        }
+        // The aggregate names its group by column as written, so the join can find it.
+        let key = FieldIdentifier::new(key.table(), key.column());
+        Some((aggregate, filter, key, outer_key))
+    }
+
+    /// Adds the join of a decorrelated subquery to the plan over the records of the
+    /// enclosing query, and the comparison of the column to the subquery's aggregate, and
+    /// returns the index of the comparison.
+    ///
+    /// # Arguments
+    ///
+    /// * `decorrelation` - Rewrite of the subquery.
+    /// * `input` - Node producing the records of the enclosing query.
+    fn add_decorrelated(
+        &mut self,
+        decorrelation: Decorrelation,
+        input: OpIndex,
+    ) -> Result<OpIndex, CrustyError> {
+        let Decorrelation {
+            column,
+            op,
+            table,
+            container_id,
+            filter,
+            key,
+            outer_key,
+            aggregate,
+        } = decorrelation;
+        let mut node = self.plan.add_node(LogicalOp::Scan(ScanNode {
+            alias: table.clone(),
+            container_id,
+        }));
+        if let Some(predicate) = filter {
+            node = self.add_filter(predicate, node)?;
+        }
+        let value = FieldIdentifier::new(&table, aggregate.alias().unwrap_or(aggregate.column()));
+        let agg = self.plan.add_node(LogicalOp::Aggregate(AggregateNode {
+            fields: vec![aggregate],
+            group_by: vec![key.clone()],
+        }));
+        self.plan.add_edge(agg, node);
+        let join = self.plan.add_node(LogicalOp::Join(JoinNode {
+            left: outer_key,
+            right: key,
+            op: SimplePredicateOp::Equals,
+            left_table: self.get_table_alias_from_op(input),
+            right_table: None,
+        }));
+        self.plan.add_edge(join, agg);
+        self.plan.add_edge(join, input);
+        let filter = self.plan.add_node(LogicalOp::Filter(FilterNode {
+            table: column.table().to_string(),
+            predicate: Predicate::SimplePredicate(SimplePredicate {
+                left: PredExpr::Ident(column),
+                op,
+                right: PredExpr::Ident(value),
+            }),
+        }));
+        self.plan.add_edge(filter, join);
+        Ok(filter)
     }
 
     /// Creates a corresponding LogicalOp, adds it to self.plan, and returns the OpIndex.
@@ -444,7 +1086,7 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
     ) -> Result<OpIndex, CrustyError> {
         match tf {
             TableFactor::Table { name, .. } => {
-                let name = get_name(name)?;
+                let name = Self::get_table_name(name)?;
                 let table_id = self
                     .catalog
                     .get_table_id(&name)
@@ -467,6 +1109,28 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         }
     }
 
+    /// Returns the name of a table in a from or join clause.
+    ///
+    /// A name qualified by the alias of an attached database is returned as `alias.table`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Table name to convert.
+    fn get_table_name(name: &ObjectName) -> Result<String, CrustyError> {
+        match name.0.len() {
+            1 | 2 => Ok(name
+                .0
+                .iter()
+                .map(|ident| ident.value.as_str())
+                .collect::<Vec<_>>()
+                .join(".")),
+            _ => Err(CrustyError::ValidationError(format!(
+                "Table names may have at most one . qualifier: {}",
+                name
+            ))),
+        }
+    }
+
     /// Returns the name of the table from the node, if the node is a table level operator, like scan. Otherwise, return none.
     ///
     /// # Arguments
@@ -533,60 +1197,135 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             "Unsupported join type",
         )))
     }
-    /// Parses an expression to a predicate node.
+    /// Parses an expression to a predicate node. Conjunctions and disjunctions of simple
+    /// predicates are flattened into a compound predicate, and other combinations, such as
+    /// mixed ANDs and ORs or negations, are kept as a tree. A bare boolean column is a
+    /// predicate that the column is true.
     ///
     /// # Arguments
     ///
     /// * `expr` - Expression to parse.
-    fn process_binary_op(&self, expr: &Expr) -> Result<Predicate, CrustyError> {
+    fn process_predicate(&mut self, expr: &Expr) -> Result<Predicate, CrustyError> {
         match expr {
+            Expr::Nested(expr) => self.process_predicate(expr),
+            Expr::UnaryOp {
+                op: UnaryOperator::Not,
+                expr,
+            } => Ok(Predicate::Not(Box::new(self.process_predicate(expr)?))),
             Expr::BinaryOp { op, .. } => match Self::binary_op_to_predicate_op(op)? {
                 PredicateOp::SimplePredicateOp(_) => Ok(Predicate::SimplePredicate(
                     self.process_simple_predicate(expr)?,
                 )),
-                PredicateOp::CompoundPredicateOp(_) => Ok(Predicate::CompoundPredicate(
-                    self.process_compound_predicate(expr)?,
-                )),
+                PredicateOp::CompoundPredicateOp(compound_op) => {
+                    let mut predicates = Vec::new();
+                    self.process_compound_predicate_helper(expr, &compound_op, &mut predicates)?;
+                    if predicates
+                        .iter()
+                        .all(|p| matches!(p, Predicate::SimplePredicate(_)))
+                    {
+                        let simple_predicates = predicates
+                            .into_iter()
+                            .filter_map(|p| match p {
+                                Predicate::SimplePredicate(simple_predicate) => {
+                                    Some(simple_predicate)
+                                }
+                                _ => None,
+                            })
+                            .collect();
+                        Ok(Predicate::CompoundPredicate(CompoundPredicate {
+                            op: compound_op,
+                            simple_predicates,
+                        }))
+                    } else {
+                        Ok(Predicate::NestedPredicate(NestedPredicate {
+                            op: compound_op,
+                            predicates,
+                        }))
+                    }
+                }
             },
-            _ => Err(CrustyError::ValidationError(String::from(
-                "Expected binary operation",
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                let ident = self.expr_to_ident(expr)?;
+                if self.ident_dtype(&ident) != Some(DataType::Boolean) {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Expected a predicate or boolean column, got {}",
+                        expr
+                    )));
+                }
+                Ok(Predicate::SimplePredicate(SimplePredicate {
+                    left: PredExpr::Ident(ident),
+                    op: SimplePredicateOp::Equals,
+                    right: PredExpr::Literal(Field::BoolField(true)),
+                }))
+            }
+            _ => Err(CrustyError::ValidationError(format!(
+                "Expected predicate, got {}",
+                expr
             ))),
         }
     }
 
-    /// Parses an expression to a simple predicate.
+    /// Returns the table a filter predicate reads. Each simple predicate must compare a column
+    /// to a literal, a parameter or a subquery, and all columns must be of the same table.
     ///
     /// # Arguments
     ///
-    /// * `expr` - Expression to parse.
-    fn process_simple_predicate(&self, expr: &Expr) -> Result<SimplePredicate, CrustyError> {
-        match expr {
-            Expr::BinaryOp { left, op, right } => Ok(SimplePredicate {
-                left: self.expr_to_pred_expr(left)?,
-                right: self.expr_to_pred_expr(right)?,
-                op: Self::binary_op_to_simple_predicate_op(op)?,
-            }),
-            _ => Err(CrustyError::ValidationError(String::from(
-                "Expected binary operation",
-            ))),
+    /// * `predicate` - Predicate of the filter.
+    fn predicate_table(predicate: &Predicate) -> Result<String, CrustyError> {
+        let mut table = None;
+        for simple_predicate in predicate.simple_predicates() {
+            let id = match (&simple_predicate.left, &simple_predicate.right) {
+                (
+                    PredExpr::Literal(_) | PredExpr::Parameter(_) | PredExpr::Subquery(_),
+                    PredExpr::Ident(id),
+                ) => id,
+                (
+                    PredExpr::Ident(id),
+                    PredExpr::Literal(_) | PredExpr::Parameter(_) | PredExpr::Subquery(_),
+                ) => id,
+                _ => {
+                    return Err(CrustyError::ValidationError(String::from("Only where predicates with at least one identifier and at least one literal are supported")));
+                }
+            };
+            match table {
+                None => table = Some(id.table()),
+                Some(table) if table != id.table() => {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Where includes identifiers to columns in multiple tables",
+                    )));
+                }
+                _ => {}
+            }
         }
+        table
+            .map(|table| table.to_string())
+            .ok_or_else(|| CrustyError::ValidationError(String::from("Empty where predicate")))
     }
 
-    /// Parses an expression to a compound predicate.
+    /// Parses an expression to a simple predicate. `LIKE` must match a string column on
+    /// the left against a pattern on the right.
     ///
     /// # Arguments
     ///
     /// * `expr` - Expression to parse.
-    fn process_compound_predicate(&self, expr: &Expr) -> Result<CompoundPredicate, CrustyError> {
+    fn process_simple_predicate(&mut self, expr: &Expr) -> Result<SimplePredicate, CrustyError> {
         match expr {
-            Expr::BinaryOp { op, .. } => {
-                let compound_predicate_op = Self::binary_op_to_compound_predicate_op(op)?;
-                let simple_predicates =
-                    self.process_compound_predicate_helper(expr, compound_predicate_op.clone())?;
-                Ok(CompoundPredicate {
-                    op: compound_predicate_op,
-                    simple_predicates,
-                })
+            Expr::BinaryOp { left, op, right } => {
+                let op = Self::binary_op_to_simple_predicate_op(op)?;
+                let mut left = self.expr_to_pred_expr(left)?;
+                let mut right = self.expr_to_pred_expr(right)?;
+                if matches!(op, SimplePredicateOp::Like | SimplePredicateOp::NotLike) {
+                    let dtype = left.ident().and_then(|ident| self.ident_dtype(ident));
+                    if left.ident().is_none() || dtype.is_some_and(|d| d != DataType::String) {
+                        return Err(CrustyError::ValidationError(format!(
+                            "{} expects a string column on the left, got {}",
+                            op, expr
+                        )));
+                    }
+                }
+                self.coerce_literal(&left, &mut right)?;
+                self.coerce_literal(&right, &mut left)?;
+                Ok(SimplePredicate { left, right, op })
             }
             _ => Err(CrustyError::ValidationError(String::from(
                 "Expected binary operation",
@@ -594,66 +1333,121 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         }
     }
 
-    /// Recursive helper function for parsing compound predicates.
+    /// Recursive helper function for parsing compound predicates. Collects the operands of a
+    /// chain of the same compound operator, so that `a AND (b AND c)` has three operands.
     ///
     /// # Arguments
     ///
     /// * `expr` - Expression to parse
-    /// * `test_compound_op` - the compound operator that has been used so far in the parsing. If expr is a compound predicate, it must use the same compount operator.
+    /// * `compound_op` - The compound operator of the chain.
+    /// * `predicates` - Operands of the chain parsed so far.
     fn process_compound_predicate_helper(
-        &self,
+        &mut self,
         expr: &Expr,
-        test_compound_op: CompoundPredicateOp,
-    ) -> Result<Vec<SimplePredicate>, CrustyError> {
+        compound_op: &CompoundPredicateOp,
+        predicates: &mut Vec<Predicate>,
+    ) -> Result<(), CrustyError> {
         match expr {
-            Expr::BinaryOp { left, op, right } => match Self::binary_op_to_predicate_op(op)? {
-                PredicateOp::SimplePredicateOp(_) => Ok(vec![self.process_simple_predicate(expr)?]),
-                PredicateOp::CompoundPredicateOp(compound_op) => {
-                    if compound_op == test_compound_op {
-                        let mut res =
-                            self.process_compound_predicate_helper(left, test_compound_op.clone())?;
-                        res.append(
-                            &mut self.process_compound_predicate_helper(right, test_compound_op)?,
-                        );
-
-                        Ok(res)
-                    } else {
-                        Err(CrustyError::ValidationError(String::from(
-                            "Cannot make a compound predicate with ors and ands",
-                        )))
-                    }
-                }
-            },
-            _ => Err(CrustyError::ValidationError(String::from(
-                "Expected binary operation",
-            ))),
+            Expr::Nested(inner) => {
+                self.process_compound_predicate_helper(inner, compound_op, predicates)
+            }
+            Expr::BinaryOp { left, op, right }
+                if Self::binary_op_to_compound_predicate_op(op).as_ref() == Ok(compound_op) =>
+            {
+                self.process_compound_predicate_helper(left, compound_op, predicates)?;
+                self.process_compound_predicate_helper(right, compound_op, predicates)
+            }
+            _ => {
+                predicates.push(self.process_predicate(expr)?);
+                Ok(())
+            }
         }
     }
 
-    /// Parses the non-operator parts of the expression to predicate expressions.
+    /// Parses the non-operator parts of the expression to predicate expressions. In a
+    /// subquery, columns of the enclosing query are parameters of the subquery.
     ///
     /// # Arguments
     ///
     /// * `expr` - Non-operator part of the expression to parse.
-    fn expr_to_pred_expr(&self, expr: &Expr) -> Result<PredExpr, CrustyError> {
+    fn expr_to_pred_expr(&mut self, expr: &Expr) -> Result<PredExpr, CrustyError> {
+        if let Some(field) = negative_number(expr) {
+            return Ok(PredExpr::Literal(field));
+        }
         match expr {
-            Expr::Value(val) => match val {
-                Value::Number(s, _) => {
-                    let i = s.parse::<i32>().map_err(|_| {
-                        CrustyError::ValidationError(format!("Unsupported literal {}", s))
-                    })?;
-                    let f = Field::IntField(i);
-                    Ok(PredExpr::Literal(f))
+            Expr::Value(val) => Ok(PredExpr::Literal(Self::value_to_field(val)?)),
+            Expr::TypedString { data_type, value } => {
+                Ok(PredExpr::Literal(parse_typed_string(data_type, value)?))
+            }
+            Expr::Subquery(query) => Ok(PredExpr::Subquery(self.process_subquery(query)?)),
+            Expr::Cast {
+                expr: value,
+                data_type,
+            } if matches!(value.as_ref(), Expr::Value(_) | Expr::TypedString { .. }) => {
+                // Casts of literals are folded, so a bad literal fails before the query runs.
+                match self.expr_to_pred_expr(value)? {
+                    PredExpr::Literal(field) => {
+                        Ok(PredExpr::Literal(field.cast(&get_attr(data_type)?)?))
+                    }
+                    _ => unreachable!("literals translate to literals"),
                 }
-                Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => {
-                    let f = Field::StringField(s.to_string());
-                    Ok(PredExpr::Literal(f))
+            }
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) if !self.outer.is_empty() => {
+                let ident = match self.expr_to_ident(expr) {
+                    Ok(ident) => ident,
+                    Err(e) => {
+                        let tables = std::mem::replace(&mut self.tables, self.outer.clone());
+                        let outer = self.expr_to_ident(expr);
+                        self.tables = tables;
+                        outer.map_err(|_| e)?
+                    }
+                };
+                let table = ident.table().to_string();
+                if self.tables.contains(&table) || !self.outer.contains(&table) {
+                    return Ok(PredExpr::Ident(ident));
                 }
-                _ => Err(CrustyError::ValidationError(String::from(
-                    "Unsupported literal in predicate",
-                ))),
-            },
-            _ => Ok(PredExpr::Ident(self.expr_to_ident(expr)?)),
+                let index = match self
+                    .correlated
+                    .iter()
+                    .position(|c| c.table() == ident.table() && c.column() == ident.column())
+                {
+                    Some(index) => index,
+                    None => {
+                        self.correlated.push(ident);
+                        self.correlated.len() - 1
+                    }
+                };
+                Ok(PredExpr::Parameter(index))
+            }
+            _ => {
+                let ident = self.expr_to_ident(expr)?;
+                if ident.expr().is_some() {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Computed expressions are not supported in predicates, got {}",
+                        expr
+                    )));
+                }
+                Ok(PredExpr::Ident(ident))
+            }
+        }
+    }
+
+    /// Converts a literal value to a field.
+    ///
+    /// # Arguments
+    ///
+    /// * `val` - Value to convert.
+    fn value_to_field(val: &Value) -> Result<Field, CrustyError> {
+        match val {
+            Value::Number(s, _) => Field::from_number(s)
+                .ok_or_else(|| CrustyError::ValidationError(format!("Unsupported literal {}", s))),
+            Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => {
+                Ok(Field::StringField(s.to_string()))
+            }
+            Value::Boolean(b) => Ok(Field::BoolField(*b)),
+            _ => Err(CrustyError::ValidationError(String::from(
+                "Unsupported literal in predicate",
+            ))),
         }
     }
 
@@ -676,6 +1470,10 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             )),
             BinaryOperator::Eq => Ok(PredicateOp::SimplePredicateOp(SimplePredicateOp::Equals)),
             BinaryOperator::NotEq => Ok(PredicateOp::SimplePredicateOp(SimplePredicateOp::NotEq)),
+            BinaryOperator::Like => Ok(PredicateOp::SimplePredicateOp(SimplePredicateOp::Like)),
+            BinaryOperator::NotLike => {
+                Ok(PredicateOp::SimplePredicateOp(SimplePredicateOp::NotLike))
+            }
 
             BinaryOperator::And => Ok(PredicateOp::CompoundPredicateOp(CompoundPredicateOp::And)),
             BinaryOperator::Or => Ok(PredicateOp::CompoundPredicateOp(CompoundPredicateOp::Or)),
@@ -702,6 +1500,8 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
             BinaryOperator::LtEq => Ok(SimplePredicateOp::LessThanOrEq),
             BinaryOperator::Eq => Ok(SimplePredicateOp::Equals),
             BinaryOperator::NotEq => Ok(SimplePredicateOp::NotEq),
+            BinaryOperator::Like => Ok(SimplePredicateOp::Like),
+            BinaryOperator::NotLike => Ok(SimplePredicateOp::NotLike),
             _ => Err(CrustyError::ValidationError(format!(
                 "Expected simple predicate op, got {}",
                 op,
@@ -737,6 +1537,17 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
     ///
     /// * `field` - Field to be aggregated.
     fn validate_aggregate(&self, field: &FieldIdentifier) -> Result<(), CrustyError> {
+        if let (Some(expr), Some(op)) = (field.expr(), field.agg_op()) {
+            return match op {
+                AggOp::Avg | AggOp::Sum if !self.is_numeric(expr) => {
+                    Err(CrustyError::ValidationError(format!(
+                        "Cannot perform operation {} on expression {}",
+                        op, expr,
+                    )))
+                }
+                _ => Ok(()),
+            };
+        }
         let split_field: Vec<&str> = field.column().split('.').collect();
         if field.agg_op().is_none() || split_field.len() != 2 {
             return Ok(());
@@ -752,12 +1563,16 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
 
         let schema = self.catalog.get_table_schema(table_id)?;
         let attr = schema
-            .get_attribute(*schema.get_field_index(col_name).unwrap())
-            .unwrap();
+            .get_field_index(col_name)
+            .and_then(|i| schema.get_attribute(*i))
+            .ok_or_else(|| {
+                CrustyError::ValidationError(format!("Unknown column {}", field.column()))
+            })?;
 
         match attr.dtype() {
-            DataType::Int => Ok(()),
-            DataType::String => match op {
+            DataType::Int | DataType::Float | DataType::Decimal(_, _) => Ok(()),
+            DataType::String | DataType::Date | DataType::Timestamp | DataType::Boolean => match op
+            {
                 AggOp::Count | AggOp::Max | AggOp::Min => Ok(()),
                 _ => Err(CrustyError::ValidationError(format!(
                     "Cannot perform operation {} on field {}",
@@ -767,6 +1582,169 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
         }
     }
 
+    /// Returns true if an expression computes numbers, so that it can be summed and averaged.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Expression to check.
+    fn is_numeric(&self, expr: &ScalarExpr) -> bool {
+        match expr {
+            ScalarExpr::Column(ident) => matches!(
+                self.ident_dtype(ident),
+                Some(DataType::Int | DataType::Float | DataType::Decimal(_, _))
+            ),
+            ScalarExpr::Literal(field) => matches!(
+                field,
+                Field::IntField(_) | Field::FloatField(_) | Field::DecimalField(_)
+            ),
+            ScalarExpr::Arithmetic(..) => true,
+            ScalarExpr::Function(function, _) => *function == ScalarFunction::Length,
+            ScalarExpr::Compare(..) => false,
+            ScalarExpr::Case(branches, otherwise) => branches
+                .iter()
+                .map(|b| &b.result)
+                .chain(otherwise.as_deref())
+                .all(|e| self.is_numeric(e)),
+            ScalarExpr::Cast(_, dtype) => matches!(
+                dtype,
+                DataType::Int | DataType::Float | DataType::Decimal(_, _)
+            ),
+        }
+    }
+
+    /// Returns the type of the column a field identifier refers to, if it is a column of a
+    /// table in the catalog.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - Field identifier to look up.
+    fn ident_dtype(&self, ident: &FieldIdentifier) -> Option<DataType> {
+        let table_id = self.catalog.get_table_id(ident.table())?;
+        let schema = self.catalog.get_table_schema(table_id).ok()?;
+        let column = ident.column().rsplit('.').next()?;
+        let attr = schema.get_attribute(*schema.get_field_index(column)?)?;
+        Some(attr.dtype().clone())
+    }
+
+    /// Returns the type of the values of a selected column, if it is a column of a table, an
+    /// aggregate of one, a literal or a cast.
+    ///
+    /// # Arguments
+    ///
+    /// * `ident` - Selected column.
+    fn selected_dtype(&self, ident: &FieldIdentifier) -> Option<DataType> {
+        let dtype = match ident.expr() {
+            Some(ScalarExpr::Column(column)) => self.ident_dtype(column),
+            Some(ScalarExpr::Literal(field)) => match field {
+                Field::IntField(_) => Some(DataType::Int),
+                Field::StringField(_) => Some(DataType::String),
+                Field::FloatField(_) => Some(DataType::Float),
+                Field::DecimalField(d) => Some(DataType::Decimal(MAX_PRECISION, d.scale)),
+                Field::DateField(_) => Some(DataType::Date),
+                Field::TimestampField(_) => Some(DataType::Timestamp),
+                Field::BoolField(_) => Some(DataType::Boolean),
+                Field::Null => None,
+            },
+            Some(ScalarExpr::Cast(_, dtype)) => Some(dtype.clone()),
+            Some(_) => None,
+            None if matches!(ident.agg_op(), Some(AggOp::Count)) => Some(DataType::Int),
+            None => self.ident_dtype(ident),
+        }?;
+        Some(match ident.agg_op() {
+            Some(op) => output_dtype(op, &dtype),
+            None => dtype,
+        })
+    }
+
+    /// Checks that the query of an INSERT selects as many columns as the table has, and that
+    /// the columns whose types are known can be stored in the columns of the table.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the table to insert into.
+    /// * `root` - Root of the plan of the query.
+    fn validate_insert_source(
+        &self,
+        table_id: ContainerId,
+        root: OpIndex,
+    ) -> Result<(), CrustyError> {
+        let mut node = root;
+        while let Some(LogicalOp::Limit(_)) = self.plan.get_operator(node) {
+            node =
+                self.plan.edges(node).next().ok_or_else(|| {
+                    CrustyError::ValidationError(String::from("Limit has no input"))
+                })?;
+        }
+        let columns = match self.plan.get_operator(node) {
+            Some(LogicalOp::Project(ProjectNode {
+                identifiers: ProjectIdentifiers::Wildcard,
+            })) => self.table_columns()?,
+            Some(LogicalOp::Project(ProjectNode {
+                identifiers: ProjectIdentifiers::List(columns),
+            })) => {
+                // Aggregated columns are renamed by the aggregate; its fields keep the
+                // aggregate operations, in the same order.
+                match self
+                    .plan
+                    .edges(node)
+                    .next()
+                    .and_then(|child| self.plan.get_operator(child))
+                {
+                    Some(LogicalOp::Aggregate(aggregate)) => aggregate.fields.clone(),
+                    _ => columns.clone(),
+                }
+            }
+            _ => return Ok(()),
+        };
+        let schema = self.catalog.get_table_schema(table_id)?;
+        if columns.len() != schema.size() {
+            return Err(CrustyError::ValidationError(format!(
+                "Insert query selects {} columns, but the table has {}",
+                columns.len(),
+                schema.size()
+            )));
+        }
+        for (column, attr) in columns.iter().zip(schema.attributes()) {
+            if let Some(dtype) = self.selected_dtype(column) {
+                if !dtype.coerces_to(attr.dtype()) {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Cannot insert {} of type {} into column {} of type {}",
+                        column,
+                        dtype,
+                        attr.name(),
+                        attr.dtype()
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Converts a literal compared to a column to the type of the column, so that integer
+    /// literals can be compared to float columns. Fails if the literal cannot be converted.
+    ///
+    /// # Arguments
+    ///
+    /// * `column` - Side of the predicate that may be a column.
+    /// * `literal` - Side of the predicate that may be a literal.
+    fn coerce_literal(&self, column: &PredExpr, literal: &mut PredExpr) -> Result<(), CrustyError> {
+        if let (PredExpr::Ident(ident), PredExpr::Literal(field)) = (column, &literal) {
+            if let Some(dtype) = self.ident_dtype(ident) {
+                let coerced = field.clone().coerce(&dtype);
+                if !coerced.has_type(&dtype) {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Cannot compare {} of type {} to {}",
+                        ident.column(),
+                        dtype,
+                        field
+                    )));
+                }
+                *literal = PredExpr::Literal(coerced);
+            }
+        }
+        Ok(())
+    }
+
     /// Converts a sqparser::ast::Expr to a LogicalOp::FieldIdent.
     ///
     /// # Arguments
@@ -785,11 +1763,7 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
                     "MAX" => AggOp::Max,
                     "MIN" => AggOp::Min,
                     "SUM" => AggOp::Sum,
-                    _ => {
-                        return Err(CrustyError::ValidationError(String::from(
-                            "Unsupported SQL function",
-                        )));
-                    }
+                    _ => return Ok(FieldIdentifier::new_expr(self.expr_to_scalar(expr)?)),
                 };
                 if args.is_empty() || args.len() > 1 {
                     return Err(CrustyError::ValidationError(format!(
@@ -803,20 +1777,159 @@ impl<'a, T: 'a + Catalog> TranslateAndValidate<'a, T> {
                 };
                 let mut field = match arg {
                     Expr::Identifier(_) | Expr::CompoundIdentifier(_) => self.expr_to_ident(arg)?,
-                    _ => {
-                        return Err(CrustyError::ValidationError(String::from(
+                    _ => FieldIdentifier::new_expr(self.expr_to_scalar(arg).map_err(|_| {
+                        CrustyError::ValidationError(String::from(
                             "Aggregate over unsupported expression",
-                        )));
-                    }
+                        ))
+                    })?),
                 };
                 field.set_op(op);
                 field.default_alias();
                 self.validate_aggregate(&field)?;
                 Ok(field)
             }
-            _ => Err(CrustyError::ValidationError(String::from(
-                "Unsupported expression",
-            ))),
+            _ => Ok(FieldIdentifier::new_expr(self.expr_to_scalar(expr)?)),
+        }
+    }
+
+    /// Converts a sqlparser::ast::Expr to a scalar expression computed from columns.
+    ///
+    /// # Arguments
+    ///
+    /// * `expr` - Expression to be converted.
+    fn expr_to_scalar(&self, expr: &Expr) -> Result<ScalarExpr, CrustyError> {
+        let unsupported =
+            || CrustyError::ValidationError(format!("Unsupported expression {}", expr));
+        let boxed = |e: &Expr| self.expr_to_scalar(e).map(Box::new);
+        match expr {
+            Expr::Identifier(_) | Expr::CompoundIdentifier(_) => {
+                Ok(ScalarExpr::Column(self.expr_to_ident(expr)?))
+            }
+            Expr::Value(val) => Ok(ScalarExpr::Literal(Self::value_to_field(val)?)),
+            Expr::TypedString { data_type, value } => {
+                Ok(ScalarExpr::Literal(parse_typed_string(data_type, value)?))
+            }
+            Expr::Nested(expr) => self.expr_to_scalar(expr),
+            Expr::UnaryOp {
+                op: UnaryOperator::Minus,
+                expr,
+            } => Ok(ScalarExpr::Arithmetic(
+                Box::new(ScalarExpr::Literal(Field::IntField(0))),
+                ArithmeticOp::Subtract,
+                boxed(expr)?,
+            )),
+            Expr::BinaryOp { left, op, right } => {
+                let op = match op {
+                    BinaryOperator::Plus => ArithmeticOp::Add,
+                    BinaryOperator::Minus => ArithmeticOp::Subtract,
+                    BinaryOperator::Multiply => ArithmeticOp::Multiply,
+                    BinaryOperator::Divide => ArithmeticOp::Divide,
+                    BinaryOperator::Modulus => ArithmeticOp::Modulo,
+                    BinaryOperator::Gt
+                    | BinaryOperator::Lt
+                    | BinaryOperator::GtEq
+                    | BinaryOperator::LtEq
+                    | BinaryOperator::Eq
+                    | BinaryOperator::NotEq
+                    | BinaryOperator::Like
+                    | BinaryOperator::NotLike => {
+                        return Ok(ScalarExpr::Compare(
+                            boxed(left)?,
+                            Self::binary_op_to_simple_predicate_op(op)?,
+                            boxed(right)?,
+                        ))
+                    }
+                    BinaryOperator::StringConcat => {
+                        return Ok(ScalarExpr::Function(
+                            ScalarFunction::Concat,
+                            vec![self.expr_to_scalar(left)?, self.expr_to_scalar(right)?],
+                        ))
+                    }
+                    _ => return Err(unsupported()),
+                };
+                Ok(ScalarExpr::Arithmetic(boxed(left)?, op, boxed(right)?))
+            }
+            Expr::Function(Function { name, args, .. }) => {
+                let (function, arity) = match &get_name(name)?.to_uppercase()[..] {
+                    "UPPER" => (ScalarFunction::Upper, 1..=1),
+                    "LOWER" => (ScalarFunction::Lower, 1..=1),
+                    "LENGTH" => (ScalarFunction::Length, 1..=1),
+                    "CONCAT" => (ScalarFunction::Concat, 1..=usize::MAX),
+                    "SUBSTRING" | "SUBSTR" => (ScalarFunction::Substring, 2..=3),
+                    "TRIM" => (ScalarFunction::Trim, 1..=1),
+                    _ => {
+                        return Err(CrustyError::ValidationError(String::from(
+                            "Unsupported SQL function",
+                        )));
+                    }
+                };
+                if !arity.contains(&args.len()) {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Wrong number of args in {} operation",
+                        name
+                    )));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        FunctionArg::Named { name: _, arg } => self.expr_to_scalar(arg),
+                        FunctionArg::Unnamed(arg) => self.expr_to_scalar(arg),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(ScalarExpr::Function(function, args))
+            }
+            Expr::Substring {
+                expr,
+                substring_from,
+                substring_for,
+            } => {
+                let mut args = vec![self.expr_to_scalar(expr)?];
+                args.push(match substring_from {
+                    Some(from) => self.expr_to_scalar(from)?,
+                    None => ScalarExpr::Literal(Field::IntField(1)),
+                });
+                if let Some(length) = substring_for {
+                    args.push(self.expr_to_scalar(length)?);
+                }
+                Ok(ScalarExpr::Function(ScalarFunction::Substring, args))
+            }
+            Expr::Case {
+                operand,
+                conditions,
+                results,
+                else_result,
+            } => {
+                let mut branches = Vec::new();
+                for (condition, result) in conditions.iter().zip(results) {
+                    // `CASE x WHEN v` compares the operand to each value.
+                    let (left, op, right) = match (operand, condition) {
+                        (Some(operand), value) => {
+                            (operand.as_ref(), SimplePredicateOp::Equals, value)
+                        }
+                        (None, Expr::BinaryOp { left, op, right }) => (
+                            left.as_ref(),
+                            Self::binary_op_to_simple_predicate_op(op)?,
+                            right.as_ref(),
+                        ),
+                        _ => return Err(unsupported()),
+                    };
+                    branches.push(CaseBranch {
+                        left: self.expr_to_scalar(left)?,
+                        op,
+                        right: self.expr_to_scalar(right)?,
+                        result: self.expr_to_scalar(result)?,
+                    });
+                }
+                let otherwise = match else_result {
+                    Some(e) => Some(boxed(e)?),
+                    None => None,
+                };
+                Ok(ScalarExpr::Case(branches, otherwise))
+            }
+            Expr::Cast { expr, data_type } => {
+                Ok(ScalarExpr::Cast(boxed(expr)?, get_attr(data_type)?))
+            }
+            _ => Err(unsupported()),
         }
     }
 }
@@ -931,6 +2044,22 @@ mod test {
             assert!(res.is_err());
         }
 
+        #[test]
+        fn test_validate_predicate_literal() {
+            let db = test_db();
+
+            // Literal that converts to the column type
+            let sql = format!("Select * from {} where {} = 1", TABLE_B, B_COLS[0]);
+            let ast = get_select_ast(&sql);
+            assert!(TranslateAndValidate::from_sql(&ast, &db).is_ok());
+
+            // Literal that does not
+            let sql = format!("Select * from {} where {} = 'abc'", TABLE_B, B_COLS[0]);
+            let ast = get_select_ast(&sql);
+            let res = TranslateAndValidate::from_sql(&ast, &db);
+            assert!(matches!(res, Err(CrustyError::ValidationError(_))));
+        }
+
         #[test]
         fn test_validate_ambiguous_column() {
             let db = test_db();
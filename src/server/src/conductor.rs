@@ -1,11 +1,18 @@
 use std::sync::Arc;
 
-use crate::queryexe::query::TranslateAndValidate;
+use crate::queryexe::opiterator::{OpIterator, Profiling};
+use crate::queryexe::query::{rewrite_plan, Attachments, TranslateAndValidate};
 use common::catalog::Catalog;
-use common::ids::LogicalTimeStamp;
-use common::physical_plan::PhysicalPlan;
+use common::foreign_key::ForeignKeys;
+use common::ids::{LogicalTimeStamp, TransactionId};
+use common::index::{IndexInfo, IndexKind};
+use common::logical_plan::LogicalPlan;
+use common::physical_plan::{
+    PhysicalDeleteNode, PhysicalInsertNode, PhysicalOp, PhysicalPlan, PhysicalUpdateNode,
+};
 use common::prelude::ContainerId;
-use common::{get_name, testutil, CrustyError, QueryResult, TableSchema};
+use common::table::Table;
+use common::{get_name, statistics, testutil, CrustyError, QueryResult, TableSchema};
 use optimizer::optimizer::Optimizer;
 use txn_manager::transactions::Transaction;
 
@@ -16,7 +23,9 @@ use crate::sql_parser::{ParserResponse, SQLParser};
 use crate::worker::Message;
 use crate::Executor;
 use common::commands;
-use sqlparser::ast::{ObjectName, SetExpr, Statement};
+use sqlparser::ast::{
+    AlterTableOperation, Expr, FunctionArg, ObjectName, ObjectType, OrderByExpr, SetExpr, Statement,
+};
 use std::fs::OpenOptions;
 use std::io::Write;
 
@@ -24,7 +33,10 @@ pub struct Conductor {
     pub parser: SQLParser,
     pub optimizer: Optimizer,
     pub executor: Executor,
-    pub active_txn: Transaction,
+    /// Transaction of the statement being run, None between statements.
+    pub active_txn: Option<Transaction>,
+    /// Storage directories attached to this session.
+    pub attachments: Attachments,
 }
 
 impl Conductor {
@@ -37,7 +49,8 @@ impl Conductor {
             parser,
             optimizer,
             executor,
-            active_txn: Transaction::new(),
+            active_txn: None,
+            attachments: Attachments::new(),
         };
         Ok(conductor)
     }
@@ -64,21 +77,39 @@ impl Conductor {
                 // Check exists and load.
                 // TODO: Figure out about using &str.
                 info!("Processing COMMAND::Connect {:?}", name);
-                server_state.connect_to_db(name, client_id)
+                let res = server_state.connect_to_db(name, client_id)?;
+                // queries of the session run against the containers of the database
+                self.executor.storage_manager = Self::client_db_state(client_id, server_state)?
+                    .storage_manager
+                    .clone();
+                Ok(res)
+            }
+            commands::Commands::Drop(name) => {
+                info!("Processing COMMAND::Drop {:?}", name);
+                server_state.drop_database(name)
             }
             commands::Commands::Import(path_and_name) => {
                 info!("Processing COMMAND::Import {:?}", path_and_name);
                 // Get db id.
                 let (table_name, new_path) = ServerState::parse_name_and_path(&path_and_name);
-                let (table_id, table_schema) =
-                    self.get_table_id_and_schema(table_name, client_id, server_state)?;
-                self.executor.import_csv(
-                    new_path,
-                    table_name,
-                    &table_id,
-                    &table_schema,
-                    self.active_txn.tid()?,
-                )
+                let (table_id, table, indexes, foreign_keys) =
+                    self.get_table_constraints(table_name, client_id, server_state)?;
+                let db_state = Self::client_db_state(client_id, server_state)?;
+                self.in_transaction(db_state, |conductor| {
+                    let tid = conductor.tid(db_state)?;
+                    let res = conductor.executor.import_csv(
+                        new_path,
+                        table_name,
+                        &table_id,
+                        &table,
+                        &indexes,
+                        &foreign_keys,
+                        tid,
+                    );
+                    db_state.result_cache.bump(table_id);
+                    db_state.refresh_table_stats(table_id, tid);
+                    res
+                })
             }
             commands::Commands::RegisterQuery(name_and_plan_path) => {
                 // Register a query (as a physical plan) to be executed at a later time (a stored procedure or view)
@@ -106,7 +137,7 @@ impl Conductor {
                     )));
                 }
                 let query_name = possible_query_name.unwrap();
-                let _cache: bool = match possible_cache.unwrap().parse() {
+                let cache: bool = match possible_cache.unwrap().parse() {
                     Ok(v) => v,
                     Err(e) => return Err(CrustyError::CrustyError(format!("Bad cache: {}", e))),
                 };
@@ -136,7 +167,9 @@ impl Conductor {
                     server_state.begin_query(query_name, None, timestamp, client_id)?;
 
                 // Run query.
-                self.run_query(query_plan, db_state, timestamp)?;
+                self.in_transaction(db_state, |conductor| {
+                    conductor.run_query(query_plan, db_state, timestamp, cache)
+                })?;
 
                 // Update metadata after finishing query.
                 server_state.finish_query(query_name, client_id)?;
@@ -185,10 +218,10 @@ impl Conductor {
                     };
 
                     let statement = statements.get(0).unwrap();
-                    if let Statement::Query(query) = statement {
+                    if let Statement::Query(_) = statement {
                         let db = &db_state.database;
                         debug!("Obtaining Logical Plan from query's AST");
-                        let logical_plan = TranslateAndValidate::from_sql(query, db)?;
+                        let logical_plan = TranslateAndValidate::from_statement(statement, db)?;
 
                         debug!("Converting this Logical Plan to a Physical Plan");
                         let physical_plan =
@@ -294,6 +327,39 @@ impl Conductor {
                 let tuples = testutil::gen_test_tuples(n);
                 csv_utils::write_tuples_to_new_csv(csv_file_name.to_string(), tuples)
             }
+            commands::Commands::Attach(path, alias) => {
+                info!("Processing COMMAND::Attach {:?} as {:?}", path, alias);
+                let table_names = self.attachments.attach(&path, &alias)?;
+                if table_names.is_empty() {
+                    Ok(format!("Attached {} as {} with no tables", path, alias))
+                } else {
+                    Ok(format!(
+                        "Attached {} as {} with tables {}",
+                        path,
+                        alias,
+                        table_names.join(",")
+                    ))
+                }
+            }
+            commands::Commands::Detach(alias) => {
+                info!("Processing COMMAND::Detach {:?}", alias);
+                self.attachments.detach(&alias)?;
+                Ok(format!("Detached {}", alias))
+            }
+            commands::Commands::ShowAttached => {
+                info!("Processing COMMAND::ShowAttached");
+                let attached: Vec<String> = self
+                    .attachments
+                    .list()
+                    .iter()
+                    .map(|(alias, path)| format!("{} {}", alias, path.display()))
+                    .collect();
+                if attached.is_empty() {
+                    Ok(String::from("No databases attached"))
+                } else {
+                    Ok(attached.join(","))
+                }
+            }
             commands::Commands::Test => {
                 let queue = server_state.task_queue.lock().unwrap();
                 queue.send(Message::Test).unwrap();
@@ -314,14 +380,72 @@ impl Conductor {
         }
     }
 
+    /// Runs SQL commands depending on the first statement, in a transaction of their own.
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - Tokenized command into statements.
+    /// * `db_state` - Database the statements run against.
+    pub fn run_sql(
+        &mut self,
+        cmd: Vec<Statement>,
+        db_state: &'static DatabaseState,
+    ) -> Result<common::QueryResult, CrustyError> {
+        self.in_transaction(db_state, |conductor| {
+            conductor.run_statements(cmd, db_state)
+        })
+    }
+
+    /// Runs a statement or command in a transaction begun with the database's transaction
+    /// manager. The transaction is committed if it succeeds and aborted otherwise, after
+    /// which its id is rejected.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_state` - Database the transaction runs against.
+    /// * `run` - Runs the statement, getting the id of the transaction with `tid`.
+    fn in_transaction<T>(
+        &mut self,
+        db_state: &'static DatabaseState,
+        run: impl FnOnce(&mut Self) -> Result<T, CrustyError>,
+    ) -> Result<T, CrustyError> {
+        let tm = db_state.transaction_manager;
+        self.active_txn = Some(tm.begin()?);
+        let res = run(self);
+        let mut txn = self.active_txn.take().unwrap();
+        let storage = db_state.storage_manager.as_ref();
+        match res {
+            Ok(res) => tm.commit(&mut txn, storage).map(|_| res),
+            Err(e) => {
+                tm.abort(&mut txn, storage)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the id of the transaction the running statement is in, if it is active.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_state` - Database the statement runs against.
+    fn tid(&self, db_state: &'static DatabaseState) -> Result<TransactionId, CrustyError> {
+        let txn = self
+            .active_txn
+            .as_ref()
+            .ok_or(CrustyError::TransactionNotActive)?;
+        let tid = txn.tid()?;
+        db_state.transaction_manager.check_active(tid)?;
+        Ok(tid)
+    }
+
     /// Runs SQL commands depending on the first statement.
     ///
     /// # Arguments
     ///
     /// * `cmd` - Tokenized command into statements.
-    /// * `id` - Thread id for lock management.
+    /// * `db_state` - Database the statements run against.
     #[allow(unused_variables)]
-    pub fn run_sql(
+    fn run_statements(
         &mut self,
         cmd: Vec<Statement>,
         db_state: &'static DatabaseState,
@@ -329,38 +453,95 @@ impl Conductor {
         if cmd.is_empty() {
             Err(CrustyError::CrustyError(String::from("Empty SQL command")))
         } else {
-            match cmd.first().unwrap() {
+            let statement = cmd.first().unwrap();
+            match statement {
                 Statement::CreateTable {
                     name: table_name,
                     columns,
                     constraints,
+                    with_options,
                     ..
                 } => {
                     info!("Processing CREATE table: {:?}", table_name);
-                    db_state.create_table(&get_name(table_name)?, columns, constraints)
+                    db_state.create_table(
+                        &get_name(table_name)?,
+                        columns,
+                        constraints,
+                        with_options,
+                    )
                 }
-                Statement::Query(qbox) => {
+                Statement::Query(_) => {
                     debug!("Processing SQL Query");
-                    let db = &db_state.database;
-
-                    // After optimizer has done its job, we obtain a physical representation of this logical-plan
-                    // This physical representation depends on the Executor implementation, so Executors must
-                    // provide a function that takes a logical plan, catalog, storage manager, etc, and gives
-                    // back a physical plan which is a thing that the Executor knows how to interpret
-
-                    debug!("Obtaining Logical Plan from query's AST");
-                    let logical_plan = TranslateAndValidate::from_sql(qbox, db)?;
-                    debug!("Converting this Logical Plan to a Physical Plan");
-                    let physical_plan =
-                        self.optimizer
-                            .logical_plan_to_physical_plan(logical_plan, db, false)?;
-                    debug!("physical plan {:?}", physical_plan);
+                    let physical_plan = self.query_to_physical_plan(statement, db_state)?;
                     self.run_query(
                         Arc::new(physical_plan),
                         db_state,
                         db_state.get_current_time(),
+                        true,
                     )
                 }
+                Statement::Explain {
+                    analyze,
+                    verbose,
+                    statement,
+                } => match statement.as_ref() {
+                    Statement::Query(_) => {
+                        debug!("Processing EXPLAIN (analyze: {})", analyze);
+                        let logical_plan = self.query_to_logical_plan(statement, db_state)?;
+                        // EXPLAIN VERBOSE shows the logical plan above the physical plan.
+                        let logical = match verbose {
+                            true => Some(logical_plan.explain()),
+                            false => None,
+                        };
+                        let mut physical_plan =
+                            self.logical_to_physical_plan(logical_plan, db_state)?;
+                        if *analyze {
+                            // Runs of the same query aggregate groups in the same order.
+                            physical_plan.sort_aggregate_groups();
+                        }
+                        let profiling = match analyze {
+                            true => Profiling::Analyze,
+                            false => Profiling::Explain,
+                        };
+                        self.configure_query(
+                            &physical_plan,
+                            db_state,
+                            db_state.get_current_time(),
+                            profiling,
+                        )?;
+                        let physical = self.executor.explain(*analyze)?;
+                        match logical {
+                            Some(logical) => Ok(QueryResult::new(&format!(
+                                "Logical plan:\n{}\n\nPhysical plan:\n{}",
+                                logical,
+                                physical.result()
+                            ))),
+                            None => Ok(physical),
+                        }
+                    }
+                    _ => Err(CrustyError::CrustyError(String::from(
+                        "EXPLAIN only supports queries",
+                    ))),
+                },
+                Statement::Analyze { table_name, .. } => {
+                    info!("Processing ANALYZE table: {:?}", table_name);
+                    let (table_id, extracted_table_name, _) =
+                        self.get_table_id_name_and_schema(table_name, db_state)?;
+                    let table = db_state.database.get_table_ptr(table_id)?;
+                    let stats = statistics::analyze(
+                        db_state.storage_manager.as_ref(),
+                        table_id,
+                        &table.read().unwrap(),
+                        1.0,
+                        self.tid(db_state)?,
+                    )?;
+                    let msg = format!(
+                        "Analyzed table {}: {} rows",
+                        extracted_table_name, stats.row_count
+                    );
+                    db_state.database.set_table_stats(table_id, stats);
+                    Ok(QueryResult::new(&msg))
+                }
                 Statement::Insert {
                     table_name,
                     columns,
@@ -378,20 +559,36 @@ impl Conductor {
                                 "Inserts with columns specified is not currently supported. Must supply values for the entire table",
                             )))
                         } else {
-                            let (table_id, extracted_table_name, table_schema) =
+                            let (table_id, extracted_table_name, _) =
                                 self.get_table_id_name_and_schema(table_name, db_state)?;
+                            let table = db_state.database.get_table_ptr(table_id)?;
                             let res_string = self.executor.import_tuples(
                                 values,
                                 &extracted_table_name,
                                 &table_id,
-                                &table_schema,
-                                self.active_txn.tid()?,
+                                &table.read().unwrap(),
+                                &db_state.database.get_indexes(table_id),
+                                &ForeignKeys::new(&db_state.database, table_id)?,
+                                self.tid(db_state)?,
                             )?;
+                            db_state.result_cache.bump(table_id);
+                            db_state.refresh_table_stats(table_id, self.tid(db_state)?);
                             Ok(QueryResult::new(&res_string))
                         }
                     } else {
-                        Err(CrustyError::CrustyError(String::from(
-                            "Inserts via query not currently supported. Must supply values",
+                        let (_, extracted_table_name, _) =
+                            self.get_table_id_name_and_schema(table_name, db_state)?;
+                        let db = &self.attachments.catalog(&db_state.database);
+                        let logical_plan = TranslateAndValidate::from_statement(statement, db)?;
+                        let physical_plan = self.optimizer.logical_plan_to_physical_plan(
+                            logical_plan,
+                            db,
+                            false,
+                        )?;
+                        let count = self.run_dml(&physical_plan, db_state)?;
+                        Ok(QueryResult::new(&format!(
+                            "Inserted {} tuples to table {}",
+                            count, extracted_table_name
                         )))
                     }
                 }
@@ -400,8 +597,17 @@ impl Conductor {
                     selection,
                 } => {
                     debug!("deleting table:{} selection: {:?}", table_name, selection);
-                    Err(CrustyError::CrustyError(String::from(
-                        "Delete not currently supported",
+                    let (_, extracted_table_name, _) =
+                        self.get_table_id_name_and_schema(table_name, db_state)?;
+                    let db = &self.attachments.catalog(&db_state.database);
+                    let logical_plan = TranslateAndValidate::from_statement(statement, db)?;
+                    let physical_plan =
+                        self.optimizer
+                            .logical_plan_to_physical_plan(logical_plan, db, false)?;
+                    let count = self.run_dml(&physical_plan, db_state)?;
+                    Ok(QueryResult::new(&format!(
+                        "Deleted {} tuples from table {}",
+                        count, extracted_table_name
                     )))
                 }
                 Statement::Truncate {
@@ -424,9 +630,19 @@ impl Conductor {
                     purge,
                 } => {
                     debug!("dropping table:{:?} type: {:?}", names, object_type);
-                    Err(CrustyError::CrustyError(String::from(
-                        "Drop not currently supported",
-                    )))
+                    match object_type {
+                        ObjectType::Index => {
+                            let mut results = Vec::new();
+                            for name in names {
+                                let res = db_state.drop_index(&get_name(name)?, *if_exists)?;
+                                results.push(res.result);
+                            }
+                            Ok(QueryResult::new(&results.join("\n")))
+                        }
+                        _ => Err(CrustyError::CrustyError(String::from(
+                            "Drop not currently supported",
+                        ))),
+                    }
                 }
                 Statement::Update {
                     table_name,
@@ -437,28 +653,21 @@ impl Conductor {
                         "Updating table:{} \n\nassignments: {:?} selection: {:?}",
                         table_name, assignments, selection
                     );
-                    let (table_id, extracted_table_name, table_schema) =
+                    let (_, extracted_table_name, _) =
                         self.get_table_id_name_and_schema(table_name, db_state)?;
-                    let db = &db_state.database;
-                    let logical_plan = TranslateAndValidate::from_update(
-                        table_id,
-                        &extracted_table_name,
-                        assignments,
-                        selection,
-                        db,
-                    )?;
+                    let db = &self.attachments.catalog(&db_state.database);
+                    let logical_plan = TranslateAndValidate::from_statement(statement, db)?;
                     //error!("{:?}", logical_plan.to_string());
                     //error!("{:?}", logical_plan.to_json());
                     let physical_plan =
                         self.optimizer
                             .logical_plan_to_physical_plan(logical_plan, db, false)?;
                     debug!("physical plan {:?}", physical_plan);
-                    self.run_query(
-                        Arc::new(physical_plan),
-                        db_state,
-                        db_state.get_current_time(),
-                    )
-                    //self.executor.update_tuples(values, predicate, table_name, &table_id, table_schema, self.active_txn.tid()?)
+                    let count = self.run_dml(&physical_plan, db_state)?;
+                    Ok(QueryResult::new(&format!(
+                        "Updated {} tuples in table {}",
+                        count, extracted_table_name
+                    )))
                 }
                 Statement::StartTransaction { modes } => {
                     debug!("Starting transaction {:?} ", modes);
@@ -489,9 +698,28 @@ impl Conductor {
                         "Creating index: {} Table:{} Cols: {:?} unique:{}, if not exists {}",
                         name, table_name, columns, unique, if_not_exists
                     );
-                    Err(CrustyError::CrustyError(String::from(
-                        "Create Index not currently supported",
-                    )))
+                    let (column_names, kind) = Conductor::index_columns(columns)?;
+                    db_state.create_index(
+                        &get_name(name)?,
+                        &get_name(table_name)?,
+                        &column_names,
+                        *unique,
+                        kind,
+                        *if_not_exists,
+                        self.tid(db_state)?,
+                    )
+                }
+                Statement::AlterTable { name, operation } => {
+                    debug!("Altering table: {} operation: {}", name, operation);
+                    match operation {
+                        AlterTableOperation::AddColumn { column_def } => {
+                            db_state.add_column(&get_name(name)?, column_def)
+                        }
+                        _ => Err(CrustyError::CrustyError(format!(
+                            "Alter table {} not currently supported",
+                            operation
+                        ))),
+                    }
                 }
                 Statement::CreateView {
                     or_replace,
@@ -511,36 +739,188 @@ impl Conductor {
         }
     }
 
+    /// Returns the names of the indexed columns of a CREATE INDEX statement and how its
+    /// entries are stored. The parser turns `USING method (columns)` into the single column
+    /// expression `method(columns)`; indexes without a method are heap indexes.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - Column expressions of the statement.
+    fn index_columns(columns: &[OrderByExpr]) -> Result<(Vec<String>, IndexKind), CrustyError> {
+        let (kind, exprs): (IndexKind, Vec<&Expr>) = match columns {
+            [OrderByExpr {
+                expr: Expr::Function(function),
+                ..
+            }] => {
+                let kind = match get_name(&function.name)?.to_lowercase().as_str() {
+                    "hash" => IndexKind::Hash,
+                    "heap" => IndexKind::Heap,
+                    "bitmap" => IndexKind::Bitmap,
+                    method => {
+                        return Err(CrustyError::ValidationError(format!(
+                            "Unknown index method {}",
+                            method
+                        )))
+                    }
+                };
+                let mut exprs = Vec::new();
+                for arg in &function.args {
+                    match arg {
+                        FunctionArg::Unnamed(expr) => exprs.push(expr),
+                        FunctionArg::Named { name, .. } => {
+                            return Err(CrustyError::ValidationError(format!(
+                                "Only columns can be indexed, not argument {}",
+                                name
+                            )))
+                        }
+                    }
+                }
+                (kind, exprs)
+            }
+            _ => (
+                IndexKind::Heap,
+                columns.iter().map(|column| &column.expr).collect(),
+            ),
+        };
+        let mut column_names = Vec::new();
+        for expr in exprs {
+            match expr {
+                Expr::Identifier(ident) => column_names.push(ident.value.clone()),
+                expr => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Only columns can be indexed, not {}",
+                        expr
+                    )))
+                }
+            }
+        }
+        Ok((column_names, kind))
+    }
+
+    /// Translates a query's AST into a physical plan.
+    ///
+    /// # Arguments
+    ///
+    /// * `statement` - AST of the query.
+    /// * `db_state` - Database the query runs on.
+    fn query_to_physical_plan(
+        &self,
+        statement: &Statement,
+        db_state: &'static DatabaseState,
+    ) -> Result<PhysicalPlan, CrustyError> {
+        let logical_plan = self.query_to_logical_plan(statement, db_state)?;
+        self.logical_to_physical_plan(logical_plan, db_state)
+    }
+
+    /// Translates a query to a logical plan and rewrites it.
+    fn query_to_logical_plan(
+        &self,
+        statement: &Statement,
+        db_state: &'static DatabaseState,
+    ) -> Result<LogicalPlan, CrustyError> {
+        // Tables of attached databases are resolved alongside the session's own.
+        let db = &self.attachments.catalog(&db_state.database);
+        debug!("Obtaining Logical Plan from query's AST");
+        let mut logical_plan = TranslateAndValidate::from_statement(statement, db)?;
+        debug!("Rewriting the Logical Plan");
+        rewrite_plan(&mut logical_plan);
+        Ok(logical_plan)
+    }
+
+    /// Converts a logical plan to a physical plan.
+    fn logical_to_physical_plan(
+        &self,
+        logical_plan: LogicalPlan,
+        db_state: &'static DatabaseState,
+    ) -> Result<PhysicalPlan, CrustyError> {
+        let db = &self.attachments.catalog(&db_state.database);
+
+        // After optimizer has done its job, we obtain a physical representation of this logical-plan
+        // This physical representation depends on the Executor implementation, so Executors must
+        // provide a function that takes a logical plan, catalog, storage manager, etc, and gives
+        // back a physical plan which is a thing that the Executor knows how to interpret
+        debug!("Converting this Logical Plan to a Physical Plan");
+        let physical_plan =
+            self.optimizer
+                .logical_plan_to_physical_plan(logical_plan, db, false)?;
+        debug!("physical plan {:?}", physical_plan);
+        Ok(physical_plan)
+    }
+
+    /// Builds the opiterator of a physical plan and configures the executor with it.
+    fn configure_query(
+        &mut self,
+        physical_plan: &PhysicalPlan,
+        db_state: &'static DatabaseState,
+        timestamp: LogicalTimeStamp,
+        profiling: Profiling,
+    ) -> Result<(), CrustyError> {
+        let op_iterator = self.build_query(physical_plan, db_state, timestamp, profiling)?;
+        // We populate the executor with the state: physical plan, and storage manager ref
+        debug!("Configuring Physical Plan");
+        self.executor.configure_query(op_iterator);
+        Ok(())
+    }
+
+    /// Builds the opiterator of a physical plan.
+    fn build_query(
+        &self,
+        physical_plan: &PhysicalPlan,
+        db_state: &'static DatabaseState,
+        timestamp: LogicalTimeStamp,
+        profiling: Profiling,
+    ) -> Result<Box<dyn OpIterator>, CrustyError> {
+        let db = &self.attachments.catalog(&db_state.database);
+        let tid = self.tid(db_state)?;
+
+        debug!("Configuring Storage Manager");
+        Executor::physical_plan_to_op_iterator(
+            &db_state.storage_manager,
+            db_state.transaction_manager.locks(),
+            db,
+            &self.attachments,
+            physical_plan,
+            &self.executor.memory_budget,
+            profiling,
+            tid,
+            timestamp,
+        )
+    }
+
     /// Runs a given query.
     ///
     /// # Arguments
     ///
     /// * `query` - Query to run.
     /// * `id` - Thread id for lock management.
+    /// * `cache` - Whether the result may be served from and kept in the result cache.
     #[allow(unused_variables)]
     fn run_query(
         &mut self,
         physical_plan: Arc<PhysicalPlan>,
         db_state: &'static DatabaseState,
         timestamp: LogicalTimeStamp,
+        cache: bool,
     ) -> Result<QueryResult, CrustyError> {
-        let db = &db_state.database;
-
-        // Start transaction
-        let txn = Transaction::new();
-
-        debug!("Configuring Storage Manager");
-        let op_iterator = Executor::physical_plan_to_op_iterator(
-            db_state.storage_manager,
-            db_state.transaction_manager,
-            db,
-            &physical_plan,
-            txn.tid()?,
-            timestamp,
-        )?;
-        // We populate the executor with the state: physical plan, and storage manager ref
-        debug!("Configuring Physical Plan");
-        self.executor.configure_query(op_iterator);
+        if cache {
+            let cache = &db_state.result_cache;
+            let cached = cache.get(&physical_plan, &self.attachments, self.tid(db_state)?);
+            let op_iterator = match cached {
+                Some(op_iterator) => op_iterator,
+                None => {
+                    let op_iterator = self.build_query(
+                        &physical_plan,
+                        db_state,
+                        timestamp,
+                        Profiling::default(),
+                    )?;
+                    cache.fill(&physical_plan, &self.attachments, op_iterator)
+                }
+            };
+            self.executor.configure_query(op_iterator);
+        } else {
+            self.configure_query(&physical_plan, db_state, timestamp, Profiling::default())?;
+        }
 
         // Finally, execute the query
         debug!("Executing query");
@@ -551,6 +931,51 @@ impl Conductor {
         }
     }
 
+    /// Runs an INSERT, UPDATE, or DELETE plan and returns the number of affected records.
+    ///
+    /// # Arguments
+    ///
+    /// * `physical_plan` - Plan whose root modifies a table.
+    /// * `db_state` - Database the plan runs against.
+    fn run_dml(
+        &mut self,
+        physical_plan: &PhysicalPlan,
+        db_state: &'static DatabaseState,
+    ) -> Result<usize, CrustyError> {
+        self.configure_query(
+            physical_plan,
+            db_state,
+            db_state.get_current_time(),
+            Profiling::default(),
+        )?;
+        debug!("Executing DML");
+        let count = self.executor.execute_count();
+        // Bump the changed tables even if the statement failed part way, and refresh their
+        // statistics if they changed enough.
+        let root = physical_plan
+            .root()
+            .and_then(|i| physical_plan.get_operator(i));
+        let changed = match root {
+            Some(PhysicalOp::Insert(PhysicalInsertNode { container_id, .. }))
+            | Some(PhysicalOp::Update(PhysicalUpdateNode { container_id, .. })) => {
+                vec![*container_id]
+            }
+            Some(PhysicalOp::Delete(PhysicalDeleteNode { container_id, .. })) => {
+                let children = ForeignKeys::new(&db_state.database, *container_id)?.children;
+                std::iter::once(*container_id)
+                    .chain(children.into_iter().map(|child| child.table_id))
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+        let tid = self.tid(db_state)?;
+        for table_id in changed {
+            db_state.result_cache.bump(table_id);
+            db_state.refresh_table_stats(table_id, tid);
+        }
+        count
+    }
+
     /// Utility to get a id, name and schema copy for table_name for a given client
     fn get_table_id_name_and_schema(
         &self,
@@ -576,25 +1001,31 @@ impl Conductor {
         Ok((table_id, extracted_table_name.to_owned(), table_schema))
     }
 
-    /// Utility to get a id and schema copy for table_id for a given client
-    fn get_table_id_and_schema(
-        &self,
-        table_name: &str,
+    /// Utility to get the database a client is connected to
+    fn client_db_state(
         client_id: u64,
         server_state: &'static ServerState,
-    ) -> Result<(ContainerId, TableSchema), CrustyError> {
+    ) -> Result<&'static DatabaseState, CrustyError> {
         let db_id_ref = server_state.active_connections.read().unwrap();
-        let db_state = match db_id_ref.get(&client_id) {
+        match db_id_ref.get(&client_id) {
             Some(db_id) => {
                 let db_ref = server_state.id_to_db.read().unwrap();
-                *db_ref.get(db_id).unwrap()
-            }
-            None => {
-                return Err(CrustyError::CrustyError(String::from(
-                    "No active DB or DB not found",
-                )))
+                Ok(*db_ref.get(db_id).unwrap())
             }
-        };
+            None => Err(CrustyError::CrustyError(String::from(
+                "No active DB or DB not found",
+            ))),
+        }
+    }
+
+    /// Utility to get a id, table copy, indexes and foreign keys for table_id for a given client
+    fn get_table_constraints(
+        &self,
+        table_name: &str,
+        client_id: u64,
+        server_state: &'static ServerState,
+    ) -> Result<(ContainerId, Table, Vec<IndexInfo>, ForeignKeys), CrustyError> {
+        let db_state = Self::client_db_state(client_id, server_state)?;
 
         let table_id = db_state.database.get_table_id(table_name).ok_or_else(|| {
             CrustyError::CrustyError(format!(
@@ -602,7 +1033,105 @@ impl Conductor {
                 table_name
             ))
         })?;
-        let table_schema = db_state.database.get_table_schema(table_id)?;
-        Ok((table_id, table_schema))
+        let table = db_state
+            .database
+            .get_table_ptr(table_id)?
+            .read()
+            .unwrap()
+            .clone();
+        let indexes = db_state.database.get_indexes(table_id);
+        let foreign_keys = ForeignKeys::new(&db_state.database, table_id)?;
+        Ok((table_id, table, indexes, foreign_keys))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{LockManager, StorageManager, StorageTrait, TransactionManager};
+    use common::traits::transaction_manager_trait::TransactionManagerTrait;
+    use std::path::Path;
+
+    /// Returns a conductor and the empty database it runs statements against.
+    fn conductor() -> (Conductor, &'static DatabaseState) {
+        let tm: &'static TransactionManager = Box::leak(Box::new(TransactionManager::new(
+            LockManager::new(Path::new("")),
+        )));
+        let sm = Arc::new(StorageManager::new_test_sm());
+        let db_state: &'static DatabaseState = Box::leak(Box::new(
+            DatabaseState::new_from_name("db", sm, tm).unwrap(),
+        ));
+        let executor = Executor::new_ref(db_state.storage_manager.clone(), tm.locks());
+        let conductor = Conductor::new(SQLParser::new(), Optimizer::new(), executor).unwrap();
+        (conductor, db_state)
+    }
+
+    fn run(
+        conductor: &mut Conductor,
+        db_state: &'static DatabaseState,
+        sql: &str,
+    ) -> Result<QueryResult, CrustyError> {
+        match SQLParser::parse_sql(sql.to_string()) {
+            ParserResponse::SQL(statements) => conductor.run_sql(statements, db_state),
+            _ => panic!("Cannot parse {}", sql),
+        }
+    }
+
+    #[test]
+    fn test_statements_run_in_transactions() {
+        let (mut conductor, db_state) = conductor();
+        let tm = db_state.transaction_manager;
+        run(
+            &mut conductor,
+            db_state,
+            "CREATE TABLE t (a INT, b INT, PRIMARY KEY (a))",
+        )
+        .unwrap();
+        run(
+            &mut conductor,
+            db_state,
+            "INSERT INTO t VALUES (1, 2), (3, 4)",
+        )
+        .unwrap();
+        let res = run(&mut conductor, db_state, "SELECT t.b FROM t WHERE t.a = 3").unwrap();
+        assert!(res.result().contains('4'));
+        assert!(conductor.active_txn.is_none());
+        assert!(tm.active_transactions().is_empty());
+
+        // A failed statement aborts its transaction.
+        assert!(run(&mut conductor, db_state, "SELECT * FROM missing").is_err());
+        assert!(tm.active_transactions().is_empty());
+
+        // Statements cannot run in a finished transaction.
+        conductor.active_txn = Some(tm.begin().unwrap());
+        let tid = conductor.tid(db_state).unwrap();
+        let storage = db_state.storage_manager.as_ref();
+        tm.commit(conductor.active_txn.as_mut().unwrap(), storage)
+            .unwrap();
+        assert!(conductor.tid(db_state).is_err());
+        assert!(tm.check_active(tid).is_err());
+    }
+
+    #[test]
+    fn test_negative_literals() {
+        let (mut conductor, db_state) = conductor();
+        run(
+            &mut conductor,
+            db_state,
+            "CREATE TABLE t (a INT, b FLOAT, PRIMARY KEY (a))",
+        )
+        .unwrap();
+        run(
+            &mut conductor,
+            db_state,
+            "INSERT INTO t VALUES (-7, -1.5), (3, 2.5)",
+        )
+        .unwrap();
+        let res = run(&mut conductor, db_state, "SELECT t.a FROM t WHERE t.b < -1").unwrap();
+        assert!(res.result().contains("-7"));
+        assert!(!res.result().contains('3'));
+        let res = run(&mut conductor, db_state, "SELECT t.b FROM t WHERE t.a > -5").unwrap();
+        assert!(res.result().contains("2.5"));
+        assert!(!res.result().contains("-1.5"));
     }
 }
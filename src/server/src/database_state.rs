@@ -10,19 +10,27 @@ use std::sync::{Arc, RwLock};
 use crate::{StorageManager, StorageTrait, TransactionManager};
 use common::catalog::Catalog;
 use common::database::Database;
+use common::foreign_key::{ForeignKey, OnDelete};
 use common::ids::{AtomicTimeStamp, StateMeta, StateType};
+use common::index::{self, IndexInfo, IndexKind};
+use common::partition::{PartitionScheme, Partitioning};
 use common::physical_plan::PhysicalPlan;
 use common::prelude::*;
-use common::table::Table;
+use common::statistics::{self, ColumnStats};
+use common::table::{Table, TableLayout};
 use common::{get_attr, Attribute, QueryResult};
-use sqlparser::ast::ColumnDef;
 use sqlparser::ast::TableConstraint;
+use sqlparser::ast::{ColumnDef, ColumnOption, Expr, Ident, ReferentialAction, SqlOption, Value};
 
 use crate::query_registrar::QueryRegistrar;
+use crate::queryexe::query::ResultCache;
 use crate::sql_parser::{ParserResponse, SQLParser};
 
 use std::sync::atomic::AtomicU32;
 
+/// Keys the bloom filter over the primary key of a new table is sized for.
+const PRIMARY_KEY_BLOOM_KEYS: usize = 10_000;
+
 #[derive(Serialize)]
 pub struct DatabaseState {
     pub id: u64,
@@ -31,7 +39,7 @@ pub struct DatabaseState {
     pub database: Database,
 
     #[serde(skip_serializing)]
-    pub storage_manager: &'static StorageManager,
+    pub storage_manager: Arc<StorageManager>,
 
     #[serde(skip_serializing)]
     pub transaction_manager: &'static TransactionManager,
@@ -48,6 +56,10 @@ pub struct DatabaseState {
 
     #[serde(skip_serializing)]
     query_registrar: QueryRegistrar,
+
+    /// Results of recent queries, disabled until given a capacity.
+    #[serde(skip_serializing)]
+    pub result_cache: ResultCache,
 }
 
 #[allow(dead_code)]
@@ -56,7 +68,7 @@ impl DatabaseState {
     pub fn new_from_path(
         path: PathBuf,
         _storage_path: String,
-        sm: &'static StorageManager,
+        sm: Arc<StorageManager>,
         tm: &'static TransactionManager,
     ) -> Result<Self, CrustyError> {
         debug!("Creating new DBState from path {:?}", path);
@@ -71,17 +83,18 @@ impl DatabaseState {
                 let db_name = cand_name.to_string();
                 let db_id = DatabaseState::get_database_id(&db_name);
 
-                let database = DatabaseState::load_database_from_file(res, sm)?;
+                let database = DatabaseState::load_database_from_file(res, &sm)?;
                 let _db_state = DatabaseState {
                     id: db_id,
                     name: db_name,
                     database,
-                    storage_manager: sm,
+                    storage_manager: sm.clone(),
                     transaction_manager: tm,
                     active_client_connections: RwLock::new(HashSet::new()),
                     container_vec: Arc::new(RwLock::new(HashMap::new())),
                     atomic_time: AtomicTimeStamp::new(0),
                     query_registrar: QueryRegistrar::new(),
+                    result_cache: ResultCache::new(sm, 0),
                 };
                 panic!("Fix container meta loading"); // TODO
                                                       //Ok(db_state)
@@ -98,7 +111,7 @@ impl DatabaseState {
 
     pub fn new_from_name(
         db_name: &str,
-        sm: &'static StorageManager,
+        sm: Arc<StorageManager>,
         tm: &'static TransactionManager,
     ) -> Result<Self, CrustyError> {
         let db_name: String = String::from(db_name);
@@ -108,41 +121,53 @@ impl DatabaseState {
             db_name, db_id
         );
         let database = Database::new(db_name.to_string());
+        sm.create_database(&db_name)?;
+        let db_sm = DatabaseState::database_storage_manager(&db_name, sm);
 
         let db_state = DatabaseState {
             id: db_id,
             name: db_name,
             database,
-            storage_manager: sm,
+            storage_manager: db_sm.clone(),
             transaction_manager: tm,
             active_client_connections: RwLock::new(HashSet::new()),
             container_vec: Arc::new(RwLock::new(HashMap::new())),
             atomic_time: AtomicU32::new(0),
             query_registrar: QueryRegistrar::new(),
+            result_cache: ResultCache::new(db_sm, 0),
         };
         Ok(db_state)
     }
 
+    /// Returns the storage manager holding the containers of the database db_name, created
+    /// in sm with `create_database`. Databases persisted before they had their own keep
+    /// their containers in sm.
+    pub fn database_storage_manager(db_name: &str, sm: Arc<StorageManager>) -> Arc<StorageManager> {
+        sm.use_database(db_name).unwrap_or(sm)
+    }
+
     pub fn load(
         filename: PathBuf,
-        sm: &'static StorageManager,
+        sm: Arc<StorageManager>,
         tm: &'static TransactionManager,
     ) -> Result<Self, CrustyError> {
         let database: Database = Database::load(filename);
         let db_name: String = database.name.clone();
         let db_id = DatabaseState::get_database_id(&db_name);
         debug!("Loading DatabaseState; name: {} id: {}", db_name, db_id);
+        let db_sm = DatabaseState::database_storage_manager(&db_name, sm);
 
         let db_state = DatabaseState {
             id: db_id,
             name: db_name,
             database,
-            storage_manager: sm,
+            storage_manager: db_sm.clone(),
             transaction_manager: tm,
             active_client_connections: RwLock::new(HashSet::new()),
             container_vec: Arc::new(RwLock::new(HashMap::new())),
             atomic_time: AtomicU32::new(0),
             query_registrar: QueryRegistrar::new(),
+            result_cache: ResultCache::new(db_sm, 0),
         };
         Ok(db_state)
     }
@@ -235,28 +260,127 @@ impl DatabaseState {
     ///
     /// * `name` - Name of the new table.
     /// * `cols` - Table columns.
+    /// * `constraints` - Table constraints.
+    /// * `options` - Table options; `storage = 'column'` creates a columnar table, and
+    ///   `partition_by = 'hash(c)'` with `partitions = n`, or `partition_by = 'range(c)'`
+    ///   with `partition_bounds = 'b1, b2, ...'`, a table partitioned on column c.
     pub fn create_table(
         &self,
         table_name: &str,
         columns: &[ColumnDef],
         constraints: &[TableConstraint],
+        options: &[SqlOption],
     ) -> Result<QueryResult, CrustyError> {
-        // Constraints aren't implemented yet
+        // Primary key, unique, NOT NULL and foreign key constraints are implemented
 
         let db = &self.database;
-        let mut tables_ref = db.tables.write().unwrap();
-        let table_id =
-            db.get_new_container_id(StateType::BaseTable, Some(table_name.to_string()))?;
+        let mut columnar = false;
+        let mut partition_by = None;
+        let mut partition_count = None;
+        let mut partition_bounds = None;
+        for option in options {
+            match (option.name.value.to_lowercase().as_str(), &option.value) {
+                ("partition_by", Value::SingleQuotedString(spec)) => partition_by = Some(spec),
+                ("partitions", Value::Number(n, _)) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => partition_count = Some(n),
+                    _ => {
+                        return Err(CrustyError::ValidationError(format!(
+                            "Invalid number of partitions {}",
+                            n
+                        )))
+                    }
+                },
+                ("partition_bounds", Value::SingleQuotedString(bounds)) => {
+                    partition_bounds = Some(bounds)
+                }
+                ("storage", Value::SingleQuotedString(storage)) => {
+                    columnar = match storage.to_lowercase().as_str() {
+                        "row" => false,
+                        "column" => true,
+                        _ => {
+                            return Err(CrustyError::ValidationError(format!(
+                                "Unknown storage '{}', expected 'row' or 'column'",
+                                storage
+                            )))
+                        }
+                    }
+                }
+                _ => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Table option {} is not supported",
+                        option
+                    )))
+                }
+            }
+        }
         let pks = match SQLParser::get_pks(columns, constraints) {
             Ok(pks) => pks,
             Err(ParserResponse::SQLConstraintError(s)) => return Err(CrustyError::CrustyError(s)),
             _ => unreachable!(),
         };
 
+        // Columns of the unique constraints, each enforced by a unique index.
+        let mut unique_keys: Vec<Vec<Ident>> = vec![pks.clone()];
+        // Foreign keys as declared: name, columns, parent table, parent columns, ON DELETE.
+        let mut declared_keys = Vec::new();
         let mut attributes: Vec<Attribute> = Vec::new();
         for col in columns {
+            let mut not_null = false;
+            let mut unique = false;
+            for option in &col.options {
+                match &option.option {
+                    ColumnOption::NotNull => not_null = true,
+                    ColumnOption::Unique { is_primary: false } => unique = true,
+                    ColumnOption::ForeignKey {
+                        foreign_table,
+                        referred_columns,
+                        on_delete,
+                        on_update,
+                    } => {
+                        if let Some(action) = on_update {
+                            if *action != ReferentialAction::Restrict
+                                && *action != ReferentialAction::NoAction
+                            {
+                                return Err(CrustyError::ValidationError(format!(
+                                    "ON UPDATE {} is not supported",
+                                    action
+                                )));
+                            }
+                        }
+                        let on_delete = match on_delete {
+                            None
+                            | Some(ReferentialAction::Restrict)
+                            | Some(ReferentialAction::NoAction) => OnDelete::Restrict,
+                            Some(ReferentialAction::Cascade) => OnDelete::Cascade,
+                            Some(action) => {
+                                return Err(CrustyError::ValidationError(format!(
+                                    "ON DELETE {} is not supported",
+                                    action
+                                )))
+                            }
+                        };
+                        declared_keys.push((
+                            format!("{}_{}_fkey", table_name, col.name.value),
+                            vec![col.name.clone()],
+                            foreign_table.to_string(),
+                            referred_columns.clone(),
+                            on_delete,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            if unique {
+                unique_keys.push(vec![col.name.clone()]);
+            }
             let constraint = if pks.contains(&col.name) {
                 common::Constraint::PrimaryKey
+            } else if unique && not_null {
+                common::Constraint::UniqueNotNull
+            } else if unique {
+                common::Constraint::Unique
+            } else if not_null {
+                common::Constraint::NotNull
             } else {
                 common::Constraint::None
             };
@@ -267,22 +391,599 @@ impl DatabaseState {
             };
             attributes.push(attr);
         }
-        let schema = TableSchema::new(attributes);
+        for constraint in constraints {
+            if let TableConstraint::Unique {
+                columns: unique_columns,
+                is_primary: false,
+                ..
+            } = constraint
+            {
+                if let [column] = unique_columns.as_slice() {
+                    if let Some(attr) = attributes.iter_mut().find(|a| a.name == column.value) {
+                        attr.constraint = match attr.constraint {
+                            common::Constraint::NotNull => common::Constraint::UniqueNotNull,
+                            common::Constraint::None => common::Constraint::Unique,
+                            ref other => other.clone(),
+                        };
+                    }
+                }
+                unique_keys.push(unique_columns.clone());
+            }
+            if let TableConstraint::ForeignKey {
+                name,
+                columns: key_columns,
+                foreign_table,
+                referred_columns,
+            } = constraint
+            {
+                let name = match name {
+                    Some(name) => name.value.clone(),
+                    None => {
+                        let names: Vec<&str> =
+                            key_columns.iter().map(|c| c.value.as_str()).collect();
+                        format!("{}_{}_fkey", table_name, names.join("_"))
+                    }
+                };
+                declared_keys.push((
+                    name,
+                    key_columns.clone(),
+                    foreign_table.to_string(),
+                    referred_columns.clone(),
+                    OnDelete::Restrict,
+                ));
+            }
+        }
+        let mut schema = TableSchema::new(attributes);
+
+        if db.get_table_id(table_name).is_some() {
+            return Err(CrustyError::CrustyError(String::from(
+                "database already has a table with this name",
+            )));
+        }
+        // Resolve the constraint indexes before anything is created.
+        let mut indexes = Vec::new();
+        for (i, key) in unique_keys.iter().enumerate() {
+            let key_columns = Self::key_columns(table_name, &schema, key)?;
+            let name = if i == 0 {
+                format!("{}_pkey", table_name)
+            } else {
+                let names: Vec<&str> = key.iter().map(|c| c.value.as_str()).collect();
+                format!("{}_{}_key", table_name, names.join("_"))
+            };
+            if db.get_index_id(&name).is_some() || indexes.iter().any(|(n, _)| *n == name) {
+                return Err(CrustyError::ValidationError(format!(
+                    "Index {} of a constraint already exists",
+                    name
+                )));
+            }
+            indexes.push((name, key_columns));
+        }
+        // Resolve the foreign keys. A parent of None is the new table itself.
+        let mut foreign_keys: Vec<(ForeignKey, Option<ContainerId>)> = Vec::new();
+        for (name, key, parent_name, referred, on_delete) in declared_keys {
+            if foreign_keys.iter().any(|(fk, _)| fk.name == name) {
+                return Err(CrustyError::ValidationError(format!(
+                    "Foreign key {} is declared twice",
+                    name
+                )));
+            }
+            let key_columns = Self::key_columns(table_name, &schema, &key)?;
+            let (parent_id, parent_schema, parent_keys) = if parent_name == table_name {
+                let keys: Vec<Vec<usize>> = indexes.iter().map(|(_, c)| c.clone()).collect();
+                (None, schema.clone(), keys)
+            } else {
+                let parent_id = db.get_table_id(&parent_name).ok_or_else(|| {
+                    CrustyError::ValidationError(format!(
+                        "Table {} referenced by foreign key {} does not exist",
+                        parent_name, name
+                    ))
+                })?;
+                let keys: Vec<Vec<usize>> = db
+                    .get_indexes(parent_id)
+                    .into_iter()
+                    .filter(|index| index.unique)
+                    .map(|index| index.columns)
+                    .collect();
+                (Some(parent_id), db.get_table_schema(parent_id)?, keys)
+            };
+            // Without referred columns the key refers to the primary key of the parent.
+            let parent_columns = if referred.is_empty() {
+                let pkey = match parent_id {
+                    None => indexes.first().map(|(_, c)| c.clone()),
+                    Some(_) => db
+                        .get_index(&format!("{}_pkey", parent_name))
+                        .map(|index| index.columns),
+                };
+                pkey.ok_or_else(|| {
+                    CrustyError::ValidationError(format!(
+                        "Table {} referenced by foreign key {} has no primary key",
+                        parent_name, name
+                    ))
+                })?
+            } else {
+                Self::key_columns(&parent_name, &parent_schema, &referred)?
+            };
+            if !parent_keys.contains(&parent_columns) {
+                return Err(CrustyError::ValidationError(format!(
+                    "Columns referenced by foreign key {} are not a primary key or unique constraint of table {}",
+                    name, parent_name
+                )));
+            }
+            if key_columns.len() != parent_columns.len()
+                || key_columns.iter().zip(&parent_columns).any(|(i, j)| {
+                    schema.get_attribute(*i).map(|a| a.dtype())
+                        != parent_schema.get_attribute(*j).map(|a| a.dtype())
+                })
+            {
+                return Err(CrustyError::ValidationError(format!(
+                    "Columns of foreign key {} do not match the types of the referenced columns",
+                    name
+                )));
+            }
+            let foreign_key = ForeignKey {
+                name,
+                columns: key_columns,
+                parent_id: parent_id.unwrap_or_default(),
+                parent_columns,
+                on_delete,
+            };
+            foreign_keys.push((foreign_key, parent_id));
+        }
+        // Rows of a columnar table cannot be deleted, so it cannot follow a parent's deletes.
+        if columnar && !foreign_keys.is_empty() {
+            return Err(CrustyError::ValidationError(String::from(
+                "Columnar tables cannot have foreign keys",
+            )));
+        }
+        let partitioning = match partition_by {
+            Some(spec) => {
+                if columnar {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Columnar tables cannot be partitioned",
+                    )));
+                }
+                // Deletes of parents scan the table's own container for child records.
+                if !foreign_keys.is_empty() {
+                    return Err(CrustyError::ValidationError(String::from(
+                        "Partitioned tables cannot have foreign keys",
+                    )));
+                }
+                Some(Self::partition_scheme(
+                    &schema,
+                    spec,
+                    partition_count,
+                    partition_bounds.map(String::as_str),
+                )?)
+            }
+            None if partition_count.is_some() || partition_bounds.is_some() => {
+                return Err(CrustyError::ValidationError(String::from(
+                    "Partitions need a partition_by option",
+                )))
+            }
+            None => None,
+        };
         debug!("Creating table with schema: {:?}", schema);
 
-        let table = Table::new(table_name.to_string(), schema);
+        let mut tables_ref = db.tables.write().unwrap();
+        let table_id =
+            db.get_new_container_id(StateType::BaseTable, Some(table_name.to_string()))?;
+
+        let mut dependencies: Vec<ContainerId> = Vec::new();
+        let foreign_keys: Vec<ForeignKey> = foreign_keys
+            .into_iter()
+            .map(|(mut foreign_key, parent_id)| {
+                foreign_key.parent_id = parent_id.unwrap_or(table_id);
+                if let Some(parent_id) = parent_id {
+                    if !dependencies.contains(&parent_id) {
+                        dependencies.push(parent_id);
+                    }
+                }
+                foreign_key
+            })
+            .collect();
+        // A single column key marks its column as referring to the parent.
+        let mut attributes: Vec<Attribute> = schema.attributes().cloned().collect();
+        for foreign_key in &foreign_keys {
+            if let [column] = foreign_key.columns.as_slice() {
+                let attr = &mut attributes[*column];
+                attr.constraint = match attr.constraint {
+                    common::Constraint::None => {
+                        common::Constraint::ForeignKey(foreign_key.parent_id)
+                    }
+                    common::Constraint::NotNull => {
+                        common::Constraint::NotNullFKey(foreign_key.parent_id)
+                    }
+                    ref other => other.clone(),
+                };
+            }
+        }
+        schema = TableSchema::new(attributes);
+
+        let mut table = Table::new(table_name.to_string(), schema);
+        table.foreign_keys = foreign_keys;
+        if columnar {
+            // The row groups in the table's container point into the column containers.
+            let mut columns = Vec::new();
+            for attr in table.schema.attributes() {
+                let name = format!("{}.{}", table_name, attr.name);
+                let container_id = db.get_new_container_id(StateType::Column, Some(name))?;
+                self.storage_manager.create_container(
+                    container_id,
+                    None,
+                    StateType::Column,
+                    None,
+                )?;
+                columns.push(container_id);
+            }
+            dependencies.extend(&columns);
+            table.layout = TableLayout::Column(columns);
+        }
+        if let Some((column, scheme, count)) = partitioning {
+            let mut partitions = Vec::new();
+            for i in 0..count {
+                let name = format!("{}.p{}", table_name, i);
+                let container_id = db.get_new_container_id(StateType::Partition, Some(name))?;
+                self.storage_manager.create_container(
+                    container_id,
+                    None,
+                    StateType::Partition,
+                    None,
+                )?;
+                partitions.push(container_id);
+            }
+            dependencies.extend(&partitions);
+            table.layout = TableLayout::Partitioned(Partitioning {
+                column,
+                scheme,
+                partitions,
+            });
+        }
         self.storage_manager.create_container(
             table_id,
             Some(table_name.to_string()),
             common::ids::StateType::BaseTable,
-            None,
+            if dependencies.is_empty() {
+                None
+            } else {
+                Some(dependencies)
+            },
         )?;
+        // Inserts check that their primary key is new, which the filter mostly answers
+        // without scanning the key's index.
+        let pkey = indexes.first().map(|(_, columns)| columns.as_slice());
+        if let (TableLayout::Row, Some([column])) = (&table.layout, pkey) {
+            self.storage_manager
+                .create_bloom_filter(table_id, *column, PRIMARY_KEY_BLOOM_KEYS)?;
+        }
         tables_ref.insert(table_id, Arc::new(RwLock::new(table)));
+        for (name, columns) in indexes {
+            let container_id = db.get_new_container_id(StateType::Index, Some(name.clone()))?;
+            self.storage_manager
+                .create_container(container_id, None, StateType::Index, None)?;
+            db.add_index(IndexInfo {
+                name,
+                container_id,
+                table_id,
+                columns,
+                unique: true,
+                constraint: true,
+                kind: IndexKind::Heap,
+            });
+        }
         Ok(QueryResult::new(&format!("Table {} created", table_name)))
     }
 
+    /// Returns the partition column, the scheme and the number of partitions of a table
+    /// partitioned as the options of CREATE TABLE declare.
+    ///
+    /// # Arguments
+    ///
+    /// * `schema` - Schema of the table.
+    /// * `spec` - Value of the `partition_by` option, `hash(c)` or `range(c)`.
+    /// * `count` - Value of the `partitions` option, required by hash partitioning.
+    /// * `bounds` - Value of the `partition_bounds` option, required by range partitioning.
+    fn partition_scheme(
+        schema: &TableSchema,
+        spec: &str,
+        count: Option<usize>,
+        bounds: Option<&str>,
+    ) -> Result<(usize, PartitionScheme, usize), CrustyError> {
+        let invalid = || {
+            CrustyError::ValidationError(format!(
+                "Invalid partition_by '{}', expected 'hash(column)' or 'range(column)'",
+                spec
+            ))
+        };
+        let (kind, column) = spec.trim().split_once('(').ok_or_else(invalid)?;
+        let column = column.strip_suffix(')').ok_or_else(invalid)?.trim();
+        let index = *schema.get_field_index(column).ok_or_else(|| {
+            CrustyError::ValidationError(format!("Partition column {} does not exist", column))
+        })?;
+        match (kind.trim().to_lowercase().as_str(), count, bounds) {
+            ("hash", Some(count), None) => Ok((index, PartitionScheme::Hash, count)),
+            ("range", None, Some(bounds)) => {
+                let dtype = schema.get_attribute(index).unwrap().dtype();
+                let bounds = PartitionScheme::parse_bounds(bounds, dtype)?;
+                let count = bounds.len() + 1;
+                Ok((index, PartitionScheme::Range(bounds), count))
+            }
+            ("hash", _, _) => Err(CrustyError::ValidationError(String::from(
+                "Hash partitioning needs the partitions option and no partition_bounds",
+            ))),
+            ("range", _, _) => Err(CrustyError::ValidationError(String::from(
+                "Range partitioning needs the partition_bounds option and no partitions",
+            ))),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Returns the indices of the columns of a key in a table schema.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - Name of the table.
+    /// * `schema` - Schema of the table.
+    /// * `key` - Names of the columns of the key.
+    fn key_columns(
+        table_name: &str,
+        schema: &TableSchema,
+        key: &[Ident],
+    ) -> Result<Vec<usize>, CrustyError> {
+        let mut key_columns = Vec::new();
+        for column in key {
+            match schema.get_field_index(&column.value) {
+                Some(j) if !key_columns.contains(j) => key_columns.push(*j),
+                Some(_) => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Column {} appears twice in a key",
+                        column
+                    )))
+                }
+                None => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Table {} has no column {}",
+                        table_name, column
+                    )))
+                }
+            }
+        }
+        Ok(key_columns)
+    }
+
+    /// Adds a column to a table. The records already stored are not rewritten: they are
+    /// read with the column's default value until they are updated.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_name` - Name of the table to alter.
+    /// * `column` - Definition of the new column.
+    pub fn add_column(
+        &self,
+        table_name: &str,
+        column: &ColumnDef,
+    ) -> Result<QueryResult, CrustyError> {
+        let db = &self.database;
+        let table_id = db
+            .get_table_id(table_name)
+            .ok_or_else(|| CrustyError::CrustyError(format!("Table {} not found", table_name)))?;
+        let dtype = get_attr(&column.data_type)?;
+        let mut default = Field::Null;
+        let mut constraint = common::Constraint::None;
+        for option in &column.options {
+            match &option.option {
+                ColumnOption::Null => {}
+                ColumnOption::NotNull => constraint = common::Constraint::NotNull,
+                ColumnOption::Default(Expr::Value(value)) => {
+                    let literal = match value {
+                        Value::Number(s, _) => Field::from_number(s),
+                        Value::SingleQuotedString(s) | Value::DoubleQuotedString(s) => {
+                            Some(Field::StringField(s.to_string()))
+                        }
+                        Value::Null => Some(Field::Null),
+                        _ => None,
+                    };
+                    default = match literal.map(|field| field.coerce(&dtype)) {
+                        Some(field) if field.has_type(&dtype) => field,
+                        _ => {
+                            return Err(CrustyError::ValidationError(format!(
+                                "Default {} does not match the type of column {}",
+                                value, column.name
+                            )))
+                        }
+                    }
+                }
+                option => {
+                    return Err(CrustyError::CrustyError(format!(
+                        "Column option {} not supported in ALTER TABLE",
+                        option
+                    )))
+                }
+            }
+        }
+        if constraint == common::Constraint::NotNull && default == Field::Null {
+            return Err(CrustyError::ValidationError(format!(
+                "Column {} is NOT NULL and needs a default value",
+                column.name
+            )));
+        }
+
+        let table = db.get_table_ptr(table_id)?;
+        let mut table = table.write().unwrap();
+        if let TableLayout::Column(_) = table.layout {
+            return Err(CrustyError::ValidationError(format!(
+                "Cannot add columns to columnar table {}",
+                table_name
+            )));
+        }
+        if table.schema.contains(&column.name.value) {
+            return Err(CrustyError::ValidationError(format!(
+                "Table {} already has a column {}",
+                table_name, column.name
+            )));
+        }
+        let attr = Attribute {
+            name: column.name.value.clone(),
+            dtype,
+            constraint,
+        };
+        table.add_column(attr, default.clone());
+        self.result_cache.bump(table_id);
+        if let Some(stats) = db.table_stats.write().unwrap().get_mut(&table_id) {
+            let column_stats = ColumnStats::constant(&default, stats.row_count);
+            stats.columns.push(column_stats);
+        }
+        Ok(QueryResult::new(&format!(
+            "Added column {} to table {}",
+            column.name, table_name
+        )))
+    }
+
+    /// Creates an index over columns of a table and bulk loads it from the table's records.
+    ///
+    /// # Arguments
+    ///
+    /// * `index_name` - Name of the new index.
+    /// * `table_name` - Name of the indexed table.
+    /// * `columns` - Names of the indexed columns, in key order.
+    /// * `unique` - Whether no two records may have the same key.
+    /// * `kind` - How the entries of the index are stored.
+    /// * `if_not_exists` - Succeed without doing anything if the index already exists.
+    /// * `tid` - Transaction reading the table.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_index(
+        &self,
+        index_name: &str,
+        table_name: &str,
+        columns: &[String],
+        unique: bool,
+        kind: IndexKind,
+        if_not_exists: bool,
+        tid: TransactionId,
+    ) -> Result<QueryResult, CrustyError> {
+        let db = &self.database;
+        if db.get_index_id(index_name).is_some() && if_not_exists {
+            return Ok(QueryResult::new(&format!(
+                "Index {} already exists",
+                index_name
+            )));
+        }
+        let table_id = db
+            .get_table_id(table_name)
+            .ok_or_else(|| CrustyError::CrustyError(format!("Table {} not found", table_name)))?;
+        let schema = db.get_table_schema(table_id)?;
+        let mut column_indices = Vec::new();
+        for column in columns {
+            match schema.get_field_index(column) {
+                Some(i) => column_indices.push(*i),
+                None => {
+                    return Err(CrustyError::ValidationError(format!(
+                        "Table {} has no column {}",
+                        table_name, column
+                    )))
+                }
+            }
+        }
+
+        let container_id =
+            db.get_new_container_id(StateType::Index, Some(index_name.to_string()))?;
+        let index = IndexInfo {
+            name: index_name.to_string(),
+            container_id,
+            table_id,
+            columns: column_indices,
+            unique,
+            constraint: false,
+            kind,
+        };
+        self.storage_manager
+            .create_container(container_id, None, StateType::Index, None)?;
+        let table = db.get_table_ptr(table_id)?;
+        let entries = match index::build(
+            self.storage_manager.as_ref(),
+            &table.read().unwrap(),
+            &index,
+            tid,
+        ) {
+            Ok(entries) => entries,
+            Err(e) => {
+                db.remove_index(index_name);
+                self.storage_manager.remove_container(container_id)?;
+                return Err(e);
+            }
+        };
+        db.add_index(index);
+        Ok(QueryResult::new(&format!(
+            "Index {} created with {} entries",
+            index_name, entries
+        )))
+    }
+
+    /// Drops an index and removes its container.
+    ///
+    /// # Arguments
+    ///
+    /// * `index_name` - Name of the index to drop.
+    /// * `if_exists` - Succeed without doing anything if the index does not exist.
+    pub fn drop_index(
+        &self,
+        index_name: &str,
+        if_exists: bool,
+    ) -> Result<QueryResult, CrustyError> {
+        if let Some(index) = self.database.get_index(index_name) {
+            if index.constraint {
+                return Err(CrustyError::CrustyError(format!(
+                    "Index {} enforces a constraint of its table and cannot be dropped",
+                    index_name
+                )));
+            }
+        }
+        match self.database.remove_index(index_name) {
+            Some(index) => {
+                self.storage_manager.remove_container(index.container_id)?;
+                Ok(QueryResult::new(&format!("Index {} dropped", index_name)))
+            }
+            None if if_exists => Ok(QueryResult::new(&format!(
+                "Index {} does not exist",
+                index_name
+            ))),
+            None => Err(CrustyError::CrustyError(format!(
+                "Index {} not found",
+                index_name
+            ))),
+        }
+    }
+
+    /// Analyzes a table again, on a sample of its pages, if enough of its records changed
+    /// since its statistics were computed; see `statistics::refresh`. A failed refresh is
+    /// logged and leaves the statistics as they were, as the change to the table is done.
+    ///
+    /// # Arguments
+    ///
+    /// * `table_id` - Container of the changed table.
+    /// * `tid` - Transaction reading the table.
+    pub fn refresh_table_stats(&self, table_id: ContainerId, tid: TransactionId) {
+        let refreshed = self.database.get_table_ptr(table_id).and_then(|table| {
+            statistics::refresh(
+                self.storage_manager.as_ref(),
+                table_id,
+                &table.read().unwrap(),
+                self.database.get_table_stats(table_id).as_ref(),
+                tid,
+            )
+        });
+        match refreshed {
+            Ok(Some(stats)) => self.database.set_table_stats(table_id, stats),
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Could not refresh the statistics of table {}: {}",
+                table_id, e
+            ),
+        }
+    }
+
     pub fn reset(&self) -> Result<(), CrustyError> {
         self.query_registrar.reset()?;
+        self.result_cache.clear();
         let mut conns = self.active_client_connections.write().unwrap();
         conns.clear();
         drop(conns);
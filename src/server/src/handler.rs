@@ -20,9 +20,10 @@ pub fn handle_client_request(mut stream: TcpStream, server_state: &'static Serve
     // FIXME: right now, this is unused
     let parser = SQLParser::new();
     let executor = Executor::new_ref(
-        server_state.storage_manager,
-        server_state.transaction_manager,
-    );
+        server_state.storage_manager.clone(),
+        server_state.transaction_manager.locks(),
+    )
+    .with_memory_budget(server_state.memory_budget.clone());
     let optimizer = Optimizer::new();
     let mut conductor = Conductor::new(parser, optimizer, executor).unwrap();
 
@@ -49,7 +50,7 @@ pub fn handle_client_request(mut stream: TcpStream, server_state: &'static Serve
                 let request_command: Commands = match serde_cbor::from_slice(&buffer[0..size]) {
                     Ok(command) => command,
                     Err(e) => {
-                        println!("{} {:?}", e, buffer);
+                        error!("Cannot decode request {:?}: {}", &buffer[0..size], e);
                         panic!("FIXME j");
                     }
                 };
@@ -16,6 +16,7 @@ use std::thread;
 use crate::daemon::Daemon;
 use crate::server_state::ServerState;
 use crate::worker::Message;
+use queryexe::opiterator::DEFAULT_MEMORY_LIMIT;
 
 mod conductor;
 mod csv_utils;
@@ -35,7 +36,9 @@ pub use memstore::storage_manager::StorageManager;
 pub use queryexe;
 pub use queryexe::query::Executor;
 
-pub use txn_manager::mock_tm::MockTransactionManager as TransactionManager;
+pub use txn_manager::mock_tm::MockTransactionManager as LockManager;
+/// Issues the transactions statements run in, registered with the lock manager.
+pub type TransactionManager = txn_manager::manager::TransactionManager<LockManager>;
 
 // For delta based system
 //pub use deltastore::storage_manager::DeltaStorageManager as StorageManager;
@@ -47,6 +50,16 @@ struct ServerConfig {
     port: String,
     db_path: String,
     workers: usize,
+    /// Number of query results each database caches, 0 to disable result caching.
+    #[serde(default)]
+    result_cache: usize,
+    /// Bytes of tuples the operators of all running queries may buffer before spilling.
+    #[serde(default = "default_memory_budget")]
+    memory_budget: usize,
+}
+
+fn default_memory_budget() -> usize {
+    DEFAULT_MEMORY_LIMIT
 }
 
 /// Entry point for server.
@@ -105,6 +118,21 @@ fn main() {
                 .help("Number of worker threads")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("result_cache")
+                .long("result_cache")
+                .value_name("results")
+                .default_value("0")
+                .help("Number of query results each database caches, 0 to disable")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("memory_budget")
+                .long("memory_budget")
+                .value_name("bytes")
+                .help("Bytes of tuples running queries may buffer before spilling")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("daemon")
                 .long("daemon")
@@ -127,11 +155,21 @@ fn main() {
             .unwrap()
             .parse::<usize>()
             .unwrap();
+        let result_cache = matches
+            .value_of("result_cache")
+            .unwrap()
+            .parse::<usize>()
+            .unwrap();
+        let memory_budget = matches
+            .value_of("memory_budget")
+            .map_or(DEFAULT_MEMORY_LIMIT, |b| b.parse::<usize>().unwrap());
         ServerConfig {
             host: host.to_string(),
             port: port.to_string(),
             db_path: db_path.to_string(),
             workers,
+            result_cache,
+            memory_budget,
         }
     };
 
@@ -145,7 +183,15 @@ fn main() {
     let receiver = Arc::new(Mutex::new(receiver));
 
     let persist_path = format!("crusty_data/persist/{}", config.db_path);
-    let server_state_box = Box::new(ServerState::new(persist_path, sender).unwrap());
+    let server_state_box = Box::new(
+        ServerState::new(
+            persist_path,
+            sender,
+            config.result_cache,
+            config.memory_budget,
+        )
+        .unwrap(),
+    );
     let server_state: &'static ServerState = Box::leak(server_state_box);
     //Create daemon thread
     let mut _daemon_thread = Daemon::new(server_state, daemon_seconds);
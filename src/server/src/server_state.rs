@@ -11,10 +11,11 @@ use common::ids::LogicalTimeStamp;
 use common::physical_plan::PhysicalPlan;
 use common::traits::transaction_manager_trait::TransactionManagerTrait;
 use common::CrustyError;
+use queryexe::opiterator::MemoryBudget;
 use std::sync::mpsc;
 use std::sync::Mutex;
 
-use crate::{StorageManager, StorageTrait, TransactionManager};
+use crate::{LockManager, StorageManager, StorageTrait, TransactionManager};
 
 const DB_DIR: &str = "dbs";
 
@@ -34,14 +35,22 @@ pub struct ServerState {
 
     workers: Mutex<Vec<worker::Worker>>,
 
-    pub storage_manager: &'static StorageManager,
+    pub storage_manager: Arc<StorageManager>,
     pub transaction_manager: &'static TransactionManager,
+
+    /// Number of query results each database caches, 0 to disable result caching.
+    result_cache: usize,
+
+    /// Memory the operators of all running queries share.
+    pub memory_budget: MemoryBudget,
 }
 
 impl ServerState {
     pub(crate) fn new(
         storage_path_str: String,
         task_queue: mpsc::Sender<Message>,
+        result_cache: usize,
+        memory_budget: usize,
     ) -> Result<Self, CrustyError> {
         let storage_path = PathBuf::from(&storage_path_str);
         if !storage_path.exists() {
@@ -52,11 +61,10 @@ impl ServerState {
             // Create dirs if they do not exist.
             fs::create_dir_all(&storage_path)?;
         }
-        // Create the storage manager. Leak so it has a static lifetime
-        let sm_box = Box::new(StorageManager::new(storage_path.clone()));
-        let sm: &'static StorageManager = Box::leak(sm_box);
+        // Create the storage manager.
+        let sm = Arc::new(StorageManager::new(storage_path.clone()));
 
-        let tm_box = Box::new(TransactionManager::new(&storage_path));
+        let tm_box = Box::new(TransactionManager::new(LockManager::new(&storage_path)));
         let tm: &'static TransactionManager = Box::leak(tm_box);
 
         // Create databases
@@ -73,8 +81,9 @@ impl ServerState {
                     let db_path = db.path();
                     debug!("Creating DatabaseState from path {:?}", db_path);
                     // let db_struct: Database = Database::load(db);
-                    let db_box = Box::new(DatabaseState::load(db_path, sm, tm)?);
+                    let db_box = Box::new(DatabaseState::load(db_path, sm.clone(), tm)?);
                     let db_state: &'static DatabaseState = Box::leak(db_box);
+                    db_state.result_cache.set_capacity(result_cache);
                     db_map.insert(db_state.id, db_state);
                 }
             }
@@ -92,6 +101,8 @@ impl ServerState {
             workers: Mutex::new(Vec::new()),
             storage_manager: sm,
             transaction_manager: tm,
+            result_cache,
+            memory_budget: MemoryBudget::new(memory_budget),
         };
 
         Ok(server_state)
@@ -227,7 +238,8 @@ impl ServerState {
     ///
     /// # Notes
     ///
-    /// * The database is currently in-memory.
+    /// * The containers of the database are stored in a database of the storage manager,
+    ///   with their own container ids.
     pub fn create_database(&self, name: String) -> Result<String, CrustyError> {
         // Create new DB
         // Represent newly created DB in server state
@@ -242,16 +254,52 @@ impl ServerState {
                 &name
             )))
         } else {
-            let db_state_box = Box::new(
-                DatabaseState::new_from_name(&name, self.storage_manager, self.transaction_manager)
-                    .unwrap(),
-            );
+            let db_state_box = Box::new(DatabaseState::new_from_name(
+                &name,
+                self.storage_manager.clone(),
+                self.transaction_manager,
+            )?);
             let db_state: &'static DatabaseState = Box::leak(db_state_box);
+            db_state.result_cache.set_capacity(self.result_cache);
             self.id_to_db.write().unwrap().insert(db_state.id, db_state);
             Ok(format!("Created database {:?}", &name))
         }
     }
 
+    /// Drops the database with name, its catalog and its containers.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the database to drop. No client may be connected to it.
+    pub fn drop_database(&self, name: String) -> Result<String, CrustyError> {
+        let db_id = self.get_db_id_from_db_name(&name)?;
+        let mut db_states = self.id_to_db.write().unwrap();
+        if self
+            .active_connections
+            .read()
+            .unwrap()
+            .values()
+            .any(|id| *id == db_id)
+        {
+            return Err(CrustyError::CrustyError(format!(
+                "database {:?} has connected clients",
+                &name
+            )));
+        }
+        let db_state = db_states.remove(&db_id).unwrap();
+        db_state.reset()?;
+        let catalog_path = self.storage_path.join(DB_DIR).join(&name);
+        if catalog_path.exists() {
+            fs::remove_file(catalog_path)?;
+        }
+        // databases persisted before they had their own have no database in the storage
+        // manager
+        if self.storage_manager.list_databases().contains(&name) {
+            self.storage_manager.drop_database(&name)?;
+        }
+        Ok(format!("Dropped database {:?}", &name))
+    }
+
     pub fn connect_to_db(&self, db_name: String, client_id: u64) -> Result<String, CrustyError> {
         let db_id = self.get_db_id_from_db_name(&db_name)?;
         let map_ref = self.id_to_db.read().unwrap();
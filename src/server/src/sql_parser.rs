@@ -1,4 +1,7 @@
+use sqlparser::dialect::keywords::Keyword;
+use sqlparser::dialect::Dialect;
 use sqlparser::parser::Parser;
+use sqlparser::tokenizer::{Token, Tokenizer};
 
 use sqlparser::ast::TableConstraint;
 use sqlparser::ast::{ColumnDef, ColumnOption, Ident, Statement};
@@ -58,12 +61,63 @@ impl SQLParser {
     /// Returns Request::SQL if given string is valid sql, else returns Request::SQLError
     fn validate_sql(sql: String) -> ParserResponse {
         let dialect = sqlparser::dialect::GenericDialect {};
+        let sql = SQLParser::rewrite_index_method(&dialect, sql);
         match Parser::parse_sql(&dialect, &sql) {
             Ok(a) => ParserResponse::SQL(a),
             Err(e) => ParserResponse::SQLError(e),
         }
     }
 
+    /// Rewrites `CREATE [UNIQUE] INDEX name ON table USING method (columns)`, whose index
+    /// method the SQL parser does not support, into a call of the method over the columns:
+    /// `CREATE INDEX name ON table (method(columns))`. Other statements are returned as is.
+    fn rewrite_index_method(dialect: &dyn Dialect, sql: String) -> String {
+        let tokens = match Tokenizer::new(dialect, &sql).tokenize() {
+            Ok(tokens) => tokens,
+            Err(_) => return sql,
+        };
+        let words: Vec<usize> = (0..tokens.len())
+            .filter(|i| !matches!(tokens[*i], Token::Whitespace(_)))
+            .collect();
+        let is_keyword = |n: usize, keyword: Keyword| matches!(words.get(n).map(|i| &tokens[*i]), Some(Token::Word(w)) if w.keyword == keyword);
+        let mut n = 1;
+        if is_keyword(n, Keyword::UNIQUE) {
+            n += 1;
+        }
+        if !is_keyword(0, Keyword::CREATE) || !is_keyword(n, Keyword::INDEX) {
+            return sql;
+        }
+        // USING must come right before the column list
+        let using = match (n..words.len()).find(|n| tokens[words[*n]] == Token::LParen) {
+            Some(lparen) if lparen >= 2 && is_keyword(lparen - 2, Keyword::USING) => lparen - 2,
+            _ => return sql,
+        };
+        let (method, lparen) = (words[using + 1], words[using + 2]);
+        if !matches!(tokens[method], Token::Word(_)) {
+            return sql;
+        }
+        let mut depth = 0;
+        let rparen = (lparen..tokens.len()).find(|i| {
+            match tokens[*i] {
+                Token::LParen => depth += 1,
+                Token::RParen => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        });
+        let rparen = match rparen {
+            Some(rparen) => rparen,
+            None => return sql,
+        };
+        let mut rewritten = tokens[..words[using]].to_vec();
+        rewritten.push(Token::LParen);
+        rewritten.push(tokens[method].clone());
+        rewritten.extend_from_slice(&tokens[lparen..=rparen]);
+        rewritten.push(Token::RParen);
+        rewritten.extend_from_slice(&tokens[rparen + 1..]);
+        rewritten.iter().map(|token| token.to_string()).collect()
+    }
+
     /// Returns a vector of the Idents of tables that are primary keys if valid
     /// Returns an error (as request) if there is a problem
     ///
@@ -243,6 +297,24 @@ mod libtests {
     }
     */
 
+    #[test]
+    fn test_create_index_using() {
+        let sql = String::from("create unique index i on t using hash (a, b)");
+        match SQLParser::parse_sql(sql) {
+            ParserResponse::SQL(ast) => {
+                assert_eq!("CREATE UNIQUE INDEX i ON t(hash(a, b))", ast[0].to_string())
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+        let sql = String::from("create index i on t (a)");
+        match SQLParser::parse_sql(sql) {
+            ParserResponse::SQL(ast) => {
+                assert_eq!("CREATE INDEX i ON t(a)", ast[0].to_string())
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+    }
+
     #[test]
     fn test_get_pks() {
         // fail cases
@@ -1,5 +1,6 @@
 #[macro_use]
 extern crate log;
+pub mod manager;
 pub mod transactions;
 
 pub mod mock_tm;
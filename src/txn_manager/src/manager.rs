@@ -0,0 +1,242 @@
+//! Issues transactions and follows them until they commit or abort.
+//!
+//! `TransactionManager::begin` hands out a `Transaction` whose id is registered with the
+//! lock manager, a `TransactionManagerTrait`. Committing validates the transaction with
+//! the lock manager, commits it in the storage manager (which logs the commit to its WAL,
+//! if it has one) and only then releases its locks. Aborting rolls it back in both.
+//!
+//! Only the state of transactions that have not finished is kept: a transaction is
+//! forgotten once it commits or aborts, and operations with the id of a transaction that
+//! finished, or was not issued by the manager, are rejected with
+//! `CrustyError::TransactionNotActive` before they reach the storage manager.
+
+use crate::transactions::{Transaction, TxnState};
+use common::ids::TransactionId;
+use common::storage_trait::StorageTrait;
+use common::traits::transaction_manager_trait::TransactionManagerTrait;
+use common::CrustyError;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Issues transactions and tracks their state.
+pub struct TransactionManager<L: TransactionManagerTrait> {
+    /// The lock manager the transactions are registered with.
+    locks: L,
+    /// The state of each transaction issued that has not finished.
+    states: RwLock<HashMap<TransactionId, TxnState>>,
+}
+
+impl<L: TransactionManagerTrait> TransactionManager<L> {
+    /// Creates a transaction manager registering its transactions with a lock manager.
+    pub fn new(locks: L) -> Self {
+        Self {
+            locks,
+            states: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the lock manager.
+    pub fn locks(&self) -> &L {
+        &self.locks
+    }
+
+    /// Begins a transaction.
+    pub fn begin(&self) -> Result<Transaction, CrustyError> {
+        let txn = Transaction::new();
+        let tid = txn.tid()?;
+        self.locks.start_transaction(tid)?;
+        self.states.write().unwrap().insert(tid, TxnState::Active);
+        debug!("Began transaction {:?}", tid);
+        Ok(txn)
+    }
+
+    /// Returns the state of a transaction, None if it finished or was not issued by this
+    /// manager.
+    pub fn state(&self, tid: TransactionId) -> Option<TxnState> {
+        self.states.read().unwrap().get(&tid).copied()
+    }
+
+    /// Returns an error unless the transaction was issued by this manager and is active.
+    /// Unknown transactions, including the ones that finished, are not active.
+    pub fn check_active(&self, tid: TransactionId) -> Result<(), CrustyError> {
+        match self.state(tid) {
+            Some(TxnState::Active) => Ok(()),
+            _ => Err(CrustyError::TransactionNotActive),
+        }
+    }
+
+    /// Returns the ids of the active transactions.
+    pub fn active_transactions(&self) -> Vec<TransactionId> {
+        let states = self.states.read().unwrap();
+        let mut tids: Vec<TransactionId> = states
+            .iter()
+            .filter(|(_, state)| **state == TxnState::Active)
+            .map(|(tid, _)| *tid)
+            .collect();
+        tids.sort_unstable_by_key(|tid| tid.id());
+        tids
+    }
+
+    /// Sets the state of an active transaction, returning its id.
+    fn transition(
+        &self,
+        txn: &mut Transaction,
+        state: TxnState,
+    ) -> Result<TransactionId, CrustyError> {
+        let tid = txn.tid()?;
+        let mut states = self.states.write().unwrap();
+        match states.get_mut(&tid) {
+            Some(current) if *current == TxnState::Active => *current = state,
+            _ => return Err(CrustyError::TransactionNotActive),
+        }
+        txn.set_state(state);
+        Ok(tid)
+    }
+
+    /// Ends a transaction being committed or aborted in a final state, forgetting it.
+    fn finish(&self, txn: &mut Transaction, tid: TransactionId, state: TxnState) {
+        debug!("Transaction {:?} finished: {:?}", tid, state);
+        self.states.write().unwrap().remove(&tid);
+        txn.set_state(state);
+    }
+
+    /// Commits a transaction. It is validated by the lock manager, committed in the storage
+    /// manager, and its locks are released. If the lock manager or the storage manager
+    /// refuse the commit, the transaction is aborted instead and their error is returned.
+    /// Transactions that are not active are rejected without reaching the storage manager.
+    pub fn commit<S: StorageTrait>(
+        &self,
+        txn: &mut Transaction,
+        storage: &S,
+    ) -> Result<(), CrustyError> {
+        let tid = self.transition(txn, TxnState::PartiallyCommitted)?;
+        if let Err(e) = self.locks.validate_txn(tid) {
+            storage.transaction_aborted(tid);
+            self.locks.rollback_txn(tid)?;
+            self.finish(txn, tid, TxnState::Aborted);
+            return Err(e);
+        }
+        if let Err(e) = storage.transaction_committed(tid) {
+            self.locks.rollback_txn(tid)?;
+            self.finish(txn, tid, TxnState::Aborted);
+            return Err(e);
+        }
+        self.locks.commit_txn(tid)?;
+        self.finish(txn, tid, TxnState::Committed);
+        Ok(())
+    }
+
+    /// Aborts a transaction, discarding its changes in the storage manager and releasing its
+    /// locks. Transactions that are not active are rejected without reaching the storage
+    /// manager.
+    pub fn abort<S: StorageTrait>(
+        &self,
+        txn: &mut Transaction,
+        storage: &S,
+    ) -> Result<(), CrustyError> {
+        let tid = self.transition(txn, TxnState::Failed)?;
+        storage.transaction_aborted(tid);
+        self.locks.rollback_txn(tid)?;
+        self.finish(txn, tid, TxnState::Aborted);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock_tm::MockTransactionManager;
+    use common::ids::Permissions;
+    use common::traits::transaction_manager_trait::IsolationLevel;
+    use std::path::Path;
+
+    fn manager() -> TransactionManager<MockTransactionManager> {
+        TransactionManager::new(MockTransactionManager::new(Path::new("")))
+    }
+
+    fn count(sm: &heapstore::storage_manager::StorageManager, tid: TransactionId) -> usize {
+        sm.get_iterator(1, tid, Permissions::ReadOnly).count()
+    }
+
+    #[test]
+    fn tm_commit_abort() {
+        let tm = manager();
+        let sm = heapstore::storage_manager::StorageManager::new_test_sm().with_mvcc();
+        sm.create_table(1).unwrap();
+
+        let mut t1 = tm.begin().unwrap();
+        let mut t2 = tm.begin().unwrap();
+        let (tid1, tid2) = (t1.tid().unwrap(), t2.tid().unwrap());
+        assert_eq!(vec![tid1, tid2], tm.active_transactions());
+        sm.insert_value(1, vec![1; 10], tid1).unwrap();
+        sm.insert_value(1, vec![2; 10], tid2).unwrap();
+        tm.commit(&mut t1, &sm).unwrap();
+        tm.abort(&mut t2, &sm).unwrap();
+        assert_eq!(&TxnState::Committed, t1.state());
+        assert_eq!(&TxnState::Aborted, t2.state());
+        // Finished transactions are forgotten
+        assert_eq!(None, tm.state(tid1));
+        assert_eq!(None, tm.state(tid2));
+        assert!(tm.states.read().unwrap().is_empty());
+
+        // Only the committed insert is kept
+        let t3 = tm.begin().unwrap();
+        assert_eq!(1, count(&sm, t3.tid().unwrap()));
+
+        // Finished transactions are rejected
+        assert!(t1.tid().is_err());
+        assert!(tm.check_active(tid1).is_err());
+        assert!(tm.commit(&mut t1, &sm).is_err());
+        assert!(tm.abort(&mut t2, &sm).is_err());
+        assert_eq!(None, tm.state(tid1));
+        tm.check_active(t3.tid().unwrap()).unwrap();
+
+        // As are transactions issued elsewhere
+        let mut other = crate::transactions::Transaction::new();
+        assert!(tm.check_active(other.tid().unwrap()).is_err());
+        assert!(tm.commit(&mut other, &sm).is_err());
+    }
+
+    #[test]
+    fn tm_commit_refused() {
+        let tm = manager();
+        let sm = heapstore::storage_manager::StorageManager::new_test_sm().with_mvcc();
+        let loader = tm.begin().unwrap().tid().unwrap();
+        sm.create_table(1).unwrap();
+        let ids = sm
+            .insert_values(1, vec![vec![1; 10], vec![2; 10]], loader)
+            .unwrap();
+        sm.commit_transaction(loader).unwrap();
+
+        // Write skew: the storage manager refuses the second commit
+        let mut t1 = tm.begin().unwrap();
+        let mut t2 = tm.begin().unwrap();
+        let (tid1, tid2) = (t1.tid().unwrap(), t2.tid().unwrap());
+        for tid in [tid1, tid2] {
+            sm.begin_transaction(tid, IsolationLevel::Serializable)
+                .unwrap();
+            assert_eq!(2, count(&sm, tid));
+        }
+        sm.delete_value(ids[0], tid1).unwrap();
+        sm.delete_value(ids[1], tid2).unwrap();
+        tm.commit(&mut t1, &sm).unwrap();
+        assert!(matches!(
+            tm.commit(&mut t2, &sm),
+            Err(CrustyError::TransactionRollback(_))
+        ));
+        assert_eq!(None, tm.state(tid2));
+        assert_eq!(&TxnState::Aborted, t2.state());
+        assert_eq!(1, count(&sm, tm.begin().unwrap().tid().unwrap()));
+    }
+
+    #[test]
+    fn tm_abort_temp_containers() {
+        let tm = manager();
+        let sm = memstore::storage_manager::StorageManager::new_test_sm();
+        let mut txn = tm.begin().unwrap();
+        let tid = txn.tid().unwrap();
+        let cid = sm.create_temp_container(tid).unwrap();
+        tm.abort(&mut txn, &sm).unwrap();
+        assert!(sm.insert_value(cid, vec![1], tid).is_err());
+    }
+}
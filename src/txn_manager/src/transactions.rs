@@ -1,7 +1,7 @@
 use common::ids::TransactionId;
 use common::CrustyError;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TxnState {
     Active,
     PartiallyCommitted,
@@ -41,33 +41,13 @@ impl Transaction {
         }
     }
 
-    /// Commits the transaction.
-    pub fn commit(&mut self) -> Result<(), CrustyError> {
-        self.state = TxnState::PartiallyCommitted;
-        self.complete()
+    /// Returns the state of the transaction.
+    pub fn state(&self) -> &TxnState {
+        &self.state
     }
 
-    /// Aborts the transaction.
-    pub fn abort(&mut self) -> Result<(), CrustyError> {
-        self.state = TxnState::Failed;
-        self.complete()
-    }
-
-    /// Completes the transaction.
-    ///
-    /// # Arguments
-    ///
-    /// * `commit` - True if the transaction should commit.
-    pub fn complete(&mut self) -> Result<(), CrustyError> {
-        if self.state != TxnState::PartiallyCommitted || self.state != TxnState::Failed {
-            error!(
-                "Error: FIXME, need to notify on txn complete {:?}",
-                self.state
-            );
-            //FIXME DBSERVER.transaction_complete(self.tid, commit)?;
-            self.state = TxnState::Terminated;
-        }
-        Ok(())
+    /// Sets the state of the transaction, as it is committed or aborted.
+    pub(crate) fn set_state(&mut self, state: TxnState) {
+        self.state = state;
     }
 }
-
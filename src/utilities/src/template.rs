@@ -92,6 +92,11 @@ impl Template {
                 let val = match field {
                     Field::IntField(i) => i.to_string(),
                     Field::StringField(s) => s.to_string(),
+                    Field::FloatField(x) => x.to_string(),
+                    Field::DecimalField(x) => x.to_string(),
+                    Field::DateField(x) => x.to_string(),
+                    Field::TimestampField(x) => x.to_string(),
+                    Field::BoolField(x) => x.to_string(),
                     Field::Null => String::from("null"),
                 };
                 res.push_str(&val);